@@ -476,9 +476,12 @@ core::arch::global_asm!(
         ite   ne               // check if the result of that bitwise AND was not 0
         movne r1, #1           // BFSR & 0b00110000 != 0; r1 = 1
         moveq r1, #0           // BFSR & 0b00110000 == 0; r1 = 0
-        and r5, r2, r1         // bitwise and r1 and r2, store in r5
-        cmp  r5, #1            //  update condition codes to reflect if r1 == 1 && r2 == 1
-        itt  eq                // if r5==1 run the next 2 instructions, else skip to branch
+        // Only use r0-r3 and r12 from here on for userspace faults: they are
+        // on the process stack, and the other registers are saved as the
+        // process's registers when returning to the kernel.
+        and r3, r2, r1         // bitwise and r1 and r2, store in r3
+        cmp  r3, #1            //  update condition codes to reflect if r1 == 1 && r2 == 1
+        itt  eq                // if r3==1 run the next 2 instructions, else skip to branch
         // if true, The hardware couldn't use the stack, so we have no saved data and
         // we cannot use the kernel stack as is. We just want to report that
         // the kernel's stack overflowed, since that is essential for
@@ -749,4 +749,9 @@ impl kernel::syscall::UserspaceKernelBoundary for SysCall {
             Err(ErrorCode::SIZE)
         }
     }
+
+    fn load_context(&self, state: &mut Riscv32iStoredState, data: &[u8]) -> Result<(), ErrorCode> {
+        *state = Riscv32iStoredState::try_from(data).or(Err(ErrorCode::INVAL))?;
+        Ok(())
+    }
 }
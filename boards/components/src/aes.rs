@@ -15,12 +15,16 @@
 //!     board_kernel,
 //!     capsules_extra::symmetric_encryption::aes::DRIVER_NUM,
 //!     aes_driver_device,
+//!     software_aes,
+//!     chacha20poly1305,
 //! )
 //! .finalize(components::aes_driver_component_static!(
 //!     capsules_core::virtualizers::virtual_aes_ccm::VirtualAES128CCM<
 //!         'static,
 //!         nrf52840::aes::AesECB<'static>,
-//!     >
+//!     >,
+//!     capsules_extra::symmetric_encryption::software_aes::SoftwareAes<'static>,
+//!     capsules_extra::symmetric_encryption::chacha20poly1305::SoftwareChaCha20Poly1305<'static>,
 //! ));
 //! ```
 
@@ -30,7 +34,8 @@ use kernel::component::Component;
 use kernel::create_capability;
 use kernel::hil;
 use kernel::hil::symmetric_encryption::{
-    AES128Ctr, AES128, AES128CBC, AES128CCM, AES128ECB, AES128GCM,
+    AES128Ctr, AES256Ctr, ChaCha20Poly1305, AES128, AES128CBC, AES128CCM, AES128ECB, AES128GCM,
+    AES256, AES256CBC, AES256CCM, AES256ECB, AES256GCM,
 };
 
 const CRYPT_SIZE: usize = 7 * hil::symmetric_encryption::AES128_BLOCK_SIZE;
@@ -50,12 +55,13 @@ macro_rules! aes_virtual_component_static {
 
 #[macro_export]
 macro_rules! aes_driver_component_static {
-    ($A:ty $(,)?) => {{
+    ($A:ty, $B:ty, $C:ty $(,)?) => {{
         const CRYPT_SIZE: usize = 7 * kernel::hil::symmetric_encryption::AES128_BLOCK_SIZE;
         let aes_src_buffer = kernel::static_buf!([u8; 16]);
         let aes_dst_buffer = kernel::static_buf!([u8; CRYPT_SIZE]);
-        let aes_driver =
-            kernel::static_buf!(capsules_extra::symmetric_encryption::aes::AesDriver<'static, $A>);
+        let aes_driver = kernel::static_buf!(
+            capsules_extra::symmetric_encryption::aes::AesDriver<'static, $A, $B, $C>
+        );
 
         (aes_driver, aes_src_buffer, aes_dst_buffer)
     };};
@@ -99,24 +105,37 @@ impl<A: 'static + AES128<'static> + AES128Ctr + AES128CBC + AES128ECB> Component
     }
 }
 
-pub struct AesDriverComponent<A: AES128<'static> + AES128CCM<'static> + 'static> {
+pub struct AesDriverComponent<
+    A: AES128<'static> + AES128CCM<'static> + 'static,
+    B: AES256<'static> + AES256CCM<'static> + 'static,
+    C: ChaCha20Poly1305<'static> + 'static,
+> {
     board_kernel: &'static kernel::Kernel,
     driver_num: usize,
     aes: &'static A,
+    aes256: &'static B,
+    chacha: &'static C,
 }
 
-impl<A: AES128<'static> + AES128Ctr + AES128CBC + AES128ECB + AES128CCM<'static>>
-    AesDriverComponent<A>
+impl<
+        A: AES128<'static> + AES128Ctr + AES128CBC + AES128ECB + AES128CCM<'static>,
+        B: AES256<'static> + AES256Ctr + AES256CBC + AES256ECB + AES256CCM<'static>,
+        C: ChaCha20Poly1305<'static>,
+    > AesDriverComponent<A, B, C>
 {
     pub fn new(
         board_kernel: &'static kernel::Kernel,
         driver_num: usize,
         aes: &'static A,
-    ) -> AesDriverComponent<A> {
+        aes256: &'static B,
+        chacha: &'static C,
+    ) -> AesDriverComponent<A, B, C> {
         AesDriverComponent {
             board_kernel,
             driver_num,
             aes,
+            aes256,
+            chacha,
         }
     }
 }
@@ -128,14 +147,23 @@ impl<
             + AES128ECB
             + AES128CCM<'static>
             + AES128GCM<'static>,
-    > Component for AesDriverComponent<A>
+        B: AES256<'static>
+            + AES256Ctr
+            + AES256CBC
+            + AES256ECB
+            + AES256CCM<'static>
+            + AES256GCM<'static>,
+        C: ChaCha20Poly1305<'static>,
+    > Component for AesDriverComponent<A, B, C>
 {
     type StaticInput = (
-        &'static mut MaybeUninit<capsules_extra::symmetric_encryption::aes::AesDriver<'static, A>>,
+        &'static mut MaybeUninit<
+            capsules_extra::symmetric_encryption::aes::AesDriver<'static, A, B, C>,
+        >,
         &'static mut MaybeUninit<[u8; 16]>,
         &'static mut MaybeUninit<[u8; CRYPT_SIZE]>,
     );
-    type Output = &'static capsules_extra::symmetric_encryption::aes::AesDriver<'static, A>;
+    type Output = &'static capsules_extra::symmetric_encryption::aes::AesDriver<'static, A, B, C>;
 
     fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
         let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
@@ -147,6 +175,8 @@ impl<
                 .0
                 .write(capsules_extra::symmetric_encryption::aes::AesDriver::new(
                     self.aes,
+                    self.aes256,
+                    self.chacha,
                     aes_src_buf,
                     aes_dst_buf,
                     self.board_kernel.create_grant(self.driver_num, &grant_cap),
@@ -154,6 +184,10 @@ impl<
 
         hil::symmetric_encryption::AES128CCM::set_client(self.aes, aes_driver);
         hil::symmetric_encryption::AES128::set_client(self.aes, aes_driver);
+        hil::symmetric_encryption::AES256CCM::set_client(self.aes256, aes_driver);
+        hil::symmetric_encryption::AES256GCM::set_client(self.aes256, aes_driver);
+        hil::symmetric_encryption::AES256::set_client(self.aes256, aes_driver);
+        self.chacha.set_client(aes_driver);
 
         aes_driver
     }
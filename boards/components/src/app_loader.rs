@@ -0,0 +1,84 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the userspace application loader.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let app_loader = components::app_loader::AppLoaderComponent::new(
+//!     board_kernel,
+//!     capsules_extra::app_loader::DRIVER_NUM,
+//!     dynamic_binary_storage,
+//!     dynamic_binary_storage,
+//! )
+//! .finalize(components::app_loader_component_static!());
+//! ```
+
+use capsules_extra::app_loader::AppLoader;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::dynamic_binary_storage::{DynamicBinaryStore, DynamicProcessLoad};
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! app_loader_component_static {
+    () => {{
+        let app_loader = kernel::static_buf!(capsules_extra::app_loader::AppLoader<'static>);
+        let buffer = kernel::static_buf!([u8; capsules_extra::app_loader::BUF_LEN]);
+
+        (app_loader, buffer)
+    };};
+}
+
+pub type AppLoaderComponentType = AppLoader<'static>;
+
+pub struct AppLoaderComponent {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    storage: &'static dyn DynamicBinaryStore<'static>,
+    loader: &'static dyn DynamicProcessLoad<'static>,
+}
+
+impl AppLoaderComponent {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        storage: &'static dyn DynamicBinaryStore<'static>,
+        loader: &'static dyn DynamicProcessLoad<'static>,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            storage,
+            loader,
+        }
+    }
+}
+
+impl Component for AppLoaderComponent {
+    type StaticInput = (
+        &'static mut MaybeUninit<AppLoader<'static>>,
+        &'static mut MaybeUninit<[u8; capsules_extra::app_loader::BUF_LEN]>,
+    );
+    type Output = &'static AppLoader<'static>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let buffer = s.1.write([0; capsules_extra::app_loader::BUF_LEN]);
+        let app_loader = s.0.write(AppLoader::new(
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+            self.storage,
+            self.loader,
+            buffer,
+        ));
+        self.storage.set_storage_client(app_loader);
+        self.loader.set_load_client(app_loader);
+
+        app_loader
+    }
+}
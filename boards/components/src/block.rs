@@ -0,0 +1,318 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Components for block devices: adapters for flash, nonvolatile storage and
+//! SD cards, the block cache and partitions.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let sdcard_block = components::block::SDCardBlockComponent::new(sdcard).finalize(
+//!     components::sdcard_block_component_static!(VirtualMuxAlarm<'static, nrf52840::rtc::Rtc>),
+//! );
+//! type SDCardBlock = capsules_extra::block::sdcard::SDCardBlock<
+//!     'static,
+//!     VirtualMuxAlarm<'static, nrf52840::rtc::Rtc>,
+//! >;
+//!
+//! let mux_partitions = components::block::MuxPartitionsComponent::new(sdcard_block)
+//!     .finalize(components::mux_partitions_component_static!(SDCardBlock, 512));
+//! let partition = components::block::PartitionComponent::new(mux_partitions, 0)
+//!     .finalize(components::partition_component_static!(SDCardBlock));
+//!
+//! let cache = components::block::BlockCacheComponent::new(partition).finalize(
+//!     components::block_cache_component_static!(
+//!         capsules_extra::block::partitions::Partition<'static, SDCardBlock>,
+//!         4,
+//!         512
+//!     ),
+//! );
+//! let _ = mux_partitions.scan();
+//! ```
+//!
+//! Flash pages are made available as blocks with:
+//!
+//! ```rust
+//! let flash_block = components::block::FlashBlockComponent::new(
+//!     mx25r6435f,
+//!     0,    // First page.
+//!     2048, // Number of pages.
+//! )
+//! .finalize(components::flash_block_component_static!(Mx25r6435f));
+//! ```
+
+use capsules_extra::block::cache::BlockCache;
+use capsules_extra::block::flash::FlashBlock;
+use capsules_extra::block::nonvolatile::NonvolatileBlock;
+use capsules_extra::block::partitions::{MuxPartitions, Partition};
+use capsules_extra::block::sdcard::SDCardBlock;
+use capsules_extra::sdcard::SDCard;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::deferred_call::DeferredCallClient;
+use kernel::hil;
+use kernel::hil::block::BlockDevice;
+use kernel::hil::flash::HasClient;
+use kernel::hil::nonvolatile_storage::NonvolatileStorage;
+
+///////////////////////
+// Adapters
+///////////////////////
+
+#[macro_export]
+macro_rules! flash_block_component_static {
+    ($F:ty $(,)?) => {{
+        let block = kernel::static_buf!(capsules_extra::block::flash::FlashBlock<'static, $F>);
+        let page = kernel::static_buf!(<$F as kernel::hil::flash::Flash>::Page);
+
+        (block, page)
+    };};
+}
+
+pub struct FlashBlockComponent<
+    F: 'static + hil::flash::Flash + HasClient<'static, FlashBlock<'static, F>>,
+> {
+    flash: &'static F,
+    first_page: usize,
+    num_pages: usize,
+}
+
+impl<F: 'static + hil::flash::Flash + HasClient<'static, FlashBlock<'static, F>>>
+    FlashBlockComponent<F>
+{
+    pub fn new(flash: &'static F, first_page: usize, num_pages: usize) -> Self {
+        Self {
+            flash,
+            first_page,
+            num_pages,
+        }
+    }
+}
+
+impl<F: 'static + hil::flash::Flash + HasClient<'static, FlashBlock<'static, F>>> Component
+    for FlashBlockComponent<F>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<FlashBlock<'static, F>>,
+        &'static mut MaybeUninit<F::Page>,
+    );
+    type Output = &'static FlashBlock<'static, F>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let page = static_buffer.1.write(F::Page::default());
+        let block = static_buffer.0.write(FlashBlock::new(
+            self.flash,
+            page,
+            self.first_page,
+            self.num_pages,
+        ));
+        self.flash.set_client(block);
+        block
+    }
+}
+
+#[macro_export]
+macro_rules! nonvolatile_block_component_static {
+    ($BLOCK_SIZE:expr $(,)?) => {{
+        let block =
+            kernel::static_buf!(capsules_extra::block::nonvolatile::NonvolatileBlock<'static>);
+        let buffer = kernel::static_buf!([u8; $BLOCK_SIZE]);
+
+        (block, buffer)
+    };};
+}
+
+pub struct NonvolatileBlockComponent<const BLOCK_SIZE: usize> {
+    storage: &'static dyn NonvolatileStorage<'static>,
+    start: usize,
+    num_blocks: usize,
+}
+
+impl<const BLOCK_SIZE: usize> NonvolatileBlockComponent<BLOCK_SIZE> {
+    pub fn new(
+        storage: &'static dyn NonvolatileStorage<'static>,
+        start: usize,
+        num_blocks: usize,
+    ) -> Self {
+        Self {
+            storage,
+            start,
+            num_blocks,
+        }
+    }
+}
+
+impl<const BLOCK_SIZE: usize> Component for NonvolatileBlockComponent<BLOCK_SIZE> {
+    type StaticInput = (
+        &'static mut MaybeUninit<NonvolatileBlock<'static>>,
+        &'static mut MaybeUninit<[u8; BLOCK_SIZE]>,
+    );
+    type Output = &'static NonvolatileBlock<'static>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let buffer = static_buffer.1.write([0; BLOCK_SIZE]);
+        let block = static_buffer.0.write(NonvolatileBlock::new(
+            self.storage,
+            buffer,
+            self.start,
+            self.num_blocks,
+        ));
+        self.storage.set_client(block);
+        block
+    }
+}
+
+#[macro_export]
+macro_rules! sdcard_block_component_static {
+    ($A:ty $(,)?) => {{
+        kernel::static_buf!(capsules_extra::block::sdcard::SDCardBlock<'static, $A>)
+    };};
+}
+
+pub struct SDCardBlockComponent<A: 'static + hil::time::Alarm<'static>> {
+    sdcard: &'static SDCard<'static, A>,
+}
+
+impl<A: 'static + hil::time::Alarm<'static>> SDCardBlockComponent<A> {
+    pub fn new(sdcard: &'static SDCard<'static, A>) -> Self {
+        Self { sdcard }
+    }
+}
+
+impl<A: 'static + hil::time::Alarm<'static>> Component for SDCardBlockComponent<A> {
+    type StaticInput = &'static mut MaybeUninit<SDCardBlock<'static, A>>;
+    type Output = &'static SDCardBlock<'static, A>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let block = static_buffer.write(SDCardBlock::new(self.sdcard));
+        self.sdcard.set_client(block);
+        block
+    }
+}
+
+///////////////////////
+// Block Cache
+///////////////////////
+
+#[macro_export]
+macro_rules! block_cache_component_static {
+    ($B:ty, $N:expr, $BLOCK_SIZE:expr $(,)?) => {{
+        let cache = kernel::static_buf!(capsules_extra::block::cache::BlockCache<'static, $B, $N>);
+        let slots = kernel::static_buf!([[u8; $BLOCK_SIZE]; $N]);
+
+        (cache, slots)
+    };};
+}
+
+pub struct BlockCacheComponent<
+    B: 'static + BlockDevice<'static>,
+    const N: usize,
+    const BLOCK_SIZE: usize,
+> {
+    device: &'static B,
+}
+
+impl<B: 'static + BlockDevice<'static>, const N: usize, const BLOCK_SIZE: usize>
+    BlockCacheComponent<B, N, BLOCK_SIZE>
+{
+    pub fn new(device: &'static B) -> Self {
+        Self { device }
+    }
+}
+
+impl<B: 'static + BlockDevice<'static>, const N: usize, const BLOCK_SIZE: usize> Component
+    for BlockCacheComponent<B, N, BLOCK_SIZE>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<BlockCache<'static, B, N>>,
+        &'static mut MaybeUninit<[[u8; BLOCK_SIZE]; N]>,
+    );
+    type Output = &'static BlockCache<'static, B, N>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let slots = static_buffer.1.write([[0; BLOCK_SIZE]; N]);
+        let cache = static_buffer
+            .0
+            .write(BlockCache::new(self.device, slots.as_flattened_mut()));
+        self.device.set_client(cache);
+        cache.register();
+        cache
+    }
+}
+
+///////////////////////
+// Partitions
+///////////////////////
+
+#[macro_export]
+macro_rules! mux_partitions_component_static {
+    ($B:ty, $BLOCK_SIZE:expr $(,)?) => {{
+        let mux =
+            kernel::static_buf!(capsules_extra::block::partitions::MuxPartitions<'static, $B>);
+        let table = kernel::static_buf!([u8; $BLOCK_SIZE]);
+
+        (mux, table)
+    };};
+}
+
+#[macro_export]
+macro_rules! partition_component_static {
+    ($B:ty $(,)?) => {{
+        kernel::static_buf!(capsules_extra::block::partitions::Partition<'static, $B>)
+    };};
+}
+
+pub struct MuxPartitionsComponent<B: 'static + BlockDevice<'static>, const BLOCK_SIZE: usize> {
+    device: &'static B,
+}
+
+impl<B: 'static + BlockDevice<'static>, const BLOCK_SIZE: usize>
+    MuxPartitionsComponent<B, BLOCK_SIZE>
+{
+    pub fn new(device: &'static B) -> Self {
+        Self { device }
+    }
+}
+
+impl<B: 'static + BlockDevice<'static>, const BLOCK_SIZE: usize> Component
+    for MuxPartitionsComponent<B, BLOCK_SIZE>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<MuxPartitions<'static, B>>,
+        &'static mut MaybeUninit<[u8; BLOCK_SIZE]>,
+    );
+    type Output = &'static MuxPartitions<'static, B>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let table = static_buffer.1.write([0; BLOCK_SIZE]);
+        let mux = static_buffer
+            .0
+            .write(MuxPartitions::new(self.device, table));
+        self.device.set_client(mux);
+        mux
+    }
+}
+
+pub struct PartitionComponent<B: 'static + BlockDevice<'static>> {
+    mux: &'static MuxPartitions<'static, B>,
+    index: usize,
+}
+
+impl<B: 'static + BlockDevice<'static>> PartitionComponent<B> {
+    /// The partition of entry `index` of the partition table.
+    pub fn new(mux: &'static MuxPartitions<'static, B>, index: usize) -> Self {
+        Self { mux, index }
+    }
+}
+
+impl<B: 'static + BlockDevice<'static>> Component for PartitionComponent<B> {
+    type StaticInput = &'static mut MaybeUninit<Partition<'static, B>>;
+    type Output = &'static Partition<'static, B>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let partition = static_buffer.write(Partition::new(self.mux, self.index));
+        partition.setup();
+        partition
+    }
+}
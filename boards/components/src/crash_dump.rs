@@ -0,0 +1,106 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Components for crash dumps.
+//!
+//! The memory for the dumps must be kept across resets, which the
+//! `.crash_dump` linker section is for.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! #[link_section = ".crash_dump"]
+//! static mut CRASH_DUMP_MEMORY: [u8; 0x2000] = [0; 0x2000];
+//!
+//! let crash_dump = components::crash_dump::CrashDumpComponent::new(
+//!     &mut *addr_of_mut!(CRASH_DUMP_MEMORY),
+//!     process_printer,
+//!     512,
+//! )
+//! .finalize(components::crash_dump_component_static!());
+//! pconsole.set_crash_dumps(crash_dump);
+//!
+//! let fault_policy = components::crash_dump::CrashDumpFaultPolicyComponent::new(
+//!     crash_dump,
+//!     capsules_system::process_policies::StopFaultPolicy {},
+//! )
+//! .finalize(components::crash_dump_fault_policy_component_static!(
+//!     capsules_system::process_policies::StopFaultPolicy
+//! ));
+//! ```
+
+use capsules_system::crash_dump::{CrashDump, CrashDumpFaultPolicy};
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::process::{ProcessFaultPolicy, ProcessPrinter};
+
+#[macro_export]
+macro_rules! crash_dump_component_static {
+    () => {{
+        kernel::static_buf!(capsules_system::crash_dump::CrashDump<'static>)
+    };};
+}
+
+pub struct CrashDumpComponent {
+    region: &'static mut [u8],
+    process_printer: &'static dyn ProcessPrinter,
+    stack_len: usize,
+}
+
+impl CrashDumpComponent {
+    /// Keep crash dumps in `region`, with up to `stack_len` bytes of stack
+    /// each.
+    pub fn new(
+        region: &'static mut [u8],
+        process_printer: &'static dyn ProcessPrinter,
+        stack_len: usize,
+    ) -> Self {
+        Self {
+            region,
+            process_printer,
+            stack_len,
+        }
+    }
+}
+
+impl Component for CrashDumpComponent {
+    type StaticInput = &'static mut MaybeUninit<CrashDump<'static>>;
+    type Output = &'static CrashDump<'static>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        static_buffer.write(CrashDump::new(
+            self.region,
+            self.process_printer,
+            self.stack_len,
+        ))
+    }
+}
+
+#[macro_export]
+macro_rules! crash_dump_fault_policy_component_static {
+    ($P:ty $(,)?) => {{
+        kernel::static_buf!(capsules_system::crash_dump::CrashDumpFaultPolicy<'static, $P>)
+    };};
+}
+
+pub struct CrashDumpFaultPolicyComponent<P: 'static + ProcessFaultPolicy> {
+    crash_dump: &'static CrashDump<'static>,
+    policy: P,
+}
+
+impl<P: 'static + ProcessFaultPolicy> CrashDumpFaultPolicyComponent<P> {
+    /// Record a crash dump of faulted processes, then follow `policy`.
+    pub fn new(crash_dump: &'static CrashDump<'static>, policy: P) -> Self {
+        Self { crash_dump, policy }
+    }
+}
+
+impl<P: 'static + ProcessFaultPolicy> Component for CrashDumpFaultPolicyComponent<P> {
+    type StaticInput = &'static mut MaybeUninit<CrashDumpFaultPolicy<'static, P>>;
+    type Output = &'static CrashDumpFaultPolicy<'static, P>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        static_buffer.write(CrashDumpFaultPolicy::new(self.crash_dump, self.policy))
+    }
+}
@@ -0,0 +1,106 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for storing and loading new process binaries at runtime.
+//!
+//! `SequentialBinaryStorageComponent` writes new process binaries into the
+//! app flash region through the chip's flash controller and loads them with
+//! the process loader.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let dynamic_binary_storage =
+//!     components::dynamic_binary_storage::SequentialBinaryStorageComponent::new(
+//!         &base_peripherals.nvmc,
+//!         loader,
+//!     )
+//!     .finalize(components::sequential_binary_storage_component_static!(
+//!         nrf52840::nvmc::Nvmc,
+//!     ));
+//! ```
+
+use capsules_extra::nonvolatile_to_pages::NonvolatileToPages;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::dynamic_binary_storage::{SequentialDynamicBinaryStorage, PADDING_TBF_HEADER_LENGTH};
+use kernel::hil;
+use kernel::process::ProcessLoadingDynamic;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! sequential_binary_storage_component_static {
+    ($F:ty $(,)?) => {{
+        let page = kernel::static_buf!(<$F as kernel::hil::flash::Flash>::Page);
+        let ntp = kernel::static_buf!(
+            capsules_extra::nonvolatile_to_pages::NonvolatileToPages<'static, $F>
+        );
+        let storage = kernel::static_buf!(
+            kernel::dynamic_binary_storage::SequentialDynamicBinaryStorage<
+                'static,
+                capsules_extra::nonvolatile_to_pages::NonvolatileToPages<'static, $F>,
+            >
+        );
+        let buffer =
+            kernel::static_buf!([u8; kernel::dynamic_binary_storage::PADDING_TBF_HEADER_LENGTH]);
+
+        (page, ntp, storage, buffer)
+    };};
+}
+
+pub type SequentialBinaryStorageComponentType<F> =
+    SequentialDynamicBinaryStorage<'static, NonvolatileToPages<'static, F>>;
+
+pub struct SequentialBinaryStorageComponent<
+    F: 'static + hil::flash::Flash + hil::flash::HasClient<'static, NonvolatileToPages<'static, F>>,
+> {
+    flash: &'static F,
+    loader: &'static dyn ProcessLoadingDynamic<'static>,
+}
+
+impl<
+        F: 'static
+            + hil::flash::Flash
+            + hil::flash::HasClient<'static, NonvolatileToPages<'static, F>>,
+    > SequentialBinaryStorageComponent<F>
+{
+    pub fn new(flash: &'static F, loader: &'static dyn ProcessLoadingDynamic<'static>) -> Self {
+        Self { flash, loader }
+    }
+}
+
+impl<
+        F: 'static
+            + hil::flash::Flash
+            + hil::flash::HasClient<'static, NonvolatileToPages<'static, F>>,
+    > Component for SequentialBinaryStorageComponent<F>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<<F as hil::flash::Flash>::Page>,
+        &'static mut MaybeUninit<NonvolatileToPages<'static, F>>,
+        &'static mut MaybeUninit<
+            SequentialDynamicBinaryStorage<'static, NonvolatileToPages<'static, F>>,
+        >,
+        &'static mut MaybeUninit<[u8; PADDING_TBF_HEADER_LENGTH]>,
+    );
+    type Output = &'static SequentialDynamicBinaryStorage<'static, NonvolatileToPages<'static, F>>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let flash_pagebuffer = s.0.write(<F as hil::flash::Flash>::Page::default());
+        let nv_to_page =
+            s.1.write(NonvolatileToPages::new(self.flash, flash_pagebuffer));
+        hil::flash::HasClient::set_client(self.flash, nv_to_page);
+
+        let buffer = s.3.write([0; PADDING_TBF_HEADER_LENGTH]);
+        let storage = s.2.write(SequentialDynamicBinaryStorage::new(
+            nv_to_page,
+            self.loader,
+            buffer,
+        ));
+        hil::nonvolatile_storage::NonvolatileStorage::set_client(nv_to_page, storage);
+        self.loader.set_dynamic_client(storage);
+
+        storage
+    }
+}
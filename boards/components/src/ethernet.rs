@@ -0,0 +1,131 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Components for Ethernet adapters.
+//!
+//! This provides two components, `MuxEthernetComponent`, which provides a
+//! multiplexed interface to an Ethernet adapter, and `EthernetTapComponent`,
+//! which provides a system call interface to transmit and receive raw
+//! Ethernet frames.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let mux_eth = components::ethernet::MuxEthernetComponent::new(virtio_net)
+//!     .finalize(components::mux_ethernet_component_static!());
+//! let tap = components::ethernet::EthernetTapComponent::new(
+//!     board_kernel,
+//!     capsules_extra::ethernet_tap::DRIVER_NUM,
+//!     mux_eth,
+//! )
+//! .finalize(components::ethernet_tap_component_static!());
+//! ```
+
+use capsules_extra::ethernet_tap::EthernetTapDriver;
+use capsules_extra::net::ethernet::virtual_ethernet::{EthernetUser, MuxEthernet};
+use capsules_extra::net::ethernet::ETHERNET_MAX_FRAME_LEN;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::ethernet::EthernetAdapterDatapath;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! mux_ethernet_component_static {
+    () => {{
+        kernel::static_buf!(capsules_extra::net::ethernet::virtual_ethernet::MuxEthernet<'static>)
+    };};
+}
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! ethernet_tap_component_static {
+    () => {{
+        let eth_user = kernel::static_buf!(
+            capsules_extra::net::ethernet::virtual_ethernet::EthernetUser<'static>
+        );
+        let tap = kernel::static_buf!(
+            capsules_extra::ethernet_tap::EthernetTapDriver<
+                'static,
+                capsules_extra::net::ethernet::virtual_ethernet::EthernetUser<'static>,
+            >
+        );
+        let tx_buf =
+            kernel::static_buf!([u8; capsules_extra::net::ethernet::ETHERNET_MAX_FRAME_LEN]);
+
+        (eth_user, tap, tx_buf)
+    };};
+}
+
+pub type EthernetTapComponentType =
+    capsules_extra::ethernet_tap::EthernetTapDriver<'static, EthernetUser<'static>>;
+
+pub struct MuxEthernetComponent<E: 'static + EthernetAdapterDatapath<'static>> {
+    adapter: &'static E,
+}
+
+impl<E: 'static + EthernetAdapterDatapath<'static>> MuxEthernetComponent<E> {
+    pub fn new(adapter: &'static E) -> MuxEthernetComponent<E> {
+        MuxEthernetComponent { adapter }
+    }
+}
+
+impl<E: 'static + EthernetAdapterDatapath<'static>> Component for MuxEthernetComponent<E> {
+    type StaticInput = &'static mut MaybeUninit<MuxEthernet<'static>>;
+    type Output = &'static MuxEthernet<'static>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let mux_eth = static_buffer.write(MuxEthernet::new(self.adapter));
+
+        self.adapter.set_client(mux_eth);
+        mux_eth
+    }
+}
+
+pub struct EthernetTapComponent {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    mux_eth: &'static MuxEthernet<'static>,
+}
+
+impl EthernetTapComponent {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        mux_eth: &'static MuxEthernet<'static>,
+    ) -> EthernetTapComponent {
+        EthernetTapComponent {
+            board_kernel,
+            driver_num,
+            mux_eth,
+        }
+    }
+}
+
+impl Component for EthernetTapComponent {
+    type StaticInput = (
+        &'static mut MaybeUninit<EthernetUser<'static>>,
+        &'static mut MaybeUninit<EthernetTapDriver<'static, EthernetUser<'static>>>,
+        &'static mut MaybeUninit<[u8; ETHERNET_MAX_FRAME_LEN]>,
+    );
+    type Output = &'static EthernetTapDriver<'static, EthernetUser<'static>>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let eth_user = s.0.write(EthernetUser::new(self.mux_eth));
+        self.mux_eth.add_user(eth_user);
+
+        let tx_buf = s.2.write([0; ETHERNET_MAX_FRAME_LEN]);
+        let tap = s.1.write(EthernetTapDriver::new(
+            eth_user,
+            tx_buf,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        eth_user.set_client(tap);
+
+        tap
+    }
+}
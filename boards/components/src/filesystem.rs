@@ -0,0 +1,238 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Components for the flash filesystem, the FAT filesystem on block devices
+//! and the filesystem userspace driver.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let mux_flash = components::flash::FlashMuxComponent::new(&base_peripherals.nvmc)
+//!     .finalize(components::flash_mux_component_static!(nrf52840::nvmc::Nvmc));
+//!
+//! let flash_fs = components::filesystem::FlashFsComponent::new(
+//!     mux_flash,
+//!     0xc0, // First page of the filesystem.
+//!     16,   // Number of pages.
+//! )
+//! .finalize(components::flash_fs_component_static!(nrf52840::nvmc::Nvmc));
+//!
+//! let filesystem = components::filesystem::FileSystemDriverComponent::new(
+//!     flash_fs,
+//!     board_kernel,
+//!     capsules_extra::filesystem_driver::DRIVER_NUM,
+//! )
+//! .finalize(components::filesystem_driver_component_static!(
+//!     components::filesystem::FlashFsComponentType<nrf52840::nvmc::Nvmc>
+//! ));
+//! ```
+//!
+//! A FAT filesystem on an SD card is set up with:
+//!
+//! ```rust
+//! let sdcard_block = components::block::SDCardBlockComponent::new(sdcard).finalize(
+//!     components::sdcard_block_component_static!(VirtualMuxAlarm<'static, nrf52840::rtc::Rtc>),
+//! );
+//! let fat = components::filesystem::FatFsComponent::new(sdcard_block).finalize(
+//!     components::fat_fs_component_static!(
+//!         capsules_extra::block::sdcard::SDCardBlock<
+//!             'static,
+//!             VirtualMuxAlarm<'static, nrf52840::rtc::Rtc>,
+//!         >
+//!     ),
+//! );
+//! ```
+
+use capsules_core::virtualizers::virtual_flash::{FlashUser, MuxFlash};
+use capsules_extra::fat::{FatFs, SECTOR_SIZE};
+use capsules_extra::filesystem_driver::FileSystemDriver;
+use capsules_extra::flash_fs::FlashFs;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::deferred_call::DeferredCallClient;
+use kernel::hil;
+use kernel::hil::flash::HasClient;
+
+///////////////////////
+// Flash Filesystem
+///////////////////////
+
+#[macro_export]
+macro_rules! flash_fs_component_static {
+    ($F:ty $(,)?) => {{
+        let flash =
+            kernel::static_buf!(capsules_core::virtualizers::virtual_flash::FlashUser<'static, $F>);
+        let fs = kernel::static_buf!(
+            capsules_extra::flash_fs::FlashFs<
+                'static,
+                capsules_core::virtualizers::virtual_flash::FlashUser<'static, $F>,
+            >
+        );
+        let meta_page = kernel::static_buf!(<$F as kernel::hil::flash::Flash>::Page);
+        let page = kernel::static_buf!(<$F as kernel::hil::flash::Flash>::Page);
+
+        (flash, fs, meta_page, page)
+    };};
+}
+
+pub type FlashFsComponentType<F> = FlashFs<'static, FlashUser<'static, F>>;
+
+pub struct FlashFsComponent<
+    F: 'static + hil::flash::Flash + hil::flash::HasClient<'static, MuxFlash<'static, F>>,
+> {
+    mux_flash: &'static MuxFlash<'static, F>,
+    first_page: usize,
+    page_count: usize,
+}
+
+impl<F: 'static + hil::flash::Flash + hil::flash::HasClient<'static, MuxFlash<'static, F>>>
+    FlashFsComponent<F>
+{
+    pub fn new(
+        mux_flash: &'static MuxFlash<'static, F>,
+        first_page: usize,
+        page_count: usize,
+    ) -> Self {
+        Self {
+            mux_flash,
+            first_page,
+            page_count,
+        }
+    }
+}
+
+impl<F: 'static + hil::flash::Flash + hil::flash::HasClient<'static, MuxFlash<'static, F>>>
+    Component for FlashFsComponent<F>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<FlashUser<'static, F>>,
+        &'static mut MaybeUninit<FlashFs<'static, FlashUser<'static, F>>>,
+        &'static mut MaybeUninit<F::Page>,
+        &'static mut MaybeUninit<F::Page>,
+    );
+    type Output = &'static FlashFs<'static, FlashUser<'static, F>>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let virtual_flash = static_buffer.0.write(FlashUser::new(self.mux_flash));
+        let meta_page = static_buffer.2.write(F::Page::default());
+        let page = static_buffer.3.write(F::Page::default());
+
+        let fs = static_buffer.1.write(FlashFs::new(
+            virtual_flash,
+            self.first_page,
+            self.page_count,
+            meta_page,
+            page,
+        ));
+        virtual_flash.set_client(fs);
+        fs.register();
+        fs
+    }
+}
+
+///////////////////////
+// FAT Filesystem
+///////////////////////
+
+#[macro_export]
+macro_rules! fat_fs_component_static {
+    ($B:ty $(,)?) => {{
+        let fs = kernel::static_buf!(capsules_extra::fat::FatFs<'static, $B>);
+        let buffer0 = kernel::static_buf!([u8; capsules_extra::fat::SECTOR_SIZE]);
+        let buffer1 = kernel::static_buf!([u8; capsules_extra::fat::SECTOR_SIZE]);
+
+        (fs, buffer0, buffer1)
+    };};
+}
+
+pub struct FatFsComponent<B: 'static + hil::block::BlockDevice<'static>> {
+    device: &'static B,
+}
+
+impl<B: 'static + hil::block::BlockDevice<'static>> FatFsComponent<B> {
+    pub fn new(device: &'static B) -> Self {
+        Self { device }
+    }
+}
+
+impl<B: 'static + hil::block::BlockDevice<'static>> Component for FatFsComponent<B> {
+    type StaticInput = (
+        &'static mut MaybeUninit<FatFs<'static, B>>,
+        &'static mut MaybeUninit<[u8; SECTOR_SIZE]>,
+        &'static mut MaybeUninit<[u8; SECTOR_SIZE]>,
+    );
+    type Output = &'static FatFs<'static, B>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let buffer0 = static_buffer.1.write([0; SECTOR_SIZE]);
+        let buffer1 = static_buffer.2.write([0; SECTOR_SIZE]);
+
+        let fs = static_buffer
+            .0
+            .write(FatFs::new(self.device, buffer0, buffer1));
+        self.device.set_client(fs);
+        fs.register();
+        fs
+    }
+}
+
+///////////////////////////////
+// Filesystem Userspace Driver
+///////////////////////////////
+
+#[macro_export]
+macro_rules! filesystem_driver_component_static {
+    ($FS:ty $(,)?) => {{
+        let driver =
+            kernel::static_buf!(capsules_extra::filesystem_driver::FileSystemDriver<'static, $FS>);
+        let buffer = kernel::static_buf!([u8; 512]);
+
+        (driver, buffer)
+    };};
+}
+
+pub type FileSystemDriverComponentType<FS> = FileSystemDriver<'static, FS>;
+
+pub struct FileSystemDriverComponent<FS: hil::filesystem::FileSystem<'static> + 'static> {
+    fs: &'static FS,
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+}
+
+impl<FS: hil::filesystem::FileSystem<'static>> FileSystemDriverComponent<FS> {
+    pub fn new(fs: &'static FS, board_kernel: &'static kernel::Kernel, driver_num: usize) -> Self {
+        Self {
+            fs,
+            board_kernel,
+            driver_num,
+        }
+    }
+}
+
+impl<FS: hil::filesystem::FileSystem<'static>> Component for FileSystemDriverComponent<FS> {
+    type StaticInput = (
+        &'static mut MaybeUninit<FileSystemDriver<'static, FS>>,
+        &'static mut MaybeUninit<[u8; 512]>,
+    );
+    type Output = &'static FileSystemDriver<'static, FS>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let buffer = static_buffer.1.write([0; 512]);
+
+        let driver = static_buffer.0.write(FileSystemDriver::new(
+            self.fs,
+            buffer,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        self.fs.set_client(driver);
+        // The filesystem refuses requests until it has been mounted, failures
+        // are reported to apps as errors of their requests.
+        let _ = self.fs.mount();
+        driver
+    }
+}
@@ -0,0 +1,95 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for GdbStub, the GDB remote serial protocol stub for debugging
+//! processes.
+//!
+//! GDB should use its own UART, as other output on the same UART confuses it.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let gdb_stub = GdbStubComponent::new(board_kernel, uart_mux)
+//!     .finalize(components::gdb_stub_component_static!());
+//! let _ = gdb_stub.start();
+//! ```
+
+use capsules_core::virtualizers::virtual_uart::{MuxUart, UartDevice};
+use capsules_system::gdb_stub::{self, GdbStub};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::hil;
+
+#[macro_export]
+macro_rules! gdb_stub_component_static {
+    () => {{
+        let uart = kernel::static_buf!(capsules_core::virtualizers::virtual_uart::UartDevice);
+        let rx_buffer = kernel::static_buf!([u8; capsules_system::gdb_stub::RX_BUF_LEN]);
+        let tx_buffer = kernel::static_buf!([u8; capsules_system::gdb_stub::TX_BUF_LEN]);
+        let packet_buffer = kernel::static_buf!([u8; capsules_system::gdb_stub::PACKET_BUF_LEN]);
+        let gdb_stub = kernel::static_buf!(
+            capsules_system::gdb_stub::GdbStub<
+                'static,
+                capsules_core::virtualizers::virtual_uart::UartDevice<'static>,
+                components::gdb_stub::Capability,
+            >
+        );
+
+        (uart, rx_buffer, tx_buffer, packet_buffer, gdb_stub)
+    };};
+}
+
+pub struct Capability;
+unsafe impl capabilities::ProcessManagementCapability for Capability {}
+
+pub struct GdbStubComponent {
+    board_kernel: &'static kernel::Kernel,
+    uart_mux: &'static MuxUart<'static>,
+}
+
+impl GdbStubComponent {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        uart_mux: &'static MuxUart<'static>,
+    ) -> GdbStubComponent {
+        GdbStubComponent {
+            board_kernel,
+            uart_mux,
+        }
+    }
+}
+
+impl Component for GdbStubComponent {
+    type StaticInput = (
+        &'static mut MaybeUninit<UartDevice<'static>>,
+        &'static mut MaybeUninit<[u8; gdb_stub::RX_BUF_LEN]>,
+        &'static mut MaybeUninit<[u8; gdb_stub::TX_BUF_LEN]>,
+        &'static mut MaybeUninit<[u8; gdb_stub::PACKET_BUF_LEN]>,
+        &'static mut MaybeUninit<GdbStub<'static, UartDevice<'static>, Capability>>,
+    );
+    type Output = &'static GdbStub<'static, UartDevice<'static>, Capability>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let uart = static_buffer.0.write(UartDevice::new(self.uart_mux, true));
+        uart.setup();
+
+        let rx_buffer = static_buffer.1.write([0; gdb_stub::RX_BUF_LEN]);
+        let tx_buffer = static_buffer.2.write([0; gdb_stub::TX_BUF_LEN]);
+        let packet_buffer = static_buffer.3.write([0; gdb_stub::PACKET_BUF_LEN]);
+
+        let gdb_stub = static_buffer.4.write(GdbStub::new(
+            uart,
+            self.board_kernel,
+            Capability,
+            rx_buffer,
+            tx_buffer,
+            packet_buffer,
+        ));
+        hil::uart::Transmit::set_transmit_client(uart, gdb_stub);
+        hil::uart::Receive::set_receive_client(uart, gdb_stub);
+
+        gdb_stub
+    }
+}
@@ -153,3 +153,34 @@ impl<
         hmac_sha256_sw
     }
 }
+
+#[macro_export]
+macro_rules! hmac_sha512_software_component_static {
+    ($(,)?) => {{
+        kernel::static_buf!(capsules_extra::hmac_sha512::HmacSha512Software<'static>)
+    };};
+}
+
+pub type HmacSha512SoftwareComponentType = capsules_extra::hmac_sha512::HmacSha512Software<'static>;
+
+pub struct HmacSha512SoftwareComponent {}
+
+impl HmacSha512SoftwareComponent {
+    pub fn new() -> HmacSha512SoftwareComponent {
+        HmacSha512SoftwareComponent {}
+    }
+}
+
+impl Component for HmacSha512SoftwareComponent {
+    type StaticInput =
+        &'static mut MaybeUninit<capsules_extra::hmac_sha512::HmacSha512Software<'static>>;
+    type Output = &'static capsules_extra::hmac_sha512::HmacSha512Software<'static>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let hmac_sha512_sw = s.write(capsules_extra::hmac_sha512::HmacSha512Software::new());
+
+        kernel::deferred_call::DeferredCallClient::register(hmac_sha512_sw);
+
+        hmac_sha512_sw
+    }
+}
@@ -0,0 +1,80 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Component to initialize the userland ICMPv6 driver.
+//!
+//! This provides one Component, ICMP6DriverComponent. This component
+//! initializes a userspace ICMPv6 driver that delivers the ICMPv6 messages
+//! received by the ICMPv6 layer to apps.
+//!
+//! Usage
+//! -----
+//! ```rust
+//!    let icmp_driver = ICMP6DriverComponent::new(
+//!        board_kernel,
+//!        capsules_extra::net::icmpv6::DRIVER_NUM,
+//!        icmp_recv,
+//!    )
+//!    .finalize(components::icmpv6_driver_component_static!());
+//! ```
+
+use capsules_extra::net::icmpv6::icmpv6_recv::{ICMP6Receiver, ICMP6RecvStruct};
+use capsules_extra::net::icmpv6::ICMP6Driver;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! icmpv6_driver_component_static {
+    () => {{
+        let icmp_rcvr =
+            kernel::static_buf!(capsules_extra::net::icmpv6::icmpv6_recv::ICMP6Receiver<'static>);
+        let icmp_driver = kernel::static_buf!(capsules_extra::net::icmpv6::ICMP6Driver);
+
+        (icmp_rcvr, icmp_driver)
+    };};
+}
+
+pub struct ICMP6DriverComponent {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    icmp_recv: &'static ICMP6RecvStruct<'static>,
+}
+
+impl ICMP6DriverComponent {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        icmp_recv: &'static ICMP6RecvStruct<'static>,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            icmp_recv,
+        }
+    }
+}
+
+impl Component for ICMP6DriverComponent {
+    type StaticInput = (
+        &'static mut MaybeUninit<ICMP6Receiver<'static>>,
+        &'static mut MaybeUninit<ICMP6Driver>,
+    );
+    type Output = &'static ICMP6Driver;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let icmp_driver = s.1.write(ICMP6Driver::new(
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+
+        let icmp_rcvr = s.0.write(ICMP6Receiver::new());
+        icmp_rcvr.set_client(icmp_driver);
+        self.icmp_recv.add_client(icmp_rcvr);
+        icmp_driver
+    }
+}
@@ -0,0 +1,62 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for message-passing IPC through kernel-copied mailboxes.
+//!
+//! Each process that uses the driver gets a mailbox of `SLOTS` messages of
+//! up to `MSG_LEN` bytes in its grant region.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let ipc_mailbox = components::ipc_mailbox::IpcMailboxComponent::new(
+//!     board_kernel,
+//!     capsules_extra::ipc_mailbox::DRIVER_NUM,
+//! )
+//! .finalize(components::ipc_mailbox_component_static!(64, 4));
+//! ```
+
+use capsules_extra::ipc_mailbox::IpcMailbox;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! ipc_mailbox_component_static {
+    ($MSG_LEN:expr, $SLOTS:expr $(,)?) => {{
+        kernel::static_buf!(capsules_extra::ipc_mailbox::IpcMailbox<$MSG_LEN, $SLOTS>)
+    };};
+}
+
+pub type IpcMailboxComponentType<const MSG_LEN: usize, const SLOTS: usize> =
+    IpcMailbox<MSG_LEN, SLOTS>;
+
+pub struct IpcMailboxComponent<const MSG_LEN: usize, const SLOTS: usize> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+}
+
+impl<const MSG_LEN: usize, const SLOTS: usize> IpcMailboxComponent<MSG_LEN, SLOTS> {
+    pub fn new(board_kernel: &'static kernel::Kernel, driver_num: usize) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+        }
+    }
+}
+
+impl<const MSG_LEN: usize, const SLOTS: usize> Component for IpcMailboxComponent<MSG_LEN, SLOTS> {
+    type StaticInput = &'static mut MaybeUninit<IpcMailbox<MSG_LEN, SLOTS>>;
+    type Output = &'static IpcMailbox<MSG_LEN, SLOTS>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        s.write(IpcMailbox::new(
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ))
+    }
+}
@@ -0,0 +1,133 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Components for the HKDF-SHA256 and PBKDF2-HMAC-SHA256 key derivation
+//! capsules.
+//!
+//! Both take over the client of the HMAC engine they are given, so that
+//! engine must not be shared with other users. The buffer length `$L` bounds
+//! the info and input keying material (HKDF) or salt (PBKDF2) that can be
+//! used; see the capsules for the exact layout.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let hkdf = components::kdf::HkdfSha256Component::<_, 128>::new(hmac)
+//!     .finalize(components::hkdf_sha256_component_static!(
+//!         HmacSha256Software<'static, Sha256Software<'static>>,
+//!         128
+//!     ));
+//!
+//! let pbkdf2 = components::kdf::Pbkdf2Sha256Component::<_, 64>::new(hmac)
+//!     .finalize(components::pbkdf2_sha256_component_static!(
+//!         HmacSha256Software<'static, Sha256Software<'static>>,
+//!         64
+//!     ));
+//! ```
+
+use capsules_extra::hkdf::HkdfSha256;
+use capsules_extra::pbkdf2::Pbkdf2Sha256;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::digest;
+
+#[macro_export]
+macro_rules! hkdf_sha256_component_static {
+    ($H:ty, $L:expr $(,)?) => {{
+        let hkdf = kernel::static_buf!(capsules_extra::hkdf::HkdfSha256<'static, $H>);
+        let data_buffer = kernel::static_buf!([u8; $L]);
+        let digest_buffer = kernel::static_buf!([u8; 32]);
+
+        (hkdf, data_buffer, digest_buffer)
+    };};
+}
+
+#[macro_export]
+macro_rules! pbkdf2_sha256_component_static {
+    ($H:ty, $L:expr $(,)?) => {{
+        let pbkdf2 = kernel::static_buf!(capsules_extra::pbkdf2::Pbkdf2Sha256<'static, $H>);
+        let data_buffer = kernel::static_buf!([u8; $L]);
+        let digest_buffer = kernel::static_buf!([u8; 32]);
+
+        (pbkdf2, data_buffer, digest_buffer)
+    };};
+}
+
+pub type HkdfSha256ComponentType<H> = HkdfSha256<'static, H>;
+
+pub struct HkdfSha256Component<
+    H: digest::Digest<'static, 32> + digest::HmacSha256 + 'static,
+    const BUF_LEN: usize,
+> {
+    hmac: &'static H,
+}
+
+impl<H: digest::Digest<'static, 32> + digest::HmacSha256, const BUF_LEN: usize>
+    HkdfSha256Component<H, BUF_LEN>
+{
+    pub fn new(hmac: &'static H) -> Self {
+        Self { hmac }
+    }
+}
+
+impl<H: digest::Digest<'static, 32> + digest::HmacSha256, const BUF_LEN: usize> Component
+    for HkdfSha256Component<H, BUF_LEN>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<HkdfSha256<'static, H>>,
+        &'static mut MaybeUninit<[u8; BUF_LEN]>,
+        &'static mut MaybeUninit<[u8; 32]>,
+    );
+    type Output = &'static HkdfSha256<'static, H>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let data_buffer = s.1.write([0; BUF_LEN]);
+        let digest_buffer = s.2.write([0; 32]);
+
+        let hkdf =
+            s.0.write(HkdfSha256::new(self.hmac, data_buffer, digest_buffer));
+        digest::Digest::set_client(self.hmac, hkdf);
+
+        hkdf
+    }
+}
+
+pub type Pbkdf2Sha256ComponentType<H> = Pbkdf2Sha256<'static, H>;
+
+pub struct Pbkdf2Sha256Component<
+    H: digest::Digest<'static, 32> + digest::HmacSha256 + 'static,
+    const BUF_LEN: usize,
+> {
+    hmac: &'static H,
+}
+
+impl<H: digest::Digest<'static, 32> + digest::HmacSha256, const BUF_LEN: usize>
+    Pbkdf2Sha256Component<H, BUF_LEN>
+{
+    pub fn new(hmac: &'static H) -> Self {
+        Self { hmac }
+    }
+}
+
+impl<H: digest::Digest<'static, 32> + digest::HmacSha256, const BUF_LEN: usize> Component
+    for Pbkdf2Sha256Component<H, BUF_LEN>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<Pbkdf2Sha256<'static, H>>,
+        &'static mut MaybeUninit<[u8; BUF_LEN]>,
+        &'static mut MaybeUninit<[u8; 32]>,
+    );
+    type Output = &'static Pbkdf2Sha256<'static, H>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let data_buffer = s.1.write([0; BUF_LEN]);
+        let digest_buffer = s.2.write([0; 32]);
+
+        let pbkdf2 =
+            s.0.write(Pbkdf2Sha256::new(self.hmac, data_buffer, digest_buffer));
+        digest::Digest::set_client(self.hmac, pbkdf2);
+
+        pbkdf2
+    }
+}
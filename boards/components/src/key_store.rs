@@ -0,0 +1,125 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the key store capsule.
+//!
+//! The key store needs its own k-v store user, AES-256-GCM engine and RNG
+//! device. The key-encryption key should be a device-unique secret, such as
+//! one derived by a hardware key manager: keys sealed under it are only as
+//! safe as it is.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let key_store = components::key_store::KeyStoreComponent::new(
+//!     board_kernel,
+//!     capsules_extra::key_store::DRIVER_NUM,
+//!     virtual_kv_key_store,
+//!     key_store_aes,
+//!     key_store_rng,
+//!     &KEY_ENCRYPTION_KEY,
+//! )
+//! .finalize(components::key_store_component_static!(
+//!     VirtualKVPermissions<'static, KVStorePermissions<'static, ...>>,
+//!     SoftwareAes<'static>,
+//!     VirtualRngMasterDevice<'static>,
+//! ));
+//! ```
+
+use capsules_extra::key_store::{KeyStore, CRYPT_BUFFER_SIZE, KV_KEY_BUFFER_SIZE, MAX_BLOB_LENGTH};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::kv::KVPermissions;
+use kernel::hil::rng::Rng;
+use kernel::hil::symmetric_encryption::{AES256GCM, AES256_KEY_SIZE};
+
+/// Room for the header of the k-v store as well as the stored key.
+pub const VALUE_BUFFER_SIZE: usize = MAX_BLOB_LENGTH + 32;
+
+#[macro_export]
+macro_rules! key_store_component_static {
+    ($K:ty, $A:ty, $R:ty $(,)?) => {{
+        let key_store =
+            kernel::static_buf!(capsules_extra::key_store::KeyStore<'static, $K, $A, $R>);
+        let kv_key_buffer =
+            kernel::static_buf!([u8; capsules_extra::key_store::KV_KEY_BUFFER_SIZE]);
+        let value_buffer = kernel::static_buf!([u8; $crate::key_store::VALUE_BUFFER_SIZE]);
+        let crypt_buffer = kernel::static_buf!([u8; capsules_extra::key_store::CRYPT_BUFFER_SIZE]);
+
+        (key_store, kv_key_buffer, value_buffer, crypt_buffer)
+    };};
+}
+
+pub type KeyStoreComponentType<K, A, R> = KeyStore<'static, K, A, R>;
+
+pub struct KeyStoreComponent<
+    K: KVPermissions<'static> + 'static,
+    A: AES256GCM<'static> + 'static,
+    R: Rng<'static> + 'static,
+> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    kv: &'static K,
+    aes: &'static A,
+    rng: &'static R,
+    kek: &'static [u8; AES256_KEY_SIZE],
+}
+
+impl<K: KVPermissions<'static>, A: AES256GCM<'static>, R: Rng<'static>> KeyStoreComponent<K, A, R> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        kv: &'static K,
+        aes: &'static A,
+        rng: &'static R,
+        kek: &'static [u8; AES256_KEY_SIZE],
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            kv,
+            aes,
+            rng,
+            kek,
+        }
+    }
+}
+
+impl<K: KVPermissions<'static>, A: AES256GCM<'static>, R: Rng<'static>> Component
+    for KeyStoreComponent<K, A, R>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<KeyStore<'static, K, A, R>>,
+        &'static mut MaybeUninit<[u8; KV_KEY_BUFFER_SIZE]>,
+        &'static mut MaybeUninit<[u8; VALUE_BUFFER_SIZE]>,
+        &'static mut MaybeUninit<[u8; CRYPT_BUFFER_SIZE]>,
+    );
+    type Output = &'static KeyStore<'static, K, A, R>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let kv_key_buffer = static_buffer.1.write([0; KV_KEY_BUFFER_SIZE]);
+        let value_buffer = static_buffer.2.write([0; VALUE_BUFFER_SIZE]);
+        let crypt_buffer = static_buffer.3.write([0; CRYPT_BUFFER_SIZE]);
+
+        let key_store = static_buffer.0.write(KeyStore::new(
+            self.kv,
+            self.aes,
+            self.rng,
+            self.kek,
+            kv_key_buffer,
+            value_buffer,
+            crypt_buffer,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        self.kv.set_client(key_store);
+        self.aes.set_client(key_store);
+        self.rng.set_client(key_store);
+
+        key_store
+    }
+}
@@ -4,6 +4,8 @@
 
 //! Components for KV stack capsules.
 
+use capsules_core::process_console::RegisteredCommand;
+use capsules_extra::kv_console::KVConsole;
 use capsules_extra::kv_driver::KVStoreDriver;
 use capsules_extra::kv_store_permissions::KVStorePermissions;
 use capsules_extra::tickv::{KVSystem, KeyType};
@@ -14,6 +16,7 @@ use kernel::capabilities;
 use kernel::component::Component;
 use kernel::create_capability;
 use kernel::hil;
+use kernel::storage_permissions::StoragePermissions;
 
 ///////////////////////
 // KV Userspace Driver
@@ -73,6 +76,69 @@ impl<V: hil::kv::KVPermissions<'static>> Component for KVDriverComponent<V> {
     }
 }
 
+////////////////////////
+// KV Process Console
+////////////////////////
+
+#[macro_export]
+macro_rules! kv_console_component_static {
+    ($V:ty $(,)?) => {{
+        let kv_console = kernel::static_buf!(capsules_extra::kv_console::KVConsole<'static, $V>);
+        let command =
+            kernel::static_buf!(capsules_core::process_console::RegisteredCommand<'static>);
+        let key_buffer = kernel::static_buf!([u8; 64]);
+        let value_buffer = kernel::static_buf!([u8; 64]);
+
+        (kv_console, command, key_buffer, value_buffer)
+    };};
+}
+
+pub type KVConsoleComponentType<V> = capsules_extra::kv_console::KVConsole<'static, V>;
+
+/// Creates the `kv` command of the process console, which the board
+/// registers with `ProcessConsole::register_command()`.
+pub struct KVConsoleComponent<V: hil::kv::KVPermissions<'static> + 'static> {
+    kv: &'static V,
+}
+
+impl<V: hil::kv::KVPermissions<'static>> KVConsoleComponent<V> {
+    pub fn new(kv: &'static V) -> Self {
+        Self { kv }
+    }
+}
+
+impl<V: hil::kv::KVPermissions<'static>> Component for KVConsoleComponent<V> {
+    type StaticInput = (
+        &'static mut MaybeUninit<KVConsole<'static, V>>,
+        &'static mut MaybeUninit<RegisteredCommand<'static>>,
+        &'static mut MaybeUninit<[u8; 64]>,
+        &'static mut MaybeUninit<[u8; 64]>,
+    );
+    type Output = &'static RegisteredCommand<'static>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let storage_cap = create_capability!(capabilities::KerneluserStorageCapability);
+
+        let key_buffer = static_buffer.2.write([0; 64]);
+        let value_buffer = static_buffer.3.write([0; 64]);
+
+        let kv_console = static_buffer.0.write(KVConsole::new(
+            self.kv,
+            key_buffer,
+            value_buffer,
+            StoragePermissions::new_kernel(&storage_cap),
+        ));
+        self.kv.set_client(kv_console);
+
+        static_buffer.1.write(RegisteredCommand::new(
+            "kv",
+            "get <key> | list",
+            "Inspect the key-value store.",
+            kv_console,
+        ))
+    }
+}
+
 //////////////
 // KV Mux
 //////////////
@@ -12,9 +12,11 @@ pub mod alarm;
 pub mod analog_comparator;
 pub mod apds9960;
 pub mod app_flash_driver;
+pub mod app_loader;
 pub mod appid;
 pub mod atecc508a;
 pub mod ble;
+pub mod block;
 pub mod bme280;
 pub mod bmm150;
 pub mod bmp280;
@@ -25,17 +27,22 @@ pub mod ccs811;
 pub mod cdc;
 pub mod chirp_i2c_moisture;
 pub mod console;
+pub mod crash_dump;
 pub mod crc;
 pub mod ctap;
 pub mod dac;
 pub mod date_time;
 pub mod debug_writer;
 pub mod dfrobot_rainfall_sensor;
+pub mod dynamic_binary_storage;
+pub mod ethernet;
 pub mod eui64;
+pub mod filesystem;
 pub mod flash;
 pub mod fm25cl;
 pub mod ft6x06;
 pub mod fxos8700;
+pub mod gdb_stub;
 pub mod gpio;
 pub mod hd44780;
 pub mod hmac;
@@ -43,8 +50,12 @@ pub mod hs3003;
 pub mod hts221;
 pub mod humidity;
 pub mod i2c;
+pub mod icmpv6_driver;
 pub mod ieee802154;
+pub mod ipc_mailbox;
 pub mod isl29035;
+pub mod kdf;
+pub mod key_store;
 pub mod keyboard_hid;
 pub mod kv;
 pub mod l3gd20;
@@ -83,12 +94,17 @@ pub mod sha;
 pub mod sht3x;
 pub mod sht4x;
 pub mod si7021;
+pub mod signature_verify;
 pub mod siphash;
+pub mod sixlowpan_nd;
 pub mod sound_pressure;
 pub mod spi;
 pub mod ssd1306;
 pub mod st77xx;
 pub mod storage_permissions;
+pub mod symmetric_encryption;
+pub mod tcp_driver;
+pub mod tcp_mux;
 pub mod temperature;
 pub mod temperature_rp2040;
 pub mod temperature_stm;
@@ -97,6 +113,8 @@ pub mod text_screen;
 pub mod thread_network;
 pub mod tickv;
 pub mod touch;
+pub mod tracer;
 pub mod udp_driver;
 pub mod udp_mux;
+pub mod udp_mux_ethernet;
 pub mod usb;
@@ -24,8 +24,9 @@ macro_rules! process_loader_sequential_component_static {
         let process_binary_array = kernel::static_buf!(
             [Option<kernel::process::ProcessBinary>; $NUMPROCS]
         );
+        let free_memory_array = kernel::static_buf!([Option<&'static mut [u8]>; $NUMPROCS]);
 
-       (loader, process_binary_array)
+       (loader, process_binary_array, free_memory_array)
     };};
 }
 
@@ -76,6 +77,7 @@ impl<C: Chip, D: ProcessStandardDebug, const NUM_PROCS: usize> Component
     type StaticInput = (
         &'static mut MaybeUninit<kernel::process::SequentialProcessLoaderMachine<'static, C, D>>,
         &'static mut MaybeUninit<[Option<kernel::process::ProcessBinary>; NUM_PROCS]>,
+        &'static mut MaybeUninit<[Option<&'static mut [u8]>; NUM_PROCS]>,
     );
 
     type Output = &'static kernel::process::SequentialProcessLoaderMachine<'static, C, D>;
@@ -86,6 +88,7 @@ impl<C: Chip, D: ProcessStandardDebug, const NUM_PROCS: usize> Component
 
         const ARRAY_REPEAT_VALUE: Option<kernel::process::ProcessBinary> = None;
         let process_binary_array = s.1.write([ARRAY_REPEAT_VALUE; NUM_PROCS]);
+        let free_memory_array = s.2.write([const { None }; NUM_PROCS]);
 
         // These symbols are defined in the standard Tock linker script.
         extern "C" {
@@ -114,6 +117,7 @@ impl<C: Chip, D: ProcessStandardDebug, const NUM_PROCS: usize> Component
                     core::ptr::addr_of_mut!(_sappmem),
                     core::ptr::addr_of!(_eappmem) as usize - core::ptr::addr_of!(_sappmem) as usize,
                 ),
+                free_memory_array,
                 self.fault_policy,
                 self.storage_policy,
                 self.appid_policy,
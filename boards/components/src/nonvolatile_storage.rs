@@ -5,7 +5,8 @@
 //! Component for non-volatile storage Drivers.
 //!
 //! This provides one component, NonvolatileStorageComponent, which provides
-//! a system call interface to non-volatile storage.
+//! a system call interface to non-volatile storage. The userspace region is
+//! split into regions of `app_region_size` bytes, one per app.
 //!
 //! Usage
 //! -----
@@ -15,6 +16,7 @@
 //!     &sam4l::flashcalw::FLASH_CONTROLLER,
 //!     0x60000,
 //!     0x20000,
+//!     0x2000,
 //!     core::ptr::addr_of!(_sstorage) as usize,
 //!     core::ptr::addr_of!(_estorage) as usize,
 //! )
@@ -58,6 +60,7 @@ pub struct NonvolatileStorageComponent<
     flash: &'static F,
     userspace_start: usize,
     userspace_length: usize,
+    app_region_size: usize,
     kernel_start: usize,
     kernel_length: usize,
 }
@@ -74,6 +77,7 @@ impl<
         flash: &'static F,
         userspace_start: usize,
         userspace_length: usize,
+        app_region_size: usize,
         kernel_start: usize,
         kernel_length: usize,
     ) -> Self {
@@ -83,6 +87,7 @@ impl<
             flash,
             userspace_start,
             userspace_length,
+            app_region_size,
             kernel_start,
             kernel_length,
         }
@@ -124,11 +129,13 @@ impl<
             self.board_kernel.create_grant(self.driver_num, &grant_cap),
             self.userspace_start, // Start address for userspace accessible region
             self.userspace_length, // Length of userspace accessible region
+            self.app_region_size, // Size of each app's region
             self.kernel_start,    // Start address of kernel region
             self.kernel_length,   // Length of kernel region
             buffer,
         ));
         hil::nonvolatile_storage::NonvolatileStorage::set_client(nv_to_page, nonvolatile_storage);
+        let _ = nonvolatile_storage.init();
         nonvolatile_storage
     }
 }
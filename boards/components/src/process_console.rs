@@ -14,6 +14,9 @@
 //! let pconsole = ProcessConsoleComponent::new(board_kernel, uart_mux, alarm_mux, process_printer, Some(reset_function))
 //!     .finalize(process_console_component_static!());
 //! ```
+//!
+//! Other capsules add their commands with `pconsole.register_command()`, see
+//! `capsules_core::process_console::ConsoleCommand`.
 
 // Author: Philip Levis <pal@cs.stanford.edu>
 // Last modified: 6/20/2018
@@ -48,6 +51,8 @@ macro_rules! process_console_component_static {
         let command_history_buffer = kernel::static_buf!(
             [capsules_core::process_console::Command; $COMMAND_HISTORY_LEN]
         );
+        let pipe_input_buffer = kernel::static_buf!([u8; capsules_core::process_console::PIPE_BUF_LEN]);
+        let pipe_output_buffer = kernel::static_buf!([u8; capsules_core::process_console::PIPE_BUF_LEN]);
 
         (
             alarm,
@@ -58,6 +63,8 @@ macro_rules! process_console_component_static {
             command_buffer,
             command_history_buffer,
             pconsole,
+            pipe_input_buffer,
+            pipe_output_buffer,
         )
     };};
     ($A: ty $(,)?) => {{
@@ -125,6 +132,8 @@ impl<const COMMAND_HISTORY_LEN: usize, A: 'static + Alarm<'static>> Component
         &'static mut MaybeUninit<
             ProcessConsole<'static, COMMAND_HISTORY_LEN, VirtualMuxAlarm<'static, A>, Capability>,
         >,
+        &'static mut MaybeUninit<[u8; capsules_core::process_console::PIPE_BUF_LEN]>,
+        &'static mut MaybeUninit<[u8; capsules_core::process_console::PIPE_BUF_LEN]>,
     );
     type Output = &'static process_console::ProcessConsole<
         'static,
@@ -170,6 +179,12 @@ impl<const COMMAND_HISTORY_LEN: usize, A: 'static + Alarm<'static>> Component
         let command_history_buffer = static_buffer
             .6
             .write([capsules_core::process_console::Command::default(); COMMAND_HISTORY_LEN]);
+        let pipe_input_buffer = static_buffer
+            .8
+            .write([0; capsules_core::process_console::PIPE_BUF_LEN]);
+        let pipe_output_buffer = static_buffer
+            .9
+            .write([0; capsules_core::process_console::PIPE_BUF_LEN]);
 
         let console = static_buffer.7.write(ProcessConsole::new(
             console_uart,
@@ -180,6 +195,8 @@ impl<const COMMAND_HISTORY_LEN: usize, A: 'static + Alarm<'static>> Component
             queue_buffer,
             command_buffer,
             command_history_buffer,
+            pipe_input_buffer,
+            pipe_output_buffer,
             self.board_kernel,
             kernel_addresses,
             self.reset_function,
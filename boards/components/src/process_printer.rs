@@ -10,6 +10,13 @@
 //! let process_printer = ProcessPrinterTextComponent::new()
 //!     .finalize(components::process_printer_component_static!());
 //! ```
+//!
+//! For a binary process printer that keeps up to 512 bytes of the stack:
+//!
+//! ```rust
+//! let process_printer = ProcessPrinterBinaryComponent::new(512)
+//!     .finalize(components::process_printer_binary_component_static!());
+//! ```
 
 use core::mem::MaybeUninit;
 use kernel::component::Component;
@@ -38,3 +45,32 @@ impl Component for ProcessPrinterTextComponent {
         static_buffer.write(capsules_system::process_printer::ProcessPrinterText::new())
     }
 }
+
+#[macro_export]
+macro_rules! process_printer_binary_component_static {
+    () => {{
+        kernel::static_buf!(capsules_system::process_printer::ProcessPrinterBinary)
+    };};
+}
+
+pub struct ProcessPrinterBinaryComponent {
+    stack_len: usize,
+}
+
+impl ProcessPrinterBinaryComponent {
+    pub fn new(stack_len: usize) -> ProcessPrinterBinaryComponent {
+        ProcessPrinterBinaryComponent { stack_len }
+    }
+}
+
+impl Component for ProcessPrinterBinaryComponent {
+    type StaticInput =
+        &'static mut MaybeUninit<capsules_system::process_printer::ProcessPrinterBinary>;
+    type Output = &'static capsules_system::process_printer::ProcessPrinterBinary;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        static_buffer.write(capsules_system::process_printer::ProcessPrinterBinary::new(
+            self.stack_len,
+        ))
+    }
+}
@@ -13,15 +13,34 @@
 //! let rng = components::rng::RngComponent::new(board_kernel, &sam4l::trng::TRNG)
 //!     .finalize(rng_component_static!());
 //! ```
+//!
+//! `RngHmacDrbgComponent` instead serves the syscall interface from an
+//! HMAC-DRBG that is seeded from the TRNG. The HMAC engine must not be shared
+//! with other users.
+//!
+//! ```rust
+//! let rng = components::rng::RngHmacDrbgComponent::new(
+//!     board_kernel,
+//!     capsules_core::rng::DRIVER_NUM,
+//!     hmac,
+//!     &base_peripherals.trng,
+//! )
+//! .finalize(components::rng_hmac_drbg_component_static!(
+//!     HmacSha256Software<'static, Sha256Software<'static>>,
+//!     nrf52840::trng::Trng
+//! ));
+//! ```
 
 // Author: Hudson Ayers <hayers@cs.stanford.edu>
 // Last modified: 07/12/2019
 
 use capsules_core::rng;
+use capsules_extra::hmac_drbg::{HmacDrbg, DATA_BUFFER_LEN};
 use core::mem::MaybeUninit;
 use kernel::capabilities;
 use kernel::component::Component;
 use kernel::create_capability;
+use kernel::hil::digest;
 use kernel::hil::entropy::Entropy32;
 use kernel::hil::rng::Rng;
 
@@ -88,3 +107,85 @@ impl<E: Entropy32<'static>> Component for RngComponent<E> {
         rng
     }
 }
+
+#[macro_export]
+macro_rules! rng_hmac_drbg_component_static {
+    ($H:ty, $E:ty $(,)?) => {{
+        let drbg = kernel::static_buf!(capsules_extra::hmac_drbg::HmacDrbg<'static, $H, $E>);
+        let rng = kernel::static_buf!(
+            capsules_core::rng::RngDriver<
+                'static,
+                capsules_extra::hmac_drbg::HmacDrbg<'static, $H, $E>,
+            >
+        );
+        let data_buffer = kernel::static_buf!([u8; capsules_extra::hmac_drbg::DATA_BUFFER_LEN]);
+        let digest_buffer = kernel::static_buf!([u8; 32]);
+
+        (drbg, rng, data_buffer, digest_buffer)
+    };};
+}
+
+pub type RngHmacDrbgComponentType<H, E> = rng::RngDriver<'static, HmacDrbg<'static, H, E>>;
+
+pub struct RngHmacDrbgComponent<
+    H: digest::Digest<'static, 32> + digest::HmacSha256 + 'static,
+    E: Entropy32<'static> + 'static,
+> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    hmac: &'static H,
+    trng: &'static E,
+}
+
+impl<H: digest::Digest<'static, 32> + digest::HmacSha256, E: Entropy32<'static>>
+    RngHmacDrbgComponent<H, E>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        hmac: &'static H,
+        trng: &'static E,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            hmac,
+            trng,
+        }
+    }
+}
+
+impl<H: digest::Digest<'static, 32> + digest::HmacSha256, E: Entropy32<'static>> Component
+    for RngHmacDrbgComponent<H, E>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<HmacDrbg<'static, H, E>>,
+        &'static mut MaybeUninit<rng::RngDriver<'static, HmacDrbg<'static, H, E>>>,
+        &'static mut MaybeUninit<[u8; DATA_BUFFER_LEN]>,
+        &'static mut MaybeUninit<[u8; 32]>,
+    );
+    type Output = &'static rng::RngDriver<'static, HmacDrbg<'static, H, E>>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let data_buffer = static_buffer.2.write([0; DATA_BUFFER_LEN]);
+        let digest_buffer = static_buffer.3.write([0; 32]);
+
+        let drbg = static_buffer.0.write(HmacDrbg::new(
+            self.hmac,
+            self.trng,
+            data_buffer,
+            digest_buffer,
+        ));
+        let rng = static_buffer.1.write(rng::RngDriver::new(
+            drbg,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        digest::Digest::set_client(self.hmac, drbg);
+        self.trng.set_client(drbg);
+        drbg.set_client(rng);
+
+        rng
+    }
+}
@@ -127,3 +127,32 @@ impl Component for ShaSoftware256Component {
         sha_256_sw
     }
 }
+
+#[macro_export]
+macro_rules! sha_software_512_component_static {
+    ($(,)?) => {{
+        kernel::static_buf!(capsules_extra::sha512::Sha512Software<'static>)
+    };};
+}
+
+pub struct ShaSoftware512Component {}
+
+impl ShaSoftware512Component {
+    pub fn new() -> ShaSoftware512Component {
+        ShaSoftware512Component {}
+    }
+}
+
+impl Component for ShaSoftware512Component {
+    type StaticInput = &'static mut MaybeUninit<capsules_extra::sha512::Sha512Software<'static>>;
+
+    type Output = &'static capsules_extra::sha512::Sha512Software<'static>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let sha_512_sw = s.write(capsules_extra::sha512::Sha512Software::new());
+
+        kernel::deferred_call::DeferredCallClient::register(sha_512_sw);
+
+        sha_512_sw
+    }
+}
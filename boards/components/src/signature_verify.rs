@@ -0,0 +1,81 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Components for the software signature verifiers.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let verifier = components::signature_verify::EcdsaP256SoftwareComponent::new(public_key)
+//!     .finalize(components::ecdsa_p256_software_component_static!(32));
+//!
+//! let verifier = components::signature_verify::Ed25519SoftwareComponent::new(public_key)
+//!     .finalize(components::ed25519_software_component_static!(32));
+//! ```
+
+use capsules_extra::public_key_crypto::ecdsa_p256::EcdsaP256SignatureVerifier;
+use capsules_extra::public_key_crypto::ed25519::Ed25519SignatureVerifier;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::deferred_call::DeferredCallClient;
+
+#[macro_export]
+macro_rules! ecdsa_p256_software_component_static {
+    ($HL:expr $(,)?) => {{
+        kernel::static_buf!(
+            capsules_extra::public_key_crypto::ecdsa_p256::EcdsaP256SignatureVerifier<'static, $HL>
+        )
+    };};
+}
+
+pub struct EcdsaP256SoftwareComponent<const HL: usize> {
+    public_key: &'static [u8; 64],
+}
+
+impl<const HL: usize> EcdsaP256SoftwareComponent<HL> {
+    pub fn new(public_key: &'static [u8; 64]) -> Self {
+        Self { public_key }
+    }
+}
+
+impl<const HL: usize> Component for EcdsaP256SoftwareComponent<HL> {
+    type StaticInput = &'static mut MaybeUninit<EcdsaP256SignatureVerifier<'static, HL>>;
+    type Output = &'static EcdsaP256SignatureVerifier<'static, HL>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let verifier = s.write(EcdsaP256SignatureVerifier::new(self.public_key));
+        verifier.register();
+        verifier
+    }
+}
+
+#[macro_export]
+macro_rules! ed25519_software_component_static {
+    ($HL:expr $(,)?) => {{
+        kernel::static_buf!(
+            capsules_extra::public_key_crypto::ed25519::Ed25519SignatureVerifier<'static, $HL>
+        )
+    };};
+}
+
+pub struct Ed25519SoftwareComponent<const HL: usize> {
+    public_key: &'static [u8; 32],
+}
+
+impl<const HL: usize> Ed25519SoftwareComponent<HL> {
+    pub fn new(public_key: &'static [u8; 32]) -> Self {
+        Self { public_key }
+    }
+}
+
+impl<const HL: usize> Component for Ed25519SoftwareComponent<HL> {
+    type StaticInput = &'static mut MaybeUninit<Ed25519SignatureVerifier<'static, HL>>;
+    type Output = &'static Ed25519SignatureVerifier<'static, HL>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let verifier = s.write(Ed25519SignatureVerifier::new(self.public_key));
+        verifier.register();
+        verifier
+    }
+}
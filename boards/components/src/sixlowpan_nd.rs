@@ -0,0 +1,273 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Component to initialize 6LoWPAN Neighbor Discovery (RFC 6775).
+//!
+//! This provides one Component, SixlowpanNDComponent. This component sets up
+//! a SixlowpanND instance with a neighbor cache of `NUM_NEIGHBORS` entries.
+//! ND messages are sent through their own MAC user, IPv6 sender and ICMPv6
+//! sender, and received through the ICMPv6 layer of the UDPMuxComponent.
+//! The node is identified by its EUI-64, from which its link-local and
+//! global addresses are derived.
+//!
+//! Neighbor discovery is not started by the component; boards call `start`
+//! on the returned instance once the radio is configured.
+//!
+//! Usage
+//! -----
+//! ```rust
+//!    let nd = SixlowpanNDComponent::new(
+//!        mux_mac,
+//!        DEFAULT_CTX_PREFIX_LEN,
+//!        DEFAULT_CTX_PREFIX,
+//!        DST_MAC_ADDR,
+//!        DEFAULT_EXT_SRC_MAC,
+//!        icmp_recv,
+//!        mux_alarm,
+//!    )
+//!    .finalize(components::sixlowpan_nd_component_static!(
+//!        sam4l::ast::Ast,
+//!        capsules_extra::ieee802154::framer::Framer<...>,
+//!        4,
+//!    ));
+//!    nd.start().unwrap();
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::ieee802154::device::MacDevice;
+use capsules_extra::net::icmpv6::icmpv6_recv::{ICMP6Receiver, ICMP6RecvStruct};
+use capsules_extra::net::icmpv6::icmpv6_send::{ICMP6SendStruct, ICMP6Sender};
+use capsules_extra::net::icmpv6::{ICMP6Header, ICMP6Type};
+use capsules_extra::net::ieee802154::MacAddress;
+use capsules_extra::net::ipv6::ipv6_send::{IP6SendStruct, IP6Sender};
+use capsules_extra::net::ipv6::{IP6Packet, IPPayload, TransportHeader};
+use capsules_extra::net::network_capabilities::{
+    AddrRange, IpVisibilityCapability, NetworkCapability, PortRange,
+};
+use capsules_extra::net::sixlowpan::sixlowpan_nd::{Neighbor, SixlowpanND, ND_BUF_LEN};
+use capsules_extra::net::sixlowpan::{sixlowpan_compression, sixlowpan_state};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::radio;
+use kernel::hil::time::Alarm;
+use kernel::utilities::cells::OptionalCell;
+use kernel::utilities::leasable_buffer::SubSliceMut;
+
+/// Registration lifetime requested from routers, in minutes.
+pub const REGISTRATION_LIFETIME: u16 = 30;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! sixlowpan_nd_component_static {
+    ($A:ty, $M:ty, $N:expr $(,)?) => {{
+        use capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm;
+        use capsules_extra::net::sixlowpan::sixlowpan_nd::ND_BUF_LEN;
+        use capsules_extra::net::sixlowpan::{sixlowpan_compression, sixlowpan_state};
+
+        let ip_alarm = kernel::static_buf!(VirtualMuxAlarm<'static, $A>);
+        let nd_alarm = kernel::static_buf!(VirtualMuxAlarm<'static, $A>);
+        let mac_user =
+            kernel::static_buf!(capsules_extra::ieee802154::virtual_mac::MacUser<'static, $M>);
+        let sixlowpan = kernel::static_buf!(
+            sixlowpan_state::Sixlowpan<
+                'static,
+                VirtualMuxAlarm<'static, $A>,
+                sixlowpan_compression::Context,
+            >
+        );
+        let ip6_send = kernel::static_buf!(
+            capsules_extra::net::ipv6::ipv6_send::IP6SendStruct<
+                'static,
+                VirtualMuxAlarm<'static, $A>,
+            >
+        );
+        let ip6_packet = kernel::static_buf!(capsules_extra::net::ipv6::IP6Packet<'static>);
+        let icmp_send = kernel::static_buf!(
+            capsules_extra::net::icmpv6::icmpv6_send::ICMP6SendStruct<
+                'static,
+                capsules_extra::net::ipv6::ipv6_send::IP6SendStruct<
+                    'static,
+                    VirtualMuxAlarm<'static, $A>,
+                >,
+            >
+        );
+        let icmp_rcvr =
+            kernel::static_buf!(capsules_extra::net::icmpv6::icmpv6_recv::ICMP6Receiver<'static>);
+        let neighbors = kernel::static_buf!(
+            [kernel::utilities::cells::OptionalCell<
+                capsules_extra::net::sixlowpan::sixlowpan_nd::Neighbor,
+            >; $N]
+        );
+        let nd = kernel::static_buf!(
+            capsules_extra::net::sixlowpan::sixlowpan_nd::SixlowpanND<
+                'static,
+                VirtualMuxAlarm<'static, $A>,
+            >
+        );
+
+        let radio_buf = kernel::static_buf!([u8; kernel::hil::radio::MAX_BUF_SIZE]);
+        let ip_payload = kernel::static_buf!([u8; ND_BUF_LEN]);
+        let tx_buf = kernel::static_buf!([u8; ND_BUF_LEN]);
+
+        let ip_vis_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::IpVisibilityCapability);
+        let net_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::NetworkCapability);
+
+        (
+            ip_alarm, nd_alarm, mac_user, sixlowpan, ip6_send, ip6_packet, icmp_send, icmp_rcvr,
+            neighbors, nd, radio_buf, ip_payload, tx_buf, ip_vis_cap, net_cap,
+        )
+    };};
+}
+
+pub struct SixlowpanNDComponent<
+    A: Alarm<'static> + 'static,
+    M: MacDevice<'static> + 'static,
+    const NUM_NEIGHBORS: usize,
+> {
+    mux_mac: &'static capsules_extra::ieee802154::virtual_mac::MuxMac<'static, M>,
+    ctx_pfix_len: u8,
+    ctx_pfix: [u8; 16],
+    dst_mac_addr: MacAddress,
+    eui64: [u8; 8],
+    icmp_recv: &'static ICMP6RecvStruct<'static>,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+}
+
+impl<A: Alarm<'static> + 'static, M: MacDevice<'static>, const NUM_NEIGHBORS: usize>
+    SixlowpanNDComponent<A, M, NUM_NEIGHBORS>
+{
+    pub fn new(
+        mux_mac: &'static capsules_extra::ieee802154::virtual_mac::MuxMac<'static, M>,
+        ctx_pfix_len: u8,
+        ctx_pfix: [u8; 16],
+        dst_mac_addr: MacAddress,
+        eui64: [u8; 8],
+        icmp_recv: &'static ICMP6RecvStruct<'static>,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+    ) -> Self {
+        Self {
+            mux_mac,
+            ctx_pfix_len,
+            ctx_pfix,
+            dst_mac_addr,
+            eui64,
+            icmp_recv,
+            alarm_mux,
+        }
+    }
+}
+
+impl<A: Alarm<'static> + 'static, M: MacDevice<'static>, const NUM_NEIGHBORS: usize> Component
+    for SixlowpanNDComponent<A, M, NUM_NEIGHBORS>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<capsules_extra::ieee802154::virtual_mac::MacUser<'static, M>>,
+        &'static mut MaybeUninit<
+            sixlowpan_state::Sixlowpan<
+                'static,
+                VirtualMuxAlarm<'static, A>,
+                sixlowpan_compression::Context,
+            >,
+        >,
+        &'static mut MaybeUninit<IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
+        &'static mut MaybeUninit<IP6Packet<'static>>,
+        &'static mut MaybeUninit<
+            ICMP6SendStruct<'static, IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
+        >,
+        &'static mut MaybeUninit<ICMP6Receiver<'static>>,
+        &'static mut MaybeUninit<[OptionalCell<Neighbor>; NUM_NEIGHBORS]>,
+        &'static mut MaybeUninit<SixlowpanND<'static, VirtualMuxAlarm<'static, A>>>,
+        &'static mut MaybeUninit<[u8; radio::MAX_BUF_SIZE]>,
+        &'static mut MaybeUninit<[u8; ND_BUF_LEN]>,
+        &'static mut MaybeUninit<[u8; ND_BUF_LEN]>,
+        &'static mut MaybeUninit<IpVisibilityCapability>,
+        &'static mut MaybeUninit<NetworkCapability>,
+    );
+    type Output = &'static SixlowpanND<'static, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let ipsender_virtual_alarm = s.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        ipsender_virtual_alarm.setup();
+        let nd_virtual_alarm = s.1.write(VirtualMuxAlarm::new(self.alarm_mux));
+        nd_virtual_alarm.setup();
+
+        // ND messages are received through the ICMPv6 layer, so this MAC
+        // user is only used to transmit.
+        let nd_mac =
+            s.2.write(capsules_extra::ieee802154::virtual_mac::MacUser::new(
+                self.mux_mac,
+            ));
+        self.mux_mac.add_user(nd_mac);
+        let create_cap = create_capability!(capabilities::NetworkCapabilityCreationCapability);
+        let ip_vis = s.13.write(IpVisibilityCapability::new(&create_cap));
+        let net_cap = s.14.write(NetworkCapability::new(
+            AddrRange::Any,
+            PortRange::Any,
+            PortRange::Any,
+            &create_cap,
+        ));
+
+        let sixlowpan = s.3.write(sixlowpan_state::Sixlowpan::new(
+            sixlowpan_compression::Context {
+                prefix: self.ctx_pfix,
+                prefix_len: self.ctx_pfix_len,
+                id: 0,
+                compress: false,
+            },
+            ipsender_virtual_alarm, // OK to reuse bc only used to get time, not set alarms
+        ));
+        let sixlowpan_tx = sixlowpan_state::TxState::new(sixlowpan);
+
+        let ip_payload_buffer = s.11.write([0; ND_BUF_LEN]);
+        let ip_pyld: IPPayload = IPPayload {
+            header: TransportHeader::ICMP(ICMP6Header::new(ICMP6Type::Type133)),
+            payload: ip_payload_buffer,
+        };
+        let ip6_dg = s.5.write(IP6Packet::new(ip_pyld));
+        let radio_buf = s.10.write([0; radio::MAX_BUF_SIZE]);
+
+        let ip_send = s.4.write(IP6SendStruct::new(
+            ip6_dg,
+            ipsender_virtual_alarm,
+            radio_buf,
+            sixlowpan_tx,
+            nd_mac,
+            self.dst_mac_addr,
+            MacAddress::Long(self.eui64),
+            ip_vis,
+        ));
+        ipsender_virtual_alarm.set_alarm_client(ip_send);
+        nd_mac.set_transmit_client(ip_send);
+
+        let icmp_send = s.6.write(ICMP6SendStruct::new(ip_send));
+        ip_send.set_client(icmp_send);
+
+        let neighbors = s.8.write(core::array::from_fn(|_| OptionalCell::empty()));
+        let tx_buffer = s.12.write([0; ND_BUF_LEN]);
+        let nd = s.9.write(SixlowpanND::new(
+            icmp_send,
+            ip_send,
+            nd_virtual_alarm,
+            self.eui64,
+            neighbors,
+            SubSliceMut::new(tx_buffer),
+            REGISTRATION_LIFETIME,
+            net_cap,
+        ));
+        icmp_send.set_client(nd);
+        nd_virtual_alarm.set_alarm_client(nd);
+
+        let icmp_rcvr = s.7.write(ICMP6Receiver::new());
+        icmp_rcvr.set_client(nd);
+        self.icmp_recv.add_client(icmp_rcvr);
+
+        nd
+    }
+}
@@ -0,0 +1,80 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Components for the software symmetric encryption engines.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let software_aes = components::symmetric_encryption::SoftwareAesComponent::new()
+//!     .finalize(components::software_aes_component_static!());
+//!
+//! let chacha20poly1305 =
+//!     components::symmetric_encryption::SoftwareChaCha20Poly1305Component::new()
+//!         .finalize(components::software_chacha20poly1305_component_static!());
+//! ```
+
+use capsules_extra::symmetric_encryption::chacha20poly1305::SoftwareChaCha20Poly1305;
+use capsules_extra::symmetric_encryption::software_aes::SoftwareAes;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::deferred_call::DeferredCallClient;
+
+#[macro_export]
+macro_rules! software_aes_component_static {
+    () => {{
+        kernel::static_buf!(
+            capsules_extra::symmetric_encryption::software_aes::SoftwareAes<'static>
+        )
+    };};
+}
+
+pub struct SoftwareAesComponent {}
+
+impl SoftwareAesComponent {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Component for SoftwareAesComponent {
+    type StaticInput = &'static mut MaybeUninit<SoftwareAes<'static>>;
+    type Output = &'static SoftwareAes<'static>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let aes = s.write(SoftwareAes::new());
+        aes.register();
+        aes
+    }
+}
+
+#[macro_export]
+macro_rules! software_chacha20poly1305_component_static {
+    () => {{
+        kernel::static_buf!(
+            capsules_extra::symmetric_encryption::chacha20poly1305::SoftwareChaCha20Poly1305<
+                'static,
+            >
+        )
+    };};
+}
+
+pub struct SoftwareChaCha20Poly1305Component {}
+
+impl SoftwareChaCha20Poly1305Component {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Component for SoftwareChaCha20Poly1305Component {
+    type StaticInput = &'static mut MaybeUninit<SoftwareChaCha20Poly1305<'static>>;
+    type Output = &'static SoftwareChaCha20Poly1305<'static>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let chacha = s.write(SoftwareChaCha20Poly1305::new());
+        chacha.register();
+        chacha
+    }
+}
@@ -0,0 +1,112 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Component to initialize the userland TCP driver.
+//!
+//! This provides one Component, TCPDriverComponent. This component creates a
+//! pool of `NUM_SOCKETS` TCP sockets, registers them with a MuxTcp, and
+//! initializes a userspace TCP driver that lets apps use them.
+//!
+//! Usage
+//! -----
+//! ```rust
+//!    let tcp_driver = TCPDriverComponent::new(
+//!        board_kernel,
+//!        capsules_extra::net::tcp::DRIVER_NUM,
+//!        tcp_mux,
+//!    )
+//!    .finalize(components::tcp_driver_component_static!(sam4l::ast::Ast, 2));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm;
+use capsules_extra::net::network_capabilities::{AddrRange, NetworkCapability, PortRange};
+use capsules_extra::net::tcp::tcp_mux::MuxTcp;
+use capsules_extra::net::tcp::tcp_socket::TcpSocket;
+use capsules_extra::net::tcp::TcpDriver;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::capabilities::NetworkCapabilityCreationCapability;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::time::Alarm;
+
+/// Size of the send and receive buffer of each socket.
+pub const SOCKET_BUF_LEN: usize = 512;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! tcp_driver_component_static {
+    ($A:ty, $N:expr $(,)?) => {{
+        use components::tcp_driver::SOCKET_BUF_LEN;
+
+        let sockets =
+            kernel::static_buf!([capsules_extra::net::tcp::tcp_socket::TcpSocket<'static>; $N]);
+        let tx_bufs = kernel::static_buf!([[u8; SOCKET_BUF_LEN]; $N]);
+        let rx_bufs = kernel::static_buf!([[u8; SOCKET_BUF_LEN]; $N]);
+        let net_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::NetworkCapability);
+        let tcp_driver = kernel::static_buf!(capsules_extra::net::tcp::TcpDriver<'static>);
+
+        (sockets, tx_bufs, rx_bufs, net_cap, tcp_driver)
+    };};
+}
+
+pub struct TCPDriverComponent<A: Alarm<'static> + 'static, const NUM_SOCKETS: usize> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    tcp_mux: &'static MuxTcp<'static, VirtualMuxAlarm<'static, A>>,
+}
+
+impl<A: Alarm<'static>, const NUM_SOCKETS: usize> TCPDriverComponent<A, NUM_SOCKETS> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        tcp_mux: &'static MuxTcp<'static, VirtualMuxAlarm<'static, A>>,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            tcp_mux,
+        }
+    }
+}
+
+impl<A: Alarm<'static>, const NUM_SOCKETS: usize> Component for TCPDriverComponent<A, NUM_SOCKETS> {
+    type StaticInput = (
+        &'static mut MaybeUninit<[TcpSocket<'static>; NUM_SOCKETS]>,
+        &'static mut MaybeUninit<[[u8; SOCKET_BUF_LEN]; NUM_SOCKETS]>,
+        &'static mut MaybeUninit<[[u8; SOCKET_BUF_LEN]; NUM_SOCKETS]>,
+        &'static mut MaybeUninit<NetworkCapability>,
+        &'static mut MaybeUninit<TcpDriver<'static>>,
+    );
+    type Output = &'static TcpDriver<'static>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+        let create_cap = create_capability!(NetworkCapabilityCreationCapability);
+
+        let net_cap = s.3.write(NetworkCapability::new(
+            AddrRange::Any,
+            PortRange::Any,
+            PortRange::Any,
+            &create_cap,
+        ));
+
+        let mut tx_bufs = s.1.write([[0; SOCKET_BUF_LEN]; NUM_SOCKETS]).iter_mut();
+        let mut rx_bufs = s.2.write([[0; SOCKET_BUF_LEN]; NUM_SOCKETS]).iter_mut();
+        let sockets = s.0.write(core::array::from_fn(|_| {
+            TcpSocket::new(tx_bufs.next().unwrap(), rx_bufs.next().unwrap(), net_cap)
+        }));
+
+        let tcp_driver = s.4.write(TcpDriver::new(
+            sockets,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        for socket in sockets.iter() {
+            self.tcp_mux.add_socket(socket);
+            socket.set_client(tcp_driver);
+        }
+        tcp_driver
+    }
+}
@@ -0,0 +1,208 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Component to initialize the tcp/6lowpan interface.
+//!
+//! This provides one Component, TCPMuxComponent. This component exposes a
+//! MuxTcp that TCP sockets can be registered with to use the TCP/6LoWPAN
+//! stack. It is built on top of the UDPMuxComponent: received TCP segments
+//! are dispatched to the MuxTcp by the IPv6 receiver of the UDP stack, and
+//! segments are sent through the same 6LoWPAN state. Like the ICMPv6 layer,
+//! TCP sends through its own IP sender and MAC user so that its transmissions
+//! cannot interfere with UDP.
+//!
+//! Usage
+//! -----
+//! ```rust
+//!    let (udp_mux, udp_recv, udp_port_table, icmp_recv, ip_receive, sixlowpan) =
+//!        UDPMuxComponent::new(...).finalize(...);
+//!    let tcp_mux = TCPMuxComponent::new(
+//!        mux_mac,
+//!        sixlowpan,
+//!        ip_receive,
+//!        DST_MAC_ADDR,
+//!        src_mac_from_serial_num,
+//!        local_ip_ifaces,
+//!        mux_alarm,
+//!    )
+//!    .finalize(components::tcp_mux_component_static!(
+//!        sam4l::ast::Ast,
+//!        capsules_extra::ieee802154::framer::Framer<...>,
+//!    ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::ieee802154::device::MacDevice;
+use capsules_extra::net::ieee802154::MacAddress;
+use capsules_extra::net::ipv6::ip_utils::IPAddr;
+use capsules_extra::net::ipv6::ipv6_recv::IP6Receiver;
+use capsules_extra::net::ipv6::ipv6_recv::IP6RecvStruct;
+use capsules_extra::net::ipv6::ipv6_send::IP6SendStruct;
+use capsules_extra::net::ipv6::ipv6_send::IP6Sender;
+use capsules_extra::net::ipv6::{IP6Packet, IPPayload, TransportHeader};
+use capsules_extra::net::network_capabilities::{
+    AddrRange, IpVisibilityCapability, NetworkCapability, PortRange,
+};
+use capsules_extra::net::sixlowpan::sixlowpan_state;
+use capsules_extra::net::tcp::tcp_mux::MuxTcp;
+use capsules_extra::net::tcp::TCPHeader;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::radio;
+use kernel::hil::time::Alarm;
+use kernel::utilities::leasable_buffer::SubSliceMut;
+
+/// The largest TCP payload the stack puts in a single segment.
+pub const MAX_SEGMENT_LEN: usize = 200;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! tcp_mux_component_static {
+    ($A:ty, $M:ty $(,)?) => {{
+        use capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm;
+        use components::tcp_mux::MAX_SEGMENT_LEN;
+
+        let ip_alarm = kernel::static_buf!(VirtualMuxAlarm<'static, $A>);
+        let tcp_alarm = kernel::static_buf!(VirtualMuxAlarm<'static, $A>);
+        let mac_user =
+            kernel::static_buf!(capsules_extra::ieee802154::virtual_mac::MacUser<'static, $M>);
+        let ip6_send = kernel::static_buf!(
+            capsules_extra::net::ipv6::ipv6_send::IP6SendStruct<
+                'static,
+                VirtualMuxAlarm<'static, $A>,
+            >
+        );
+        let ip6_packet = kernel::static_buf!(capsules_extra::net::ipv6::IP6Packet<'static>);
+        let mux_tcp = kernel::static_buf!(
+            capsules_extra::net::tcp::tcp_mux::MuxTcp<'static, VirtualMuxAlarm<'static, $A>>
+        );
+
+        let radio_buf = kernel::static_buf!([u8; kernel::hil::radio::MAX_BUF_SIZE]);
+        let ip_payload = kernel::static_buf!([u8; MAX_SEGMENT_LEN]);
+        let segment = kernel::static_buf!([u8; MAX_SEGMENT_LEN]);
+
+        let ip_vis_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::IpVisibilityCapability);
+        let net_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::NetworkCapability);
+
+        (
+            ip_alarm, tcp_alarm, mac_user, ip6_send, ip6_packet, mux_tcp, radio_buf, ip_payload,
+            segment, ip_vis_cap, net_cap,
+        )
+    };};
+}
+
+pub struct TCPMuxComponent<A: Alarm<'static> + 'static, M: MacDevice<'static> + 'static> {
+    mux_mac: &'static capsules_extra::ieee802154::virtual_mac::MuxMac<'static, M>,
+    sixlowpan: &'static dyn sixlowpan_state::SixlowpanState<'static>,
+    ip_receive: &'static IP6RecvStruct<'static>,
+    dst_mac_addr: MacAddress,
+    src_mac_addr: MacAddress,
+    interface_list: &'static [IPAddr],
+    alarm_mux: &'static MuxAlarm<'static, A>,
+}
+
+impl<A: Alarm<'static> + 'static, M: MacDevice<'static>> TCPMuxComponent<A, M> {
+    pub fn new(
+        mux_mac: &'static capsules_extra::ieee802154::virtual_mac::MuxMac<'static, M>,
+        sixlowpan: &'static dyn sixlowpan_state::SixlowpanState<'static>,
+        ip_receive: &'static IP6RecvStruct<'static>,
+        dst_mac_addr: MacAddress,
+        src_mac_addr: MacAddress,
+        interface_list: &'static [IPAddr],
+        alarm_mux: &'static MuxAlarm<'static, A>,
+    ) -> Self {
+        Self {
+            mux_mac,
+            sixlowpan,
+            ip_receive,
+            dst_mac_addr,
+            src_mac_addr,
+            interface_list,
+            alarm_mux,
+        }
+    }
+}
+
+impl<A: Alarm<'static> + 'static, M: MacDevice<'static>> Component for TCPMuxComponent<A, M> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<capsules_extra::ieee802154::virtual_mac::MacUser<'static, M>>,
+        &'static mut MaybeUninit<IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
+        &'static mut MaybeUninit<IP6Packet<'static>>,
+        &'static mut MaybeUninit<MuxTcp<'static, VirtualMuxAlarm<'static, A>>>,
+        &'static mut MaybeUninit<[u8; radio::MAX_BUF_SIZE]>,
+        &'static mut MaybeUninit<[u8; MAX_SEGMENT_LEN]>,
+        &'static mut MaybeUninit<[u8; MAX_SEGMENT_LEN]>,
+        &'static mut MaybeUninit<IpVisibilityCapability>,
+        &'static mut MaybeUninit<NetworkCapability>,
+    );
+    type Output = &'static MuxTcp<'static, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let ipsender_virtual_alarm = s.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        ipsender_virtual_alarm.setup();
+        let tcp_virtual_alarm = s.1.write(VirtualMuxAlarm::new(self.alarm_mux));
+        tcp_virtual_alarm.setup();
+
+        // The MAC user is only used to send: segments are received through
+        // the MAC user of the UDP stack.
+        let tcp_mac =
+            s.2.write(capsules_extra::ieee802154::virtual_mac::MacUser::new(
+                self.mux_mac,
+            ));
+        self.mux_mac.add_user(tcp_mac);
+        let create_cap = create_capability!(capabilities::NetworkCapabilityCreationCapability);
+        let ip_vis = s.9.write(IpVisibilityCapability::new(&create_cap));
+        // Segments that are not associated with a socket (resets) may be
+        // sent to any peer.
+        let net_cap = s.10.write(NetworkCapability::new(
+            AddrRange::Any,
+            PortRange::Any,
+            PortRange::Any,
+            &create_cap,
+        ));
+
+        let ip_payload_buffer = s.7.write([0; MAX_SEGMENT_LEN]);
+        let ip_pyld: IPPayload = IPPayload {
+            header: TransportHeader::TCP(TCPHeader::new()),
+            payload: ip_payload_buffer,
+        };
+        let ip6_dg = s.4.write(IP6Packet::new(ip_pyld));
+
+        let radio_buf = s.6.write([0; radio::MAX_BUF_SIZE]);
+
+        let ip_send = s.3.write(IP6SendStruct::new(
+            ip6_dg,
+            ipsender_virtual_alarm,
+            radio_buf,
+            sixlowpan_state::TxState::new(self.sixlowpan),
+            tcp_mac,
+            self.dst_mac_addr,
+            self.src_mac_addr,
+            ip_vis,
+        ));
+        ipsender_virtual_alarm.set_alarm_client(ip_send);
+        ip_send.set_addr(self.interface_list[0]);
+        tcp_mac.set_transmit_client(ip_send);
+
+        let segment_buffer = s.8.write([0; MAX_SEGMENT_LEN]);
+        let tcp_mux = s.5.write(MuxTcp::new(
+            ip_send,
+            tcp_virtual_alarm,
+            self.interface_list,
+            SubSliceMut::new(segment_buffer),
+            net_cap,
+        ));
+        ip_send.set_client(tcp_mux);
+        self.ip_receive.set_tcp_client(tcp_mux);
+        tcp_virtual_alarm.set_alarm_client(tcp_mux);
+
+        tcp_mux
+    }
+}
@@ -0,0 +1,119 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for RingBufferTracer, the ring buffer backend for kernel tracing.
+//!
+//! The tracer is registered as the kernel's tracer and drains its records to
+//! a `uart::Transmit`, for example a `UartDevice` on its own UART, SEGGER RTT
+//! or USB CDC. The kernel crate must be built with the `trace_events` feature.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let trace_uart = components::console::UartMuxComponent::new(&base_peripherals.uarte0, 1_000_000)
+//!     .finalize(components::uart_mux_component_static!());
+//! let trace_device = static_init!(
+//!     capsules_core::virtualizers::virtual_uart::UartDevice<'static>,
+//!     capsules_core::virtualizers::virtual_uart::UartDevice::new(trace_uart, false)
+//! );
+//! trace_device.setup();
+//! let tracer = components::tracer::RingBufferTracerComponent::new(mux_alarm, trace_device, 100)
+//!     .finalize(components::ring_buffer_tracer_component_static!(
+//!         nrf52840::rtc::Rtc,
+//!         capsules_core::virtualizers::virtual_uart::UartDevice<'static>,
+//!         256
+//!     ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_system::tracer::{self, Record, RingBufferTracer};
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::time::Alarm;
+use kernel::hil::uart;
+
+#[macro_export]
+macro_rules! ring_buffer_tracer_component_static {
+    ($A:ty, $U:ty, $RECORDS:expr $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let records = kernel::static_buf!([capsules_system::tracer::Record; $RECORDS]);
+        let tx_buffer = kernel::static_buf!([u8; capsules_system::tracer::TX_BUF_LEN]);
+        let tracer = kernel::static_buf!(
+            capsules_system::tracer::RingBufferTracer<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $U,
+            >
+        );
+
+        (alarm, records, tx_buffer, tracer)
+    };};
+}
+
+pub struct RingBufferTracerComponent<
+    A: 'static + Alarm<'static>,
+    U: 'static + uart::Transmit<'static>,
+    const RECORDS: usize,
+> {
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    uart: &'static U,
+    drain_interval_ms: u32,
+}
+
+impl<A: 'static + Alarm<'static>, U: 'static + uart::Transmit<'static>, const RECORDS: usize>
+    RingBufferTracerComponent<A, U, RECORDS>
+{
+    pub fn new(
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        uart: &'static U,
+        drain_interval_ms: u32,
+    ) -> Self {
+        Self {
+            alarm_mux,
+            uart,
+            drain_interval_ms,
+        }
+    }
+}
+
+impl<A: 'static + Alarm<'static>, U: 'static + uart::Transmit<'static>, const RECORDS: usize>
+    Component for RingBufferTracerComponent<A, U, RECORDS>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<[Record; RECORDS]>,
+        &'static mut MaybeUninit<[u8; tracer::TX_BUF_LEN]>,
+        &'static mut MaybeUninit<RingBufferTracer<'static, VirtualMuxAlarm<'static, A>, U>>,
+    );
+    type Output = &'static RingBufferTracer<'static, VirtualMuxAlarm<'static, A>, U>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let records = static_buffer.1.write([Record::default(); RECORDS]);
+        let tx_buffer = static_buffer.2.write([0; tracer::TX_BUF_LEN]);
+
+        let tracer = static_buffer.3.write(RingBufferTracer::new(
+            alarm,
+            self.uart,
+            records,
+            tx_buffer,
+            self.drain_interval_ms,
+        ));
+        alarm.set_alarm_client(tracer);
+        self.uart.set_transmit_client(tracer);
+
+        // SAFETY: Components are finalized from the kernel thread while the
+        // board is set up, before the kernel loop starts.
+        unsafe {
+            kernel::trace::set_tracer(tracer);
+        }
+        tracer.start();
+
+        tracer
+    }
+}
@@ -18,12 +18,24 @@
 //!        local_ip_ifaces,
 //!        PAYLOAD_LEN,
 //!     )
-//!     .finalize(components::udp_driver_component_static!());
+//!     .finalize(components::udp_driver_component_static!(sam4l::ast::Ast));
+//! ```
+//!
+//! The UDP stack is built on top of an `IP6Sender`. The macro above assumes
+//! the 6LoWPAN `IP6SendStruct`; for other senders, name the sender type
+//! explicitly:
+//!
+//! ```rust
+//!     .finalize(components::udp_driver_component_static!(
+//!         @sender capsules_extra::net::ipv6::ipv6_ethernet::IP6EthernetStruct<
+//!             'static,
+//!             VirtualMuxAlarm<'static, QemuRv32VirtClint<'static>>,
+//!         >
+//!     ));
 //! ```
 
-use capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm;
 use capsules_extra::net::ipv6::ip_utils::IPAddr;
-use capsules_extra::net::ipv6::ipv6_send::IP6SendStruct;
+use capsules_extra::net::ipv6::ipv6_send::IP6Sender;
 use capsules_extra::net::network_capabilities::{
     AddrRange, NetworkCapability, PortRange, UdpVisibilityCapability,
 };
@@ -36,7 +48,6 @@ use kernel::capabilities;
 use kernel::capabilities::NetworkCapabilityCreationCapability;
 use kernel::component::Component;
 use kernel::create_capability;
-use kernel::hil::time::Alarm;
 
 const MAX_PAYLOAD_LEN: usize = super::udp_mux::MAX_PAYLOAD_LEN;
 
@@ -44,17 +55,17 @@ const MAX_PAYLOAD_LEN: usize = super::udp_mux::MAX_PAYLOAD_LEN;
 #[macro_export]
 macro_rules! udp_driver_component_static {
     ($A:ty $(,)?) => {{
-        use components::udp_mux::MAX_PAYLOAD_LEN;
-
-        let udp_send = kernel::static_buf!(
-            capsules_extra::net::udp::udp_send::UDPSendStruct<
+        $crate::udp_driver_component_static!(
+            @sender capsules_extra::net::ipv6::ipv6_send::IP6SendStruct<
                 'static,
-                capsules_extra::net::ipv6::ipv6_send::IP6SendStruct<
-                    'static,
-                    capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
-                >,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
             >
-        );
+        )
+    };};
+    (@sender $S:ty $(,)?) => {{
+        use components::udp_mux::MAX_PAYLOAD_LEN;
+
+        let udp_send = kernel::static_buf!(capsules_extra::net::udp::udp_send::UDPSendStruct<'static, $S>);
         let udp_vis_cap =
             kernel::static_buf!(capsules_extra::net::network_capabilities::UdpVisibilityCapability);
         let net_cap =
@@ -68,24 +79,20 @@ macro_rules! udp_driver_component_static {
     };};
 }
 
-pub struct UDPDriverComponent<A: Alarm<'static> + 'static> {
+pub struct UDPDriverComponent<S: IP6Sender<'static> + 'static> {
     board_kernel: &'static kernel::Kernel,
     driver_num: usize,
-    udp_send_mux:
-        &'static MuxUdpSender<'static, IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
+    udp_send_mux: &'static MuxUdpSender<'static, S>,
     udp_recv_mux: &'static MuxUdpReceiver<'static>,
     port_table: &'static UdpPortManager,
     interface_list: &'static [IPAddr],
 }
 
-impl<A: Alarm<'static>> UDPDriverComponent<A> {
+impl<S: IP6Sender<'static>> UDPDriverComponent<S> {
     pub fn new(
         board_kernel: &'static kernel::Kernel,
         driver_num: usize,
-        udp_send_mux: &'static MuxUdpSender<
-            'static,
-            IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>,
-        >,
+        udp_send_mux: &'static MuxUdpSender<'static, S>,
         udp_recv_mux: &'static MuxUdpReceiver<'static>,
         port_table: &'static UdpPortManager,
         interface_list: &'static [IPAddr],
@@ -101,17 +108,9 @@ impl<A: Alarm<'static>> UDPDriverComponent<A> {
     }
 }
 
-impl<A: Alarm<'static>> Component for UDPDriverComponent<A> {
+impl<S: IP6Sender<'static>> Component for UDPDriverComponent<S> {
     type StaticInput = (
-        &'static mut MaybeUninit<
-            UDPSendStruct<
-                'static,
-                capsules_extra::net::ipv6::ipv6_send::IP6SendStruct<
-                    'static,
-                    VirtualMuxAlarm<'static, A>,
-                >,
-            >,
-        >,
+        &'static mut MaybeUninit<UDPSendStruct<'static, S>>,
         &'static mut MaybeUninit<
             capsules_extra::net::network_capabilities::UdpVisibilityCapability,
         >,
@@ -6,22 +6,28 @@
 //!
 //! This provides one Component, UDPMuxComponent. This component
 //! exposes a MuxUdpSender that other components can implement
-//! UDPSenders on top of to use the UDP/6Lowpan stack.
+//! UDPSenders on top of to use the UDP/6Lowpan stack. It also sets up
+//! the ICMPv6 receive path, which answers Echo Requests and passes
+//! ICMPv6 errors to the UDP layer, and exposes it so that other
+//! components can receive ICMPv6 messages. The IPv6 receiver and 6LoWPAN
+//! state are exposed as well, so that other transport protocols (e.g. the
+//! TCPMuxComponent) can share the receive path.
 //!
 //! Usage
 //! -----
 //! ```rust
-//!    let (udp_mux, udp_recv) = UDPMuxComponent::new(
-//!        mux_mac,
-//!        DEFAULT_CTX_PREFIX_LEN,
-//!        DEFAULT_CTX_PREFIX,
-//!        DST_MAC_ADDR,
-//!        src_mac_from_serial_num,
-//!        local_ip_ifaces,
-//!        mux_alarm,
-//!        MAX_PAYLOAD_LEN,
-//!    )
-//!    .finalize(components::udp_mux_component_static!());
+//!    let (udp_mux, udp_recv, udp_port_table, icmp_recv, ip_receive, sixlowpan) =
+//!        UDPMuxComponent::new(
+//!            mux_mac,
+//!            DEFAULT_CTX_PREFIX_LEN,
+//!            DEFAULT_CTX_PREFIX,
+//!            DST_MAC_ADDR,
+//!            src_mac_from_serial_num,
+//!            local_ip_ifaces,
+//!            mux_alarm,
+//!            MAX_PAYLOAD_LEN,
+//!        )
+//!        .finalize(components::udp_mux_component_static!());
 //! ```
 
 // Author: Hudson Ayers <hayers@stanford.edu>
@@ -29,6 +35,8 @@
 
 use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
 use capsules_extra::ieee802154::device::MacDevice;
+use capsules_extra::net::icmpv6::icmpv6_recv::{ICMP6Receiver, ICMP6RecvStruct};
+use capsules_extra::net::icmpv6::{ICMP6Header, ICMP6Type};
 use capsules_extra::net::ieee802154::MacAddress;
 use capsules_extra::net::ipv6::ip_utils::IPAddr;
 use capsules_extra::net::ipv6::ipv6_recv::IP6Receiver;
@@ -36,7 +44,9 @@ use capsules_extra::net::ipv6::ipv6_recv::IP6RecvStruct;
 use capsules_extra::net::ipv6::ipv6_send::IP6SendStruct;
 use capsules_extra::net::ipv6::ipv6_send::IP6Sender;
 use capsules_extra::net::ipv6::{IP6Packet, IPPayload, TransportHeader};
-use capsules_extra::net::network_capabilities::{IpVisibilityCapability, UdpVisibilityCapability};
+use capsules_extra::net::network_capabilities::{
+    AddrRange, IpVisibilityCapability, NetworkCapability, PortRange, UdpVisibilityCapability,
+};
 use capsules_extra::net::sixlowpan::{sixlowpan_compression, sixlowpan_state};
 use capsules_extra::net::udp::udp_port_table::{
     SocketBindingEntry, UdpPortManager, MAX_NUM_BOUND_PORTS,
@@ -50,6 +60,7 @@ use kernel::component::Component;
 use kernel::create_capability;
 use kernel::hil::radio;
 use kernel::hil::time::Alarm;
+use kernel::utilities::leasable_buffer::SubSliceMut;
 
 // The UDP stack requires several packet buffers:
 //
@@ -59,8 +70,12 @@ use kernel::hil::time::Alarm;
 //
 //   Additionally, every capsule using the stack needs an additional buffer to craft packets for
 //   tx which can then be passed to the MuxUdpSender for tx.
+//
+//   Echo Replies are sent by the ICMPv6 layer through a separate IP6_Sender, which needs its own
+//   RADIO_BUF and IP6_Packet payload, plus a buffer to craft the reply in.
 
 pub const MAX_PAYLOAD_LEN: usize = 200; //The max size UDP message that can be sent by userspace apps or capsules
+pub const MAX_ECHO_PAYLOAD_LEN: usize = 64; //The max size Echo Request data that is answered
 
 // Setup static space for the objects.
 #[macro_export]
@@ -70,7 +85,7 @@ macro_rules! udp_mux_component_static {
         use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
         use capsules_extra::net::sixlowpan::{sixlowpan_compression, sixlowpan_state};
         use capsules_extra::net::udp::udp_send::MuxUdpSender;
-        use components::udp_mux::MAX_PAYLOAD_LEN;
+        use components::udp_mux::{MAX_ECHO_PAYLOAD_LEN, MAX_PAYLOAD_LEN};
         use core::mem::MaybeUninit;
 
         let alarm = kernel::static_buf!(VirtualMuxAlarm<'static, $A>);
@@ -133,6 +148,26 @@ macro_rules! udp_mux_component_static {
         let ip_vis_cap =
             kernel::static_buf!(capsules_extra::net::network_capabilities::IpVisibilityCapability);
 
+        let icmp_alarm = kernel::static_buf!(VirtualMuxAlarm<'static, $A>);
+        let icmp_mac_user =
+            kernel::static_buf!(capsules_extra::ieee802154::virtual_mac::MacUser<'static, $M>);
+        let icmp_ip6_send = kernel::static_buf!(
+            capsules_extra::net::ipv6::ipv6_send::IP6SendStruct<
+                'static,
+                VirtualMuxAlarm<'static, $A>,
+            >
+        );
+        let icmp_ip6_packet = kernel::static_buf!(capsules_extra::net::ipv6::IP6Packet<'static>);
+        let icmp_recv =
+            kernel::static_buf!(capsules_extra::net::icmpv6::icmpv6_recv::ICMP6RecvStruct<'static>);
+        let icmp_udp_rcvr =
+            kernel::static_buf!(capsules_extra::net::icmpv6::icmpv6_recv::ICMP6Receiver<'static>);
+        let icmp_radio_buf = kernel::static_buf!([u8; kernel::hil::radio::MAX_BUF_SIZE]);
+        let icmp_payload = kernel::static_buf!([u8; MAX_ECHO_PAYLOAD_LEN]);
+        let icmp_reply = kernel::static_buf!([u8; MAX_ECHO_PAYLOAD_LEN]);
+        let icmp_net_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::NetworkCapability);
+
         (
             alarm,
             mac_user,
@@ -150,6 +185,16 @@ macro_rules! udp_mux_component_static {
             udp_dgram,
             udp_vis_cap,
             ip_vis_cap,
+            icmp_alarm,
+            icmp_mac_user,
+            icmp_ip6_send,
+            icmp_ip6_packet,
+            icmp_recv,
+            icmp_udp_rcvr,
+            icmp_radio_buf,
+            icmp_payload,
+            icmp_reply,
+            icmp_net_cap,
         )
     };};
 }
@@ -223,11 +268,24 @@ impl<A: Alarm<'static> + 'static, M: MacDevice<'static>> Component for UDPMuxCom
         &'static mut MaybeUninit<[u8; MAX_PAYLOAD_LEN]>,
         &'static mut MaybeUninit<UdpVisibilityCapability>,
         &'static mut MaybeUninit<IpVisibilityCapability>,
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<capsules_extra::ieee802154::virtual_mac::MacUser<'static, M>>,
+        &'static mut MaybeUninit<IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
+        &'static mut MaybeUninit<IP6Packet<'static>>,
+        &'static mut MaybeUninit<ICMP6RecvStruct<'static>>,
+        &'static mut MaybeUninit<ICMP6Receiver<'static>>,
+        &'static mut MaybeUninit<[u8; radio::MAX_BUF_SIZE]>,
+        &'static mut MaybeUninit<[u8; MAX_ECHO_PAYLOAD_LEN]>,
+        &'static mut MaybeUninit<[u8; MAX_ECHO_PAYLOAD_LEN]>,
+        &'static mut MaybeUninit<NetworkCapability>,
     );
     type Output = (
         &'static MuxUdpSender<'static, IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
         &'static MuxUdpReceiver<'static>,
         &'static UdpPortManager,
+        &'static ICMP6RecvStruct<'static>,
+        &'static IP6RecvStruct<'static>,
+        &'static dyn sixlowpan_state::SixlowpanState<'static>,
     );
 
     fn finalize(self, s: Self::StaticInput) -> Self::Output {
@@ -315,6 +373,64 @@ impl<A: Alarm<'static> + 'static, M: MacDevice<'static>> Component for UDPMuxCom
             udp_vis,
         ));
 
-        (udp_send_mux, udp_recv_mux, udp_port_table)
+        // The ICMPv6 layer shares the receive path, but sends Echo Replies
+        // through its own IP sender so they cannot interfere with UDP
+        // transmissions.
+        let icmp_virtual_alarm = s.16.write(VirtualMuxAlarm::new(self.alarm_mux));
+        icmp_virtual_alarm.setup();
+        let icmp_mac =
+            s.17.write(capsules_extra::ieee802154::virtual_mac::MacUser::new(
+                self.mux_mac,
+            ));
+        self.mux_mac.add_user(icmp_mac);
+        let icmp_payload_buffer = s.23.write([0; MAX_ECHO_PAYLOAD_LEN]);
+        let icmp_pyld: IPPayload = IPPayload {
+            header: TransportHeader::ICMP(ICMP6Header::new(ICMP6Type::Type129)),
+            payload: icmp_payload_buffer,
+        };
+        let icmp_ip6_dg = s.19.write(IP6Packet::new(icmp_pyld));
+        let icmp_radio_buf = s.22.write([0; radio::MAX_BUF_SIZE]);
+        let icmp_ip_send = s.18.write(IP6SendStruct::new(
+            icmp_ip6_dg,
+            icmp_virtual_alarm,
+            icmp_radio_buf,
+            sixlowpan_state::TxState::new(sixlowpan_state),
+            icmp_mac,
+            self.dst_mac_addr,
+            self.src_mac_addr,
+            ip_vis,
+        ));
+        icmp_virtual_alarm.set_alarm_client(icmp_ip_send);
+        icmp_ip_send.set_addr(self.interface_list[0]);
+        icmp_mac.set_transmit_client(icmp_ip_send);
+
+        let icmp_net_cap = s.25.write(NetworkCapability::new(
+            AddrRange::Any,
+            PortRange::Any,
+            PortRange::Any,
+            &create_cap,
+        ));
+        let icmp_reply_buffer = s.24.write([0; MAX_ECHO_PAYLOAD_LEN]);
+        let icmp_recv = s.20.write(ICMP6RecvStruct::new(
+            icmp_ip_send,
+            self.interface_list,
+            SubSliceMut::new(icmp_reply_buffer),
+            icmp_net_cap,
+        ));
+        icmp_ip_send.set_client(icmp_recv);
+        ip_receive.set_icmp_client(icmp_recv);
+
+        let icmp_udp_rcvr = s.21.write(ICMP6Receiver::new());
+        icmp_udp_rcvr.set_client(udp_recv_mux);
+        icmp_recv.add_client(icmp_udp_rcvr);
+
+        (
+            udp_send_mux,
+            udp_recv_mux,
+            udp_port_table,
+            icmp_recv,
+            ip_receive,
+            sixlowpan_state,
+        )
     }
 }
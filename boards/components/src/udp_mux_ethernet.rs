@@ -0,0 +1,326 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Component to initialize the UDP/IPv6 interface over Ethernet.
+//!
+//! This provides one Component, UDPMuxEthernetComponent. It is the Ethernet
+//! counterpart of the `UDPMuxComponent`: it exposes a MuxUdpSender and a
+//! MuxUdpReceiver on top of an IPv6-over-Ethernet sender, and sets up the
+//! ICMPv6 receive path which answers Echo Requests and passes ICMPv6 errors
+//! to the UDP layer.
+//!
+//! Echo Replies are sent through a second IPv6 sender on its own Ethernet
+//! user, which shares the neighbor cache with the main sender. Only the main
+//! sender receives frames and answers Neighbor Solicitations.
+//!
+//! The first address of the interface list is used as the source address,
+//! and should be the link-local address derived from the Ethernet address.
+//!
+//! Usage
+//! -----
+//! ```rust
+//!    let (udp_mux, udp_recv, udp_port_table, icmp_recv) = UDPMuxEthernetComponent::new(
+//!        mux_eth,
+//!        src_mac_addr,
+//!        local_ip_ifaces,
+//!        mux_alarm,
+//!    )
+//!    .finalize(components::udp_mux_ethernet_component_static!(
+//!        qemu_rv32_virt_chip::chip::QemuRv32VirtClint,
+//!    ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::net::ethernet::virtual_ethernet::{EthernetUser, MuxEthernet};
+use capsules_extra::net::ethernet::EthernetAddress;
+use capsules_extra::net::icmpv6::icmpv6_recv::{ICMP6Receiver, ICMP6RecvStruct};
+use capsules_extra::net::icmpv6::{ICMP6Header, ICMP6Type};
+use capsules_extra::net::ipv6::ip_utils::IPAddr;
+use capsules_extra::net::ipv6::ipv6_ethernet::{
+    EthernetNeighbor, IP6EthernetStruct, ND_FRAME_LEN, TX_BUF_LEN,
+};
+use capsules_extra::net::ipv6::ipv6_recv::{IP6Receiver, IP6RecvStruct};
+use capsules_extra::net::ipv6::ipv6_send::IP6Sender;
+use capsules_extra::net::ipv6::{IP6Packet, IPPayload, TransportHeader};
+use capsules_extra::net::network_capabilities::{
+    AddrRange, IpVisibilityCapability, NetworkCapability, PortRange, UdpVisibilityCapability,
+};
+use capsules_extra::net::udp::udp_port_table::{
+    SocketBindingEntry, UdpPortManager, MAX_NUM_BOUND_PORTS,
+};
+use capsules_extra::net::udp::udp_recv::MuxUdpReceiver;
+use capsules_extra::net::udp::udp_send::MuxUdpSender;
+use capsules_extra::net::udp::UDPHeader;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::ethernet::EthernetAdapterDatapath;
+use kernel::hil::time::Alarm;
+use kernel::utilities::cells::OptionalCell;
+use kernel::utilities::leasable_buffer::SubSliceMut;
+
+use crate::udp_mux::{MAX_ECHO_PAYLOAD_LEN, MAX_PAYLOAD_LEN};
+
+/// Number of entries in the neighbor cache.
+pub const NUM_NEIGHBORS: usize = 8;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! udp_mux_ethernet_component_static {
+    ($A:ty $(,)?) => {{
+        use capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm;
+        use capsules_extra::net::ethernet::virtual_ethernet::EthernetUser;
+        use capsules_extra::net::ipv6::ipv6_ethernet::{
+            EthernetNeighbor, IP6EthernetStruct, ND_FRAME_LEN, TX_BUF_LEN,
+        };
+        use components::udp_mux::{MAX_ECHO_PAYLOAD_LEN, MAX_PAYLOAD_LEN};
+        use components::udp_mux_ethernet::NUM_NEIGHBORS;
+
+        let alarm = kernel::static_buf!(VirtualMuxAlarm<'static, $A>);
+        let eth_user = kernel::static_buf!(EthernetUser<'static>);
+        let ip6_send =
+            kernel::static_buf!(IP6EthernetStruct<'static, VirtualMuxAlarm<'static, $A>>);
+        let mux_udp_send = kernel::static_buf!(
+            capsules_extra::net::udp::udp_send::MuxUdpSender<
+                'static,
+                IP6EthernetStruct<'static, VirtualMuxAlarm<'static, $A>>,
+            >
+        );
+        let mux_udp_recv =
+            kernel::static_buf!(capsules_extra::net::udp::udp_recv::MuxUdpReceiver<'static>);
+        let udp_port_manager =
+            kernel::static_buf!(capsules_extra::net::udp::udp_port_table::UdpPortManager);
+        let ip6_packet = kernel::static_buf!(capsules_extra::net::ipv6::IP6Packet<'static>);
+        let ip6_receive =
+            kernel::static_buf!(capsules_extra::net::ipv6::ipv6_recv::IP6RecvStruct<'static>);
+        let used_ports = kernel::static_buf!(
+            [Option<capsules_extra::net::udp::udp_port_table::SocketBindingEntry>;
+                capsules_extra::net::udp::udp_port_table::MAX_NUM_BOUND_PORTS]
+        );
+        let neighbors = kernel::static_buf!(
+            [kernel::utilities::cells::OptionalCell<EthernetNeighbor>; NUM_NEIGHBORS]
+        );
+        let tx_buf = kernel::static_buf!([u8; TX_BUF_LEN]);
+        let nd_buf = kernel::static_buf!([u8; ND_FRAME_LEN]);
+        let udp_dgram = kernel::static_buf!([u8; MAX_PAYLOAD_LEN]);
+
+        let udp_vis_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::UdpVisibilityCapability);
+        let ip_vis_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::IpVisibilityCapability);
+
+        let icmp_alarm = kernel::static_buf!(VirtualMuxAlarm<'static, $A>);
+        let icmp_eth_user = kernel::static_buf!(EthernetUser<'static>);
+        let icmp_ip6_send =
+            kernel::static_buf!(IP6EthernetStruct<'static, VirtualMuxAlarm<'static, $A>>);
+        let icmp_ip6_packet = kernel::static_buf!(capsules_extra::net::ipv6::IP6Packet<'static>);
+        let icmp_recv =
+            kernel::static_buf!(capsules_extra::net::icmpv6::icmpv6_recv::ICMP6RecvStruct<'static>);
+        let icmp_udp_rcvr =
+            kernel::static_buf!(capsules_extra::net::icmpv6::icmpv6_recv::ICMP6Receiver<'static>);
+        let icmp_tx_buf = kernel::static_buf!([u8; TX_BUF_LEN]);
+        let icmp_nd_buf = kernel::static_buf!([u8; ND_FRAME_LEN]);
+        let icmp_payload = kernel::static_buf!([u8; MAX_ECHO_PAYLOAD_LEN]);
+        let icmp_reply = kernel::static_buf!([u8; MAX_ECHO_PAYLOAD_LEN]);
+        let icmp_net_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::NetworkCapability);
+
+        (
+            alarm,
+            eth_user,
+            ip6_send,
+            mux_udp_send,
+            mux_udp_recv,
+            udp_port_manager,
+            ip6_packet,
+            ip6_receive,
+            used_ports,
+            neighbors,
+            tx_buf,
+            nd_buf,
+            udp_dgram,
+            udp_vis_cap,
+            ip_vis_cap,
+            icmp_alarm,
+            icmp_eth_user,
+            icmp_ip6_send,
+            icmp_ip6_packet,
+            icmp_recv,
+            icmp_udp_rcvr,
+            icmp_tx_buf,
+            icmp_nd_buf,
+            icmp_payload,
+            icmp_reply,
+            icmp_net_cap,
+        )
+    };};
+}
+
+pub type IP6EthernetComponentType<A> = IP6EthernetStruct<'static, VirtualMuxAlarm<'static, A>>;
+
+pub struct UDPMuxEthernetComponent<A: Alarm<'static> + 'static> {
+    mux_eth: &'static MuxEthernet<'static>,
+    src_mac_addr: EthernetAddress,
+    interface_list: &'static [IPAddr],
+    alarm_mux: &'static MuxAlarm<'static, A>,
+}
+
+impl<A: Alarm<'static> + 'static> UDPMuxEthernetComponent<A> {
+    pub fn new(
+        mux_eth: &'static MuxEthernet<'static>,
+        src_mac_addr: EthernetAddress,
+        interface_list: &'static [IPAddr],
+        alarm_mux: &'static MuxAlarm<'static, A>,
+    ) -> Self {
+        Self {
+            mux_eth,
+            src_mac_addr,
+            interface_list,
+            alarm_mux,
+        }
+    }
+}
+
+impl<A: Alarm<'static> + 'static> Component for UDPMuxEthernetComponent<A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<EthernetUser<'static>>,
+        &'static mut MaybeUninit<IP6EthernetComponentType<A>>,
+        &'static mut MaybeUninit<MuxUdpSender<'static, IP6EthernetComponentType<A>>>,
+        &'static mut MaybeUninit<MuxUdpReceiver<'static>>,
+        &'static mut MaybeUninit<UdpPortManager>,
+        &'static mut MaybeUninit<IP6Packet<'static>>,
+        &'static mut MaybeUninit<IP6RecvStruct<'static>>,
+        &'static mut MaybeUninit<[Option<SocketBindingEntry>; MAX_NUM_BOUND_PORTS]>,
+        &'static mut MaybeUninit<[OptionalCell<EthernetNeighbor>; NUM_NEIGHBORS]>,
+        &'static mut MaybeUninit<[u8; TX_BUF_LEN]>,
+        &'static mut MaybeUninit<[u8; ND_FRAME_LEN]>,
+        &'static mut MaybeUninit<[u8; MAX_PAYLOAD_LEN]>,
+        &'static mut MaybeUninit<UdpVisibilityCapability>,
+        &'static mut MaybeUninit<IpVisibilityCapability>,
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<EthernetUser<'static>>,
+        &'static mut MaybeUninit<IP6EthernetComponentType<A>>,
+        &'static mut MaybeUninit<IP6Packet<'static>>,
+        &'static mut MaybeUninit<ICMP6RecvStruct<'static>>,
+        &'static mut MaybeUninit<ICMP6Receiver<'static>>,
+        &'static mut MaybeUninit<[u8; TX_BUF_LEN]>,
+        &'static mut MaybeUninit<[u8; ND_FRAME_LEN]>,
+        &'static mut MaybeUninit<[u8; MAX_ECHO_PAYLOAD_LEN]>,
+        &'static mut MaybeUninit<[u8; MAX_ECHO_PAYLOAD_LEN]>,
+        &'static mut MaybeUninit<NetworkCapability>,
+    );
+    type Output = (
+        &'static MuxUdpSender<'static, IP6EthernetComponentType<A>>,
+        &'static MuxUdpReceiver<'static>,
+        &'static UdpPortManager,
+        &'static ICMP6RecvStruct<'static>,
+    );
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let create_cap = create_capability!(capabilities::NetworkCapabilityCreationCapability);
+        let udp_vis = s.13.write(UdpVisibilityCapability::new(&create_cap));
+        let ip_vis = s.14.write(IpVisibilityCapability::new(&create_cap));
+
+        let neighbors = s.9.write(core::array::from_fn(|_| OptionalCell::empty()));
+
+        let ip_virtual_alarm = s.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        ip_virtual_alarm.setup();
+        let eth_user = s.1.write(EthernetUser::new(self.mux_eth));
+        self.mux_eth.add_user(eth_user);
+
+        let udp_dgram_buffer = s.12.write([0; MAX_PAYLOAD_LEN]);
+        let ip_pyld: IPPayload = IPPayload {
+            header: TransportHeader::UDP(UDPHeader::new()),
+            payload: udp_dgram_buffer,
+        };
+        let ip6_dg = s.6.write(IP6Packet::new(ip_pyld));
+
+        let ip_send = s.2.write(IP6EthernetStruct::new(
+            ip6_dg,
+            s.10.write([0; TX_BUF_LEN]),
+            s.11.write([0; ND_FRAME_LEN]),
+            eth_user,
+            ip_virtual_alarm,
+            self.src_mac_addr,
+            neighbors,
+            ip_vis,
+        ));
+        ip_virtual_alarm.set_alarm_client(ip_send);
+        eth_user.set_client(ip_send);
+
+        // Initially, set src IP of the sender to be the first IP in the
+        // Interface list. Userland apps can change this if they so choose.
+        ip_send.set_addr(self.interface_list[0]);
+
+        let ip_receive = s.7.write(IP6RecvStruct::new());
+        ip_send.set_receive_client(ip_receive);
+        let udp_recv_mux = s.4.write(MuxUdpReceiver::new());
+        ip_receive.set_client(udp_recv_mux);
+
+        let udp_send_mux = s.3.write(MuxUdpSender::new(ip_send));
+        ip_send.set_client(udp_send_mux);
+
+        let kernel_ports = s.8.write([None; MAX_NUM_BOUND_PORTS]);
+        let create_table_cap = create_capability!(capabilities::CreatePortTableCapability);
+        let udp_port_table = s.5.write(UdpPortManager::new(
+            &create_table_cap,
+            kernel_ports,
+            udp_vis,
+        ));
+
+        // Echo Replies are sent through their own IP sender so they cannot
+        // interfere with UDP transmissions. It shares the neighbor cache
+        // filled by the main sender, but does not receive frames itself.
+        let icmp_virtual_alarm = s.15.write(VirtualMuxAlarm::new(self.alarm_mux));
+        icmp_virtual_alarm.setup();
+        let icmp_eth_user = s.16.write(EthernetUser::new(self.mux_eth));
+        self.mux_eth.add_user(icmp_eth_user);
+
+        let icmp_payload_buffer = s.23.write([0; MAX_ECHO_PAYLOAD_LEN]);
+        let icmp_pyld: IPPayload = IPPayload {
+            header: TransportHeader::ICMP(ICMP6Header::new(ICMP6Type::Type129)),
+            payload: icmp_payload_buffer,
+        };
+        let icmp_ip6_dg = s.18.write(IP6Packet::new(icmp_pyld));
+        let icmp_ip_send = s.17.write(IP6EthernetStruct::new(
+            icmp_ip6_dg,
+            s.21.write([0; TX_BUF_LEN]),
+            s.22.write([0; ND_FRAME_LEN]),
+            icmp_eth_user,
+            icmp_virtual_alarm,
+            self.src_mac_addr,
+            neighbors,
+            ip_vis,
+        ));
+        icmp_virtual_alarm.set_alarm_client(icmp_ip_send);
+        icmp_eth_user.set_client(icmp_ip_send);
+        icmp_ip_send.set_addr(self.interface_list[0]);
+
+        let icmp_net_cap = s.25.write(NetworkCapability::new(
+            AddrRange::Any,
+            PortRange::Any,
+            PortRange::Any,
+            &create_cap,
+        ));
+        let icmp_reply_buffer = s.24.write([0; MAX_ECHO_PAYLOAD_LEN]);
+        let icmp_recv = s.19.write(ICMP6RecvStruct::new(
+            icmp_ip_send,
+            self.interface_list,
+            SubSliceMut::new(icmp_reply_buffer),
+            icmp_net_cap,
+        ));
+        icmp_ip_send.set_client(icmp_recv);
+        ip_receive.set_icmp_client(icmp_recv);
+
+        let icmp_udp_rcvr = s.20.write(ICMP6Receiver::new());
+        icmp_udp_rcvr.set_client(udp_recv_mux);
+        icmp_recv.add_client(icmp_udp_rcvr);
+
+        eth_user.enable_receive();
+
+        (udp_send_mux, udp_recv_mux, udp_port_table, icmp_recv)
+    }
+}
@@ -76,12 +76,17 @@ impl SyscallDriverLookup for Platform {
 
 struct TestLauncher {
     test_index: Cell<usize>,
+    board_kernel: &'static kernel::Kernel,
     peripherals: &'static Nrf52DefaultPeripherals<'static>,
 }
 impl TestLauncher {
-    fn new(peripherals: &'static Nrf52DefaultPeripherals<'static>) -> Self {
+    fn new(
+        board_kernel: &'static kernel::Kernel,
+        peripherals: &'static Nrf52DefaultPeripherals<'static>,
+    ) -> Self {
         Self {
             test_index: Cell::new(0),
+            board_kernel,
             peripherals,
         }
     }
@@ -96,6 +101,27 @@ impl TestLauncher {
             3 => unsafe { test::aes_test::run_aes128_ctr(&self.peripherals.ecb, self) },
             4 => unsafe { test::aes_test::run_aes128_cbc(&self.peripherals.ecb, self) },
             5 => unsafe { test::aes_test::run_aes128_ecb(&self.peripherals.ecb, self) },
+            6 => unsafe { test::signature_verify_test::run_ecdsa_p256(self) },
+            7 => unsafe { test::signature_verify_test::run_ed25519(self) },
+            8 => unsafe { test::symmetric_encryption_test::run_aes256_gcm(self) },
+            9 => unsafe { test::symmetric_encryption_test::run_chacha20poly1305(self) },
+            10 => unsafe { test::kdf_test::run_hkdf(self) },
+            11 => unsafe { test::kdf_test::run_pbkdf2(self) },
+            12 => unsafe { test::sha512_test::run_sha384(self) },
+            13 => unsafe { test::sha512_test::run_sha512(self) },
+            14 => unsafe { test::sha512_test::run_hmacsha384(self) },
+            15 => unsafe { test::sha512_test::run_hmacsha512(self) },
+            16 => unsafe { test::flash_fs_test::run_flash_fs(&self.peripherals.nvmc, self) },
+            17 => unsafe { test::block_test::run_block_cache(&self.peripherals.nvmc, self) },
+            18 => unsafe { test::fat_test::run_fat(self) },
+            19 => unsafe {
+                test::key_store_test::run_key_store(
+                    self.board_kernel,
+                    &self.peripherals.nvmc,
+                    &self.peripherals.trng,
+                    self,
+                )
+            },
             _ => kernel::debug!("All tests finished."),
         }
     }
@@ -253,7 +279,10 @@ pub unsafe fn main() {
         systick: cortexm4::systick::SysTick::new_with_calibration(64000000),
     };
 
-    let test_launcher = static_init!(TestLauncher, TestLauncher::new(base_peripherals));
+    let test_launcher = static_init!(
+        TestLauncher,
+        TestLauncher::new(board_kernel, base_peripherals)
+    );
 
     //--------------------------------------------------------------------------
     // TESTS
@@ -0,0 +1,69 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! This tests the block cache on flash pages of the nRF52840.
+//!
+//! Three pages of a storage volume are written through a two block cache, so
+//! the first block is written back before the cache is flushed. The expected
+//! output is
+//! BlockDeviceTest: passed: true
+
+use core::ptr::addr_of;
+
+use capsules_core::test::capsule_test::{CapsuleTest, CapsuleTestClient};
+use capsules_extra::block::cache::BlockCache;
+use capsules_extra::block::flash::FlashBlock;
+use capsules_extra::test::block::TestBlockDevice;
+use kernel::deferred_call::DeferredCallClient;
+use kernel::hil::block::BlockDevice;
+use kernel::hil::flash::HasClient;
+use kernel::static_init;
+use nrf52840::nvmc::{NrfPage, Nvmc};
+
+kernel::storage_volume!(BLOCK_TEST_STORAGE, 16);
+
+const NUM_BLOCKS: usize = 3;
+
+type Cache = BlockCache<'static, FlashBlock<'static, Nvmc>, 2>;
+
+pub unsafe fn run_block_cache(nvmc: &'static Nvmc, client: &'static dyn CapsuleTestClient) {
+    let t = static_init_test(nvmc, client);
+    t.run();
+}
+
+unsafe fn static_init_test(
+    nvmc: &'static Nvmc,
+    client: &'static dyn CapsuleTestClient,
+) -> &'static TestBlockDevice<'static, Cache> {
+    let page_size = core::mem::size_of::<NrfPage>();
+    // The volume is only aligned to 512 bytes, so use the pages entirely
+    // inside of it.
+    let storage = addr_of!(BLOCK_TEST_STORAGE);
+    let first_page = (storage as usize).div_ceil(page_size);
+
+    let page = static_init!(NrfPage, NrfPage::default());
+    let flash_block = static_init!(
+        FlashBlock<'static, Nvmc>,
+        FlashBlock::new(nvmc, page, first_page, NUM_BLOCKS)
+    );
+    nvmc.set_client(flash_block);
+
+    let slots = static_init!([[u8; 4096]; 2], [[0; 4096]; 2]);
+    let cache = static_init!(
+        Cache,
+        BlockCache::new(flash_block, slots.as_flattened_mut())
+    );
+    flash_block.set_client(cache);
+    cache.register();
+
+    let buffer = static_init!([u8; 4096], [0; 4096]);
+    let test = static_init!(
+        TestBlockDevice<'static, Cache>,
+        TestBlockDevice::new(cache, NUM_BLOCKS, 0x5a, buffer)
+    );
+    cache.set_client(test);
+    test.set_client(client);
+
+    test
+}
@@ -0,0 +1,78 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! This tests the FAT filesystem on FAT12, FAT16 and FAT32 volumes in RAM.
+//!
+//! Each volume gets a file with a long filename in a subdirectory whose name
+//! also needs a long filename entry. The expected output is
+//! FatTest: FAT12 passed: true
+//! FatTest: FAT16 passed: true
+//! FatTest: FAT32 passed: true
+
+use capsules_core::test::capsule_test::{CapsuleTest, CapsuleTestClient};
+use capsules_extra::fat::{FatFs, SECTOR_SIZE};
+use capsules_extra::test::fat::{SparseRamBlock, TestFat};
+use kernel::capabilities::KerneluserStorageCapability;
+use kernel::deferred_call::DeferredCallClient;
+use kernel::hil::block::BlockDevice;
+use kernel::hil::filesystem::FileSystem;
+use kernel::storage_permissions::StoragePermissions;
+use kernel::{create_capability, static_init};
+
+/// Sectors stored by the RAM device. The test writes at most seven.
+const NUM_SECTORS: usize = 12;
+
+static DIRECTORY: [u8; 4] = *b"Logs";
+static PATH: [u8; 26] = *b"Logs/2024 Temperatures.csv";
+
+static DATA: [u8; 64] = [
+    0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+    0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f,
+    0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x28, 0x29, 0x2a, 0x2b, 0x2c, 0x2d, 0x2e, 0x2f,
+    0x30, 0x31, 0x32, 0x33, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x3b, 0x3c, 0x3d, 0x3e, 0x3f,
+];
+
+type Device = SparseRamBlock<'static, NUM_SECTORS>;
+
+pub unsafe fn run_fat(client: &'static dyn CapsuleTestClient) {
+    let t = static_init_test(client);
+    t.run();
+}
+
+unsafe fn static_init_test(
+    client: &'static dyn CapsuleTestClient,
+) -> &'static TestFat<'static, FatFs<'static, Device>, NUM_SECTORS> {
+    let storage_cap = create_capability!(KerneluserStorageCapability);
+
+    let sectors = static_init!(
+        [[u8; SECTOR_SIZE]; NUM_SECTORS],
+        [[0; SECTOR_SIZE]; NUM_SECTORS]
+    );
+    let device = static_init!(Device, SparseRamBlock::new(sectors));
+    device.register();
+
+    let buffer0 = static_init!([u8; SECTOR_SIZE], [0; SECTOR_SIZE]);
+    let buffer1 = static_init!([u8; SECTOR_SIZE], [0; SECTOR_SIZE]);
+    let fs = static_init!(FatFs<'static, Device>, FatFs::new(device, buffer0, buffer1));
+    device.set_client(fs);
+    fs.register();
+
+    let buffer = static_init!([u8; 64], [0; 64]);
+    let test = static_init!(
+        TestFat<'static, FatFs<'static, Device>, NUM_SECTORS>,
+        TestFat::new(
+            fs,
+            device,
+            StoragePermissions::new_kernel(&storage_cap),
+            &DIRECTORY,
+            &PATH,
+            &DATA,
+            buffer,
+        )
+    );
+    fs.set_client(test);
+    test.set_client(client);
+
+    test
+}
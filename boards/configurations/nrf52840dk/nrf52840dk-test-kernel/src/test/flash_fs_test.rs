@@ -0,0 +1,80 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! This tests the flash filesystem on the internal flash of the nRF52840.
+//!
+//! The filesystem uses a storage volume in the kernel flash region and is
+//! formatted the first time the test runs. The expected output is
+//! FileSystemTest: data matches: true
+//! FileSystemTest: directory entry matches: true
+//! FileSystemTest: passed: true
+
+use core::ptr::addr_of;
+
+use capsules_core::test::capsule_test::{CapsuleTest, CapsuleTestClient};
+use capsules_extra::flash_fs::FlashFs;
+use capsules_extra::test::filesystem::TestFileSystem;
+use kernel::capabilities::KerneluserStorageCapability;
+use kernel::deferred_call::DeferredCallClient;
+use kernel::hil::filesystem::FileSystem;
+use kernel::hil::flash::HasClient;
+use kernel::storage_permissions::StoragePermissions;
+use kernel::{create_capability, static_init};
+use nrf52840::nvmc::{NrfPage, Nvmc};
+
+kernel::storage_volume!(FLASH_FS_STORAGE, 32);
+
+static NAME: [u8; 9] = *b"test.file";
+
+static DATA: [u8; 64] = [
+    0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+    0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f,
+    0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x28, 0x29, 0x2a, 0x2b, 0x2c, 0x2d, 0x2e, 0x2f,
+    0x30, 0x31, 0x32, 0x33, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x3b, 0x3c, 0x3d, 0x3e, 0x3f,
+];
+
+pub unsafe fn run_flash_fs(nvmc: &'static Nvmc, client: &'static dyn CapsuleTestClient) {
+    let t = static_init_test(nvmc, client);
+    t.run();
+}
+
+unsafe fn static_init_test(
+    nvmc: &'static Nvmc,
+    client: &'static dyn CapsuleTestClient,
+) -> &'static TestFileSystem<'static, FlashFs<'static, Nvmc>> {
+    let storage_cap = create_capability!(KerneluserStorageCapability);
+
+    let page_size = core::mem::size_of::<NrfPage>();
+    let storage = addr_of!(FLASH_FS_STORAGE);
+    let meta_page = static_init!(NrfPage, NrfPage::default());
+    let page = static_init!(NrfPage, NrfPage::default());
+    let fs = static_init!(
+        FlashFs<'static, Nvmc>,
+        FlashFs::new(
+            nvmc,
+            storage as usize / page_size,
+            (*storage).len() / page_size,
+            meta_page,
+            page,
+        )
+    );
+    nvmc.set_client(fs);
+    fs.register();
+
+    let buffer = static_init!([u8; 64], [0; 64]);
+    let test = static_init!(
+        TestFileSystem<'static, FlashFs<'static, Nvmc>>,
+        TestFileSystem::new(
+            fs,
+            StoragePermissions::new_kernel(&storage_cap),
+            &NAME,
+            &DATA,
+            buffer,
+        )
+    );
+    fs.set_client(test);
+    test.set_client(client);
+
+    test
+}
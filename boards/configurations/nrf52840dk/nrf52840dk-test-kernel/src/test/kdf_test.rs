@@ -0,0 +1,136 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! This tests the HKDF-SHA256 and PBKDF2-HMAC-SHA256 capsules on top of the
+//! software HMAC-SHA256.
+//!
+//! The HKDF vector is test case 1 from RFC 5869, the PBKDF2 vector is the
+//! two iteration "password"/"salt" case of RFC 6070 computed with SHA-256
+//! instead of SHA-1. The expected output is
+//! HkdfTest: output matches: true
+//! Pbkdf2Test: output matches: true
+
+use core::ptr::addr_of_mut;
+
+use capsules_core::test::capsule_test::{CapsuleTest, CapsuleTestClient};
+use capsules_extra::hkdf::HkdfSha256;
+use capsules_extra::hmac_sha256::HmacSha256Software;
+use capsules_extra::pbkdf2::Pbkdf2Sha256;
+use capsules_extra::sha256::Sha256Software;
+use capsules_extra::test::kdf::{TestHkdf, TestPbkdf2};
+use kernel::deferred_call::DeferredCallClient;
+use kernel::static_init;
+
+type Hmac = HmacSha256Software<'static, Sha256Software<'static>>;
+
+pub unsafe fn run_hkdf(client: &'static dyn CapsuleTestClient) {
+    let t = static_init_test_hkdf(client);
+    t.run();
+}
+
+pub unsafe fn run_pbkdf2(client: &'static dyn CapsuleTestClient) {
+    let t = static_init_test_pbkdf2(client);
+    t.run();
+}
+
+static HKDF_IKM: [u8; 22] = [0x0b; 22];
+
+static HKDF_SALT: [u8; 13] = [
+    0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c,
+];
+
+static HKDF_INFO: [u8; 10] = [0xf0, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9];
+
+static HKDF_OKM: [u8; 42] = [
+    0x3c, 0xb2, 0x5f, 0x25, 0xfa, 0xac, 0xd5, 0x7a, 0x90, 0x43, 0x4f, 0x64, 0xd0, 0x36, 0x2f, 0x2a,
+    0x2d, 0x2d, 0x0a, 0x90, 0xcf, 0x1a, 0x5a, 0x4c, 0x5d, 0xb0, 0x2d, 0x56, 0xec, 0xc4, 0xc5, 0xbf,
+    0x34, 0x00, 0x72, 0x08, 0xd5, 0xb8, 0x87, 0x18, 0x58, 0x65,
+];
+
+static mut HKDF_OUT: [u8; 42] = [0; 42];
+
+static PBKDF2_PASSWORD: [u8; 8] = *b"password";
+static PBKDF2_SALT: [u8; 4] = *b"salt";
+
+static PBKDF2_DK: [u8; 32] = [
+    0xae, 0x4d, 0x0c, 0x95, 0xaf, 0x6b, 0x46, 0xd3, 0x2d, 0x0a, 0xdf, 0xf9, 0x28, 0xf0, 0x6d, 0xd0,
+    0x2a, 0x30, 0x3f, 0x8e, 0xf3, 0xc2, 0x51, 0xdf, 0xd6, 0xe2, 0xd8, 0x5a, 0x95, 0x47, 0x4c, 0x43,
+];
+
+static mut PBKDF2_OUT: [u8; 32] = [0; 32];
+
+unsafe fn static_init_hmac() -> &'static Hmac {
+    let sha256 = static_init!(Sha256Software<'static>, Sha256Software::new());
+    sha256.register();
+
+    let hmac_data_buf = static_init!([u8; 64], [0; 64]);
+    let hmac_verify_buf = static_init!([u8; 32], [0; 32]);
+    let hmac = static_init!(
+        Hmac,
+        HmacSha256Software::new(sha256, hmac_data_buf, hmac_verify_buf)
+    );
+    kernel::hil::digest::Digest::set_client(sha256, hmac);
+
+    hmac
+}
+
+unsafe fn static_init_test_hkdf(
+    client: &'static dyn CapsuleTestClient,
+) -> &'static TestHkdf<'static, Hmac> {
+    let hmac = static_init_hmac();
+
+    let data_buf = static_init!([u8; 128], [0; 128]);
+    let digest_buf = static_init!([u8; 32], [0; 32]);
+    let hkdf = static_init!(
+        HkdfSha256<'static, Hmac>,
+        HkdfSha256::new(hmac, data_buf, digest_buf)
+    );
+    kernel::hil::digest::Digest::set_client(hmac, hkdf);
+
+    let test = static_init!(
+        TestHkdf<'static, Hmac>,
+        TestHkdf::new(
+            hkdf,
+            &HKDF_SALT,
+            &HKDF_IKM,
+            &HKDF_INFO,
+            &HKDF_OKM,
+            &mut *addr_of_mut!(HKDF_OUT),
+        )
+    );
+    hkdf.set_client(test);
+    test.set_client(client);
+
+    test
+}
+
+unsafe fn static_init_test_pbkdf2(
+    client: &'static dyn CapsuleTestClient,
+) -> &'static TestPbkdf2<'static, Hmac> {
+    let hmac = static_init_hmac();
+
+    let data_buf = static_init!([u8; 64], [0; 64]);
+    let digest_buf = static_init!([u8; 32], [0; 32]);
+    let pbkdf2 = static_init!(
+        Pbkdf2Sha256<'static, Hmac>,
+        Pbkdf2Sha256::new(hmac, data_buf, digest_buf)
+    );
+    kernel::hil::digest::Digest::set_client(hmac, pbkdf2);
+
+    let test = static_init!(
+        TestPbkdf2<'static, Hmac>,
+        TestPbkdf2::new(
+            pbkdf2,
+            &PBKDF2_PASSWORD,
+            &PBKDF2_SALT,
+            2,
+            &PBKDF2_DK,
+            &mut *addr_of_mut!(PBKDF2_OUT),
+        )
+    );
+    pbkdf2.set_client(test);
+    test.set_client(client);
+
+    test
+}
@@ -0,0 +1,180 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! This tests the key store on a TicKV database in the internal flash of the
+//! nRF52840.
+//!
+//! Keys are stored for two `ShortId`s, using the software AES-256-GCM engine
+//! and the hardware RNG. The expected output is
+//! KeyStoreTest: opened key matches: true
+//! KeyStoreTest: other ShortId denied: true
+//! KeyStoreTest: moved key fails to unseal: true
+//! KeyStoreTest: passed: true
+
+use core::num::NonZeroU32;
+use core::ptr::addr_of;
+
+use capsules_core::rng::Entropy32ToRandom;
+use capsules_core::test::capsule_test::{CapsuleTest, CapsuleTestClient};
+use capsules_extra::key_store::{KeyStore, KV_KEY_BUFFER_SIZE, MAX_BLOB_LENGTH, MAX_KEY_SIZE};
+use capsules_extra::kv_store_permissions::HEADER_LENGTH;
+use capsules_extra::symmetric_encryption::software_aes::SoftwareAes;
+use capsules_extra::test::key_store::TestKeyStore;
+use kernel::capabilities::{ApplicationStorageCapability, MemoryAllocationCapability};
+use kernel::component::Component;
+use kernel::hil::entropy::Entropy32;
+use kernel::hil::kv::KVPermissions;
+use kernel::hil::rng::Rng;
+use kernel::hil::symmetric_encryption::AES256GCM;
+use kernel::storage_permissions::StoragePermissions;
+use kernel::{create_capability, static_init};
+use nrf52840::nvmc::{NrfPage, Nvmc};
+use nrf52840::trng::Trng;
+
+kernel::storage_volume!(KEY_STORE_STORAGE, 32);
+
+/// Key-encryption key for the test, a real board uses a device secret.
+static KEY_ENCRYPTION_KEY: [u8; 32] = [0x4b; 32];
+
+const TICKV_PAGE_SIZE: usize = core::mem::size_of::<NrfPage>();
+
+type Siphasher24 = components::siphash::Siphasher24ComponentType;
+type TicKVDedicatedFlash =
+    components::tickv::TicKVDedicatedFlashComponentType<Nvmc, Siphasher24, TICKV_PAGE_SIZE>;
+type TicKVKVStore = components::kv::TicKVKVStoreComponentType<
+    TicKVDedicatedFlash,
+    capsules_extra::tickv::TicKVKeyType,
+>;
+type KVStorePermissions = components::kv::KVStorePermissionsComponentType<TicKVKVStore>;
+type VirtualKVPermissions = components::kv::VirtualKVPermissionsComponentType<KVStorePermissions>;
+type KeyStoreType = KeyStore<
+    'static,
+    VirtualKVPermissions,
+    SoftwareAes<'static>,
+    Entropy32ToRandom<'static, Trng<'static>>,
+>;
+type KeyStoreTestType = TestKeyStore<
+    'static,
+    VirtualKVPermissions,
+    SoftwareAes<'static>,
+    Entropy32ToRandom<'static, Trng<'static>>,
+>;
+
+pub unsafe fn run_key_store(
+    board_kernel: &'static kernel::Kernel,
+    nvmc: &'static Nvmc,
+    trng: &'static Trng<'static>,
+    client: &'static dyn CapsuleTestClient,
+) {
+    let t = static_init_test(board_kernel, nvmc, trng, client);
+    t.run();
+}
+
+unsafe fn static_init_test(
+    board_kernel: &'static kernel::Kernel,
+    nvmc: &'static Nvmc,
+    trng: &'static Trng<'static>,
+    client: &'static dyn CapsuleTestClient,
+) -> &'static KeyStoreTestType {
+    let grant_cap = create_capability!(MemoryAllocationCapability);
+    let storage_cap = create_capability!(ApplicationStorageCapability);
+
+    // TicKV in a storage volume of the internal flash.
+    let storage = addr_of!(KEY_STORE_STORAGE);
+    let sip_hash = components::siphash::Siphasher24Component::new()
+        .finalize(components::siphasher24_component_static!());
+    let page = static_init!(NrfPage, NrfPage::default());
+    let tickv = components::tickv::TicKVDedicatedFlashComponent::new(
+        sip_hash,
+        nvmc,
+        storage as usize / TICKV_PAGE_SIZE,
+        (*storage).len(),
+        page,
+    )
+    .finalize(components::tickv_dedicated_flash_component_static!(
+        Nvmc,
+        Siphasher24,
+        TICKV_PAGE_SIZE,
+    ));
+    let tickv_kv_store = components::kv::TicKVKVStoreComponent::new(tickv).finalize(
+        components::tickv_kv_store_component_static!(
+            TicKVDedicatedFlash,
+            capsules_extra::tickv::TicKVKeyType,
+        ),
+    );
+    let kv_store_permissions = components::kv::KVStorePermissionsComponent::new(tickv_kv_store)
+        .finalize(components::kv_store_permissions_component_static!(
+            TicKVKVStore
+        ));
+    let mux_kv = components::kv::KVPermissionsMuxComponent::new(kv_store_permissions).finalize(
+        components::kv_permissions_mux_component_static!(KVStorePermissions),
+    );
+
+    // One k-v user for the key store, and one for the test to move values.
+    let key_store_kv = components::kv::VirtualKVPermissionsComponent::new(mux_kv).finalize(
+        components::virtual_kv_permissions_component_static!(KVStorePermissions),
+    );
+    let test_kv = components::kv::VirtualKVPermissionsComponent::new(mux_kv).finalize(
+        components::virtual_kv_permissions_component_static!(KVStorePermissions),
+    );
+
+    let aes = static_init!(SoftwareAes<'static>, SoftwareAes::new());
+    kernel::deferred_call::DeferredCallClient::register(aes);
+
+    let rng = static_init!(
+        Entropy32ToRandom<'static, Trng<'static>>,
+        Entropy32ToRandom::new(trng)
+    );
+    trng.set_client(rng);
+
+    let kv_key_buffer = static_init!([u8; KV_KEY_BUFFER_SIZE], [0; KV_KEY_BUFFER_SIZE]);
+    let value_buffer = static_init!(
+        [u8; MAX_BLOB_LENGTH + HEADER_LENGTH],
+        [0; MAX_BLOB_LENGTH + HEADER_LENGTH]
+    );
+    let crypt_buffer = static_init!(
+        [u8; capsules_extra::key_store::CRYPT_BUFFER_SIZE],
+        [0; capsules_extra::key_store::CRYPT_BUFFER_SIZE]
+    );
+    let key_store = static_init!(
+        KeyStoreType,
+        KeyStore::new(
+            key_store_kv,
+            aes,
+            rng,
+            &KEY_ENCRYPTION_KEY,
+            kv_key_buffer,
+            value_buffer,
+            crypt_buffer,
+            board_kernel.create_grant(capsules_extra::key_store::DRIVER_NUM, &grant_cap),
+        )
+    );
+    key_store_kv.set_client(key_store);
+    aes.set_client(key_store);
+    rng.set_client(key_store);
+
+    let key_buffer = static_init!([u8; MAX_KEY_SIZE], [0; MAX_KEY_SIZE]);
+    let test_kv_key_buffer = static_init!([u8; KV_KEY_BUFFER_SIZE], [0; KV_KEY_BUFFER_SIZE]);
+    let test_value_buffer = static_init!(
+        [u8; MAX_BLOB_LENGTH + HEADER_LENGTH],
+        [0; MAX_BLOB_LENGTH + HEADER_LENGTH]
+    );
+    let test = static_init!(
+        KeyStoreTestType,
+        TestKeyStore::new(
+            key_store,
+            test_kv,
+            StoragePermissions::new_self_only(NonZeroU32::new(0x1000).unwrap(), &storage_cap),
+            StoragePermissions::new_self_only(NonZeroU32::new(0x2000).unwrap(), &storage_cap),
+            key_buffer,
+            test_kv_key_buffer,
+            test_value_buffer,
+        )
+    );
+    key_store.set_client(test);
+    test_kv.set_client(test);
+    test.set_client(client);
+
+    test
+}
@@ -3,6 +3,14 @@
 // Copyright Tock Contributors 2023.
 
 pub(crate) mod aes_test;
+pub(crate) mod block_test;
+pub(crate) mod fat_test;
+pub(crate) mod flash_fs_test;
 pub(crate) mod hmac_sha256_test;
+pub(crate) mod kdf_test;
+pub(crate) mod key_store_test;
 pub(crate) mod sha256_test;
+pub(crate) mod sha512_test;
+pub(crate) mod signature_verify_test;
 pub(crate) mod siphash24_test;
+pub(crate) mod symmetric_encryption_test;
@@ -0,0 +1,109 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! This tests the software SHA-384, SHA-512, HMAC-SHA384 and HMAC-SHA512
+//! implementations.
+//!
+//! The hashes are of 12 repetitions of "hello " (72 bytes), the same message
+//! as the SHA-256 test, added in two parts. The HMAC vectors are test case 2
+//! from RFC 4231. The expected output is
+//! Sha512Test: SHA-384 matches: true
+//! Sha512Test: SHA-512 matches: true
+//! HmacSha512Test: HMAC-SHA384 matches: true
+//! HmacSha512Test: HMAC-SHA512 matches: true
+
+use capsules_core::test::capsule_test::{CapsuleTest, CapsuleTestClient};
+use capsules_extra::hmac_sha512::HmacSha512Software;
+use capsules_extra::sha512::Sha512Software;
+use capsules_extra::test::hmac_sha512::TestHmacSha512;
+use capsules_extra::test::sha512::TestSha512;
+use kernel::deferred_call::DeferredCallClient;
+use kernel::static_init;
+
+static LSTRING: [u8; 72] =
+    *b"hello hello hello hello hello hello hello hello hello hello hello hello ";
+
+static SHA384_HASH: [u8; 48] = [
+    0x4a, 0xf8, 0x10, 0x2c, 0x94, 0x5f, 0x20, 0x86, 0x6c, 0xd9, 0xe6, 0x61, 0xb4, 0x03, 0xf4, 0xc6,
+    0x2f, 0x68, 0xf4, 0x47, 0xf8, 0x7d, 0xdb, 0x6c, 0x84, 0x25, 0x37, 0x43, 0x8f, 0x34, 0xb4, 0xeb,
+    0x24, 0x6e, 0x21, 0xab, 0xfc, 0xc9, 0xc6, 0xdb, 0xc9, 0x16, 0x9a, 0x47, 0x45, 0x36, 0xb2, 0xd8,
+];
+
+static SHA512_HASH: [u8; 64] = [
+    0x91, 0xb8, 0x3f, 0x49, 0x9e, 0x89, 0xc5, 0xd2, 0x48, 0x2c, 0x9c, 0x48, 0xbc, 0x81, 0x56, 0x24,
+    0xda, 0xb5, 0x96, 0x6b, 0xf8, 0xaa, 0x90, 0xf0, 0x40, 0xe6, 0x85, 0x8f, 0x3f, 0xf8, 0x7d, 0x9f,
+    0xce, 0xa6, 0x41, 0xd0, 0xa6, 0xe2, 0x99, 0xf5, 0x8e, 0x81, 0x86, 0xf0, 0xca, 0x21, 0x71, 0x94,
+    0x11, 0x5c, 0xeb, 0x88, 0x61, 0x3b, 0x17, 0x70, 0x4d, 0x4a, 0xec, 0xdc, 0x32, 0x2b, 0xc9, 0xed,
+];
+
+static HMAC_KEY: [u8; 4] = *b"Jefe";
+static HMAC_DATA: [u8; 28] = *b"what do ya want for nothing?";
+
+static HMAC_SHA384: [u8; 48] = [
+    0xaf, 0x45, 0xd2, 0xe3, 0x76, 0x48, 0x40, 0x31, 0x61, 0x7f, 0x78, 0xd2, 0xb5, 0x8a, 0x6b, 0x1b,
+    0x9c, 0x7e, 0xf4, 0x64, 0xf5, 0xa0, 0x1b, 0x47, 0xe4, 0x2e, 0xc3, 0x73, 0x63, 0x22, 0x44, 0x5e,
+    0x8e, 0x22, 0x40, 0xca, 0x5e, 0x69, 0xe2, 0xc7, 0x8b, 0x32, 0x39, 0xec, 0xfa, 0xb2, 0x16, 0x49,
+];
+
+static HMAC_SHA512: [u8; 64] = [
+    0x16, 0x4b, 0x7a, 0x7b, 0xfc, 0xf8, 0x19, 0xe2, 0xe3, 0x95, 0xfb, 0xe7, 0x3b, 0x56, 0xe0, 0xa3,
+    0x87, 0xbd, 0x64, 0x22, 0x2e, 0x83, 0x1f, 0xd6, 0x10, 0x27, 0x0c, 0xd7, 0xea, 0x25, 0x05, 0x54,
+    0x97, 0x58, 0xbf, 0x75, 0xc0, 0x5a, 0x99, 0x4a, 0x6d, 0x03, 0x4f, 0x65, 0xf8, 0xf0, 0xe6, 0xfd,
+    0xca, 0xea, 0xb1, 0xa3, 0x4d, 0x4a, 0x6b, 0x4b, 0x63, 0x6e, 0x07, 0x0a, 0x38, 0xbc, 0xe7, 0x37,
+];
+
+// Each test needs its own engine and buffers, so these are macros rather than
+// functions: every expansion gets its own `static_init!` storage.
+macro_rules! static_init_test_sha512 {
+    ($correct:expr, $client:expr) => {{
+        let sha = static_init!(Sha512Software<'static>, Sha512Software::new());
+        sha.register();
+
+        let data = static_init!([u8; 72], LSTRING);
+        let digest = static_init!([u8; 64], [0; 64]);
+
+        let test = static_init!(TestSha512, TestSha512::new(sha, data, digest, $correct));
+        test.set_client($client);
+
+        test
+    }};
+}
+
+macro_rules! static_init_test_hmacsha512 {
+    ($correct:expr, $client:expr) => {{
+        let hmac = static_init!(HmacSha512Software<'static>, HmacSha512Software::new());
+        hmac.register();
+
+        let data = static_init!([u8; 28], HMAC_DATA);
+        let digest = static_init!([u8; 64], [0; 64]);
+
+        let test = static_init!(
+            TestHmacSha512,
+            TestHmacSha512::new(hmac, &HMAC_KEY, data, digest, $correct)
+        );
+        test.set_client($client);
+
+        test
+    }};
+}
+
+pub unsafe fn run_sha384(client: &'static dyn CapsuleTestClient) {
+    let t = static_init_test_sha512!(&SHA384_HASH, client);
+    t.run();
+}
+
+pub unsafe fn run_sha512(client: &'static dyn CapsuleTestClient) {
+    let t = static_init_test_sha512!(&SHA512_HASH, client);
+    t.run();
+}
+
+pub unsafe fn run_hmacsha384(client: &'static dyn CapsuleTestClient) {
+    let t = static_init_test_hmacsha512!(&HMAC_SHA384, client);
+    t.run();
+}
+
+pub unsafe fn run_hmacsha512(client: &'static dyn CapsuleTestClient) {
+    let t = static_init_test_hmacsha512!(&HMAC_SHA512, client);
+    t.run();
+}
@@ -0,0 +1,136 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! This tests the software ECDSA P-256 and Ed25519 signature verifiers.
+//!
+//! Each test verifies the signature of a SHA-256 hash under a fixed public
+//! key, and then checks that changed copies of the signature and hash are
+//! rejected. The expected output of each test is
+//! SignatureVerifyTest: Verification result: Ok(true)
+//! followed by one line of
+//! SignatureVerifyTest: Verification result: Ok(false)
+//! for each change.
+
+use core::ptr::addr_of_mut;
+
+use capsules_core::test::capsule_test::{CapsuleTest, CapsuleTestClient};
+use capsules_extra::public_key_crypto::ecdsa_p256::EcdsaP256SignatureVerifier;
+use capsules_extra::public_key_crypto::ed25519::Ed25519SignatureVerifier;
+use capsules_extra::test::signature_verify::{Tamper, TestSignatureVerify};
+use kernel::static_init;
+
+pub unsafe fn run_ecdsa_p256(client: &'static dyn CapsuleTestClient) {
+    let t = static_init_test_ecdsa_p256(client);
+    t.run();
+}
+
+pub unsafe fn run_ed25519(client: &'static dyn CapsuleTestClient) {
+    let t = static_init_test_ed25519(client);
+    t.run();
+}
+
+// The uncompressed public key `x || y`.
+static ECDSA_P256_PUBLIC_KEY: [u8; 64] = [
+    0x14, 0xb8, 0xa2, 0xc9, 0x56, 0x26, 0xf1, 0x64, 0xe3, 0x87, 0x03, 0xbd, 0x97, 0x6b, 0x20, 0x0e,
+    0x06, 0x50, 0x50, 0x3e, 0x4b, 0x70, 0x1e, 0xcb, 0xf2, 0x9f, 0x96, 0xab, 0xf7, 0x86, 0xd3, 0x1f,
+    0x9b, 0x97, 0x8f, 0x67, 0xb1, 0xea, 0x48, 0x27, 0x36, 0xe6, 0x3b, 0x98, 0xc4, 0x45, 0x74, 0x5a,
+    0x52, 0x11, 0x35, 0xbf, 0x46, 0x8d, 0x6d, 0x0c, 0x16, 0x8e, 0xf6, 0x6a, 0x41, 0x63, 0xf4, 0x6f,
+];
+pub static mut ECDSA_P256_HASH: [u8; 32] = [
+    0x42, 0xa9, 0x8f, 0x3d, 0x3e, 0xe0, 0x95, 0x18, 0xc8, 0xe2, 0x36, 0x99, 0xaf, 0x60, 0xfa, 0x6d,
+    0x97, 0xbb, 0x45, 0x74, 0x36, 0xa6, 0x81, 0x42, 0xb3, 0x42, 0xd2, 0x39, 0x5e, 0xcf, 0xe4, 0x05,
+];
+pub static mut ECDSA_P256_SIGNATURE: [u8; 64] = [
+    0x8d, 0xd0, 0xcb, 0x91, 0xf7, 0x83, 0x32, 0x8c, 0x76, 0xcb, 0xdb, 0xdc, 0x31, 0x06, 0xe4, 0x43,
+    0x5e, 0x34, 0xcd, 0x76, 0x35, 0xa7, 0x47, 0xf1, 0x35, 0xf4, 0x45, 0x7e, 0x8e, 0xcf, 0x1a, 0x6c,
+    0xc7, 0x74, 0x6c, 0xa1, 0x8e, 0xb3, 0x7d, 0xb7, 0xca, 0x14, 0x7b, 0x16, 0xdd, 0x17, 0x97, 0xab,
+    0x52, 0xba, 0x63, 0xbc, 0x2a, 0x63, 0x47, 0x85, 0x4a, 0x07, 0x1c, 0xa4, 0x74, 0x5d, 0x46, 0x91,
+];
+
+// The order `n` of the P-256 base point, which is not a valid `r` or `s`.
+static ECDSA_P256_ORDER: [u8; 32] = [
+    0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xbc, 0xe6, 0xfa, 0xad, 0xa7, 0x17, 0x9e, 0x84, 0xf3, 0xb9, 0xca, 0xc2, 0xfc, 0x63, 0x25, 0x51,
+];
+static ECDSA_P256_TAMPERS: [Tamper; 4] = [
+    Tamper::FlipSignatureByte(17),
+    Tamper::FlipHashByte(0),
+    Tamper::OverwriteSignature(0, &ECDSA_P256_ORDER),
+    Tamper::OverwriteSignature(32, &ECDSA_P256_ORDER),
+];
+
+static ED25519_PUBLIC_KEY: [u8; 32] = [
+    0x77, 0x49, 0xf8, 0xd4, 0x5d, 0x88, 0x68, 0x6f, 0xed, 0x70, 0x80, 0x8c, 0xe1, 0x8e, 0xa8, 0x14,
+    0xa5, 0xbe, 0x23, 0x24, 0xfd, 0xb0, 0x80, 0x17, 0x93, 0xd0, 0x36, 0x21, 0x48, 0xee, 0x88, 0xbb,
+];
+pub static mut ED25519_HASH: [u8; 32] = [
+    0xe4, 0x22, 0x3e, 0xd2, 0x0d, 0x7e, 0xa5, 0x74, 0x0a, 0x32, 0x6e, 0x2b, 0x26, 0x8c, 0xa6, 0xdb,
+    0x91, 0xd0, 0x41, 0xcf, 0x51, 0x94, 0xf5, 0x77, 0xe3, 0x93, 0xa8, 0xba, 0x3b, 0x85, 0xd8, 0xe9,
+];
+pub static mut ED25519_SIGNATURE: [u8; 64] = [
+    0x32, 0x39, 0x16, 0xb1, 0x5c, 0x79, 0xc7, 0x21, 0xfb, 0xd4, 0xe9, 0x6b, 0xad, 0x65, 0x09, 0xcf,
+    0xc0, 0x0b, 0x8b, 0x2d, 0x1d, 0x28, 0xc7, 0xde, 0x8e, 0xb4, 0xab, 0x13, 0x7d, 0x2a, 0xdc, 0xd7,
+    0x25, 0xce, 0x50, 0x25, 0x19, 0xb8, 0x10, 0xc8, 0x41, 0x7c, 0x9b, 0x4b, 0x3a, 0x44, 0x2f, 0x3d,
+    0xea, 0xa9, 0x2e, 0x83, 0x28, 0xda, 0x8d, 0x19, 0xbe, 0xbc, 0x48, 0x57, 0xff, 0x10, 0xae, 0x09,
+];
+// The order `L` of the Ed25519 base point in little endian, which is not a
+// valid `S`.
+static ED25519_ORDER: [u8; 32] = [
+    0xed, 0xd3, 0xf5, 0x5c, 0x1a, 0x63, 0x12, 0x58, 0xd6, 0x9c, 0xf7, 0xa2, 0xde, 0xf9, 0xde, 0x14,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10,
+];
+static ED25519_TAMPERS: [Tamper; 4] = [
+    Tamper::FlipSignatureByte(5),
+    Tamper::FlipSignatureByte(40),
+    Tamper::FlipHashByte(31),
+    Tamper::OverwriteSignature(32, &ED25519_ORDER),
+];
+
+unsafe fn static_init_test_ecdsa_p256(
+    client: &'static dyn CapsuleTestClient,
+) -> &'static TestSignatureVerify<EcdsaP256SignatureVerifier<'static, 32>, 32, 64> {
+    let verifier = static_init!(
+        EcdsaP256SignatureVerifier<'static, 32>,
+        EcdsaP256SignatureVerifier::new(&ECDSA_P256_PUBLIC_KEY)
+    );
+    kernel::deferred_call::DeferredCallClient::register(verifier);
+
+    let test = static_init!(
+        TestSignatureVerify<EcdsaP256SignatureVerifier<'static, 32>, 32, 64>,
+        TestSignatureVerify::new(
+            verifier,
+            &mut *addr_of_mut!(ECDSA_P256_HASH),
+            &mut *addr_of_mut!(ECDSA_P256_SIGNATURE),
+            true,
+            &ECDSA_P256_TAMPERS,
+        )
+    );
+    test.set_client(client);
+
+    test
+}
+
+unsafe fn static_init_test_ed25519(
+    client: &'static dyn CapsuleTestClient,
+) -> &'static TestSignatureVerify<Ed25519SignatureVerifier<'static, 32>, 32, 64> {
+    let verifier = static_init!(
+        Ed25519SignatureVerifier<'static, 32>,
+        Ed25519SignatureVerifier::new(&ED25519_PUBLIC_KEY)
+    );
+    kernel::deferred_call::DeferredCallClient::register(verifier);
+
+    let test = static_init!(
+        TestSignatureVerify<Ed25519SignatureVerifier<'static, 32>, 32, 64>,
+        TestSignatureVerify::new(
+            verifier,
+            &mut *addr_of_mut!(ED25519_HASH),
+            &mut *addr_of_mut!(ED25519_SIGNATURE),
+            true,
+            &ED25519_TAMPERS,
+        )
+    );
+    test.set_client(client);
+
+    test
+}
@@ -0,0 +1,157 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! This tests the software AES-256-GCM and ChaCha20-Poly1305 engines.
+//!
+//! The AES-256-GCM vector is test case 16 from "The Galois/Counter Mode of
+//! Operation (GCM)", the ChaCha20-Poly1305 vector is from RFC 8439 section
+//! 2.8.2. The expected output ends with
+//! Aes256GcmTest: decrypted, plaintext matches: true, tag is valid: true
+//! Aes256GcmTest: decrypted with a corrupted tag, tag is valid: false
+//! Aes256GcmTest: decrypted a corrupted ciphertext, tag is valid: false
+//! and the same lines for ChaCha20Poly1305Test.
+
+use core::ptr::addr_of_mut;
+
+use capsules_core::test::capsule_test::{CapsuleTest, CapsuleTestClient};
+use capsules_extra::symmetric_encryption::chacha20poly1305::SoftwareChaCha20Poly1305;
+use capsules_extra::symmetric_encryption::software_aes::SoftwareAes;
+use capsules_extra::test::aes256_gcm::TestAes256Gcm;
+use capsules_extra::test::chacha20poly1305::TestChaCha20Poly1305;
+use kernel::hil::symmetric_encryption::{ChaCha20Poly1305, AES256GCM};
+use kernel::static_init;
+
+pub unsafe fn run_aes256_gcm(client: &'static dyn CapsuleTestClient) {
+    let t = static_init_test_aes256_gcm(client);
+    t.run();
+}
+
+pub unsafe fn run_chacha20poly1305(client: &'static dyn CapsuleTestClient) {
+    let t = static_init_test_chacha20poly1305(client);
+    t.run();
+}
+
+static AES256_GCM_KEY: [u8; 32] = [
+    0xfe, 0xff, 0xe9, 0x92, 0x86, 0x65, 0x73, 0x1c, 0x6d, 0x6a, 0x8f, 0x94, 0x67, 0x30, 0x83, 0x08,
+    0xfe, 0xff, 0xe9, 0x92, 0x86, 0x65, 0x73, 0x1c, 0x6d, 0x6a, 0x8f, 0x94, 0x67, 0x30, 0x83, 0x08,
+];
+
+static AES256_GCM_IV: [u8; 12] = [
+    0xca, 0xfe, 0xba, 0xbe, 0xfa, 0xce, 0xdb, 0xad, 0xde, 0xca, 0xf8, 0x88,
+];
+
+static AES256_GCM_AAD: [u8; 20] = [
+    0xfe, 0xed, 0xfa, 0xce, 0xde, 0xad, 0xbe, 0xef, 0xfe, 0xed, 0xfa, 0xce, 0xde, 0xad, 0xbe, 0xef,
+    0xab, 0xad, 0xda, 0xd2,
+];
+
+static AES256_GCM_PLAINTEXT: [u8; 60] = [
+    0xd9, 0x31, 0x32, 0x25, 0xf8, 0x84, 0x06, 0xe5, 0xa5, 0x59, 0x09, 0xc5, 0xaf, 0xf5, 0x26, 0x9a,
+    0x86, 0xa7, 0xa9, 0x53, 0x15, 0x34, 0xf7, 0xda, 0x2e, 0x4c, 0x30, 0x3d, 0x8a, 0x31, 0x8a, 0x72,
+    0x1c, 0x3c, 0x0c, 0x95, 0x95, 0x68, 0x09, 0x53, 0x2f, 0xcf, 0x0e, 0x24, 0x49, 0xa6, 0xb5, 0x25,
+    0xb1, 0x6a, 0xed, 0xf5, 0xaa, 0x0d, 0xe6, 0x57, 0xba, 0x63, 0x7b, 0x39,
+];
+
+// The ciphertext followed by the tag
+static AES256_GCM_CIPHERTEXT: [u8; 76] = [
+    0x52, 0x2d, 0xc1, 0xf0, 0x99, 0x56, 0x7d, 0x07, 0xf4, 0x7f, 0x37, 0xa3, 0x2a, 0x84, 0x42, 0x7d,
+    0x64, 0x3a, 0x8c, 0xdc, 0xbf, 0xe5, 0xc0, 0xc9, 0x75, 0x98, 0xa2, 0xbd, 0x25, 0x55, 0xd1, 0xaa,
+    0x8c, 0xb0, 0x8e, 0x48, 0x59, 0x0d, 0xbb, 0x3d, 0xa7, 0xb0, 0x8b, 0x10, 0x56, 0x82, 0x88, 0x38,
+    0xc5, 0xf6, 0x1e, 0x63, 0x93, 0xba, 0x7a, 0x0a, 0xbc, 0xc9, 0xf6, 0x62, 0x76, 0xfc, 0x6e, 0xce,
+    0x0f, 0x4e, 0x17, 0x68, 0xcd, 0xdf, 0x88, 0x53, 0xbb, 0x2d, 0x55, 0x1b,
+];
+
+static mut AES256_GCM_BUF: [u8; 96] = [0; 96];
+
+static CHACHA20_POLY1305_KEY: [u8; 32] = [
+    0x80, 0x81, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89, 0x8a, 0x8b, 0x8c, 0x8d, 0x8e, 0x8f,
+    0x90, 0x91, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0x9b, 0x9c, 0x9d, 0x9e, 0x9f,
+];
+
+static CHACHA20_POLY1305_NONCE: [u8; 12] = [
+    0x07, 0x00, 0x00, 0x00, 0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47,
+];
+
+static CHACHA20_POLY1305_AAD: [u8; 12] = [
+    0x50, 0x51, 0x52, 0x53, 0xc0, 0xc1, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7,
+];
+
+// "Ladies and Gentlemen of the class of '99: If I could offer you only one
+// tip for the future, sunscreen would be it."
+static CHACHA20_POLY1305_PLAINTEXT: [u8; 114] = [
+    0x4c, 0x61, 0x64, 0x69, 0x65, 0x73, 0x20, 0x61, 0x6e, 0x64, 0x20, 0x47, 0x65, 0x6e, 0x74, 0x6c,
+    0x65, 0x6d, 0x65, 0x6e, 0x20, 0x6f, 0x66, 0x20, 0x74, 0x68, 0x65, 0x20, 0x63, 0x6c, 0x61, 0x73,
+    0x73, 0x20, 0x6f, 0x66, 0x20, 0x27, 0x39, 0x39, 0x3a, 0x20, 0x49, 0x66, 0x20, 0x49, 0x20, 0x63,
+    0x6f, 0x75, 0x6c, 0x64, 0x20, 0x6f, 0x66, 0x66, 0x65, 0x72, 0x20, 0x79, 0x6f, 0x75, 0x20, 0x6f,
+    0x6e, 0x6c, 0x79, 0x20, 0x6f, 0x6e, 0x65, 0x20, 0x74, 0x69, 0x70, 0x20, 0x66, 0x6f, 0x72, 0x20,
+    0x74, 0x68, 0x65, 0x20, 0x66, 0x75, 0x74, 0x75, 0x72, 0x65, 0x2c, 0x20, 0x73, 0x75, 0x6e, 0x73,
+    0x63, 0x72, 0x65, 0x65, 0x6e, 0x20, 0x77, 0x6f, 0x75, 0x6c, 0x64, 0x20, 0x62, 0x65, 0x20, 0x69,
+    0x74, 0x2e,
+];
+
+// The ciphertext followed by the tag
+static CHACHA20_POLY1305_CIPHERTEXT: [u8; 130] = [
+    0xd3, 0x1a, 0x8d, 0x34, 0x64, 0x8e, 0x60, 0xdb, 0x7b, 0x86, 0xaf, 0xbc, 0x53, 0xef, 0x7e, 0xc2,
+    0xa4, 0xad, 0xed, 0x51, 0x29, 0x6e, 0x08, 0xfe, 0xa9, 0xe2, 0xb5, 0xa7, 0x36, 0xee, 0x62, 0xd6,
+    0x3d, 0xbe, 0xa4, 0x5e, 0x8c, 0xa9, 0x67, 0x12, 0x82, 0xfa, 0xfb, 0x69, 0xda, 0x92, 0x72, 0x8b,
+    0x1a, 0x71, 0xde, 0x0a, 0x9e, 0x06, 0x0b, 0x29, 0x05, 0xd6, 0xa5, 0xb6, 0x7e, 0xcd, 0x3b, 0x36,
+    0x92, 0xdd, 0xbd, 0x7f, 0x2d, 0x77, 0x8b, 0x8c, 0x98, 0x03, 0xae, 0xe3, 0x28, 0x09, 0x1b, 0x58,
+    0xfa, 0xb3, 0x24, 0xe4, 0xfa, 0xd6, 0x75, 0x94, 0x55, 0x85, 0x80, 0x8b, 0x48, 0x31, 0xd7, 0xbc,
+    0x3f, 0xf4, 0xde, 0xf0, 0x8e, 0x4b, 0x7a, 0x9d, 0xe5, 0x76, 0xd2, 0x65, 0x86, 0xce, 0xc6, 0x4b,
+    0x61, 0x16, 0x1a, 0xe1, 0x0b, 0x59, 0x4f, 0x09, 0xe2, 0x6a, 0x7e, 0x90, 0x2e, 0xcb, 0xd0, 0x60,
+    0x06, 0x91,
+];
+
+static mut CHACHA20_POLY1305_BUF: [u8; 142] = [0; 142];
+
+unsafe fn static_init_test_aes256_gcm(
+    client: &'static dyn CapsuleTestClient,
+) -> &'static TestAes256Gcm<'static, SoftwareAes<'static>> {
+    let aes = static_init!(SoftwareAes<'static>, SoftwareAes::new());
+    kernel::deferred_call::DeferredCallClient::register(aes);
+
+    let test = static_init!(
+        TestAes256Gcm<'static, SoftwareAes<'static>>,
+        TestAes256Gcm::new(
+            aes,
+            &AES256_GCM_KEY,
+            &AES256_GCM_IV,
+            &AES256_GCM_AAD,
+            &AES256_GCM_PLAINTEXT,
+            &AES256_GCM_CIPHERTEXT,
+            &mut *addr_of_mut!(AES256_GCM_BUF),
+        )
+    );
+    AES256GCM::set_client(aes, test);
+    test.set_client(client);
+
+    test
+}
+
+unsafe fn static_init_test_chacha20poly1305(
+    client: &'static dyn CapsuleTestClient,
+) -> &'static TestChaCha20Poly1305<'static, SoftwareChaCha20Poly1305<'static>> {
+    let chacha = static_init!(
+        SoftwareChaCha20Poly1305<'static>,
+        SoftwareChaCha20Poly1305::new()
+    );
+    kernel::deferred_call::DeferredCallClient::register(chacha);
+
+    let test = static_init!(
+        TestChaCha20Poly1305<'static, SoftwareChaCha20Poly1305<'static>>,
+        TestChaCha20Poly1305::new(
+            chacha,
+            &CHACHA20_POLY1305_KEY,
+            &CHACHA20_POLY1305_NONCE,
+            &CHACHA20_POLY1305_AAD,
+            &CHACHA20_POLY1305_PLAINTEXT,
+            &CHACHA20_POLY1305_CIPHERTEXT,
+            &mut *addr_of_mut!(CHACHA20_POLY1305_BUF),
+        )
+    );
+    chacha.set_client(test);
+    test.set_client(client);
+
+    test
+}
@@ -161,6 +161,8 @@ struct Imix {
     ipc: kernel::ipc::IPC<{ NUM_PROCS as u8 }>,
     ninedof: &'static capsules_extra::ninedof::NineDof<'static>,
     udp_driver: &'static capsules_extra::net::udp::UDPDriver<'static>,
+    #[cfg(feature = "icmpv6_driver")]
+    icmp_driver: &'static capsules_extra::net::icmpv6::ICMP6Driver,
     crc: &'static capsules_extra::crc::CrcDriver<'static, sam4l::crccu::Crccu<'static>>,
     usb_driver: &'static capsules_extra::usb::usb_user::UsbSyscallDriver<
         'static,
@@ -194,6 +196,8 @@ impl SyscallDriverLookup for Imix {
             capsules_extra::crc::DRIVER_NUM => f(Some(self.crc)),
             capsules_extra::usb::usb_user::DRIVER_NUM => f(Some(self.usb_driver)),
             capsules_extra::net::udp::DRIVER_NUM => f(Some(self.udp_driver)),
+            #[cfg(feature = "icmpv6_driver")]
+            capsules_extra::net::icmpv6::DRIVER_NUM => f(Some(self.icmp_driver)),
             capsules_extra::nrf51822_serialization::DRIVER_NUM => f(Some(self.nrf51822)),
             capsules_extra::nonvolatile_storage_driver::DRIVER_NUM => {
                 f(Some(self.nonvolatile_storage))
@@ -641,6 +645,7 @@ unsafe fn start() -> (
         &peripherals.flash_controller,
         0x60000, // Start address for userspace accessible region
         0x20000, // Length of userspace accessible region
+        0x2000,  // Size of each app's region
         core::ptr::addr_of!(_sstorage) as usize, //start address of kernel region
         core::ptr::addr_of!(_estorage) as usize - core::ptr::addr_of!(_sstorage) as usize, // length of kernel region
     )
@@ -663,20 +668,23 @@ unsafe fn start() -> (
         ]
     );
 
-    let (udp_send_mux, udp_recv_mux, udp_port_table) = components::udp_mux::UDPMuxComponent::new(
-        mux_mac,
-        DEFAULT_CTX_PREFIX_LEN,
-        DEFAULT_CTX_PREFIX,
-        DST_MAC_ADDR,
-        src_mac_from_serial_num, //comment out for dual rx test only
-        //MacAddress::Short(49138), //comment in for dual rx test only
-        local_ip_ifaces,
-        mux_alarm,
-    )
-    .finalize(components::udp_mux_component_static!(
-        sam4l::ast::Ast,
-        Ieee802154MacDevice
-    ));
+    // `icmp_recv` is only used by the optional ICMPv6 driver and 6LoWPAN ND.
+    #[allow(unused_variables)]
+    let (udp_send_mux, udp_recv_mux, udp_port_table, icmp_recv, _, _) =
+        components::udp_mux::UDPMuxComponent::new(
+            mux_mac,
+            DEFAULT_CTX_PREFIX_LEN,
+            DEFAULT_CTX_PREFIX,
+            DST_MAC_ADDR,
+            src_mac_from_serial_num, //comment out for dual rx test only
+            //MacAddress::Short(49138), //comment in for dual rx test only
+            local_ip_ifaces,
+            mux_alarm,
+        )
+        .finalize(components::udp_mux_component_static!(
+            sam4l::ast::Ast,
+            Ieee802154MacDevice
+        ));
 
     // UDP driver initialization happens here
     let udp_driver = components::udp_driver::UDPDriverComponent::new(
@@ -689,6 +697,34 @@ unsafe fn start() -> (
     )
     .finalize(components::udp_driver_component_static!(sam4l::ast::Ast));
 
+    #[cfg(feature = "icmpv6_driver")]
+    let icmp_driver = components::icmpv6_driver::ICMP6DriverComponent::new(
+        board_kernel,
+        capsules_extra::net::icmpv6::DRIVER_NUM,
+        icmp_recv,
+    )
+    .finalize(components::icmpv6_driver_component_static!());
+
+    // Join a 6LoWPAN network managed by a border router, if there is one.
+    #[cfg(feature = "sixlowpan_nd")]
+    {
+        let sixlowpan_nd = components::sixlowpan_nd::SixlowpanNDComponent::new(
+            mux_mac,
+            DEFAULT_CTX_PREFIX_LEN,
+            DEFAULT_CTX_PREFIX,
+            DST_MAC_ADDR,
+            DEFAULT_EXT_SRC_MAC,
+            icmp_recv,
+            mux_alarm,
+        )
+        .finalize(components::sixlowpan_nd_component_static!(
+            sam4l::ast::Ast,
+            Ieee802154MacDevice,
+            4
+        ));
+        let _ = sixlowpan_nd.start();
+    }
+
     let scheduler = components::sched::round_robin::RoundRobinComponent::new(&*addr_of!(PROCESSES))
         .finalize(components::round_robin_component_static!(NUM_PROCS));
 
@@ -759,6 +795,8 @@ unsafe fn start() -> (
         ipc: kernel::ipc::IPC::new(board_kernel, kernel::ipc::DRIVER_NUM, &grant_cap),
         ninedof,
         udp_driver,
+        #[cfg(feature = "icmpv6_driver")]
+        icmp_driver,
         usb_driver,
         nrf51822: nrf_serialization,
         nonvolatile_storage,
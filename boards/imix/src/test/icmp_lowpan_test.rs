@@ -37,6 +37,7 @@ use kernel::debug;
 use kernel::hil::radio;
 use kernel::hil::time::{self, Alarm, ConvertTicks};
 use kernel::static_init;
+use kernel::utilities::leasable_buffer::SubSliceMut;
 
 pub const SRC_ADDR: IPAddr = IPAddr([
     0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
@@ -257,7 +258,7 @@ impl<'a, A: time::Alarm<'a>> LowpanICMPTest<'a, A> {
             self.icmp_sender.send(
                 DST_ADDR,
                 icmp_hdr,
-                &mut *addr_of_mut!(ICMP_PAYLOAD),
+                &SubSliceMut::new(&mut *addr_of_mut!(ICMP_PAYLOAD)),
                 self.net_cap,
             )
         };
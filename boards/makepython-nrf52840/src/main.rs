@@ -154,10 +154,12 @@ pub struct Platform {
     adc: &'static capsules_core::adc::AdcVirtualized<'static>,
     rng: &'static RngDriver,
     ipc: kernel::ipc::IPC<{ NUM_PROCS as u8 }>,
+    ipc_mailbox: &'static capsules_extra::ipc_mailbox::IpcMailbox<64, 4>,
     alarm: &'static AlarmDriver,
     button: &'static capsules_core::button::Button<'static, nrf52840::gpio::GPIOPin<'static>>,
     screen: &'static ScreenDriver,
     udp_driver: &'static capsules_extra::net::udp::UDPDriver<'static>,
+    app_loader: &'static capsules_extra::app_loader::AppLoader<'static>,
     scheduler: &'static RoundRobinSched<'static>,
     systick: cortexm4::systick::SysTick,
 }
@@ -179,7 +181,9 @@ impl SyscallDriverLookup for Platform {
             capsules_extra::ble_advertising_driver::DRIVER_NUM => f(Some(self.ble_radio)),
             capsules_extra::ieee802154::DRIVER_NUM => f(Some(self.ieee802154_radio)),
             capsules_extra::net::udp::DRIVER_NUM => f(Some(self.udp_driver)),
+            capsules_extra::app_loader::DRIVER_NUM => f(Some(self.app_loader)),
             kernel::ipc::DRIVER_NUM => f(Some(&self.ipc)),
+            capsules_extra::ipc_mailbox::DRIVER_NUM => f(Some(self.ipc_mailbox)),
             _ => f(None),
         }
     }
@@ -606,19 +610,20 @@ pub unsafe fn start() -> (
         ]
     );
 
-    let (udp_send_mux, udp_recv_mux, udp_port_table) = components::udp_mux::UDPMuxComponent::new(
-        mux_mac,
-        DEFAULT_CTX_PREFIX_LEN,
-        DEFAULT_CTX_PREFIX,
-        DST_MAC_ADDR,
-        MacAddress::Short(device_id_bottom_16),
-        local_ip_ifaces,
-        mux_alarm,
-    )
-    .finalize(components::udp_mux_component_static!(
-        nrf52840::rtc::Rtc,
-        Ieee802154MacDevice
-    ));
+    let (udp_send_mux, udp_recv_mux, udp_port_table, _icmp_recv, _, _) =
+        components::udp_mux::UDPMuxComponent::new(
+            mux_mac,
+            DEFAULT_CTX_PREFIX_LEN,
+            DEFAULT_CTX_PREFIX,
+            DST_MAC_ADDR,
+            MacAddress::Short(device_id_bottom_16),
+            local_ip_ifaces,
+            mux_alarm,
+        )
+        .finalize(components::udp_mux_component_static!(
+            nrf52840::rtc::Rtc,
+            Ieee802154MacDevice
+        ));
 
     // UDP driver initialization happens here
     let udp_driver = components::udp_driver::UDPDriverComponent::new(
@@ -669,7 +674,7 @@ pub unsafe fn start() -> (
     //--------------------------------------------------------------------------
 
     // Create and start the asynchronous process loader.
-    let _loader = components::loader::sequential::ProcessLoaderSequentialComponent::new(
+    let loader = components::loader::sequential::ProcessLoaderSequentialComponent::new(
         checker,
         &mut *addr_of_mut!(PROCESSES),
         board_kernel,
@@ -684,6 +689,43 @@ pub unsafe fn start() -> (
         NUM_PROCS
     ));
 
+    // Allow processes to install new applications at runtime, for example
+    // over the USB console, by writing them into free app flash.
+    let dynamic_binary_storage =
+        components::dynamic_binary_storage::SequentialBinaryStorageComponent::new(
+            &base_peripherals.nvmc,
+            loader,
+        )
+        .finalize(components::sequential_binary_storage_component_static!(
+            nrf52840::nvmc::Nvmc,
+        ));
+
+    let app_loader = components::app_loader::AppLoaderComponent::new(
+        board_kernel,
+        capsules_extra::app_loader::DRIVER_NUM,
+        dynamic_binary_storage,
+        dynamic_binary_storage,
+    )
+    .finalize(components::app_loader_component_static!());
+
+    // Let the process console remove processes so that their flash and
+    // memory can be reused by newly installed applications.
+    pconsole.set_process_unloader(dynamic_binary_storage);
+    kernel::dynamic_binary_storage::DynamicProcessUnload::set_unload_client(
+        dynamic_binary_storage,
+        pconsole,
+    );
+
+    //--------------------------------------------------------------------------
+    // IPC MAILBOXES
+    //--------------------------------------------------------------------------
+
+    let ipc_mailbox = components::ipc_mailbox::IpcMailboxComponent::new(
+        board_kernel,
+        capsules_extra::ipc_mailbox::DRIVER_NUM,
+    )
+    .finalize(components::ipc_mailbox_component_static!(64, 4));
+
     //--------------------------------------------------------------------------
     // FINAL SETUP AND BOARD BOOT
     //--------------------------------------------------------------------------
@@ -708,11 +750,13 @@ pub unsafe fn start() -> (
         screen,
         alarm,
         udp_driver,
+        app_loader,
         ipc: kernel::ipc::IPC::new(
             board_kernel,
             kernel::ipc::DRIVER_NUM,
             &memory_allocation_capability,
         ),
+        ipc_mailbox,
         scheduler,
         systick: cortexm4::systick::SysTick::new_with_calibration(64000000),
     };
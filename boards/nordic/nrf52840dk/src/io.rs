@@ -72,9 +72,26 @@ pub unsafe fn panic_fmt(pi: &core::panic::PanicInfo) -> ! {
     use nrf52840::gpio::Pin;
 
     use crate::CHIP;
+    use crate::CRASH_DUMP;
     use crate::PROCESSES;
     use crate::PROCESS_PRINTER;
 
+    // Record the panic before printing it, in case printing fails.
+    if let Some(crash_dump) = *addr_of!(CRASH_DUMP) {
+        extern "C" {
+            /// End of the kernel stack, defined in the linker script.
+            static _estack: u8;
+        }
+        // The stack from here up, which holds the frames of the panic.
+        let stack_pointer = addr_of!(pi) as usize;
+        let stack_end = addr_of!(_estack) as usize;
+        let kernel_stack = core::slice::from_raw_parts(
+            stack_pointer as *const u8,
+            stack_end.saturating_sub(stack_pointer),
+        );
+        let _ = crash_dump.record_panic(pi, kernel_stack);
+    }
+
     // The nRF52840DK LEDs (see back of board)
     let led_kernel_pin = &nrf52840::gpio::GPIOPin::new(Pin::P0_13);
     let led = &mut led::LedLow::new(led_kernel_pin);
@@ -70,7 +70,7 @@
 #![no_std]
 #![deny(missing_docs)]
 
-use core::ptr::addr_of;
+use core::ptr::{addr_of, addr_of_mut};
 
 use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
 use capsules_extra::net::ieee802154::MacAddress;
@@ -147,6 +147,12 @@ pub static mut PROCESSES: [Option<&'static dyn kernel::process::Process>; NUM_PR
 static mut CHIP: Option<&'static nrf52840::chip::NRF52<Nrf52840DefaultPeripherals>> = None;
 static mut PROCESS_PRINTER: Option<&'static capsules_system::process_printer::ProcessPrinterText> =
     None;
+/// Crash dumps of faulted processes and kernel panics.
+pub static mut CRASH_DUMP: Option<&'static capsules_system::crash_dump::CrashDump<'static>> = None;
+
+/// Memory for crash dumps, which is kept across resets.
+#[link_section = ".crash_dump"]
+static mut CRASH_DUMP_MEMORY: [u8; 0x2000] = [0; 0x2000];
 
 /// Dummy buffer that causes the linker to reserve enough space for the stack.
 #[no_mangle]
@@ -302,7 +308,7 @@ impl KernelResources<Chip> for Platform {
     }
 }
 
-/// Create the capsules needed for the in-kernel UDP and 15.4 stack.
+/// Create the capsules needed for the in-kernel UDP, TCP and 15.4 stack.
 pub unsafe fn ieee802154_udp(
     board_kernel: &'static kernel::Kernel,
     nrf52840_peripherals: &'static Nrf52840DefaultPeripherals<'static>,
@@ -311,6 +317,7 @@ pub unsafe fn ieee802154_udp(
     &'static Eui64Driver,
     &'static Ieee802154Driver,
     &'static capsules_extra::net::udp::UDPDriver<'static>,
+    &'static capsules_extra::net::tcp::TcpDriver<'static>,
 ) {
     //--------------------------------------------------------------------------
     // AES
@@ -364,32 +371,61 @@ pub unsafe fn ieee802154_udp(
         ]
     );
 
-    let (udp_send_mux, udp_recv_mux, udp_port_table) = components::udp_mux::UDPMuxComponent::new(
+    let (udp_send_mux, udp_recv_mux, udp_port_table, _icmp_recv, ip_receive, sixlowpan) =
+        components::udp_mux::UDPMuxComponent::new(
+            mux_mac,
+            DEFAULT_CTX_PREFIX_LEN,
+            DEFAULT_CTX_PREFIX,
+            DST_MAC_ADDR,
+            MacAddress::Long(device_id),
+            local_ip_ifaces,
+            mux_alarm,
+        )
+        .finalize(components::udp_mux_component_static!(
+            nrf52840::rtc::Rtc,
+            Ieee802154MacDevice
+        ));
+
+    // UDP driver initialization happens here
+    let udp_driver = components::udp_driver::UDPDriverComponent::new(
+        board_kernel,
+        capsules_extra::net::udp::driver::DRIVER_NUM,
+        udp_send_mux,
+        udp_recv_mux,
+        udp_port_table,
+        local_ip_ifaces,
+    )
+    .finalize(components::udp_driver_component_static!(nrf52840::rtc::Rtc));
+
+    //--------------------------------------------------------------------------
+    // TCP
+    //--------------------------------------------------------------------------
+
+    let tcp_mux = components::tcp_mux::TCPMuxComponent::new(
         mux_mac,
-        DEFAULT_CTX_PREFIX_LEN,
-        DEFAULT_CTX_PREFIX,
+        sixlowpan,
+        ip_receive,
         DST_MAC_ADDR,
         MacAddress::Long(device_id),
         local_ip_ifaces,
         mux_alarm,
     )
-    .finalize(components::udp_mux_component_static!(
+    .finalize(components::tcp_mux_component_static!(
         nrf52840::rtc::Rtc,
         Ieee802154MacDevice
     ));
 
-    // UDP driver initialization happens here
-    let udp_driver = components::udp_driver::UDPDriverComponent::new(
+    let tcp_driver = components::tcp_driver::TCPDriverComponent::new(
         board_kernel,
-        capsules_extra::net::udp::driver::DRIVER_NUM,
-        udp_send_mux,
-        udp_recv_mux,
-        udp_port_table,
-        local_ip_ifaces,
+        capsules_extra::net::tcp::DRIVER_NUM,
+        tcp_mux,
     )
-    .finalize(components::udp_driver_component_static!(nrf52840::rtc::Rtc));
+    .finalize(components::tcp_driver_component_static!(
+        nrf52840::rtc::Rtc,
+        2
+    ));
 
-    (eui64_driver, ieee802154_driver, udp_driver)
+    (eui64_driver, ieee802154_driver, udp_driver, tcp_driver)
 }
 
 /// This is in a separate, inline(never) function so that its stack frame is
@@ -593,6 +629,15 @@ pub unsafe fn start() -> (
         .finalize(components::process_printer_text_component_static!());
     PROCESS_PRINTER = Some(process_printer);
 
+    // Crash dumps of faulted processes and kernel panics, kept across resets.
+    let crash_dump = components::crash_dump::CrashDumpComponent::new(
+        &mut *addr_of_mut!(CRASH_DUMP_MEMORY),
+        process_printer,
+        512,
+    )
+    .finalize(components::crash_dump_component_static!());
+    CRASH_DUMP = Some(crash_dump);
+
     // Virtualize the UART channel for the console and for kernel debug.
     let uart_mux = components::console::UartMuxComponent::new(uart_channel, 115200)
         .finalize(components::uart_mux_component_static!());
@@ -609,6 +654,7 @@ pub unsafe fn start() -> (
     .finalize(components::process_console_component_static!(
         nrf52840::rtc::Rtc<'static>
     ));
+    pconsole.set_crash_dumps(crash_dump);
 
     // Setup the serial console for userspace.
     let console = components::console::ConsoleComponent::new(
@@ -790,6 +836,15 @@ pub unsafe fn start() -> (
         VirtualKVPermissions
     ));
 
+    // Inspect the KV store from the process console with the `kv` command.
+    let virtual_kv_console = components::kv::VirtualKVPermissionsComponent::new(mux_kv).finalize(
+        components::virtual_kv_permissions_component_static!(KVStorePermissions),
+    );
+    let kv_console = components::kv::KVConsoleComponent::new(virtual_kv_console).finalize(
+        components::kv_console_component_static!(VirtualKVPermissions),
+    );
+    pconsole.register_command(kv_console);
+
     //--------------------------------------------------------------------------
     // I2C CONTROLLER/TARGET
     //--------------------------------------------------------------------------
@@ -12,6 +12,7 @@
 
 use core::ptr::addr_of_mut;
 
+use kernel::component::Component;
 use kernel::debug;
 use kernel::platform::{KernelResources, SyscallDriverLookup};
 use kernel::{capabilities, create_capability};
@@ -22,11 +23,16 @@ use nrf52840dk_lib::{self, PROCESSES};
 const FAULT_RESPONSE: capsules_system::process_policies::PanicFaultPolicy =
     capsules_system::process_policies::PanicFaultPolicy {};
 
+type FlashFs = components::filesystem::FlashFsComponentType<nrf52840::nvmc::Nvmc>;
+type FileSystemDriver = components::filesystem::FileSystemDriverComponentType<FlashFs>;
+
 struct Platform {
     base: nrf52840dk_lib::Platform,
     eui64_driver: &'static nrf52840dk_lib::Eui64Driver,
     ieee802154_driver: &'static nrf52840dk_lib::Ieee802154Driver,
     udp_driver: &'static capsules_extra::net::udp::UDPDriver<'static>,
+    tcp_driver: &'static capsules_extra::net::tcp::TcpDriver<'static>,
+    filesystem: &'static FileSystemDriver,
 }
 
 impl SyscallDriverLookup for Platform {
@@ -37,7 +43,9 @@ impl SyscallDriverLookup for Platform {
         match driver_num {
             capsules_extra::eui64::DRIVER_NUM => f(Some(self.eui64_driver)),
             capsules_extra::net::udp::DRIVER_NUM => f(Some(self.udp_driver)),
+            capsules_extra::net::tcp::DRIVER_NUM => f(Some(self.tcp_driver)),
             capsules_extra::ieee802154::DRIVER_NUM => f(Some(self.ieee802154_driver)),
+            capsules_extra::filesystem_driver::DRIVER_NUM => f(Some(self.filesystem)),
             _ => self.base.with_driver(driver_num, f),
         }
     }
@@ -85,19 +93,61 @@ pub unsafe fn main() {
         nrf52840dk_lib::start();
 
     //--------------------------------------------------------------------------
-    // IEEE 802.15.4 and UDP
+    // IEEE 802.15.4, UDP and TCP
     //--------------------------------------------------------------------------
 
-    let (eui64_driver, ieee802154_driver, udp_driver) =
+    let (eui64_driver, ieee802154_driver, udp_driver, tcp_driver) =
         nrf52840dk_lib::ieee802154_udp(board_kernel, default_peripherals, mux_alarm);
 
+    //--------------------------------------------------------------------------
+    // FILESYSTEM
+    //--------------------------------------------------------------------------
+
+    // 28kB of internal flash for the filesystem, page aligned. The volume is
+    // placed in the 256kB kernel region, which it shares with the network
+    // stack:
+    kernel::storage_volume!(FILESYSTEM_STORAGE, 28);
+
+    let page_size = core::mem::size_of::<nrf52840::nvmc::NrfPage>();
+    let mux_flash = components::flash::FlashMuxComponent::new(&default_peripherals.nrf52.nvmc)
+        .finalize(components::flash_mux_component_static!(
+            nrf52840::nvmc::Nvmc
+        ));
+    let flash_fs = components::filesystem::FlashFsComponent::new(
+        mux_flash,
+        core::ptr::addr_of!(FILESYSTEM_STORAGE) as usize / page_size,
+        FILESYSTEM_STORAGE.len() / page_size,
+    )
+    .finalize(components::flash_fs_component_static!(nrf52840::nvmc::Nvmc));
+    let filesystem = components::filesystem::FileSystemDriverComponent::new(
+        flash_fs,
+        board_kernel,
+        capsules_extra::filesystem_driver::DRIVER_NUM,
+    )
+    .finalize(components::filesystem_driver_component_static!(FlashFs));
+
     let platform = Platform {
         base: base_platform,
         eui64_driver,
         ieee802154_driver,
         udp_driver,
+        tcp_driver,
+        filesystem,
     };
 
+    // Record a crash dump of faulted processes before responding to the fault.
+    let fault_policy: &'static dyn kernel::process::ProcessFaultPolicy =
+        match *core::ptr::addr_of!(nrf52840dk_lib::CRASH_DUMP) {
+            Some(crash_dump) => components::crash_dump::CrashDumpFaultPolicyComponent::new(
+                crash_dump,
+                FAULT_RESPONSE,
+            )
+            .finalize(components::crash_dump_fault_policy_component_static!(
+                capsules_system::process_policies::PanicFaultPolicy
+            )),
+            None => &FAULT_RESPONSE,
+        };
+
     // These symbols are defined in the linker script.
     extern "C" {
         /// Beginning of the ROM region containing app images.
@@ -124,7 +174,7 @@ pub unsafe fn main() {
             core::ptr::addr_of!(_eappmem) as usize - core::ptr::addr_of!(_sappmem) as usize,
         ),
         &mut *addr_of_mut!(PROCESSES),
-        &FAULT_RESPONSE,
+        fault_policy,
         &process_management_capability,
     )
     .unwrap_or_else(|err| {
@@ -156,6 +156,19 @@ static mut RSA_HARDWARE: Option<&lowrisc::rsa::OtbnRsa<'static>> = None;
 #[cfg(test)]
 static mut SHA256SOFT: Option<&capsules_extra::sha256::Sha256Software<'static>> = None;
 
+/// Key used to seal the keys in the key store.
+///
+/// This is a fixed placeholder published in the source tree, so keys in the
+/// store are only protected from apps and not from anyone who can read the
+/// flash. The key store is therefore only included with the
+/// `insecure_key_store` feature, until this is replaced by a device-unique
+/// secret derived by the key manager.
+#[cfg(feature = "insecure_key_store")]
+static KEY_ENCRYPTION_KEY: [u8; 32] = [
+    0x5e, 0x1f, 0x7c, 0x02, 0x93, 0x4a, 0xd8, 0x61, 0x0b, 0xe7, 0x35, 0xc9, 0x84, 0x2d, 0x70, 0xfa,
+    0x16, 0xa3, 0x58, 0xbf, 0x2e, 0xd1, 0x67, 0x09, 0xc4, 0x7b, 0x90, 0x3e, 0xe5, 0x12, 0xab, 0x46,
+];
+
 static mut CHIP: Option<&'static EarlGreyChip> = None;
 static mut PROCESS_PRINTER: Option<&'static capsules_system::process_printer::ProcessPrinterText> =
     None;
@@ -202,7 +215,7 @@ struct EarlGrey {
     >,
     rng: &'static capsules_core::rng::RngDriver<
         'static,
-        capsules_core::rng::Entropy32ToRandom<'static, lowrisc::csrng::CsRng<'static>>,
+        capsules_core::virtualizers::virtual_rng::VirtualRngMasterDevice<'static>,
     >,
     aes: &'static capsules_extra::symmetric_encryption::aes::AesDriver<
         'static,
@@ -210,6 +223,8 @@ struct EarlGrey {
             'static,
             virtual_aes_ccm::VirtualAES128CCM<'static, earlgrey::aes::Aes<'static>>,
         >,
+        capsules_extra::symmetric_encryption::software_aes::SoftwareAes<'static>,
+        capsules_extra::symmetric_encryption::chacha20poly1305::SoftwareChaCha20Poly1305<'static>,
     >,
     kv_driver: &'static capsules_extra::kv_driver::KVStoreDriver<
         'static,
@@ -233,6 +248,31 @@ struct EarlGrey {
             >,
         >,
     >,
+    #[cfg(feature = "insecure_key_store")]
+    key_store: &'static capsules_extra::key_store::KeyStore<
+        'static,
+        capsules_extra::virtual_kv::VirtualKVPermissions<
+            'static,
+            capsules_extra::kv_store_permissions::KVStorePermissions<
+                'static,
+                capsules_extra::tickv_kv_store::TicKVKVStore<
+                    'static,
+                    capsules_extra::tickv::TicKVSystem<
+                        'static,
+                        capsules_core::virtualizers::virtual_flash::FlashUser<
+                            'static,
+                            lowrisc::flash_ctrl::FlashCtrl<'static>,
+                        >,
+                        capsules_extra::sip_hash::SipHasher24<'static>,
+                        2048,
+                    >,
+                    [u8; 8],
+                >,
+            >,
+        >,
+        capsules_extra::symmetric_encryption::software_aes::SoftwareAes<'static>,
+        capsules_core::virtualizers::virtual_rng::VirtualRngMasterDevice<'static>,
+    >,
     syscall_filter: &'static TbfHeaderFilterDefaultAllow,
     scheduler: &'static PrioritySched,
     scheduler_timer: &'static VirtualSchedulerTimer<
@@ -259,6 +299,8 @@ impl SyscallDriverLookup for EarlGrey {
             capsules_core::rng::DRIVER_NUM => f(Some(self.rng)),
             capsules_extra::symmetric_encryption::aes::DRIVER_NUM => f(Some(self.aes)),
             capsules_extra::kv_driver::DRIVER_NUM => f(Some(self.kv_driver)),
+            #[cfg(feature = "insecure_key_store")]
+            capsules_extra::key_store::DRIVER_NUM => f(Some(self.key_store)),
             _ => f(None),
         }
     }
@@ -731,6 +773,24 @@ unsafe fn setup() -> (
         >
     ));
 
+    #[cfg(feature = "insecure_key_store")]
+    let virtual_kv_key_store = components::kv::VirtualKVPermissionsComponent::new(mux_kv).finalize(
+        components::virtual_kv_permissions_component_static!(
+            capsules_extra::kv_store_permissions::KVStorePermissions<
+                capsules_extra::tickv_kv_store::TicKVKVStore<
+                    capsules_extra::tickv::TicKVSystem<
+                        capsules_core::virtualizers::virtual_flash::FlashUser<
+                            lowrisc::flash_ctrl::FlashCtrl,
+                        >,
+                        capsules_extra::sip_hash::SipHasher24<'static>,
+                        2048,
+                    >,
+                    capsules_extra::tickv::TicKVKeyType,
+                >,
+            >
+        ),
+    );
+
     let mux_otbn = crate::otbn::AccelMuxComponent::new(&peripherals.otbn)
         .finalize(otbn_mux_component_static!());
 
@@ -773,18 +833,32 @@ unsafe fn setup() -> (
         capsules_core::rng::Entropy32ToRandom::new(&peripherals.rng)
     );
     peripherals.rng.set_client(entropy_to_random);
+    // Share the RNG between userspace and the key store
+    let mux_rng = static_init!(
+        capsules_core::virtualizers::virtual_rng::MuxRngMaster<'static>,
+        capsules_core::virtualizers::virtual_rng::MuxRngMaster::new(entropy_to_random)
+    );
+    let rng_user = static_init!(
+        capsules_core::virtualizers::virtual_rng::VirtualRngMasterDevice<'static>,
+        capsules_core::virtualizers::virtual_rng::VirtualRngMasterDevice::new(mux_rng)
+    );
+    #[cfg(feature = "insecure_key_store")]
+    let key_store_rng = static_init!(
+        capsules_core::virtualizers::virtual_rng::VirtualRngMasterDevice<'static>,
+        capsules_core::virtualizers::virtual_rng::VirtualRngMasterDevice::new(mux_rng)
+    );
     // Setup RNG for userspace
     let rng = static_init!(
         capsules_core::rng::RngDriver<
             'static,
-            capsules_core::rng::Entropy32ToRandom<'static, lowrisc::csrng::CsRng<'static>>,
+            capsules_core::virtualizers::virtual_rng::VirtualRngMasterDevice<'static>,
         >,
         capsules_core::rng::RngDriver::new(
-            entropy_to_random,
+            rng_user,
             board_kernel.create_grant(capsules_core::rng::DRIVER_NUM, &memory_allocation_cap)
         )
     );
-    entropy_to_random.set_client(rng);
+    rng_user.set_client(rng);
 
     const CRYPT_SIZE: usize = 7 * AES128_BLOCK_SIZE;
 
@@ -809,16 +883,28 @@ unsafe fn setup() -> (
     );
     ccm_client.set_client(gcm_client);
 
+    // The AES engine only supports 128 bit keys, so AES-256 and
+    // ChaCha20-Poly1305 are done in software.
+    let software_aes = components::symmetric_encryption::SoftwareAesComponent::new()
+        .finalize(components::software_aes_component_static!());
+    let chacha20poly1305 =
+        components::symmetric_encryption::SoftwareChaCha20Poly1305Component::new()
+            .finalize(components::software_chacha20poly1305_component_static!());
+
     let aes = components::aes::AesDriverComponent::new(
         board_kernel,
         capsules_extra::symmetric_encryption::aes::DRIVER_NUM,
         gcm_client,
+        software_aes,
+        chacha20poly1305,
     )
     .finalize(components::aes_driver_component_static!(
         aes_gcm::Aes128Gcm<
             'static,
             virtual_aes_ccm::VirtualAES128CCM<'static, earlgrey::aes::Aes<'static>>,
         >,
+        capsules_extra::symmetric_encryption::software_aes::SoftwareAes<'static>,
+        capsules_extra::symmetric_encryption::chacha20poly1305::SoftwareChaCha20Poly1305<'static>,
     ));
 
     AES = Some(gcm_client);
@@ -836,6 +922,49 @@ unsafe fn setup() -> (
     hil::symmetric_encryption::AES128GCM::set_client(gcm_client, aes);
     hil::symmetric_encryption::AES128::set_client(gcm_client, ccm_client);
 
+    // Key store, which the AES and HMAC drivers take key handles from
+    #[cfg(feature = "insecure_key_store")]
+    let key_store = {
+        debug!("WARNING: the key store seals keys with a fixed key-encryption key.");
+        debug!("WARNING: anyone who can read the flash can unseal the stored keys.");
+        let key_store_aes = components::symmetric_encryption::SoftwareAesComponent::new()
+            .finalize(components::software_aes_component_static!());
+        let key_store = components::key_store::KeyStoreComponent::new(
+            board_kernel,
+            capsules_extra::key_store::DRIVER_NUM,
+            virtual_kv_key_store,
+            key_store_aes,
+            key_store_rng,
+            &KEY_ENCRYPTION_KEY,
+        )
+        .finalize(components::key_store_component_static!(
+            capsules_extra::virtual_kv::VirtualKVPermissions<
+                'static,
+                capsules_extra::kv_store_permissions::KVStorePermissions<
+                    'static,
+                    capsules_extra::tickv_kv_store::TicKVKVStore<
+                        'static,
+                        capsules_extra::tickv::TicKVSystem<
+                            'static,
+                            capsules_core::virtualizers::virtual_flash::FlashUser<
+                                'static,
+                                lowrisc::flash_ctrl::FlashCtrl<'static>,
+                            >,
+                            capsules_extra::sip_hash::SipHasher24<'static>,
+                            2048,
+                        >,
+                        [u8; 8],
+                    >,
+                >,
+            >,
+            capsules_extra::symmetric_encryption::software_aes::SoftwareAes<'static>,
+            capsules_core::virtualizers::virtual_rng::VirtualRngMasterDevice<'static>,
+        ));
+        aes.set_key_store(key_store);
+        hmac.set_key_store(key_store);
+        key_store
+    };
+
     let syscall_filter = static_init!(TbfHeaderFilterDefaultAllow, TbfHeaderFilterDefaultAllow {});
     let scheduler = components::sched::priority::PriorityComponent::new(board_kernel)
         .finalize(components::priority_component_static!());
@@ -855,6 +984,8 @@ unsafe fn setup() -> (
             rng,
             aes,
             kv_driver,
+            #[cfg(feature = "insecure_key_store")]
+            key_store,
             syscall_filter,
             scheduler,
             scheduler_timer,
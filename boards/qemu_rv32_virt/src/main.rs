@@ -29,6 +29,11 @@ pub mod io;
 
 pub const NUM_PROCS: usize = 4;
 
+/// MAC address of the VirtIO NetworkCard. This must match the `mac`
+/// parameter passed to the QEMU `virtio-net-device` in the Makefile.
+const VIRTIO_NET_MAC_ADDR: capsules_extra::net::ethernet::EthernetAddress =
+    capsules_extra::net::ethernet::EthernetAddress([0x52, 0x54, 0x00, 0x12, 0x34, 0x56]);
+
 // Actual memory for holding the active process structures. Need an empty list
 // at least.
 static mut PROCESSES: [Option<&'static dyn kernel::process::Process>; NUM_PROCS] =
@@ -82,6 +87,9 @@ struct QemuRv32VirtPlatform {
             qemu_rv32_virt_chip::virtio::devices::virtio_rng::VirtIORng<'static, 'static>,
         >,
     >,
+    ethernet_tap: Option<&'static components::ethernet::EthernetTapComponentType>,
+    udp_driver: Option<&'static capsules_extra::net::udp::UDPDriver<'static>>,
+    icmp_driver: Option<&'static capsules_extra::net::icmpv6::ICMP6Driver>,
 }
 
 /// Mapping of integer syscalls to objects that implement syscalls.
@@ -101,6 +109,15 @@ impl SyscallDriverLookup for QemuRv32VirtPlatform {
                     f(None)
                 }
             }
+            capsules_extra::ethernet_tap::DRIVER_NUM => f(self
+                .ethernet_tap
+                .map(|d| d as &dyn kernel::syscall::SyscallDriver)),
+            capsules_extra::net::udp::DRIVER_NUM => f(self
+                .udp_driver
+                .map(|d| d as &dyn kernel::syscall::SyscallDriver)),
+            capsules_extra::net::icmpv6::DRIVER_NUM => f(self
+                .icmp_driver
+                .map(|d| d as &dyn kernel::syscall::SyscallDriver)),
             kernel::ipc::DRIVER_NUM => f(Some(&self.ipc)),
             _ => f(None),
         }
@@ -366,12 +383,9 @@ unsafe fn start() -> (
     };
 
     // If there is a VirtIO NetworkCard present, use the appropriate VirtIONet
-    // driver. Currently this is not used, as work on the userspace network
-    // driver and kernel network stack is in progress.
-    //
-    // A template dummy driver is provided to verify basic functionality of this
-    // interface.
-    let _virtio_net_if: Option<
+    // driver. It is shared between the IPv6 stack and the userspace Ethernet
+    // tap driver below.
+    let virtio_net_if: Option<
         &'static qemu_rv32_virt_chip::virtio::devices::virtio_net::VirtIONet<'static>,
     > = if let Some(net_idx) = virtio_net_idx {
         use qemu_rv32_virt_chip::virtio::devices::virtio_net::VirtIONet;
@@ -442,19 +456,71 @@ unsafe fn start() -> (
             .initialize(virtio_net, mmio_queues)
             .unwrap();
 
-        // Don't forget to enable RX once when integrating this into a
-        // proper Ethernet stack:
-        // virtio_net.enable_rx();
-
-        // TODO: When we have a proper Ethernet driver available for userspace,
-        // return that. For now, just return a reference to the raw VirtIONet
-        // driver:
+        // Reception is enabled through the Ethernet HIL once a user of the
+        // interface asks for frames.
         Some(virtio_net as &'static VirtIONet)
     } else {
         // No VirtIO NetworkCard discovered
         None
     };
 
+    // ---------- NETWORKING ----------
+    //
+    // Expose the VirtIO NetworkCard to userspace as a raw Ethernet tap, and
+    // run the UDP/IPv6 stack over it. The node uses the link-local address
+    // derived from the MAC address QEMU is configured with.
+    let (ethernet_tap, udp_driver, icmp_driver) = if let Some(virtio_net) = virtio_net_if {
+        use capsules_extra::net::ipv6::ip_utils::IPAddr;
+
+        let mux_eth = components::ethernet::MuxEthernetComponent::new(virtio_net)
+            .finalize(components::mux_ethernet_component_static!());
+
+        let ethernet_tap = components::ethernet::EthernetTapComponent::new(
+            board_kernel,
+            capsules_extra::ethernet_tap::DRIVER_NUM,
+            mux_eth,
+        )
+        .finalize(components::ethernet_tap_component_static!());
+
+        let local_ip_ifaces = static_init!([IPAddr; 1], [VIRTIO_NET_MAC_ADDR.to_link_local()]);
+
+        let (udp_send_mux, udp_recv_mux, udp_port_table, icmp_recv) =
+            components::udp_mux_ethernet::UDPMuxEthernetComponent::new(
+                mux_eth,
+                VIRTIO_NET_MAC_ADDR,
+                local_ip_ifaces,
+                mux_alarm,
+            )
+            .finalize(components::udp_mux_ethernet_component_static!(
+                qemu_rv32_virt_chip::chip::QemuRv32VirtClint
+            ));
+
+        let udp_driver = components::udp_driver::UDPDriverComponent::new(
+            board_kernel,
+            capsules_extra::net::udp::DRIVER_NUM,
+            udp_send_mux,
+            udp_recv_mux,
+            udp_port_table,
+            local_ip_ifaces,
+        )
+        .finalize(components::udp_driver_component_static!(
+            @sender components::udp_mux_ethernet::IP6EthernetComponentType<
+                qemu_rv32_virt_chip::chip::QemuRv32VirtClint,
+            >
+        ));
+
+        let icmp_driver = components::icmpv6_driver::ICMP6DriverComponent::new(
+            board_kernel,
+            capsules_extra::net::icmpv6::DRIVER_NUM,
+            icmp_recv,
+        )
+        .finalize(components::icmpv6_driver_component_static!());
+
+        (Some(ethernet_tap), Some(udp_driver), Some(icmp_driver))
+    } else {
+        (None, None, None)
+    };
+
     // ---------- INITIALIZE CHIP, ENABLE INTERRUPTS ---------
 
     let chip = static_init!(
@@ -528,6 +594,9 @@ unsafe fn start() -> (
         scheduler,
         scheduler_timer,
         virtio_rng: virtio_rng_driver,
+        ethernet_tap,
+        udp_driver,
+        icmp_driver,
         ipc: kernel::ipc::IPC::new(
             board_kernel,
             kernel::ipc::DRIVER_NUM,
@@ -26,6 +26,8 @@ pub enum NUM {
 
     // Kernel
     Ipc                   = 0x10000,
+    AppLoader             = 0x10001,
+    IpcMailbox            = 0x10002,
 
     // HW Buses
     Spi                   = 0x20001,
@@ -44,6 +46,8 @@ pub enum NUM {
     Thread                = 0x30005,
     Eui64                 = 0x30006,
     EthernetTap           = 0x30007,
+    Tcp                   = 0x30008,
+    Icmpv6                = 0x30009,
 
     // Cryptography
     Rng                   = 0x40001,
@@ -52,12 +56,14 @@ pub enum NUM {
     CtapHid               = 0x40004,
     Sha                   = 0x40005,
     Aes                   = 0x40006,
+    KeyStore              = 0x40007,
 
     // Storage
     AppFlash              = 0x50000,
     NvmStorage            = 0x50001,
     SdCard                = 0x50002,
     Kv                    = 0x50003,
+    FileSystem            = 0x50004,
 
     // Sensors
     Temperature           = 0x60000,
@@ -6,6 +6,23 @@
 //! a terminal to inspect and control userspace processes.
 //!
 //! For a more in-depth documentation check /doc/Process_Console.md
+//!
+//! Commands that act on processes select them by name, by a glob pattern such
+//! as `stop sensor*`, or by a fixed ShortID such as `stop 0x1234`. Commands can
+//! be piped, as in `list | grep Running`: the output of each command is the
+//! input of the next one, which `grep` and registered commands can read. Other
+//! capsules can add commands by implementing `ConsoleCommand` and registering
+//! it with the console:
+//!
+//! ```rust,ignore
+//! let command = static_init!(
+//!     RegisteredCommand<'static>,
+//!     RegisteredCommand::new("kv", "get <key>", "Inspect the key-value store.", kv_console)
+//! );
+//! pconsole.register_command(command);
+//! ```
+//!
+//! `capsules_extra::kv_console` is an example of such a command.
 use core::cell::Cell;
 use core::cmp;
 use core::fmt;
@@ -13,16 +30,20 @@ use core::fmt::write;
 use core::str;
 use kernel::capabilities::ProcessManagementCapability;
 use kernel::capabilities::ProcessStartCapability;
+use kernel::collections::list::{List, ListLink, ListNode};
+use kernel::crash_dump::CrashDumpStore;
 use kernel::hil::time::ConvertTicks;
 use kernel::utilities::cells::MapCell;
+use kernel::utilities::cells::OptionalCell;
 use kernel::utilities::cells::TakeCell;
 use kernel::ProcessId;
 
 use kernel::debug;
+use kernel::dynamic_binary_storage::{DynamicProcessUnload, DynamicProcessUnloadClient};
 use kernel::hil::time::{Alarm, AlarmClient};
 use kernel::hil::uart;
 use kernel::introspection::KernelInfo;
-use kernel::process::{ProcessPrinter, ProcessPrinterContext, State};
+use kernel::process::{Process, ProcessPrinter, ProcessPrinterContext, ShortId, State};
 use kernel::utilities::binary_write::BinaryWrite;
 use kernel::ErrorCode;
 use kernel::Kernel;
@@ -40,11 +61,21 @@ pub const READ_BUF_LEN: usize = 4;
 pub const COMMAND_BUF_LEN: usize = 64;
 /// Default size for the history command.
 pub const DEFAULT_COMMAND_HISTORY_LEN: usize = 10;
+/// Size of the buffers holding the output a command passes to the next one in
+/// a pipe. It is no longer than the queue, so output filtered from it is
+/// printed in full.
+pub const PIPE_BUF_LEN: usize = QUEUE_BUF_LEN;
 
 /// List of valid commands for printing help. Consolidated as these are
-/// displayed in a few different cases.
+/// displayed in a few different cases. Registered commands are listed after
+/// these.
 const VALID_COMMANDS_STR: &[u8] =
-    b"help status list stop start fault boot terminate process kernel reset panic console-start console-stop\r\n";
+    b"help status list stats stop start fault boot terminate unload process kernel crashdump grep reset panic console-start console-stop";
+
+/// Help for the argument of the commands that act on processes, and for
+/// pipes.
+const SELECTORS_HELP_STR: &[u8] =
+    b"Processes are selected by name, glob (sensor*) or ShortID (0x1234).\r\nPipes: list | grep Running\r\n";
 
 /// Escape character for ANSI escape sequences.
 const ESC: u8 = b'\x1B';
@@ -91,6 +122,9 @@ enum WriterState {
         index: isize,
         total: isize,
     },
+    CrashDump {
+        offset: usize,
+    },
 }
 
 /// Key that can be part from an escape sequence.
@@ -270,6 +304,26 @@ pub struct ProcessConsole<
     /// Function used to reset the device in bootloader mode
     reset_function: Option<fn() -> !>,
 
+    /// Used to remove terminated processes so that other applications can be
+    /// loaded in their place.
+    process_unloader: OptionalCell<&'a dyn DynamicProcessUnload<'a>>,
+
+    /// Crash dumps shown by the `crashdump` command.
+    crash_dumps: OptionalCell<&'a dyn CrashDumpStore>,
+
+    /// Commands registered by other capsules.
+    commands: CommandRegistry<'a>,
+
+    /// Input of the command running in a pipe, the output of the previous
+    /// command.
+    pipe_input: TakeCell<'static, [u8]>,
+    /// Output of a command that is piped into another one.
+    pipe_output: TakeCell<'static, [u8]>,
+    /// Length of the output in `pipe_output`, set while it is being captured.
+    pipe_size: OptionalCell<usize>,
+    /// Whether captured output did not fit into `pipe_output`.
+    pipe_truncated: Cell<bool>,
+
     /// This capsule needs to use potentially dangerous APIs related to
     /// processes, and requires a capability to access those APIs.
     capability: C,
@@ -402,12 +456,23 @@ impl<'a, const COMMAND_HISTORY_LEN: usize> CommandHistory<'a, COMMAND_HISTORY_LE
 pub struct ConsoleWriter {
     buf: [u8; 500],
     size: usize,
+    truncate: bool,
 }
 impl ConsoleWriter {
     pub fn new() -> ConsoleWriter {
         ConsoleWriter {
             buf: [EOL; 500],
             size: 0,
+            truncate: false,
+        }
+    }
+    /// A writer that drops text that does not fit, for registered commands,
+    /// which may write more than the buffer holds. Built-in commands use
+    /// `new()`, so that output that outgrows the buffer is caught.
+    fn new_truncating() -> ConsoleWriter {
+        ConsoleWriter {
+            truncate: true,
+            ..ConsoleWriter::new()
         }
     }
     pub fn clear(&mut self) {
@@ -416,6 +481,10 @@ impl ConsoleWriter {
 }
 impl fmt::Write for ConsoleWriter {
     fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.truncate {
+            let _ = self.write_buffer(s.as_bytes());
+            return Ok(());
+        }
         let curr = s.len();
         self.buf[self.size..self.size + curr].copy_from_slice(s.as_bytes());
         self.size += curr;
@@ -434,6 +503,194 @@ impl BinaryWrite for ConsoleWriter {
     }
 }
 
+/// The whitespace-separated arguments of a console command, and its input
+/// when it runs in a pipe.
+pub struct Arguments<'s> {
+    args: str::SplitAsciiWhitespace<'s>,
+    input: &'s str,
+}
+
+impl<'s> Arguments<'s> {
+    fn new(command: &'s str, input: &'s str) -> Self {
+        Arguments {
+            args: command.split_ascii_whitespace(),
+            input,
+        }
+    }
+
+    /// The output of the previous command in a pipe, such as the output of
+    /// `list` in `list | my-command`. Empty if the command is not piped.
+    pub fn input(&self) -> &'s str {
+        self.input
+    }
+
+    /// The next argument as a number, in decimal or in hexadecimal with a
+    /// `0x` prefix. Returns `INVAL` if it is missing or not a number.
+    pub fn next_number(&mut self) -> Result<u32, ErrorCode> {
+        self.next().and_then(parse_number).ok_or(ErrorCode::INVAL)
+    }
+
+    /// The next argument as a process selector. Returns `INVAL` if it is
+    /// missing.
+    pub fn next_process(&mut self) -> Result<ProcessSelector<'s>, ErrorCode> {
+        self.next()
+            .map(ProcessSelector::new)
+            .ok_or(ErrorCode::INVAL)
+    }
+}
+
+impl<'s> Iterator for Arguments<'s> {
+    type Item = &'s str;
+
+    fn next(&mut self) -> Option<&'s str> {
+        self.args.next()
+    }
+}
+
+fn parse_number(number: &str) -> Option<u32> {
+    let (digits, radix) = match number.strip_prefix("0x") {
+        Some(hex) => (hex, 16),
+        None => (number, 10),
+    };
+    u32::from_str_radix(digits, radix).ok()
+}
+
+/// Whether `name` matches the glob `pattern`, in which `*` matches any
+/// characters and `?` matches one character.
+fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // Where to retry after a mismatch: after the last `*`, with that `*`
+    // matching one more character of the name.
+    let mut retry = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                p += 1;
+                retry = Some((p, n));
+            }
+            Some(&c) if c == b'?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match retry {
+                Some((retry_p, retry_n)) => {
+                    p = retry_p;
+                    n = retry_n + 1;
+                    retry = Some((retry_p, n));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// Selects processes by name, by a glob pattern of names such as `sensor*`,
+/// or by a fixed ShortID written in hexadecimal such as `0x1234`.
+#[derive(Clone, Copy)]
+pub struct ProcessSelector<'s>(&'s str);
+
+impl<'s> ProcessSelector<'s> {
+    pub fn new(selector: &'s str) -> Self {
+        ProcessSelector(selector)
+    }
+
+    /// Whether `process` is selected.
+    pub fn matches(&self, process: &dyn Process) -> bool {
+        if self.0.starts_with("0x") {
+            if let (Some(id), ShortId::Fixed(short_id)) =
+                (parse_number(self.0), process.short_app_id())
+            {
+                if short_id.get() == id {
+                    return true;
+                }
+            }
+        }
+        glob_match(self.0.as_bytes(), process.get_process_name().as_bytes())
+    }
+}
+
+/// A command that another capsule adds to the process console, for example
+/// to inspect or configure the service it provides.
+pub trait ConsoleCommand {
+    /// Run the command with the arguments that follow its name, writing its
+    /// output to `writer`. In a pipe, `args.input()` holds the output of the
+    /// previous command, and the output written to `writer` is passed on to
+    /// the next one.
+    ///
+    /// Returning `INVAL` prints the usage of the command, and other errors
+    /// are printed after its name. Commands that complete asynchronously
+    /// print their result later, for example with `debug!`.
+    fn execute(&self, args: Arguments, writer: &mut ConsoleWriter) -> Result<(), ErrorCode>;
+}
+
+/// A `ConsoleCommand` registered with the process console, with the name it
+/// is run by and its help.
+pub struct RegisteredCommand<'a> {
+    name: &'static str,
+    usage: &'static str,
+    help: &'static str,
+    command: &'a dyn ConsoleCommand,
+    next: ListLink<'a, RegisteredCommand<'a>>,
+}
+
+impl<'a> RegisteredCommand<'a> {
+    /// `usage` describes the arguments, such as `<key> [value]`, and `help`
+    /// what the command does. Both are shown by `help <name>`.
+    pub fn new(
+        name: &'static str,
+        usage: &'static str,
+        help: &'static str,
+        command: &'a dyn ConsoleCommand,
+    ) -> Self {
+        RegisteredCommand {
+            name,
+            usage,
+            help,
+            command,
+            next: ListLink::empty(),
+        }
+    }
+}
+
+impl<'a> ListNode<'a, RegisteredCommand<'a>> for RegisteredCommand<'a> {
+    fn next(&'a self) -> &'a ListLink<'a, RegisteredCommand<'a>> {
+        &self.next
+    }
+}
+
+/// The commands registered with the process console.
+struct CommandRegistry<'a> {
+    list: List<'a, RegisteredCommand<'a>>,
+}
+
+impl<'a> CommandRegistry<'a> {
+    fn new() -> Self {
+        CommandRegistry { list: List::new() }
+    }
+
+    fn register(&self, command: &'a RegisteredCommand<'a>) {
+        self.list.push_tail(command);
+    }
+
+    fn find(&self, name: &str) -> Option<&'a RegisteredCommand<'a>> {
+        self.list.iter().find(|command| command.name == name)
+    }
+
+    /// Run the command `name` with `args`, writing its output to `writer`.
+    /// Returns the command and its result, or `None` if no command with that
+    /// name is registered.
+    fn run(
+        &self,
+        name: &str,
+        args: Arguments,
+        writer: &mut ConsoleWriter,
+    ) -> Option<(&'a RegisteredCommand<'a>, Result<(), ErrorCode>)> {
+        let command = self.find(name)?;
+        Some((command, command.command.execute(args, writer)))
+    }
+}
+
 impl<
         'a,
         const COMMAND_HISTORY_LEN: usize,
@@ -450,6 +707,8 @@ impl<
         queue_buffer: &'static mut [u8],
         cmd_buffer: &'static mut [u8],
         cmd_history_buffer: &'static mut [Command; COMMAND_HISTORY_LEN],
+        pipe_input_buffer: &'static mut [u8],
+        pipe_output_buffer: &'static mut [u8],
         kernel: &'static Kernel,
         kernel_addresses: KernelAddresses,
         reset_function: Option<fn() -> !>,
@@ -476,10 +735,54 @@ impl<
             kernel,
             kernel_addresses,
             reset_function,
+            process_unloader: OptionalCell::empty(),
+            crash_dumps: OptionalCell::empty(),
+            commands: CommandRegistry::new(),
+            pipe_input: TakeCell::new(pipe_input_buffer),
+            pipe_output: TakeCell::new(pipe_output_buffer),
+            pipe_size: OptionalCell::empty(),
+            pipe_truncated: Cell::new(false),
             capability,
         }
     }
 
+    /// Enable the `unload` command, which terminates and removes a process
+    /// with `unloader`.
+    pub fn set_process_unloader(&self, unloader: &'a dyn DynamicProcessUnload<'a>) {
+        self.process_unloader.set(unloader);
+    }
+
+    /// Enable the `crashdump` command, which shows the dumps in `crash_dumps`.
+    pub fn set_crash_dumps(&self, crash_dumps: &'a dyn CrashDumpStore) {
+        self.crash_dumps.set(crash_dumps);
+    }
+
+    /// Add `command` to the commands of the console.
+    ///
+    /// Built-in commands take precedence over registered commands with the
+    /// same name.
+    pub fn register_command(&self, command: &'a RegisteredCommand<'a>) {
+        self.commands.register(command);
+    }
+
+    /// Write the text collected in `console_writer`.
+    ///
+    /// This is not inlined into the many places that print through a
+    /// `ConsoleWriter`, which saves flash on boards that are close to filling
+    /// their kernel region.
+    #[inline(never)]
+    fn write_console_writer(&self, console_writer: &ConsoleWriter) {
+        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+    }
+
+    /// Format and write `args`, for the same reason not inlined.
+    #[inline(never)]
+    fn write_args(&self, args: fmt::Arguments) {
+        let mut console_writer = ConsoleWriter::new();
+        let _ = write(&mut console_writer, args);
+        self.write_console_writer(&console_writer);
+    }
+
     /// Start the process console listening for user commands.
     pub fn start(&self) -> Result<(), ErrorCode> {
         if self.mode.get() == ProcessConsoleState::Off {
@@ -515,21 +818,10 @@ impl<
         }
 
         // Display pconsole info.
-        let mut console_writer = ConsoleWriter::new();
-        let _ = write(
-            &mut console_writer,
-            format_args!(
-                "Kernel version: {}.{} (build {})\r\n",
-                kernel::KERNEL_MAJOR_VERSION,
-                kernel::KERNEL_MINOR_VERSION,
-                option_env!("TOCK_KERNEL_VERSION").unwrap_or("unknown"),
-            ),
-        );
-        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+        self.print_kernel_version();
 
         let _ = self.write_bytes(b"Welcome to the process console.\r\n");
-        let _ = self.write_bytes(b"Valid commands are: ");
-        let _ = self.write_bytes(VALID_COMMANDS_STR);
+        self.print_commands();
         self.prompt();
     }
 
@@ -562,6 +854,7 @@ impl<
                     }
                 }
             }
+            WriterState::CrashDump { offset } => WriterState::CrashDump { offset },
             WriterState::Empty => WriterState::Empty,
         }
     }
@@ -570,100 +863,69 @@ impl<
     fn create_state_buffer(&self, state: WriterState) {
         match state {
             WriterState::KernelBss => {
-                let mut console_writer = ConsoleWriter::new();
-
                 let bss_start = self.kernel_addresses.bss_start as usize;
                 let bss_end = self.kernel_addresses.bss_end as usize;
                 let bss_size = bss_end - bss_start;
 
-                let _ = write(
-                    &mut console_writer,
-                    format_args!(
-                        "\r\n ╔═══════════╤══════════════════════════════╗\
-                    \r\n ║  Address  │ Region Name    Used (bytes)  ║\
-                    \r\n ╚{:#010X}═╪══════════════════════════════╝\
-                    \r\n             │   BSS        {:6}",
-                        bss_end, bss_size
-                    ),
-                );
-
-                let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                self.write_args(format_args!(
+                    "\r\n ╔═══════════╤══════════════════════════════╗\
+                \r\n ║  Address  │ Region Name    Used (bytes)  ║\
+                \r\n ╚{:#010X}═╪══════════════════════════════╝\
+                \r\n             │   BSS        {:6}",
+                    bss_end, bss_size
+                ));
             }
             WriterState::KernelInit => {
-                let mut console_writer = ConsoleWriter::new();
-
                 let relocate_start = self.kernel_addresses.relocations_start as usize;
                 let relocate_end = self.kernel_addresses.relocations_end as usize;
                 let relocate_size = relocate_end - relocate_start;
 
-                let _ = write(
-                    &mut console_writer,
-                    format_args!(
-                        "\
-                    \r\n  {:#010X} ┼─────────────────────────────── S\
-                    \r\n             │   Relocate   {:6}            R",
-                        relocate_end, relocate_size
-                    ),
-                );
-                let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                self.write_args(format_args!(
+                    "\
+                \r\n  {:#010X} ┼─────────────────────────────── S\
+                \r\n             │   Relocate   {:6}            R",
+                    relocate_end, relocate_size
+                ));
             }
             WriterState::KernelStack => {
-                let mut console_writer = ConsoleWriter::new();
-
                 let stack_start = self.kernel_addresses.stack_start as usize;
                 let stack_end = self.kernel_addresses.stack_end as usize;
                 let stack_size = stack_end - stack_start;
 
-                let _ = write(
-                    &mut console_writer,
-                    format_args!(
-                        "\
-                    \r\n  {:#010X} ┼─────────────────────────────── A\
-                    \r\n             │ ▼ Stack      {:6}            M\
-                    \r\n  {:#010X} ┼───────────────────────────────",
-                        stack_end, stack_size, stack_start
-                    ),
-                );
-                let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                self.write_args(format_args!(
+                    "\
+                \r\n  {:#010X} ┼─────────────────────────────── A\
+                \r\n             │ ▼ Stack      {:6}            M\
+                \r\n  {:#010X} ┼───────────────────────────────",
+                    stack_end, stack_size, stack_start
+                ));
             }
             WriterState::KernelRoData => {
-                let mut console_writer = ConsoleWriter::new();
-
                 let rodata_start = self.kernel_addresses.read_only_data_start as usize;
                 let text_end = self.kernel_addresses.text_end as usize;
                 let rodata_size = text_end - rodata_start;
 
-                let _ = write(
-                    &mut console_writer,
-                    format_args!(
-                        "\
-                        \r\n             .....\
-                     \r\n  {:#010X} ┼─────────────────────────────── F\
-                     \r\n             │   RoData     {:6}            L",
-                        text_end, rodata_size
-                    ),
-                );
-                let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                self.write_args(format_args!(
+                    "\
+                    \r\n             .....\
+                 \r\n  {:#010X} ┼─────────────────────────────── F\
+                 \r\n             │   RoData     {:6}            L",
+                    text_end, rodata_size
+                ));
             }
             WriterState::KernelText => {
-                let mut console_writer = ConsoleWriter::new();
-
                 let code_start = self.kernel_addresses.text_start as usize;
                 let code_end = self.kernel_addresses.read_only_data_start as usize;
                 let code_size = code_end - code_start;
 
-                let _ = write(
-                    &mut console_writer,
-                    format_args!(
-                        "\
-                     \r\n  {:#010X} ┼─────────────────────────────── A\
-                     \r\n             │   Code       {:6}            S\
-                     \r\n  {:#010X} ┼─────────────────────────────── H\
-                     \r\n",
-                        code_end, code_size, code_start
-                    ),
-                );
-                let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                self.write_args(format_args!(
+                    "\
+                 \r\n  {:#010X} ┼─────────────────────────────── A\
+                 \r\n             │   Code       {:6}            S\
+                 \r\n  {:#010X} ┼─────────────────────────────── H\
+                 \r\n",
+                    code_end, code_size, code_start
+                ));
             }
             WriterState::ProcessPrint {
                 process_id,
@@ -679,7 +941,7 @@ impl<
                                 context,
                             );
 
-                            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                            self.write_console_writer(&console_writer);
 
                             if new_context.is_some() {
                                 self.writer_state.replace(WriterState::ProcessPrint {
@@ -710,42 +972,53 @@ impl<
 
                             let (grants_used, grants_total) =
                                 info.number_app_grant_uses(process_id, &self.capability);
-                            let mut console_writer = ConsoleWriter::new();
-
                             // Display process id.
-                            let _ = write(&mut console_writer, format_args!(" {:<7?}", process_id));
+                            self.write_args(format_args!(" {:<7?}", process_id));
                             // Display short id.
                             match short_id {
                                 kernel::process::ShortId::LocallyUnique => {
-                                    let _ = write(
-                                        &mut console_writer,
-                                        format_args!("{}", "Unique     ",),
-                                    );
+                                    let _ = self.write_bytes(b"Unique     ");
                                 }
                                 kernel::process::ShortId::Fixed(id) => {
-                                    let _ =
-                                        write(&mut console_writer, format_args!("0x{:<8x} ", id));
+                                    self.write_args(format_args!("0x{:<8x} ", id));
                                 }
                             }
                             // Display everything else.
-                            let _ = write(
-                                &mut console_writer,
-                                format_args!(
-                                    "{:<20}{:6}{:10}{:10}  {:2}/{:2}   {:?}\r\n",
-                                    pname,
-                                    process.debug_timeslice_expiration_count(),
-                                    process.debug_syscall_count(),
-                                    process.get_restart_count(),
-                                    grants_used,
-                                    grants_total,
-                                    process.get_state(),
-                                ),
-                            );
-
-                            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                            self.write_args(format_args!(
+                                "{:<20}{:6}{:10}{:10}  {:2}/{:2}   {:?}\r\n",
+                                pname,
+                                process.debug_timeslice_expiration_count(),
+                                process.debug_syscall_count(),
+                                process.get_restart_count(),
+                                grants_used,
+                                grants_total,
+                                process.get_state(),
+                            ));
                         }
                     });
             }
+            WriterState::CrashDump { offset } => {
+                let mut console_writer = ConsoleWriter::new();
+                let next_offset = self
+                    .crash_dumps
+                    .and_then(|crash_dumps| crash_dumps.print(&mut console_writer, offset));
+                self.write_console_writer(&console_writer);
+
+                match next_offset {
+                    Some(offset) => {
+                        self.writer_state.replace(WriterState::CrashDump { offset });
+                    }
+                    None => {
+                        self.writer_state.replace(WriterState::Empty);
+                        // When continuing the print, the prompt has to be
+                        // printed here as with `ProcessPrint`. Otherwise the
+                        // command itself prints it.
+                        if offset != 0 {
+                            self.prompt();
+                        }
+                    }
+                }
+            }
             WriterState::Empty => {
                 self.prompt();
             }
@@ -753,6 +1026,77 @@ impl<
         }
     }
 
+    /// Print the kernel version, shown by the welcome message and `kernel`.
+    #[inline(never)]
+    fn print_kernel_version(&self) {
+        self.write_args(format_args!(
+            "Kernel version: {}.{} (build {})\r\n",
+            kernel::KERNEL_MAJOR_VERSION,
+            kernel::KERNEL_MINOR_VERSION,
+            option_env!("TOCK_KERNEL_VERSION").unwrap_or("unknown"),
+        ));
+    }
+
+    /// Print the names of the built-in and registered commands.
+    fn print_commands(&self) {
+        let _ = self.write_bytes(b"Valid commands are: ");
+        let _ = self.write_bytes(VALID_COMMANDS_STR);
+        for command in self.commands.list.iter() {
+            let _ = self.write_bytes(b" ");
+            let _ = self.write_bytes(command.name.as_bytes());
+        }
+        let _ = self.write_bytes(b"\r\n");
+    }
+
+    /// Run `action` on each process selected by `selector`, or print that
+    /// there is none.
+    ///
+    /// This takes a `dyn FnMut` so that the commands that select processes
+    /// share one copy of the loop, which keeps the console small.
+    #[inline(never)]
+    fn each_selected_process(&self, selector: Option<&str>, action: &mut dyn FnMut(&dyn Process)) {
+        let mut found = false;
+        if let Some(selector) = selector.map(ProcessSelector::new) {
+            self.kernel
+                .process_each_capability(&self.capability, |proc| {
+                    if selector.matches(proc) {
+                        found = true;
+                        action(proc);
+                    }
+                });
+        }
+        if !found {
+            let _ = self.write_bytes(b"No matching process\r\n");
+        }
+    }
+
+    /// Run the registered command `name`, returning false if there is none.
+    fn run_registered_command(&self, name: &str, args: Arguments) -> bool {
+        let mut console_writer = ConsoleWriter::new_truncating();
+        let Some((command, result)) = self.commands.run(name, args, &mut console_writer) else {
+            return false;
+        };
+        self.write_console_writer(&console_writer);
+        match result {
+            Ok(()) => {}
+            Err(ErrorCode::INVAL) => self.print_usage(command),
+            Err(e) => self.write_args(format_args!("{} failed: {:?}\r\n", command.name, e)),
+        }
+        true
+    }
+
+    fn find_command(&self, name: &str) -> Option<&'a RegisteredCommand<'a>> {
+        self.commands.find(name)
+    }
+
+    /// Print the arguments of a registered command and what it does.
+    fn print_usage(&self, command: &RegisteredCommand) {
+        self.write_args(format_args!(
+            "Usage: {} {}\r\n{}\r\n",
+            command.name, command.usage, command.help
+        ));
+    }
+
     // Process the command in the command buffer and clear the buffer.
     fn read_command(&self) {
         self.command_buffer.map(|command| {
@@ -766,7 +1110,7 @@ impl<
 
                 match cmd_str {
                     Ok(s) => {
-                        let clean_str = s.trim();
+                        let clean_str = s.trim_ascii();
 
                         // Check if the command history is enabled by the user
                         // and check if the command is not full of whitespaces
@@ -778,244 +1122,19 @@ impl<
                             }
                         }
 
-                        if clean_str.starts_with("console-start") {
+                        let name = clean_str.split_ascii_whitespace().next().unwrap_or("");
+                        if name == "console-start" {
                             self.mode.set(ProcessConsoleState::Active);
                         } else if self.mode.get() == ProcessConsoleState::Hibernating {
                             // Ignore all commands in hibernating mode. We put
                             // this case early so we ensure we get stuck here
                             // even if the user typed a valid command.
-                        } else if clean_str.starts_with("help") {
-                            let _ = self.write_bytes(b"Welcome to the process console.\r\n");
-                            let _ = self.write_bytes(b"Valid commands are: ");
-                            let _ = self.write_bytes(VALID_COMMANDS_STR);
-                        } else if clean_str.starts_with("console-stop") {
-                            let _ = self.write_bytes(b"Disabling the process console.\r\n");
-                            let _ = self.write_bytes(b"Run console-start to reactivate.\r\n");
-                            self.mode.set(ProcessConsoleState::Hibernating);
-                        } else if clean_str.starts_with("start") {
-                            let argument = clean_str.split_whitespace().nth(1);
-                            argument.map(|name| {
-                                self.kernel
-                                    .process_each_capability(&self.capability, |proc| {
-                                        let proc_name = proc.get_process_name();
-                                        if proc_name == name {
-                                            proc.resume();
-                                            let mut console_writer = ConsoleWriter::new();
-                                            let _ = write(
-                                                &mut console_writer,
-                                                format_args!("Process {} resumed.\r\n", name),
-                                            );
-
-                                            let _ = self.write_bytes(
-                                                &(console_writer.buf)[..console_writer.size],
-                                            );
-                                        }
-                                    });
-                            });
-                        } else if clean_str.starts_with("stop") {
-                            let argument = clean_str.split_whitespace().nth(1);
-                            argument.map(|name| {
-                                self.kernel
-                                    .process_each_capability(&self.capability, |proc| {
-                                        let proc_name = proc.get_process_name();
-                                        if proc_name == name {
-                                            proc.stop();
-                                            let mut console_writer = ConsoleWriter::new();
-                                            let _ = write(
-                                                &mut console_writer,
-                                                format_args!("Process {} stopped\r\n", proc_name),
-                                            );
-
-                                            let _ = self.write_bytes(
-                                                &(console_writer.buf)[..console_writer.size],
-                                            );
-                                        }
-                                    });
-                            });
-                        } else if clean_str.starts_with("fault") {
-                            let argument = clean_str.split_whitespace().nth(1);
-                            argument.map(|name| {
-                                self.kernel
-                                    .process_each_capability(&self.capability, |proc| {
-                                        let proc_name = proc.get_process_name();
-                                        if proc_name == name {
-                                            proc.set_fault_state();
-                                            let mut console_writer = ConsoleWriter::new();
-                                            let _ = write(
-                                                &mut console_writer,
-                                                format_args!(
-                                                    "Process {} now faulted\r\n",
-                                                    proc_name
-                                                ),
-                                            );
-
-                                            let _ = self.write_bytes(
-                                                &(console_writer.buf)[..console_writer.size],
-                                            );
-                                        }
-                                    });
-                            });
-                        } else if clean_str.starts_with("terminate") {
-                            let argument = clean_str.split_whitespace().nth(1);
-                            argument.map(|name| {
-                                self.kernel
-                                    .process_each_capability(&self.capability, |proc| {
-                                        let proc_name = proc.get_process_name();
-                                        if proc_name == name {
-                                            proc.terminate(None);
-                                            let mut console_writer = ConsoleWriter::new();
-                                            let _ = write(
-                                                &mut console_writer,
-                                                format_args!(
-                                                    "Process {} terminated\r\n",
-                                                    proc_name
-                                                ),
-                                            );
-
-                                            let _ = self.write_bytes(
-                                                &(console_writer.buf)[..console_writer.size],
-                                            );
-                                        }
-                                    });
-                            });
-                        } else if clean_str.starts_with("boot") {
-                            let argument = clean_str.split_whitespace().nth(1);
-                            argument.map(|name| {
-                                self.kernel
-                                    .process_each_capability(&self.capability, |proc| {
-                                        let proc_name = proc.get_process_name();
-                                        if proc_name == name
-                                            && proc.get_state() == State::Terminated
-                                        {
-                                            proc.start(&self.capability);
-                                        }
-                                    });
-                            });
-                        } else if clean_str.starts_with("list") {
-                            let _ = self
-                                .write_bytes(b" PID    ShortID    Name                Quanta  ");
-                            let _ = self.write_bytes(b"Syscalls  Restarts  Grants  State\r\n");
-
-                            // Count the number of current processes.
-                            let mut count = 0;
-                            self.kernel.process_each_capability(&self.capability, |_| {
-                                count += 1;
-                            });
-
-                            if count > 0 {
-                                // Start the state machine to print each separately.
-                                self.write_state(WriterState::List {
-                                    index: -1,
-                                    total: count,
-                                });
-                            }
-                        } else if clean_str.starts_with("status") {
-                            let info: KernelInfo = KernelInfo::new(self.kernel);
-                            let mut console_writer = ConsoleWriter::new();
-                            let _ = write(
-                                &mut console_writer,
-                                format_args!(
-                                    "Total processes: {}\r\n",
-                                    info.number_loaded_processes(&self.capability)
-                                ),
-                            );
-                            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
-                            console_writer.clear();
-                            let _ = write(
-                                &mut console_writer,
-                                format_args!(
-                                    "Active processes: {}\r\n",
-                                    info.number_active_processes(&self.capability)
-                                ),
-                            );
-                            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
-                            console_writer.clear();
-                            let _ = write(
-                                &mut console_writer,
-                                format_args!(
-                                    "Timeslice expirations: {}\r\n",
-                                    info.timeslice_expirations(&self.capability)
-                                ),
-                            );
-                            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
-                        } else if clean_str.starts_with("process") {
-                            let argument = clean_str.split_whitespace().nth(1);
-                            argument.map(|name| {
-                                // If two processes have the same name, only
-                                // print the first one we find.
-                                let mut found = false;
-                                self.kernel
-                                    .process_each_capability(&self.capability, |proc| {
-                                        if found {
-                                            return;
-                                        }
-                                        let proc_name = proc.get_process_name();
-                                        if proc_name == name {
-                                            let mut console_writer = ConsoleWriter::new();
-                                            let mut context: Option<ProcessPrinterContext> = None;
-                                            context = self.process_printer.print_overview(
-                                                proc,
-                                                &mut console_writer,
-                                                context,
-                                            );
-
-                                            let _ = self.write_bytes(
-                                                &(console_writer.buf)[..console_writer.size],
-                                            );
-
-                                            if context.is_some() {
-                                                self.writer_state.replace(
-                                                    WriterState::ProcessPrint {
-                                                        process_id: proc.processid(),
-                                                        context,
-                                                    },
-                                                );
-                                            }
-
-                                            found = true;
-                                        }
-                                    });
-                            });
-                        } else if clean_str.starts_with("kernel") {
-                            let mut console_writer = ConsoleWriter::new();
-                            let _ = write(
-                                &mut console_writer,
-                                format_args!(
-                                    "Kernel version: {}.{} (build {})\r\n",
-                                    kernel::KERNEL_MAJOR_VERSION,
-                                    kernel::KERNEL_MINOR_VERSION,
-                                    option_env!("TOCK_KERNEL_VERSION").unwrap_or("unknown")
-                                ),
-                            );
-                            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
-                            console_writer.clear();
-
-                            // Prints kernel memory by moving the writer to the
-                            // start state.
-                            self.writer_state.replace(WriterState::KernelStart);
-                        } else if clean_str.starts_with("reset") {
-                            self.reset_function.map_or_else(
-                                || {
-                                    let _ = self.write_bytes(b"Reset function is not implemented");
-                                },
-                                |f| {
-                                    f();
-                                },
-                            );
-                        } else if clean_str.starts_with("panic") {
-                            panic!("Process Console forced a kernel panic.");
                         } else {
-                            let _ = self.write_bytes(b"Valid commands are: ");
-                            let _ = self.write_bytes(VALID_COMMANDS_STR);
+                            self.run_pipeline(clean_str);
                         }
                     }
                     Err(_e) => {
-                        let mut console_writer = ConsoleWriter::new();
-                        let _ = write(
-                            &mut console_writer,
-                            format_args!("Invalid command: {:?}", command),
-                        );
-                        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                        let _ = self.write_bytes(b"Invalid command\r\n");
                     }
                 }
             }
@@ -1029,8 +1148,282 @@ impl<
         }
     }
 
+    /// Run the commands of a pipeline such as `list | grep Running`, or a
+    /// single command. The output of each command but the last is captured
+    /// and passed to the next command as its input.
+    fn run_pipeline(&self, mut line: &str) {
+        let mut input_len = 0;
+        loop {
+            let next = line.split_once('|');
+            let stage = next.map_or(line, |(stage, _)| stage);
+            if next.is_some() {
+                self.pipe_size.set(0);
+            }
+            self.pipe_input.map(|input| {
+                let mut args =
+                    Arguments::new(stage, str::from_utf8(&input[..input_len]).unwrap_or(""));
+                let name = args.next().unwrap_or("");
+                self.run_command(name, args);
+            });
+            let Some((_, rest)) = next else {
+                break;
+            };
+            line = rest;
+
+            // Commands such as `list` print in several steps, which finish
+            // here instead of after each transmission.
+            let mut state = self.writer_state.get();
+            while state != WriterState::Empty {
+                self.write_state(state);
+                if self.writer_state.get() == state {
+                    break;
+                }
+                state = self.writer_state.get();
+            }
+            self.writer_state.set(WriterState::Empty);
+
+            input_len = self.pipe_size.take().unwrap_or(0);
+            if let (Some(input), Some(output)) = (self.pipe_input.take(), self.pipe_output.take()) {
+                self.pipe_input.replace(output);
+                self.pipe_output.replace(input);
+            }
+            if self.pipe_truncated.take() {
+                let _ = self.write_bytes(b"Pipe output truncated\r\n");
+            }
+        }
+    }
+
+    /// Run the command `name`, built in or registered, with `args`.
+    fn run_command(&self, name: &str, mut args: Arguments) {
+        match name {
+            "help" => match args.next().and_then(|topic| self.find_command(topic)) {
+                Some(command) => self.print_usage(command),
+                None => {
+                    let _ = self.write_bytes(b"Welcome to the process console.\r\n");
+                    self.print_commands();
+                    let _ = self.write_bytes(SELECTORS_HELP_STR);
+                }
+            },
+            "console-stop" => {
+                let _ = self.write_bytes(b"Disabling the process console.\r\n");
+                let _ = self.write_bytes(b"Run console-start to reactivate.\r\n");
+                self.mode.set(ProcessConsoleState::Hibernating);
+            }
+            "start" | "stop" | "fault" | "terminate" | "boot" => {
+                self.each_selected_process(args.next(), &mut |proc| {
+                    let done = match name {
+                        "start" => {
+                            proc.resume();
+                            "resumed"
+                        }
+                        "stop" => {
+                            proc.stop();
+                            "stopped"
+                        }
+                        "fault" => {
+                            proc.set_fault_state();
+                            "faulted"
+                        }
+                        "terminate" => {
+                            proc.terminate(None);
+                            "terminated"
+                        }
+                        _ => {
+                            if proc.get_state() != State::Terminated {
+                                return;
+                            }
+                            proc.start(&self.capability);
+                            "booted"
+                        }
+                    };
+                    self.write_args(format_args!(
+                        "Process {} {}\r\n",
+                        proc.get_process_name(),
+                        done
+                    ));
+                });
+            }
+            "unload" => {
+                // Unloading changes the processes array, so find the process
+                // before removing it. If several processes match, only the
+                // first one is unloaded. Only terminated processes can be
+                // unloaded, so the process is terminated first.
+                let unloader = self.process_unloader.get();
+                let mut selected = None;
+                self.each_selected_process(args.next(), &mut |proc| {
+                    if selected.is_none() {
+                        if unloader.is_some() {
+                            proc.terminate(None);
+                        }
+                        selected = Some((proc.processid(), proc.get_process_name()));
+                    }
+                });
+                if let Some((processid, name)) = selected {
+                    let result = match unloader {
+                        Some(unloader) => unloader.unload(processid, &self.capability),
+                        None => Err(ErrorCode::NOSUPPORT),
+                    };
+                    match result {
+                        Ok(()) => self.write_args(format_args!("Process {} unloaded\r\n", name)),
+                        Err(e) => {
+                            self.write_args(format_args!("Unable to unload {}: {:?}\r\n", name, e))
+                        }
+                    }
+                }
+            }
+            "list" => {
+                let _ = self.write_bytes(b" PID    ShortID    Name                Quanta  ");
+                let _ = self.write_bytes(b"Syscalls  Restarts  Grants  State\r\n");
+
+                // Count the number of current processes.
+                let mut count = 0;
+                self.kernel.process_each_capability(&self.capability, |_| {
+                    count += 1;
+                });
+
+                if count > 0 {
+                    // Start the state machine to print each separately.
+                    self.write_state(WriterState::List {
+                        index: -1,
+                        total: count,
+                    });
+                }
+            }
+            "stats" => {
+                let info: KernelInfo = KernelInfo::new(self.kernel);
+                self.each_selected_process(args.next(), &mut |proc| {
+                    let process_id = proc.processid();
+                    let cpu_time_us = info.app_cpu_time_us(process_id, &self.capability);
+                    self.write_args(format_args!(
+                        "{}: CPU time: {}.{:03} ms\r\n",
+                        proc.get_process_name(),
+                        cpu_time_us / 1000,
+                        cpu_time_us % 1000
+                    ));
+                    let peaks = [
+                        (
+                            "stack",
+                            info.app_peak_stack_usage(process_id, &self.capability),
+                        ),
+                        (
+                            "heap",
+                            info.app_peak_heap_usage(process_id, &self.capability),
+                        ),
+                    ];
+                    for (region, peak) in peaks {
+                        match peak {
+                            Some(bytes) => self
+                                .write_args(format_args!("Peak {}: {} bytes\r\n", region, bytes)),
+                            None => self.write_args(format_args!("Peak {}: ?\r\n", region)),
+                        }
+                    }
+                    self.write_args(format_args!(
+                        "Task queue high water: {}\r\nSyscalls: {}\r\n",
+                        info.app_task_queue_high_water(process_id, &self.capability),
+                        proc.debug_syscall_count()
+                    ));
+                    // Each line is written on its own, as there may be more
+                    // drivers than one `ConsoleWriter` holds.
+                    let histogram = info.app_syscall_histogram(process_id, &self.capability);
+                    for (driver_num, count) in histogram.iter() {
+                        self.write_args(format_args!("  driver {:#x}: {}\r\n", driver_num, count));
+                    }
+                    if histogram.other() > 0 {
+                        self.write_args(format_args!("  other drivers: {}\r\n", histogram.other()));
+                    }
+                });
+            }
+            "status" => {
+                let info: KernelInfo = KernelInfo::new(self.kernel);
+                self.write_args(format_args!(
+                    "Total processes: {}\r\n",
+                    info.number_loaded_processes(&self.capability)
+                ));
+                self.write_args(format_args!(
+                    "Active processes: {}\r\n",
+                    info.number_active_processes(&self.capability)
+                ));
+                self.write_args(format_args!(
+                    "Timeslice expirations: {}\r\n",
+                    info.timeslice_expirations(&self.capability)
+                ));
+            }
+            "process" => {
+                // If several processes match, only print the first one, as the
+                // printer continues asynchronously.
+                let mut found = false;
+                self.each_selected_process(args.next(), &mut |proc| {
+                    if found {
+                        return;
+                    }
+                    found = true;
+                    let mut console_writer = ConsoleWriter::new();
+                    let context =
+                        self.process_printer
+                            .print_overview(proc, &mut console_writer, None);
+                    self.write_console_writer(&console_writer);
+
+                    if context.is_some() {
+                        self.writer_state.replace(WriterState::ProcessPrint {
+                            process_id: proc.processid(),
+                            context,
+                        });
+                    }
+                });
+            }
+            "kernel" => {
+                self.print_kernel_version();
+
+                // Prints kernel memory by moving the writer to the start
+                // state.
+                self.writer_state.replace(WriterState::KernelStart);
+            }
+            "crashdump" => {
+                if args.next() == Some("clear") {
+                    self.crash_dumps.map(|crash_dumps| crash_dumps.clear());
+                } else {
+                    // Prints the first part of the crash dumps, which moves
+                    // the writer to the print state if there is more.
+                    self.create_state_buffer(WriterState::CrashDump { offset: 0 });
+                }
+            }
+            "grep" => {
+                // Print the lines of the input that contain the pattern.
+                let pattern = args.next().unwrap_or("").as_bytes();
+                for line in args.input().as_bytes().split(|&b| b == NLINE) {
+                    if pattern.is_empty() || line.windows(pattern.len()).any(|w| w == pattern) {
+                        let _ = self.write_bytes(line);
+                        let _ = self.write_bytes(b"\n");
+                    }
+                }
+            }
+            "reset" => {
+                self.reset_function.map_or_else(
+                    || {
+                        let _ = self.write_bytes(b"Reset function is not implemented");
+                    },
+                    |f| {
+                        f();
+                    },
+                );
+            }
+            "panic" => {
+                panic!("Process Console forced a kernel panic.");
+            }
+            _ => {
+                if !self.run_registered_command(name, args) {
+                    self.print_commands();
+                }
+            }
+        }
+    }
+
     fn prompt(&self) {
-        // Only display the prompt in active mode.
+        // Only display the prompt in active mode, and not in the middle of a
+        // pipe.
+        if self.pipe_size.is_some() {
+            return;
+        }
         match self.mode.get() {
             ProcessConsoleState::Active => {
                 let _ = self.write_bytes(b"tock$ ");
@@ -1064,6 +1457,20 @@ impl<
     }
 
     fn write_bytes(&self, bytes: &[u8]) -> Result<(), ErrorCode> {
+        if let Some(size) = self.pipe_size.get() {
+            // Capture the output of a command that is piped into another.
+            // Output that does not fit is dropped whole, which keeps the
+            // captured text valid UTF-8.
+            self.pipe_output
+                .map(|buf| match buf.get_mut(size..size + bytes.len()) {
+                    Some(dest) => {
+                        dest.copy_from_slice(bytes);
+                        self.pipe_size.set(size + bytes.len());
+                    }
+                    None => self.pipe_truncated.set(true),
+                });
+            return Ok(());
+        }
         if self.tx_in_progress.get() {
             self.queue_buffer.map(|buf| {
                 let size = self.queue_size.get();
@@ -1130,6 +1537,23 @@ impl<
     }
 }
 
+impl<
+        'a,
+        const COMMAND_HISTORY_LEN: usize,
+        A: Alarm<'a>,
+        C: ProcessManagementCapability + ProcessStartCapability,
+    > DynamicProcessUnloadClient for ProcessConsole<'a, COMMAND_HISTORY_LEN, A, C>
+{
+    fn unload_done(&self, result: Result<(), ErrorCode>) {
+        if let Err(e) = result {
+            self.write_args(format_args!(
+                "Unable to disable the unloaded process binary: {:?}\r\n",
+                e
+            ));
+        }
+    }
+}
+
 impl<
         'a,
         const COMMAND_HISTORY_LEN: usize,
@@ -1215,7 +1639,7 @@ impl<
 
                         if let EscState::Complete(key) = esc_state {
                             match key {
-                                EscKey::Up | EscKey::Down if COMMAND_HISTORY_LEN >= 1 => {
+                                EscKey::Up | EscKey::Down if COMMAND_HISTORY_LEN > 1 => {
                                     self.command_history.map(|ht| {
                                         if let Some(next_index) = if matches!(key, EscKey::Up) {
                                             ht.next_cmd_idx()
@@ -1434,3 +1858,102 @@ impl<
         let _ = self.uart.receive_buffer(read_buf, 1);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{
+        glob_match, parse_number, Arguments, CommandRegistry, ConsoleCommand, ConsoleWriter,
+        RegisteredCommand,
+    };
+    use core::fmt::Write;
+    use kernel::ErrorCode;
+
+    /// Prints its arguments, or its input if it has none.
+    struct Echo;
+
+    impl ConsoleCommand for Echo {
+        fn execute(&self, args: Arguments, writer: &mut ConsoleWriter) -> Result<(), ErrorCode> {
+            let input = args.input();
+            let mut words = 0;
+            for arg in args {
+                let _ = write!(writer, "{}{}", if words > 0 { " " } else { "" }, arg);
+                words += 1;
+            }
+            if words == 0 {
+                if input.is_empty() {
+                    return Err(ErrorCode::INVAL);
+                }
+                let _ = writer.write_str(input);
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn glob_matches_names() {
+        assert!(glob_match(b"blink", b"blink"));
+        assert!(!glob_match(b"blink", b"blinky"));
+        assert!(glob_match(b"sensor*", b"sensor"));
+        assert!(glob_match(b"sensor*", b"sensor_temp"));
+        assert!(!glob_match(b"sensor*", b"my_sensor"));
+        assert!(glob_match(b"*", b"anything"));
+        assert!(glob_match(b"*_temp", b"sensor_temp"));
+        assert!(glob_match(b"s*r*p", b"sensor_temp"));
+        assert!(glob_match(b"app?", b"app1"));
+        assert!(!glob_match(b"app?", b"app"));
+        assert!(!glob_match(b"", b"app"));
+    }
+
+    #[test]
+    fn parses_arguments() {
+        let mut args = Arguments::new("  0x1F  42 key\tvalue x", "");
+        assert_eq!(args.next_number(), Ok(0x1F));
+        assert_eq!(args.next_number(), Ok(42));
+        assert_eq!(args.next(), Some("key"));
+        assert_eq!(args.next(), Some("value"));
+        assert!(args.next_number().is_err());
+        assert!(args.next_process().is_err());
+        assert_eq!(parse_number("0x"), None);
+        assert_eq!(parse_number("4294967296"), None);
+    }
+
+    #[test]
+    fn passes_pipe_input() {
+        let mut args = Arguments::new(" grep Running ", "blink Running\r\nsensor Stopped");
+        assert_eq!(args.next(), Some("grep"));
+        assert_eq!(args.next(), Some("Running"));
+        assert_eq!(args.next(), None);
+        assert_eq!(args.input().lines().next(), Some("blink Running"));
+    }
+
+    #[test]
+    fn dispatches_registered_commands() {
+        let echo = Echo;
+        let command = RegisteredCommand::new("echo", "[words]", "Print the words.", &echo);
+        let registry = CommandRegistry::new();
+        registry.register(&command);
+
+        let mut writer = ConsoleWriter::new_truncating();
+        let mut args = Arguments::new("echo hello  world", "");
+        let name = args.next().unwrap();
+        let (found, result) = registry.run(name, args, &mut writer).unwrap();
+        assert_eq!(found.name, "echo");
+        assert_eq!(result, Ok(()));
+        assert_eq!(&writer.buf[..writer.size], b"hello world");
+
+        writer.clear();
+        let mut args = Arguments::new("echo", "blink Running");
+        let name = args.next().unwrap();
+        assert_eq!(registry.run(name, args, &mut writer).unwrap().1, Ok(()));
+        assert_eq!(&writer.buf[..writer.size], b"blink Running");
+
+        let args = Arguments::new("", "");
+        assert_eq!(
+            registry.run("echo", args, &mut writer).unwrap().1,
+            Err(ErrorCode::INVAL)
+        );
+        let args = Arguments::new("", "");
+        assert!(registry.run("list", args, &mut writer).is_none());
+        assert!(registry.find("echo").is_some());
+    }
+}
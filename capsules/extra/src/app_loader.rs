@@ -0,0 +1,291 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Provides userspace with the ability to install new applications at
+//! runtime.
+//!
+//! A process receives a new application (a TBF binary) over any transport it
+//! has access to, for example the console, USB, or the network, and passes
+//! it to this capsule in chunks. The capsule stores the binary in free app
+//! flash and then loads it into a new process, without reflashing the kernel
+//! or the other applications.
+//!
+//! Only one process can install an application at a time. If that process
+//! exits before it finishes, another process can abort the installation and
+//! start its own.
+//!
+//! ```text
+//! +-----------------------------------------------+
+//! | capsules::app_loader::AppLoader (this)        |
+//! +-----------------------------------------------+
+//!   kernel::dynamic_binary_storage::{DynamicBinaryStore, DynamicProcessLoad}
+//! +-----------------------------------------------+
+//! | kernel::dynamic_binary_storage::              |
+//! |     SequentialDynamicBinaryStorage            |
+//! +-----------------------------------------------+
+//!   hil::nonvolatile_storage    process::ProcessLoadingDynamic
+//! ```
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let buffer = static_init!(
+//!     [u8; capsules_extra::app_loader::BUF_LEN],
+//!     [0; capsules_extra::app_loader::BUF_LEN]
+//! );
+//! let app_loader = static_init!(
+//!     capsules_extra::app_loader::AppLoader<'static>,
+//!     capsules_extra::app_loader::AppLoader::new(
+//!         board_kernel.create_grant(
+//!             capsules_extra::app_loader::DRIVER_NUM,
+//!             &memory_allocation_capability
+//!         ),
+//!         dynamic_binary_storage,
+//!         dynamic_binary_storage,
+//!         buffer,
+//!     )
+//! );
+//! dynamic_binary_storage.set_storage_client(app_loader);
+//! dynamic_binary_storage.set_load_client(app_loader);
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! ### Command
+//!
+//! - `0`: Driver existence check.
+//! - `1`: Reserve flash for an application of `arg1` bytes. Completion is
+//!   signalled through upcall `0`.
+//! - `2`: Write `arg2` bytes from the read-only allow buffer at offset `arg1`
+//!   of the application. Chunks can be written in any order. Completion is
+//!   signalled through upcall `1`.
+//! - `3`: Check the written application and load it into a new process.
+//!   Completion is signalled through upcall `2`.
+//! - `4`: Discard the application being written. This completes immediately.
+//!
+//! ### Allow
+//!
+//! - Read-only `0`: Chunk of the application to write.
+//!
+//! ### Upcalls
+//!
+//! The first argument of every upcall is the status of the operation.
+//!
+//! - `0`: Setup finished.
+//! - `1`: Write finished.
+//! - `2`: Load finished.
+
+use kernel::dynamic_binary_storage::{
+    DynamicBinaryStore, DynamicBinaryStoreClient, DynamicProcessLoad, DynamicProcessLoadClient,
+};
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::process::ProcessLoadError;
+use kernel::processbuffer::ReadableProcessBuffer;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::AppLoader as usize;
+
+/// Maximum number of bytes written in one chunk.
+pub const BUF_LEN: usize = 512;
+
+/// Ids for subscribed upcalls.
+mod upcall {
+    pub const SETUP_DONE: usize = 0;
+    pub const WRITE_DONE: usize = 1;
+    pub const LOAD_DONE: usize = 2;
+    /// The number of upcalls the kernel stores for this grant
+    pub const COUNT: u8 = 3;
+}
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    pub const WRITE: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 0;
+}
+
+#[derive(Default)]
+pub struct App;
+
+pub struct AppLoader<'a> {
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    storage: &'a dyn DynamicBinaryStore<'a>,
+    loader: &'a dyn DynamicProcessLoad<'a>,
+    buffer: TakeCell<'static, [u8]>,
+    /// Process that is installing an application.
+    current_process: OptionalCell<ProcessId>,
+}
+
+impl<'a> AppLoader<'a> {
+    pub fn new(
+        apps: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+        storage: &'a dyn DynamicBinaryStore<'a>,
+        loader: &'a dyn DynamicProcessLoad<'a>,
+        buffer: &'static mut [u8],
+    ) -> AppLoader<'a> {
+        AppLoader {
+            apps,
+            storage,
+            loader,
+            buffer: TakeCell::new(buffer),
+            current_process: OptionalCell::empty(),
+        }
+    }
+
+    /// Whether `processid` may use the loader. Ownership passes to
+    /// `processid` if the process that was installing an application no
+    /// longer exists.
+    fn claim(&self, processid: ProcessId) -> bool {
+        let owned = self.current_process.map_or(false, |current| {
+            current != processid && self.apps.enter(current, |_, _| {}).is_ok()
+        });
+        if !owned {
+            self.current_process.set(processid);
+        }
+        !owned
+    }
+
+    /// Copy a chunk of the application from the allow buffer and write it.
+    fn write(&self, processid: ProcessId, offset: usize, length: usize) -> Result<(), ErrorCode> {
+        let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+        let res = self
+            .apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readonly_processbuffer(ro_allow::WRITE)
+                    .and_then(|chunk| {
+                        chunk.enter(|chunk| {
+                            if length > chunk.len() || length > buffer.len() {
+                                return Err(ErrorCode::SIZE);
+                            }
+                            chunk[..length].copy_to_slice(&mut buffer[..length]);
+                            Ok(())
+                        })
+                    })
+                    .unwrap_or(Err(ErrorCode::RESERVE))
+            })
+            .unwrap_or_else(|err| Err(err.into()));
+        if let Err(e) = res {
+            self.buffer.replace(buffer);
+            return Err(e);
+        }
+
+        self.storage
+            .write(buffer, offset, length)
+            .map_err(|(e, buffer)| {
+                self.buffer.replace(buffer);
+                e
+            })
+    }
+
+    fn schedule_upcall(&self, upcall_num: usize, result: Result<(), ErrorCode>) {
+        self.current_process.map(|processid| {
+            let _ = self.apps.enter(processid, |_, kernel_data| {
+                kernel_data
+                    .schedule_upcall(
+                        upcall_num,
+                        (kernel::errorcode::into_statuscode(result), 0, 0),
+                    )
+                    .ok();
+            });
+        });
+    }
+}
+
+impl DynamicBinaryStoreClient for AppLoader<'_> {
+    fn setup_done(&self, result: Result<(), ErrorCode>) {
+        self.schedule_upcall(upcall::SETUP_DONE, result);
+        if result.is_err() {
+            self.current_process.clear();
+        }
+    }
+
+    fn write_done(&self, result: Result<(), ErrorCode>, buffer: &'static mut [u8], _length: usize) {
+        self.buffer.replace(buffer);
+        self.schedule_upcall(upcall::WRITE_DONE, result);
+    }
+}
+
+impl DynamicProcessLoadClient for AppLoader<'_> {
+    fn load_done(&self, result: Result<(), ProcessLoadError>) {
+        let status = result.map_err(|e| match e {
+            ProcessLoadError::NotEnoughMemory | ProcessLoadError::NoProcessSlot => ErrorCode::NOMEM,
+            ProcessLoadError::AppIdConflict => ErrorCode::ALREADY,
+            ProcessLoadError::BinaryError(_) | ProcessLoadError::CheckError(_) => ErrorCode::INVAL,
+            _ => ErrorCode::FAIL,
+        });
+        self.schedule_upcall(upcall::LOAD_DONE, status);
+        if status.is_ok() {
+            self.current_process.clear();
+        }
+    }
+}
+
+impl SyscallDriver for AppLoader<'_> {
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Return Ok(()) if this driver is included on the platform.
+    /// - `1`: Reserve flash for an application of `arg1` bytes.
+    /// - `2`: Write `arg2` bytes from the read-only buffer at offset `arg1`.
+    /// - `3`: Load the written application.
+    /// - `4`: Abort writing the application.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        arg2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        if command_num == 0 {
+            return CommandReturn::success();
+        }
+        if !self.claim(processid) {
+            return CommandReturn::failure(ErrorCode::RESERVE);
+        }
+
+        let res = match command_num {
+            1 => self.storage.setup(arg1),
+            2 => self.write(processid, arg1, arg2),
+            3 => self.loader.load(),
+            4 => self.storage.abort(),
+            _ => Err(ErrorCode::NOSUPPORT),
+        };
+        // A setup or abort that does not fail because of an ongoing operation
+        // leaves no installation behind.
+        if (command_num == 1 && res.is_err() || command_num == 4) && res != Err(ErrorCode::BUSY) {
+            self.current_process.clear();
+        }
+        CommandReturn::from(res)
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
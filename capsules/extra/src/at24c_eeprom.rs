@@ -37,6 +37,7 @@
 //!         at24c_capsule,
 //!         0x0,
 //!         0x10000,
+//!         0x1000,
 //!         0x0,
 //!         0x0,
 //!     ).finalize(components::nonvolatile_storage_component_static!(capsules_extra::at24c_eeprom::AT24C));
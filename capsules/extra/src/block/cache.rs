@@ -0,0 +1,372 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Write-back cache for block devices.
+//!
+//! Keeps `N` blocks of another block device in memory. Reads of cached blocks
+//! and writes are served from memory, and written blocks are only written to
+//! the device when their slot is needed for another block or on `flush()`.
+//! The least recently used block is replaced first. A failed write-back keeps
+//! the block in the cache, so no written data is dropped before it has been
+//! stored or the block has been erased.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::{hil, static_init};
+//! # use kernel::deferred_call::DeferredCallClient;
+//!
+//! let slots = static_init!([u8; 4 * 512], [0; 4 * 512]);
+//! let cache = static_init!(
+//!     capsules_extra::block::cache::BlockCache<'static, SDCardBlock<'static, Alarm>, 4>,
+//!     capsules_extra::block::cache::BlockCache::new(sdcard_block, slots)
+//! );
+//! hil::block::BlockDevice::set_client(sdcard_block, cache);
+//! cache.register();
+//! ```
+
+use core::cell::Cell;
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::block::{BlockClient, BlockDevice};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Idle,
+    Read(usize),
+    Write(usize),
+    Erase(usize),
+    Flush,
+}
+
+/// A block held in memory.
+struct Slot {
+    buffer: TakeCell<'static, [u8]>,
+    block: Cell<Option<usize>>,
+    dirty: Cell<bool>,
+    used: Cell<u32>,
+}
+
+pub struct BlockCache<'a, B: BlockDevice<'a>, const N: usize> {
+    device: &'a B,
+    client: OptionalCell<&'a dyn BlockClient>,
+    deferred_call: DeferredCall,
+    slots: [Slot; N],
+    block_size: usize,
+    clock: Cell<u32>,
+    op: Cell<Op>,
+    buffer: TakeCell<'static, [u8]>,
+}
+
+impl<'a, B: BlockDevice<'a>, const N: usize> BlockCache<'a, B, N> {
+    /// Cache blocks of `device` in `buffer`, which must hold `N` blocks.
+    pub fn new(device: &'a B, buffer: &'static mut [u8]) -> Self {
+        let block_size = device.block_size();
+        let mut chunks = buffer.chunks_exact_mut(block_size);
+        BlockCache {
+            device,
+            client: OptionalCell::empty(),
+            deferred_call: DeferredCall::new(),
+            slots: core::array::from_fn(|_| Slot {
+                buffer: chunks.next().map_or(TakeCell::empty(), TakeCell::new),
+                block: Cell::new(None),
+                dirty: Cell::new(false),
+                used: Cell::new(0),
+            }),
+            block_size,
+            clock: Cell::new(0),
+            op: Cell::new(Op::Idle),
+            buffer: TakeCell::empty(),
+        }
+    }
+
+    fn touch(&self, slot: usize) {
+        let now = self.clock.get().wrapping_add(1);
+        self.clock.set(now);
+        self.slots[slot].used.set(now);
+    }
+
+    fn lookup(&self, block: usize) -> Option<usize> {
+        (0..N).find(|&slot| self.slots[slot].block.get() == Some(block))
+    }
+
+    /// The slot to replace: an empty one, else the least recently used clean
+    /// one, else the least recently used one.
+    fn victim(&self) -> usize {
+        (0..N)
+            .min_by_key(|&slot| {
+                let slot = &self.slots[slot];
+                (
+                    slot.block.get().is_some(),
+                    slot.dirty.get(),
+                    slot.used.get(),
+                )
+            })
+            .unwrap_or(0)
+    }
+
+    fn write_back(&self, slot: usize) -> Result<(), ErrorCode> {
+        let block = self.slots[slot].block.get().ok_or(ErrorCode::FAIL)?;
+        let buffer = self.slots[slot].buffer.take().ok_or(ErrorCode::NOMEM)?;
+        self.device.write(block, buffer).map_err(|(err, buffer)| {
+            self.slots[slot].buffer.replace(buffer);
+            err
+        })
+    }
+
+    /// Get a slot for `block`, writing back its current block if needed.
+    /// Returns `None` while a write-back is in progress.
+    fn claim(&self, block: usize) -> Result<Option<usize>, ErrorCode> {
+        if let Some(slot) = self.lookup(block) {
+            return Ok(Some(slot));
+        }
+        let slot = self.victim();
+        if self.slots[slot].dirty.get() {
+            self.write_back(slot)?;
+            return Ok(None);
+        }
+        Ok(Some(slot))
+    }
+
+    /// Take the next step of the current operation. Returns `Ok(true)` when
+    /// the operation has completed and `Ok(false)` while it waits for the
+    /// device.
+    fn advance(&self) -> Result<bool, ErrorCode> {
+        match self.op.get() {
+            Op::Read(block) => {
+                let Some(slot) = self.claim(block)? else {
+                    return Ok(false);
+                };
+                if self.slots[slot].block.get() == Some(block) {
+                    self.touch(slot);
+                    self.copy_out(slot);
+                    return Ok(true);
+                }
+                let buffer = self.slots[slot].buffer.take().ok_or(ErrorCode::NOMEM)?;
+                self.slots[slot].block.set(None);
+                self.device.read(block, buffer).map_err(|(err, buffer)| {
+                    self.slots[slot].buffer.replace(buffer);
+                    err
+                })?;
+                Ok(false)
+            }
+            Op::Write(block) => {
+                let Some(slot) = self.claim(block)? else {
+                    return Ok(false);
+                };
+                self.copy_in(slot);
+                self.slots[slot].block.set(Some(block));
+                self.slots[slot].dirty.set(true);
+                self.touch(slot);
+                Ok(true)
+            }
+            Op::Flush => {
+                if let Some(slot) = (0..N).find(|&slot| self.slots[slot].dirty.get()) {
+                    self.write_back(slot)?;
+                    return Ok(false);
+                }
+                match self.device.flush() {
+                    Ok(()) => Ok(false),
+                    Err(ErrorCode::ALREADY) => Ok(true),
+                    Err(err) => Err(err),
+                }
+            }
+            Op::Erase(_) | Op::Idle => Ok(true),
+        }
+    }
+
+    fn copy_out(&self, slot: usize) {
+        self.buffer.map(|buffer| {
+            self.slots[slot]
+                .buffer
+                .map(|data| buffer[..self.block_size].copy_from_slice(data));
+        });
+    }
+
+    fn copy_in(&self, slot: usize) {
+        self.buffer.map(|buffer| {
+            self.slots[slot]
+                .buffer
+                .map(|data| data.copy_from_slice(&buffer[..self.block_size]));
+        });
+    }
+
+    fn step(&self) {
+        match self.advance() {
+            Ok(true) => self.finish(Ok(())),
+            Ok(false) => {}
+            Err(err) => self.finish(Err(err)),
+        }
+    }
+
+    fn finish(&self, result: Result<(), ErrorCode>) {
+        match self.op.replace(Op::Idle) {
+            Op::Read(block) => {
+                if let Some(buffer) = self.buffer.take() {
+                    self.client
+                        .map(|client| client.read_done(block, buffer, result));
+                }
+            }
+            Op::Write(block) => {
+                if let Some(buffer) = self.buffer.take() {
+                    self.client
+                        .map(|client| client.write_done(block, buffer, result));
+                }
+            }
+            Op::Erase(block) => {
+                self.client.map(|client| client.erase_done(block, result));
+            }
+            Op::Flush => {
+                self.client.map(|client| client.flush_done(result));
+            }
+            Op::Idle => {}
+        }
+    }
+
+    fn start(
+        &self,
+        op: Op,
+        block: usize,
+        buffer: &'static mut [u8],
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.op.get() != Op::Idle {
+            return Err((ErrorCode::BUSY, buffer));
+        }
+        if buffer.len() < self.block_size {
+            return Err((ErrorCode::SIZE, buffer));
+        }
+        let num_blocks = self.device.num_blocks();
+        if num_blocks != 0 && block >= num_blocks {
+            return Err((ErrorCode::INVAL, buffer));
+        }
+        self.op.set(op);
+        self.buffer.replace(buffer);
+        match self.advance() {
+            Ok(true) => {
+                self.deferred_call.set();
+                Ok(())
+            }
+            Ok(false) => Ok(()),
+            Err(err) => {
+                self.op.set(Op::Idle);
+                let buffer = self.buffer.take().unwrap_or(&mut []);
+                Err((err, buffer))
+            }
+        }
+    }
+}
+
+impl<'a, B: BlockDevice<'a>, const N: usize> BlockDevice<'a> for BlockCache<'a, B, N> {
+    fn set_client(&self, client: &'a dyn BlockClient) {
+        self.client.set(client);
+    }
+
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn num_blocks(&self) -> usize {
+        self.device.num_blocks()
+    }
+
+    fn read(
+        &self,
+        block: usize,
+        buffer: &'static mut [u8],
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        self.start(Op::Read(block), block, buffer)
+    }
+
+    fn write(
+        &self,
+        block: usize,
+        buffer: &'static mut [u8],
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        self.start(Op::Write(block), block, buffer)
+    }
+
+    fn erase(&self, block: usize) -> Result<(), ErrorCode> {
+        if self.op.get() != Op::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        // The cached contents are not needed anymore either.
+        if let Some(slot) = self.lookup(block) {
+            self.slots[slot].block.set(None);
+            self.slots[slot].dirty.set(false);
+        }
+        self.device.erase(block)?;
+        self.op.set(Op::Erase(block));
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<(), ErrorCode> {
+        if self.op.get() != Op::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.op.set(Op::Flush);
+        match self.advance() {
+            Ok(false) => Ok(()),
+            // Nothing was written since the last flush.
+            Ok(true) => {
+                self.op.set(Op::Idle);
+                Err(ErrorCode::ALREADY)
+            }
+            Err(err) => {
+                self.op.set(Op::Idle);
+                Err(err)
+            }
+        }
+    }
+}
+
+impl<'a, B: BlockDevice<'a>, const N: usize> BlockClient for BlockCache<'a, B, N> {
+    fn read_done(&self, block: usize, buffer: &'static mut [u8], result: Result<(), ErrorCode>) {
+        // Reads only fill empty slots.
+        if let Some(slot) = (0..N).find(|&slot| self.slots[slot].buffer.is_none()) {
+            self.slots[slot].buffer.replace(buffer);
+            if result.is_ok() {
+                self.slots[slot].block.set(Some(block));
+                self.slots[slot].dirty.set(false);
+            }
+        }
+        match result {
+            Ok(()) => self.step(),
+            Err(err) => self.finish(Err(err)),
+        }
+    }
+
+    fn write_done(&self, block: usize, buffer: &'static mut [u8], result: Result<(), ErrorCode>) {
+        if let Some(slot) = (0..N).find(|&slot| self.slots[slot].buffer.is_none()) {
+            self.slots[slot].buffer.replace(buffer);
+            if result.is_ok() && self.slots[slot].block.get() == Some(block) {
+                self.slots[slot].dirty.set(false);
+            }
+        }
+        match result {
+            Ok(()) => self.step(),
+            Err(err) => self.finish(Err(err)),
+        }
+    }
+
+    fn erase_done(&self, _block: usize, result: Result<(), ErrorCode>) {
+        self.finish(result);
+    }
+
+    fn flush_done(&self, result: Result<(), ErrorCode>) {
+        self.finish(result);
+    }
+}
+
+impl<'a, B: BlockDevice<'a>, const N: usize> DeferredCallClient for BlockCache<'a, B, N> {
+    fn handle_deferred_call(&self) {
+        self.finish(Ok(()));
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
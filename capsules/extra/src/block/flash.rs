@@ -0,0 +1,208 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Block device on flash pages.
+//!
+//! Makes a range of pages of a `hil::flash::Flash` available as a block
+//! device with one block per page, for example the sectors of an MX25R6435F
+//! flash chip or the pages of an AT24C EEPROM. Blocks are copied through a
+//! page buffer, so buffers of any type can be used with the device.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::{hil, static_init};
+//!
+//! let page = static_init!(
+//!     capsules_extra::mx25r6435f::Mx25r6435fSector,
+//!     capsules_extra::mx25r6435f::Mx25r6435fSector::default()
+//! );
+//! let block = static_init!(
+//!     capsules_extra::block::flash::FlashBlock<'static, Mx25r6435f>,
+//!     capsules_extra::block::flash::FlashBlock::new(mx25r6435f, page, 0, 2048)
+//! );
+//! hil::flash::HasClient::set_client(mx25r6435f, block);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil;
+use kernel::hil::block::{BlockClient, BlockDevice};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Idle,
+    Read(usize),
+    Write(usize),
+    Erase(usize),
+}
+
+pub struct FlashBlock<'a, F: hil::flash::Flash + 'static> {
+    flash: &'a F,
+    client: OptionalCell<&'a dyn BlockClient>,
+    page: TakeCell<'static, F::Page>,
+    page_size: usize,
+    first_page: usize,
+    num_pages: usize,
+    op: Cell<Op>,
+    buffer: TakeCell<'static, [u8]>,
+}
+
+impl<'a, F: hil::flash::Flash> FlashBlock<'a, F> {
+    /// Use `num_pages` pages of `flash` starting at `first_page` as blocks.
+    pub fn new(
+        flash: &'a F,
+        page: &'static mut F::Page,
+        first_page: usize,
+        num_pages: usize,
+    ) -> Self {
+        let page_size = page.as_mut().len();
+        FlashBlock {
+            flash,
+            client: OptionalCell::empty(),
+            page: TakeCell::new(page),
+            page_size,
+            first_page,
+            num_pages,
+            op: Cell::new(Op::Idle),
+            buffer: TakeCell::empty(),
+        }
+    }
+
+    fn check(&self, block: usize, buffer: &[u8]) -> Result<(), ErrorCode> {
+        if self.op.get() != Op::Idle {
+            Err(ErrorCode::BUSY)
+        } else if buffer.len() < self.page_size {
+            Err(ErrorCode::SIZE)
+        } else if block >= self.num_pages {
+            Err(ErrorCode::INVAL)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn complete(&self, page: &'static mut F::Page, result: Result<(), hil::flash::Error>) {
+        let result = result.map_err(|_| ErrorCode::FAIL);
+        let op = self.op.replace(Op::Idle);
+        let buffer = self.buffer.take();
+        match (op, buffer) {
+            (Op::Read(block), Some(buffer)) => {
+                if result.is_ok() {
+                    buffer[..self.page_size].copy_from_slice(&page.as_mut()[..self.page_size]);
+                }
+                self.page.replace(page);
+                self.client
+                    .map(|client| client.read_done(block, buffer, result));
+            }
+            (Op::Write(block), Some(buffer)) => {
+                self.page.replace(page);
+                self.client
+                    .map(|client| client.write_done(block, buffer, result));
+            }
+            _ => {
+                self.page.replace(page);
+            }
+        }
+    }
+}
+
+impl<'a, F: hil::flash::Flash> BlockDevice<'a> for FlashBlock<'a, F> {
+    fn set_client(&self, client: &'a dyn BlockClient) {
+        self.client.set(client);
+    }
+
+    fn block_size(&self) -> usize {
+        self.page_size
+    }
+
+    fn num_blocks(&self) -> usize {
+        self.num_pages
+    }
+
+    fn read(
+        &self,
+        block: usize,
+        buffer: &'static mut [u8],
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if let Err(err) = self.check(block, buffer) {
+            return Err((err, buffer));
+        }
+        let Some(page) = self.page.take() else {
+            return Err((ErrorCode::NOMEM, buffer));
+        };
+        match self.flash.read_page(self.first_page + block, page) {
+            Ok(()) => {
+                self.op.set(Op::Read(block));
+                self.buffer.replace(buffer);
+                Ok(())
+            }
+            Err((err, page)) => {
+                self.page.replace(page);
+                Err((err, buffer))
+            }
+        }
+    }
+
+    fn write(
+        &self,
+        block: usize,
+        buffer: &'static mut [u8],
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if let Err(err) = self.check(block, buffer) {
+            return Err((err, buffer));
+        }
+        let Some(page) = self.page.take() else {
+            return Err((ErrorCode::NOMEM, buffer));
+        };
+        page.as_mut()[..self.page_size].copy_from_slice(&buffer[..self.page_size]);
+        match self.flash.write_page(self.first_page + block, page) {
+            Ok(()) => {
+                self.op.set(Op::Write(block));
+                self.buffer.replace(buffer);
+                Ok(())
+            }
+            Err((err, page)) => {
+                self.page.replace(page);
+                Err((err, buffer))
+            }
+        }
+    }
+
+    fn erase(&self, block: usize) -> Result<(), ErrorCode> {
+        if self.op.get() != Op::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        if block >= self.num_pages {
+            return Err(ErrorCode::INVAL);
+        }
+        self.flash.erase_page(self.first_page + block)?;
+        self.op.set(Op::Erase(block));
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<(), ErrorCode> {
+        // Pages are written directly.
+        Err(ErrorCode::ALREADY)
+    }
+}
+
+impl<F: hil::flash::Flash> hil::flash::Client<F> for FlashBlock<'_, F> {
+    fn read_complete(&self, page: &'static mut F::Page, result: Result<(), hil::flash::Error>) {
+        self.complete(page, result);
+    }
+
+    fn write_complete(&self, page: &'static mut F::Page, result: Result<(), hil::flash::Error>) {
+        self.complete(page, result);
+    }
+
+    fn erase_complete(&self, result: Result<(), hil::flash::Error>) {
+        if let Op::Erase(block) = self.op.replace(Op::Idle) {
+            self.client
+                .map(|client| client.erase_done(block, result.map_err(|_| ErrorCode::FAIL)));
+        }
+    }
+}
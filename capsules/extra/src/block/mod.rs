@@ -0,0 +1,14 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Block devices.
+//!
+//! Adapters making storage drivers available as a `hil::block::BlockDevice`,
+//! and capsules building block devices on top of other block devices.
+
+pub mod cache;
+pub mod flash;
+pub mod nonvolatile;
+pub mod partitions;
+pub mod sdcard;
@@ -0,0 +1,188 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Block device on byte-addressable nonvolatile storage.
+//!
+//! Splits a region of a `hil::nonvolatile_storage::NonvolatileStorage`, for
+//! example an FM25CL FRAM, into blocks of the size of the block buffer passed
+//! to `new()`. The storage does not need erasing, so `erase()` returns
+//! `ALREADY`.
+//!
+//! `NonvolatileStorage` keeps the buffer when an operation fails to start, so
+//! blocks are copied through the block buffer rather than passing the client
+//! buffer to the storage. After such a failure the device fails all
+//! operations with `NOMEM`.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::{hil, static_init};
+//!
+//! let buffer = static_init!([u8; 256], [0; 256]);
+//! let block = static_init!(
+//!     capsules_extra::block::nonvolatile::NonvolatileBlock<'static>,
+//!     capsules_extra::block::nonvolatile::NonvolatileBlock::new(fm25cl, buffer, 0, 32)
+//! );
+//! hil::nonvolatile_storage::NonvolatileStorage::set_client(fm25cl, block);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::block::{BlockClient, BlockDevice};
+use kernel::hil::nonvolatile_storage::{NonvolatileStorage, NonvolatileStorageClient};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Idle,
+    Read(usize),
+    Write(usize),
+}
+
+pub struct NonvolatileBlock<'a> {
+    storage: &'a dyn NonvolatileStorage<'a>,
+    client: OptionalCell<&'a dyn BlockClient>,
+    block_buffer: TakeCell<'static, [u8]>,
+    block_size: usize,
+    start: usize,
+    num_blocks: usize,
+    op: Cell<Op>,
+    buffer: TakeCell<'static, [u8]>,
+}
+
+impl<'a> NonvolatileBlock<'a> {
+    /// Use `num_blocks` blocks of the length of `block_buffer` starting at
+    /// address `start` of `storage`.
+    pub fn new(
+        storage: &'a dyn NonvolatileStorage<'a>,
+        block_buffer: &'static mut [u8],
+        start: usize,
+        num_blocks: usize,
+    ) -> Self {
+        let block_size = block_buffer.len();
+        NonvolatileBlock {
+            storage,
+            client: OptionalCell::empty(),
+            block_buffer: TakeCell::new(block_buffer),
+            block_size,
+            start,
+            num_blocks,
+            op: Cell::new(Op::Idle),
+            buffer: TakeCell::empty(),
+        }
+    }
+
+    fn start_op(
+        &self,
+        op: Op,
+        block: usize,
+        buffer: &'static mut [u8],
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.op.get() != Op::Idle {
+            return Err((ErrorCode::BUSY, buffer));
+        }
+        if buffer.len() < self.block_size {
+            return Err((ErrorCode::SIZE, buffer));
+        }
+        if block >= self.num_blocks {
+            return Err((ErrorCode::INVAL, buffer));
+        }
+        let Some(block_buffer) = self.block_buffer.take() else {
+            return Err((ErrorCode::NOMEM, buffer));
+        };
+        let address = self.start + block * self.block_size;
+        let result = match op {
+            Op::Write(_) => {
+                block_buffer.copy_from_slice(&buffer[..self.block_size]);
+                self.storage.write(block_buffer, address, self.block_size)
+            }
+            _ => self.storage.read(block_buffer, address, self.block_size),
+        };
+        match result {
+            Ok(()) => {
+                self.op.set(op);
+                self.buffer.replace(buffer);
+                Ok(())
+            }
+            Err(err) => Err((err, buffer)),
+        }
+    }
+}
+
+impl<'a> BlockDevice<'a> for NonvolatileBlock<'a> {
+    fn set_client(&self, client: &'a dyn BlockClient) {
+        self.client.set(client);
+    }
+
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn num_blocks(&self) -> usize {
+        self.num_blocks
+    }
+
+    fn read(
+        &self,
+        block: usize,
+        buffer: &'static mut [u8],
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        self.start_op(Op::Read(block), block, buffer)
+    }
+
+    fn write(
+        &self,
+        block: usize,
+        buffer: &'static mut [u8],
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        self.start_op(Op::Write(block), block, buffer)
+    }
+
+    fn erase(&self, block: usize) -> Result<(), ErrorCode> {
+        if block >= self.num_blocks {
+            Err(ErrorCode::INVAL)
+        } else {
+            Err(ErrorCode::ALREADY)
+        }
+    }
+
+    fn flush(&self) -> Result<(), ErrorCode> {
+        Err(ErrorCode::ALREADY)
+    }
+}
+
+impl NonvolatileStorageClient for NonvolatileBlock<'_> {
+    fn read_done(&self, block_buffer: &'static mut [u8], length: usize) {
+        let result = if length == self.block_size {
+            Ok(())
+        } else {
+            Err(ErrorCode::FAIL)
+        };
+        if let (Op::Read(block), Some(buffer)) = (self.op.replace(Op::Idle), self.buffer.take()) {
+            if result.is_ok() {
+                buffer[..self.block_size].copy_from_slice(block_buffer);
+            }
+            self.block_buffer.replace(block_buffer);
+            self.client
+                .map(|client| client.read_done(block, buffer, result));
+        } else {
+            self.block_buffer.replace(block_buffer);
+        }
+    }
+
+    fn write_done(&self, block_buffer: &'static mut [u8], length: usize) {
+        self.block_buffer.replace(block_buffer);
+        let result = if length == self.block_size {
+            Ok(())
+        } else {
+            Err(ErrorCode::FAIL)
+        };
+        if let (Op::Write(block), Some(buffer)) = (self.op.replace(Op::Idle), self.buffer.take()) {
+            self.client
+                .map(|client| client.write_done(block, buffer, result));
+        }
+    }
+}
@@ -0,0 +1,585 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Partitions of a block device.
+//!
+//! `MuxPartitions` reads the partition table of a block device and shares the
+//! device between `Partition`s, which are block devices covering one entry of
+//! the table each. The table is either an MBR, of which the four primary
+//! partitions are used, or a GUID partition table (GPT). Entries are
+//! identified by their index in the table, starting at 0. Extended MBR
+//! partitions and the backup GPT are not supported.
+//!
+//! The table is read by `scan()`. Until the scan has completed, partitions
+//! have no blocks and their operations fail with `OFF`. Operations of
+//! different partitions are queued and run one after the other.
+//!
+//! ```text
+//!   +-------------+ +-------------+
+//!   | Partition 0 | | Partition 1 |
+//!   +-------------+ +-------------+
+//!            |             |
+//!         +-------------------+
+//!         |  MuxPartitions    |
+//!         +-------------------+
+//!                   |
+//!         hil::block::BlockDevice
+//! ```
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::{hil, static_init};
+//!
+//! let table = static_init!([u8; 512], [0; 512]);
+//! let mux = static_init!(
+//!     capsules_extra::block::partitions::MuxPartitions<'static, Device>,
+//!     capsules_extra::block::partitions::MuxPartitions::new(device, table)
+//! );
+//! hil::block::BlockDevice::set_client(device, mux);
+//!
+//! let partition = static_init!(
+//!     capsules_extra::block::partitions::Partition<'static, Device>,
+//!     capsules_extra::block::partitions::Partition::new(mux, 0)
+//! );
+//! partition.setup();
+//! mux.scan();
+//! ```
+
+use core::cell::Cell;
+
+use kernel::collections::list::{List, ListLink, ListNode};
+use kernel::hil::block::{BlockClient, BlockDevice};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// Smallest block size holding a partition table.
+const TABLE_SIZE: usize = 512;
+/// MBR partition type of a protective MBR in front of a GPT.
+const GPT_PROTECTIVE: u8 = 0xee;
+/// Signature of a GPT header.
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+
+/// The type of a partition, as recorded in the partition table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PartitionType {
+    /// The partition type byte of an MBR entry.
+    Mbr(u8),
+    /// The partition type GUID of a GPT entry, as stored on the device.
+    Gpt([u8; 16]),
+}
+
+/// Callback for the end of a partition table scan.
+pub trait PartitionTableClient {
+    /// The partition table has been read.
+    ///
+    /// - `result`: The number of entries in the table, or `NOSUPPORT` if the
+    ///   device has no partition table and `FAIL` if the table is corrupted
+    ///   or could not be read.
+    fn scan_done(&self, result: Result<usize, ErrorCode>);
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Scan {
+    Idle,
+    Mbr,
+    GptHeader,
+    GptEntries,
+}
+
+/// Location of the GPT entries, read from the GPT header.
+#[derive(Clone, Copy, Default)]
+struct GptEntries {
+    /// The block holding the next entries.
+    block: usize,
+    /// The index of the next entry.
+    index: u32,
+    count: u32,
+    size: usize,
+    crc: u32,
+}
+
+pub struct MuxPartitions<'a, B: BlockDevice<'a>> {
+    device: &'a B,
+    partitions: List<'a, Partition<'a, B>>,
+    inflight: OptionalCell<&'a Partition<'a, B>>,
+    client: OptionalCell<&'a dyn PartitionTableClient>,
+    table: TakeCell<'static, [u8]>,
+    scan: Cell<Scan>,
+    gpt: Cell<GptEntries>,
+    crc: Cell<u32>,
+    found: Cell<usize>,
+}
+
+impl<'a, B: BlockDevice<'a>> MuxPartitions<'a, B> {
+    /// `table` is used to read the partition table and must hold a block.
+    pub fn new(device: &'a B, table: &'static mut [u8]) -> Self {
+        MuxPartitions {
+            device,
+            partitions: List::new(),
+            inflight: OptionalCell::empty(),
+            client: OptionalCell::empty(),
+            table: TakeCell::new(table),
+            scan: Cell::new(Scan::Idle),
+            gpt: Cell::new(GptEntries::default()),
+            crc: Cell::new(0),
+            found: Cell::new(0),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn PartitionTableClient) {
+        self.client.set(client);
+    }
+
+    /// Read the partition table of the device.
+    ///
+    /// Returns `BUSY` while partitions have operations in progress and `SIZE`
+    /// if the blocks of the device are too small for a partition table.
+    pub fn scan(&self) -> Result<(), ErrorCode> {
+        if self.scan.get() != Scan::Idle
+            || self.inflight.is_some()
+            || self
+                .partitions
+                .iter()
+                .any(|partition| partition.operation.get() != Op::Idle)
+        {
+            return Err(ErrorCode::BUSY);
+        }
+        let block_size = self.device.block_size();
+        if block_size < TABLE_SIZE || self.table.map_or(0, |table| table.len()) < block_size {
+            return Err(ErrorCode::SIZE);
+        }
+        for partition in self.partitions.iter() {
+            partition.clear();
+        }
+        self.found.set(0);
+        self.read_table(Scan::Mbr, 0)
+    }
+
+    fn read_table(&self, scan: Scan, block: usize) -> Result<(), ErrorCode> {
+        let table = self.table.take().ok_or(ErrorCode::NOMEM)?;
+        match self.device.read(block, table) {
+            Ok(()) => {
+                self.scan.set(scan);
+                Ok(())
+            }
+            Err((err, table)) => {
+                self.table.replace(table);
+                Err(err)
+            }
+        }
+    }
+
+    /// Record table entry `index` for the partitions using it.
+    fn found_entry(&self, index: usize, first: u64, count: u64, kind: PartitionType) {
+        self.found.set(self.found.get() + 1);
+        let (Ok(first), Ok(count)) = (usize::try_from(first), usize::try_from(count)) else {
+            return;
+        };
+        let num_blocks = self.device.num_blocks();
+        match first.checked_add(count) {
+            Some(end) if count > 0 && (num_blocks == 0 || end <= num_blocks) => {}
+            _ => return,
+        }
+        for partition in self.partitions.iter() {
+            if partition.index == index {
+                partition.first.set(first);
+                partition.count.set(count);
+                partition.kind.set(kind);
+            }
+        }
+    }
+
+    /// Handle a block of the partition table. Returns the next block to read,
+    /// or `None` when the scan has completed.
+    fn scan_block(&self, table: &[u8]) -> Result<Option<(Scan, usize)>, ErrorCode> {
+        match self.scan.get() {
+            Scan::Mbr => {
+                if table[510] != 0x55 || table[511] != 0xaa {
+                    return Err(ErrorCode::NOSUPPORT);
+                }
+                let entries = &table[446..510];
+                // Boot sectors without a partition table have code here.
+                if entries.chunks(16).any(|entry| entry[0] & 0x7f != 0) {
+                    return Err(ErrorCode::NOSUPPORT);
+                }
+                if entries.chunks(16).any(|entry| entry[4] == GPT_PROTECTIVE) {
+                    return Ok(Some((Scan::GptHeader, 1)));
+                }
+                for (index, entry) in entries.chunks(16).enumerate() {
+                    if entry[4] != 0 {
+                        let first = le32(entry, 8) as u64;
+                        let count = le32(entry, 12) as u64;
+                        self.found_entry(index, first, count, PartitionType::Mbr(entry[4]));
+                    }
+                }
+                Ok(None)
+            }
+            Scan::GptHeader => {
+                let header_size = le32(table, 12) as usize;
+                if &table[..8] != GPT_SIGNATURE || header_size < 92 || header_size > table.len() {
+                    return Err(ErrorCode::FAIL);
+                }
+                let crc = crc32(
+                    crc32(crc32(0, &table[..16]), &[0; 4]),
+                    &table[20..header_size],
+                );
+                let entries = GptEntries {
+                    block: usize::try_from(le64(table, 72)).map_err(|_| ErrorCode::FAIL)?,
+                    index: 0,
+                    count: le32(table, 80),
+                    size: le32(table, 84) as usize,
+                    crc: le32(table, 88),
+                };
+                if crc != le32(table, 16)
+                    || entries.size < 128
+                    || !entries.size.is_power_of_two()
+                    || entries.size > table.len()
+                {
+                    return Err(ErrorCode::FAIL);
+                }
+                self.gpt.set(entries);
+                self.crc.set(0);
+                if entries.count == 0 {
+                    return self.check_entries();
+                }
+                Ok(Some((Scan::GptEntries, entries.block)))
+            }
+            Scan::GptEntries => {
+                let mut gpt = self.gpt.get();
+                for entry in table.chunks_exact(gpt.size) {
+                    if gpt.index == gpt.count {
+                        break;
+                    }
+                    self.crc.set(crc32(self.crc.get(), entry));
+                    if entry[..16].iter().any(|&byte| byte != 0) {
+                        let mut guid = [0; 16];
+                        guid.copy_from_slice(&entry[..16]);
+                        let first = le64(entry, 32);
+                        let count = le64(entry, 40).wrapping_sub(first).wrapping_add(1);
+                        self.found_entry(
+                            gpt.index as usize,
+                            first,
+                            count,
+                            PartitionType::Gpt(guid),
+                        );
+                    }
+                    gpt.index += 1;
+                }
+                gpt.block += 1;
+                self.gpt.set(gpt);
+                if gpt.index < gpt.count {
+                    return Ok(Some((Scan::GptEntries, gpt.block)));
+                }
+                self.check_entries()
+            }
+            Scan::Idle => Ok(None),
+        }
+    }
+
+    fn check_entries(&self) -> Result<Option<(Scan, usize)>, ErrorCode> {
+        if self.crc.get() != self.gpt.get().crc {
+            return Err(ErrorCode::FAIL);
+        }
+        Ok(None)
+    }
+
+    fn scan_complete(&self, result: Result<(), ErrorCode>) {
+        self.scan.set(Scan::Idle);
+        let result = result.map(|()| self.found.get());
+        if result.is_err() {
+            for partition in self.partitions.iter() {
+                partition.clear();
+            }
+        }
+        self.client.map(|client| client.scan_done(result));
+        self.do_next_op();
+    }
+
+    /// Run the operation of `partition` now if the device is free, otherwise
+    /// leave it queued.
+    fn submit(&self, partition: &Partition<'a, B>) -> Result<(), ErrorCode> {
+        if self.inflight.is_some() || self.scan.get() != Scan::Idle {
+            return Ok(());
+        }
+        self.partitions
+            .iter()
+            .find(|node| core::ptr::eq(*node, partition))
+            .map_or(Err(ErrorCode::FAIL), |node| self.issue(node))
+    }
+
+    fn issue(&self, partition: &'a Partition<'a, B>) -> Result<(), ErrorCode> {
+        let first = partition.first.get();
+        let result = match partition.operation.get() {
+            Op::Read(block) | Op::Write(block) => {
+                let buffer = partition.buffer.take().ok_or(ErrorCode::NOMEM)?;
+                let result = if let Op::Read(_) = partition.operation.get() {
+                    self.device.read(first + block, buffer)
+                } else {
+                    self.device.write(first + block, buffer)
+                };
+                result.map_err(|(err, buffer)| {
+                    partition.buffer.replace(buffer);
+                    err
+                })
+            }
+            Op::Erase(block) => self.device.erase(first + block),
+            Op::Flush => self.device.flush(),
+            Op::Idle => return Ok(()),
+        };
+        if result.is_ok() {
+            self.inflight.set(partition);
+        }
+        result
+    }
+
+    /// Start the next queued operation of a partition.
+    fn do_next_op(&self) {
+        while self.inflight.is_none() && self.scan.get() == Scan::Idle {
+            let Some(partition) = self
+                .partitions
+                .iter()
+                .find(|partition| partition.operation.get() != Op::Idle)
+            else {
+                return;
+            };
+            if let Err(err) = self.issue(partition) {
+                // The operation was accepted by the partition, so report the
+                // error with a callback.
+                partition.complete(
+                    None,
+                    match err {
+                        ErrorCode::ALREADY => Ok(()),
+                        err => Err(err),
+                    },
+                );
+            }
+        }
+    }
+
+    fn device_done(&self, buffer: Option<&'static mut [u8]>, result: Result<(), ErrorCode>) {
+        if self.scan.get() != Scan::Idle {
+            let Some(table) = buffer else {
+                return;
+            };
+            let block_size = self.device.block_size();
+            let scanned = result
+                .map_err(|_| ErrorCode::FAIL)
+                .and_then(|()| self.scan_block(&table[..block_size]));
+            self.table.replace(table);
+            match scanned.and_then(|next| {
+                next.map_or(Ok(()), |(scan, block)| self.read_table(scan, block))
+                    .map(|()| next.is_none())
+            }) {
+                Ok(true) => self.scan_complete(Ok(())),
+                Ok(false) => {}
+                Err(err) => self.scan_complete(Err(err)),
+            }
+            return;
+        }
+        if let Some(partition) = self.inflight.take() {
+            partition.complete(buffer, result);
+        }
+        self.do_next_op();
+    }
+}
+
+impl<'a, B: BlockDevice<'a>> BlockClient for MuxPartitions<'a, B> {
+    fn read_done(&self, _block: usize, buffer: &'static mut [u8], result: Result<(), ErrorCode>) {
+        self.device_done(Some(buffer), result);
+    }
+
+    fn write_done(&self, _block: usize, buffer: &'static mut [u8], result: Result<(), ErrorCode>) {
+        self.device_done(Some(buffer), result);
+    }
+
+    fn erase_done(&self, _block: usize, result: Result<(), ErrorCode>) {
+        self.device_done(None, result);
+    }
+
+    fn flush_done(&self, result: Result<(), ErrorCode>) {
+        self.device_done(None, result);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Idle,
+    Read(usize),
+    Write(usize),
+    Erase(usize),
+    Flush,
+}
+
+/// A partition of a block device.
+pub struct Partition<'a, B: BlockDevice<'a>> {
+    mux: &'a MuxPartitions<'a, B>,
+    index: usize,
+    first: Cell<usize>,
+    count: Cell<usize>,
+    kind: OptionalCell<PartitionType>,
+    operation: Cell<Op>,
+    buffer: TakeCell<'static, [u8]>,
+    next: ListLink<'a, Partition<'a, B>>,
+    client: OptionalCell<&'a dyn BlockClient>,
+}
+
+impl<'a, B: BlockDevice<'a>> Partition<'a, B> {
+    /// The partition of entry `index` of the partition table.
+    pub fn new(mux: &'a MuxPartitions<'a, B>, index: usize) -> Self {
+        Partition {
+            mux,
+            index,
+            first: Cell::new(0),
+            count: Cell::new(0),
+            kind: OptionalCell::empty(),
+            operation: Cell::new(Op::Idle),
+            buffer: TakeCell::empty(),
+            next: ListLink::empty(),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn setup(&'a self) {
+        self.mux.partitions.push_head(self);
+    }
+
+    /// The type of the partition, if it has been found in the table.
+    pub fn partition_type(&self) -> Option<PartitionType> {
+        self.kind.get()
+    }
+
+    fn clear(&self) {
+        self.first.set(0);
+        self.count.set(0);
+        self.kind.clear();
+    }
+
+    fn start(&self, op: Op, block: usize) -> Result<(), ErrorCode> {
+        if self.operation.get() != Op::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        if self.count.get() == 0 {
+            return Err(ErrorCode::OFF);
+        }
+        if block >= self.count.get() {
+            return Err(ErrorCode::INVAL);
+        }
+        self.operation.set(op);
+        self.mux.submit(self).inspect_err(|_| {
+            self.operation.set(Op::Idle);
+        })
+    }
+
+    fn start_transfer(
+        &self,
+        op: Op,
+        block: usize,
+        buffer: &'static mut [u8],
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if buffer.len() < self.mux.device.block_size() {
+            return Err((ErrorCode::SIZE, buffer));
+        }
+        if self.operation.get() != Op::Idle {
+            return Err((ErrorCode::BUSY, buffer));
+        }
+        self.buffer.replace(buffer);
+        self.start(op, block).map_err(|err| {
+            let buffer = self.buffer.take().unwrap_or(&mut []);
+            (err, buffer)
+        })
+    }
+
+    fn complete(&self, buffer: Option<&'static mut [u8]>, result: Result<(), ErrorCode>) {
+        let operation = self.operation.replace(Op::Idle);
+        let buffer = buffer.or_else(|| self.buffer.take());
+        match (operation, buffer) {
+            (Op::Read(block), Some(buffer)) => {
+                self.client
+                    .map(|client| client.read_done(block, buffer, result));
+            }
+            (Op::Write(block), Some(buffer)) => {
+                self.client
+                    .map(|client| client.write_done(block, buffer, result));
+            }
+            (Op::Erase(block), _) => {
+                self.client.map(|client| client.erase_done(block, result));
+            }
+            (Op::Flush, _) => {
+                self.client.map(|client| client.flush_done(result));
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<'a, B: BlockDevice<'a>> ListNode<'a, Partition<'a, B>> for Partition<'a, B> {
+    fn next(&'a self) -> &'a ListLink<'a, Partition<'a, B>> {
+        &self.next
+    }
+}
+
+impl<'a, B: BlockDevice<'a>> BlockDevice<'a> for Partition<'a, B> {
+    fn set_client(&self, client: &'a dyn BlockClient) {
+        self.client.set(client);
+    }
+
+    fn block_size(&self) -> usize {
+        self.mux.device.block_size()
+    }
+
+    fn num_blocks(&self) -> usize {
+        self.count.get()
+    }
+
+    fn read(
+        &self,
+        block: usize,
+        buffer: &'static mut [u8],
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        self.start_transfer(Op::Read(block), block, buffer)
+    }
+
+    fn write(
+        &self,
+        block: usize,
+        buffer: &'static mut [u8],
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        self.start_transfer(Op::Write(block), block, buffer)
+    }
+
+    fn erase(&self, block: usize) -> Result<(), ErrorCode> {
+        self.start(Op::Erase(block), block)
+    }
+
+    fn flush(&self) -> Result<(), ErrorCode> {
+        self.start(Op::Flush, 0)
+    }
+}
+
+fn le32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+fn le64(data: &[u8], offset: usize) -> u64 {
+    le32(data, offset) as u64 | (le32(data, offset + 4) as u64) << 32
+}
+
+/// Continue the CRC-32 of the GPT, starting from 0, with `data`.
+fn crc32(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
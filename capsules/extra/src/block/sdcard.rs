@@ -0,0 +1,217 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Block device on an SD card.
+//!
+//! Makes the 512 byte blocks of an SD card available as a block device. The
+//! card is initialized by the first operation after it has been inserted, so
+//! `num_blocks()` is 0 until then. Operations fail with `UNINSTALLED` while
+//! no card is inserted.
+//!
+//! The adapter must be the only client of the SD card.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let block = static_init!(
+//!     capsules_extra::block::sdcard::SDCardBlock<'static, VirtualMuxAlarm<'static, Rtc>>,
+//!     capsules_extra::block::sdcard::SDCardBlock::new(sdcard)
+//! );
+//! sdcard.set_client(block);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil;
+use kernel::hil::block::{BlockClient, BlockDevice};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+use crate::sdcard::{SDCard, SDCardClient};
+
+/// Size of the blocks of SD cards.
+pub const BLOCK_SIZE: usize = 512;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Idle,
+    Read(usize),
+    Write(usize),
+}
+
+pub struct SDCardBlock<'a, A: hil::time::Alarm<'a>> {
+    sdcard: &'a SDCard<'a, A>,
+    client: OptionalCell<&'a dyn BlockClient>,
+    num_blocks: Cell<usize>,
+    op: Cell<Op>,
+    /// Whether the operation waits for the card to be initialized.
+    initializing: Cell<bool>,
+    buffer: TakeCell<'static, [u8]>,
+}
+
+impl<'a, A: hil::time::Alarm<'a>> SDCardBlock<'a, A> {
+    pub fn new(sdcard: &'a SDCard<'a, A>) -> Self {
+        SDCardBlock {
+            sdcard,
+            client: OptionalCell::empty(),
+            num_blocks: Cell::new(0),
+            op: Cell::new(Op::Idle),
+            initializing: Cell::new(false),
+            buffer: TakeCell::empty(),
+        }
+    }
+
+    fn start_op(
+        &self,
+        op: Op,
+        block: usize,
+        buffer: &'static mut [u8],
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.op.get() != Op::Idle {
+            return Err((ErrorCode::BUSY, buffer));
+        }
+        if buffer.len() < BLOCK_SIZE {
+            return Err((ErrorCode::SIZE, buffer));
+        }
+        if !self.sdcard.is_installed() {
+            return Err((ErrorCode::UNINSTALLED, buffer));
+        }
+        if !self.sdcard.is_initialized() {
+            if let Err(err) = self.sdcard.initialize() {
+                return Err((err, buffer));
+            }
+            self.op.set(op);
+            self.initializing.set(true);
+            self.buffer.replace(buffer);
+            return Ok(());
+        }
+        if block >= self.num_blocks.get() {
+            return Err((ErrorCode::INVAL, buffer));
+        }
+        self.transfer(op, buffer)?;
+        self.op.set(op);
+        Ok(())
+    }
+
+    fn transfer(
+        &self,
+        op: Op,
+        buffer: &'static mut [u8],
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        match op {
+            Op::Read(block) => self.sdcard.read_blocks(buffer, block as u32, 1),
+            Op::Write(block) => self.sdcard.write_blocks(buffer, block as u32, 1),
+            Op::Idle => Err((ErrorCode::FAIL, buffer)),
+        }
+    }
+
+    fn complete(&self, buffer: &'static mut [u8], result: Result<(), ErrorCode>) {
+        match self.op.replace(Op::Idle) {
+            Op::Read(block) => self
+                .client
+                .map(|client| client.read_done(block, buffer, result)),
+            Op::Write(block) => self
+                .client
+                .map(|client| client.write_done(block, buffer, result)),
+            Op::Idle => None,
+        };
+    }
+}
+
+impl<'a, A: hil::time::Alarm<'a>> BlockDevice<'a> for SDCardBlock<'a, A> {
+    fn set_client(&self, client: &'a dyn BlockClient) {
+        self.client.set(client);
+    }
+
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn num_blocks(&self) -> usize {
+        self.num_blocks.get()
+    }
+
+    fn read(
+        &self,
+        block: usize,
+        buffer: &'static mut [u8],
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        self.start_op(Op::Read(block), block, buffer)
+    }
+
+    fn write(
+        &self,
+        block: usize,
+        buffer: &'static mut [u8],
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        self.start_op(Op::Write(block), block, buffer)
+    }
+
+    fn erase(&self, block: usize) -> Result<(), ErrorCode> {
+        // The card erases blocks itself when they are written.
+        if self.num_blocks.get() != 0 && block >= self.num_blocks.get() {
+            Err(ErrorCode::INVAL)
+        } else {
+            Err(ErrorCode::ALREADY)
+        }
+    }
+
+    fn flush(&self) -> Result<(), ErrorCode> {
+        Err(ErrorCode::ALREADY)
+    }
+}
+
+impl<'a, A: hil::time::Alarm<'a>> SDCardClient for SDCardBlock<'a, A> {
+    fn card_detection_changed(&self, installed: bool) {
+        if !installed {
+            self.num_blocks.set(0);
+        }
+    }
+
+    fn init_done(&self, _block_size: u32, total_size: u64) {
+        let num_blocks = usize::try_from(total_size / BLOCK_SIZE as u64).unwrap_or(usize::MAX);
+        self.num_blocks.set(num_blocks);
+        if !self.initializing.replace(false) {
+            return;
+        }
+        if let Some(buffer) = self.buffer.take() {
+            let op = self.op.get();
+            let (Op::Read(block) | Op::Write(block)) = op else {
+                return;
+            };
+            let result = if block < num_blocks {
+                self.transfer(op, buffer)
+            } else {
+                Err((ErrorCode::INVAL, buffer))
+            };
+            if let Err((err, buffer)) = result {
+                self.complete(buffer, Err(err));
+            }
+        }
+    }
+
+    fn read_done(&self, data: &'static mut [u8], _len: usize) {
+        self.complete(data, Ok(()));
+    }
+
+    fn write_done(&self, buffer: &'static mut [u8]) {
+        self.complete(buffer, Ok(()));
+    }
+
+    fn error(&self, _error: u32) {
+        let buffer = if self.initializing.replace(false) {
+            self.buffer.take()
+        } else {
+            self.sdcard.take_buffer()
+        };
+        match buffer {
+            Some(buffer) => self.complete(buffer, Err(ErrorCode::FAIL)),
+            // The buffer is lost, but the device can be used again.
+            None => self.op.set(Op::Idle),
+        }
+    }
+}
@@ -0,0 +1,338 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Provides userspace with raw access to an Ethernet adapter.
+//!
+//! Processes can transmit complete Ethernet frames (starting with the
+//! destination MAC address, without the frame check sequence) and receive
+//! every frame seen by the adapter. This is meant for testing and for
+//! userspace network stacks; it does not filter frames, so it should be
+//! used together with the `MuxEthernet` virtualizer to coexist with the
+//! in-kernel IPv6 stack.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let tap_buf = static_init!([u8; 1514], [0; 1514]);
+//! let tap = static_init!(
+//!     capsules_extra::ethernet_tap::EthernetTapDriver<'static, EthernetUser<'static>>,
+//!     capsules_extra::ethernet_tap::EthernetTapDriver::new(
+//!         eth_user,
+//!         tap_buf,
+//!         board_kernel.create_grant(
+//!             capsules_extra::ethernet_tap::DRIVER_NUM,
+//!             &memory_allocation_capability
+//!         ),
+//!     )
+//! );
+//! eth_user.set_client(tap);
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! ### Command
+//!
+//! - `0`: Driver existence check.
+//! - `1`: Transmit the first `arg1` bytes of the read-only allow buffer as
+//!   an Ethernet frame. Each process can have one transmission pending;
+//!   completion is signalled through upcall `1`.
+//! - `2`: Start receiving frames into the read-write allow buffer.
+//! - `3`: Stop receiving frames.
+//!
+//! ### Allow
+//!
+//! - Read-only `0`: Frame to transmit.
+//! - Read-write `0`: Buffer that received frames are copied into.
+//!
+//! ### Upcalls
+//!
+//! - `0`: A frame was received. Arguments are the length of the frame and
+//!   the number of bytes copied into the read-write buffer, which is
+//!   smaller if the buffer was too short.
+//! - `1`: A transmission completed. Arguments are the status and the length
+//!   of the frame.
+
+use core::cmp;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::ethernet::{EthernetAdapterDatapath, EthernetAdapterDatapathClient};
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::EthernetTap as usize;
+
+/// Ids for subscribed upcalls.
+mod upcall {
+    pub const RX_FRAME: usize = 0;
+    pub const TX_FRAME: usize = 1;
+    /// The number of upcalls the kernel stores for this grant
+    pub const COUNT: u8 = 2;
+}
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    pub const TX_FRAME: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    pub const RX_FRAME: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+#[derive(Default)]
+pub struct App {
+    receive_enabled: bool,
+    // Length of the frame waiting to be transmitted, if any.
+    tx_pending: Option<u16>,
+}
+
+pub struct EthernetTapDriver<'a, E: EthernetAdapterDatapath<'a>> {
+    iface: &'a E,
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    tx_buf: TakeCell<'static, [u8]>,
+    current_app: OptionalCell<ProcessId>,
+}
+
+impl<'a, E: EthernetAdapterDatapath<'a>> EthernetTapDriver<'a, E> {
+    pub fn new(
+        iface: &'a E,
+        tx_buf: &'static mut [u8],
+        apps: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+    ) -> EthernetTapDriver<'a, E> {
+        EthernetTapDriver {
+            iface,
+            apps,
+            tx_buf: TakeCell::new(tx_buf),
+            current_app: OptionalCell::empty(),
+        }
+    }
+
+    /// Enables reception on the adapter while at least one process wants
+    /// to receive frames.
+    fn update_receive(&self) {
+        let receive = self
+            .apps
+            .iter()
+            .any(|app| app.enter(|app, _| app.receive_enabled));
+        if receive {
+            self.iface.enable_receive();
+        } else {
+            self.iface.disable_receive();
+        }
+    }
+
+    /// Copies the pending frame of `processid` into the kernel buffer and
+    /// hands it to the adapter.
+    fn transmit(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        let tx_buf = self.tx_buf.take().ok_or(ErrorCode::BUSY)?;
+        let res = self
+            .apps
+            .enter(processid, |app, kernel_data| {
+                let len = app.tx_pending.take().ok_or(ErrorCode::FAIL)?;
+                kernel_data
+                    .get_readonly_processbuffer(ro_allow::TX_FRAME)
+                    .and_then(|frame| {
+                        frame.enter(|frame| {
+                            let len_usize = usize::from(len);
+                            if len_usize > frame.len() || len_usize > tx_buf.len() {
+                                return Err(ErrorCode::SIZE);
+                            }
+                            frame[..len_usize].copy_to_slice(&mut tx_buf[..len_usize]);
+                            Ok(len)
+                        })
+                    })
+                    .unwrap_or(Err(ErrorCode::RESERVE))
+            })
+            .unwrap_or_else(|err| Err(err.into()));
+
+        let len = match res {
+            Ok(len) => len,
+            Err(e) => {
+                self.tx_buf.replace(tx_buf);
+                return Err(e);
+            }
+        };
+
+        match self.iface.transmit_frame(tx_buf, len, 0) {
+            Ok(()) => {
+                self.current_app.set(processid);
+                Ok(())
+            }
+            Err((e, buf)) => {
+                self.tx_buf.replace(buf);
+                Err(e)
+            }
+        }
+    }
+
+    /// Starts the next pending transmission, reporting immediate failures
+    /// through the transmit upcall.
+    fn transmit_next(&self) {
+        while self.current_app.is_none() {
+            let next = self.apps.iter().find_map(|app| {
+                let processid = app.processid();
+                app.enter(|app, _| app.tx_pending.map(|len| (processid, len)))
+            });
+            let (processid, len) = match next {
+                Some(next) => next,
+                None => return,
+            };
+            if let Err(e) = self.transmit(processid) {
+                let _ = self.apps.enter(processid, |app, kernel_data| {
+                    app.tx_pending = None;
+                    kernel_data
+                        .schedule_upcall(
+                            upcall::TX_FRAME,
+                            (
+                                kernel::errorcode::into_statuscode(Err(e)),
+                                usize::from(len),
+                                0,
+                            ),
+                        )
+                        .ok();
+                });
+            }
+        }
+    }
+}
+
+impl<'a, E: EthernetAdapterDatapath<'a>> EthernetAdapterDatapathClient
+    for EthernetTapDriver<'a, E>
+{
+    fn transmit_frame_done(
+        &self,
+        err: Result<(), ErrorCode>,
+        frame_buffer: &'static mut [u8],
+        len: u16,
+        _transmission_identifier: usize,
+        _timestamp: Option<u64>,
+    ) {
+        self.tx_buf.replace(frame_buffer);
+        self.current_app.take().map(|processid| {
+            let _ = self.apps.enter(processid, |_, kernel_data| {
+                kernel_data
+                    .schedule_upcall(
+                        upcall::TX_FRAME,
+                        (kernel::errorcode::into_statuscode(err), usize::from(len), 0),
+                    )
+                    .ok();
+            });
+        });
+        self.transmit_next();
+    }
+
+    fn received_frame(&self, frame: &[u8], _timestamp: Option<u64>) {
+        self.apps.each(|_, app, kernel_data| {
+            if !app.receive_enabled {
+                return;
+            }
+            let copied = kernel_data
+                .get_readwrite_processbuffer(rw_allow::RX_FRAME)
+                .and_then(|buf| {
+                    buf.mut_enter(|buf| {
+                        let len = cmp::min(frame.len(), buf.len());
+                        buf[..len].copy_from_slice(&frame[..len]);
+                        len
+                    })
+                })
+                .unwrap_or(0);
+            kernel_data
+                .schedule_upcall(upcall::RX_FRAME, (frame.len(), copied, 0))
+                .ok();
+        });
+    }
+}
+
+impl<'a, E: EthernetAdapterDatapath<'a>> SyscallDriver for EthernetTapDriver<'a, E> {
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Return Ok(()) if this driver is included on the platform.
+    /// - `1`: Transmit a frame of `arg1` bytes from the read-only buffer.
+    /// - `2`: Enable reception.
+    /// - `3`: Disable reception.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => {
+                let len = match u16::try_from(arg1) {
+                    Ok(len) if len > 0 => len,
+                    _ => return CommandReturn::failure(ErrorCode::SIZE),
+                };
+                let res = self
+                    .apps
+                    .enter(processid, |app, _| {
+                        if app.tx_pending.is_some() || self.current_app.contains(&processid) {
+                            Err(ErrorCode::BUSY)
+                        } else {
+                            app.tx_pending = Some(len);
+                            Ok(())
+                        }
+                    })
+                    .unwrap_or_else(|err| Err(err.into()));
+                if let Err(e) = res {
+                    return CommandReturn::failure(e);
+                }
+
+                if self.current_app.is_none() {
+                    // The adapter is idle, so report errors synchronously.
+                    if let Err(e) = self.transmit(processid) {
+                        let _ = self.apps.enter(processid, |app, _| app.tx_pending = None);
+                        return CommandReturn::failure(e);
+                    }
+                }
+                CommandReturn::success()
+            }
+
+            2 | 3 => {
+                let res = self.apps.enter(processid, |app, _| {
+                    app.receive_enabled = command_num == 2;
+                });
+                match res {
+                    Ok(()) => {
+                        self.update_receive();
+                        CommandReturn::success()
+                    }
+                    Err(err) => CommandReturn::failure(err.into()),
+                }
+            }
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
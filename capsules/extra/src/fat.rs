@@ -0,0 +1,2249 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! FAT filesystem on a block device.
+//!
+//! Implements `hil::filesystem::FileSystem` for FAT12, FAT16 and FAT32
+//! volumes on a `hil::block::BlockDevice` with 512 byte blocks, so SD cards
+//! can be exchanged with other computers. The volume either covers the whole
+//! device or is the first FAT partition of its MBR. Volumes in other
+//! partitions are used through a `Partition` of
+//! `capsules_extra::block::partitions`.
+//!
+//! Files of the kernel (directory 0) are kept in the root directory. The files
+//! of each application are kept in a top-level directory named by its storage
+//! identifier as eight hexadecimal digits, for example `0000A1B2`. File names
+//! are paths in these directories and can be long filenames. Missing
+//! directories along a path are created with the file.
+//!
+//! ```text
+//! /
+//! +-- kernel.log
+//! +-- 0000A1B2/
+//!     +-- readings/
+//!         +-- 2024 temperatures.csv
+//! ```
+//!
+//! Two sectors are cached and dirty sectors are written back, and the device
+//! flushed, at the end of every operation. A FAT volume is not safe against
+//! power failures though. Reads and writes transfer at most one sector,
+//! callers continue with more calls. The capsule keeps track of 16 open files:
+//! opening more files invalidates the `FileId`s of the files used the longest
+//! time ago. The volume must be mounted again after an operation failed with
+//! `UNINSTALLED` because the card was removed.
+//!
+//! The capsule must be the only client of the block device.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::{hil, static_init};
+//!
+//! let fat = static_init!(
+//!     capsules_extra::fat::FatFs<'static, SDCardBlock<'static, Alarm>>,
+//!     capsules_extra::fat::FatFs::new(sdcard_block, sector_buffer0, sector_buffer1)
+//! );
+//! hil::block::BlockDevice::set_client(sdcard_block, fat);
+//! fat.register();
+//! fat.set_client(fs_client);
+//! fat.mount();
+//! ```
+
+use core::cell::Cell;
+use core::cmp;
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::block::{BlockClient, BlockDevice};
+use kernel::hil::filesystem::{DirEntry, FileId, FileInfo, FileSystem, FileSystemClient};
+use kernel::storage_permissions::StoragePermissions;
+use kernel::utilities::cells::{MapCell, OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::ErrorCode;
+
+/// Size of a sector, the only one supported.
+pub const SECTOR_SIZE: usize = 512;
+
+/// Number of files that can be open at the same time.
+const OPEN_FILES: usize = 16;
+/// Bits of a `FileId` holding the index of the open file.
+const OPEN_FILE_BITS: usize = 4;
+/// Directory entries per sector.
+const ENTRIES: u32 = 16;
+/// Size of a directory entry.
+const ENTRY_SIZE: usize = 32;
+/// Directories have at most this many entries.
+const MAX_ENTRIES: u32 = 65536;
+/// Characters in a long filename entry.
+const LFN_CHARS: usize = 13;
+/// Offsets of the characters in a long filename entry.
+const LFN_OFFSETS: [usize; LFN_CHARS] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+/// Longest name, in UTF-16 code units.
+const NAME_MAX: usize = 255;
+/// Long filenames have at most 20 entries.
+const LFN_MAX: usize = 20 * LFN_CHARS;
+
+const ATTR_READ_ONLY: u8 = 0x01;
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_ARCHIVE: u8 = 0x20;
+const ATTR_LFN: u8 = 0x0f;
+/// Short name flags for a lower case base and extension.
+const CASE_BASE: u8 = 0x08;
+const CASE_EXT: u8 = 0x10;
+/// First byte of free entries, and of entries after the last one.
+const DELETED: u8 = 0xe5;
+const END: u8 = 0x00;
+/// Flag of the long filename entry holding the end of the name.
+const LFN_LAST: u8 = 0x40;
+/// Files are dated 2024-01-01 as there is no clock.
+const DATE: u16 = (44 << 9) | (1 << 5) | 1;
+/// MBR partition types of FAT volumes.
+const FAT_PARTITIONS: [u8; 6] = [0x01, 0x04, 0x06, 0x0b, 0x0c, 0x0e];
+/// Characters allowed in short names besides letters and digits.
+const SHORT_SPECIAL: &[u8] = b"$%'-_@~`!(){}^#&";
+/// Characters not allowed in names.
+const FORBIDDEN: &[u8] = b"\"*/:<>?\\|";
+const HEX: &[u8; 16] = b"0123456789ABCDEF";
+
+#[derive(Clone, Copy, PartialEq)]
+enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+/// Layout of the mounted volume. All sectors are blocks of the device.
+#[derive(Clone, Copy)]
+struct Volume {
+    fat_type: FatType,
+    fat_start: u32,
+    fat_sectors: u32,
+    fats: u32,
+    /// The fixed root directory of FAT12 and FAT16.
+    root_start: u32,
+    root_entries: u32,
+    /// The first cluster of the root directory of FAT32.
+    root_cluster: u32,
+    data_start: u32,
+    cluster_sectors: u32,
+    clusters: u32,
+}
+
+impl Volume {
+    const fn new() -> Self {
+        Volume {
+            fat_type: FatType::Fat16,
+            fat_start: 0,
+            fat_sectors: 0,
+            fats: 0,
+            root_start: 0,
+            root_entries: 0,
+            root_cluster: 0,
+            data_start: 0,
+            cluster_sectors: 1,
+            clusters: 0,
+        }
+    }
+
+    /// Parse the boot sector of a volume starting at sector `start`.
+    fn parse(boot: &[u8], start: u32) -> Option<Self> {
+        if !is_boot_sector(boot) {
+            return None;
+        }
+        let cluster_sectors = u32::from(boot[13]);
+        let reserved = le16(boot, 14);
+        let fats = u32::from(boot[16]);
+        let root_entries = le16(boot, 17);
+        let total = match le16(boot, 19) {
+            0 => le32(boot, 32),
+            total => total,
+        };
+        let fat_sectors = match le16(boot, 22) {
+            0 => le32(boot, 36),
+            sectors => sectors,
+        };
+        let root_sectors = (root_entries * ENTRY_SIZE as u32).div_ceil(SECTOR_SIZE as u32);
+        let fats_sectors = fats.checked_mul(fat_sectors)?;
+        let data = fats_sectors.checked_add(reserved + root_sectors)?;
+        // Sectors of clusters are computed without further checks, which
+        // cannot overflow if the last sector of the volume does not.
+        start.checked_add(total)?;
+        let clusters = total.checked_sub(data)?.checked_div(cluster_sectors)?;
+        let fat_type = match clusters {
+            0 => return None,
+            1..=4084 => FatType::Fat12,
+            4085..=65524 => FatType::Fat16,
+            _ => FatType::Fat32,
+        };
+        let root_cluster = le32(boot, 44);
+        if (fat_type == FatType::Fat32) != (root_entries == 0)
+            || (fat_type == FatType::Fat32 && root_cluster < 2)
+        {
+            return None;
+        }
+        // Never use clusters past the end of the FAT.
+        let fat_bytes = fat_sectors.checked_mul(SECTOR_SIZE as u32)?;
+        let fat_entries = match fat_type {
+            FatType::Fat12 => fat_bytes.checked_mul(2)? / 3,
+            FatType::Fat16 => fat_bytes / 2,
+            FatType::Fat32 => fat_bytes / 4,
+        };
+        let fat_start = start.checked_add(reserved)?;
+        Some(Volume {
+            fat_type,
+            fat_start,
+            fat_sectors,
+            fats,
+            root_start: fat_start.checked_add(fats_sectors)?,
+            root_entries,
+            root_cluster,
+            data_start: start.checked_add(data)?,
+            cluster_sectors,
+            clusters: cmp::min(clusters, fat_entries.saturating_sub(2)),
+        })
+    }
+
+    /// Whether `cluster` is not a cluster of the volume, which ends chains.
+    fn is_end(&self, cluster: u32) -> bool {
+        cluster < 2 || cluster >= self.clusters + 2
+    }
+
+    /// The FAT value ending a chain.
+    fn end(&self) -> u32 {
+        match self.fat_type {
+            FatType::Fat12 => 0xfff,
+            FatType::Fat16 => 0xffff,
+            FatType::Fat32 => 0x0fff_ffff,
+        }
+    }
+
+    /// The directory that is the root, 0 for the fixed root directory.
+    fn root(&self) -> u32 {
+        match self.fat_type {
+            FatType::Fat32 => self.root_cluster,
+            _ => 0,
+        }
+    }
+
+    fn cluster_sector(&self, cluster: u32) -> u32 {
+        self.data_start + (cluster - 2) * self.cluster_sectors
+    }
+
+    fn cluster_bytes(&self) -> usize {
+        self.cluster_sectors as usize * SECTOR_SIZE
+    }
+}
+
+/// The location of a directory entry.
+#[derive(Clone, Copy, Default, PartialEq)]
+struct Location {
+    sector: u32,
+    index: u32,
+}
+
+/// A directory entry.
+#[derive(Clone, Copy, Default)]
+struct Found {
+    entry: Location,
+    /// The first entry of the long filename, or `entry` without one.
+    lfn: Location,
+    attr: u8,
+    cluster: u32,
+    size: u32,
+}
+
+/// An open file.
+#[derive(Clone, Copy, Default)]
+struct Handle {
+    entry: Location,
+    lfn: Location,
+    dir: u32,
+    generation: u32,
+    used: u32,
+    open: bool,
+}
+
+/// A position in a directory.
+#[derive(Clone, Copy, Default)]
+struct DirPos {
+    /// The current cluster, unused in the fixed root directory.
+    cluster: u32,
+    /// The entry in the current cluster, or in the fixed root directory.
+    slot: u32,
+    /// The entry in the directory.
+    index: u32,
+}
+
+/// A cached sector.
+struct CacheSlot {
+    buffer: TakeCell<'static, [u8]>,
+    sector: Cell<u32>,
+    valid: Cell<bool>,
+    dirty: Cell<bool>,
+    used: Cell<u32>,
+}
+
+impl CacheSlot {
+    fn new(buffer: &'static mut [u8; SECTOR_SIZE]) -> Self {
+        CacheSlot {
+            buffer: TakeCell::new(buffer),
+            sector: Cell::new(0),
+            valid: Cell::new(false),
+            dirty: Cell::new(false),
+            used: Cell::new(0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Io {
+    Idle,
+    Read(usize),
+    Write(usize),
+    Flush,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Mount,
+    Open,
+    Stat,
+    Read,
+    Write,
+    Unlink,
+    ReadDir,
+}
+
+/// Phases of operations. Each phase can be restarted after the sectors it
+/// needs have been loaded, and uses at most two sectors at a time.
+#[derive(Clone, Copy, PartialEq)]
+enum Phase {
+    Idle,
+    /// The operation starts from a deferred call.
+    Start,
+    MountInit,
+    MountBoot,
+    MountVolume,
+    MountFsInfo,
+    /// Look up the directory of the app.
+    BaseDir,
+    /// Look up the next component of the path.
+    NextComponent,
+    Lookup,
+    Alloc,
+    Zero,
+    MakeDots,
+    CreateEntries,
+    Listing,
+    /// Load the directory entry of an open file.
+    File,
+    Walk,
+    Allocated,
+    Data,
+    UpdateEntry,
+    UnlinkEntries,
+    FreeChain,
+    Flush,
+    /// Flush the writes buffered by the device.
+    Sync,
+    Finish,
+}
+
+/// Why a phase stopped.
+enum Stop {
+    /// Waiting for the block device.
+    Pending,
+    Error(ErrorCode),
+}
+
+impl From<ErrorCode> for Stop {
+    fn from(err: ErrorCode) -> Self {
+        Stop::Error(err)
+    }
+}
+
+type Step<T> = Result<T, Stop>;
+
+pub struct FatFs<'a, B: BlockDevice<'a>> {
+    device: &'a B,
+    client: OptionalCell<&'a dyn FileSystemClient>,
+    deferred_call: DeferredCall,
+    cache: [CacheSlot; 2],
+    io: Cell<Io>,
+    clock: Cell<u32>,
+    volume: Cell<Volume>,
+    mounted: Cell<bool>,
+
+    // The current operation.
+    op: Cell<Op>,
+    phase: Cell<Phase>,
+    result: Cell<Result<usize, ErrorCode>>,
+    buffer: MapCell<SubSliceMut<'static, u8>>,
+    permissions: OptionalCell<StoragePermissions>,
+    dir_id: Cell<u32>,
+    create: Cell<bool>,
+    file: Cell<FileId>,
+    offset: Cell<usize>,
+    length: Cell<usize>,
+    current: Cell<Found>,
+    listed: Cell<Option<DirEntry>>,
+
+    // Path lookups.
+    path_pos: Cell<usize>,
+    component: Cell<(usize, usize)>,
+    app_dir: Cell<bool>,
+    last: Cell<bool>,
+    hex: Cell<[u8; 8]>,
+
+    // Directory scans.
+    dir: Cell<u32>,
+    pos: Cell<DirPos>,
+    creating: Cell<bool>,
+    found: Cell<Option<Found>>,
+    lfn: MapCell<[u16; LFN_MAX]>,
+    lfn_len: Cell<usize>,
+    lfn_next: Cell<u8>,
+    lfn_sum: Cell<u8>,
+    lfn_start: Cell<Location>,
+    free_start: Cell<DirPos>,
+    free_count: Cell<u32>,
+    dir_end: Cell<bool>,
+    tails: Cell<u16>,
+    basis: Cell<([u8; 11], usize)>,
+    short: Cell<([u8; 11], u8)>,
+    needed: Cell<u32>,
+    written: Cell<u32>,
+
+    // Cluster chains.
+    walk: Cell<(u32, u32, u32)>,
+    new_first: Cell<bool>,
+    alloc_prev: Cell<u32>,
+    alloc_cluster: Cell<u32>,
+    alloc_left: Cell<u32>,
+    alloc_stage: Cell<u8>,
+    alloc_hint: Cell<u32>,
+    alloc_then: Cell<Phase>,
+    zeroed: Cell<u32>,
+    zero_then: Cell<Phase>,
+    fat_copy: Cell<u32>,
+    chain: Cell<u32>,
+    chain_next: OptionalCell<u32>,
+    chain_left: Cell<u32>,
+    unlink_pos: Cell<Location>,
+    checksum: Cell<u8>,
+
+    handles: [Cell<Handle>; OPEN_FILES],
+    generation: Cell<u32>,
+}
+
+impl<'a, B: BlockDevice<'a>> FatFs<'a, B> {
+    pub fn new(
+        device: &'a B,
+        buffer0: &'static mut [u8; SECTOR_SIZE],
+        buffer1: &'static mut [u8; SECTOR_SIZE],
+    ) -> Self {
+        FatFs {
+            device,
+            client: OptionalCell::empty(),
+            deferred_call: DeferredCall::new(),
+            cache: [CacheSlot::new(buffer0), CacheSlot::new(buffer1)],
+            io: Cell::new(Io::Idle),
+            clock: Cell::new(0),
+            volume: Cell::new(Volume::new()),
+            mounted: Cell::new(false),
+            op: Cell::new(Op::Mount),
+            phase: Cell::new(Phase::Idle),
+            result: Cell::new(Ok(0)),
+            buffer: MapCell::empty(),
+            permissions: OptionalCell::empty(),
+            dir_id: Cell::new(0),
+            create: Cell::new(false),
+            file: Cell::new(0),
+            offset: Cell::new(0),
+            length: Cell::new(0),
+            current: Cell::new(Found::default()),
+            listed: Cell::new(None),
+            path_pos: Cell::new(0),
+            component: Cell::new((0, 0)),
+            app_dir: Cell::new(false),
+            last: Cell::new(false),
+            hex: Cell::new([0; 8]),
+            dir: Cell::new(0),
+            pos: Cell::new(DirPos::default()),
+            creating: Cell::new(false),
+            found: Cell::new(None),
+            lfn: MapCell::new([0; LFN_MAX]),
+            lfn_len: Cell::new(0),
+            lfn_next: Cell::new(0xff),
+            lfn_sum: Cell::new(0),
+            lfn_start: Cell::new(Location::default()),
+            free_start: Cell::new(DirPos::default()),
+            free_count: Cell::new(0),
+            dir_end: Cell::new(false),
+            tails: Cell::new(0),
+            basis: Cell::new(([0; 11], 0)),
+            short: Cell::new(([0; 11], 0)),
+            needed: Cell::new(0),
+            written: Cell::new(0),
+            walk: Cell::new((0, 0, 0)),
+            new_first: Cell::new(false),
+            alloc_prev: Cell::new(0),
+            alloc_cluster: Cell::new(0),
+            alloc_left: Cell::new(0),
+            alloc_stage: Cell::new(0),
+            alloc_hint: Cell::new(2),
+            alloc_then: Cell::new(Phase::Idle),
+            zeroed: Cell::new(0),
+            zero_then: Cell::new(Phase::Idle),
+            fat_copy: Cell::new(0),
+            chain: Cell::new(0),
+            chain_next: OptionalCell::empty(),
+            chain_left: Cell::new(0),
+            unlink_pos: Cell::new(Location::default()),
+            checksum: Cell::new(0),
+            handles: core::array::from_fn(|_| Cell::new(Handle::default())),
+            generation: Cell::new(0),
+        }
+    }
+
+    fn tick(&self) -> u32 {
+        let now = self.clock.get().wrapping_add(1);
+        self.clock.set(now);
+        now
+    }
+
+    // Sector cache.
+
+    fn cached(&self, sector: u32) -> Option<usize> {
+        (0..self.cache.len())
+            .find(|&slot| self.cache[slot].valid.get() && self.cache[slot].sector.get() == sector)
+    }
+
+    /// The slot to load a sector into: an empty one or the least recently
+    /// used one.
+    fn victim(&self) -> usize {
+        (0..self.cache.len())
+            .min_by_key(|&slot| (self.cache[slot].valid.get(), self.cache[slot].used.get()))
+            .unwrap_or(0)
+    }
+
+    fn invalidate_cache(&self) {
+        for slot in self.cache.iter() {
+            slot.valid.set(false);
+            slot.dirty.set(false);
+        }
+    }
+
+    /// Start writing back a dirty slot.
+    fn write_back(&self, slot: usize) -> Stop {
+        let Some(buffer) = self.cache[slot].buffer.take() else {
+            self.cache[slot].dirty.set(false);
+            return ErrorCode::NOMEM.into();
+        };
+        let sector = self.cache[slot].sector.get() as usize;
+        match self.device.write(sector, buffer) {
+            Ok(()) => {
+                self.io.set(Io::Write(slot));
+                Stop::Pending
+            }
+            Err((err, buffer)) => {
+                self.cache[slot].buffer.replace(buffer);
+                err.into()
+            }
+        }
+    }
+
+    /// Get the slot holding `sector`, loading it if needed.
+    fn load(&self, sector: u32) -> Step<usize> {
+        if let Some(slot) = self.cached(sector) {
+            self.cache[slot].used.set(self.tick());
+            return Ok(slot);
+        }
+        let slot = self.victim();
+        if self.cache[slot].dirty.get() {
+            return Err(self.write_back(slot));
+        }
+        let buffer = self.cache[slot].buffer.take().ok_or(ErrorCode::NOMEM)?;
+        self.cache[slot].valid.set(false);
+        self.cache[slot].sector.set(sector);
+        if let Err((err, buffer)) = self.device.read(sector as usize, buffer) {
+            self.cache[slot].buffer.replace(buffer);
+            return Err(err.into());
+        }
+        self.io.set(Io::Read(slot));
+        Err(Stop::Pending)
+    }
+
+    /// Get a slot holding `sector` filled with zeros, without reading it.
+    fn load_zeroed(&self, sector: u32) -> Step<usize> {
+        let slot = match self.cached(sector) {
+            Some(slot) => slot,
+            None => {
+                let slot = self.victim();
+                if self.cache[slot].dirty.get() {
+                    return Err(self.write_back(slot));
+                }
+                slot
+            }
+        };
+        self.cache[slot]
+            .buffer
+            .map(|buffer| buffer.fill(0))
+            .ok_or(ErrorCode::NOMEM)?;
+        self.cache[slot].sector.set(sector);
+        self.cache[slot].valid.set(true);
+        self.cache[slot].dirty.set(true);
+        self.cache[slot].used.set(self.tick());
+        Ok(slot)
+    }
+
+    fn read_slot<R>(&self, slot: usize, f: impl FnOnce(&[u8]) -> R) -> Step<R> {
+        self.cache[slot]
+            .buffer
+            .map(|buffer| f(buffer))
+            .ok_or(Stop::Error(ErrorCode::FAIL))
+    }
+
+    fn modify_slot<R>(&self, slot: usize, f: impl FnOnce(&mut [u8]) -> R) -> Step<R> {
+        self.cache[slot].dirty.set(true);
+        self.cache[slot]
+            .buffer
+            .map(f)
+            .ok_or(Stop::Error(ErrorCode::FAIL))
+    }
+
+    fn entry(&self, slot: usize, index: u32) -> Step<[u8; ENTRY_SIZE]> {
+        self.read_slot(slot, |buffer| {
+            let at = index as usize * ENTRY_SIZE;
+            let mut entry = [0; ENTRY_SIZE];
+            entry.copy_from_slice(&buffer[at..at + ENTRY_SIZE]);
+            entry
+        })
+    }
+
+    fn modify_entry(&self, slot: usize, index: u32, f: impl FnOnce(&mut [u8])) -> Step<()> {
+        self.modify_slot(slot, |buffer| {
+            let at = index as usize * ENTRY_SIZE;
+            f(&mut buffer[at..at + ENTRY_SIZE]);
+        })
+    }
+
+    /// Start writing back the next dirty slot.
+    fn flush(&self) -> Step<()> {
+        match (0..self.cache.len()).find(|&slot| self.cache[slot].dirty.get()) {
+            Some(slot) => Err(self.write_back(slot)),
+            None => Ok(()),
+        }
+    }
+
+    // FAT.
+
+    fn fat_location(&self, cluster: u32) -> (u32, usize) {
+        let volume = self.volume.get();
+        let offset = match volume.fat_type {
+            FatType::Fat12 => cluster + cluster / 2,
+            FatType::Fat16 => cluster * 2,
+            FatType::Fat32 => cluster * 4,
+        };
+        (
+            volume.fat_start + offset / SECTOR_SIZE as u32,
+            offset as usize % SECTOR_SIZE,
+        )
+    }
+
+    /// Get the FAT entry of `cluster`, the next cluster of its chain.
+    fn get_fat(&self, cluster: u32) -> Step<u32> {
+        let (sector, offset) = self.fat_location(cluster);
+        let slot = self.load(sector)?;
+        match self.volume.get().fat_type {
+            FatType::Fat12 => {
+                // Entries can cross a sector boundary.
+                let (high_slot, high_offset) = if offset == SECTOR_SIZE - 1 {
+                    (self.load(sector + 1)?, 0)
+                } else {
+                    (slot, offset + 1)
+                };
+                let low = self.read_slot(slot, |buffer| buffer[offset])?;
+                let high = self.read_slot(high_slot, |buffer| buffer[high_offset])?;
+                let value = u16::from_le_bytes([low, high]);
+                Ok(u32::from(if cluster & 1 == 1 {
+                    value >> 4
+                } else {
+                    value & 0xfff
+                }))
+            }
+            FatType::Fat16 => self.read_slot(slot, |buffer| le16(buffer, offset)),
+            FatType::Fat32 => self.read_slot(slot, |buffer| le32(buffer, offset) & 0x0fff_ffff),
+        }
+    }
+
+    /// Set the FAT entry of `cluster` in every copy of the FAT. Continues
+    /// with the next copy when restarted.
+    fn set_fat(&self, cluster: u32, value: u32) -> Step<()> {
+        let volume = self.volume.get();
+        let (sector, offset) = self.fat_location(cluster);
+        while self.fat_copy.get() < volume.fats {
+            let sector = sector + self.fat_copy.get() * volume.fat_sectors;
+            let slot = self.load(sector)?;
+            match volume.fat_type {
+                FatType::Fat12 => {
+                    let (high_slot, high_offset) = if offset == SECTOR_SIZE - 1 {
+                        (self.load(sector + 1)?, 0)
+                    } else {
+                        (slot, offset + 1)
+                    };
+                    let value = value as u16 & 0xfff;
+                    if cluster & 1 == 1 {
+                        self.modify_slot(slot, |buffer| {
+                            buffer[offset] = (buffer[offset] & 0x0f) | (value << 4) as u8;
+                        })?;
+                        self.modify_slot(high_slot, |buffer| {
+                            buffer[high_offset] = (value >> 4) as u8;
+                        })?;
+                    } else {
+                        self.modify_slot(slot, |buffer| buffer[offset] = value as u8)?;
+                        self.modify_slot(high_slot, |buffer| {
+                            buffer[high_offset] = (buffer[high_offset] & 0xf0) | (value >> 8) as u8;
+                        })?;
+                    }
+                }
+                FatType::Fat16 => self.modify_slot(slot, |buffer| {
+                    buffer[offset..offset + 2].copy_from_slice(&(value as u16).to_le_bytes());
+                })?,
+                FatType::Fat32 => self.modify_slot(slot, |buffer| {
+                    // The top four bits are reserved.
+                    let value = (le32(buffer, offset) & 0xf000_0000) | (value & 0x0fff_ffff);
+                    buffer[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+                })?,
+            }
+            self.fat_copy.set(self.fat_copy.get() + 1);
+        }
+        self.fat_copy.set(0);
+        Ok(())
+    }
+
+    /// Allocate a cluster, linking it after `prev` unless it is 0, and
+    /// continue with `then`. The cluster is in `alloc_cluster`.
+    fn start_alloc(&self, prev: u32, then: Phase) {
+        let volume = self.volume.get();
+        let hint = self.alloc_hint.get();
+        self.alloc_prev.set(prev);
+        self.alloc_cluster
+            .set(if volume.is_end(hint) { 2 } else { hint });
+        self.alloc_left.set(volume.clusters);
+        self.alloc_stage.set(0);
+        self.alloc_then.set(then);
+        self.zeroed.set(0);
+        self.phase.set(Phase::Alloc);
+    }
+
+    fn phase_alloc(&self) -> Step<()> {
+        let volume = self.volume.get();
+        loop {
+            let cluster = self.alloc_cluster.get();
+            match self.alloc_stage.get() {
+                0 => {
+                    if self.alloc_left.get() == 0 {
+                        return Err(ErrorCode::NOMEM.into());
+                    }
+                    if self.get_fat(cluster)? == 0 {
+                        self.alloc_stage.set(1);
+                    } else {
+                        let next = cluster + 1;
+                        self.alloc_cluster
+                            .set(if volume.is_end(next) { 2 } else { next });
+                        self.alloc_left.set(self.alloc_left.get() - 1);
+                    }
+                }
+                1 => {
+                    self.set_fat(cluster, volume.end())?;
+                    self.alloc_stage.set(2);
+                }
+                2 => {
+                    if self.alloc_prev.get() != 0 {
+                        self.set_fat(self.alloc_prev.get(), cluster)?;
+                    }
+                    self.alloc_stage.set(3);
+                }
+                _ => {
+                    self.alloc_hint.set(cluster + 1);
+                    self.phase.set(self.alloc_then.get());
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Fill the allocated cluster with zeros and continue with `zero_then`.
+    fn phase_zero(&self) -> Step<()> {
+        let volume = self.volume.get();
+        let first = volume.cluster_sector(self.alloc_cluster.get());
+        while self.zeroed.get() < volume.cluster_sectors {
+            self.load_zeroed(first + self.zeroed.get())?;
+            self.zeroed.set(self.zeroed.get() + 1);
+        }
+        self.phase.set(self.zero_then.get());
+        Ok(())
+    }
+
+    // Directories.
+
+    /// Start scanning directory `dir` at entry `index`.
+    fn dir_rewind(&self, dir: u32, index: u32) {
+        self.dir.set(dir);
+        self.pos.set(DirPos {
+            cluster: dir,
+            slot: index,
+            index,
+        });
+    }
+
+    /// The location of the current entry, or `None` at the end of the
+    /// directory.
+    fn dir_location(&self) -> Step<Option<Location>> {
+        let volume = self.volume.get();
+        let mut pos = self.pos.get();
+        if pos.index >= MAX_ENTRIES {
+            return Ok(None);
+        }
+        if self.dir.get() == 0 {
+            if pos.slot >= volume.root_entries {
+                return Ok(None);
+            }
+            return Ok(Some(Location {
+                sector: volume.root_start + pos.slot / ENTRIES,
+                index: pos.slot % ENTRIES,
+            }));
+        }
+        let cluster_entries = volume.cluster_sectors * ENTRIES;
+        while pos.slot >= cluster_entries {
+            let next = self.get_fat(pos.cluster)?;
+            if volume.is_end(next) {
+                return Ok(None);
+            }
+            pos.cluster = next;
+            pos.slot -= cluster_entries;
+            self.pos.set(pos);
+        }
+        Ok(Some(Location {
+            sector: volume.cluster_sector(pos.cluster) + pos.slot / ENTRIES,
+            index: pos.slot % ENTRIES,
+        }))
+    }
+
+    fn dir_advance(&self) {
+        let mut pos = self.pos.get();
+        pos.slot += 1;
+        pos.index += 1;
+        self.pos.set(pos);
+    }
+
+    /// The location of the entry after `location`.
+    fn next_location(&self, location: Location) -> Step<Location> {
+        if location.index + 1 < ENTRIES {
+            return Ok(Location {
+                index: location.index + 1,
+                ..location
+            });
+        }
+        let volume = self.volume.get();
+        let sector = location.sector + 1;
+        if location.sector < volume.data_start
+            || (sector - volume.data_start) % volume.cluster_sectors != 0
+        {
+            return Ok(Location { sector, index: 0 });
+        }
+        let cluster = (location.sector - volume.data_start) / volume.cluster_sectors + 2;
+        let next = self.get_fat(cluster)?;
+        if volume.is_end(next) {
+            return Err(ErrorCode::FAIL.into());
+        }
+        Ok(Location {
+            sector: volume.cluster_sector(next),
+            index: 0,
+        })
+    }
+
+    fn found_entry(&self, entry: &[u8], location: Location, lfn: Location) -> Found {
+        let high = if self.volume.get().fat_type == FatType::Fat32 {
+            le16(entry, 20) << 16
+        } else {
+            0
+        };
+        Found {
+            entry: location,
+            lfn,
+            attr: entry[11],
+            cluster: high | le16(entry, 26),
+            size: le32(entry, 28),
+        }
+    }
+
+    fn lfn_reset(&self) {
+        self.lfn_next.set(0xff);
+    }
+
+    /// Add a long filename entry to the name being collected.
+    fn lfn_add(&self, entry: &[u8], location: Location) {
+        let seq = entry[0] & 0x1f;
+        if entry[0] & LFN_LAST != 0 {
+            if seq == 0 || usize::from(seq) * LFN_CHARS > LFN_MAX {
+                self.lfn_reset();
+                return;
+            }
+            self.lfn_sum.set(entry[13]);
+            self.lfn_start.set(location);
+            self.lfn_len.set(usize::from(seq) * LFN_CHARS);
+        } else if seq == 0 || seq != self.lfn_next.get() || entry[13] != self.lfn_sum.get() {
+            self.lfn_reset();
+            return;
+        }
+        self.lfn.map(|lfn| {
+            let start = usize::from(seq - 1) * LFN_CHARS;
+            for (i, &offset) in LFN_OFFSETS.iter().enumerate() {
+                lfn[start + i] = le16(entry, offset) as u16;
+            }
+            if entry[0] & LFN_LAST != 0 {
+                // The name ends with a 0 unless it fills the last entry.
+                let end = lfn[start..start + LFN_CHARS].iter().position(|&c| c == 0);
+                self.lfn_len.set(start + end.unwrap_or(LFN_CHARS));
+            }
+        });
+        self.lfn_next.set(seq - 1);
+    }
+
+    /// Whether the collected long filename belongs to short entry `entry`.
+    fn lfn_valid(&self, entry: &[u8]) -> bool {
+        self.lfn_next.get() == 0 && self.lfn_sum.get() == checksum(&entry[..11])
+    }
+
+    /// Call `f` with the name being looked up.
+    fn with_target<R>(&self, f: impl FnOnce(&[u8]) -> R) -> R {
+        if self.app_dir.get() {
+            return f(&self.hex.get());
+        }
+        let (start, end) = self.component.get();
+        match self.buffer.take() {
+            Some(mut path) => {
+                let result = f(&path.as_slice()[start..end]);
+                self.buffer.replace(path);
+                result
+            }
+            None => f(&[]),
+        }
+    }
+
+    fn matches(&self, entry: &[u8]) -> bool {
+        let lfn = self.lfn_valid(entry);
+        self.with_target(|target| {
+            (lfn && self.lfn.map_or(false, |units| {
+                lfn_equals(target, &units[..self.lfn_len.get()])
+            })) || short_equals(target, entry)
+        })
+    }
+
+    /// Start looking up the target in the current directory.
+    fn start_lookup(&self) -> Step<()> {
+        let creating = self.op.get() == Op::Open && self.create.get();
+        let needed = self.with_target(|name| {
+            if name == b"." || name == b".." {
+                return Err(ErrorCode::INVAL);
+            }
+            if !creating {
+                return Ok(0);
+            }
+            let units = name_units(name)?;
+            match short_name(name) {
+                Some(short) => {
+                    self.short.set(short);
+                    Ok(1)
+                }
+                None => {
+                    self.basis.set(basis(name));
+                    Ok(units.div_ceil(LFN_CHARS) as u32 + 1)
+                }
+            }
+        })?;
+        self.creating.set(creating);
+        self.needed.set(needed);
+        self.found.set(None);
+        self.free_count.set(0);
+        self.dir_end.set(false);
+        self.tails.set(0);
+        self.lfn_reset();
+        self.dir_rewind(self.dir.get(), 0);
+        self.phase.set(Phase::Lookup);
+        Ok(())
+    }
+
+    fn phase_base_dir(&self) -> Step<()> {
+        let dir = self.dir_id.get();
+        let permissions = self.permissions.get().ok_or(ErrorCode::FAIL)?;
+        if !permissions.check_read_permission(dir) {
+            return Err(ErrorCode::NOSUPPORT.into());
+        }
+        if self.create.get() && permissions.get_write_id() != Some(dir) {
+            return Err(ErrorCode::NOSUPPORT.into());
+        }
+        self.dir_rewind(self.volume.get().root(), 0);
+        self.path_pos.set(0);
+        if dir == 0 {
+            self.phase.set(Phase::NextComponent);
+            return Ok(());
+        }
+        let mut hex = [0; 8];
+        for (i, digit) in hex.iter_mut().enumerate() {
+            *digit = HEX[(dir >> (28 - 4 * i)) as usize & 0xf];
+        }
+        self.hex.set(hex);
+        self.app_dir.set(true);
+        self.last.set(false);
+        self.start_lookup()
+    }
+
+    fn phase_next_component(&self) -> Step<()> {
+        self.app_dir.set(false);
+        let pos = self.path_pos.get();
+        let (start, end, last) = self
+            .buffer
+            .map(|path| {
+                let path = path.as_slice();
+                let start = pos + path[pos..].iter().take_while(|&&c| c == b'/').count();
+                let end = start + path[start..].iter().take_while(|&&c| c != b'/').count();
+                (start, end, path[end..].iter().all(|&c| c == b'/'))
+            })
+            .ok_or(ErrorCode::FAIL)?;
+        if start == end {
+            // The whole path has been looked up.
+            if self.op.get() != Op::ReadDir {
+                return Err(ErrorCode::INVAL.into());
+            }
+            let cursor = u32::try_from(self.offset.get()).unwrap_or(MAX_ENTRIES);
+            self.dir_rewind(self.dir.get(), cursor);
+            self.lfn_reset();
+            self.phase.set(Phase::Listing);
+            return Ok(());
+        }
+        self.component.set((start, end));
+        self.last.set(last);
+        self.path_pos.set(end);
+        self.start_lookup()
+    }
+
+    fn phase_lookup(&self) -> Step<()> {
+        loop {
+            let Some(location) = self.dir_location()? else {
+                return self.lookup_done();
+            };
+            let slot = self.load(location.sector)?;
+            let entry = self.entry(slot, location.index)?;
+            if entry[0] == END || entry[0] == DELETED {
+                if entry[0] == END {
+                    // There are no more entries, only free space.
+                    if !self.creating.get() {
+                        return self.lookup_done();
+                    }
+                    self.dir_end.set(true);
+                }
+                self.lfn_reset();
+                if self.free_count.get() == 0 {
+                    self.free_start.set(self.pos.get());
+                }
+                self.free_count.set(self.free_count.get() + 1);
+                if self.dir_end.get() && self.free_count.get() >= self.needed.get() {
+                    return self.lookup_done();
+                }
+            } else {
+                self.free_count.set(0);
+                if entry[11] & 0x3f == ATTR_LFN {
+                    self.lfn_add(&entry, location);
+                } else {
+                    if entry[11] & ATTR_VOLUME_ID == 0 && entry[0] != b'.' && self.matches(&entry) {
+                        let lfn = if self.lfn_valid(&entry) {
+                            self.lfn_start.get()
+                        } else {
+                            location
+                        };
+                        self.found
+                            .set(Some(self.found_entry(&entry, location, lfn)));
+                        return self.lookup_done();
+                    }
+                    if self.creating.get() {
+                        self.note_tail(&entry);
+                    }
+                    self.lfn_reset();
+                }
+            }
+            self.dir_advance();
+        }
+    }
+
+    /// Record the numeric tail of a short name like the one to create.
+    fn note_tail(&self, entry: &[u8]) {
+        let (basis, len) = self.basis.get();
+        let k = cmp::min(len, 6);
+        if entry[..k] == basis[..k]
+            && entry[k] == b'~'
+            && (b'1'..=b'9').contains(&entry[k + 1])
+            && (k + 2 == 8 || entry[k + 2] == b' ')
+        {
+            self.tails
+                .set(self.tails.get() | 1 << (entry[k + 1] - b'0'));
+        }
+    }
+
+    fn lookup_done(&self) -> Step<()> {
+        let volume = self.volume.get();
+        let directory = self.app_dir.get() || !self.last.get() || self.op.get() == Op::ReadDir;
+        if let Some(found) = self.found.take() {
+            if (found.attr & ATTR_DIRECTORY != 0) != directory {
+                return Err(ErrorCode::NOSUPPORT.into());
+            }
+            if directory {
+                let dir = if found.cluster == 0 {
+                    volume.root()
+                } else {
+                    found.cluster
+                };
+                self.dir_rewind(dir, 0);
+                self.phase.set(Phase::NextComponent);
+            } else {
+                let file = self.register(found);
+                self.done(Ok(file));
+            }
+            return Ok(());
+        }
+
+        if !self.creating.get() {
+            if self.op.get() == Op::ReadDir && self.app_dir.get() {
+                // The directory of an app without files.
+                self.done(Ok(0));
+                return Ok(());
+            }
+            return Err(ErrorCode::NOSUPPORT.into());
+        }
+        if self.free_count.get() < self.needed.get() {
+            if self.dir.get() == 0 || self.pos.get().index >= MAX_ENTRIES {
+                return Err(ErrorCode::NOMEM.into());
+            }
+            // Add a cluster to the directory and continue the scan in it.
+            self.zero_then.set(Phase::Lookup);
+            self.start_alloc(self.pos.get().cluster, Phase::Zero);
+            return Ok(());
+        }
+
+        if self.needed.get() > 1 {
+            self.short.set((self.numbered_short(), 0));
+            let len = self.with_target(|name| {
+                self.lfn.map_or(0, |lfn| {
+                    let mut len = 0;
+                    for (unit, c) in lfn.iter_mut().zip(Utf8Units::new(name)) {
+                        *unit = c;
+                        len += 1;
+                    }
+                    len
+                })
+            });
+            self.lfn_len.set(len);
+        }
+        self.written.set(0);
+        if directory {
+            self.zero_then.set(Phase::MakeDots);
+            self.start_alloc(0, Phase::Zero);
+        } else {
+            self.pos.set(self.free_start.get());
+            self.phase.set(Phase::CreateEntries);
+        }
+        Ok(())
+    }
+
+    /// The short name of a long name, with a numeric tail not used in the
+    /// directory.
+    fn numbered_short(&self) -> [u8; 11] {
+        let (mut short, len) = self.basis.get();
+        let tails = self.tails.get();
+        match (1..10u8).find(|n| tails & (1 << n) == 0) {
+            Some(n) => {
+                let k = cmp::min(len, 6);
+                short[k] = b'~';
+                short[k + 1] = b'0' + n;
+                short[k + 2..8].fill(b' ');
+            }
+            None => {
+                // Use a hash of the name once the tails run out.
+                let hash = self.with_target(|name| {
+                    name.iter().fold(0x811c_9dc5u32, |h, &c| {
+                        (h ^ u32::from(c)).wrapping_mul(0x0100_0193)
+                    })
+                });
+                let k = cmp::min(len, 2);
+                for i in 0..4 {
+                    short[k + i] = HEX[(hash >> (12 - 4 * i)) as usize & 0xf];
+                }
+                short[k + 4] = b'~';
+                short[k + 5] = b'1';
+                short[k + 6..8].fill(b' ');
+            }
+        }
+        short
+    }
+
+    /// Write "." and ".." to a new directory.
+    fn phase_make_dots(&self) -> Step<()> {
+        let volume = self.volume.get();
+        let cluster = self.alloc_cluster.get();
+        let parent = if self.dir.get() == volume.root() {
+            0
+        } else {
+            self.dir.get()
+        };
+        let slot = self.load(volume.cluster_sector(cluster))?;
+        self.modify_entry(slot, 0, |entry| {
+            entry.copy_from_slice(&short_entry(b".          ", 0, ATTR_DIRECTORY, cluster));
+        })?;
+        self.modify_entry(slot, 1, |entry| {
+            entry.copy_from_slice(&short_entry(b"..         ", 0, ATTR_DIRECTORY, parent));
+        })?;
+        self.pos.set(self.free_start.get());
+        self.phase.set(Phase::CreateEntries);
+        Ok(())
+    }
+
+    /// Write the long filename entries and the short entry of a new file or
+    /// directory.
+    fn phase_create_entries(&self) -> Step<()> {
+        let needed = self.needed.get();
+        let directory = self.app_dir.get() || !self.last.get();
+        let (short, case) = self.short.get();
+        let sum = checksum(&short);
+        loop {
+            let n = self.written.get();
+            let location = self.dir_location()?.ok_or(Stop::Error(ErrorCode::FAIL))?;
+            let slot = self.load(location.sector)?;
+            if n == 0 {
+                self.lfn_start.set(location);
+            }
+            if n + 1 < needed {
+                let seq = needed - 1 - n;
+                let len = self.lfn_len.get();
+                self.lfn
+                    .map(|lfn| {
+                        self.modify_entry(slot, location.index, |entry| {
+                            entry.fill(0);
+                            entry[0] = seq as u8 | if n == 0 { LFN_LAST } else { 0 };
+                            entry[11] = ATTR_LFN;
+                            entry[13] = sum;
+                            let start = (seq as usize - 1) * LFN_CHARS;
+                            for (i, &offset) in LFN_OFFSETS.iter().enumerate() {
+                                // The name ends with a 0, the rest is padding.
+                                let unit = match start + i {
+                                    at if at < len => lfn[at],
+                                    at if at == len => 0,
+                                    _ => 0xffff,
+                                };
+                                entry[offset..offset + 2].copy_from_slice(&unit.to_le_bytes());
+                            }
+                        })
+                    })
+                    .unwrap_or(Err(Stop::Error(ErrorCode::FAIL)))?;
+                self.written.set(n + 1);
+                self.dir_advance();
+                continue;
+            }
+
+            let (attr, cluster) = if directory {
+                (ATTR_DIRECTORY, self.alloc_cluster.get())
+            } else {
+                (ATTR_ARCHIVE, 0)
+            };
+            self.modify_entry(slot, location.index, |entry| {
+                entry.copy_from_slice(&short_entry(&short, case, attr, cluster));
+            })?;
+            if directory {
+                self.dir_rewind(cluster, 0);
+                self.phase.set(Phase::NextComponent);
+            } else {
+                let found = Found {
+                    entry: location,
+                    lfn: self.lfn_start.get(),
+                    attr,
+                    cluster,
+                    size: 0,
+                };
+                let file = self.register(found);
+                self.done(Ok(file));
+            }
+            return Ok(());
+        }
+    }
+
+    /// Return the first entry at or after the cursor.
+    fn phase_listing(&self) -> Step<()> {
+        loop {
+            let Some(location) = self.dir_location()? else {
+                return self.list_done(None);
+            };
+            let slot = self.load(location.sector)?;
+            let entry = self.entry(slot, location.index)?;
+            match entry[0] {
+                END => return self.list_done(None),
+                DELETED => self.lfn_reset(),
+                _ if entry[11] & 0x3f == ATTR_LFN => self.lfn_add(&entry, location),
+                _ if entry[11] & ATTR_VOLUME_ID != 0 || entry[0] == b'.' => self.lfn_reset(),
+                _ => return self.list_done(Some(entry)),
+            }
+            self.dir_advance();
+        }
+    }
+
+    fn list_done(&self, entry: Option<[u8; ENTRY_SIZE]>) -> Step<()> {
+        let Some(entry) = entry else {
+            self.done(Ok(0));
+            return Ok(());
+        };
+        let lfn = self.lfn_valid(&entry);
+        let len = self
+            .buffer
+            .map(|name| {
+                name.reset();
+                let out = name.as_slice();
+                let len = if lfn {
+                    self.lfn.map_or(Err(ErrorCode::FAIL), |units| {
+                        utf16_to_utf8(&units[..self.lfn_len.get()], out)
+                    })
+                } else {
+                    let (short, len) = format_short(&entry);
+                    out.get_mut(..len)
+                        .map(|out| {
+                            out.copy_from_slice(&short[..len]);
+                            len
+                        })
+                        .ok_or(ErrorCode::SIZE)
+                };
+                if let Ok(len) = len {
+                    name.slice(..len);
+                }
+                len
+            })
+            .unwrap_or(Err(ErrorCode::FAIL))?;
+        self.listed.set(Some(DirEntry {
+            file: None,
+            info: FileInfo {
+                dir: self.dir_id.get(),
+                size: le32(&entry, 28) as usize,
+            },
+            directory: entry[11] & ATTR_DIRECTORY != 0,
+            next: self.pos.get().index as usize + 1,
+        }));
+        self.done(Ok(len));
+        Ok(())
+    }
+
+    // Open files.
+
+    fn register(&self, found: Found) -> FileId {
+        let dir = self.dir_id.get();
+        let used = self.tick();
+        let slot = match self.handles.iter().position(|handle| {
+            let handle = handle.get();
+            handle.open && handle.entry == found.entry && handle.dir == dir
+        }) {
+            Some(slot) => slot,
+            None => {
+                let slot = (0..OPEN_FILES)
+                    .min_by_key(|&slot| {
+                        let handle = self.handles[slot].get();
+                        (handle.open, handle.used)
+                    })
+                    .unwrap_or(0);
+                let generation = self.generation.get().wrapping_add(1) & 0x0fff_ffff;
+                self.generation.set(generation);
+                self.handles[slot].set(Handle {
+                    entry: found.entry,
+                    lfn: found.lfn,
+                    dir,
+                    generation,
+                    used,
+                    open: true,
+                });
+                slot
+            }
+        };
+        let handle = self.handles[slot].get();
+        self.handles[slot].set(Handle { used, ..handle });
+        slot | (handle.generation as usize) << OPEN_FILE_BITS
+    }
+
+    fn handle(&self, file: FileId) -> Result<Handle, ErrorCode> {
+        let slot = file & (OPEN_FILES - 1);
+        let handle = self.handles[slot].get();
+        if !handle.open || handle.generation as usize != file >> OPEN_FILE_BITS {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+        self.handles[slot].set(Handle {
+            used: self.tick(),
+            ..handle
+        });
+        Ok(handle)
+    }
+
+    fn phase_file(&self) -> Step<()> {
+        let handle = self.handle(self.file.get())?;
+        let op = self.op.get();
+        let permissions = self.permissions.get().ok_or(ErrorCode::FAIL)?;
+        let allowed = match op {
+            Op::Stat | Op::Read => permissions.check_read_permission(handle.dir),
+            _ => permissions.check_modify_permission(handle.dir),
+        };
+        if !allowed {
+            return Err(ErrorCode::NOSUPPORT.into());
+        }
+        self.dir_id.set(handle.dir);
+
+        let slot = self.load(handle.entry.sector)?;
+        let entry = self.entry(slot, handle.entry.index)?;
+        if entry[0] == END
+            || entry[0] == DELETED
+            || entry[11] & (ATTR_DIRECTORY | ATTR_VOLUME_ID) != 0
+            || (op != Op::Read && op != Op::Stat && entry[11] & ATTR_READ_ONLY != 0)
+        {
+            return Err(ErrorCode::NOSUPPORT.into());
+        }
+        let found = self.found_entry(&entry, handle.entry, handle.lfn);
+        self.current.set(found);
+        let size = found.size as usize;
+        let offset = self.offset.get();
+        let available = self.buffer.map_or(0, |buffer| buffer.len());
+        let sector_left = SECTOR_SIZE - offset % SECTOR_SIZE;
+        match op {
+            Op::Stat => self.done(Ok(size)),
+            Op::Read => {
+                if offset >= size || available == 0 {
+                    self.done(Ok(0));
+                } else {
+                    self.length
+                        .set(cmp::min(available, cmp::min(sector_left, size - offset)));
+                    self.start_walk();
+                }
+            }
+            Op::Write => {
+                if offset > size {
+                    return Err(ErrorCode::INVAL.into());
+                }
+                let length = cmp::min(available, sector_left);
+                if offset + length > u32::MAX as usize {
+                    return Err(ErrorCode::NOMEM.into());
+                }
+                if length == 0 {
+                    self.done(Ok(0));
+                } else {
+                    self.length.set(length);
+                    self.start_walk();
+                }
+            }
+            _ => {
+                self.unlink_pos.set(handle.lfn);
+                self.checksum.set(checksum(&entry[..11]));
+                self.phase.set(Phase::UnlinkEntries);
+            }
+        }
+        Ok(())
+    }
+
+    /// Walk the chain of the current file to the cluster of the offset,
+    /// continuing from the last walk when possible.
+    fn start_walk(&self) {
+        let first = self.current.get().cluster;
+        let target = (self.offset.get() / self.volume.get().cluster_bytes()) as u32;
+        let (walked, index, _) = self.walk.get();
+        if walked != first || first == 0 || index > target {
+            self.walk.set((first, 0, first));
+        }
+        self.phase.set(Phase::Walk);
+    }
+
+    fn phase_walk(&self) -> Step<()> {
+        let volume = self.volume.get();
+        let write = self.op.get() == Op::Write;
+        let target = (self.offset.get() / volume.cluster_bytes()) as u32;
+        let (first, mut index, mut cluster) = self.walk.get();
+        if first == 0 {
+            // Files are empty until the first write allocates a cluster.
+            if !write {
+                return Err(ErrorCode::FAIL.into());
+            }
+            self.start_alloc(0, Phase::Allocated);
+            return Ok(());
+        }
+        while index < target {
+            let next = self.get_fat(cluster)?;
+            if volume.is_end(next) {
+                if write && index + 1 == target {
+                    self.start_alloc(cluster, Phase::Allocated);
+                    return Ok(());
+                }
+                return Err(ErrorCode::FAIL.into());
+            }
+            index += 1;
+            cluster = next;
+            self.walk.set((first, index, cluster));
+        }
+        self.phase.set(Phase::Data);
+        Ok(())
+    }
+
+    fn phase_allocated(&self) -> Step<()> {
+        let cluster = self.alloc_cluster.get();
+        let (first, index, _) = self.walk.get();
+        if first == 0 {
+            self.new_first.set(true);
+            self.walk.set((cluster, 0, cluster));
+        } else {
+            self.walk.set((first, index + 1, cluster));
+        }
+        self.phase.set(Phase::Data);
+        Ok(())
+    }
+
+    fn phase_data(&self) -> Step<()> {
+        let volume = self.volume.get();
+        let offset = self.offset.get();
+        let length = self.length.get();
+        let (_, _, cluster) = self.walk.get();
+        let sector = volume.cluster_sector(cluster)
+            + ((offset % volume.cluster_bytes()) / SECTOR_SIZE) as u32;
+        let at = offset % SECTOR_SIZE;
+        if self.op.get() == Op::Read {
+            let slot = self.load(sector)?;
+            self.read_slot(slot, |data| {
+                self.buffer.map(|buffer| {
+                    buffer.as_slice()[..length].copy_from_slice(&data[at..at + length]);
+                })
+            })?;
+            self.done(Ok(length));
+        } else {
+            // Sectors that are overwritten or past the end are not read.
+            let slot = if at == 0
+                && (length == SECTOR_SIZE || offset >= self.current.get().size as usize)
+            {
+                self.load_zeroed(sector)?
+            } else {
+                self.load(sector)?
+            };
+            self.modify_slot(slot, |data| {
+                self.buffer.map(|buffer| {
+                    data[at..at + length].copy_from_slice(&buffer.as_slice()[..length]);
+                })
+            })?;
+            self.phase.set(Phase::UpdateEntry);
+        }
+        Ok(())
+    }
+
+    fn phase_update_entry(&self) -> Step<()> {
+        let found = self.current.get();
+        let end = (self.offset.get() + self.length.get()) as u32;
+        let first = self.new_first.get().then(|| self.walk.get().0);
+        let slot = self.load(found.entry.sector)?;
+        self.modify_entry(slot, found.entry.index, |entry| {
+            let size = cmp::max(le32(entry, 28), end);
+            entry[28..32].copy_from_slice(&size.to_le_bytes());
+            if let Some(first) = first {
+                entry[20..22].copy_from_slice(&((first >> 16) as u16).to_le_bytes());
+                entry[26..28].copy_from_slice(&(first as u16).to_le_bytes());
+            }
+            entry[11] |= ATTR_ARCHIVE;
+            entry[22..24].fill(0);
+            entry[24..26].copy_from_slice(&DATE.to_le_bytes());
+        })?;
+        self.done(Ok(self.length.get()));
+        Ok(())
+    }
+
+    /// Mark the long filename entries and the short entry of the file free.
+    fn phase_unlink_entries(&self) -> Step<()> {
+        let found = self.current.get();
+        loop {
+            let location = self.unlink_pos.get();
+            let slot = self.load(location.sector)?;
+            if location == found.entry {
+                self.modify_entry(slot, location.index, |entry| entry[0] = DELETED)?;
+                break;
+            }
+            let entry = self.entry(slot, location.index)?;
+            if entry[0] != DELETED
+                && entry[11] & 0x3f == ATTR_LFN
+                && entry[13] == self.checksum.get()
+            {
+                self.modify_entry(slot, location.index, |entry| entry[0] = DELETED)?;
+            }
+            self.unlink_pos.set(self.next_location(location)?);
+        }
+
+        for handle in self.handles.iter() {
+            if handle.get().entry == found.entry {
+                handle.set(Handle::default());
+            }
+        }
+        self.walk.set((0, 0, 0));
+        self.chain.set(found.cluster);
+        self.chain_next.clear();
+        self.chain_left.set(self.volume.get().clusters);
+        self.phase.set(Phase::FreeChain);
+        Ok(())
+    }
+
+    fn phase_free_chain(&self) -> Step<()> {
+        let volume = self.volume.get();
+        loop {
+            let cluster = self.chain.get();
+            if volume.is_end(cluster) || self.chain_left.get() == 0 {
+                break;
+            }
+            let next = match self.chain_next.get() {
+                Some(next) => next,
+                None => {
+                    let next = self.get_fat(cluster)?;
+                    self.chain_next.set(next);
+                    next
+                }
+            };
+            self.set_fat(cluster, 0)?;
+            self.chain.set(next);
+            self.chain_next.clear();
+            self.chain_left.set(self.chain_left.get() - 1);
+        }
+        self.done(Ok(0));
+        Ok(())
+    }
+
+    // Mounting.
+
+    fn phase_mount_init(&self) -> Step<()> {
+        self.mounted.set(false);
+        self.invalidate_cache();
+        for handle in self.handles.iter() {
+            handle.set(Handle::default());
+        }
+        self.walk.set((0, 0, 0));
+        if self.device.block_size() != SECTOR_SIZE {
+            return Err(ErrorCode::NOSUPPORT.into());
+        }
+        self.phase.set(Phase::MountBoot);
+        Ok(())
+    }
+
+    /// Find the volume in the first sector of the card.
+    fn phase_mount_boot(&self) -> Step<()> {
+        let slot = self.load(0)?;
+        let start = self.read_slot(slot, |boot| {
+            if is_boot_sector(boot) {
+                return Ok(0);
+            }
+            if boot[510] != 0x55 || boot[511] != 0xaa {
+                return Err(ErrorCode::NOSUPPORT);
+            }
+            (0..4)
+                .map(|i| &boot[446 + 16 * i..446 + 16 * (i + 1)])
+                .find(|partition| FAT_PARTITIONS.contains(&partition[4]))
+                .map(|partition| le32(partition, 8))
+                .ok_or(ErrorCode::NOSUPPORT)
+        })??;
+        self.offset.set(start as usize);
+        self.phase.set(Phase::MountVolume);
+        Ok(())
+    }
+
+    fn phase_mount_volume(&self) -> Step<()> {
+        let start = self.offset.get() as u32;
+        let slot = self.load(start)?;
+        let (volume, fsinfo) = self
+            .read_slot(slot, |boot| {
+                let fsinfo = le16(boot, 48);
+                Volume::parse(boot, start).map(|volume| (volume, fsinfo))
+            })?
+            .ok_or(ErrorCode::NOSUPPORT)?;
+        self.volume.set(volume);
+        self.alloc_hint.set(2);
+        // The free cluster count of FAT32 is not kept up to date, mark it
+        // unknown.
+        if volume.fat_type == FatType::Fat32 && fsinfo > 0 && fsinfo < volume.fat_start - start {
+            self.offset.set((start + fsinfo) as usize);
+            self.phase.set(Phase::MountFsInfo);
+        } else {
+            self.done(Ok(0));
+        }
+        Ok(())
+    }
+
+    fn phase_mount_fsinfo(&self) -> Step<()> {
+        let slot = self.load(self.offset.get() as u32)?;
+        let known = self.read_slot(slot, |fsinfo| {
+            le32(fsinfo, 0) == 0x4161_5252
+                && le32(fsinfo, 484) == 0x6141_7272
+                && (le32(fsinfo, 488) != u32::MAX || le32(fsinfo, 492) != u32::MAX)
+        })?;
+        if known {
+            self.modify_slot(slot, |fsinfo| fsinfo[488..496].fill(0xff))?;
+        }
+        self.done(Ok(0));
+        Ok(())
+    }
+
+    // Operations.
+
+    fn start(&self, op: Op) -> Result<(), ErrorCode> {
+        if self.phase.get() != Phase::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        if op != Op::Mount && !self.mounted.get() {
+            return Err(ErrorCode::OFF);
+        }
+        self.op.set(op);
+        self.result.set(Ok(0));
+        self.listed.set(None);
+        self.new_first.set(false);
+        self.create.set(false);
+        self.fat_copy.set(0);
+        self.phase.set(Phase::Start);
+        self.deferred_call.set();
+        Ok(())
+    }
+
+    /// Finish the operation with `result` once dirty sectors are written.
+    fn done(&self, result: Result<usize, ErrorCode>) {
+        self.result.set(result);
+        self.phase.set(Phase::Flush);
+    }
+
+    fn phase_flush(&self) -> Step<()> {
+        match self.flush() {
+            Ok(()) => self.phase.set(Phase::Sync),
+            Err(Stop::Pending) => return Err(Stop::Pending),
+            Err(Stop::Error(err)) => {
+                self.result.set(Err(err));
+                self.phase.set(Phase::Finish);
+            }
+        }
+        Ok(())
+    }
+
+    fn phase_sync(&self) -> Step<()> {
+        self.phase.set(Phase::Finish);
+        match self.device.flush() {
+            Ok(()) => {
+                self.io.set(Io::Flush);
+                Err(Stop::Pending)
+            }
+            Err(ErrorCode::ALREADY) => Ok(()),
+            Err(err) => {
+                self.result.set(Err(err));
+                Ok(())
+            }
+        }
+    }
+
+    fn run(&self) {
+        while self.io.get() == Io::Idle {
+            let step = match self.phase.get() {
+                Phase::Idle | Phase::Start => return,
+                Phase::MountInit => self.phase_mount_init(),
+                Phase::MountBoot => self.phase_mount_boot(),
+                Phase::MountVolume => self.phase_mount_volume(),
+                Phase::MountFsInfo => self.phase_mount_fsinfo(),
+                Phase::BaseDir => self.phase_base_dir(),
+                Phase::NextComponent => self.phase_next_component(),
+                Phase::Lookup => self.phase_lookup(),
+                Phase::Alloc => self.phase_alloc(),
+                Phase::Zero => self.phase_zero(),
+                Phase::MakeDots => self.phase_make_dots(),
+                Phase::CreateEntries => self.phase_create_entries(),
+                Phase::Listing => self.phase_listing(),
+                Phase::File => self.phase_file(),
+                Phase::Walk => self.phase_walk(),
+                Phase::Allocated => self.phase_allocated(),
+                Phase::Data => self.phase_data(),
+                Phase::UpdateEntry => self.phase_update_entry(),
+                Phase::UnlinkEntries => self.phase_unlink_entries(),
+                Phase::FreeChain => self.phase_free_chain(),
+                Phase::Flush => self.phase_flush(),
+                Phase::Sync => self.phase_sync(),
+                Phase::Finish => {
+                    self.finish();
+                    return;
+                }
+            };
+            match step {
+                Ok(()) => {}
+                Err(Stop::Pending) => return,
+                Err(Stop::Error(err)) => self.done(Err(err)),
+            }
+        }
+    }
+
+    /// Continue the operation after a transfer of the device.
+    fn io_done(&self, result: Result<(), ErrorCode>) {
+        match result {
+            Ok(()) => self.run(),
+            Err(err) => {
+                if self.phase.get() != Phase::Idle {
+                    self.result.set(Err(err));
+                    self.finish();
+                }
+            }
+        }
+    }
+
+    fn finish(&self) {
+        self.phase.set(Phase::Idle);
+        let result = self.result.get();
+        if result == Err(ErrorCode::UNINSTALLED) {
+            // Mount again after a card is inserted.
+            self.mounted.set(false);
+            self.invalidate_cache();
+        }
+        match self.op.get() {
+            Op::Mount => {
+                self.mounted.set(result.is_ok());
+                self.client
+                    .map(|client| client.mount_done(result.map(|_| ())));
+            }
+            Op::Open => {
+                if let Some(name) = self.buffer.take() {
+                    self.client.map(|client| client.open_done(result, name));
+                }
+            }
+            Op::Stat => {
+                let result = result.map(|size| FileInfo {
+                    dir: self.dir_id.get(),
+                    size,
+                });
+                self.client.map(|client| client.stat_done(result));
+            }
+            Op::Read => {
+                if let Some(buffer) = self.buffer.take() {
+                    self.client.map(|client| client.read_done(result, buffer));
+                }
+            }
+            Op::Write => {
+                if let Some(buffer) = self.buffer.take() {
+                    self.client.map(|client| client.write_done(result, buffer));
+                }
+            }
+            Op::Unlink => {
+                self.client
+                    .map(|client| client.unlink_done(result.map(|_| ())));
+            }
+            Op::ReadDir => {
+                let result = result.map(|_| self.listed.take());
+                if let Some(name) = self.buffer.take() {
+                    self.client.map(|client| client.read_dir_done(result, name));
+                }
+            }
+        }
+    }
+}
+
+impl<'a, B: BlockDevice<'a>> FileSystem<'a> for FatFs<'a, B> {
+    fn set_client(&self, client: &'a dyn FileSystemClient) {
+        self.client.set(client);
+    }
+
+    fn mount(&self) -> Result<(), ErrorCode> {
+        self.start(Op::Mount)
+    }
+
+    fn open(
+        &self,
+        dir: u32,
+        name: SubSliceMut<'static, u8>,
+        create: bool,
+        permissions: StoragePermissions,
+    ) -> Result<(), (ErrorCode, SubSliceMut<'static, u8>)> {
+        if let Err(err) = self.start(Op::Open) {
+            return Err((err, name));
+        }
+        self.dir_id.set(dir);
+        self.create.set(create);
+        self.permissions.set(permissions);
+        self.buffer.replace(name);
+        Ok(())
+    }
+
+    fn stat(&self, file: FileId, permissions: StoragePermissions) -> Result<(), ErrorCode> {
+        self.start(Op::Stat)?;
+        self.file.set(file);
+        self.offset.set(0);
+        self.permissions.set(permissions);
+        Ok(())
+    }
+
+    fn read(
+        &self,
+        file: FileId,
+        offset: usize,
+        buffer: SubSliceMut<'static, u8>,
+        permissions: StoragePermissions,
+    ) -> Result<(), (ErrorCode, SubSliceMut<'static, u8>)> {
+        if let Err(err) = self.start(Op::Read) {
+            return Err((err, buffer));
+        }
+        self.file.set(file);
+        self.offset.set(offset);
+        self.permissions.set(permissions);
+        self.buffer.replace(buffer);
+        Ok(())
+    }
+
+    fn write(
+        &self,
+        file: FileId,
+        offset: usize,
+        buffer: SubSliceMut<'static, u8>,
+        permissions: StoragePermissions,
+    ) -> Result<(), (ErrorCode, SubSliceMut<'static, u8>)> {
+        if let Err(err) = self.start(Op::Write) {
+            return Err((err, buffer));
+        }
+        self.file.set(file);
+        self.offset.set(offset);
+        self.permissions.set(permissions);
+        self.buffer.replace(buffer);
+        Ok(())
+    }
+
+    fn unlink(&self, file: FileId, permissions: StoragePermissions) -> Result<(), ErrorCode> {
+        self.start(Op::Unlink)?;
+        self.file.set(file);
+        self.offset.set(0);
+        self.permissions.set(permissions);
+        Ok(())
+    }
+
+    fn read_dir(
+        &self,
+        dir: u32,
+        cursor: usize,
+        name: SubSliceMut<'static, u8>,
+        permissions: StoragePermissions,
+    ) -> Result<(), (ErrorCode, SubSliceMut<'static, u8>)> {
+        if let Err(err) = self.start(Op::ReadDir) {
+            return Err((err, name));
+        }
+        self.dir_id.set(dir);
+        self.offset.set(cursor);
+        self.permissions.set(permissions);
+        self.buffer.replace(name);
+        Ok(())
+    }
+}
+
+impl<'a, B: BlockDevice<'a>> BlockClient for FatFs<'a, B> {
+    fn read_done(&self, _block: usize, buffer: &'static mut [u8], result: Result<(), ErrorCode>) {
+        if let Io::Read(slot) = self.io.replace(Io::Idle) {
+            self.cache[slot].buffer.replace(buffer);
+            self.cache[slot].valid.set(result.is_ok());
+            self.cache[slot].dirty.set(false);
+            self.cache[slot].used.set(self.tick());
+        }
+        self.io_done(result);
+    }
+
+    fn write_done(&self, _block: usize, buffer: &'static mut [u8], result: Result<(), ErrorCode>) {
+        if let Io::Write(slot) = self.io.replace(Io::Idle) {
+            self.cache[slot].buffer.replace(buffer);
+            if result.is_err() {
+                // Data that could not be written is lost.
+                self.cache[slot].valid.set(false);
+            }
+            self.cache[slot].dirty.set(false);
+        }
+        self.io_done(result);
+    }
+
+    fn erase_done(&self, _block: usize, _result: Result<(), ErrorCode>) {}
+
+    fn flush_done(&self, result: Result<(), ErrorCode>) {
+        if self.io.replace(Io::Idle) == Io::Flush {
+            self.io_done(result);
+        }
+    }
+}
+
+impl<'a, B: BlockDevice<'a>> DeferredCallClient for FatFs<'a, B> {
+    fn handle_deferred_call(&self) {
+        if self.phase.get() == Phase::Start {
+            self.phase.set(match self.op.get() {
+                Op::Mount => Phase::MountInit,
+                Op::Open | Op::ReadDir => Phase::BaseDir,
+                _ => Phase::File,
+            });
+            self.run();
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+fn le16(buffer: &[u8], at: usize) -> u32 {
+    u32::from(u16::from_le_bytes([buffer[at], buffer[at + 1]]))
+}
+
+fn le32(buffer: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([buffer[at], buffer[at + 1], buffer[at + 2], buffer[at + 3]])
+}
+
+fn is_boot_sector(boot: &[u8]) -> bool {
+    (boot[0] == 0xeb || boot[0] == 0xe9)
+        && le16(boot, 11) == SECTOR_SIZE as u32
+        && boot[13].is_power_of_two()
+        && le16(boot, 14) != 0
+        && boot[16] != 0
+}
+
+/// The checksum of a short name stored in its long filename entries.
+fn checksum(short: &[u8]) -> u8 {
+    short[..11]
+        .iter()
+        .fold(0u8, |sum, &c| sum.rotate_right(1).wrapping_add(c))
+}
+
+fn short_entry(short: &[u8; 11], case: u8, attr: u8, cluster: u32) -> [u8; ENTRY_SIZE] {
+    let mut entry = [0; ENTRY_SIZE];
+    entry[..11].copy_from_slice(short);
+    entry[11] = attr;
+    entry[12] = case;
+    for date in [16, 18, 24] {
+        entry[date..date + 2].copy_from_slice(&DATE.to_le_bytes());
+    }
+    entry[20..22].copy_from_slice(&((cluster >> 16) as u16).to_le_bytes());
+    entry[26..28].copy_from_slice(&(cluster as u16).to_le_bytes());
+    entry
+}
+
+/// Format the short name of an entry as `NAME.EXT`.
+fn format_short(entry: &[u8]) -> ([u8; 12], usize) {
+    let mut name = [0; 12];
+    let mut len = 0;
+    for (part, case) in [(&entry[..8], CASE_BASE), (&entry[8..11], CASE_EXT)] {
+        let part_len = part
+            .iter()
+            .rposition(|&c| c != b' ')
+            .map_or(0, |end| end + 1);
+        if part_len == 0 {
+            continue;
+        }
+        if case == CASE_EXT {
+            name[len] = b'.';
+            len += 1;
+        }
+        for &c in &part[..part_len] {
+            name[len] = if entry[12] & case != 0 {
+                c.to_ascii_lowercase()
+            } else {
+                c
+            };
+            len += 1;
+        }
+    }
+    if name[0] == 0x05 {
+        name[0] = DELETED;
+    }
+    (name, len)
+}
+
+fn short_equals(target: &[u8], entry: &[u8]) -> bool {
+    let (name, len) = format_short(entry);
+    target.eq_ignore_ascii_case(&name[..len])
+}
+
+fn fold(c: u32) -> u32 {
+    if (u32::from(b'a')..=u32::from(b'z')).contains(&c) {
+        c - 32
+    } else {
+        c
+    }
+}
+
+/// Decode the next character of UTF-8 `bytes` at `at`, `None` at the end or
+/// at invalid bytes.
+fn decode_utf8(bytes: &[u8], at: &mut usize) -> Option<u32> {
+    let rest = bytes.get(*at..)?;
+    let len = match *rest.first()? {
+        0x00..=0x7f => 1,
+        0xc2..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf4 => 4,
+        _ => return None,
+    };
+    let c = core::str::from_utf8(rest.get(..len)?)
+        .ok()?
+        .chars()
+        .next()?;
+    *at += len;
+    Some(c as u32)
+}
+
+/// Decode the next character of UTF-16 `units` at `at`.
+fn decode_utf16(units: &[u16], at: &mut usize) -> Option<u32> {
+    let unit = u32::from(*units.get(*at)?);
+    *at += 1;
+    if (0xd800..0xdc00).contains(&unit) {
+        if let Some(&low) = units
+            .get(*at)
+            .filter(|&&low| (0xdc00..0xe000).contains(&low))
+        {
+            *at += 1;
+            return Some(0x10000 + ((unit - 0xd800) << 10) + (u32::from(low) - 0xdc00));
+        }
+    }
+    Some(unit)
+}
+
+/// The UTF-16 code units of a UTF-8 name.
+struct Utf8Units<'b> {
+    bytes: &'b [u8],
+    at: usize,
+    low: Option<u16>,
+}
+
+impl<'b> Utf8Units<'b> {
+    fn new(bytes: &'b [u8]) -> Self {
+        Utf8Units {
+            bytes,
+            at: 0,
+            low: None,
+        }
+    }
+}
+
+impl Iterator for Utf8Units<'_> {
+    type Item = u16;
+
+    fn next(&mut self) -> Option<u16> {
+        if let Some(low) = self.low.take() {
+            return Some(low);
+        }
+        let c = decode_utf8(self.bytes, &mut self.at)?;
+        if c < 0x10000 {
+            return Some(c as u16);
+        }
+        let c = c - 0x10000;
+        self.low = Some(0xdc00 | (c & 0x3ff) as u16);
+        Some(0xd800 | (c >> 10) as u16)
+    }
+}
+
+fn lfn_equals(target: &[u8], units: &[u16]) -> bool {
+    let (mut i, mut j) = (0, 0);
+    loop {
+        match (decode_utf8(target, &mut i), decode_utf16(units, &mut j)) {
+            (None, None) => return i == target.len(),
+            (Some(a), Some(b)) if fold(a) == fold(b) => {}
+            _ => return false,
+        }
+    }
+}
+
+fn utf16_to_utf8(units: &[u16], out: &mut [u8]) -> Result<usize, ErrorCode> {
+    let mut at = 0;
+    let mut len = 0;
+    while let Some(c) = decode_utf16(units, &mut at) {
+        let c = char::from_u32(c).unwrap_or(char::REPLACEMENT_CHARACTER);
+        let encoded = out
+            .get_mut(len..len + c.len_utf8())
+            .ok_or(ErrorCode::SIZE)?;
+        c.encode_utf8(encoded);
+        len += c.len_utf8();
+    }
+    Ok(len)
+}
+
+/// Check a name for a new file or directory and count its UTF-16 units.
+fn name_units(name: &[u8]) -> Result<usize, ErrorCode> {
+    if name.is_empty() || name.ends_with(b".") || name.ends_with(b" ") {
+        return Err(ErrorCode::INVAL);
+    }
+    let mut at = 0;
+    let mut units = 0;
+    while at < name.len() {
+        let c = decode_utf8(name, &mut at).ok_or(ErrorCode::INVAL)?;
+        if c < 0x20 || c == 0x7f || (c < 0x80 && FORBIDDEN.contains(&(c as u8))) {
+            return Err(ErrorCode::INVAL);
+        }
+        units += if c < 0x10000 { 1 } else { 2 };
+    }
+    if units > NAME_MAX {
+        return Err(ErrorCode::SIZE);
+    }
+    Ok(units)
+}
+
+/// The short name and case flags of names that are valid short names.
+fn short_name(name: &[u8]) -> Option<([u8; 11], u8)> {
+    let (base, ext) = match name.iter().position(|&c| c == b'.') {
+        Some(dot) => (&name[..dot], &name[dot + 1..]),
+        None => (name, &name[..0]),
+    };
+    if base.is_empty() || base.len() > 8 || ext.len() > 3 {
+        return None;
+    }
+    let mut short = [b' '; 11];
+    let mut case = 0;
+    for (part, start, flag) in [(base, 0, CASE_BASE), (ext, 8, CASE_EXT)] {
+        let lower = part.iter().any(u8::is_ascii_lowercase);
+        if lower && part.iter().any(u8::is_ascii_uppercase) {
+            return None;
+        }
+        if lower {
+            case |= flag;
+        }
+        for (i, &c) in part.iter().enumerate() {
+            if !c.is_ascii_alphanumeric() && !SHORT_SPECIAL.contains(&c) {
+                return None;
+            }
+            short[start + i] = c.to_ascii_uppercase();
+        }
+    }
+    Some((short, case))
+}
+
+/// The short name stem of a long name, with the length of its base.
+fn basis(name: &[u8]) -> ([u8; 11], usize) {
+    let dot = name.iter().rposition(|&c| c == b'.').filter(|&dot| dot > 0);
+    let (base, ext) = match dot {
+        Some(dot) => (&name[..dot], &name[dot + 1..]),
+        None => (name, &name[..0]),
+    };
+    let mut short = [b' '; 11];
+    let mut base_len = 0;
+    for (part, start, max) in [(base, 0, 8), (ext, 8, 3)] {
+        let mut at = 0;
+        let mut len = 0;
+        while len < max {
+            let Some(c) = decode_utf8(part, &mut at) else {
+                break;
+            };
+            short[start + len] = match u8::try_from(c) {
+                Ok(b' ' | b'.') => continue,
+                Ok(c) if c.is_ascii_alphanumeric() || SHORT_SPECIAL.contains(&c) => {
+                    c.to_ascii_uppercase()
+                }
+                _ => b'_',
+            };
+            len += 1;
+        }
+        if start == 0 {
+            base_len = len;
+        }
+    }
+    if base_len == 0 {
+        short[0] = b'_';
+        base_len = 1;
+    }
+    (short, base_len)
+}
@@ -0,0 +1,555 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Filesystem Userspace Driver.
+//!
+//! Provides userspace access to files in a filesystem. Each process has a
+//! directory, named by its storage write id, in which it can create files.
+//! Access to files in other directories is restricted based on the
+//! `StoragePermissions` from the TBF headers of the process.
+//!
+//! The driver keeps a small table of open files for each process, with the
+//! position of reads and writes in each file.
+//!
+//! ```rust,ignore
+//! +===============+
+//! ||  Userspace  ||
+//! +===============+
+//!
+//! -----Syscall Interface-----
+//!
+//! +---------------------------------+
+//! |  Filesystem Driver (this file)  |
+//! +---------------------------------+
+//!
+//!    hil::filesystem::FileSystem
+//!
+//! +---------------------------------+
+//! |  Filesystem                     |
+//! +---------------------------------+
+//! ```
+
+use capsules_core::driver;
+/// Syscall driver number.
+pub const DRIVER_NUM: usize = driver::NUM::FileSystem as usize;
+
+use core::cmp;
+use kernel::errorcode;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, GrantKernelData, UpcallCount};
+use kernel::hil::filesystem::{DirEntry, FileId, FileInfo, FileSystem, FileSystemClient};
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::{ErrorCode, ProcessId};
+
+/// Number of files each process can have open at the same time.
+pub const OPEN_FILES: usize = 4;
+
+/// IDs for read-only allow buffers.
+mod ro_allow {
+    /// File name for open.
+    pub const NAME: usize = 0;
+    /// Data for write.
+    pub const DATA: usize = 1;
+    /// Subdirectory path for read directory.
+    pub const PATH: usize = 2;
+    /// The number of RO allow buffers the kernel stores for this grant.
+    pub const COUNT: u8 = 3;
+}
+
+/// IDs for read-write allow buffers.
+mod rw_allow {
+    /// Data for read.
+    pub const DATA: usize = 0;
+    /// File name for read directory.
+    pub const NAME: usize = 1;
+    /// The number of RW allow buffers the kernel stores for this grant.
+    pub const COUNT: u8 = 2;
+}
+
+/// IDs for upcalls.
+mod upcalls {
+    /// Single upcall.
+    pub const DONE: usize = 0;
+    /// The number of upcalls the kernel stores for this grant.
+    pub const COUNT: u8 = 1;
+}
+
+#[derive(Copy, Clone)]
+enum UserSpaceOp {
+    Open { dir: u32, create: bool },
+    Read { fd: usize, len: usize },
+    Write { fd: usize, len: usize },
+    Stat { fd: usize },
+    Unlink { fd: usize },
+    ReadDir { dir: u32, cursor: usize },
+}
+
+/// A file opened by a process.
+#[derive(Copy, Clone)]
+struct OpenFile {
+    file: FileId,
+    /// Offset of the next read or write.
+    position: usize,
+}
+
+/// Contents of the grant for each app.
+#[derive(Default)]
+pub struct App {
+    op: OptionalCell<UserSpaceOp>,
+    files: [Option<OpenFile>; OPEN_FILES],
+}
+
+impl App {
+    fn file(&self, fd: usize) -> Result<OpenFile, ErrorCode> {
+        self.files
+            .get(fd)
+            .copied()
+            .flatten()
+            .ok_or(ErrorCode::INVAL)
+    }
+}
+
+/// Capsule that provides userspace access to a filesystem.
+pub struct FileSystemDriver<'a, F: FileSystem<'a>> {
+    /// Underlying filesystem.
+    fs: &'a F,
+    /// Grant storage for each app.
+    apps: Grant<
+        App,
+        UpcallCount<{ upcalls::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    /// App that is actively using the filesystem.
+    processid: OptionalCell<ProcessId>,
+    /// Buffer for file names and data.
+    buffer: TakeCell<'static, [u8]>,
+}
+
+impl<'a, F: FileSystem<'a>> FileSystemDriver<'a, F> {
+    pub fn new(
+        fs: &'a F,
+        buffer: &'static mut [u8],
+        grant: Grant<
+            App,
+            UpcallCount<{ upcalls::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+    ) -> FileSystemDriver<'a, F> {
+        FileSystemDriver {
+            fs,
+            apps: grant,
+            processid: OptionalCell::empty(),
+            buffer: TakeCell::new(buffer),
+        }
+    }
+
+    /// Start the pending operation of the active app.
+    fn run(&self) -> Result<(), ErrorCode> {
+        let processid = self.processid.get().ok_or(ErrorCode::RESERVE)?;
+        let perms = processid
+            .get_storage_permissions()
+            .ok_or(ErrorCode::INVAL)?;
+        // Directory 0 is the directory of the app.
+        let directory = |dir| match dir {
+            0 => perms.get_write_id().ok_or(ErrorCode::NOSUPPORT),
+            dir => Ok(dir),
+        };
+
+        self.apps
+            .enter(processid, |app, kernel_data| {
+                let op = app.op.get().ok_or(ErrorCode::FAIL)?;
+                let mut buffer = SubSliceMut::new(self.buffer.take().ok_or(ErrorCode::NOMEM)?);
+
+                let result = match op {
+                    UserSpaceOp::Open { dir, create } => match directory(dir).and_then(|dir| {
+                        if app.files.iter().all(Option::is_some) {
+                            return Err(ErrorCode::NOMEM);
+                        }
+                        let len = kernel_data
+                            .get_readonly_processbuffer(ro_allow::NAME)
+                            .and_then(|name| {
+                                name.enter(|name| {
+                                    if name.len() > buffer.len() {
+                                        return Err(ErrorCode::SIZE);
+                                    }
+                                    name.copy_to_slice(&mut buffer.as_slice()[..name.len()]);
+                                    Ok(name.len())
+                                })
+                            })
+                            .unwrap_or(Err(ErrorCode::RESERVE))?;
+                        buffer.slice(..len);
+                        Ok(dir)
+                    }) {
+                        Ok(dir) => self.fs.open(dir, buffer, create, perms),
+                        Err(e) => Err((e, buffer)),
+                    },
+                    UserSpaceOp::Read { fd, len } => match app.file(fd) {
+                        Ok(file) => {
+                            let app_len = kernel_data
+                                .get_readwrite_processbuffer(rw_allow::DATA)
+                                .map_or(0, |data| data.len());
+                            buffer.slice(..cmp::min(len, cmp::min(app_len, buffer.len())));
+                            self.fs.read(file.file, file.position, buffer, perms)
+                        }
+                        Err(e) => Err((e, buffer)),
+                    },
+                    UserSpaceOp::Write { fd, len } => match app.file(fd).and_then(|file| {
+                        let len = kernel_data
+                            .get_readonly_processbuffer(ro_allow::DATA)
+                            .and_then(|data| {
+                                data.enter(|data| {
+                                    let len = cmp::min(len, cmp::min(data.len(), buffer.len()));
+                                    data[..len].copy_to_slice(&mut buffer.as_slice()[..len]);
+                                    len
+                                })
+                            })
+                            .map_err(|_| ErrorCode::RESERVE)?;
+                        buffer.slice(..len);
+                        Ok(file)
+                    }) {
+                        Ok(file) => self.fs.write(file.file, file.position, buffer, perms),
+                        Err(e) => Err((e, buffer)),
+                    },
+                    UserSpaceOp::Stat { fd } => {
+                        self.buffer.replace(buffer.take());
+                        return app.file(fd).and_then(|file| self.fs.stat(file.file, perms));
+                    }
+                    UserSpaceOp::Unlink { fd } => {
+                        self.buffer.replace(buffer.take());
+                        return app
+                            .file(fd)
+                            .and_then(|file| self.fs.unlink(file.file, perms));
+                    }
+                    UserSpaceOp::ReadDir { dir, cursor } => match directory(dir).and_then(|dir| {
+                        // Without a path the directory itself is read.
+                        let len = kernel_data
+                            .get_readonly_processbuffer(ro_allow::PATH)
+                            .and_then(|path| {
+                                path.enter(|path| {
+                                    if path.len() > buffer.len() {
+                                        return Err(ErrorCode::SIZE);
+                                    }
+                                    path.copy_to_slice(&mut buffer.as_slice()[..path.len()]);
+                                    Ok(path.len())
+                                })
+                            })
+                            .unwrap_or(Ok(0))?;
+                        buffer.slice(..len);
+                        Ok(dir)
+                    }) {
+                        Ok(dir) => self.fs.read_dir(dir, cursor, buffer, perms),
+                        Err(e) => Err((e, buffer)),
+                    },
+                };
+                result.map_err(|(e, buffer)| {
+                    self.buffer.replace(buffer.take());
+                    e
+                })
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+    }
+
+    /// Start the next pending operation, if the filesystem is not in use.
+    fn check_queue(&self) {
+        // If an app is already running let it complete.
+        if self.processid.is_some() {
+            return;
+        }
+
+        for appiter in self.apps.iter() {
+            let processid = appiter.processid();
+            if !appiter.enter(|app, _| app.op.is_some()) {
+                continue;
+            }
+            self.processid.set(processid);
+            match self.run() {
+                Ok(()) => break,
+                Err(e) => {
+                    // The command already succeeded, so report the error with
+                    // the upcall.
+                    self.processid.clear();
+                    let _ = self.apps.enter(processid, |app, kernel_data| {
+                        app.op.clear();
+                        kernel_data
+                            .schedule_upcall(
+                                upcalls::DONE,
+                                (errorcode::into_statuscode(Err(e)), 0, 0),
+                            )
+                            .ok();
+                    });
+                }
+            }
+        }
+    }
+
+    /// Complete the operation of the active app and return the buffer.
+    /// `complete` gets the data in the buffer, updates the grant and returns
+    /// the arguments of the upcall.
+    fn complete(
+        &self,
+        buffer: Option<SubSliceMut<'static, u8>>,
+        complete: impl FnOnce(
+            &mut App,
+            &GrantKernelData,
+            UserSpaceOp,
+            &[u8],
+        ) -> (Result<(), ErrorCode>, usize, usize),
+    ) {
+        let mut buffer = buffer;
+        self.processid.take().map(|processid| {
+            self.apps.enter(processid, |app, kernel_data| {
+                if let Some(op) = app.op.take() {
+                    let data = buffer.as_mut().map_or(&[][..], |buffer| buffer.as_slice());
+                    let (result, r1, r2) = complete(app, kernel_data, op, data);
+                    kernel_data
+                        .schedule_upcall(
+                            upcalls::DONE,
+                            (errorcode::into_statuscode(result), r1, r2),
+                        )
+                        .ok();
+                }
+            })
+        });
+        if let Some(buffer) = buffer {
+            self.buffer.replace(buffer.take());
+        }
+
+        // We have completed the operation so see if there is a queued operation
+        // to run next.
+        self.check_queue();
+    }
+}
+
+/// Copy `data` into the read-write allow buffer `allow_num`.
+fn copy_to_app(
+    kernel_data: &GrantKernelData,
+    allow_num: usize,
+    data: &[u8],
+) -> Result<(), ErrorCode> {
+    kernel_data
+        .get_readwrite_processbuffer(allow_num)
+        .and_then(|buffer| {
+            buffer.mut_enter(|buffer| {
+                let len = cmp::min(data.len(), buffer.len());
+                buffer[..len].copy_from_slice(&data[..len]);
+                if len < data.len() {
+                    Err(ErrorCode::SIZE)
+                } else {
+                    Ok(())
+                }
+            })
+        })
+        .unwrap_or(Err(ErrorCode::RESERVE))
+}
+
+impl<'a, F: FileSystem<'a>> FileSystemClient for FileSystemDriver<'a, F> {
+    fn mount_done(&self, _result: Result<(), ErrorCode>) {
+        self.check_queue();
+    }
+
+    fn open_done(&self, result: Result<FileId, ErrorCode>, name: SubSliceMut<'static, u8>) {
+        self.complete(Some(name), |app, _, _, _| {
+            let result = result.and_then(|file| {
+                let fd = app
+                    .files
+                    .iter()
+                    .position(Option::is_none)
+                    .ok_or(ErrorCode::NOMEM)?;
+                app.files[fd] = Some(OpenFile { file, position: 0 });
+                Ok(fd)
+            });
+            match result {
+                Ok(fd) => (Ok(()), fd, 0),
+                Err(e) => (Err(e), 0, 0),
+            }
+        });
+    }
+
+    fn stat_done(&self, result: Result<FileInfo, ErrorCode>) {
+        self.complete(None, |_, _, _, _| match result {
+            Ok(info) => (Ok(()), info.size, info.dir as usize),
+            Err(e) => (Err(e), 0, 0),
+        });
+    }
+
+    fn read_done(&self, result: Result<usize, ErrorCode>, buffer: SubSliceMut<'static, u8>) {
+        self.complete(Some(buffer), |app, kernel_data, op, data| {
+            let result = result.and_then(|len| {
+                copy_to_app(kernel_data, rw_allow::DATA, &data[..len]).map(|()| len)
+            });
+            match (result, op) {
+                (Ok(len), UserSpaceOp::Read { fd, .. }) => {
+                    if let Some(Some(file)) = app.files.get_mut(fd) {
+                        file.position += len;
+                    }
+                    (Ok(()), len, 0)
+                }
+                (Ok(_), _) => (Err(ErrorCode::FAIL), 0, 0),
+                (Err(e), _) => (Err(e), 0, 0),
+            }
+        });
+    }
+
+    fn write_done(&self, result: Result<usize, ErrorCode>, buffer: SubSliceMut<'static, u8>) {
+        self.complete(Some(buffer), |app, _, op, _| match (result, op) {
+            (Ok(len), UserSpaceOp::Write { fd, .. }) => {
+                if let Some(Some(file)) = app.files.get_mut(fd) {
+                    file.position += len;
+                }
+                (Ok(()), len, 0)
+            }
+            (Ok(_), _) => (Err(ErrorCode::FAIL), 0, 0),
+            (Err(e), _) => (Err(e), 0, 0),
+        });
+    }
+
+    fn unlink_done(&self, result: Result<(), ErrorCode>) {
+        self.complete(None, |app, _, op, _| {
+            if let (Ok(()), UserSpaceOp::Unlink { fd }) = (result, op) {
+                if let Some(file) = app.files.get_mut(fd) {
+                    *file = None;
+                }
+            }
+            (result, 0, 0)
+        });
+    }
+
+    fn read_dir_done(
+        &self,
+        result: Result<Option<DirEntry>, ErrorCode>,
+        name: SubSliceMut<'static, u8>,
+    ) {
+        self.complete(Some(name), |_, kernel_data, _, name| match result {
+            Ok(Some(entry)) => match copy_to_app(kernel_data, rw_allow::NAME, name) {
+                // Subdirectories are flagged in the top bit of the length.
+                Ok(()) => (
+                    Ok(()),
+                    entry.next,
+                    name.len() | usize::from(entry.directory) << 31,
+                ),
+                Err(e) => (Err(e), 0, 0),
+            },
+            // The end of the directory.
+            Ok(None) => (Ok(()), 0, 0),
+            Err(e) => (Err(e), 0, 0),
+        });
+    }
+}
+
+impl<'a, F: FileSystem<'a>> SyscallDriver for FileSystemDriver<'a, F> {
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Open the file named by the read-only allow buffer 0 in directory
+    ///   `data1` (0 for the directory of the app). Bit 0 of `data2` creates the
+    ///   file if it does not exist.
+    /// - `2`: Close file `data1`.
+    /// - `3`: Read at most `data2` bytes from file `data1` into the read-write
+    ///   allow buffer 0.
+    /// - `4`: Write at most `data2` bytes from the read-only allow buffer 1 to
+    ///   file `data1`.
+    /// - `5`: Set the position of file `data1` to `data2`.
+    /// - `6`: Get the size and directory of file `data1`.
+    /// - `7`: Remove file `data1` and close it.
+    /// - `8`: Read the entry of directory `data1` (0 for the directory of the
+    ///   app), or of its subdirectory named by the read-only allow buffer 2,
+    ///   at or after cursor `data2` and copy its name into the read-write
+    ///   allow buffer 1.
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        let op = match command_num {
+            // check if present
+            0 => return CommandReturn::success(),
+
+            1 => UserSpaceOp::Open {
+                dir: data1 as u32,
+                create: data2 & 1 != 0,
+            },
+
+            // close and seek do not use the filesystem
+            2 | 5 => {
+                return self
+                    .apps
+                    .enter(processid, |app, _| match app.files.get_mut(data1) {
+                        Some(Some(file)) if command_num == 5 => {
+                            file.position = data2;
+                            CommandReturn::success()
+                        }
+                        Some(file @ Some(_)) => {
+                            *file = None;
+                            CommandReturn::success()
+                        }
+                        _ => CommandReturn::failure(ErrorCode::INVAL),
+                    })
+                    .unwrap_or_else(|err| err.into());
+            }
+
+            3 => UserSpaceOp::Read {
+                fd: data1,
+                len: data2,
+            },
+            4 => UserSpaceOp::Write {
+                fd: data1,
+                len: data2,
+            },
+            6 => UserSpaceOp::Stat { fd: data1 },
+            7 => UserSpaceOp::Unlink { fd: data1 },
+            8 => UserSpaceOp::ReadDir {
+                dir: data1 as u32,
+                cursor: data2,
+            },
+
+            // default
+            _ => return CommandReturn::failure(ErrorCode::NOSUPPORT),
+        };
+
+        let queued = self
+            .apps
+            .enter(processid, |app, _| {
+                if app.op.is_some() {
+                    // Each app can only have one pending operation.
+                    Err(ErrorCode::BUSY)
+                } else {
+                    app.op.set(op);
+                    Ok(())
+                }
+            })
+            .unwrap_or_else(|err| Err(err.into()));
+        if let Err(e) = queued {
+            return CommandReturn::failure(e);
+        }
+
+        if self.processid.is_some() {
+            // Another app is using the filesystem, this operation runs once it
+            // is done.
+            return CommandReturn::success();
+        }
+
+        self.processid.set(processid);
+        match self.run() {
+            Ok(()) => CommandReturn::success(),
+            Err(e) => {
+                self.processid.clear();
+                let _ = self.apps.enter(processid, |app, _| app.op.clear());
+                self.check_queue();
+                CommandReturn::failure(e)
+            }
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
@@ -0,0 +1,897 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! A small power-fail-safe filesystem for flash.
+//!
+//! The filesystem uses a range of flash pages, which are its blocks. Like
+//! littlefs, it keeps its metadata in a pair of blocks and updates it copy on
+//! write: each change writes the complete metadata, with an incremented
+//! revision, to the block of the pair not holding the current metadata. When
+//! mounting, the valid block with the newest revision is used. A change that is
+//! interrupted by a power failure therefore leaves the previous metadata in
+//! place.
+//!
+//! The metadata holds the allocation map, which for each block records the
+//! next block of the file using it, and a table of file entries:
+//!
+//! ```text
+//! +-------+----------+----------+--------------+---------------+
+//! | magic | revision | checksum | block count, | map[blocks]   |
+//! |       |          |          | file count   | entry[files]  |
+//! +-------+----------+----------+--------------+---------------+
+//!
+//! entry: | directory | size | first block | name length | name |
+//! ```
+//!
+//! File data is never overwritten in place either. A write copies the block it
+//! changes into a newly allocated block and then commits metadata pointing to
+//! the new block. Blocks are allocated round-robin starting at a point that
+//! moves with every mount, which spreads erases over all data blocks. The
+//! metadata pair itself is not moved, so it sees the most erases.
+//!
+//! Writes change at most one block, callers write larger buffers with multiple
+//! calls.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let fs = static_init!(
+//!     capsules_extra::flash_fs::FlashFs<'static, nrf52840::nvmc::Nvmc>,
+//!     capsules_extra::flash_fs::FlashFs::new(
+//!         &base_peripherals.nvmc,
+//!         0xc0,          // First page of the filesystem.
+//!         16,            // Number of pages.
+//!         metadata_page,
+//!         data_page,
+//!     )
+//! );
+//! kernel::hil::flash::HasClient::set_client(&base_peripherals.nvmc, fs);
+//! fs.register();
+//! fs.set_client(fs_client);
+//! fs.mount();
+//! ```
+
+use core::cell::Cell;
+use core::cmp;
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::filesystem::{DirEntry, FileId, FileInfo, FileSystem, FileSystemClient};
+use kernel::hil::flash::{self, Flash};
+use kernel::storage_permissions::StoragePermissions;
+use kernel::utilities::cells::{MapCell, OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::ErrorCode;
+
+/// Marks valid metadata ("TkFS").
+const MAGIC: u32 = 0x5346_6b54;
+/// Bytes before the allocation map in the metadata.
+const HEADER_LEN: usize = 16;
+/// Bytes used by each file entry.
+const ENTRY_LEN: usize = 32;
+/// Longest file name.
+pub const NAME_LEN: usize = 20;
+/// Most files in a filesystem. Fewer fit if the metadata does not fit in a
+/// page otherwise.
+pub const MAX_FILES: usize = 64;
+/// Number of blocks used for the metadata pair.
+const META_BLOCKS: usize = 2;
+
+/// Allocation map value of a free block.
+const FREE: usize = 0;
+/// Allocation map value of the last block of a file. This is a metadata
+/// block, so it cannot be the next block of a file.
+const END: usize = 1;
+/// `FileId` standing for no file.
+const NONE: usize = usize::MAX;
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    /// The operation completes from a deferred call.
+    Deferred,
+    /// Reading the first block of the metadata pair.
+    MountFirst,
+    /// Reading the second block of the metadata pair.
+    MountSecond,
+    /// Reading file data.
+    Read,
+    /// Reading the block a write changes.
+    WriteRead,
+    /// Erasing the block a write goes to.
+    WriteErase,
+    /// Writing the changed block.
+    WriteData,
+    /// Erasing the metadata block the next revision goes to.
+    CommitErase,
+    /// Writing the next revision of the metadata.
+    CommitWrite,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Mount,
+    Open,
+    Stat,
+    Read,
+    Write,
+    Unlink,
+    ReadDir,
+}
+
+fn get_u16(buf: &[u8], offset: usize) -> usize {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]]) as usize
+}
+
+fn set_u16(buf: &mut [u8], offset: usize, value: usize) {
+    buf[offset..offset + 2].copy_from_slice(&(value as u16).to_le_bytes());
+}
+
+fn get_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        buf[offset],
+        buf[offset + 1],
+        buf[offset + 2],
+        buf[offset + 3],
+    ])
+}
+
+fn set_u32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// FNV-1a hash used to detect incompletely written metadata.
+fn checksum(buf: &[u8]) -> u32 {
+    buf.iter().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ *byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+pub struct FlashFs<'a, F: Flash + 'static> {
+    flash: &'a F,
+    client: OptionalCell<&'a dyn FileSystemClient>,
+    /// Flash page of block 0.
+    first_page: usize,
+    /// Number of blocks, including the metadata pair.
+    block_count: usize,
+    /// Bytes per block.
+    block_size: usize,
+    /// Number of file entries in the metadata.
+    max_files: usize,
+
+    /// The current metadata.
+    meta: TakeCell<'static, F::Page>,
+    /// Buffer for flash operations.
+    page: TakeCell<'static, F::Page>,
+    /// Buffer of the operation in progress.
+    buffer: MapCell<SubSliceMut<'static, u8>>,
+
+    state: Cell<State>,
+    op: Cell<Op>,
+    mounted: Cell<bool>,
+    /// Which block of the metadata pair holds the current metadata.
+    active_meta: Cell<usize>,
+    /// Where to start looking for a free block.
+    next_alloc: Cell<usize>,
+
+    /// File of the operation in progress.
+    file: Cell<FileId>,
+    /// Offset in the file of the read or write in progress.
+    offset: Cell<usize>,
+    /// Bytes read or written by the operation in progress.
+    length: Cell<usize>,
+    /// Block a write replaces, if any.
+    old_block: Cell<usize>,
+    /// Block a write goes to.
+    new_block: Cell<usize>,
+    /// Result of an operation completing from a deferred call.
+    result: Cell<Result<(), ErrorCode>>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a, F: Flash> FlashFs<'a, F> {
+    pub fn new(
+        flash: &'a F,
+        first_page: usize,
+        page_count: usize,
+        meta: &'static mut F::Page,
+        page: &'static mut F::Page,
+    ) -> Self {
+        let block_size = page.as_mut().len();
+        let max_files = cmp::min(
+            MAX_FILES,
+            block_size.saturating_sub(HEADER_LEN + 2 * page_count) / ENTRY_LEN,
+        );
+        Self {
+            flash,
+            client: OptionalCell::empty(),
+            first_page,
+            block_count: page_count,
+            block_size,
+            max_files,
+            meta: TakeCell::new(meta),
+            page: TakeCell::new(page),
+            buffer: MapCell::empty(),
+            state: Cell::new(State::Idle),
+            op: Cell::new(Op::Mount),
+            mounted: Cell::new(false),
+            active_meta: Cell::new(0),
+            next_alloc: Cell::new(META_BLOCKS),
+            file: Cell::new(NONE),
+            offset: Cell::new(0),
+            length: Cell::new(0),
+            old_block: Cell::new(NONE),
+            new_block: Cell::new(NONE),
+            result: Cell::new(Ok(())),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    fn map_offset(&self, block: usize) -> usize {
+        HEADER_LEN + 2 * block
+    }
+
+    fn entry_offset(&self, file: FileId) -> usize {
+        HEADER_LEN + 2 * self.block_count + ENTRY_LEN * file
+    }
+
+    /// Length of the metadata.
+    fn meta_len(&self) -> usize {
+        self.entry_offset(self.max_files)
+    }
+
+    /// Check that `meta` holds valid metadata for this filesystem and return
+    /// its revision.
+    fn validate(&self, meta: &[u8]) -> Option<u32> {
+        let len = self.meta_len();
+        (get_u32(meta, 0) == MAGIC
+            && get_u16(meta, 12) == self.block_count
+            && get_u16(meta, 14) == self.max_files
+            && get_u32(meta, 8) == checksum(&meta[12..len]))
+        .then(|| get_u32(meta, 4))
+    }
+
+    /// Run `f` on the current metadata.
+    fn with_meta<R>(&self, f: impl FnOnce(&mut [u8]) -> R) -> Option<R> {
+        self.meta.map(|meta| f(meta.as_mut()))
+    }
+
+    fn next_block(&self, meta: &[u8], block: usize) -> usize {
+        get_u16(meta, self.map_offset(block))
+    }
+
+    /// The `n`th block of the file starting at `block`, if the file has that
+    /// many blocks.
+    fn nth_block(&self, meta: &[u8], mut block: usize, n: usize) -> Option<usize> {
+        for _ in 0..n {
+            if block < META_BLOCKS {
+                return None;
+            }
+            block = self.next_block(meta, block);
+        }
+        (block >= META_BLOCKS && block < self.block_count).then_some(block)
+    }
+
+    /// Find a free block, starting at `next_alloc`.
+    fn allocate(&self, meta: &[u8]) -> Option<usize> {
+        let data_blocks = self.block_count - META_BLOCKS;
+        let start = self.next_alloc.get() - META_BLOCKS;
+        (0..data_blocks)
+            .map(|i| META_BLOCKS + (start + i) % data_blocks)
+            .find(|block| self.next_block(meta, *block) == FREE)
+            .inspect(|block| {
+                self.next_alloc
+                    .set(META_BLOCKS + (*block + 1 - META_BLOCKS) % data_blocks)
+            })
+    }
+
+    /// The directory and name of `file`, if it exists.
+    fn entry<'m>(&self, meta: &'m [u8], file: FileId) -> Option<(u32, &'m [u8])> {
+        if file >= self.max_files {
+            return None;
+        }
+        let entry = &meta[self.entry_offset(file)..self.entry_offset(file + 1)];
+        let name_len = entry[10] as usize;
+        (name_len != 0 && name_len <= NAME_LEN)
+            .then(|| (get_u32(entry, 0), &entry[12..12 + name_len]))
+    }
+
+    fn file_info(&self, meta: &[u8], file: FileId) -> FileInfo {
+        FileInfo {
+            dir: get_u32(meta, self.entry_offset(file)),
+            size: get_u32(meta, self.entry_offset(file) + 4) as usize,
+        }
+    }
+
+    fn first_block(&self, meta: &[u8], file: FileId) -> usize {
+        get_u16(meta, self.entry_offset(file) + 8)
+    }
+
+    /// Check that the filesystem can start an operation.
+    fn start(&self, op: Op) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            Err(ErrorCode::BUSY)
+        } else if !self.mounted.get() && op != Op::Mount {
+            Err(ErrorCode::OFF)
+        } else {
+            self.op.set(op);
+            Ok(())
+        }
+    }
+
+    /// Complete the current operation from a deferred call.
+    fn defer(&self, result: Result<(), ErrorCode>) {
+        self.result.set(result);
+        self.state.set(State::Deferred);
+        self.deferred_call.set();
+    }
+
+    /// Complete the current operation and report `result` to the client.
+    fn finish(&self, result: Result<(), ErrorCode>) {
+        self.state.set(State::Idle);
+        let file = self.file.get();
+        match self.op.get() {
+            Op::Mount => {
+                self.mounted.set(result.is_ok());
+                self.client.map(|client| client.mount_done(result));
+            }
+            Op::Open => {
+                if let Some(name) = self.buffer.take() {
+                    self.client
+                        .map(|client| client.open_done(result.map(|()| file), name));
+                }
+            }
+            Op::Stat => {
+                let result = result.and_then(|()| {
+                    self.with_meta(|meta| self.file_info(meta, file))
+                        .ok_or(ErrorCode::FAIL)
+                });
+                self.client.map(|client| client.stat_done(result));
+            }
+            Op::Read | Op::Write => {
+                let result = result.map(|()| self.length.get());
+                if let Some(buffer) = self.buffer.take() {
+                    self.client.map(|client| {
+                        if self.op.get() == Op::Read {
+                            client.read_done(result, buffer)
+                        } else {
+                            client.write_done(result, buffer)
+                        }
+                    });
+                }
+            }
+            Op::Unlink => {
+                self.client.map(|client| client.unlink_done(result));
+            }
+            Op::ReadDir => {
+                let result = result.and_then(|()| {
+                    if file == NONE {
+                        return Ok(None);
+                    }
+                    self.with_meta(|meta| {
+                        Some(DirEntry {
+                            file: Some(file),
+                            info: self.file_info(meta, file),
+                            directory: false,
+                            next: file + 1,
+                        })
+                    })
+                    .ok_or(ErrorCode::FAIL)
+                });
+                if let Some(name) = self.buffer.take() {
+                    self.client.map(|client| client.read_dir_done(result, name));
+                }
+            }
+        }
+    }
+
+    /// Store the metadata as the next revision.
+    fn commit(&self) {
+        let len = self.meta_len();
+        self.with_meta(|meta| {
+            set_u32(meta, 4, get_u32(meta, 4).wrapping_add(1));
+            set_u32(meta, 8, checksum(&meta[12..len]));
+            self.page
+                .map(|page| page.as_mut()[..len].copy_from_slice(&meta[..len]));
+        });
+        self.state.set(State::CommitErase);
+        let block = 1 - self.active_meta.get();
+        if self.flash.erase_page(self.first_page + block).is_err() {
+            self.finish(Err(ErrorCode::FAIL));
+        }
+    }
+
+    /// Erase the block a write goes to, after the changed data has been put in
+    /// `page`.
+    fn write_erase(&self, page: &'static mut F::Page) {
+        self.page.replace(page);
+        self.state.set(State::WriteErase);
+        if self
+            .flash
+            .erase_page(self.first_page + self.new_block.get())
+            .is_err()
+        {
+            self.finish(Err(ErrorCode::FAIL));
+        }
+    }
+
+    /// Copy the data of the write in progress into `page`.
+    fn copy_write_data(&self, page: &mut F::Page) {
+        let offset = self.offset.get() % self.block_size;
+        let length = self.length.get();
+        self.buffer.map(|buffer| {
+            page.as_mut()[offset..offset + length].copy_from_slice(&buffer.as_slice()[..length])
+        });
+    }
+
+    /// Point the file at the block a write has gone to.
+    fn link_new_block(&self, meta: &mut [u8]) {
+        let file = self.file.get();
+        let offset = self.offset.get();
+        let old = self.old_block.get();
+        let new = self.new_block.get();
+        let index = offset / self.block_size;
+
+        let next = if old == NONE {
+            END
+        } else {
+            self.next_block(meta, old)
+        };
+        set_u16(meta, self.map_offset(new), next);
+        if old != NONE {
+            set_u16(meta, self.map_offset(old), FREE);
+        }
+
+        let first = self.first_block(meta, file);
+        match index.checked_sub(1) {
+            Some(previous) => {
+                if let Some(previous) = self.nth_block(meta, first, previous) {
+                    set_u16(meta, self.map_offset(previous), new);
+                }
+            }
+            None => set_u16(meta, self.entry_offset(file) + 8, new),
+        }
+
+        let size = self.file_info(meta, file).size;
+        let end = offset + self.length.get();
+        if end > size {
+            set_u32(meta, self.entry_offset(file) + 4, end as u32);
+        }
+    }
+
+    /// Set up empty metadata.
+    fn format(&self, meta: &mut [u8]) {
+        meta.fill(0);
+        set_u32(meta, 0, MAGIC);
+        set_u16(meta, 12, self.block_count);
+        set_u16(meta, 14, self.max_files);
+    }
+
+    fn mount_read_done(&self, page: &'static mut F::Page) {
+        let second = self.validate(page.as_mut());
+        let first = self.with_meta(|meta| self.validate(meta)).flatten();
+        let use_second = match (first, second) {
+            (Some(first), Some(second)) => (second.wrapping_sub(first) as i32) > 0,
+            (None, Some(_)) => true,
+            _ => false,
+        };
+        if use_second {
+            let len = self.meta_len();
+            self.with_meta(|meta| meta[..len].copy_from_slice(&page.as_mut()[..len]));
+        }
+        self.page.replace(page);
+
+        self.active_meta.set(usize::from(use_second));
+        match first.or(second) {
+            Some(_) => {
+                // Start allocating somewhere else after every mount.
+                let revision = self.with_meta(|meta| get_u32(meta, 4)).unwrap_or(0);
+                self.next_alloc
+                    .set(META_BLOCKS + revision as usize % (self.block_count - META_BLOCKS));
+                self.finish(Ok(()));
+            }
+            None => {
+                // No valid metadata, create an empty filesystem. Committing
+                // writes it to the first block of the pair.
+                self.active_meta.set(1);
+                self.with_meta(|meta| self.format(meta));
+                self.commit();
+            }
+        }
+    }
+}
+
+impl<'a, F: Flash> FileSystem<'a> for FlashFs<'a, F> {
+    fn set_client(&self, client: &'a dyn FileSystemClient) {
+        self.client.set(client);
+    }
+
+    fn mount(&self) -> Result<(), ErrorCode> {
+        if self.mounted.get() {
+            return Err(ErrorCode::ALREADY);
+        }
+        if self.block_count <= META_BLOCKS
+            || self.block_count > u16::MAX as usize
+            || self.max_files == 0
+        {
+            return Err(ErrorCode::INVAL);
+        }
+        self.start(Op::Mount)?;
+        let meta = self.meta.take().ok_or(ErrorCode::FAIL)?;
+        self.state.set(State::MountFirst);
+        self.flash
+            .read_page(self.first_page, meta)
+            .map_err(|(err, meta)| {
+                self.meta.replace(meta);
+                self.state.set(State::Idle);
+                err
+            })
+    }
+
+    fn open(
+        &self,
+        dir: u32,
+        name: SubSliceMut<'static, u8>,
+        create: bool,
+        permissions: StoragePermissions,
+    ) -> Result<(), (ErrorCode, SubSliceMut<'static, u8>)> {
+        if let Err(err) = self.start(Op::Open) {
+            return Err((err, name));
+        }
+        let mut name = name;
+        let result = self
+            .with_meta(|meta| {
+                let found = (0..self.max_files)
+                    .find(|file| self.entry(meta, *file) == Some((dir, name.as_slice())));
+                if let Some(file) = found {
+                    self.file.set(file);
+                    return if permissions.check_read_permission(dir) {
+                        Ok(false)
+                    } else {
+                        Err(ErrorCode::NOSUPPORT)
+                    };
+                }
+
+                if !create || permissions.get_write_id() != Some(dir) {
+                    return Err(ErrorCode::NOSUPPORT);
+                }
+                let name = name.as_slice();
+                if name.is_empty() {
+                    return Err(ErrorCode::INVAL);
+                }
+                if name.len() > NAME_LEN {
+                    return Err(ErrorCode::SIZE);
+                }
+                let file = (0..self.max_files)
+                    .find(|file| self.entry(meta, *file).is_none())
+                    .ok_or(ErrorCode::NOMEM)?;
+                let entry = &mut meta[self.entry_offset(file)..self.entry_offset(file + 1)];
+                entry.fill(0);
+                set_u32(entry, 0, dir);
+                entry[10] = name.len() as u8;
+                entry[12..12 + name.len()].copy_from_slice(name);
+                self.file.set(file);
+                Ok(true)
+            })
+            .unwrap_or(Err(ErrorCode::FAIL));
+
+        self.buffer.replace(name);
+        match result {
+            Ok(true) => self.commit(),
+            Ok(false) => self.defer(Ok(())),
+            Err(err) => self.defer(Err(err)),
+        }
+        Ok(())
+    }
+
+    fn stat(&self, file: FileId, permissions: StoragePermissions) -> Result<(), ErrorCode> {
+        self.start(Op::Stat)?;
+        let readable = self
+            .with_meta(|meta| {
+                self.entry(meta, file)
+                    .is_some_and(|(dir, _)| permissions.check_read_permission(dir))
+            })
+            .unwrap_or(false);
+        self.file.set(file);
+        self.defer(if readable {
+            Ok(())
+        } else {
+            Err(ErrorCode::NOSUPPORT)
+        });
+        Ok(())
+    }
+
+    fn read(
+        &self,
+        file: FileId,
+        offset: usize,
+        buffer: SubSliceMut<'static, u8>,
+        permissions: StoragePermissions,
+    ) -> Result<(), (ErrorCode, SubSliceMut<'static, u8>)> {
+        if let Err(err) = self.start(Op::Read) {
+            return Err((err, buffer));
+        }
+        let block = self
+            .with_meta(|meta| {
+                let (dir, _) = self.entry(meta, file).ok_or(ErrorCode::NOSUPPORT)?;
+                if !permissions.check_read_permission(dir) {
+                    return Err(ErrorCode::NOSUPPORT);
+                }
+                let size = self.file_info(meta, file).size;
+                if offset >= size {
+                    return Ok(None);
+                }
+                let length = cmp::min(
+                    buffer.len(),
+                    cmp::min(self.block_size - offset % self.block_size, size - offset),
+                );
+                self.length.set(length);
+                self.nth_block(meta, self.first_block(meta, file), offset / self.block_size)
+                    .map(Some)
+                    .ok_or(ErrorCode::FAIL)
+            })
+            .unwrap_or(Err(ErrorCode::FAIL));
+
+        self.buffer.replace(buffer);
+        self.offset.set(offset);
+        self.length.set(match block {
+            Ok(Some(_)) => self.length.get(),
+            _ => 0,
+        });
+        match block {
+            Ok(Some(block)) => match self.page.take() {
+                Some(page) => {
+                    self.state.set(State::Read);
+                    if let Err((_, page)) = self.flash.read_page(self.first_page + block, page) {
+                        self.page.replace(page);
+                        self.defer(Err(ErrorCode::FAIL));
+                    }
+                }
+                None => self.defer(Err(ErrorCode::FAIL)),
+            },
+            Ok(None) => self.defer(Ok(())),
+            Err(err) => self.defer(Err(err)),
+        }
+        Ok(())
+    }
+
+    fn write(
+        &self,
+        file: FileId,
+        offset: usize,
+        buffer: SubSliceMut<'static, u8>,
+        permissions: StoragePermissions,
+    ) -> Result<(), (ErrorCode, SubSliceMut<'static, u8>)> {
+        if let Err(err) = self.start(Op::Write) {
+            return Err((err, buffer));
+        }
+        let length = cmp::min(buffer.len(), self.block_size - offset % self.block_size);
+        let blocks = self
+            .with_meta(|meta| {
+                let (dir, _) = self.entry(meta, file).ok_or(ErrorCode::NOSUPPORT)?;
+                if !permissions.check_modify_permission(dir) {
+                    return Err(ErrorCode::NOSUPPORT);
+                }
+                if offset > self.file_info(meta, file).size {
+                    return Err(ErrorCode::INVAL);
+                }
+                let old =
+                    self.nth_block(meta, self.first_block(meta, file), offset / self.block_size);
+                let new = self.allocate(meta).ok_or(ErrorCode::NOMEM)?;
+                Ok((old, new))
+            })
+            .unwrap_or(Err(ErrorCode::FAIL));
+
+        self.buffer.replace(buffer);
+        self.file.set(file);
+        self.offset.set(offset);
+        self.length.set(length);
+        match blocks {
+            Ok(_) if length == 0 => self.defer(Ok(())),
+            Ok((old, new)) => {
+                self.old_block.set(old.unwrap_or(NONE));
+                self.new_block.set(new);
+                match (self.page.take(), old) {
+                    (Some(page), Some(old)) => {
+                        // Keep the rest of the block.
+                        self.state.set(State::WriteRead);
+                        if let Err((_, page)) = self.flash.read_page(self.first_page + old, page) {
+                            self.page.replace(page);
+                            self.defer(Err(ErrorCode::FAIL));
+                        }
+                    }
+                    (Some(page), None) => {
+                        page.as_mut().fill(0xff);
+                        self.copy_write_data(page);
+                        self.write_erase(page);
+                    }
+                    (None, _) => self.defer(Err(ErrorCode::FAIL)),
+                }
+            }
+            Err(err) => self.defer(Err(err)),
+        }
+        Ok(())
+    }
+
+    fn unlink(&self, file: FileId, permissions: StoragePermissions) -> Result<(), ErrorCode> {
+        self.start(Op::Unlink)?;
+        let result = self
+            .with_meta(|meta| {
+                let (dir, _) = self.entry(meta, file).ok_or(ErrorCode::NOSUPPORT)?;
+                if !permissions.check_modify_permission(dir) {
+                    return Err(ErrorCode::NOSUPPORT);
+                }
+                let mut block = self.first_block(meta, file);
+                for _ in 0..self.block_count {
+                    if block < META_BLOCKS || block >= self.block_count {
+                        break;
+                    }
+                    let next = self.next_block(meta, block);
+                    set_u16(meta, self.map_offset(block), FREE);
+                    block = next;
+                }
+                meta[self.entry_offset(file)..self.entry_offset(file + 1)].fill(0);
+                Ok(())
+            })
+            .unwrap_or(Err(ErrorCode::FAIL));
+
+        match result {
+            Ok(()) => self.commit(),
+            Err(err) => self.defer(Err(err)),
+        }
+        Ok(())
+    }
+
+    fn read_dir(
+        &self,
+        dir: u32,
+        cursor: usize,
+        name: SubSliceMut<'static, u8>,
+        permissions: StoragePermissions,
+    ) -> Result<(), (ErrorCode, SubSliceMut<'static, u8>)> {
+        if let Err(err) = self.start(Op::ReadDir) {
+            return Err((err, name));
+        }
+        let mut name = name;
+        // There are no subdirectories.
+        let result = if name.len() > 0 {
+            Err(ErrorCode::NOSUPPORT)
+        } else if permissions.check_read_permission(dir) {
+            self.with_meta(|meta| {
+                let found = (cursor..self.max_files).find_map(|file| {
+                    self.entry(meta, file)
+                        .filter(|(d, _)| *d == dir)
+                        .map(|(_, n)| (file, n))
+                });
+                match found {
+                    Some((file, entry_name)) => {
+                        self.file.set(file);
+                        name.reset();
+                        if entry_name.len() > name.len() {
+                            return Err(ErrorCode::SIZE);
+                        }
+                        name.as_slice()[..entry_name.len()].copy_from_slice(entry_name);
+                        name.slice(..entry_name.len());
+                        Ok(())
+                    }
+                    None => {
+                        self.file.set(NONE);
+                        Ok(())
+                    }
+                }
+            })
+            .unwrap_or(Err(ErrorCode::FAIL))
+        } else {
+            Err(ErrorCode::NOSUPPORT)
+        };
+
+        self.buffer.replace(name);
+        self.defer(result);
+        Ok(())
+    }
+}
+
+impl<F: Flash> flash::Client<F> for FlashFs<'_, F> {
+    fn read_complete(&self, page: &'static mut F::Page, result: Result<(), flash::Error>) {
+        if result.is_err() {
+            match self.state.get() {
+                State::MountFirst => self.meta.replace(page),
+                _ => self.page.replace(page),
+            };
+            self.finish(Err(ErrorCode::FAIL));
+            return;
+        }
+
+        match self.state.get() {
+            State::MountFirst => {
+                self.meta.replace(page);
+                match self.page.take() {
+                    Some(page) => {
+                        self.state.set(State::MountSecond);
+                        if let Err((_, page)) = self.flash.read_page(self.first_page + 1, page) {
+                            self.page.replace(page);
+                            self.finish(Err(ErrorCode::FAIL));
+                        }
+                    }
+                    None => self.finish(Err(ErrorCode::FAIL)),
+                }
+            }
+            State::MountSecond => self.mount_read_done(page),
+            State::Read => {
+                let offset = self.offset.get() % self.block_size;
+                let length = self.length.get();
+                self.buffer.map(|buffer| {
+                    buffer.as_slice()[..length]
+                        .copy_from_slice(&page.as_mut()[offset..offset + length])
+                });
+                self.page.replace(page);
+                self.finish(Ok(()));
+            }
+            State::WriteRead => {
+                self.copy_write_data(page);
+                self.write_erase(page);
+            }
+            _ => {
+                self.page.replace(page);
+            }
+        }
+    }
+
+    fn write_complete(&self, page: &'static mut F::Page, result: Result<(), flash::Error>) {
+        self.page.replace(page);
+        if result.is_err() {
+            self.finish(Err(ErrorCode::FAIL));
+            return;
+        }
+
+        match self.state.get() {
+            State::WriteData => {
+                self.with_meta(|meta| self.link_new_block(meta));
+                self.commit();
+            }
+            State::CommitWrite => {
+                self.active_meta.set(1 - self.active_meta.get());
+                self.finish(Ok(()));
+            }
+            _ => {}
+        }
+    }
+
+    fn erase_complete(&self, result: Result<(), flash::Error>) {
+        if result.is_err() {
+            self.finish(Err(ErrorCode::FAIL));
+            return;
+        }
+
+        let (block, next_state) = match self.state.get() {
+            State::WriteErase => (self.new_block.get(), State::WriteData),
+            State::CommitErase => (1 - self.active_meta.get(), State::CommitWrite),
+            _ => return,
+        };
+        match self.page.take() {
+            Some(page) => {
+                self.state.set(next_state);
+                if let Err((_, page)) = self.flash.write_page(self.first_page + block, page) {
+                    self.page.replace(page);
+                    self.finish(Err(ErrorCode::FAIL));
+                }
+            }
+            None => self.finish(Err(ErrorCode::FAIL)),
+        }
+    }
+}
+
+impl<F: Flash> DeferredCallClient for FlashFs<'_, F> {
+    fn handle_deferred_call(&self) {
+        if self.state.get() == State::Deferred {
+            self.finish(self.result.get());
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
@@ -0,0 +1,267 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! HKDF-SHA256 key derivation (RFC 5869) on top of an HMAC-SHA256 engine.
+//!
+//! The input keying material and info are copied into the data buffer given
+//! to [`HkdfSha256::new`], so that buffer limits how large they can be: it
+//! must hold 32 bytes of working space, the info, a counter byte and the
+//! input keying material.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let hkdf = static_init!(
+//!     capsules_extra::hkdf::HkdfSha256<'static, HmacSha256Software<'static, Sha256Software<'static>>>,
+//!     capsules_extra::hkdf::HkdfSha256::new(hmac, data_buffer, digest_buffer)
+//! );
+//! kernel::hil::digest::Digest::set_client(hmac, hkdf);
+//! hkdf.set_client(client);
+//!
+//! hkdf.derive(salt, ikm, info, okm);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::digest;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::{SubSlice, SubSliceMut};
+use kernel::ErrorCode;
+
+const HASH_LEN: usize = 32;
+/// Longest salt supported, which is the block size of SHA-256.
+pub const MAX_SALT_LEN: usize = 64;
+/// Longest output HKDF-SHA256 can produce.
+pub const MAX_OUTPUT_LEN: usize = 255 * HASH_LEN;
+
+pub trait HkdfClient {
+    /// Called when a derivation started with `derive` completes.
+    ///
+    /// On success `okm` holds the output keying material.
+    fn derive_done(&self, result: Result<(), ErrorCode>, okm: &'static mut [u8]);
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum State {
+    Idle,
+    Extract,
+    Expand,
+}
+
+pub struct HkdfSha256<'a, H: digest::Digest<'a, 32> + digest::HmacSha256> {
+    hmac: &'a H,
+    client: OptionalCell<&'a dyn HkdfClient>,
+    state: Cell<State>,
+
+    /// Holds the previous output block, the info and the counter, followed
+    /// by the input keying material while extracting.
+    data_buffer: TakeCell<'static, [u8]>,
+    digest_buffer: TakeCell<'static, [u8; 32]>,
+    okm: TakeCell<'static, [u8]>,
+
+    prk: Cell<[u8; HASH_LEN]>,
+    info_len: Cell<usize>,
+    counter: Cell<u8>,
+    produced: Cell<usize>,
+}
+
+impl<'a, H: digest::Digest<'a, 32> + digest::HmacSha256> HkdfSha256<'a, H> {
+    pub fn new(
+        hmac: &'a H,
+        data_buffer: &'static mut [u8],
+        digest_buffer: &'static mut [u8; 32],
+    ) -> Self {
+        HkdfSha256 {
+            hmac,
+            client: OptionalCell::empty(),
+            state: Cell::new(State::Idle),
+            data_buffer: TakeCell::new(data_buffer),
+            digest_buffer: TakeCell::new(digest_buffer),
+            okm: TakeCell::empty(),
+            prk: Cell::new([0; HASH_LEN]),
+            info_len: Cell::new(0),
+            counter: Cell::new(0),
+            produced: Cell::new(0),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn HkdfClient) {
+        self.client.set(client);
+    }
+
+    /// Derive `okm.len()` bytes of keying material from `ikm`.
+    ///
+    /// An empty `salt` is treated as 32 zero bytes, as RFC 5869 specifies.
+    ///
+    /// ### Return
+    ///
+    /// - On success returns `Ok(())` and `derive_done` will be called.
+    /// - On error, returns `okm` and:
+    ///   - `BUSY`: A derivation is already in progress.
+    ///   - `SIZE`: `okm` is empty or longer than `MAX_OUTPUT_LEN`, `salt` is
+    ///     longer than `MAX_SALT_LEN`, or `ikm` and `info` do not fit in the
+    ///     data buffer.
+    pub fn derive(
+        &self,
+        salt: &[u8],
+        ikm: &[u8],
+        info: &[u8],
+        okm: &'static mut [u8],
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.state.get() != State::Idle {
+            return Err((ErrorCode::BUSY, okm));
+        }
+        if okm.is_empty() || okm.len() > MAX_OUTPUT_LEN || salt.len() > MAX_SALT_LEN {
+            return Err((ErrorCode::SIZE, okm));
+        }
+
+        let ikm_start = HASH_LEN + info.len() + 1;
+        let ikm_end = ikm_start + ikm.len();
+        let copied = self.data_buffer.map_or(Err(ErrorCode::BUSY), |buf| {
+            if buf.len() < ikm_end {
+                return Err(ErrorCode::SIZE);
+            }
+            buf[HASH_LEN..HASH_LEN + info.len()].copy_from_slice(info);
+            buf[ikm_start..ikm_end].copy_from_slice(ikm);
+            Ok(())
+        });
+        if let Err(e) = copied {
+            return Err((e, okm));
+        }
+
+        self.info_len.set(info.len());
+        self.produced.set(0);
+        self.state.set(State::Extract);
+
+        // PRK = HMAC-Hash(salt, IKM)
+        let result = if salt.is_empty() {
+            self.start_hmac(&[0; HASH_LEN], ikm_start, ikm_end)
+        } else {
+            self.start_hmac(salt, ikm_start, ikm_end)
+        };
+        match result {
+            Ok(()) => {
+                self.okm.replace(okm);
+                Ok(())
+            }
+            Err(e) => {
+                self.state.set(State::Idle);
+                self.scrub();
+                Err((e, okm))
+            }
+        }
+    }
+
+    /// Start an HMAC over `data_buffer[start..end]`.
+    fn start_hmac(&self, key: &[u8], start: usize, end: usize) -> Result<(), ErrorCode> {
+        self.hmac.set_mode_hmacsha256(key)?;
+        let buf = self.data_buffer.take().ok_or(ErrorCode::FAIL)?;
+        let mut data = SubSliceMut::new(buf);
+        data.slice(start..end);
+        self.hmac.add_mut_data(data).map_err(|(e, data)| {
+            self.data_buffer.replace(data.take());
+            e
+        })
+    }
+
+    /// T(i) = HMAC-Hash(PRK, T(i-1) | info | i), where T(0) is empty.
+    fn expand_block(&self) -> Result<(), ErrorCode> {
+        let counter = self.counter.get();
+        let counter_offset = HASH_LEN + self.info_len.get();
+        self.data_buffer.map(|buf| buf[counter_offset] = counter);
+        let start = if counter == 1 { HASH_LEN } else { 0 };
+        self.start_hmac(&self.prk.get(), start, counter_offset + 1)
+    }
+
+    /// Erase the intermediate keys.
+    fn scrub(&self) {
+        self.prk.set([0; HASH_LEN]);
+        self.data_buffer
+            .map(|buf| buf.iter_mut().for_each(|b| *b = 0));
+    }
+
+    fn done(&self, result: Result<(), ErrorCode>) {
+        self.state.set(State::Idle);
+        self.scrub();
+        if result.is_err() {
+            self.hmac.clear_data();
+        }
+        self.okm.take().map(|okm| {
+            self.client.map(|client| client.derive_done(result, okm));
+        });
+    }
+}
+
+impl<'a, H: digest::Digest<'a, 32> + digest::HmacSha256> digest::ClientData<32>
+    for HkdfSha256<'a, H>
+{
+    fn add_data_done(&self, _result: Result<(), ErrorCode>, _data: SubSlice<'static, u8>) {}
+
+    fn add_mut_data_done(&self, result: Result<(), ErrorCode>, data: SubSliceMut<'static, u8>) {
+        self.data_buffer.replace(data.take());
+        if self.state.get() == State::Idle {
+            return;
+        }
+
+        let result = result.and_then(|()| {
+            let digest = self.digest_buffer.take().ok_or(ErrorCode::FAIL)?;
+            self.hmac.run(digest).map_err(|(e, digest)| {
+                self.digest_buffer.replace(digest);
+                e
+            })
+        });
+        if let Err(e) = result {
+            self.done(Err(e));
+        }
+    }
+}
+
+impl<'a, H: digest::Digest<'a, 32> + digest::HmacSha256> digest::ClientHash<32>
+    for HkdfSha256<'a, H>
+{
+    fn hash_done(&self, result: Result<(), ErrorCode>, digest: &'static mut [u8; 32]) {
+        let output = *digest;
+        digest.iter_mut().for_each(|b| *b = 0);
+        self.digest_buffer.replace(digest);
+
+        let result = result.and_then(|()| match self.state.get() {
+            State::Extract => {
+                self.prk.set(output);
+                self.state.set(State::Expand);
+                self.counter.set(1);
+                self.expand_block()
+            }
+            State::Expand => {
+                let produced = self.produced.get();
+                let remaining = self.okm.map_or(0, |okm| {
+                    let n = core::cmp::min(HASH_LEN, okm.len() - produced);
+                    okm[produced..produced + n].copy_from_slice(&output[..n]);
+                    self.produced.set(produced + n);
+                    okm.len() - produced - n
+                });
+                if remaining == 0 {
+                    self.done(Ok(()));
+                    return Ok(());
+                }
+
+                self.data_buffer
+                    .map(|buf| buf[..HASH_LEN].copy_from_slice(&output));
+                self.counter.set(self.counter.get() + 1);
+                self.expand_block()
+            }
+            State::Idle => Ok(()),
+        });
+        if let Err(e) = result {
+            self.done(Err(e));
+        }
+    }
+}
+
+impl<'a, H: digest::Digest<'a, 32> + digest::HmacSha256> digest::ClientVerify<32>
+    for HkdfSha256<'a, H>
+{
+    fn verification_done(&self, _result: Result<bool, ErrorCode>, _compare: &'static mut [u8; 32]) {
+    }
+}
@@ -50,6 +50,8 @@ mod rw_allow {
 
 use core::cell::Cell;
 
+use crate::key_store::KeyResolver;
+
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
 use kernel::hil::digest;
 use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
@@ -65,6 +67,17 @@ enum ShaOperation {
     Sha512,
 }
 
+impl ShaOperation {
+    /// Length of the digest this algorithm produces.
+    fn output_len(&self) -> usize {
+        match self {
+            ShaOperation::Sha256 => 32,
+            ShaOperation::Sha384 => 48,
+            ShaOperation::Sha512 => 64,
+        }
+    }
+}
+
 // Temporary buffer to copy the keys from userspace into
 //
 // Needs to be able to accommodate the largest key sizes, e.g. 512
@@ -72,6 +85,7 @@ const TMP_KEY_BUFFER_SIZE: usize = 512 / 8;
 
 pub struct HmacDriver<'a, H: digest::Digest<'a, L>, const L: usize> {
     hmac: &'a H,
+    key_store: OptionalCell<&'a dyn KeyResolver>,
 
     active: Cell<bool>,
 
@@ -107,6 +121,7 @@ impl<
     ) -> HmacDriver<'a, H, L> {
         HmacDriver {
             hmac,
+            key_store: OptionalCell::empty(),
             active: Cell::new(false),
             apps: grant,
             processid: OptionalCell::empty(),
@@ -116,37 +131,47 @@ impl<
         }
     }
 
+    /// Lets apps use keys they have opened in `key_store` instead of passing
+    /// key bytes in an allow buffer.
+    pub fn set_key_store(&self, key_store: &'a dyn KeyResolver) {
+        self.key_store.set(key_store);
+    }
+
     fn run(&self) -> Result<(), ErrorCode> {
         self.processid.map_or(Err(ErrorCode::RESERVE), |processid| {
             self.apps
                 .enter(processid, |app, kernel_data| {
-                    kernel_data
-                        .get_readonly_processbuffer(ro_allow::KEY)
-                        .and_then(|key| {
-                            key.enter(|k| {
-                                if let Some(op) = &app.sha_operation {
-                                    let mut tmp_key_buffer: [u8; TMP_KEY_BUFFER_SIZE] =
-                                        [0; TMP_KEY_BUFFER_SIZE];
+                    let op = app.sha_operation.as_ref().ok_or(ErrorCode::INVAL)?;
+
+                    let mut tmp_key_buffer: [u8; TMP_KEY_BUFFER_SIZE] = [0; TMP_KEY_BUFFER_SIZE];
+                    let key_len = match app.key_handle {
+                        // The key never passes through process memory.
+                        Some(handle) => self.key_store.map_or(Err(ErrorCode::NOSUPPORT), |ks| {
+                            ks.copy_key(processid, handle, &mut tmp_key_buffer)
+                        })?,
+                        None => kernel_data
+                            .get_readonly_processbuffer(ro_allow::KEY)
+                            .and_then(|key| {
+                                key.enter(|k| {
                                     let key_len = core::cmp::min(k.len(), TMP_KEY_BUFFER_SIZE);
                                     k[..key_len].copy_to_slice(&mut tmp_key_buffer[..key_len]);
-
-                                    match op {
-                                        ShaOperation::Sha256 => self
-                                            .hmac
-                                            .set_mode_hmacsha256(&tmp_key_buffer[..key_len]),
-                                        ShaOperation::Sha384 => self
-                                            .hmac
-                                            .set_mode_hmacsha384(&tmp_key_buffer[..key_len]),
-                                        ShaOperation::Sha512 => self
-                                            .hmac
-                                            .set_mode_hmacsha512(&tmp_key_buffer[..key_len]),
-                                    }
-                                } else {
-                                    Err(ErrorCode::INVAL)
-                                }
+                                    key_len
+                                })
                             })
-                        })
-                        .unwrap_or(Err(ErrorCode::RESERVE))?;
+                            .map_err(|_| ErrorCode::RESERVE)?,
+                    };
+
+                    match op {
+                        ShaOperation::Sha256 => {
+                            self.hmac.set_mode_hmacsha256(&tmp_key_buffer[..key_len])
+                        }
+                        ShaOperation::Sha384 => {
+                            self.hmac.set_mode_hmacsha384(&tmp_key_buffer[..key_len])
+                        }
+                        ShaOperation::Sha512 => {
+                            self.hmac.set_mode_hmacsha512(&tmp_key_buffer[..key_len])
+                        }
+                    }?;
 
                     kernel_data
                         .get_readonly_processbuffer(ro_allow::DATA)
@@ -391,7 +416,7 @@ impl<
     fn hash_done(&self, result: Result<(), ErrorCode>, digest: &'static mut [u8; L]) {
         self.processid.map(|id| {
             self.apps
-                .enter(id, |_, kernel_data| {
+                .enter(id, |app, kernel_data| {
                     self.hmac.clear_data();
 
                     let pointer = digest[0] as *mut u8;
@@ -400,13 +425,14 @@ impl<
                         .get_readwrite_processbuffer(rw_allow::DEST)
                         .and_then(|dest| {
                             dest.mut_enter(|dest| {
-                                let len = dest.len();
-
-                                if len < L {
-                                    dest.copy_from_slice(&digest[0..len]);
-                                } else {
-                                    dest[0..L].copy_from_slice(digest);
-                                }
+                                // Only copy the digest of the selected
+                                // algorithm, which may be shorter than `L`.
+                                let output_len = app
+                                    .sha_operation
+                                    .as_ref()
+                                    .map_or(L, |op| op.output_len().min(L));
+                                let len = dest.len().min(output_len);
+                                dest[0..len].copy_from_slice(&digest[0..len]);
                             })
                         });
 
@@ -518,6 +544,10 @@ impl<
     /// - `1`: run
     /// - `2`: update
     /// - `3`: finish
+    /// - `4`: verify
+    /// - `5`: verify_finish
+    /// - `6`: use the key store handle `data1` as the key
+    /// - `7`: use the key in the key allow buffer
     fn command(
         &self,
         command_num: usize,
@@ -739,6 +769,22 @@ impl<
                         }
                     }
 
+                    // use key handle
+                    6 => {
+                        if self.key_store.is_some() {
+                            app.key_handle = Some(data1);
+                            CommandReturn::success()
+                        } else {
+                            CommandReturn::failure(ErrorCode::NOSUPPORT)
+                        }
+                    }
+
+                    // use key buffer
+                    7 => {
+                        app.key_handle = None;
+                        CommandReturn::success()
+                    }
+
                     // default
                     _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
                 }
@@ -763,4 +809,5 @@ pub struct App {
     pending_run_app: Option<ProcessId>,
     sha_operation: Option<ShaOperation>,
     op: Cell<Option<UserSpaceOp>>,
+    key_handle: Option<usize>,
 }
@@ -0,0 +1,406 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! HMAC-DRBG deterministic random bit generator (NIST SP 800-90A) using
+//! HMAC-SHA256.
+//!
+//! The DRBG seeds itself from an `Entropy32` source the first time random
+//! numbers are requested, and reseeds after every `RESEED_INTERVAL` generate
+//! requests. Between reseeds randomness is produced only with HMAC
+//! operations, so this provides high-rate random numbers on chips with a
+//! slow TRNG. It implements `Rng` and can be used anywhere an `Rng` is
+//! expected, such as below the `RngDriver`.
+//!
+//! Each generate request produces `OUTPUT_LEN` bytes that are handed to the
+//! client as 32-bit words. Output that the client does not consume is
+//! discarded.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let drbg = static_init!(
+//!     capsules_extra::hmac_drbg::HmacDrbg<'static, HmacSha256Software<'static, Sha256Software<'static>>, Trng>,
+//!     capsules_extra::hmac_drbg::HmacDrbg::new(hmac, trng, data_buffer, digest_buffer)
+//! );
+//! kernel::hil::digest::Digest::set_client(hmac, drbg);
+//! kernel::hil::entropy::Entropy32::set_client(trng, drbg);
+//! kernel::hil::rng::Rng::set_client(drbg, client);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::digest;
+use kernel::hil::entropy;
+use kernel::hil::rng;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::{SubSlice, SubSliceMut};
+use kernel::ErrorCode;
+
+const HASH_LEN: usize = 32;
+/// Bytes of entropy collected to instantiate or reseed. This is the 256-bit
+/// security strength plus a 128-bit nonce.
+pub const SEED_LEN: usize = 48;
+/// Bytes produced by each generate request.
+pub const OUTPUT_LEN: usize = 4 * HASH_LEN;
+/// Number of generate requests between reseeds.
+pub const RESEED_INTERVAL: usize = 1024;
+/// Size of the data buffer required by [`HmacDrbg::new`].
+pub const DATA_BUFFER_LEN: usize = HASH_LEN + 1 + SEED_LEN;
+
+#[derive(Copy, Clone, PartialEq)]
+enum State {
+    Idle,
+    /// Waiting for entropy.
+    Seeding,
+    /// Computing K = HMAC(K, V || round || provided_data).
+    UpdateKey(u8),
+    /// Computing V = HMAC(K, V).
+    UpdateValue(u8),
+    /// Computing output blocks V = HMAC(K, V).
+    Generate,
+}
+
+pub struct HmacDrbg<'a, H: digest::Digest<'a, 32> + digest::HmacSha256, E: entropy::Entropy32<'a>> {
+    hmac: &'a H,
+    entropy: &'a E,
+    client: OptionalCell<&'a dyn rng::Client>,
+    state: Cell<State>,
+    cancelled: Cell<bool>,
+
+    key: Cell<[u8; HASH_LEN]>,
+    value: Cell<[u8; HASH_LEN]>,
+    seeded: Cell<bool>,
+    reseed_counter: Cell<usize>,
+
+    seed: Cell<[u8; SEED_LEN]>,
+    seed_len: Cell<usize>,
+    /// Length of the provided data for the update in progress.
+    provided_len: Cell<usize>,
+
+    output: Cell<[u8; OUTPUT_LEN]>,
+    output_len: Cell<usize>,
+
+    /// Holds V, the update round byte and the provided data.
+    data_buffer: TakeCell<'static, [u8]>,
+    digest_buffer: TakeCell<'static, [u8; 32]>,
+}
+
+impl<'a, H: digest::Digest<'a, 32> + digest::HmacSha256, E: entropy::Entropy32<'a>>
+    HmacDrbg<'a, H, E>
+{
+    /// `data_buffer` must be at least `DATA_BUFFER_LEN` bytes long.
+    pub fn new(
+        hmac: &'a H,
+        entropy: &'a E,
+        data_buffer: &'static mut [u8],
+        digest_buffer: &'static mut [u8; 32],
+    ) -> Self {
+        HmacDrbg {
+            hmac,
+            entropy,
+            client: OptionalCell::empty(),
+            state: Cell::new(State::Idle),
+            cancelled: Cell::new(false),
+            key: Cell::new([0; HASH_LEN]),
+            value: Cell::new([0; HASH_LEN]),
+            seeded: Cell::new(false),
+            reseed_counter: Cell::new(0),
+            seed: Cell::new([0; SEED_LEN]),
+            seed_len: Cell::new(0),
+            provided_len: Cell::new(0),
+            output: Cell::new([0; OUTPUT_LEN]),
+            output_len: Cell::new(0),
+            data_buffer: TakeCell::new(data_buffer),
+            digest_buffer: TakeCell::new(digest_buffer),
+        }
+    }
+
+    /// Start a generate request, first collecting entropy if the DRBG has
+    /// not been seeded or is due for a reseed.
+    fn request(&self) -> Result<(), ErrorCode> {
+        if !self.seeded.get() || self.reseed_counter.get() > RESEED_INTERVAL {
+            self.seed_len.set(0);
+            self.state.set(State::Seeding);
+            self.entropy.get()
+        } else {
+            self.generate()
+        }
+    }
+
+    fn generate(&self) -> Result<(), ErrorCode> {
+        self.output_len.set(0);
+        self.state.set(State::Generate);
+        self.hmac_value()
+    }
+
+    /// Run the HMAC_DRBG_Update function with the first `provided_len` bytes
+    /// of the seed as the provided data.
+    fn update(&self, provided_len: usize) -> Result<(), ErrorCode> {
+        self.provided_len.set(provided_len);
+        let seed = self.seed.get();
+        self.data_buffer.map(|buf| {
+            buf[HASH_LEN + 1..HASH_LEN + 1 + provided_len].copy_from_slice(&seed[..provided_len])
+        });
+        self.update_key(0)
+    }
+
+    /// K = HMAC(K, V || round || provided_data)
+    fn update_key(&self, round: u8) -> Result<(), ErrorCode> {
+        self.state.set(State::UpdateKey(round));
+        let value = self.value.get();
+        self.data_buffer.map(|buf| {
+            buf[..HASH_LEN].copy_from_slice(&value);
+            buf[HASH_LEN] = round;
+        });
+        self.start_hmac(HASH_LEN + 1 + self.provided_len.get())
+    }
+
+    /// V = HMAC(K, V)
+    fn hmac_value(&self) -> Result<(), ErrorCode> {
+        let value = self.value.get();
+        self.data_buffer
+            .map(|buf| buf[..HASH_LEN].copy_from_slice(&value));
+        self.start_hmac(HASH_LEN)
+    }
+
+    /// Start an HMAC keyed with K over `data_buffer[..len]`.
+    fn start_hmac(&self, len: usize) -> Result<(), ErrorCode> {
+        self.hmac.set_mode_hmacsha256(&self.key.get())?;
+        let buf = self.data_buffer.take().ok_or(ErrorCode::FAIL)?;
+        let mut data = SubSliceMut::new(buf);
+        data.slice(..len);
+        self.hmac.add_mut_data(data).map_err(|(e, data)| {
+            self.data_buffer.replace(data.take());
+            e
+        })
+    }
+
+    /// Called when an update finishes.
+    fn update_done(&self) -> Result<(), ErrorCode> {
+        self.seed.set([0; SEED_LEN]);
+        self.data_buffer
+            .map(|buf| buf.iter_mut().for_each(|b| *b = 0));
+
+        if self.output_len.get() == OUTPUT_LEN {
+            // This update followed a generate request.
+            self.reseed_counter.set(self.reseed_counter.get() + 1);
+            self.deliver(Ok(()));
+            Ok(())
+        } else {
+            // This update (re)seeded the DRBG.
+            self.seeded.set(true);
+            self.reseed_counter.set(1);
+            if self.cancelled.get() {
+                self.deliver(Err(ErrorCode::CANCEL));
+                Ok(())
+            } else {
+                self.generate()
+            }
+        }
+    }
+
+    /// Pass the output to the client and generate more if it asks for it.
+    fn deliver(&self, result: Result<(), ErrorCode>) {
+        self.state.set(State::Idle);
+        let output = self.output.get();
+        let len = if result.is_ok() {
+            self.output_len.get()
+        } else {
+            0
+        };
+        self.output.set([0; OUTPUT_LEN]);
+        self.output_len.set(0);
+
+        let result = if self.cancelled.get() {
+            Err(ErrorCode::CANCEL)
+        } else {
+            result
+        };
+        self.cancelled.set(false);
+
+        let mut words = output[..len]
+            .chunks_exact(4)
+            .map(|chunk| u32::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]));
+        let more = self.client.map_or(rng::Continue::Done, |client| {
+            client.randomness_available(&mut words, result)
+        });
+
+        if result.is_ok() && more == rng::Continue::More {
+            if let Err(e) = self.request() {
+                self.fail(e);
+            }
+        }
+    }
+
+    /// Abort the current request. The internal state may be inconsistent so
+    /// force a reseed before the next request.
+    fn fail(&self, error: ErrorCode) {
+        self.seeded.set(false);
+        self.seed.set([0; SEED_LEN]);
+        self.hmac.clear_data();
+        self.deliver(Err(error));
+    }
+}
+
+impl<'a, H: digest::Digest<'a, 32> + digest::HmacSha256, E: entropy::Entropy32<'a>> rng::Rng<'a>
+    for HmacDrbg<'a, H, E>
+{
+    fn get(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.cancelled.set(false);
+        self.request().inspect_err(|_| {
+            self.state.set(State::Idle);
+        })
+    }
+
+    fn cancel(&self) -> Result<(), ErrorCode> {
+        match self.state.get() {
+            State::Idle => Ok(()),
+            State::Seeding if self.entropy.cancel().is_ok() => {
+                self.state.set(State::Idle);
+                Ok(())
+            }
+            _ => {
+                self.cancelled.set(true);
+                Err(ErrorCode::FAIL)
+            }
+        }
+    }
+
+    fn set_client(&'a self, client: &'a dyn rng::Client) {
+        self.client.set(client);
+    }
+}
+
+impl<'a, H: digest::Digest<'a, 32> + digest::HmacSha256, E: entropy::Entropy32<'a>>
+    entropy::Client32 for HmacDrbg<'a, H, E>
+{
+    fn entropy_available(
+        &self,
+        entropy: &mut dyn Iterator<Item = u32>,
+        error: Result<(), ErrorCode>,
+    ) -> entropy::Continue {
+        if self.state.get() != State::Seeding {
+            return entropy::Continue::Done;
+        }
+        if self.cancelled.get() {
+            self.deliver(Err(ErrorCode::CANCEL));
+            return entropy::Continue::Done;
+        }
+        if let Err(e) = error {
+            self.fail(e);
+            return entropy::Continue::Done;
+        }
+
+        let mut seed = self.seed.get();
+        let mut seed_len = self.seed_len.get();
+        while seed_len < SEED_LEN {
+            match entropy.next() {
+                Some(word) => {
+                    seed[seed_len..seed_len + 4].copy_from_slice(&word.to_ne_bytes());
+                    seed_len += 4;
+                }
+                None => break,
+            }
+        }
+        self.seed.set(seed);
+        self.seed_len.set(seed_len);
+        if seed_len < SEED_LEN {
+            return entropy::Continue::More;
+        }
+
+        if !self.seeded.get() {
+            // Instantiate: K = 0x00 00...00, V = 0x01 01...01
+            self.key.set([0; HASH_LEN]);
+            self.value.set([1; HASH_LEN]);
+        }
+        if let Err(e) = self.update(SEED_LEN) {
+            self.fail(e);
+        }
+        entropy::Continue::Done
+    }
+}
+
+impl<'a, H: digest::Digest<'a, 32> + digest::HmacSha256, E: entropy::Entropy32<'a>>
+    digest::ClientData<32> for HmacDrbg<'a, H, E>
+{
+    fn add_data_done(&self, _result: Result<(), ErrorCode>, _data: SubSlice<'static, u8>) {}
+
+    fn add_mut_data_done(&self, result: Result<(), ErrorCode>, data: SubSliceMut<'static, u8>) {
+        self.data_buffer.replace(data.take());
+        if self.state.get() == State::Idle {
+            return;
+        }
+
+        let result = result.and_then(|()| {
+            let digest = self.digest_buffer.take().ok_or(ErrorCode::FAIL)?;
+            self.hmac.run(digest).map_err(|(e, digest)| {
+                self.digest_buffer.replace(digest);
+                e
+            })
+        });
+        if let Err(e) = result {
+            self.fail(e);
+        }
+    }
+}
+
+impl<'a, H: digest::Digest<'a, 32> + digest::HmacSha256, E: entropy::Entropy32<'a>>
+    digest::ClientHash<32> for HmacDrbg<'a, H, E>
+{
+    fn hash_done(&self, result: Result<(), ErrorCode>, digest: &'static mut [u8; 32]) {
+        let output = *digest;
+        digest.iter_mut().for_each(|b| *b = 0);
+        self.digest_buffer.replace(digest);
+        if let Err(e) = result {
+            self.fail(e);
+            return;
+        }
+
+        let result = match self.state.get() {
+            State::UpdateKey(round) => {
+                self.key.set(output);
+                self.state.set(State::UpdateValue(round));
+                self.hmac_value()
+            }
+            State::UpdateValue(round) => {
+                self.value.set(output);
+                if round == 0 && self.provided_len.get() > 0 {
+                    self.update_key(1)
+                } else {
+                    self.update_done()
+                }
+            }
+            State::Generate => {
+                self.value.set(output);
+                let len = self.output_len.get();
+                let mut buf = self.output.get();
+                buf[len..len + HASH_LEN].copy_from_slice(&output);
+                self.output.set(buf);
+                self.output_len.set(len + HASH_LEN);
+                if len + HASH_LEN < OUTPUT_LEN {
+                    self.hmac_value()
+                } else {
+                    // Update the state with no provided data so earlier
+                    // output cannot be recovered from it.
+                    self.update(0)
+                }
+            }
+            State::Idle | State::Seeding => Ok(()),
+        };
+        if let Err(e) = result {
+            self.fail(e);
+        }
+    }
+}
+
+impl<'a, H: digest::Digest<'a, 32> + digest::HmacSha256, E: entropy::Entropy32<'a>>
+    digest::ClientVerify<32> for HmacDrbg<'a, H, E>
+{
+    fn verification_done(&self, _result: Result<bool, ErrorCode>, _compare: &'static mut [u8; 32]) {
+    }
+}
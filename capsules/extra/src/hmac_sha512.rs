@@ -0,0 +1,366 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Software implementation of HMAC-SHA384 and HMAC-SHA512.
+//!
+//! This uses the SHA-384/SHA-512 implementation from
+//! [`sha512`](crate::sha512) directly rather than through the digest HIL, so
+//! the whole HMAC is computed synchronously in the method calls and callbacks
+//! are issued from a deferred call. The output is always returned in a 64
+//! byte buffer; for HMAC-SHA384 only the first 48 bytes are written and
+//! compared.
+//!
+//! A key must be set with `set_mode_hmacsha384` or `set_mode_hmacsha512`
+//! before adding data, and is cleared once the HMAC has been computed. Keys
+//! longer than the 128 byte block size are hashed first, as RFC 2104
+//! specifies. HMAC-SHA256 is not supported.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let hmac = static_init!(HmacSha512Software<'static>, HmacSha512Software::new());
+//! hmac.register();
+//! kernel::hil::digest::Digest::set_client(hmac, client);
+//! ```
+
+use core::cell::Cell;
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+
+use kernel::hil::digest::{Client, ClientData, ClientHash, ClientVerify};
+use kernel::hil::digest::{ClientDataHash, ClientDataVerify, DigestDataHash, DigestDataVerify};
+use kernel::hil::digest::{Digest, DigestData, DigestHash, DigestVerify};
+use kernel::hil::digest::{HmacSha256, HmacSha384, HmacSha512};
+use kernel::utilities::cells::{MapCell, OptionalCell};
+use kernel::utilities::leasable_buffer::SubSlice;
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::utilities::leasable_buffer::SubSliceMutImmut;
+use kernel::ErrorCode;
+
+use crate::sha512::{Mode, Sha512State, SHA_512_BLOCK_LEN_BYTES, SHA_512_OUTPUT_LEN_BYTES};
+
+/// Value to XOR the key with on the inner hash.
+const INNER_PAD_BYTE: u8 = 0x36;
+/// Value to XOR the key with on the outer hash.
+const OUTER_PAD_BYTE: u8 = 0x5c;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum State {
+    Idle,
+    Data,
+    Hash,
+    Verify,
+    CancelData,
+    CancelHash,
+    CancelVerify,
+}
+
+pub struct HmacSha512Software<'a> {
+    state: Cell<State>,
+    mode: Cell<Mode>,
+
+    client: OptionalCell<&'a dyn Client<SHA_512_OUTPUT_LEN_BYTES>>,
+    input_data: OptionalCell<SubSliceMutImmut<'static, u8>>,
+    /// The inner hash, which has the key XORed with the inner pad as its
+    /// first block. Empty if no key has been set.
+    inner: MapCell<Sha512State>,
+    /// The key XORed with the outer pad.
+    outer_key: MapCell<[u8; SHA_512_BLOCK_LEN_BYTES]>,
+
+    // Used to store the HMAC or the HMAC to compare against with verify
+    output_data: Cell<Option<&'static mut [u8; SHA_512_OUTPUT_LEN_BYTES]>>,
+    verified: Cell<bool>,
+
+    deferred_call: DeferredCall,
+}
+
+impl HmacSha512Software<'_> {
+    pub fn new() -> Self {
+        Self {
+            state: Cell::new(State::Idle),
+            mode: Cell::new(Mode::Sha512),
+            client: OptionalCell::empty(),
+            input_data: OptionalCell::empty(),
+            inner: MapCell::empty(),
+            outer_key: MapCell::new([0; SHA_512_BLOCK_LEN_BYTES]),
+            output_data: Cell::new(None),
+            verified: Cell::new(false),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    pub fn busy(&self) -> bool {
+        self.state.get() != State::Idle
+    }
+
+    /// Forget the key and any data added so far.
+    fn initialize(&self) {
+        let new_state = match self.state.get() {
+            State::Idle => State::Idle,
+            State::Data | State::CancelData => State::CancelData,
+            State::Hash | State::CancelHash => State::CancelHash,
+            State::Verify | State::CancelVerify => State::CancelVerify,
+        };
+        self.state.set(new_state);
+        self.inner.take();
+        self.outer_key.map(|key| key.fill(0));
+    }
+
+    fn set_key(&self, mode: Mode, key: &[u8]) -> Result<(), ErrorCode> {
+        if self.busy() {
+            return Err(ErrorCode::BUSY);
+        }
+
+        let mut key_block = [0; SHA_512_BLOCK_LEN_BYTES];
+        if key.len() > SHA_512_BLOCK_LEN_BYTES {
+            let mut hash = Sha512State::new(mode);
+            hash.update(key);
+            let digest = hash.finish();
+            key_block[..mode.output_len()].copy_from_slice(&digest[..mode.output_len()]);
+        } else {
+            key_block[..key.len()].copy_from_slice(key);
+        }
+
+        let mut inner = Sha512State::new(mode);
+        key_block.iter_mut().for_each(|b| *b ^= INNER_PAD_BYTE);
+        inner.update(&key_block);
+        key_block
+            .iter_mut()
+            .for_each(|b| *b ^= INNER_PAD_BYTE ^ OUTER_PAD_BYTE);
+        self.outer_key.replace(key_block);
+        key_block.fill(0);
+
+        self.mode.set(mode);
+        self.inner.replace(inner);
+        Ok(())
+    }
+
+    /// Finish the inner hash and compute the outer hash over it.
+    fn finish(&self) -> Result<[u8; SHA_512_OUTPUT_LEN_BYTES], ErrorCode> {
+        let mode = self.mode.get();
+        let mut inner = self.inner.take().ok_or(ErrorCode::OFF)?;
+        let inner_digest = inner.finish();
+
+        let mut outer = Sha512State::new(mode);
+        self.outer_key.map(|key| outer.update(key));
+        outer.update(&inner_digest[..mode.output_len()]);
+        Ok(outer.finish())
+    }
+}
+
+impl<'a> DigestData<'a, 64> for HmacSha512Software<'a> {
+    fn add_data(
+        &self,
+        mut data: SubSlice<'static, u8>,
+    ) -> Result<(), (ErrorCode, SubSlice<'static, u8>)> {
+        if self.busy() {
+            Err((ErrorCode::BUSY, data))
+        } else if self.inner.is_none() {
+            Err((ErrorCode::OFF, data))
+        } else {
+            self.state.set(State::Data);
+            self.inner.map(|inner| inner.update(data.as_slice()));
+            data.slice(data.len()..data.len());
+            self.input_data.set(SubSliceMutImmut::Immutable(data));
+            self.deferred_call.set();
+            Ok(())
+        }
+    }
+
+    fn add_mut_data(
+        &self,
+        mut data: SubSliceMut<'static, u8>,
+    ) -> Result<(), (ErrorCode, SubSliceMut<'static, u8>)> {
+        if self.busy() {
+            Err((ErrorCode::BUSY, data))
+        } else if self.inner.is_none() {
+            Err((ErrorCode::OFF, data))
+        } else {
+            self.state.set(State::Data);
+            self.inner.map(|inner| inner.update(data.as_slice()));
+            data.slice(data.len()..data.len());
+            self.input_data.set(SubSliceMutImmut::Mutable(data));
+            self.deferred_call.set();
+            Ok(())
+        }
+    }
+
+    fn clear_data(&self) {
+        self.initialize();
+    }
+
+    fn set_data_client(&'a self, _client: &'a (dyn ClientData<64> + 'a)) {
+        unimplemented!()
+    }
+}
+
+impl<'a> DigestHash<'a, 64> for HmacSha512Software<'a> {
+    fn run(
+        &'a self,
+        digest: &'static mut [u8; 64],
+    ) -> Result<(), (ErrorCode, &'static mut [u8; 64])> {
+        if self.busy() {
+            return Err((ErrorCode::BUSY, digest));
+        }
+        match self.finish() {
+            Ok(hmac) => {
+                let output_len = self.mode.get().output_len();
+                digest[..output_len].copy_from_slice(&hmac[..output_len]);
+                self.state.set(State::Hash);
+                self.output_data.set(Some(digest));
+                self.deferred_call.set();
+                Ok(())
+            }
+            Err(e) => Err((e, digest)),
+        }
+    }
+
+    fn set_hash_client(&'a self, _client: &'a (dyn ClientHash<64> + 'a)) {
+        unimplemented!()
+    }
+}
+
+impl<'a> DigestVerify<'a, 64> for HmacSha512Software<'a> {
+    fn verify(
+        &'a self,
+        compare: &'static mut [u8; 64],
+    ) -> Result<(), (ErrorCode, &'static mut [u8; 64])> {
+        if self.busy() {
+            return Err((ErrorCode::BUSY, compare));
+        }
+        match self.finish() {
+            Ok(hmac) => {
+                let output_len = self.mode.get().output_len();
+                // Compare every byte so the time taken does not depend on
+                // where the first difference is.
+                let difference = hmac[..output_len]
+                    .iter()
+                    .zip(compare[..output_len].iter())
+                    .fold(0, |acc, (a, b)| acc | (a ^ b));
+                self.verified.set(difference == 0);
+                self.state.set(State::Verify);
+                self.output_data.set(Some(compare));
+                self.deferred_call.set();
+                Ok(())
+            }
+            Err(e) => Err((e, compare)),
+        }
+    }
+
+    fn set_verify_client(&'a self, _client: &'a (dyn ClientVerify<64> + 'a)) {
+        unimplemented!()
+    }
+}
+
+impl<'a> Digest<'a, 64> for HmacSha512Software<'a> {
+    fn set_client(&'a self, client: &'a dyn Client<64>) {
+        self.client.set(client);
+    }
+}
+
+impl DeferredCallClient for HmacSha512Software<'_> {
+    fn handle_deferred_call(&self) {
+        let prior = self.state.get();
+        self.state.set(State::Idle);
+        match prior {
+            State::Idle => {}
+            State::Data => {
+                // Data already hashed in method call
+                match self.input_data.take() {
+                    Some(SubSliceMutImmut::Mutable(buffer)) => {
+                        self.client.map(|client| {
+                            client.add_mut_data_done(Ok(()), buffer);
+                        });
+                    }
+                    Some(SubSliceMutImmut::Immutable(buffer)) => {
+                        self.client.map(|client| {
+                            client.add_data_done(Ok(()), buffer);
+                        });
+                    }
+                    None => {}
+                }
+            }
+            State::Hash => {
+                // HMAC already copied in method call.
+                self.clear_data();
+                if let Some(output) = self.output_data.take() {
+                    self.client.map(|client| {
+                        client.hash_done(Ok(()), output);
+                    });
+                }
+            }
+            State::Verify => {
+                // Comparison already done in method call.
+                self.clear_data();
+                if let Some(output) = self.output_data.take() {
+                    self.client.map(|client| {
+                        client.verification_done(Ok(self.verified.get()), output);
+                    });
+                }
+            }
+            State::CancelData => match self.input_data.take() {
+                Some(SubSliceMutImmut::Mutable(buffer)) => {
+                    self.client.map(|client| {
+                        client.add_mut_data_done(Err(ErrorCode::CANCEL), buffer);
+                    });
+                }
+                Some(SubSliceMutImmut::Immutable(buffer)) => {
+                    self.client.map(|client| {
+                        client.add_data_done(Err(ErrorCode::CANCEL), buffer);
+                    });
+                }
+                None => {}
+            },
+            State::CancelHash => {
+                if let Some(output) = self.output_data.take() {
+                    output.fill(0);
+                    self.client.map(|client| {
+                        client.hash_done(Err(ErrorCode::CANCEL), output);
+                    });
+                }
+            }
+            State::CancelVerify => {
+                if let Some(output) = self.output_data.take() {
+                    self.client.map(|client| {
+                        client.verification_done(Err(ErrorCode::CANCEL), output);
+                    });
+                }
+            }
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+impl HmacSha256 for HmacSha512Software<'_> {
+    fn set_mode_hmacsha256(&self, _key: &[u8]) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+}
+
+impl HmacSha384 for HmacSha512Software<'_> {
+    fn set_mode_hmacsha384(&self, key: &[u8]) -> Result<(), ErrorCode> {
+        self.set_key(Mode::Sha384, key)
+    }
+}
+
+impl HmacSha512 for HmacSha512Software<'_> {
+    fn set_mode_hmacsha512(&self, key: &[u8]) -> Result<(), ErrorCode> {
+        self.set_key(Mode::Sha512, key)
+    }
+}
+
+impl<'a> DigestDataHash<'a, 64> for HmacSha512Software<'a> {
+    fn set_client(&'a self, _client: &'a dyn ClientDataHash<64>) {
+        unimplemented!()
+    }
+}
+
+impl<'a> DigestDataVerify<'a, 64> for HmacSha512Software<'a> {
+    fn set_client(&'a self, _client: &'a dyn ClientDataVerify<64>) {
+        unimplemented!()
+    }
+}
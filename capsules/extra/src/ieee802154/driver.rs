@@ -604,10 +604,8 @@ impl<'a, M: device::MacDevice<'a>> framer::KeyProcedure for RadioDriver<'a, M> {
                 // "backup" procedure (Thread in this case). This is somewhat clunky and removing
                 // the network keys being stored in the 15.4 driver is a longer term TODO.
                 || {
-                    self.backup_key_procedure.and_then(|procedure| {
-                        // TODO: security_level / keyID are hardcoded for now
-                        procedure.lookup_key(SecurityLevel::EncMic32, KeyId::Index(2))
-                    })
+                    self.backup_key_procedure
+                        .and_then(|procedure| procedure.lookup_key(level, key_id))
                 },
                 |res| Some(res),
             )
@@ -0,0 +1,462 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Message-passing inter-process communication through kernel-copied
+//! mailboxes.
+//!
+//! Unlike the shared-buffer IPC in `kernel::ipc`, processes using this
+//! driver never share memory. Each process has a mailbox in its grant
+//! region that holds up to `SLOTS` messages of at most `MSG_LEN` bytes. When
+//! a process sends a message, the kernel copies it from the sender's
+//! read-only allow buffer into the receiver's mailbox, and later from the
+//! mailbox into the receiver's read-write allow buffer when the receiver
+//! asks for it.
+//!
+//! Processes are addressed by their `ShortId`, and the receiver is told the
+//! `ShortId` of the sender of each message. Since `ShortId`s are assigned
+//! from the credentials of an application and persist across restarts, a
+//! receiver can decide whether to trust a message based on its sender.
+//! Processes with a `LocallyUnique` `ShortId` can send messages, which are
+//! reported with a sender of `0`, but cannot receive them. A process can
+//! only receive messages once it has used this driver, which allocates its
+//! mailbox.
+//!
+//! If the mailbox of the receiver is full, sending fails with `BUSY`. The
+//! sender is then notified with an upcall once the receiver takes a message
+//! out of its mailbox.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let ipc_mailbox = static_init!(
+//!     capsules_extra::ipc_mailbox::IpcMailbox<64, 4>,
+//!     capsules_extra::ipc_mailbox::IpcMailbox::new(
+//!         board_kernel.create_grant(
+//!             capsules_extra::ipc_mailbox::DRIVER_NUM,
+//!             &memory_allocation_capability
+//!         ),
+//!     )
+//! );
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! ### Command
+//!
+//! - `0`: Driver existence check.
+//! - `1`: Send the first `arg2` bytes of the read-only allow buffer to the
+//!   process with `ShortId` `arg1`. The message is copied into the mailbox
+//!   of the receiver before the command returns. Returns `SIZE` if the
+//!   message is too long, `NODEVICE` if there is no such receiver and `BUSY`
+//!   if its mailbox is full.
+//! - `2`: Receive the next message into the read-write allow buffer.
+//!   Completion is signalled through upcall `0`, immediately if a message is
+//!   already waiting. A process can block until it receives a message with
+//!   `yield-wait-for` on upcall `0`.
+//! - `3`: Stop waiting for a message.
+//!
+//! ### Allow
+//!
+//! - Read-only `0`: Message to send.
+//! - Read-write `0`: Buffer that received messages are copied into.
+//!
+//! ### Upcalls
+//!
+//! - `0`: A message was received. Arguments are the `ShortId` of the sender,
+//!   the length of the message and the number of bytes copied into the
+//!   read-write buffer, which is smaller if the buffer was too short.
+//! - `1`: A mailbox that was full when this process sent to it has space
+//!   again. The argument is the `ShortId` of its owner.
+
+use core::cmp;
+use core::num::NonZeroU32;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, GrantKernelData, UpcallCount};
+use kernel::process::ShortId;
+use kernel::processbuffer::{ReadableProcessBuffer, ReadableProcessSlice, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::IpcMailbox as usize;
+
+/// Ids for subscribed upcalls.
+mod upcall {
+    pub const MESSAGE_RECEIVED: usize = 0;
+    pub const SPACE_AVAILABLE: usize = 1;
+    /// The number of upcalls the kernel stores for this grant
+    pub const COUNT: u8 = 2;
+}
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    pub const MESSAGE: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    pub const MESSAGE: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// The value a `ShortId` is reported as to userspace.
+fn short_id_value(id: ShortId) -> u32 {
+    match id {
+        ShortId::Fixed(id) => id.get(),
+        ShortId::LocallyUnique => 0,
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Message<const MSG_LEN: usize> {
+    sender: u32,
+    len: usize,
+    data: [u8; MSG_LEN],
+}
+
+pub struct App<const MSG_LEN: usize, const SLOTS: usize> {
+    mailbox: [Message<MSG_LEN>; SLOTS],
+    // Index of the oldest message in the mailbox.
+    head: usize,
+    count: usize,
+    receive_pending: bool,
+    // `ShortId` of the receiver whose mailbox was full the last time this
+    // process sent a message.
+    blocked_on: Option<u32>,
+}
+
+impl<const MSG_LEN: usize, const SLOTS: usize> Default for App<MSG_LEN, SLOTS> {
+    fn default() -> Self {
+        App {
+            mailbox: [Message {
+                sender: 0,
+                len: 0,
+                data: [0; MSG_LEN],
+            }; SLOTS],
+            head: 0,
+            count: 0,
+            receive_pending: false,
+            blocked_on: None,
+        }
+    }
+}
+
+impl<const MSG_LEN: usize, const SLOTS: usize> App<MSG_LEN, SLOTS> {
+    /// Copies the message into the next free slot of the mailbox.
+    fn push(&mut self, sender: u32, message: &ReadableProcessSlice) -> Result<(), ErrorCode> {
+        if self.count == SLOTS {
+            return Err(ErrorCode::BUSY);
+        }
+        let slot = &mut self.mailbox[(self.head + self.count) % SLOTS];
+        message.copy_to_slice(&mut slot.data[..message.len()]);
+        slot.sender = sender;
+        slot.len = message.len();
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Takes the oldest message out of the mailbox if the process is waiting
+    /// for one. Also returns whether this made space in a full mailbox.
+    fn take(&mut self) -> Option<(&Message<MSG_LEN>, bool)> {
+        if !self.receive_pending || self.count == 0 {
+            return None;
+        }
+        let was_full = self.count == SLOTS;
+        let head = self.head;
+        self.head = (head + 1) % SLOTS;
+        self.count -= 1;
+        self.receive_pending = false;
+        Some((&self.mailbox[head], was_full))
+    }
+
+    /// Hands the oldest message to the process if it is waiting for one.
+    /// Returns whether this made space in a full mailbox.
+    fn deliver(&mut self, kernel_data: &GrantKernelData) -> bool {
+        let (message, was_full) = match self.take() {
+            Some(next) => next,
+            None => return false,
+        };
+        let copied = kernel_data
+            .get_readwrite_processbuffer(rw_allow::MESSAGE)
+            .and_then(|buf| {
+                buf.mut_enter(|buf| {
+                    let len = cmp::min(message.len, buf.len());
+                    buf[..len].copy_from_slice(&message.data[..len]);
+                    len
+                })
+            })
+            .unwrap_or(0);
+        kernel_data
+            .schedule_upcall(
+                upcall::MESSAGE_RECEIVED,
+                (message.sender as usize, message.len, copied),
+            )
+            .ok();
+        was_full
+    }
+
+    /// Remembers that the mailbox of `receiver` was full, so that this
+    /// process is notified once it has space again.
+    fn wait_for_space(&mut self, receiver: u32) {
+        self.blocked_on = Some(receiver);
+    }
+
+    /// Returns whether this process is waiting for the mailbox of `receiver`
+    /// to have space, which it now has, and stops waiting.
+    fn space_available(&mut self, receiver: u32) -> bool {
+        if self.blocked_on == Some(receiver) {
+            self.blocked_on = None;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+pub struct IpcMailbox<const MSG_LEN: usize, const SLOTS: usize> {
+    apps: Grant<
+        App<MSG_LEN, SLOTS>,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+}
+
+impl<const MSG_LEN: usize, const SLOTS: usize> IpcMailbox<MSG_LEN, SLOTS> {
+    pub fn new(
+        apps: Grant<
+            App<MSG_LEN, SLOTS>,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+    ) -> IpcMailbox<MSG_LEN, SLOTS> {
+        IpcMailbox { apps }
+    }
+
+    /// Copies a message of `length` bytes from `processid` into the mailbox
+    /// of the process with `ShortId` `destination`.
+    fn send(
+        &self,
+        processid: ProcessId,
+        destination: usize,
+        length: usize,
+    ) -> Result<(), ErrorCode> {
+        let destination = u32::try_from(destination)
+            .ok()
+            .and_then(NonZeroU32::new)
+            .ok_or(ErrorCode::INVAL)?;
+        let sender = processid.short_app_id();
+        if sender == ShortId::Fixed(destination) {
+            return Err(ErrorCode::INVAL);
+        }
+        if length > MSG_LEN {
+            return Err(ErrorCode::SIZE);
+        }
+
+        let receiver = self
+            .apps
+            .iter()
+            .map(|app| app.processid())
+            .find(|receiver| receiver.short_app_id() == ShortId::Fixed(destination))
+            .ok_or(ErrorCode::NODEVICE)?;
+
+        self.apps
+            .enter(processid, |app, kernel_data| {
+                let res = kernel_data
+                    .get_readonly_processbuffer(ro_allow::MESSAGE)
+                    .and_then(|message| {
+                        message.enter(|message| {
+                            if length > message.len() {
+                                return Err(ErrorCode::SIZE);
+                            }
+                            self.apps
+                                .enter(receiver, |receiver_app, _| {
+                                    receiver_app.push(short_id_value(sender), &message[..length])
+                                })
+                                .unwrap_or_else(|err| Err(err.into()))
+                        })
+                    })
+                    .unwrap_or(Err(ErrorCode::RESERVE));
+                if res == Err(ErrorCode::BUSY) {
+                    app.wait_for_space(destination.get());
+                }
+                res
+            })
+            .unwrap_or_else(|err| Err(err.into()))?;
+
+        self.deliver(receiver);
+        Ok(())
+    }
+
+    /// Hands the oldest message in the mailbox of `processid` to it, if it
+    /// is waiting for one.
+    fn deliver(&self, processid: ProcessId) {
+        let made_space = self
+            .apps
+            .enter(processid, |app, kernel_data| app.deliver(kernel_data))
+            .unwrap_or(false);
+        if made_space {
+            self.notify_space_available(processid);
+        }
+    }
+
+    /// Notifies the processes that found the mailbox of `receiver` full that
+    /// it has space again.
+    fn notify_space_available(&self, receiver: ProcessId) {
+        let receiver = match receiver.short_app_id() {
+            ShortId::Fixed(id) => id.get(),
+            ShortId::LocallyUnique => return,
+        };
+        self.apps.each(|_, app, kernel_data| {
+            if app.space_available(receiver) {
+                kernel_data
+                    .schedule_upcall(upcall::SPACE_AVAILABLE, (receiver as usize, 0, 0))
+                    .ok();
+            }
+        });
+    }
+}
+
+impl<const MSG_LEN: usize, const SLOTS: usize> SyscallDriver for IpcMailbox<MSG_LEN, SLOTS> {
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Return Ok(()) if this driver is included on the platform.
+    /// - `1`: Send `arg2` bytes from the read-only buffer to the process with
+    ///   `ShortId` `arg1`.
+    /// - `2`: Receive the next message.
+    /// - `3`: Stop waiting for a message.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        arg2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => match self.send(processid, arg1, arg2) {
+                Ok(()) => CommandReturn::success(),
+                Err(e) => CommandReturn::failure(e),
+            },
+
+            2 => {
+                let res = self
+                    .apps
+                    .enter(processid, |app, _| {
+                        if app.receive_pending {
+                            Err(ErrorCode::ALREADY)
+                        } else {
+                            app.receive_pending = true;
+                            Ok(())
+                        }
+                    })
+                    .unwrap_or_else(|err| Err(err.into()));
+                match res {
+                    Ok(()) => {
+                        self.deliver(processid);
+                        CommandReturn::success()
+                    }
+                    Err(e) => CommandReturn::failure(e),
+                }
+            }
+
+            3 => {
+                let res = self.apps.enter(processid, |app, _| {
+                    app.receive_pending = false;
+                });
+                match res {
+                    Ok(()) => CommandReturn::success(),
+                    Err(err) => CommandReturn::failure(err.into()),
+                }
+            }
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec::Vec;
+
+    type TestApp = App<8, 2>;
+
+    fn push(app: &mut TestApp, sender: u32, data: &[u8]) -> Result<(), ErrorCode> {
+        app.push(sender, data.into())
+    }
+
+    fn take(app: &mut TestApp) -> Option<(u32, Vec<u8>, bool)> {
+        app.receive_pending = true;
+        app.take()
+            .map(|(m, was_full)| (m.sender, m.data[..m.len].to_vec(), was_full))
+    }
+
+    #[test]
+    fn full_mailbox_is_busy() {
+        let mut receiver = TestApp::default();
+        assert_eq!(push(&mut receiver, 1, b"one"), Ok(()));
+        assert_eq!(push(&mut receiver, 2, b"two"), Ok(()));
+        assert_eq!(push(&mut receiver, 3, b"three"), Err(ErrorCode::BUSY));
+
+        // The rejected message did not replace a queued one.
+        assert_eq!(take(&mut receiver), Some((1, b"one".to_vec(), true)));
+        assert_eq!(push(&mut receiver, 3, b"three"), Ok(()));
+        assert_eq!(take(&mut receiver), Some((2, b"two".to_vec(), true)));
+        assert_eq!(take(&mut receiver), Some((3, b"three".to_vec(), false)));
+        assert_eq!(take(&mut receiver), None);
+    }
+
+    #[test]
+    fn messages_wait_until_receive() {
+        let mut receiver = TestApp::default();
+        assert_eq!(push(&mut receiver, 1, b"one"), Ok(()));
+        assert!(receiver.take().is_none());
+        assert_eq!(take(&mut receiver), Some((1, b"one".to_vec(), false)));
+    }
+
+    #[test]
+    fn space_available_notifies_blocked_sender_once() {
+        let mut receiver = TestApp::default();
+        let mut sender = TestApp::default();
+        let mut other = TestApp::default();
+        push(&mut receiver, 5, b"a").unwrap();
+        push(&mut receiver, 5, b"b").unwrap();
+        if push(&mut receiver, 5, b"c") == Err(ErrorCode::BUSY) {
+            sender.wait_for_space(7);
+        }
+        other.wait_for_space(8);
+
+        // Taking from a full mailbox makes space, which the sender blocked on
+        // it is told about, but not processes blocked on other mailboxes.
+        let (_, _, made_space) = take(&mut receiver).unwrap();
+        assert!(made_space);
+        assert!(sender.space_available(7));
+        assert!(!other.space_available(7));
+
+        // The sender is only notified once per full mailbox.
+        assert!(!sender.space_available(7));
+        let (_, _, made_space) = take(&mut receiver).unwrap();
+        assert!(!made_space);
+        assert!(other.space_available(8));
+    }
+}
@@ -0,0 +1,935 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Key-management service with per-process key handles.
+//!
+//! Processes hand their long-term keys to this capsule rather than keeping
+//! them in their own memory. Keys are sealed with AES-256-GCM under a
+//! board-provided key-encryption key before they are written to the
+//! key-value store, and are only ever unsealed into kernel memory. A process
+//! that opens a key receives an opaque handle, which other drivers (such as
+//! the AES and HMAC drivers) resolve through the [`KeyResolver`] trait. Keys
+//! created with the generate command never exist in process memory at all.
+//!
+//! Keys are scoped by the `StoragePermissions` of the process. The name of a
+//! key in the k-v store is prefixed with the write ID of the process (which is
+//! derived from its `ShortId`), so a process can only reach its own keys. The
+//! sealed value authenticates that name, so a value copied to another name in
+//! the store will fail to unseal.
+//!
+//! Kernel capsules can store and open keys as well, with
+//! [`KeyStore::import_key`] and [`KeyStore::open_key`]. They pass the
+//! `StoragePermissions` to scope the key by, and an opened key is copied into
+//! a buffer of the [`KeyStoreClient`] instead of being held behind a handle.
+//!
+//! ```rust,ignore
+//! +===============+
+//! ||  Userspace  ||
+//! +===============+
+//!
+//! -----Syscall Interface-----
+//!
+//! +--------------------------+      +-------------------------+
+//! |  Key store (this file)   | <--- |  AES / HMAC drivers     |
+//! +--------------------------+      +-------------------------+
+//!
+//!    hil::kv::KVPermissions, hil::symmetric_encryption::AES256GCM,
+//!    hil::rng::Rng
+//! ```
+//!
+//! Stored format
+//! -------------
+//!
+//! The value stored for each key is a version byte, the key length, the
+//! 12 byte GCM IV, the sealed key and the 16 byte GCM tag. The IV is drawn
+//! from the RNG each time a key is stored.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let key_store = components::key_store::KeyStoreComponent::new(
+//!     board_kernel,
+//!     capsules_extra::key_store::DRIVER_NUM,
+//!     virtual_kv_key_store,
+//!     key_store_aes,
+//!     key_store_rng,
+//!     &KEY_ENCRYPTION_KEY,
+//! )
+//! .finalize(components::key_store_component_static!(
+//!     VirtualKVPermissions<'static, KVStorePermissions<'static, ...>>,
+//!     SoftwareAes<'static>,
+//!     VirtualRngMasterDevice<'static>,
+//! ));
+//! aes.set_key_store(key_store);
+//! hmac.set_key_store(key_store);
+//! ```
+
+use capsules_core::driver;
+/// Syscall driver number.
+pub const DRIVER_NUM: usize = driver::NUM::KeyStore as usize;
+
+use core::cell::Cell;
+
+use kernel::errorcode::into_statuscode;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::kv;
+use kernel::hil::rng::{self, Continue, Rng};
+use kernel::hil::symmetric_encryption::{GCMClient, AES256GCM, AES256_KEY_SIZE, GCM_IV_LENGTH};
+use kernel::processbuffer::ReadableProcessBuffer;
+use kernel::storage_permissions::StoragePermissions;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::{ErrorCode, ProcessId};
+
+/// Longest key name a process can use.
+pub const MAX_KEY_NAME_LENGTH: usize = 32;
+/// Longest key the store will hold, enough for an HMAC-SHA512 key.
+pub const MAX_KEY_SIZE: usize = 64;
+/// Number of keys each process can have open at once.
+pub const MAX_OPEN_KEYS: usize = 4;
+
+/// Prefix of every name this capsule uses in the k-v store.
+pub(crate) const KV_KEY_PREFIX: &[u8] = b"tock-key";
+/// Size of the buffer holding the name of a key in the k-v store: the
+/// prefix, the write ID of the process and the name chosen by the process.
+pub const KV_KEY_BUFFER_SIZE: usize = KV_KEY_PREFIX.len() + 4 + MAX_KEY_NAME_LENGTH;
+
+const GCM_TAG_LENGTH: usize = 16;
+/// Size of the buffer keys are sealed and unsealed in. It holds the k-v name
+/// (as additional data), the key and the tag.
+pub const CRYPT_BUFFER_SIZE: usize = KV_KEY_BUFFER_SIZE + MAX_KEY_SIZE + GCM_TAG_LENGTH;
+
+const BLOB_VERSION: u8 = 1;
+/// The version and key length bytes.
+const BLOB_HEADER_LENGTH: usize = 2;
+/// Longest value this capsule stores, excluding the k-v store header.
+pub const MAX_BLOB_LENGTH: usize =
+    BLOB_HEADER_LENGTH + GCM_IV_LENGTH + MAX_KEY_SIZE + GCM_TAG_LENGTH;
+
+/// IDs for read-only allow buffers.
+mod ro_allow {
+    /// Name of the key.
+    pub const NAME: usize = 0;
+    /// Key material for import.
+    pub const KEY: usize = 1;
+    /// The number of RO allow buffers the kernel stores for this grant.
+    pub const COUNT: u8 = 2;
+}
+
+/// IDs for read-write allow buffers.
+mod rw_allow {
+    /// The number of RW allow buffers the kernel stores for this grant.
+    pub const COUNT: u8 = 0;
+}
+
+/// IDs for upcalls.
+mod upcalls {
+    /// Operation complete.
+    pub const DONE: usize = 0;
+    /// The number of upcalls the kernel stores for this grant.
+    pub const COUNT: u8 = 1;
+}
+
+/// Access to keys that processes have opened in a key store.
+///
+/// Drivers that take keys from userspace implement key handles by asking a
+/// `KeyResolver` for the key instead of reading an allow buffer.
+pub trait KeyResolver {
+    /// Copy the key behind `handle` into `key`, if `processid` opened it.
+    ///
+    /// Returns the length of the key. Returns `INVAL` if `handle` is not one
+    /// of the keys the process has open and `SIZE` if `key` is too short.
+    fn copy_key(
+        &self,
+        processid: ProcessId,
+        handle: usize,
+        key: &mut [u8],
+    ) -> Result<usize, ErrorCode>;
+}
+
+/// Client for keys that kernel capsules store and open.
+pub trait KeyStoreClient {
+    /// A call to [`KeyStore::import_key`] finished.
+    fn import_done(&self, result: Result<(), ErrorCode>);
+
+    /// A call to [`KeyStore::open_key`] finished. On success, the first
+    /// bytes of `key` hold the unsealed key and the result is its length.
+    fn open_done(&self, result: Result<usize, ErrorCode>, key: &'static mut [u8]);
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum UserSpaceOp {
+    Import,
+    Generate(usize),
+    Open,
+    Delete,
+}
+
+/// A key a process has opened.
+struct KeySlot {
+    len: usize,
+    key: [u8; MAX_KEY_SIZE],
+}
+
+impl Default for KeySlot {
+    fn default() -> Self {
+        KeySlot {
+            len: 0,
+            key: [0; MAX_KEY_SIZE],
+        }
+    }
+}
+
+impl KeySlot {
+    fn clear(&mut self) {
+        self.key.iter_mut().for_each(|b| *b = 0);
+        self.len = 0;
+    }
+}
+
+/// Contents of the grant for each app.
+///
+/// Open keys live in the grant, which the process cannot access, and are
+/// released with it when the process exits.
+#[derive(Default)]
+pub struct App {
+    op: OptionalCell<UserSpaceOp>,
+    slots: [KeySlot; MAX_OPEN_KEYS],
+}
+
+pub struct KeyStore<'a, K: kv::KVPermissions<'a>, A: AES256GCM<'a>, R: Rng<'a>> {
+    kv: &'a K,
+    aes: &'a A,
+    rng: &'a R,
+    /// Key used to seal keys before they are stored.
+    kek: &'static [u8; AES256_KEY_SIZE],
+
+    apps: Grant<
+        App,
+        UpcallCount<{ upcalls::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    /// App that is actively using the key store.
+    processid: OptionalCell<ProcessId>,
+    /// Permissions of the kernel operation in progress, if any.
+    kernel_perms: OptionalCell<StoragePermissions>,
+    /// Buffer a key opened by the kernel is copied into.
+    kernel_key: TakeCell<'static, [u8]>,
+    client: OptionalCell<&'a dyn KeyStoreClient>,
+    operation: OptionalCell<UserSpaceOp>,
+
+    /// Name of the key in the k-v store.
+    kv_key_buffer: TakeCell<'static, [u8]>,
+    kv_key_len: Cell<usize>,
+    value_buffer: TakeCell<'static, [u8]>,
+    crypt_buffer: TakeCell<'static, [u8]>,
+    key_len: Cell<usize>,
+    iv: Cell<[u8; GCM_IV_LENGTH]>,
+    /// Random bytes needed for the IV and, when generating, the key.
+    random_needed: Cell<usize>,
+    random_filled: Cell<usize>,
+}
+
+impl<'a, K: kv::KVPermissions<'a>, A: AES256GCM<'a>, R: Rng<'a>> KeyStore<'a, K, A, R> {
+    pub fn new(
+        kv: &'a K,
+        aes: &'a A,
+        rng: &'a R,
+        kek: &'static [u8; AES256_KEY_SIZE],
+        kv_key_buffer: &'static mut [u8],
+        value_buffer: &'static mut [u8],
+        crypt_buffer: &'static mut [u8],
+        grant: Grant<
+            App,
+            UpcallCount<{ upcalls::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+    ) -> Self {
+        KeyStore {
+            kv,
+            aes,
+            rng,
+            kek,
+            apps: grant,
+            processid: OptionalCell::empty(),
+            kernel_perms: OptionalCell::empty(),
+            kernel_key: TakeCell::empty(),
+            client: OptionalCell::empty(),
+            operation: OptionalCell::empty(),
+            kv_key_buffer: TakeCell::new(kv_key_buffer),
+            kv_key_len: Cell::new(0),
+            value_buffer: TakeCell::new(value_buffer),
+            crypt_buffer: TakeCell::new(crypt_buffer),
+            key_len: Cell::new(0),
+            iv: Cell::new([0; GCM_IV_LENGTH]),
+            random_needed: Cell::new(0),
+            random_filled: Cell::new(0),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn KeyStoreClient) {
+        self.client.set(client);
+    }
+
+    /// Seal `key` and store it as `name` in the namespace of `perms`,
+    /// replacing any key with the same name.
+    ///
+    /// Returns `BUSY` if the key store is in use.
+    pub fn import_key(
+        &self,
+        name: &[u8],
+        key: &[u8],
+        perms: StoragePermissions,
+    ) -> Result<(), ErrorCode> {
+        if self.busy() {
+            return Err(ErrorCode::BUSY);
+        }
+        let result = self.stage_name(perms, name.len(), |buf| buf.copy_from_slice(name));
+        let result = result.and_then(|()| {
+            let key_len = self.stage_key(key.len(), |buf| buf.copy_from_slice(key))?;
+            self.kernel_perms.set(perms);
+            self.operation.set(UserSpaceOp::Import);
+            self.start(UserSpaceOp::Import, perms, key_len)
+        });
+        result.inspect_err(|_| self.abort_kernel())
+    }
+
+    /// Open the key `name` in the namespace of `perms`, copying it into
+    /// `key`.
+    ///
+    /// Returns `BUSY` if the key store is in use. `key` should be
+    /// [`MAX_KEY_SIZE`] bytes long, a shorter buffer fails with `SIZE` if the
+    /// key does not fit.
+    pub fn open_key(
+        &self,
+        name: &[u8],
+        perms: StoragePermissions,
+        key: &'static mut [u8],
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.busy() {
+            return Err((ErrorCode::BUSY, key));
+        }
+        self.kernel_key.replace(key);
+        let result = self
+            .stage_name(perms, name.len(), |buf| buf.copy_from_slice(name))
+            .and_then(|()| {
+                self.kernel_perms.set(perms);
+                self.operation.set(UserSpaceOp::Open);
+                self.start(UserSpaceOp::Open, perms, 0)
+            });
+        result.map_err(|e| {
+            self.abort_kernel();
+            (e, self.kernel_key.take().unwrap_or(&mut []))
+        })
+    }
+
+    /// Whether a process or the kernel is using the key store.
+    fn busy(&self) -> bool {
+        self.processid.is_some() || self.kernel_perms.is_some()
+    }
+
+    /// Clean up after a kernel operation that failed to start.
+    fn abort_kernel(&self) {
+        self.scrub();
+        self.operation.clear();
+        self.kernel_perms.clear();
+    }
+
+    /// Place a key name of `name_len` bytes in the namespace of `perms`.
+    /// `copy` fills in the name.
+    fn stage_name(
+        &self,
+        perms: StoragePermissions,
+        name_len: usize,
+        copy: impl FnOnce(&mut [u8]),
+    ) -> Result<(), ErrorCode> {
+        let write_id = perms.get_write_id().ok_or(ErrorCode::NOSUPPORT)?;
+        if name_len == 0 || name_len > MAX_KEY_NAME_LENGTH {
+            return Err(ErrorCode::SIZE);
+        }
+        self.kv_key_buffer.map_or(Err(ErrorCode::NOMEM), |buf| {
+            let id_end = KV_KEY_PREFIX.len() + 4;
+            buf[..KV_KEY_PREFIX.len()].copy_from_slice(KV_KEY_PREFIX);
+            buf[KV_KEY_PREFIX.len()..id_end].copy_from_slice(&write_id.to_le_bytes());
+            copy(&mut buf[id_end..id_end + name_len]);
+            self.kv_key_len.set(id_end + name_len);
+            Ok(())
+        })
+    }
+
+    /// Place a key of `key_len` bytes after the staged name in the crypt
+    /// buffer, returning its length. `copy` fills in the key.
+    fn stage_key(&self, key_len: usize, copy: impl FnOnce(&mut [u8])) -> Result<usize, ErrorCode> {
+        if key_len == 0 || key_len > MAX_KEY_SIZE {
+            return Err(ErrorCode::SIZE);
+        }
+        let kv_key_len = self.kv_key_len.get();
+        self.crypt_buffer.map_or(Err(ErrorCode::NOMEM), |buf| {
+            copy(&mut buf[kv_key_len..kv_key_len + key_len]);
+            Ok(key_len)
+        })
+    }
+
+    fn run(&self) -> Result<(), ErrorCode> {
+        self.processid.map_or(Err(ErrorCode::RESERVE), |processid| {
+            self.apps
+                .enter(processid, |app, kernel_data| {
+                    let op = app.op.get().ok_or(ErrorCode::RESERVE)?;
+                    let perms = processid
+                        .get_storage_permissions()
+                        .ok_or(ErrorCode::INVAL)?;
+
+                    // Every operation names a key, which we place in the
+                    // namespace of this process.
+                    kernel_data
+                        .get_readonly_processbuffer(ro_allow::NAME)
+                        .and_then(|name| {
+                            name.enter(|name| {
+                                self.stage_name(perms, name.len(), |buf| name.copy_to_slice(buf))
+                            })
+                        })
+                        .unwrap_or(Err(ErrorCode::RESERVE))?;
+                    self.operation.set(op);
+
+                    let key_len = match op {
+                        UserSpaceOp::Import => kernel_data
+                            .get_readonly_processbuffer(ro_allow::KEY)
+                            .and_then(|key| {
+                                key.enter(|key| {
+                                    self.stage_key(key.len(), |buf| key.copy_to_slice(buf))
+                                })
+                            })
+                            .unwrap_or(Err(ErrorCode::RESERVE))?,
+                        _ => 0,
+                    };
+                    self.start(op, perms, key_len)
+                })
+                .unwrap_or_else(|err| Err(err.into()))
+        })
+    }
+
+    /// Start `op` on the staged name, with `key_len` bytes of key staged for
+    /// an import.
+    fn start(
+        &self,
+        op: UserSpaceOp,
+        perms: StoragePermissions,
+        key_len: usize,
+    ) -> Result<(), ErrorCode> {
+        let kv_key_len = self.kv_key_len.get();
+        match op {
+            UserSpaceOp::Import => self.start_seal(key_len, GCM_IV_LENGTH),
+            UserSpaceOp::Generate(key_len) => {
+                if key_len == 0 || key_len > MAX_KEY_SIZE {
+                    return Err(ErrorCode::SIZE);
+                }
+                self.start_seal(key_len, GCM_IV_LENGTH + key_len)
+            }
+            UserSpaceOp::Open => {
+                let key = self.kv_key_buffer.take().ok_or(ErrorCode::NOMEM)?;
+                let Some(value) = self.value_buffer.take() else {
+                    self.kv_key_buffer.replace(key);
+                    return Err(ErrorCode::NOMEM);
+                };
+                let mut key = SubSliceMut::new(key);
+                key.slice(..kv_key_len);
+
+                self.kv
+                    .get(key, SubSliceMut::new(value), perms)
+                    .map_err(|(key, value, e)| {
+                        self.kv_key_buffer.replace(key.take());
+                        self.value_buffer.replace(value.take());
+                        e
+                    })
+            }
+            UserSpaceOp::Delete => {
+                let key = self.kv_key_buffer.take().ok_or(ErrorCode::NOMEM)?;
+                let mut key = SubSliceMut::new(key);
+                key.slice(..kv_key_len);
+
+                self.kv.delete(key, perms).map_err(|(key, e)| {
+                    self.kv_key_buffer.replace(key.take());
+                    e
+                })
+            }
+        }
+    }
+
+    /// Copy the k-v name in as additional data and request the random bytes
+    /// needed to seal the key.
+    fn start_seal(&self, key_len: usize, random_needed: usize) -> Result<(), ErrorCode> {
+        let kv_key_len = self.kv_key_len.get();
+        self.crypt_buffer.map_or(Err(ErrorCode::NOMEM), |buf| {
+            self.kv_key_buffer.map_or(Err(ErrorCode::NOMEM), |name| {
+                buf[..kv_key_len].copy_from_slice(&name[..kv_key_len]);
+                Ok(())
+            })
+        })?;
+
+        self.key_len.set(key_len);
+        self.random_needed.set(random_needed);
+        self.random_filled.set(0);
+        self.rng.get()
+    }
+
+    /// Seal or unseal the key in the crypt buffer.
+    fn crypt(&self, encrypting: bool) -> Result<(), ErrorCode> {
+        self.aes.set_key(self.kek)?;
+        self.aes.set_iv(&self.iv.get())?;
+        let buf = self.crypt_buffer.take().ok_or(ErrorCode::NOMEM)?;
+        self.aes
+            .crypt(
+                buf,
+                0,
+                self.kv_key_len.get(),
+                self.key_len.get(),
+                encrypting,
+            )
+            .map_err(|(e, buf)| {
+                self.crypt_buffer.replace(buf);
+                e
+            })
+    }
+
+    /// Write the sealed key in `buf` to the k-v store.
+    fn store_sealed(&self, buf: &[u8]) -> Result<(), ErrorCode> {
+        let perms = self
+            .kernel_perms
+            .get()
+            .or_else(|| {
+                self.processid
+                    .and_then(|processid| processid.get_storage_permissions())
+            })
+            .ok_or(ErrorCode::FAIL)?;
+        let kv_key_len = self.kv_key_len.get();
+        let key_len = self.key_len.get();
+        let sealed_len = key_len + GCM_TAG_LENGTH;
+        let header_size = self.kv.header_size();
+        let value_len = header_size + BLOB_HEADER_LENGTH + GCM_IV_LENGTH + sealed_len;
+
+        let value = self.value_buffer.take().ok_or(ErrorCode::NOMEM)?;
+        if value.len() < value_len {
+            self.value_buffer.replace(value);
+            return Err(ErrorCode::SIZE);
+        }
+        let Some(key) = self.kv_key_buffer.take() else {
+            self.value_buffer.replace(value);
+            return Err(ErrorCode::NOMEM);
+        };
+
+        let blob = &mut value[header_size..value_len];
+        blob[0] = BLOB_VERSION;
+        blob[1] = key_len as u8;
+        blob[BLOB_HEADER_LENGTH..BLOB_HEADER_LENGTH + GCM_IV_LENGTH]
+            .copy_from_slice(&self.iv.get());
+        blob[BLOB_HEADER_LENGTH + GCM_IV_LENGTH..]
+            .copy_from_slice(&buf[kv_key_len..kv_key_len + sealed_len]);
+
+        let mut key = SubSliceMut::new(key);
+        key.slice(..kv_key_len);
+        let mut value = SubSliceMut::new(value);
+        value.slice(..value_len);
+
+        self.kv.set(key, value, perms).map_err(|(key, value, e)| {
+            self.kv_key_buffer.replace(key.take());
+            self.value_buffer.replace(value.take());
+            e
+        })
+    }
+
+    /// Start unsealing the key stored in `blob`.
+    fn load_sealed(&self, blob: &[u8]) -> Result<(), ErrorCode> {
+        if blob.len() < BLOB_HEADER_LENGTH + GCM_IV_LENGTH || blob[0] != BLOB_VERSION {
+            return Err(ErrorCode::FAIL);
+        }
+        let key_len = blob[1] as usize;
+        let sealed_len = key_len + GCM_TAG_LENGTH;
+        let sealed = &blob[BLOB_HEADER_LENGTH + GCM_IV_LENGTH..];
+        if key_len == 0 || key_len > MAX_KEY_SIZE || sealed.len() < sealed_len {
+            return Err(ErrorCode::FAIL);
+        }
+
+        let mut iv = [0; GCM_IV_LENGTH];
+        iv.copy_from_slice(&blob[BLOB_HEADER_LENGTH..BLOB_HEADER_LENGTH + GCM_IV_LENGTH]);
+        self.iv.set(iv);
+        self.key_len.set(key_len);
+
+        let kv_key_len = self.kv_key_len.get();
+        self.crypt_buffer.map_or(Err(ErrorCode::NOMEM), |buf| {
+            self.kv_key_buffer.map_or(Err(ErrorCode::NOMEM), |name| {
+                buf[..kv_key_len].copy_from_slice(&name[..kv_key_len]);
+                buf[kv_key_len..kv_key_len + sealed_len].copy_from_slice(&sealed[..sealed_len]);
+                Ok(())
+            })
+        })?;
+
+        self.crypt(false)
+    }
+
+    /// Place an unsealed key in a free slot of the active process, returning
+    /// its handle. A key opened by the kernel is copied into its buffer.
+    fn install_key(&self, key: &[u8]) -> Result<usize, ErrorCode> {
+        if self.kernel_perms.is_some() {
+            return self.kernel_key.map_or(Err(ErrorCode::FAIL), |buf| {
+                let dest = buf.get_mut(..key.len()).ok_or(ErrorCode::SIZE)?;
+                dest.copy_from_slice(key);
+                Ok(0)
+            });
+        }
+        self.processid.map_or(Err(ErrorCode::FAIL), |processid| {
+            self.apps
+                .enter(processid, |app, _| {
+                    let (handle, slot) = app
+                        .slots
+                        .iter_mut()
+                        .enumerate()
+                        .find(|(_, slot)| slot.len == 0)
+                        .ok_or(ErrorCode::NOMEM)?;
+                    slot.key[..key.len()].copy_from_slice(key);
+                    slot.len = key.len();
+                    Ok(handle)
+                })
+                .unwrap_or_else(|err| Err(err.into()))
+        })
+    }
+
+    /// Zero the crypt buffer so no key material is left behind.
+    fn scrub(&self) {
+        self.crypt_buffer
+            .map(|buf| buf.iter_mut().for_each(|b| *b = 0));
+    }
+
+    /// Finish the active operation, signal the app or kernel client and
+    /// start the next one.
+    fn complete(&self, result: Result<(), ErrorCode>, handle: usize, key_len: usize) {
+        self.scrub();
+        self.operation.clear();
+        self.random_needed.set(0);
+
+        if self.kernel_perms.take().is_some() {
+            match self.kernel_key.take() {
+                Some(key) => self
+                    .client
+                    .map(|client| client.open_done(result.map(|()| key_len), key)),
+                None => self.client.map(|client| client.import_done(result)),
+            };
+        }
+
+        self.processid.take().map(|processid| {
+            let _ = self.apps.enter(processid, |app, kernel_data| {
+                app.op.clear();
+                kernel_data
+                    .schedule_upcall(upcalls::DONE, (into_statuscode(result), handle, key_len))
+                    .ok();
+            });
+        });
+
+        self.check_queue();
+    }
+
+    fn check_queue(&self) {
+        // If an operation is already running let it complete.
+        if self.busy() {
+            return;
+        }
+
+        for appiter in self.apps.iter() {
+            let processid = appiter.processid();
+            let has_pending_op = appiter.enter(|app, _| app.op.is_some());
+            if !has_pending_op {
+                continue;
+            }
+
+            self.processid.set(processid);
+            match self.run() {
+                Ok(()) => break,
+                Err(e) => {
+                    self.scrub();
+                    self.operation.clear();
+                    self.processid.clear();
+                    let _ = self.apps.enter(processid, |app, kernel_data| {
+                        app.op.clear();
+                        kernel_data
+                            .schedule_upcall(upcalls::DONE, (into_statuscode(Err(e)), 0, 0))
+                            .ok();
+                    });
+                }
+            }
+        }
+    }
+}
+
+impl<'a, K: kv::KVPermissions<'a>, A: AES256GCM<'a>, R: Rng<'a>> rng::Client
+    for KeyStore<'a, K, A, R>
+{
+    fn randomness_available(
+        &self,
+        randomness: &mut dyn Iterator<Item = u32>,
+        error: Result<(), ErrorCode>,
+    ) -> Continue {
+        let needed = self.random_needed.get();
+        if needed == 0 {
+            return Continue::Done;
+        }
+        if let Err(e) = error {
+            self.complete(Err(e), 0, 0);
+            return Continue::Done;
+        }
+
+        // The IV comes first, followed by the key when generating one.
+        let kv_key_len = self.kv_key_len.get();
+        let mut filled = self.random_filled.get();
+        let mut iv = self.iv.get();
+        self.crypt_buffer.map(|buf| {
+            for word in randomness {
+                for byte in word.to_le_bytes() {
+                    if filled < GCM_IV_LENGTH {
+                        iv[filled] = byte;
+                    } else if filled < needed {
+                        buf[kv_key_len + filled - GCM_IV_LENGTH] = byte;
+                    }
+                    filled += 1;
+                }
+                if filled >= needed {
+                    break;
+                }
+            }
+        });
+        self.iv.set(iv);
+        self.random_filled.set(filled);
+
+        if filled < needed {
+            return Continue::More;
+        }
+
+        self.random_needed.set(0);
+        if let Err(e) = self.crypt(true) {
+            self.complete(Err(e), 0, 0);
+        }
+        Continue::Done
+    }
+}
+
+impl<'a, K: kv::KVPermissions<'a>, A: AES256GCM<'a>, R: Rng<'a>> GCMClient
+    for KeyStore<'a, K, A, R>
+{
+    fn crypt_done(&self, buf: &'static mut [u8], res: Result<(), ErrorCode>, tag_is_valid: bool) {
+        let result = match self.operation.get() {
+            Some(UserSpaceOp::Import) | Some(UserSpaceOp::Generate(_)) => {
+                res.and_then(|()| self.store_sealed(buf)).map(|()| None)
+            }
+            Some(UserSpaceOp::Open) => {
+                let kv_key_len = self.kv_key_len.get();
+                let key_len = self.key_len.get();
+                res.and_then(|()| {
+                    if tag_is_valid {
+                        self.install_key(&buf[kv_key_len..kv_key_len + key_len])
+                    } else {
+                        // The stored key was modified, or sealed under a
+                        // different key-encryption key.
+                        Err(ErrorCode::FAIL)
+                    }
+                })
+                .map(Some)
+            }
+            _ => Ok(None),
+        };
+
+        buf.iter_mut().for_each(|b| *b = 0);
+        self.crypt_buffer.replace(buf);
+
+        match result {
+            // Waiting for the k-v store.
+            Ok(None) => {}
+            Ok(Some(handle)) => self.complete(Ok(()), handle, self.key_len.get()),
+            Err(e) => self.complete(Err(e), 0, 0),
+        }
+    }
+}
+
+impl<'a, K: kv::KVPermissions<'a>, A: AES256GCM<'a>, R: Rng<'a>> kv::KVClient
+    for KeyStore<'a, K, A, R>
+{
+    fn get_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        mut value: SubSliceMut<'static, u8>,
+    ) {
+        self.kv_key_buffer.replace(key.take());
+        let result = result.and_then(|()| self.load_sealed(value.as_slice()));
+        self.value_buffer.replace(value.take());
+
+        if let Err(e) = result {
+            self.complete(Err(e), 0, 0);
+        }
+    }
+
+    fn set_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.kv_key_buffer.replace(key.take());
+        self.value_buffer.replace(value.take());
+        self.complete(result, 0, 0);
+    }
+
+    fn add_complete(
+        &self,
+        _result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.kv_key_buffer.replace(key.take());
+        self.value_buffer.replace(value.take());
+    }
+
+    fn update_complete(
+        &self,
+        _result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.kv_key_buffer.replace(key.take());
+        self.value_buffer.replace(value.take());
+    }
+
+    fn delete_complete(&self, result: Result<(), ErrorCode>, key: SubSliceMut<'static, u8>) {
+        self.kv_key_buffer.replace(key.take());
+        self.complete(result, 0, 0);
+    }
+
+    fn garbage_collection_complete(&self, _result: Result<(), ErrorCode>) {}
+
+    fn next_key_complete(
+        &self,
+        _result: Result<(), ErrorCode>,
+        _cursor: usize,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.kv_key_buffer.replace(key.take());
+        self.value_buffer.replace(value.take());
+    }
+}
+
+impl<'a, K: kv::KVPermissions<'a>, A: AES256GCM<'a>, R: Rng<'a>> KeyResolver
+    for KeyStore<'a, K, A, R>
+{
+    fn copy_key(
+        &self,
+        processid: ProcessId,
+        handle: usize,
+        key: &mut [u8],
+    ) -> Result<usize, ErrorCode> {
+        self.apps
+            .enter(processid, |app, _| {
+                let slot = app
+                    .slots
+                    .get(handle)
+                    .filter(|slot| slot.len > 0)
+                    .ok_or(ErrorCode::INVAL)?;
+                let dest = key.get_mut(..slot.len).ok_or(ErrorCode::SIZE)?;
+                dest.copy_from_slice(&slot.key[..slot.len]);
+                Ok(slot.len)
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+    }
+}
+
+impl<'a, K: kv::KVPermissions<'a>, A: AES256GCM<'a>, R: Rng<'a>> SyscallDriver
+    for KeyStore<'a, K, A, R>
+{
+    /// Manage the keys of a process.
+    ///
+    /// All operations except close name the key with read-only allow `0`.
+    /// Operations that complete asynchronously signal upcall `0` with the
+    /// status, and for open the handle and the length of the key.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Import the key in read-only allow `1`, replacing any key with
+    ///   the same name.
+    /// - `2`: Generate a random key of `data1` bytes, replacing any key with
+    ///   the same name.
+    /// - `3`: Open a key and return a handle to it in the upcall.
+    /// - `4`: Close the key handle `data1`.
+    /// - `5`: Delete a key. Handles to it stay valid until they are closed.
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        _data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        let op = match command_num {
+            0 => return CommandReturn::success(),
+            1 => UserSpaceOp::Import,
+            2 => UserSpaceOp::Generate(data1),
+            3 => UserSpaceOp::Open,
+            5 => UserSpaceOp::Delete,
+
+            // close
+            4 => {
+                return self
+                    .apps
+                    .enter(processid, |app, _| match app.slots.get_mut(data1) {
+                        Some(slot) if slot.len > 0 => {
+                            slot.clear();
+                            CommandReturn::success()
+                        }
+                        _ => CommandReturn::failure(ErrorCode::INVAL),
+                    })
+                    .unwrap_or_else(|err| err.into());
+            }
+
+            _ => return CommandReturn::failure(ErrorCode::NOSUPPORT),
+        };
+
+        let queued = self
+            .apps
+            .enter(processid, |app, _| {
+                if app.op.is_some() {
+                    // This app already has an operation outstanding.
+                    Err(ErrorCode::BUSY)
+                } else {
+                    app.op.set(op);
+                    Ok(())
+                }
+            })
+            .unwrap_or_else(|err| Err(err.into()));
+        if let Err(e) = queued {
+            return CommandReturn::failure(e);
+        }
+
+        if self.busy() {
+            // Another app or the kernel is using the key store, this request
+            // will run once it is done.
+            return CommandReturn::success();
+        }
+
+        self.processid.set(processid);
+        if let Err(e) = self.run() {
+            self.scrub();
+            self.operation.clear();
+            self.processid.clear();
+            let _ = self.apps.enter(processid, |app, _| app.op.clear());
+            self.check_queue();
+            CommandReturn::failure(e)
+        } else {
+            CommandReturn::success()
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
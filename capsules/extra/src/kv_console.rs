@@ -0,0 +1,213 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Process console command to inspect a KV store.
+//!
+//! `KVConsole` implements the `kv` command of the process console:
+//!
+//! ```text
+//! kv get <key>    Print the value of <key>.
+//! kv list         Print the hash and value length of every key.
+//! ```
+//!
+//! The command accesses the store with the kernel's storage permissions, so
+//! it can read the keys of every application. The store completes operations
+//! asynchronously, so their results are printed with `debug!()`. Values that
+//! are not UTF-8 text are shown by their length.
+//!
+//! `kv list` walks the store with `KVPermissions::next_key()`. Stores such as
+//! TicKV only keep a hash of each key, which is printed in hexadecimal. Values
+//! longer than the buffer of the command are shown with their length as
+//! `>N`.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let kv_console = components::kv::KVConsoleComponent::new(virtual_kv)
+//!     .finalize(components::kv_console_component_static!(VirtualKVPermissions));
+//! pconsole.register_command(kv_console);
+//! ```
+
+use core::cell::Cell;
+use core::fmt;
+use core::str;
+
+use capsules_core::process_console::{Arguments, ConsoleCommand, ConsoleWriter};
+use kernel::debug;
+use kernel::hil::kv;
+use kernel::storage_permissions::StoragePermissions;
+use kernel::utilities::cells::TakeCell;
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::ErrorCode;
+
+pub struct KVConsole<'a, V: kv::KVPermissions<'a>> {
+    kv: &'a V,
+    key_buffer: TakeCell<'static, [u8]>,
+    value_buffer: TakeCell<'static, [u8]>,
+    permissions: StoragePermissions,
+    /// Number of keys printed by the running `kv list`.
+    listed: Cell<usize>,
+}
+
+impl<'a, V: kv::KVPermissions<'a>> KVConsole<'a, V> {
+    /// `permissions` are used for every access, and are normally the
+    /// kernel's permissions.
+    pub fn new(
+        kv: &'a V,
+        key_buffer: &'static mut [u8],
+        value_buffer: &'static mut [u8],
+        permissions: StoragePermissions,
+    ) -> Self {
+        Self {
+            kv,
+            key_buffer: TakeCell::new(key_buffer),
+            value_buffer: TakeCell::new(value_buffer),
+            permissions,
+            listed: Cell::new(0),
+        }
+    }
+
+    fn take_buffers(
+        &self,
+    ) -> Result<(SubSliceMut<'static, u8>, SubSliceMut<'static, u8>), ErrorCode> {
+        match (self.key_buffer.take(), self.value_buffer.take()) {
+            (Some(key_buffer), Some(value_buffer)) => {
+                Ok((SubSliceMut::new(key_buffer), SubSliceMut::new(value_buffer)))
+            }
+            _ => Err(ErrorCode::BUSY),
+        }
+    }
+
+    fn get(&self, name: &str) -> Result<(), ErrorCode> {
+        let (mut key, value) = self.take_buffers()?;
+        if name.len() > key.len() {
+            self.replace_buffers(key, value);
+            return Err(ErrorCode::SIZE);
+        }
+        key.slice(..name.len());
+        key.as_slice().copy_from_slice(name.as_bytes());
+
+        self.kv
+            .get(key, value, self.permissions)
+            .map_err(|(key, value, e)| {
+                self.replace_buffers(key, value);
+                e
+            })
+    }
+
+    fn list(&self) -> Result<(), ErrorCode> {
+        let (key, value) = self.take_buffers()?;
+        self.listed.set(0);
+        self.kv
+            .next_key(0, key, value, self.permissions)
+            .map_err(|(key, value, e)| {
+                self.replace_buffers(key, value);
+                e
+            })
+    }
+
+    fn replace_buffers(&self, key: SubSliceMut<'static, u8>, value: SubSliceMut<'static, u8>) {
+        self.key_buffer.replace(key.take());
+        self.value_buffer.replace(value.take());
+    }
+}
+
+impl<'a, V: kv::KVPermissions<'a>> ConsoleCommand for KVConsole<'a, V> {
+    fn execute(&self, mut args: Arguments, _writer: &mut ConsoleWriter) -> Result<(), ErrorCode> {
+        match args.next() {
+            Some("get") => self.get(args.next().ok_or(ErrorCode::INVAL)?),
+            Some("list") => self.list(),
+            _ => Err(ErrorCode::INVAL),
+        }
+    }
+}
+
+impl<'a, V: kv::KVPermissions<'a>> kv::KVClient for KVConsole<'a, V> {
+    fn get_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        mut value: SubSliceMut<'static, u8>,
+    ) {
+        match result {
+            // On `SIZE` the start of the value is shown.
+            Ok(()) | Err(ErrorCode::SIZE) => match str::from_utf8(value.as_slice()) {
+                Ok(text) => debug!("{}", text),
+                Err(_) => debug!("{} bytes", value.len()),
+            },
+            Err(ErrorCode::NOSUPPORT) => debug!("kv: key not found"),
+            Err(e) => debug!("kv failed: {:?}", e),
+        }
+        self.replace_buffers(key, value);
+    }
+
+    fn set_complete(
+        &self,
+        _result: Result<(), ErrorCode>,
+        _key: SubSliceMut<'static, u8>,
+        _value: SubSliceMut<'static, u8>,
+    ) {
+    }
+
+    fn add_complete(
+        &self,
+        _result: Result<(), ErrorCode>,
+        _key: SubSliceMut<'static, u8>,
+        _value: SubSliceMut<'static, u8>,
+    ) {
+    }
+
+    fn update_complete(
+        &self,
+        _result: Result<(), ErrorCode>,
+        _key: SubSliceMut<'static, u8>,
+        _value: SubSliceMut<'static, u8>,
+    ) {
+    }
+
+    fn delete_complete(&self, _result: Result<(), ErrorCode>, _key: SubSliceMut<'static, u8>) {}
+
+    fn garbage_collection_complete(&self, _result: Result<(), ErrorCode>) {}
+
+    fn next_key_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        cursor: usize,
+        mut key: SubSliceMut<'static, u8>,
+        mut value: SubSliceMut<'static, u8>,
+    ) {
+        match result {
+            Ok(()) | Err(ErrorCode::SIZE) => {
+                let more = if result.is_err() { ">" } else { "" };
+                debug!("{} {}{}", Hex(key.as_slice()), more, value.len());
+                self.listed.set(self.listed.get() + 1);
+
+                key.reset();
+                value.reset();
+                match self.kv.next_key(cursor, key, value, self.permissions) {
+                    Ok(()) => return,
+                    Err((returned_key, returned_value, e)) => {
+                        debug!("kv failed: {:?}", e);
+                        key = returned_key;
+                        value = returned_value;
+                    }
+                }
+            }
+            // There are no more keys.
+            Err(ErrorCode::NOSUPPORT) => debug!("{} keys", self.listed.get()),
+            Err(e) => debug!("kv failed: {:?}", e),
+        }
+        self.replace_buffers(key, value);
+    }
+}
+
+/// Formats bytes, such as a key hash, as hexadecimal.
+struct Hex<'b>(&'b [u8]);
+
+impl fmt::Display for Hex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+    }
+}
@@ -44,6 +44,7 @@ use capsules_core::driver;
 /// Syscall driver number.
 pub const DRIVER_NUM: usize = driver::NUM::Kv as usize;
 
+use core::cell::Cell;
 use core::cmp;
 use kernel::errorcode;
 use kernel::grant::Grant;
@@ -67,10 +68,12 @@ mod ro_allow {
 
 /// IDs for read-write allow buffers.
 mod rw_allow {
-    /// Output value for get.
+    /// Output value for get and next key.
     pub const VALUE: usize = 0;
+    /// Output key for next key.
+    pub const KEY: usize = 1;
     /// The number of RW allow buffers the kernel stores for this grant.
-    pub const COUNT: u8 = 1;
+    pub const COUNT: u8 = 2;
 }
 
 /// IDs for upcalls.
@@ -89,12 +92,15 @@ enum UserSpaceOp {
     Add,
     Update,
     GarbageCollect,
+    NextKey,
 }
 
 /// Contents of the grant for each app.
 #[derive(Default)]
 pub struct App {
     op: OptionalCell<UserSpaceOp>,
+    /// Where to continue listing keys from.
+    cursor: Cell<usize>,
 }
 
 /// Capsule that provides userspace access to a key-value store.
@@ -141,8 +147,9 @@ impl<'a, V: kv::KVPermissions<'a>> KVStoreDriver<'a, V> {
         self.processid.map_or(Err(ErrorCode::RESERVE), |processid| {
             self.apps
                 .enter(processid, |app, kernel_data| {
-                    let key_len = if app.op.is_some() {
-                        // For all operations we need to copy in the key.
+                    let key_len = if app.op.is_some() && !app.op.contains(&UserSpaceOp::NextKey) {
+                        // For all operations except listing the keys we need
+                        // to copy in the key.
                         kernel_data
                             .get_readonly_processbuffer(ro_allow::KEY)
                             .and_then(|buffer| {
@@ -271,6 +278,29 @@ impl<'a, V: kv::KVPermissions<'a>> KVStoreDriver<'a, V> {
                             self.kv.garbage_collect()?;
                             return Ok(());
                         }
+                        Some(UserSpaceOp::NextKey) => {
+                            if let Some(Some(e)) = self.key_buffer.take().map(|key_buf| {
+                                self.value_buffer.take().map(|val_buf| {
+                                    let perms = processid
+                                        .get_storage_permissions()
+                                        .ok_or(ErrorCode::INVAL)?;
+
+                                    let key = SubSliceMut::new(key_buf);
+                                    let value = SubSliceMut::new(val_buf);
+
+                                    if let Err((key_ret, val_ret, e)) =
+                                        self.kv.next_key(app.cursor.get(), key, value, perms)
+                                    {
+                                        self.key_buffer.replace(key_ret.take());
+                                        self.value_buffer.replace(val_ret.take());
+                                        return Err(e);
+                                    }
+                                    Ok(())
+                                })
+                            }) {
+                                return e;
+                            }
+                        }
 
                         _ => {}
                     }
@@ -489,13 +519,93 @@ impl<'a, V: kv::KVPermissions<'a>> kv::KVClient for KVStoreDriver<'a, V> {
         self.processid.clear();
         self.check_queue();
     }
+
+    fn next_key_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        cursor: usize,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.processid.map(|id| {
+            self.apps.enter(id, |app, upcalls| {
+                if app.op.contains(&UserSpaceOp::NextKey) {
+                    app.op.clear();
+                    app.cursor.set(cursor);
+
+                    // On a `SIZE` error the start of the value is still
+                    // provided.
+                    if let Err(e) = result.or_else(|e| match e {
+                        ErrorCode::SIZE => Ok(()),
+                        e => Err(e),
+                    }) {
+                        upcalls
+                            .schedule_upcall(
+                                upcalls::VALUE,
+                                (errorcode::into_statuscode(e.into()), 0, 0),
+                            )
+                            .ok();
+                    } else {
+                        let key_len = key.len();
+                        let value_len = value.len();
+                        let key_ret = upcalls
+                            .get_readwrite_processbuffer(rw_allow::KEY)
+                            .and_then(|buffer| {
+                                buffer.mut_enter(|appslice| {
+                                    let copy_len = cmp::min(key_len, appslice.len());
+                                    appslice[..copy_len].copy_from_slice(&key[..copy_len]);
+                                    if copy_len < key_len {
+                                        Err(ErrorCode::SIZE)
+                                    } else {
+                                        Ok(())
+                                    }
+                                })
+                            })
+                            .unwrap_or(Err(ErrorCode::RESERVE));
+                        let value_ret = upcalls
+                            .get_readwrite_processbuffer(rw_allow::VALUE)
+                            .and_then(|buffer| {
+                                buffer.mut_enter(|appslice| {
+                                    let copy_len = cmp::min(value_len, appslice.len());
+                                    appslice[..copy_len].copy_from_slice(&value[..copy_len]);
+                                    if copy_len < value_len {
+                                        Err(ErrorCode::SIZE)
+                                    } else {
+                                        Ok(())
+                                    }
+                                })
+                            })
+                            .unwrap_or(Err(ErrorCode::RESERVE));
+
+                        // Like for get, userspace should only read the
+                        // portions that fit in its buffers on a `SIZE` error.
+                        let ret = result.and(key_ret).and(value_ret);
+                        upcalls
+                            .schedule_upcall(
+                                upcalls::VALUE,
+                                (errorcode::into_statuscode(ret), value_len, key_len),
+                            )
+                            .ok();
+                    }
+                }
+            })
+        });
+
+        self.key_buffer.replace(key.take());
+        self.value_buffer.replace(value.take());
+
+        // We have completed the operation so see if there is a queued operation
+        // to run next.
+        self.processid.clear();
+        self.check_queue();
+    }
 }
 
 impl<'a, V: kv::KVPermissions<'a>> SyscallDriver for KVStoreDriver<'a, V> {
     fn command(
         &self,
         command_num: usize,
-        _data1: usize,
+        data1: usize,
         _data2: usize,
         processid: ProcessId,
     ) -> CommandReturn {
@@ -503,8 +613,8 @@ impl<'a, V: kv::KVPermissions<'a>> SyscallDriver for KVStoreDriver<'a, V> {
             // check if present
             0 => CommandReturn::success(),
 
-            // get, set, delete, add, update, garbage collect
-            1 | 2 | 3 | 4 | 5 | 6 => {
+            // get, set, delete, add, update, garbage collect, next key
+            1 | 2 | 3 | 4 | 5 | 6 | 7 => {
                 if self.processid.is_none() {
                     // Nothing is using the KV store, so we can handle this
                     // request.
@@ -516,6 +626,12 @@ impl<'a, V: kv::KVPermissions<'a>> SyscallDriver for KVStoreDriver<'a, V> {
                         4 => app.op.set(UserSpaceOp::Add),
                         5 => app.op.set(UserSpaceOp::Update),
                         6 => app.op.set(UserSpaceOp::GarbageCollect),
+                        7 => {
+                            if data1 == 0 {
+                                app.cursor.set(0);
+                            }
+                            app.op.set(UserSpaceOp::NextKey)
+                        }
                         _ => {}
                     });
                     let ret = self.run();
@@ -546,6 +662,12 @@ impl<'a, V: kv::KVPermissions<'a>> SyscallDriver for KVStoreDriver<'a, V> {
                                     4 => app.op.set(UserSpaceOp::Add),
                                     5 => app.op.set(UserSpaceOp::Update),
                                     6 => app.op.set(UserSpaceOp::GarbageCollect),
+                                    7 => {
+                                        if data1 == 0 {
+                                            app.cursor.set(0);
+                                        }
+                                        app.op.set(UserSpaceOp::NextKey)
+                                    }
                                     _ => {}
                                 }
                                 CommandReturn::success()
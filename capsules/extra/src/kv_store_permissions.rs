@@ -41,6 +41,7 @@ enum Operation {
     Update,
     Delete,
     GarbageCollect,
+    NextKey,
 }
 
 /// Current version of the Tock K-V header.
@@ -299,6 +300,41 @@ impl<'a, K: kv::KV<'a>> kv::KVPermissions<'a> for KVStorePermissions<'a, K> {
         self.kv.garbage_collect()
     }
 
+    fn next_key(
+        &self,
+        cursor: usize,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+        permissions: StoragePermissions,
+    ) -> Result<
+        (),
+        (
+            SubSliceMut<'static, u8>,
+            SubSliceMut<'static, u8>,
+            ErrorCode,
+        ),
+    > {
+        if self.operation.is_some() {
+            return Err((key, value, ErrorCode::BUSY));
+        }
+
+        // The header is needed to check the permissions.
+        if value.len() < HEADER_LENGTH {
+            return Err((key, value, ErrorCode::SIZE));
+        }
+
+        self.operation.set(Operation::NextKey);
+        self.valid_ids.set(permissions);
+
+        match self.kv.next_key(cursor, key, value) {
+            Ok(()) => Ok(()),
+            Err((key, val, e)) => {
+                self.operation.clear();
+                Err((key, val, e))
+            }
+        }
+    }
+
     fn header_size(&self) -> usize {
         HEADER_LENGTH
     }
@@ -521,4 +557,60 @@ impl<'a, K: kv::KV<'a>> kv::KVClient for KVStorePermissions<'a, K> {
             cb.garbage_collection_complete(result);
         });
     }
+
+    fn next_key_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        cursor: usize,
+        mut key: SubSliceMut<'static, u8>,
+        mut value: SubSliceMut<'static, u8>,
+    ) {
+        let mut read_allowed = false;
+
+        // Objects without a valid header, like the one the store keeps for
+        // itself, are skipped as well.
+        if (result.is_ok() || result.err() == Some(ErrorCode::SIZE)) && value.len() >= HEADER_LENGTH
+        {
+            let header = KeyHeader::new_from_buf(value.as_slice());
+
+            if header.version == HEADER_VERSION {
+                self.valid_ids.map(|perms| {
+                    read_allowed = perms.check_read_permission(header.write_id);
+                });
+            }
+        }
+
+        if read_allowed {
+            // Remove the header from the accessible portion of the buffer.
+            value.slice(HEADER_LENGTH..);
+            self.operation.clear();
+            self.client.map(move |cb| {
+                cb.next_key_complete(result, cursor, key, value);
+            });
+            return;
+        }
+
+        // Don't leak the value of a key the caller can't read.
+        value.as_slice().iter_mut().for_each(|m| *m = 0);
+
+        if result.is_ok() || result.err() == Some(ErrorCode::SIZE) {
+            // Skip this key and look for the next one.
+            key.reset();
+            value.reset();
+            match self.kv.next_key(cursor, key, value) {
+                Ok(()) => {}
+                Err((key, value, e)) => {
+                    self.operation.clear();
+                    self.client.map(move |cb| {
+                        cb.next_key_complete(Err(e), cursor, key, value);
+                    });
+                }
+            }
+        } else {
+            self.operation.clear();
+            self.client.map(move |cb| {
+                cb.next_key_complete(result, cursor, key, value);
+            });
+        }
+    }
 }
@@ -18,9 +18,11 @@ pub mod analog_comparator;
 pub mod analog_sensor;
 pub mod apds9960;
 pub mod app_flash_driver;
+pub mod app_loader;
 pub mod at24c_eeprom;
 pub mod atecc508a;
 pub mod ble_advertising_driver;
+pub mod block;
 pub mod bme280;
 pub mod bmm150;
 pub mod bmp280;
@@ -37,20 +39,30 @@ pub mod date_time;
 pub mod debug_process_restart;
 pub mod dfrobot_rainfall_sensor;
 pub mod distance;
+pub mod ethernet_tap;
 pub mod eui64;
+pub mod fat;
+pub mod filesystem_driver;
+pub mod flash_fs;
 pub mod fm25cl;
 pub mod ft6x06;
 pub mod fxos8700cq;
 pub mod gpio_async;
 pub mod hc_sr04;
 pub mod hd44780;
+pub mod hkdf;
 pub mod hmac;
+pub mod hmac_drbg;
 pub mod hmac_sha256;
+pub mod hmac_sha512;
 pub mod hs3003;
 pub mod hts221;
 pub mod humidity;
 pub mod ieee802154;
+pub mod ipc_mailbox;
 pub mod isl29035;
+pub mod key_store;
+pub mod kv_console;
 pub mod kv_driver;
 pub mod kv_store_permissions;
 pub mod l3gd20;
@@ -74,6 +86,7 @@ pub mod nonvolatile_storage_driver;
 pub mod nonvolatile_to_pages;
 pub mod nrf51822_serialization;
 pub mod panic_button;
+pub mod pbkdf2;
 pub mod pca9544a;
 pub mod pressure;
 pub mod proximity;
@@ -92,6 +105,7 @@ pub mod sg90;
 pub mod sh1106;
 pub mod sha;
 pub mod sha256;
+pub mod sha512;
 pub mod sht3x;
 pub mod sht4x;
 pub mod si7021;
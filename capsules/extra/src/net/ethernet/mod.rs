@@ -0,0 +1,131 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Ethernet II framing.
+//!
+//! This module defines the Ethernet II frame header and helpers for
+//! mapping IPv6 addresses onto Ethernet MAC addresses (RFC 2464). Frames
+//! exchanged with an [`EthernetAdapterDatapath`] start with the header
+//! defined here and do not include the frame check sequence.
+//!
+//! [`EthernetAdapterDatapath`]: kernel::hil::ethernet::EthernetAdapterDatapath
+
+pub mod virtual_ethernet;
+
+use crate::net::ieee802154::MacAddress;
+use crate::net::ipv6::ip_utils::IPAddr;
+use crate::net::stream::{decode_bytes, decode_u16, encode_bytes, encode_u16, SResult};
+
+/// Length of an Ethernet II header: destination, source and EtherType.
+pub const ETHERNET_HDR_LEN: usize = 14;
+
+/// Maximum payload carried in a single (non-jumbo) Ethernet frame.
+pub const ETHERNET_MTU: usize = 1500;
+
+/// Maximum length of an Ethernet frame excluding the frame check sequence.
+pub const ETHERNET_MAX_FRAME_LEN: usize = ETHERNET_HDR_LEN + ETHERNET_MTU;
+
+pub mod ethertype {
+    pub const IPV4: u16 = 0x0800;
+    pub const ARP: u16 = 0x0806;
+    pub const IPV6: u16 = 0x86DD;
+}
+
+/// A 48-bit Ethernet MAC address.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub struct EthernetAddress(pub [u8; 6]);
+
+impl EthernetAddress {
+    pub const BROADCAST: EthernetAddress = EthernetAddress([0xff; 6]);
+
+    /// Whether the group bit (the least significant bit of the first octet)
+    /// is set. This covers both multicast and broadcast addresses.
+    pub fn is_multicast(&self) -> bool {
+        self.0[0] & 0x01 != 0
+    }
+
+    pub fn is_broadcast(&self) -> bool {
+        *self == Self::BROADCAST
+    }
+
+    /// Maps an IPv6 multicast address onto the corresponding Ethernet
+    /// multicast address `33:33:xx:xx:xx:xx`, where the last four octets
+    /// are the last four octets of the IPv6 address (RFC 2464, Section 7).
+    pub fn from_ipv6_multicast(addr: &IPAddr) -> EthernetAddress {
+        EthernetAddress([0x33, 0x33, addr.0[12], addr.0[13], addr.0[14], addr.0[15]])
+    }
+
+    /// Builds the modified EUI-64 interface identifier for this address by
+    /// inserting `ff:fe` in the middle and flipping the universal/local bit
+    /// (RFC 4291, Appendix A).
+    pub fn to_interface_id(&self) -> [u8; 8] {
+        let m = self.0;
+        [m[0] ^ 0x02, m[1], m[2], 0xff, 0xfe, m[3], m[4], m[5]]
+    }
+
+    /// Builds the IPv6 link-local address `fe80::/64` with the interface
+    /// identifier derived from this address.
+    pub fn to_link_local(&self) -> IPAddr {
+        let mut addr = IPAddr::new();
+        addr.set_unicast_link_local();
+        addr.0[8..16].copy_from_slice(&self.to_interface_id());
+        addr
+    }
+
+    /// Converts an IEEE 802.15.4 extended address carrying an EUI-48 derived
+    /// EUI-64 (with `ff:fe` in the middle) back to the Ethernet address it
+    /// was derived from. Other addresses cannot be represented and yield
+    /// `None`.
+    pub fn from_mac_address(addr: MacAddress) -> Option<EthernetAddress> {
+        match addr {
+            MacAddress::Long(l) if l[3] == 0xff && l[4] == 0xfe => {
+                Some(EthernetAddress([l[0], l[1], l[2], l[5], l[6], l[7]]))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// An Ethernet II frame header.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct EthernetHeader {
+    pub dst: EthernetAddress,
+    pub src: EthernetAddress,
+    pub ethertype: u16,
+}
+
+impl EthernetHeader {
+    pub fn new(dst: EthernetAddress, src: EthernetAddress, ethertype: u16) -> EthernetHeader {
+        EthernetHeader {
+            dst,
+            src,
+            ethertype,
+        }
+    }
+
+    pub fn encode(&self, buf: &mut [u8]) -> SResult<usize> {
+        stream_len_cond!(buf, ETHERNET_HDR_LEN);
+        let off = enc_consume!(buf, 0; encode_bytes, &self.dst.0);
+        let off = enc_consume!(buf, off; encode_bytes, &self.src.0);
+        let off = enc_consume!(buf, off; encode_u16, self.ethertype);
+        stream_done!(off, off);
+    }
+
+    pub fn decode(buf: &[u8]) -> SResult<EthernetHeader> {
+        stream_len_cond!(buf, ETHERNET_HDR_LEN);
+        let mut dst = [0; 6];
+        let mut src = [0; 6];
+        let off = dec_consume!(buf, 0; decode_bytes, &mut dst);
+        let off = dec_consume!(buf, off; decode_bytes, &mut src);
+        let (off, ethertype) = dec_try!(buf, off; decode_u16);
+        stream_done!(
+            off,
+            EthernetHeader {
+                dst: EthernetAddress(dst),
+                src: EthernetAddress(src),
+                ethertype,
+            }
+        );
+    }
+}
@@ -0,0 +1,216 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Virtual Ethernet adapter
+//!
+//! `MuxEthernet` provides multiplexed access to an Ethernet adapter. This
+//! allows a single adapter to be shared by multiple users, for example the
+//! IPv6 stack and the raw-frame userspace tap driver. Transmissions are
+//! sequenced, with each user able to have one frame pending. Every frame
+//! received is provided to all users that have enabled reception, so that
+//! each user can perform its own filtering.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let mux_eth = static_init!(
+//!     capsules_extra::net::ethernet::virtual_ethernet::MuxEthernet<'static>,
+//!     capsules_extra::net::ethernet::virtual_ethernet::MuxEthernet::new(adapter));
+//! adapter.set_client(mux_eth);
+//!
+//! let eth_user = static_init!(
+//!     capsules_extra::net::ethernet::virtual_ethernet::EthernetUser<'static>,
+//!     capsules_extra::net::ethernet::virtual_ethernet::EthernetUser::new(mux_eth));
+//! mux_eth.add_user(eth_user);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::collections::list::{List, ListLink, ListNode};
+use kernel::hil::ethernet::{EthernetAdapterDatapath, EthernetAdapterDatapathClient};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// Ethernet adapter muxer that keeps a list of users and sequences any
+/// pending transmission requests.
+pub struct MuxEthernet<'a> {
+    adapter: &'a dyn EthernetAdapterDatapath<'a>,
+    users: List<'a, EthernetUser<'a>>,
+    inflight: OptionalCell<&'a EthernetUser<'a>>,
+    receiving: Cell<bool>,
+}
+
+impl<'a> MuxEthernet<'a> {
+    pub const fn new(adapter: &'a dyn EthernetAdapterDatapath<'a>) -> MuxEthernet<'a> {
+        MuxEthernet {
+            adapter,
+            users: List::new(),
+            inflight: OptionalCell::empty(),
+            receiving: Cell::new(false),
+        }
+    }
+
+    /// Registers a user with this mux. Each user should only be registered
+    /// once.
+    pub fn add_user(&self, user: &'a EthernetUser<'a>) {
+        self.users.push_head(user);
+    }
+
+    /// Enables reception on the adapter while at least one user wants to
+    /// receive frames, and disables it otherwise.
+    fn update_receive(&self) {
+        let receive = self.users.iter().any(|user| user.receive_enabled.get());
+        if receive != self.receiving.get() {
+            self.receiving.set(receive);
+            if receive {
+                self.adapter.enable_receive();
+            } else {
+                self.adapter.disable_receive();
+            }
+        }
+    }
+
+    /// Starts the next pending transmission, if the adapter is idle.
+    ///
+    /// Returns the result of the adapter call if the transmission started
+    /// belongs to `requester`, so that it can be reported synchronously.
+    /// Failures of other users' transmissions are reported through their
+    /// clients.
+    fn do_next_op(
+        &self,
+        requester: Option<&EthernetUser<'a>>,
+    ) -> Option<Result<(), (ErrorCode, &'static mut [u8])>> {
+        if self.inflight.is_some() {
+            return None;
+        }
+
+        let user = self.users.iter().find(|user| user.pending.is_some())?;
+        let frame = user.pending.take()?;
+        let (len, id) = user.pending_info.get();
+        let result = self.adapter.transmit_frame(frame, len, id);
+
+        let sync = requester.is_some_and(|r| core::ptr::eq(r, user));
+        match result {
+            Ok(()) => {
+                self.inflight.set(user);
+                sync.then_some(Ok(()))
+            }
+            Err((ecode, frame)) => {
+                if sync {
+                    Some(Err((ecode, frame)))
+                } else {
+                    user.client
+                        .map(|client| client.transmit_frame_done(Err(ecode), frame, len, id, None));
+                    // Give the remaining users a chance to transmit.
+                    self.do_next_op(None);
+                    None
+                }
+            }
+        }
+    }
+}
+
+impl EthernetAdapterDatapathClient for MuxEthernet<'_> {
+    fn transmit_frame_done(
+        &self,
+        err: Result<(), ErrorCode>,
+        frame_buffer: &'static mut [u8],
+        len: u16,
+        transmission_identifier: usize,
+        timestamp: Option<u64>,
+    ) {
+        self.inflight.take().map(|user| {
+            user.client.map(|client| {
+                client.transmit_frame_done(
+                    err,
+                    frame_buffer,
+                    len,
+                    transmission_identifier,
+                    timestamp,
+                )
+            })
+        });
+        self.do_next_op(None);
+    }
+
+    fn received_frame(&self, frame: &[u8], timestamp: Option<u64>) {
+        for user in self.users.iter() {
+            if user.receive_enabled.get() {
+                user.client
+                    .map(|client| client.received_frame(frame, timestamp));
+            }
+        }
+    }
+}
+
+/// A user of the virtualized Ethernet adapter.
+///
+/// Each `EthernetUser` behaves like an independent Ethernet adapter, except
+/// that it shares the MAC address and link of the underlying adapter.
+pub struct EthernetUser<'a> {
+    mux: &'a MuxEthernet<'a>,
+    pending: TakeCell<'static, [u8]>,
+    pending_info: Cell<(u16, usize)>,
+    receive_enabled: Cell<bool>,
+    next: ListLink<'a, EthernetUser<'a>>,
+    client: OptionalCell<&'a dyn EthernetAdapterDatapathClient>,
+}
+
+impl<'a> EthernetUser<'a> {
+    pub const fn new(mux: &'a MuxEthernet<'a>) -> EthernetUser<'a> {
+        EthernetUser {
+            mux,
+            pending: TakeCell::empty(),
+            pending_info: Cell::new((0, 0)),
+            receive_enabled: Cell::new(false),
+            next: ListLink::empty(),
+            client: OptionalCell::empty(),
+        }
+    }
+}
+
+impl<'a> ListNode<'a, EthernetUser<'a>> for EthernetUser<'a> {
+    fn next(&'a self) -> &'a ListLink<'a, EthernetUser<'a>> {
+        &self.next
+    }
+}
+
+impl<'a> EthernetAdapterDatapath<'a> for EthernetUser<'a> {
+    fn set_client(&self, client: &'a dyn EthernetAdapterDatapathClient) {
+        self.client.set(client);
+    }
+
+    fn enable_receive(&self) {
+        self.receive_enabled.set(true);
+        self.mux.update_receive();
+    }
+
+    fn disable_receive(&self) {
+        self.receive_enabled.set(false);
+        self.mux.update_receive();
+    }
+
+    fn transmit_frame(
+        &self,
+        frame_buffer: &'static mut [u8],
+        len: u16,
+        transmission_identifier: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        // Each user can only have one transmission request queued in addition
+        // to the one that may currently be in flight.
+        if self.pending.is_some() {
+            return Err((ErrorCode::BUSY, frame_buffer));
+        }
+        if usize::from(len) > frame_buffer.len() {
+            return Err((ErrorCode::SIZE, frame_buffer));
+        }
+
+        self.pending.replace(frame_buffer);
+        self.pending_info.set((len, transmission_identifier));
+        self.mux.do_next_op(Some(self)).unwrap_or(Ok(()))
+    }
+}
@@ -0,0 +1,134 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! ICMPv6 userspace interface for receiving messages.
+//!
+//! Delivers the ICMPv6 messages passed up by the
+//! [ICMP6RecvStruct](../icmpv6_recv/struct.ICMP6RecvStruct.html) to every
+//! process that has subscribed to them. This lets apps learn about
+//! unreachable peers (Destination Unreachable, Packet Too Big) and observe
+//! Echo Replies. Echo Requests are answered by the kernel and are not
+//! delivered.
+
+use crate::net::icmpv6::icmpv6_recv::ICMP6RecvClient;
+use crate::net::icmpv6::ICMP6Header;
+use crate::net::ipv6::ip_utils::IPAddr;
+use crate::net::ipv6::IP6Header;
+
+use core::cmp;
+use core::mem::size_of;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::processbuffer::WriteableProcessBuffer;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::Icmpv6 as usize;
+
+/// Length of the ICMPv6 header copied to the read buffer.
+const ICMP_HDR_LEN: usize = 8;
+
+/// IDs for subscribed upcalls.
+mod upcall {
+    /// Callback for when a message is received. The arguments are the number
+    /// of bytes written to the read buffer, the ICMPv6 type and the ICMPv6
+    /// code.
+    pub const MESSAGE_RECEIVED: usize = 0;
+    /// Number of upcalls.
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Read buffer. Will contain the 16 byte IPv6 address of the sender,
+    /// followed by the ICMPv6 message (header and body), truncated to the
+    /// length of the buffer. Messages are not delivered if the buffer cannot
+    /// hold the sender address.
+    pub const READ: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+#[derive(Default)]
+pub struct App {}
+
+pub struct ICMP6Driver {
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<0>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+}
+
+impl ICMP6Driver {
+    pub fn new(
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<0>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+    ) -> ICMP6Driver {
+        ICMP6Driver { apps: grant }
+    }
+}
+
+impl SyscallDriver for ICMP6Driver {
+    /// ICMPv6 control
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    fn command(&self, command_num: usize, _: usize, _: usize, _: ProcessId) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+impl ICMP6RecvClient for ICMP6Driver {
+    fn receive(&self, ip_header: &IP6Header, icmp_header: ICMP6Header, payload: &[u8]) {
+        let src_addr = ip_header.get_src_addr();
+        let mut header = [0; ICMP_HDR_LEN];
+        if icmp_header.encode(&mut header, 0).done().is_none() {
+            return;
+        }
+
+        self.apps.each(|_, _, kernel_data| {
+            let len = kernel_data
+                .get_readwrite_processbuffer(rw_allow::READ)
+                .and_then(|read| {
+                    read.mut_enter(|rbuf| {
+                        let mut copied = 0;
+                        for part in [&src_addr.0[..], &header[..], payload] {
+                            let n = cmp::min(part.len(), rbuf.len() - copied);
+                            rbuf[copied..copied + n].copy_from_slice(&part[..n]);
+                            copied += n;
+                        }
+                        copied
+                    })
+                })
+                .unwrap_or(0);
+            if len >= size_of::<IPAddr>() {
+                kernel_data
+                    .schedule_upcall(
+                        upcall::MESSAGE_RECEIVED,
+                        (
+                            len,
+                            icmp_header.get_type_as_int() as usize,
+                            icmp_header.get_code() as usize,
+                        ),
+                    )
+                    .ok();
+            }
+        });
+    }
+}
@@ -23,27 +23,68 @@ pub struct ICMP6Header {
 
 #[derive(Copy, Clone)]
 pub enum ICMP6HeaderOptions {
-    Type1 { unused: u32 },
-    Type3 { unused: u32 },
-    Type128 { id: u16, seqno: u16 },
-    Type129 { id: u16, seqno: u16 },
+    Type1 {
+        unused: u32,
+    },
+    Type2 {
+        mtu: u32,
+    },
+    Type3 {
+        unused: u32,
+    },
+    Type128 {
+        id: u16,
+        seqno: u16,
+    },
+    Type129 {
+        id: u16,
+        seqno: u16,
+    },
+    Type133 {
+        reserved: u32,
+    },
+    Type134 {
+        hop_limit: u8,
+        flags: u8,
+        router_lifetime: u16,
+    },
+    Type135 {
+        reserved: u32,
+    },
+    Type136 {
+        flags: u32,
+    },
 }
 
 #[derive(Copy, Clone)]
 pub enum ICMP6Type {
     Type1,   // Destination Unreachable
+    Type2,   // Packet Too Big
     Type3,   // Time Exceeded
     Type128, // Echo Request
     Type129, // Echo Reply
+    Type133, // Router Solicitation
+    Type134, // Router Advertisement
+    Type135, // Neighbor Solicitation
+    Type136, // Neighbor Advertisement
 }
 
 impl ICMP6Header {
     pub fn new(icmp_type: ICMP6Type) -> ICMP6Header {
         let options = match icmp_type {
             ICMP6Type::Type1 => ICMP6HeaderOptions::Type1 { unused: 0 },
+            ICMP6Type::Type2 => ICMP6HeaderOptions::Type2 { mtu: 0 },
             ICMP6Type::Type3 => ICMP6HeaderOptions::Type3 { unused: 0 },
             ICMP6Type::Type128 => ICMP6HeaderOptions::Type128 { id: 0, seqno: 0 },
             ICMP6Type::Type129 => ICMP6HeaderOptions::Type129 { id: 0, seqno: 0 },
+            ICMP6Type::Type133 => ICMP6HeaderOptions::Type133 { reserved: 0 },
+            ICMP6Type::Type134 => ICMP6HeaderOptions::Type134 {
+                hop_limit: 0,
+                flags: 0,
+                router_lifetime: 0,
+            },
+            ICMP6Type::Type135 => ICMP6HeaderOptions::Type135 { reserved: 0 },
+            ICMP6Type::Type136 => ICMP6HeaderOptions::Type136 { flags: 0 },
         };
 
         ICMP6Header {
@@ -55,12 +96,7 @@ impl ICMP6Header {
     }
 
     pub fn set_type(&mut self, icmp_type: ICMP6Type) {
-        match icmp_type {
-            ICMP6Type::Type1 => self.set_options(ICMP6HeaderOptions::Type1 { unused: 0 }),
-            ICMP6Type::Type3 => self.set_options(ICMP6HeaderOptions::Type3 { unused: 0 }),
-            ICMP6Type::Type128 => self.set_options(ICMP6HeaderOptions::Type128 { id: 0, seqno: 0 }),
-            ICMP6Type::Type129 => self.set_options(ICMP6HeaderOptions::Type129 { id: 0, seqno: 0 }),
-        }
+        self.set_options(Self::new(icmp_type).options);
     }
 
     pub fn set_code(&mut self, code: u8) {
@@ -82,18 +118,28 @@ impl ICMP6Header {
     pub fn get_type(&self) -> ICMP6Type {
         match self.options {
             ICMP6HeaderOptions::Type1 { .. } => ICMP6Type::Type1,
+            ICMP6HeaderOptions::Type2 { .. } => ICMP6Type::Type2,
             ICMP6HeaderOptions::Type3 { .. } => ICMP6Type::Type3,
             ICMP6HeaderOptions::Type128 { .. } => ICMP6Type::Type128,
             ICMP6HeaderOptions::Type129 { .. } => ICMP6Type::Type129,
+            ICMP6HeaderOptions::Type133 { .. } => ICMP6Type::Type133,
+            ICMP6HeaderOptions::Type134 { .. } => ICMP6Type::Type134,
+            ICMP6HeaderOptions::Type135 { .. } => ICMP6Type::Type135,
+            ICMP6HeaderOptions::Type136 { .. } => ICMP6Type::Type136,
         }
     }
 
     pub fn get_type_as_int(&self) -> u8 {
         match self.get_type() {
             ICMP6Type::Type1 => 1,
+            ICMP6Type::Type2 => 2,
             ICMP6Type::Type3 => 3,
             ICMP6Type::Type128 => 128,
             ICMP6Type::Type129 => 129,
+            ICMP6Type::Type133 => 133,
+            ICMP6Type::Type134 => 134,
+            ICMP6Type::Type135 => 135,
+            ICMP6Type::Type136 => 136,
         }
     }
 
@@ -139,6 +185,24 @@ impl ICMP6Header {
             ICMP6HeaderOptions::Type1 { unused } | ICMP6HeaderOptions::Type3 { unused } => {
                 off = enc_consume!(buf, off; encode_u32, unused);
             }
+            ICMP6HeaderOptions::Type133 { reserved } | ICMP6HeaderOptions::Type135 { reserved } => {
+                off = enc_consume!(buf, off; encode_u32, reserved);
+            }
+            ICMP6HeaderOptions::Type134 {
+                hop_limit,
+                flags,
+                router_lifetime,
+            } => {
+                off = enc_consume!(buf, off; encode_u8, hop_limit);
+                off = enc_consume!(buf, off; encode_u8, flags);
+                off = enc_consume!(buf, off; encode_u16, router_lifetime);
+            }
+            ICMP6HeaderOptions::Type136 { flags } => {
+                off = enc_consume!(buf, off; encode_u32, flags);
+            }
+            ICMP6HeaderOptions::Type2 { mtu } => {
+                off = enc_consume!(buf, off; encode_u32, mtu);
+            }
             ICMP6HeaderOptions::Type128 { id, seqno }
             | ICMP6HeaderOptions::Type129 { id, seqno } => {
                 off = enc_consume!(buf, off; encode_u16, id);
@@ -157,16 +221,22 @@ impl ICMP6Header {
     ///
     /// # Return Value
     ///
-    /// This function returns the `ICMP6Header`, wrapped in an SResult
+    /// This function returns the `ICMP6Header`, wrapped in an SResult. The
+    /// `len` field is set to the length of `buf`.
     pub fn decode(buf: &[u8]) -> SResult<ICMP6Header> {
         let off = 0;
         let (off, type_num) = dec_try!(buf, off; decode_u8);
 
         let icmp_type = match type_num {
             1 => ICMP6Type::Type1,
+            2 => ICMP6Type::Type2,
             3 => ICMP6Type::Type3,
             128 => ICMP6Type::Type128,
             129 => ICMP6Type::Type129,
+            133 => ICMP6Type::Type133,
+            134 => ICMP6Type::Type134,
+            135 => ICMP6Type::Type135,
+            136 => ICMP6Type::Type136,
             _ => return SResult::Error(()),
         };
 
@@ -175,34 +245,64 @@ impl ICMP6Header {
         let (off, code) = dec_try!(buf, off; decode_u8);
         icmp_header.set_code(code);
         let (off, cksum) = dec_try!(buf, off; decode_u16);
-        icmp_header.set_cksum(u16::from_be(cksum));
+        icmp_header.set_cksum(cksum);
 
-        match icmp_type {
+        let off = match icmp_type {
             ICMP6Type::Type1 => {
-                let (_off, unused) = dec_try!(buf, off; decode_u32);
-                let unused = u32::from_be(unused);
+                let (off, unused) = dec_try!(buf, off; decode_u32);
                 icmp_header.set_options(ICMP6HeaderOptions::Type1 { unused });
+                off
+            }
+            ICMP6Type::Type2 => {
+                let (off, mtu) = dec_try!(buf, off; decode_u32);
+                icmp_header.set_options(ICMP6HeaderOptions::Type2 { mtu });
+                off
             }
             ICMP6Type::Type3 => {
-                let (_off, unused) = dec_try!(buf, off; decode_u32);
-                let unused = u32::from_be(unused);
+                let (off, unused) = dec_try!(buf, off; decode_u32);
                 icmp_header.set_options(ICMP6HeaderOptions::Type3 { unused });
+                off
             }
             ICMP6Type::Type128 => {
-                let (_off, id) = dec_try!(buf, off; decode_u16);
-                let id = u16::from_be(id);
-                let (_off, seqno) = dec_try!(buf, off; decode_u16);
-                let seqno = u16::from_be(seqno);
+                let (off, id) = dec_try!(buf, off; decode_u16);
+                let (off, seqno) = dec_try!(buf, off; decode_u16);
                 icmp_header.set_options(ICMP6HeaderOptions::Type128 { id, seqno });
+                off
             }
             ICMP6Type::Type129 => {
-                let (_off, id) = dec_try!(buf, off; decode_u16);
-                let id = u16::from_be(id);
-                let (_off, seqno) = dec_try!(buf, off; decode_u16);
-                let seqno = u16::from_be(seqno);
+                let (off, id) = dec_try!(buf, off; decode_u16);
+                let (off, seqno) = dec_try!(buf, off; decode_u16);
                 icmp_header.set_options(ICMP6HeaderOptions::Type129 { id, seqno });
+                off
             }
-        }
+            ICMP6Type::Type133 => {
+                let (off, reserved) = dec_try!(buf, off; decode_u32);
+                icmp_header.set_options(ICMP6HeaderOptions::Type133 { reserved });
+                off
+            }
+            ICMP6Type::Type134 => {
+                let (off, hop_limit) = dec_try!(buf, off; decode_u8);
+                let (off, flags) = dec_try!(buf, off; decode_u8);
+                let (off, router_lifetime) = dec_try!(buf, off; decode_u16);
+                icmp_header.set_options(ICMP6HeaderOptions::Type134 {
+                    hop_limit,
+                    flags,
+                    router_lifetime,
+                });
+                off
+            }
+            ICMP6Type::Type135 => {
+                let (off, reserved) = dec_try!(buf, off; decode_u32);
+                icmp_header.set_options(ICMP6HeaderOptions::Type135 { reserved });
+                off
+            }
+            ICMP6Type::Type136 => {
+                let (off, flags) = dec_try!(buf, off; decode_u32);
+                icmp_header.set_options(ICMP6HeaderOptions::Type136 { flags });
+                off
+            }
+        };
+        icmp_header.set_len(buf.len() as u16);
 
         stream_done!(off, icmp_header);
     }
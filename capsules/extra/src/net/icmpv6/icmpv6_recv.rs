@@ -0,0 +1,174 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Definition and implementation of the ICMPv6 reception interface.
+//!
+//! `ICMP6RecvStruct` is set as the ICMPv6 client of an `IP6Receiver`, which
+//! has already verified the checksum of every message it passes up. Echo
+//! Requests addressed to one of the local interfaces are answered directly
+//! by this layer, using its own `IP6Sender`. All other messages (e.g.
+//! Destination Unreachable, Packet Too Big or Echo Reply) are dispatched to
+//! every registered [ICMP6Receiver](struct.ICMP6Receiver.html), such as the
+//! UDP layer or the ICMPv6 userspace driver.
+//!
+//! Messages of types not supported by `ICMP6Header` are silently discarded,
+//! as required by RFC 4443 for unknown informational messages.
+
+use crate::net::icmpv6::{ICMP6Header, ICMP6HeaderOptions, ICMP6Type};
+use crate::net::ipv6::ip_utils::{ip6_nh, IPAddr};
+use crate::net::ipv6::ipv6_recv::IP6RecvClient;
+use crate::net::ipv6::ipv6_send::{IP6SendClient, IP6Sender};
+use crate::net::ipv6::{IP6Header, TransportHeader};
+use crate::net::network_capabilities::NetworkCapability;
+
+use core::cell::Cell;
+
+use kernel::collections::list::{List, ListLink, ListNode};
+use kernel::debug;
+use kernel::utilities::cells::{MapCell, OptionalCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::ErrorCode;
+
+/// Client interface trait to receive ICMPv6 messages.
+pub trait ICMP6RecvClient {
+    /// Called for every received ICMPv6 message other than an Echo Request.
+    /// `ip_header` is the IPv6 header of the packet carrying the message,
+    /// e.g. for clients that need to check its hop limit. `payload` is the
+    /// message body following the 8 byte header. For error messages, it
+    /// holds as much of the packet that caused the error as fit into the
+    /// message, starting with its IPv6 header.
+    fn receive(&self, ip_header: &IP6Header, icmp_header: ICMP6Header, payload: &[u8]);
+}
+
+/// This struct is registered with the ICMP6RecvStruct, and passes received
+/// messages up to whatever client assigns itself as the ICMP6RecvClient held
+/// by this ICMP6Receiver.
+pub struct ICMP6Receiver<'a> {
+    client: OptionalCell<&'a dyn ICMP6RecvClient>,
+    next: ListLink<'a, ICMP6Receiver<'a>>,
+}
+
+impl<'a> ListNode<'a, ICMP6Receiver<'a>> for ICMP6Receiver<'a> {
+    fn next(&'a self) -> &'a ListLink<'a, ICMP6Receiver<'a>> {
+        &self.next
+    }
+}
+
+impl<'a> ICMP6Receiver<'a> {
+    pub fn new() -> ICMP6Receiver<'a> {
+        ICMP6Receiver {
+            client: OptionalCell::empty(),
+            next: ListLink::empty(),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn ICMP6RecvClient) {
+        self.client.set(client);
+    }
+}
+
+pub struct ICMP6RecvStruct<'a> {
+    rcvr_list: List<'a, ICMP6Receiver<'a>>,
+    ip_sender: &'a dyn IP6Sender<'a>,
+    interface_list: &'static [IPAddr],
+    /// Holds the data of the Echo Reply being sent.
+    reply_buffer: MapCell<SubSliceMut<'static, u8>>,
+    busy: Cell<bool>,
+    net_cap: &'static NetworkCapability,
+}
+
+impl<'a> ICMP6RecvStruct<'a> {
+    pub fn new(
+        ip_sender: &'a dyn IP6Sender<'a>,
+        interface_list: &'static [IPAddr],
+        reply_buffer: SubSliceMut<'static, u8>,
+        net_cap: &'static NetworkCapability,
+    ) -> ICMP6RecvStruct<'a> {
+        ICMP6RecvStruct {
+            rcvr_list: List::new(),
+            ip_sender,
+            interface_list,
+            reply_buffer: MapCell::new(reply_buffer),
+            busy: Cell::new(false),
+            net_cap,
+        }
+    }
+
+    pub fn add_client(&self, rcvr: &'a ICMP6Receiver<'a>) {
+        self.rcvr_list.push_tail(rcvr);
+    }
+
+    /// Answers an Echo Request from `dst` carrying `data`. The request is
+    /// dropped if a previous reply is still being sent or the data does not
+    /// fit into the reply buffer, as the reply must contain all of it.
+    fn send_echo_reply(&self, dst: IPAddr, id: u16, seqno: u16, data: &[u8]) {
+        if self.busy.get() {
+            return;
+        }
+        self.reply_buffer.take().map(|mut buf| {
+            if data.len() > buf.len() {
+                self.reply_buffer.replace(buf);
+                return;
+            }
+            buf[..data.len()].copy_from_slice(data);
+            buf.slice(0..data.len());
+
+            let mut icmp_header = ICMP6Header::new(ICMP6Type::Type129);
+            icmp_header.set_options(ICMP6HeaderOptions::Type129 { id, seqno });
+            icmp_header.set_len((icmp_header.get_hdr_size() + data.len()) as u16);
+
+            self.busy.set(true);
+            let result =
+                self.ip_sender
+                    .send_to(dst, TransportHeader::ICMP(icmp_header), &buf, self.net_cap);
+            buf.reset();
+            self.reply_buffer.replace(buf);
+            if result != Ok(()) {
+                debug!("[ICMP6] Error: failed to send echo reply: {:?}", result);
+                self.busy.set(false);
+            }
+        });
+    }
+}
+
+impl IP6RecvClient for ICMP6RecvStruct<'_> {
+    fn receive(&self, ip_header: IP6Header, payload: &[u8]) {
+        if ip_header.get_next_header() != ip6_nh::ICMP {
+            return;
+        }
+        let (offset, icmp_header) = match ICMP6Header::decode(payload).done() {
+            Some(decoded) => decoded,
+            None => return,
+        };
+        let src_addr = ip_header.get_src_addr();
+        let dst_addr = ip_header.get_dst_addr();
+
+        match icmp_header.get_options() {
+            ICMP6HeaderOptions::Type128 { id, seqno } => {
+                // Only answer requests sent to one of our unicast addresses.
+                if self.interface_list.contains(&dst_addr) {
+                    self.send_echo_reply(src_addr, id, seqno, &payload[offset..]);
+                }
+            }
+            _ => {
+                for rcvr in self.rcvr_list.iter() {
+                    rcvr.client
+                        .map(|client| client.receive(&ip_header, icmp_header, &payload[offset..]));
+                }
+            }
+        }
+    }
+}
+
+impl IP6SendClient for ICMP6RecvStruct<'_> {
+    fn send_done(&self, result: Result<(), ErrorCode>) {
+        if result != Ok(()) {
+            debug!(
+                "[ICMP6] Error: echo reply transmission failed: {:?}",
+                result
+            );
+        }
+        self.busy.set(false);
+    }
+}
@@ -2,8 +2,13 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 // Copyright Tock Contributors 2022.
 
+pub mod driver;
+pub mod icmpv6_recv;
 pub mod icmpv6_send;
 
+pub use self::driver::ICMP6Driver;
+pub use self::driver::DRIVER_NUM;
+
 // Reexport the exports of the [`icmpv6`] module, to avoid redundant
 // module paths (e.g. `capsules::net::icmpv6::icmpv6::ICMP6Header`)
 mod icmpv6;
@@ -11,6 +11,7 @@
 use crate::net::icmpv6::{ICMP6Header, ICMP6HeaderOptions};
 use crate::net::ieee802154::MacAddress;
 use crate::net::ipv6::IP6Header;
+use crate::net::tcp::{TCPHeader, TCP_HDR_LEN};
 use crate::net::udp::UDPHeader;
 
 #[derive(Copy, Clone, PartialEq)]
@@ -186,6 +187,12 @@ pub fn compute_udp_checksum(
     sum as u16 //Return result as u16 in host byte order */
 }
 
+/// Computes the ICMPv6 checksum over the IPv6 pseudo-header, the ICMPv6
+/// header and the message body (RFC 4443, Section 2.3).
+///
+/// The checksum field of `icmp_header` is included in the sum, so it should
+/// be zero when building a message. When verifying a received message, a
+/// result of zero indicates that the checksum is correct.
 pub fn compute_icmp_checksum(
     ipv6_header: &IP6Header,
     icmp_header: &ICMP6Header,
@@ -196,10 +203,11 @@ pub fn compute_icmp_checksum(
     // add ipv6 pseudo-header
     sum += compute_ipv6_ph_sum(ipv6_header);
 
-    // add type and code
+    // add type, code and checksum
     let msb = (icmp_header.get_type_as_int() as u32) << 8;
     let lsb = icmp_header.get_code() as u32;
     sum += msb + lsb;
+    sum += icmp_header.get_cksum() as u32;
 
     // add options
     match icmp_header.get_options() {
@@ -207,6 +215,26 @@ pub fn compute_icmp_checksum(
             sum += unused >> 16; // upper 16 bits
             sum += unused & 0xffff; // lower 16 bits
         }
+        ICMP6HeaderOptions::Type2 { mtu } => {
+            sum += mtu >> 16; // upper 16 bits
+            sum += mtu & 0xffff; // lower 16 bits
+        }
+        ICMP6HeaderOptions::Type133 { reserved } | ICMP6HeaderOptions::Type135 { reserved } => {
+            sum += reserved >> 16; // upper 16 bits
+            sum += reserved & 0xffff; // lower 16 bits
+        }
+        ICMP6HeaderOptions::Type134 {
+            hop_limit,
+            flags,
+            router_lifetime,
+        } => {
+            sum += ((hop_limit as u32) << 8) + flags as u32;
+            sum += router_lifetime as u32;
+        }
+        ICMP6HeaderOptions::Type136 { flags } => {
+            sum += flags >> 16; // upper 16 bits
+            sum += flags & 0xffff; // lower 16 bits
+        }
         ICMP6HeaderOptions::Type128 { id, seqno } | ICMP6HeaderOptions::Type129 { id, seqno } => {
             sum += id as u32;
             sum += seqno as u32;
@@ -221,7 +249,7 @@ pub fn compute_icmp_checksum(
     while sum > 0xffff {
         let sum_upper = sum >> 16;
         let sum_lower = sum & 0xffff;
-        sum += sum_upper + sum_lower;
+        sum = sum_upper + sum_lower;
     }
 
     sum = !sum;
@@ -230,6 +258,65 @@ pub fn compute_icmp_checksum(
     sum as u16
 }
 
+/// Computes the TCP checksum over the IPv6 pseudo-header, the TCP header
+/// and the segment payload (RFC 9293, Section 3.1).
+///
+/// The checksum field of `tcp_header` is included in the sum. When building
+/// a segment it should therefore be zero, and the return value is the
+/// checksum to transmit. When verifying a received segment, a result of zero
+/// indicates that the checksum is correct.
+///
+/// `payload` holds everything following the fixed 20 byte header, so for
+/// received segments any TCP options are summed as part of it.
+pub fn compute_tcp_checksum(ip6_header: &IP6Header, tcp_header: &TCPHeader, payload: &[u8]) -> u16 {
+    let tcp_len = tcp_header.get_len() as u32;
+    let payload_len = tcp_len as usize - TCP_HDR_LEN;
+    let mut sum: u32 = 0;
+
+    // add ipv6 pseudo-header
+    let mut i = 0;
+    while i < 16 {
+        sum += ((ip6_header.src_addr.0[i] as u32) << 8) + ip6_header.src_addr.0[i + 1] as u32;
+        sum += ((ip6_header.dst_addr.0[i] as u32) << 8) + ip6_header.dst_addr.0[i + 1] as u32;
+        i += 2;
+    }
+    sum += tcp_len >> 16;
+    sum += tcp_len & 0xffff;
+    sum += ip6_nh::TCP as u32;
+
+    // add tcp header
+    sum += tcp_header.get_src_port() as u32;
+    sum += tcp_header.get_dst_port() as u32;
+    sum += tcp_header.get_seq_num() >> 16;
+    sum += tcp_header.get_seq_num() & 0xffff;
+    sum += tcp_header.get_ack_num() >> 16;
+    sum += tcp_header.get_ack_num() & 0xffff;
+    sum += (((tcp_header.get_data_offset() / 4) as u32) << 12) | tcp_header.get_flags() as u32;
+    sum += tcp_header.get_window() as u32;
+    sum += tcp_header.get_cksum() as u32;
+    sum += tcp_header.get_urg_ptr() as u32;
+
+    // add tcp payload, padding an odd trailing byte with zero
+    let mut i = 0;
+    while i < payload_len {
+        let msb = (payload[i] as u32) << 8;
+        let lsb = if i + 1 < payload_len {
+            payload[i + 1] as u32
+        } else {
+            0
+        };
+        sum += msb + lsb;
+        i += 2;
+    }
+
+    // carry overflow
+    while sum > 0xffff {
+        sum = (sum >> 16) + (sum & 0xffff);
+    }
+
+    !sum as u16
+}
+
 pub fn compute_ipv6_ph_sum(ip6_header: &IP6Header) -> u32 {
     let mut sum: u32 = 0;
 
@@ -247,7 +334,7 @@ pub fn compute_ipv6_ph_sum(ip6_header: &IP6Header) -> u32 {
         i += 2;
     }
 
-    sum += ip6_header.payload_len as u32;
+    sum += ip6_header.get_payload_len() as u32;
     sum += ip6_header.next_header as u32;
 
     sum
@@ -259,10 +346,108 @@ pub fn compute_sum(buf: &[u8], len: u16) -> u32 {
     let mut i: usize = 0;
     while i < (len as usize) {
         let msb = (buf[i] as u32) << 8;
-        let lsb = buf[i + 1] as u32;
+        // Pad an odd trailing byte with zero
+        let lsb = if i + 1 < len as usize {
+            buf[i + 1] as u32
+        } else {
+            0
+        };
         sum += msb + lsb;
         i += 2;
     }
 
     sum
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::net::icmpv6::ICMP6Type;
+    use std::vec::Vec;
+
+    const SRC_ADDR: IPAddr = IPAddr([
+        0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0x02, 0x11, 0x22, 0xff, 0xfe, 0x33, 0x44, 0x55,
+    ]);
+    const DST_ADDR: IPAddr = IPAddr([
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xfe,
+    ]);
+
+    /// The headers of an echo request with `payload`.
+    fn echo_request(payload: &[u8], cksum: u16) -> (IP6Header, ICMP6Header) {
+        let mut icmp_header = ICMP6Header::new(ICMP6Type::Type128);
+        icmp_header.set_options(ICMP6HeaderOptions::Type128 {
+            id: 0xfffe,
+            seqno: 0xff01,
+        });
+        icmp_header.set_len((icmp_header.get_hdr_size() + payload.len()) as u16);
+        icmp_header.set_cksum(cksum);
+
+        let mut ip6_header = IP6Header::new();
+        ip6_header.src_addr = SRC_ADDR;
+        ip6_header.dst_addr = DST_ADDR;
+        ip6_header.set_next_header(ip6_nh::ICMP);
+        ip6_header.set_payload_len(icmp_header.get_len());
+        (ip6_header, icmp_header)
+    }
+
+    /// The RFC 1071 checksum of the pseudo-header and the echo request
+    /// message, computed independently over the bytes.
+    fn reference_checksum(payload: &[u8], cksum: u16) -> u16 {
+        let len = (8 + payload.len()) as u32;
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&SRC_ADDR.0);
+        bytes.extend_from_slice(&DST_ADDR.0);
+        bytes.extend_from_slice(&len.to_be_bytes());
+        bytes.extend_from_slice(&[0, 0, 0, ip6_nh::ICMP]);
+        bytes.extend_from_slice(&[128, 0]);
+        bytes.extend_from_slice(&cksum.to_be_bytes());
+        bytes.extend_from_slice(&[0xff, 0xfe, 0xff, 0x01]);
+        bytes.extend_from_slice(payload);
+        if bytes.len() % 2 != 0 {
+            bytes.push(0);
+        }
+        let mut sum: u64 = bytes
+            .chunks(2)
+            .map(|word| u16::from_be_bytes([word[0], word[1]]) as u64)
+            .sum();
+        while sum > 0xffff {
+            sum = (sum >> 16) + (sum & 0xffff);
+        }
+        !(sum as u16)
+    }
+
+    fn icmp_checksum(payload: &[u8], cksum: u16) -> u16 {
+        let (ip6_header, icmp_header) = echo_request(payload, cksum);
+        compute_icmp_checksum(&ip6_header, &icmp_header, payload)
+    }
+
+    #[test]
+    fn icmp_checksum_pads_odd_length_payload() {
+        let payload = b"ping!";
+        let cksum = icmp_checksum(payload, 0);
+        assert_eq!(cksum, reference_checksum(payload, 0));
+        // The trailing byte is padded with zero, not with what follows it.
+        let (ip6_header, icmp_header) = echo_request(payload, 0);
+        assert_eq!(
+            compute_icmp_checksum(&ip6_header, &icmp_header, b"ping!\xff"),
+            cksum
+        );
+        assert_eq!(icmp_checksum(payload, cksum), 0);
+    }
+
+    #[test]
+    fn icmp_checksum_folds_end_around_carry() {
+        // The words of the message add up to many times 0xffff, so the carry
+        // has to be folded back more than once.
+        let payload = [0xff; 64];
+        let cksum = icmp_checksum(&payload, 0);
+        assert_eq!(cksum, reference_checksum(&payload, 0));
+        // A message with the checksum in place sums to 0xffff, which is only
+        // reached by folding the carry back in.
+        assert_eq!(icmp_checksum(&payload, cksum), 0);
+        assert_eq!(reference_checksum(&payload, cksum), 0);
+    }
+}
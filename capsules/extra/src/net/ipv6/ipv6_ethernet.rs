@@ -0,0 +1,514 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! IPv6 over Ethernet (RFC 2464).
+//!
+//! `IP6EthernetStruct` implements the `IP6Sender` trait on top of an
+//! Ethernet adapter, so that the UDP, TCP and ICMPv6 layers can be used over
+//! Ethernet just as they are over 6LoWPAN. Received frames carrying IPv6 are
+//! passed to an `IP6RecvStruct` through its `SixlowpanRxClient` interface,
+//! which is independent of the link layer despite its name.
+//!
+//! Link-layer addresses are resolved with a minimal subset of Neighbor
+//! Discovery (RFC 4861): the adapter answers Neighbor Solicitations for its
+//! own address, and sends Neighbor Solicitations for unknown on-link
+//! destinations. Neighbors are also learned from the source addresses of
+//! received packets. Off-link destinations are sent to the gateway, if one
+//! has been configured; otherwise every destination is treated as on-link.
+//! Router discovery, duplicate address detection and neighbor
+//! unreachability detection are not implemented.
+//!
+//! Only packets addressed to the configured source address or to a
+//! multicast group are passed up the stack.
+
+use crate::net::ethernet::{
+    ethertype, EthernetAddress, EthernetHeader, ETHERNET_HDR_LEN, ETHERNET_MAX_FRAME_LEN,
+};
+use crate::net::ieee802154::MacAddress;
+use crate::net::ipv6::ip_utils::{compute_ipv6_ph_sum, compute_sum, ip6_nh, IPAddr};
+use crate::net::ipv6::ipv6_send::{IP6SendClient, IP6Sender};
+use crate::net::ipv6::{IP6Header, IP6Packet, TransportHeader};
+use crate::net::network_capabilities::{IpVisibilityCapability, NetworkCapability};
+use crate::net::sixlowpan::sixlowpan_state::SixlowpanRxClient;
+
+use core::cell::Cell;
+
+use kernel::hil::ethernet::{EthernetAdapterDatapath, EthernetAdapterDatapathClient};
+use kernel::hil::time::{self, ConvertTicks};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::ErrorCode;
+
+const IP6_HDR_LEN: usize = 40;
+
+/// Length of a Neighbor Solicitation or Advertisement carrying a single
+/// link-layer address option.
+const ND_MSG_LEN: usize = 32;
+
+/// Length of the frames this layer uses for Neighbor Discovery messages.
+pub const ND_FRAME_LEN: usize = ETHERNET_HDR_LEN + IP6_HDR_LEN + ND_MSG_LEN;
+
+/// Length of the buffer needed to transmit IPv6 packets of the maximum size
+/// an Ethernet frame can carry.
+pub const TX_BUF_LEN: usize = ETHERNET_MAX_FRAME_LEN;
+
+const ICMP_NEIGHBOR_SOLICITATION: u8 = 135;
+const ICMP_NEIGHBOR_ADVERTISEMENT: u8 = 136;
+
+const OPT_SOURCE_LL_ADDR: u8 = 1;
+const OPT_TARGET_LL_ADDR: u8 = 2;
+
+const NA_FLAG_SOLICITED: u8 = 0x40;
+const NA_FLAG_OVERRIDE: u8 = 0x20;
+
+const ALL_NODES: IPAddr = IPAddr([0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01]);
+
+// Neighbor Discovery protocol constants (RFC 4861, Section 10)
+const RETRANS_TIMER_MS: u32 = 1000;
+const MAX_MULTICAST_SOLICIT: u8 = 3;
+
+// Transmission identifiers passed to the Ethernet adapter
+const TX_DATA: usize = 0;
+const TX_ND: usize = 1;
+
+/// An entry in the neighbor cache, mapping an on-link IPv6 address to the
+/// Ethernet address of the neighbor.
+#[derive(Copy, Clone, Debug)]
+pub struct EthernetNeighbor {
+    pub ip_addr: IPAddr,
+    pub mac_addr: EthernetAddress,
+}
+
+/// Implementation of the `IP6Sender` trait which sends IPv6 packets in
+/// Ethernet frames, and receiver of IPv6 packets from an Ethernet adapter.
+pub struct IP6EthernetStruct<'a, A: time::Alarm<'a>> {
+    ip6_packet: TakeCell<'static, IP6Packet<'static>>,
+    tx_buf: TakeCell<'static, [u8]>,
+    nd_buf: TakeCell<'static, [u8]>,
+    ethernet: &'a dyn EthernetAdapterDatapath<'a>,
+    alarm: &'a A,
+    src_mac_addr: EthernetAddress,
+    src_addr: Cell<IPAddr>,
+    gateway: OptionalCell<EthernetAddress>,
+    neighbors: &'a [OptionalCell<EthernetNeighbor>],
+    next_evict: Cell<usize>,
+    // Next hop whose link-layer address is being resolved for the packet in
+    // `ip6_packet`, and the remaining solicitations to send for it.
+    resolving: OptionalCell<IPAddr>,
+    solicitations: Cell<u8>,
+    // Whether a packet is waiting for resolution or being transmitted.
+    sending: Cell<bool>,
+    client: OptionalCell<&'a dyn IP6SendClient>,
+    rx_client: OptionalCell<&'a dyn SixlowpanRxClient>,
+    ip_vis: &'static IpVisibilityCapability,
+}
+
+impl<'a, A: time::Alarm<'a>> IP6Sender<'a> for IP6EthernetStruct<'a, A> {
+    fn set_client(&self, client: &'a dyn IP6SendClient) {
+        self.client.set(client);
+    }
+
+    fn set_addr(&self, src_addr: IPAddr) {
+        self.src_addr.set(src_addr);
+    }
+
+    /// Sets the gateway for off-link destinations. As the interface is
+    /// shared with 6LoWPAN, the gateway is given as an extended address
+    /// holding the EUI-64 derived from its Ethernet address (with `ff:fe`
+    /// in the middle). Any other address clears the gateway.
+    fn set_gateway(&self, gateway: MacAddress) {
+        self.gateway
+            .insert(EthernetAddress::from_mac_address(gateway));
+    }
+
+    fn set_header(&mut self, ip6_header: IP6Header) {
+        self.ip6_packet
+            .map(|ip6_packet| ip6_packet.header = ip6_header);
+    }
+
+    fn send_to(
+        &self,
+        dst: IPAddr,
+        transport_header: TransportHeader,
+        payload: &SubSliceMut<'static, u8>,
+        net_cap: &'static NetworkCapability,
+    ) -> Result<(), ErrorCode> {
+        if !net_cap.remote_addr_valid(dst, self.ip_vis) {
+            return Err(ErrorCode::FAIL);
+        }
+        if self.sending.get() {
+            return Err(ErrorCode::BUSY);
+        }
+
+        self.ip6_packet
+            .map(|ip6_packet| {
+                ip6_packet.header = IP6Header::default();
+                ip6_packet.header.src_addr = self.src_addr.get();
+                ip6_packet.header.dst_addr = dst;
+                ip6_packet.set_payload(transport_header, payload);
+                ip6_packet.set_transport_checksum();
+            })
+            .ok_or(ErrorCode::NOMEM)?;
+
+        match self.next_hop(dst) {
+            Ok(dst_mac_addr) => self.transmit_packet(dst_mac_addr),
+            Err(next_hop) => {
+                // Hold on to the packet until the next hop is resolved.
+                self.sending.set(true);
+                self.resolving.set(next_hop);
+                self.solicitations.set(MAX_MULTICAST_SOLICIT);
+                self.solicit();
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> IP6EthernetStruct<'a, A> {
+    pub fn new(
+        ip6_packet: &'static mut IP6Packet<'static>,
+        tx_buf: &'static mut [u8],
+        nd_buf: &'static mut [u8; ND_FRAME_LEN],
+        ethernet: &'a dyn EthernetAdapterDatapath<'a>,
+        alarm: &'a A,
+        src_mac_addr: EthernetAddress,
+        neighbors: &'a [OptionalCell<EthernetNeighbor>],
+        ip_vis: &'static IpVisibilityCapability,
+    ) -> IP6EthernetStruct<'a, A> {
+        IP6EthernetStruct {
+            ip6_packet: TakeCell::new(ip6_packet),
+            tx_buf: TakeCell::new(tx_buf),
+            nd_buf: TakeCell::new(nd_buf),
+            ethernet,
+            alarm,
+            src_mac_addr,
+            src_addr: Cell::new(IPAddr::new()),
+            gateway: OptionalCell::empty(),
+            neighbors,
+            next_evict: Cell::new(0),
+            resolving: OptionalCell::empty(),
+            solicitations: Cell::new(0),
+            sending: Cell::new(false),
+            client: OptionalCell::empty(),
+            rx_client: OptionalCell::empty(),
+            ip_vis,
+        }
+    }
+
+    /// Sets the client that received IPv6 packets are passed to, usually an
+    /// `IP6RecvStruct`.
+    pub fn set_receive_client(&self, client: &'a dyn SixlowpanRxClient) {
+        self.rx_client.set(client);
+    }
+
+    /// Returns the Ethernet address of a neighbor, if it is in the
+    /// neighbor cache.
+    pub fn lookup(&self, addr: IPAddr) -> Option<EthernetAddress> {
+        self.neighbors.iter().find_map(|entry| {
+            entry
+                .get()
+                .and_then(|n| (n.ip_addr == addr).then_some(n.mac_addr))
+        })
+    }
+
+    /// Adds or refreshes a neighbor cache entry. When the cache is full,
+    /// entries are evicted in round-robin order.
+    fn update_neighbor(&self, ip_addr: IPAddr, mac_addr: EthernetAddress) {
+        let neighbor = EthernetNeighbor { ip_addr, mac_addr };
+        let existing = self
+            .neighbors
+            .iter()
+            .find(|entry| entry.get().is_some_and(|n| n.ip_addr == ip_addr));
+        if let Some(entry) = existing {
+            entry.set(neighbor);
+        } else if let Some(entry) = self.neighbors.iter().find(|entry| entry.is_none()) {
+            entry.set(neighbor);
+        } else if !self.neighbors.is_empty() {
+            let idx = self.next_evict.get() % self.neighbors.len();
+            self.neighbors[idx].set(neighbor);
+            self.next_evict.set(idx + 1);
+        }
+    }
+
+    /// Determines the Ethernet address to send a packet for `dst` to, or
+    /// the next hop whose address has to be resolved first.
+    fn next_hop(&self, dst: IPAddr) -> Result<EthernetAddress, IPAddr> {
+        if dst.is_multicast() {
+            return Ok(EthernetAddress::from_ipv6_multicast(&dst));
+        }
+        if let Some(mac_addr) = self.lookup(dst) {
+            return Ok(mac_addr);
+        }
+        if !dst.is_unicast_link_local() {
+            if let Some(gateway) = self.gateway.get() {
+                return Ok(gateway);
+            }
+        }
+        Err(dst)
+    }
+
+    /// Frames and transmits the packet in `ip6_packet`.
+    fn transmit_packet(&self, dst_mac_addr: EthernetAddress) -> Result<(), ErrorCode> {
+        let tx_buf = self.tx_buf.take().ok_or(ErrorCode::BUSY)?;
+        let header = EthernetHeader::new(dst_mac_addr, self.src_mac_addr, ethertype::IPV6);
+
+        let frame_len = header.encode(tx_buf).done().and_then(|(off, _)| {
+            self.ip6_packet
+                .map(|ip6_packet| ip6_packet.encode(&mut tx_buf[off..]).done())
+                .flatten()
+                .map(|(len, _)| off + len)
+        });
+        let frame_len = match frame_len.and_then(|len| u16::try_from(len).ok()) {
+            Some(len) => len,
+            None => {
+                self.tx_buf.replace(tx_buf);
+                return Err(ErrorCode::SIZE);
+            }
+        };
+
+        match self.ethernet.transmit_frame(tx_buf, frame_len, TX_DATA) {
+            Ok(()) => {
+                self.sending.set(true);
+                Ok(())
+            }
+            Err((ecode, buf)) => {
+                self.tx_buf.replace(buf);
+                Err(ecode)
+            }
+        }
+    }
+
+    /// Sends a Neighbor Solicitation for the next hop being resolved and
+    /// schedules the retransmission timer. If the ND buffer is in use, the
+    /// solicitation is skipped and retried when the timer fires.
+    fn solicit(&self) {
+        let target = match self.resolving.get() {
+            Some(target) => target,
+            None => return,
+        };
+        self.solicitations
+            .set(self.solicitations.get().saturating_sub(1));
+
+        // Solicited-node multicast address ff02::1:ffXX:XXXX
+        let mut dst = ALL_NODES;
+        dst.0[11] = 0x01;
+        dst.0[12] = 0xff;
+        dst.0[13..16].copy_from_slice(&target.0[13..16]);
+
+        self.send_nd(
+            EthernetAddress::from_ipv6_multicast(&dst),
+            dst,
+            ICMP_NEIGHBOR_SOLICITATION,
+            0,
+            target,
+            OPT_SOURCE_LL_ADDR,
+        );
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(RETRANS_TIMER_MS));
+    }
+
+    /// Builds and transmits a Neighbor Solicitation or Advertisement
+    /// carrying our own link-layer address.
+    fn send_nd(
+        &self,
+        dst_mac_addr: EthernetAddress,
+        dst: IPAddr,
+        icmp_type: u8,
+        flags: u8,
+        target: IPAddr,
+        opt_type: u8,
+    ) {
+        let buf = match self.nd_buf.take() {
+            Some(buf) => buf,
+            None => return,
+        };
+
+        let _ = EthernetHeader::new(dst_mac_addr, self.src_mac_addr, ethertype::IPV6).encode(buf);
+
+        let mut ip6_header = IP6Header::default();
+        ip6_header.src_addr = self.src_addr.get();
+        ip6_header.dst_addr = dst;
+        ip6_header.set_next_header(ip6_nh::ICMP);
+        ip6_header.set_payload_len(ND_MSG_LEN as u16);
+        let _ = ip6_header.encode(&mut buf[ETHERNET_HDR_LEN..]);
+
+        let msg = &mut buf[ETHERNET_HDR_LEN + IP6_HDR_LEN..ND_FRAME_LEN];
+        msg.fill(0);
+        msg[0] = icmp_type;
+        msg[4] = flags;
+        msg[8..24].copy_from_slice(&target.0);
+        msg[24] = opt_type;
+        msg[25] = 1; // Option length in units of 8 bytes
+        msg[26..32].copy_from_slice(&self.src_mac_addr.0);
+
+        let mut sum = compute_ipv6_ph_sum(&ip6_header) + compute_sum(msg, ND_MSG_LEN as u16);
+        while sum > 0xffff {
+            sum = (sum >> 16) + (sum & 0xffff);
+        }
+        msg[2..4].copy_from_slice(&(!sum as u16).to_be_bytes());
+
+        if let Err((_, buf)) = self
+            .ethernet
+            .transmit_frame(buf, ND_FRAME_LEN as u16, TX_ND)
+        {
+            self.nd_buf.replace(buf);
+        }
+    }
+
+    /// Handles a received Neighbor Solicitation or Advertisement.
+    /// `msg` is the ICMPv6 message following the IPv6 header.
+    fn receive_nd(&self, ip6_header: &IP6Header, eth_src: EthernetAddress, msg: &[u8]) {
+        // Neighbor Discovery messages must not have been forwarded.
+        if ip6_header.get_hop_limit() != 255 || msg.len() < 24 || msg[1] != 0 {
+            return;
+        }
+        let mut target = IPAddr::new();
+        target.0.copy_from_slice(&msg[8..24]);
+
+        // Find the link-layer address option, if any.
+        let mut ll_addr = None;
+        let mut opts = &msg[24..];
+        while opts.len() >= 8 {
+            let opt_len = usize::from(opts[1]) * 8;
+            if opt_len == 0 || opt_len > opts.len() {
+                return;
+            }
+            if opt_len == 8 && (opts[0] == OPT_SOURCE_LL_ADDR || opts[0] == OPT_TARGET_LL_ADDR) {
+                let mut mac = [0; 6];
+                mac.copy_from_slice(&opts[2..8]);
+                ll_addr = Some(EthernetAddress(mac));
+            }
+            opts = &opts[opt_len..];
+        }
+
+        match msg[0] {
+            ICMP_NEIGHBOR_SOLICITATION => {
+                if target.is_multicast() || target != self.src_addr.get() {
+                    return;
+                }
+                let src = ip6_header.get_src_addr();
+                let (dst, dst_mac_addr, flags) = if src.is_unspecified() {
+                    // Duplicate address detection probe; reply to all nodes.
+                    (
+                        ALL_NODES,
+                        EthernetAddress::from_ipv6_multicast(&ALL_NODES),
+                        NA_FLAG_OVERRIDE,
+                    )
+                } else {
+                    let mac_addr = ll_addr.unwrap_or(eth_src);
+                    self.update_neighbor(src, mac_addr);
+                    (src, mac_addr, NA_FLAG_SOLICITED | NA_FLAG_OVERRIDE)
+                };
+                self.send_nd(
+                    dst_mac_addr,
+                    dst,
+                    ICMP_NEIGHBOR_ADVERTISEMENT,
+                    flags,
+                    target,
+                    OPT_TARGET_LL_ADDR,
+                );
+            }
+            ICMP_NEIGHBOR_ADVERTISEMENT => {
+                if target.is_multicast() {
+                    return;
+                }
+                let mac_addr = ll_addr.unwrap_or(eth_src);
+                self.update_neighbor(target, mac_addr);
+                if self.resolving.contains(&target) {
+                    self.resolving.clear();
+                    let _ = self.alarm.disarm();
+                    self.sending.set(false);
+                    let result = self.transmit_packet(mac_addr);
+                    if result.is_err() {
+                        self.send_completed(result);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn send_completed(&self, result: Result<(), ErrorCode>) {
+        self.sending.set(false);
+        self.client.map(|client| client.send_done(result));
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> time::AlarmClient for IP6EthernetStruct<'a, A> {
+    fn alarm(&self) {
+        if self.resolving.is_none() {
+            return;
+        }
+        if self.solicitations.get() > 0 {
+            self.solicit();
+        } else {
+            // Address resolution failed, drop the packet.
+            self.resolving.clear();
+            self.send_completed(Err(ErrorCode::FAIL));
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> EthernetAdapterDatapathClient for IP6EthernetStruct<'a, A> {
+    fn transmit_frame_done(
+        &self,
+        err: Result<(), ErrorCode>,
+        frame_buffer: &'static mut [u8],
+        _len: u16,
+        transmission_identifier: usize,
+        _timestamp: Option<u64>,
+    ) {
+        if transmission_identifier == TX_ND {
+            self.nd_buf.replace(frame_buffer);
+        } else {
+            self.tx_buf.replace(frame_buffer);
+            self.send_completed(err);
+        }
+    }
+
+    fn received_frame(&self, frame: &[u8], _timestamp: Option<u64>) {
+        let (off, header) = match EthernetHeader::decode(frame).done() {
+            Some(res) => res,
+            None => return,
+        };
+        if header.ethertype != ethertype::IPV6
+            || (header.dst != self.src_mac_addr && !header.dst.is_multicast())
+        {
+            return;
+        }
+
+        let packet = &frame[off..];
+        let ip6_header = match IP6Header::decode(packet).done() {
+            Some((_, ip6_header)) => ip6_header,
+            None => return,
+        };
+        // Ethernet frames may be padded, so trim the packet to the length
+        // given in the IPv6 header.
+        let packet_len = IP6_HDR_LEN + usize::from(ip6_header.get_payload_len());
+        if packet_len > packet.len() {
+            return;
+        }
+        let packet = &packet[..packet_len];
+
+        let dst = ip6_header.get_dst_addr();
+        if !dst.is_multicast() && dst != self.src_addr.get() {
+            return;
+        }
+
+        // Glean the sender's link-layer address. For packets from off-link
+        // sources this is the router they were forwarded by, which is also
+        // the right next hop for replies.
+        let src = ip6_header.get_src_addr();
+        if !src.is_unspecified() && !src.is_multicast() && !header.src.is_multicast() {
+            self.update_neighbor(src, header.src);
+        }
+
+        if ip6_header.get_next_header() == ip6_nh::ICMP {
+            self.receive_nd(&ip6_header, header.src, &packet[IP6_HDR_LEN..]);
+        }
+
+        self.rx_client
+            .map(|client| client.receive(packet, packet_len, Ok(())));
+    }
+}
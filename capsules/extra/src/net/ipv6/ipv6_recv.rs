@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 // Copyright Tock Contributors 2022.
 
+use crate::net::ipv6::ip_utils::ip6_nh;
 use crate::net::ipv6::IP6Header;
 use crate::net::sixlowpan::sixlowpan_state::SixlowpanRxClient;
 
@@ -27,7 +28,8 @@ use kernel::ErrorCode;
 - `sixlowpan_state` has a single rx_client, which in our case is a single struct that
   implements the `ip_receive ` trait.
 - the `ip_receive` implementing struct (`IP6RecvStruct`) has a single client, which is
-  udp_recv, a `UDPReceive` struct.
+  udp_recv, a `UDPReceive` struct, and separate clients for ICMPv6 packets
+  (icmp_recv, an `ICMP6RecvStruct`) and for TCP packets (tcp_mux, a `MuxTcp`).
 - The UDPReceive struct is a field of the UDPDriver, which ultimately passes the
   packets up to userland.
 */
@@ -45,22 +47,42 @@ pub trait IP6RecvClient {
 /// that are not among the local addresses of this device.
 pub trait IP6Receiver<'a> {
     fn set_client(&self, client: &'a dyn IP6RecvClient);
+
+    /// Sets the client that receives ICMPv6 packets. If no ICMPv6 client is
+    /// set, ICMPv6 packets are passed to the regular client instead.
+    fn set_icmp_client(&self, client: &'a dyn IP6RecvClient);
+
+    /// Sets the client that receives TCP packets. If no TCP client is set,
+    /// TCP packets are passed to the regular client instead.
+    fn set_tcp_client(&self, client: &'a dyn IP6RecvClient);
 }
 
 pub struct IP6RecvStruct<'a> {
     client: OptionalCell<&'a dyn IP6RecvClient>,
+    icmp_client: OptionalCell<&'a dyn IP6RecvClient>,
+    tcp_client: OptionalCell<&'a dyn IP6RecvClient>,
 }
 
 impl<'a> IP6Receiver<'a> for IP6RecvStruct<'a> {
     fn set_client(&self, client: &'a dyn IP6RecvClient) {
         self.client.set(client);
     }
+
+    fn set_icmp_client(&self, client: &'a dyn IP6RecvClient) {
+        self.icmp_client.set(client);
+    }
+
+    fn set_tcp_client(&self, client: &'a dyn IP6RecvClient) {
+        self.tcp_client.set(client);
+    }
 }
 
 impl<'a> IP6RecvStruct<'a> {
     pub fn new() -> IP6RecvStruct<'a> {
         IP6RecvStruct {
             client: OptionalCell::empty(),
+            icmp_client: OptionalCell::empty(),
+            tcp_client: OptionalCell::empty(),
         }
     }
 }
@@ -78,11 +100,15 @@ impl SixlowpanRxClient for IP6RecvStruct<'_> {
                     debug!("cksum fail!: {:?}", checksum_result);
                     return; //Dropped.
                 }
-                // Note: Protocols for which checksum verification is not implemented (TCP, etc.)
+                // Note: Protocols for which checksum verification is not implemented (raw IP, etc.)
                 // are automatically assumed as fine, rather than dropped
 
-                self.client
-                    .map(|client| client.receive(ip6_header, &buf[offset..len]));
+                let client = match ip6_header.get_next_header() {
+                    ip6_nh::ICMP => self.icmp_client.get().or(self.client.get()),
+                    ip6_nh::TCP => self.tcp_client.get().or(self.client.get()),
+                    _ => self.client.get(),
+                };
+                client.map(|client| client.receive(ip6_header, &buf[offset..len]));
             }
             None => {
                 debug!("failed to decode ipv6 header");
@@ -27,7 +27,7 @@ use crate::net::ipv6::ip_utils::IPAddr;
 use crate::net::ipv6::{IP6Header, IP6Packet, TransportHeader};
 use crate::net::network_capabilities::{IpVisibilityCapability, NetworkCapability};
 use crate::net::sixlowpan::sixlowpan_state::TxState;
-use crate::net::thread::thread_utils::{mac_from_ipv6, MULTICAST_IPV6};
+use crate::net::thread::thread_utils::mac_from_ipv6;
 
 use core::cell::Cell;
 
@@ -110,7 +110,6 @@ pub struct IP6SendStruct<'a, A: time::Alarm<'a>> {
     tx_buf: TakeCell<'static, [u8]>,
     sixlowpan: TxState<'a>,
     radio: &'a dyn MacDevice<'a>,
-    dst_mac_addr: MacAddress,
     src_mac_addr: MacAddress,
     client: OptionalCell<&'a dyn IP6SendClient>,
     ip_vis: &'static IpVisibilityCapability,
@@ -151,7 +150,7 @@ impl<'a, A: time::Alarm<'a>> IP6Sender<'a> for IP6SendStruct<'a, A> {
         // but may conflict with some other or future protocol
         // that sits above and uses IPV6
         let dst_mac_addr;
-        if dst == MULTICAST_IPV6 {
+        if dst.is_multicast() {
             // use short multicast ipv6 for dst mac address
             dst_mac_addr = MacAddress::Short(0xFFFF)
         } else if dst.0[0..8] == [0xfe, 0x80, 0, 0, 0, 0, 0, 0] {
@@ -159,7 +158,10 @@ impl<'a, A: time::Alarm<'a>> IP6Sender<'a> for IP6SendStruct<'a, A> {
             // helper function to determine ipv6 to send to
             dst_mac_addr = MacAddress::Long(mac_from_ipv6(dst))
         } else {
-            dst_mac_addr = self.dst_mac_addr;
+            // Off-link destinations are reached through the gateway, which
+            // defaults to the `dst_mac_addr` passed to `new` until a router
+            // is discovered (e.g. by 6LoWPAN-ND).
+            dst_mac_addr = self.gateway.get();
         }
 
         // TODO: add error handling here
@@ -192,7 +194,6 @@ impl<'a, A: time::Alarm<'a>> IP6SendStruct<'a, A> {
             tx_buf: TakeCell::new(tx_buf),
             sixlowpan,
             radio,
-            dst_mac_addr,
             src_mac_addr,
             client: OptionalCell::empty(),
             ip_vis,
@@ -135,6 +135,17 @@ impl NetworkCapability {
         }
     }
 
+    /// Creates a capability that allows any address and port, for unit tests
+    /// that cannot create a `NetworkCapabilityCreationCapability`.
+    #[cfg(test)]
+    pub(crate) fn new_unrestricted() -> NetworkCapability {
+        NetworkCapability {
+            remote_addrs: AddrRange::Any,
+            remote_ports: PortRange::Any,
+            local_ports: PortRange::Any,
+        }
+    }
+
     pub fn get_range(&self, _ip_cap: &'static IpVisibilityCapability) -> AddrRange {
         self.remote_addrs
     }
@@ -0,0 +1,1080 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! 6LoWPAN Neighbor Discovery (RFC 6775) for hosts.
+//!
+//! `SixlowpanND` lets a 6LoWPAN host join a network managed by a border
+//! router. It solicits Router Advertisements, forms a global address from an
+//! advertised prefix and its EUI-64, and registers that address with the
+//! router by sending a Neighbor Solicitation that carries an Address
+//! Registration Option (ARO). The registration is refreshed before its
+//! lifetime runs out.
+//!
+//! As in RFC 6775, hosts do not multicast address resolution requests: every
+//! off-link destination is reached through the default router, whose
+//! link-layer address is learned from the Source Link-Layer Address Option
+//! (SLLAO) of its advertisements. Routers are kept in a small neighbor cache
+//! together with their lifetimes. When the default router expires, stops
+//! answering registrations or withdraws itself, the host goes back to
+//! soliciting routers.
+//!
+//! Messages are sent through an `ICMP6Sender` that has its own `IP6Sender`,
+//! and received as a client of the `ICMP6RecvStruct`. All timers have a
+//! granularity of one second and share a single alarm, which is only armed
+//! for the next deadline (but at least every `MAX_SLEEP_S` seconds, so the
+//! underlying counter cannot wrap between two firings).
+//!
+//! 6LoWPAN Context Options and Authoritative Border Router Options are
+//! ignored, so header compression keeps using the context configured in
+//! `sixlowpan_state`.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let nd = SixlowpanND::new(icmp_send, ip_send, alarm, eui64, neighbors, tx_buffer, 30, net_cap);
+//! icmp_send.set_client(nd);
+//! icmp_rcvr.set_client(nd);
+//! alarm.set_alarm_client(nd);
+//!
+//! nd.set_client(client);
+//! nd.start();
+//! ```
+
+use crate::net::icmpv6::icmpv6_recv::ICMP6RecvClient;
+use crate::net::icmpv6::icmpv6_send::{ICMP6SendClient, ICMP6Sender};
+use crate::net::icmpv6::{ICMP6Header, ICMP6HeaderOptions, ICMP6Type};
+use crate::net::ieee802154::MacAddress;
+use crate::net::ipv6::ip_utils::IPAddr;
+use crate::net::ipv6::ipv6_send::IP6Sender;
+use crate::net::ipv6::IP6Header;
+use crate::net::network_capabilities::NetworkCapability;
+use crate::net::thread::thread_utils::mac_from_ipv6;
+
+use core::cell::Cell;
+use core::cmp;
+
+use kernel::debug;
+use kernel::hil::time::{self, ConvertTicks, Ticks};
+use kernel::utilities::cells::{MapCell, OptionalCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::ErrorCode;
+
+/// Neighbor Discovery option types (RFC 4861, RFC 6775).
+pub mod nd_opt {
+    pub const SLLAO: u8 = 1;
+    pub const PIO: u8 = 3;
+    pub const ARO: u8 = 33;
+}
+
+/// Status values of the Address Registration Option.
+pub mod aro_status {
+    pub const SUCCESS: u8 = 0;
+    pub const DUPLICATE: u8 = 1;
+    pub const NEIGHBOR_CACHE_FULL: u8 = 2;
+}
+
+/// Length of an SLLAO or ARO carrying an EUI-64.
+const EUI64_OPT_LEN: usize = 16;
+
+/// Size of the buffer needed to build ND messages. The largest one is the
+/// registration NS: a target address followed by an SLLAO and an ARO.
+pub const ND_BUF_LEN: usize = 16 + 2 * EUI64_OPT_LEN;
+
+/// Hop limit of all ND messages, which receivers check to make sure a
+/// message was sent on the link.
+const ND_HOP_LIMIT: u8 = 255;
+
+/// All-routers link-local multicast address (ff02::2).
+const ALL_ROUTERS: IPAddr = IPAddr([0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x02]);
+
+// Host protocol constants from RFC 6775 (section 9) and RFC 4861 (section
+// 10), in seconds.
+const RTR_SOLICITATION_INTERVAL: u32 = 10;
+const MAX_RTR_SOLICITATIONS: u8 = 3;
+const MAX_RTR_SOLICITATION_INTERVAL: u32 = 60;
+const RETRANS_TIMER: u32 = 1;
+const MAX_UNICAST_SOLICIT: u8 = 3;
+
+/// Longest time, in seconds, the alarm is armed for.
+const MAX_SLEEP_S: u32 = 60;
+
+/// Prefix Information Option flag: the prefix can be used for address
+/// autoconfiguration.
+const PIO_FLAG_AUTONOMOUS: u8 = 0x40;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NDState {
+    /// Neighbor discovery has not been started.
+    Idle,
+    /// Sending Router Solicitations until a router advertises a prefix.
+    Soliciting,
+    /// Waiting for the default router to confirm the address registration.
+    Registering,
+    /// The global address is registered with the default router.
+    Registered,
+}
+
+/// An entry of the neighbor cache.
+#[derive(Copy, Clone, Debug)]
+pub struct Neighbor {
+    pub ip_addr: IPAddr,
+    pub mac_addr: MacAddress,
+    pub is_router: bool,
+    /// Time on the ND clock, in seconds, at which the entry expires.
+    expires: u32,
+}
+
+/// Client interface of `SixlowpanND`.
+pub trait SixlowpanNDClient {
+    /// Called when `addr` has been registered with the default router, whose
+    /// link-layer address is `router_mac`. From now on, `addr` can be used as
+    /// the source address of packets sent through that router.
+    fn address_registered(&self, addr: IPAddr, router_mac: MacAddress);
+
+    /// Called when `addr` can no longer be used, or could not be registered.
+    /// `reason` is `ALREADY` if the router reported the address as a
+    /// duplicate (neighbor discovery then stops), `NOMEM` if the neighbor
+    /// cache of the router is full, `FAIL` if the router expired or stopped
+    /// answering and `CANCEL` if neighbor discovery was stopped. Unless it
+    /// stopped, neighbor discovery looks for another router.
+    fn address_lost(&self, addr: IPAddr, reason: ErrorCode);
+}
+
+pub struct SixlowpanND<'a, A: time::Alarm<'a>> {
+    icmp_sender: &'a dyn ICMP6Sender<'a>,
+    /// The IPv6 sender underlying `icmp_sender`, used to pick the source
+    /// address of each message.
+    ip_sender: &'a dyn IP6Sender<'a>,
+    alarm: &'a A,
+    client: OptionalCell<&'a dyn SixlowpanNDClient>,
+    eui64: [u8; 8],
+    link_local_addr: IPAddr,
+    global_addr: OptionalCell<IPAddr>,
+    /// Link-local address of the default router.
+    router: OptionalCell<IPAddr>,
+    neighbors: &'a [OptionalCell<Neighbor>],
+    state: Cell<NDState>,
+    registered: Cell<bool>,
+    /// Number of solicitations sent since entering the current state.
+    tries: Cell<u8>,
+    /// Time of the next solicitation or registration refresh.
+    deadline: Cell<Option<u32>>,
+    /// Registration lifetime requested from routers, in minutes.
+    registration_lifetime: u16,
+    tx_buffer: MapCell<SubSliceMut<'static, u8>>,
+    busy: Cell<bool>,
+    net_cap: &'static NetworkCapability,
+    // The ND clock counts seconds since `start`. `clock_ref` holds the alarm
+    // time corresponding to `clock_s` seconds.
+    clock_s: Cell<u32>,
+    clock_ref: Cell<A::Ticks>,
+}
+
+impl<'a, A: time::Alarm<'a>> SixlowpanND<'a, A> {
+    /// `eui64` is the extended MAC address of this node, from which its
+    /// link-local and global addresses are derived. `tx_buffer` must be at
+    /// least `ND_BUF_LEN` bytes long.
+    pub fn new(
+        icmp_sender: &'a dyn ICMP6Sender<'a>,
+        ip_sender: &'a dyn IP6Sender<'a>,
+        alarm: &'a A,
+        eui64: [u8; 8],
+        neighbors: &'a [OptionalCell<Neighbor>],
+        tx_buffer: SubSliceMut<'static, u8>,
+        registration_lifetime: u16,
+        net_cap: &'static NetworkCapability,
+    ) -> SixlowpanND<'a, A> {
+        SixlowpanND {
+            icmp_sender,
+            ip_sender,
+            alarm,
+            client: OptionalCell::empty(),
+            eui64,
+            link_local_addr: IPAddr::generate_from_mac(MacAddress::Long(eui64)),
+            global_addr: OptionalCell::empty(),
+            router: OptionalCell::empty(),
+            neighbors,
+            state: Cell::new(NDState::Idle),
+            registered: Cell::new(false),
+            tries: Cell::new(0),
+            deadline: Cell::new(None),
+            registration_lifetime,
+            tx_buffer: MapCell::new(tx_buffer),
+            busy: Cell::new(false),
+            net_cap,
+            clock_s: Cell::new(0),
+            clock_ref: Cell::new(A::Ticks::from(0)),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn SixlowpanNDClient) {
+        self.client.set(client);
+    }
+
+    /// Starts soliciting routers. Returns `ALREADY` if neighbor discovery
+    /// is already running.
+    pub fn start(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != NDState::Idle {
+            return Err(ErrorCode::ALREADY);
+        }
+        self.clock_ref.set(self.alarm.now());
+        self.solicit_routers();
+        self.update_timer();
+        Ok(())
+    }
+
+    /// Stops neighbor discovery and flushes the neighbor cache. The client
+    /// is told that the registered address, if any, is lost.
+    pub fn stop(&self) {
+        self.lose_address(ErrorCode::CANCEL);
+        self.state.set(NDState::Idle);
+        self.deadline.set(None);
+        self.router.clear();
+        self.neighbors.iter().for_each(|entry| entry.clear());
+        self.update_timer();
+    }
+
+    pub fn get_state(&self) -> NDState {
+        self.state.get()
+    }
+
+    /// Returns the global address once it has been registered.
+    pub fn get_global_addr(&self) -> Option<IPAddr> {
+        if self.registered.get() {
+            self.global_addr.get()
+        } else {
+            None
+        }
+    }
+
+    pub fn get_link_local_addr(&self) -> IPAddr {
+        self.link_local_addr
+    }
+
+    /// Returns the neighbor cache entry of the default router.
+    pub fn get_default_router(&self) -> Option<Neighbor> {
+        self.router
+            .get()
+            .and_then(|router| self.find_neighbor(router))
+    }
+
+    /// Returns the link-layer address packets to `dst` should be sent to:
+    /// the address of `dst` itself if it is in the neighbor cache, and that
+    /// of the default router otherwise.
+    pub fn lookup(&self, dst: IPAddr) -> Option<MacAddress> {
+        self.find_neighbor(dst)
+            .or_else(|| self.get_default_router())
+            .map(|neighbor| neighbor.mac_addr)
+    }
+
+    /// Current time on the ND clock, in seconds.
+    fn now(&self) -> u32 {
+        let elapsed = self.alarm.now().wrapping_sub(self.clock_ref.get());
+        self.clock_s.get() + self.alarm.ticks_to_ms(elapsed) / 1000
+    }
+
+    /// Moves the reference point of the ND clock to the current time, so
+    /// that the time elapsed since then always fits the alarm counter.
+    fn rebase_clock(&self) {
+        let now = self.now();
+        let elapsed_ms = (now - self.clock_s.get()) * 1000;
+        self.clock_ref.set(
+            self.clock_ref
+                .get()
+                .wrapping_add(self.alarm.ticks_from_ms(elapsed_ms)),
+        );
+        self.clock_s.set(now);
+    }
+
+    /// Arms the alarm for the earliest pending deadline, or disarms it if
+    /// there is none.
+    fn update_timer(&self) {
+        let next = self
+            .neighbors
+            .iter()
+            .filter_map(|entry| entry.get().map(|neighbor| neighbor.expires))
+            .chain(self.deadline.get())
+            .min();
+        match next {
+            Some(deadline) if self.state.get() != NDState::Idle => {
+                let delay = cmp::min(deadline.saturating_sub(self.now()), MAX_SLEEP_S);
+                self.alarm
+                    .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(delay * 1000));
+            }
+            _ => {
+                let _ = self.alarm.disarm();
+            }
+        }
+    }
+
+    fn find_neighbor(&self, ip_addr: IPAddr) -> Option<Neighbor> {
+        self.neighbors
+            .iter()
+            .filter_map(|entry| entry.get())
+            .find(|neighbor| neighbor.ip_addr == ip_addr)
+    }
+
+    /// Adds or refreshes a neighbor cache entry. If the cache is full, the
+    /// entry closest to expiry is replaced, except for the default router.
+    fn update_neighbor(
+        &self,
+        ip_addr: IPAddr,
+        mac_addr: MacAddress,
+        is_router: bool,
+        lifetime: u32,
+    ) {
+        let neighbor = Neighbor {
+            ip_addr,
+            mac_addr,
+            is_router,
+            expires: self.now().saturating_add(lifetime),
+        };
+        let slot = self
+            .neighbors
+            .iter()
+            .find(|entry| entry.get().is_some_and(|n| n.ip_addr == ip_addr))
+            .or_else(|| self.neighbors.iter().find(|entry| entry.is_none()))
+            .or_else(|| {
+                self.neighbors
+                    .iter()
+                    .filter(|entry| {
+                        entry
+                            .get()
+                            .is_some_and(|n| Some(n.ip_addr) != self.router.get())
+                    })
+                    .min_by_key(|entry| entry.get().map_or(0, |n| n.expires))
+            });
+        if let Some(entry) = slot {
+            entry.set(neighbor);
+        }
+    }
+
+    fn remove_neighbor(&self, ip_addr: IPAddr) {
+        self.neighbors
+            .iter()
+            .filter(|entry| entry.get().is_some_and(|n| n.ip_addr == ip_addr))
+            .for_each(|entry| entry.clear());
+    }
+
+    /// Gives up the global address, registered or not, and tells the client.
+    fn lose_address(&self, reason: ErrorCode) {
+        self.registered.set(false);
+        self.global_addr
+            .take()
+            .map(|addr| self.client.map(|client| client.address_lost(addr, reason)));
+    }
+
+    /// Drops the default router and starts looking for a new one.
+    fn router_lost(&self, reason: ErrorCode) {
+        self.router
+            .take()
+            .map(|router| self.remove_neighbor(router));
+        self.lose_address(reason);
+        self.solicit_routers();
+    }
+
+    fn solicit_routers(&self) {
+        self.state.set(NDState::Soliciting);
+        self.tries.set(0);
+        self.send_router_solicitation();
+    }
+
+    fn register_address(&self) {
+        self.state.set(NDState::Registering);
+        self.tries.set(0);
+        self.send_registration();
+    }
+
+    /// Sends a Router Solicitation to all routers. After the first
+    /// `MAX_RTR_SOLICITATIONS` attempts, the retransmission interval backs
+    /// off exponentially up to `MAX_RTR_SOLICITATION_INTERVAL`.
+    fn send_router_solicitation(&self) {
+        let tries = self.tries.get();
+        let interval = if tries < MAX_RTR_SOLICITATIONS {
+            RTR_SOLICITATION_INTERVAL
+        } else {
+            let backoff = cmp::min(tries - MAX_RTR_SOLICITATIONS + 1, 3);
+            cmp::min(
+                RTR_SOLICITATION_INTERVAL << backoff,
+                MAX_RTR_SOLICITATION_INTERVAL,
+            )
+        };
+        self.tries.set(tries.saturating_add(1));
+        self.deadline.set(Some(self.now() + interval));
+
+        let eui64 = self.eui64;
+        self.send(
+            self.link_local_addr,
+            ALL_ROUTERS,
+            ICMP6Header::new(ICMP6Type::Type133),
+            |buf| {
+                write_eui64_option(&mut buf[..EUI64_OPT_LEN], nd_opt::SLLAO, 0, &eui64);
+                EUI64_OPT_LEN
+            },
+        );
+    }
+
+    /// Sends a Neighbor Solicitation with an ARO to the default router,
+    /// asking it to register the global address.
+    fn send_registration(&self) {
+        let (global_addr, router) = match (self.global_addr.get(), self.router.get()) {
+            (Some(global_addr), Some(router)) => (global_addr, router),
+            _ => return,
+        };
+        self.tries.set(self.tries.get().saturating_add(1));
+        self.deadline.set(Some(self.now() + RETRANS_TIMER));
+
+        let eui64 = self.eui64;
+        let lifetime = self.registration_lifetime;
+        self.send(
+            global_addr,
+            router,
+            ICMP6Header::new(ICMP6Type::Type135),
+            |buf| {
+                buf[..16].copy_from_slice(&global_addr.0);
+                write_eui64_option(&mut buf[16..32], nd_opt::SLLAO, 0, &eui64);
+                write_eui64_option(&mut buf[32..48], nd_opt::ARO, lifetime, &eui64);
+                ND_BUF_LEN
+            },
+        );
+    }
+
+    /// Builds the message body with `fill`, which returns its length, and
+    /// sends it. If a previous message is still being sent, the message is
+    /// dropped and left to the retransmission timer.
+    fn send<F: FnOnce(&mut [u8]) -> usize>(
+        &self,
+        src: IPAddr,
+        dst: IPAddr,
+        icmp_header: ICMP6Header,
+        fill: F,
+    ) {
+        if self.busy.get() {
+            return;
+        }
+        self.tx_buffer.take().map(|mut buf| {
+            let len = fill(buf.as_slice());
+            buf.slice(0..len);
+
+            self.busy.set(true);
+            self.ip_sender.set_addr(src);
+            let result = self.icmp_sender.send(dst, icmp_header, &buf, self.net_cap);
+            buf.reset();
+            self.tx_buffer.replace(buf);
+            if result != Ok(()) {
+                debug!("[6LoWPAN-ND] Error: failed to send message: {:?}", result);
+                self.busy.set(false);
+            }
+        });
+    }
+
+    fn receive_router_advertisement(&self, src_addr: IPAddr, router_lifetime: u16, body: &[u8]) {
+        // The body starts with the Reachable Time and Retrans Timer fields.
+        if !src_addr.is_unicast_link_local() || body.len() < 8 {
+            return;
+        }
+        let mut router_mac = None;
+        let mut prefix = None;
+        let valid = for_each_option(&body[8..], |opt_type, opt| match opt_type {
+            nd_opt::SLLAO => router_mac = parse_link_layer_addr(opt),
+            nd_opt::PIO if opt.len() == 32 => {
+                let prefix_len = opt[2];
+                let flags = opt[3];
+                let valid_lifetime = u32::from_be_bytes([opt[4], opt[5], opt[6], opt[7]]);
+                if prefix_len == 64 && flags & PIO_FLAG_AUTONOMOUS != 0 && valid_lifetime != 0 {
+                    let mut addr = self.link_local_addr;
+                    addr.set_prefix(&opt[16..24], prefix_len);
+                    prefix = Some(addr);
+                }
+            }
+            _ => {}
+        });
+        if !valid {
+            return;
+        }
+
+        if router_lifetime == 0 {
+            // The sender is not (or no longer) a default router.
+            if self.router.get() == Some(src_addr) {
+                self.router_lost(ErrorCode::FAIL);
+            } else {
+                self.remove_neighbor(src_addr);
+            }
+            return;
+        }
+        let router_mac = router_mac.unwrap_or(MacAddress::Long(mac_from_ipv6(src_addr)));
+        self.update_neighbor(src_addr, router_mac, true, router_lifetime as u32);
+
+        if self.state.get() == NDState::Soliciting {
+            if let Some(global_addr) = prefix {
+                self.global_addr.set(global_addr);
+                self.router.set(src_addr);
+                self.register_address();
+            }
+        }
+    }
+
+    fn receive_neighbor_advertisement(&self, src_addr: IPAddr, body: &[u8]) {
+        if self.state.get() != NDState::Registering || self.router.get() != Some(src_addr) {
+            return;
+        }
+        // The body starts with the target address, which must be the
+        // address being registered.
+        let global_addr = match self.global_addr.get() {
+            Some(global_addr) if body.len() >= 16 && body[..16] == global_addr.0 => global_addr,
+            _ => return,
+        };
+        let mut aro = None;
+        let valid = for_each_option(&body[16..], |opt_type, opt| {
+            if opt_type == nd_opt::ARO && opt.len() == EUI64_OPT_LEN && opt[8..16] == self.eui64 {
+                aro = Some((opt[2], u16::from_be_bytes([opt[6], opt[7]])));
+            }
+        });
+        if !valid {
+            return;
+        }
+
+        match aro {
+            Some((aro_status::SUCCESS, lifetime)) => {
+                // Refresh the registration well before it expires.
+                let lifetime = lifetime as u32 * 60;
+                self.state.set(NDState::Registered);
+                self.deadline
+                    .set(Some(self.now() + cmp::max(lifetime * 3 / 4, RETRANS_TIMER)));
+                if !self.registered.replace(true) {
+                    let router_mac = self.find_neighbor(src_addr).map(|n| n.mac_addr);
+                    router_mac.map(|router_mac| {
+                        self.client
+                            .map(|client| client.address_registered(global_addr, router_mac))
+                    });
+                }
+            }
+            Some((aro_status::DUPLICATE, _)) => {
+                self.lose_address(ErrorCode::ALREADY);
+                self.stop();
+            }
+            Some((aro_status::NEIGHBOR_CACHE_FULL, _)) => {
+                self.router_lost(ErrorCode::NOMEM);
+            }
+            // Any other status is a rejection; try another router.
+            Some(_) => self.router_lost(ErrorCode::FAIL),
+            // Not an answer to our registration.
+            None => {}
+        }
+    }
+}
+
+/// Writes an SLLAO or ARO for `eui64` into `buf`, which must be
+/// `EUI64_OPT_LEN` bytes long. `lifetime` is only used by the ARO.
+fn write_eui64_option(buf: &mut [u8], opt_type: u8, lifetime: u16, eui64: &[u8; 8]) {
+    buf.fill(0);
+    buf[0] = opt_type;
+    buf[1] = (EUI64_OPT_LEN / 8) as u8;
+    if opt_type == nd_opt::ARO {
+        buf[6..8].copy_from_slice(&lifetime.to_be_bytes());
+        buf[8..16].copy_from_slice(eui64);
+    } else {
+        buf[2..10].copy_from_slice(eui64);
+    }
+}
+
+/// Parses the link-layer address of an SLLAO, which is either a short or
+/// an extended 802.15.4 address (RFC 4944, section 8).
+fn parse_link_layer_addr(opt: &[u8]) -> Option<MacAddress> {
+    match opt.len() {
+        8 => Some(MacAddress::Short(u16::from_be_bytes([opt[2], opt[3]]))),
+        16 => {
+            let mut addr = [0; 8];
+            addr.copy_from_slice(&opt[2..10]);
+            Some(MacAddress::Long(addr))
+        }
+        _ => None,
+    }
+}
+
+/// Calls `f` with the type and contents (including the type and length
+/// bytes) of every option in `buf`. Returns false if the options are
+/// malformed, in which case the whole message must be discarded.
+fn for_each_option<F: FnMut(u8, &[u8])>(buf: &[u8], mut f: F) -> bool {
+    let mut off = 0;
+    while off < buf.len() {
+        if buf.len() - off < 2 {
+            return false;
+        }
+        let len = buf[off + 1] as usize * 8;
+        if len == 0 || off + len > buf.len() {
+            return false;
+        }
+        f(buf[off], &buf[off..off + len]);
+        off += len;
+    }
+    true
+}
+
+impl<'a, A: time::Alarm<'a>> ICMP6RecvClient for SixlowpanND<'a, A> {
+    fn receive(&self, ip_header: &IP6Header, icmp_header: ICMP6Header, payload: &[u8]) {
+        // A hop limit of 255 shows that the message was not forwarded by a
+        // router, i.e. that it comes from this link (RFC 4861, section 6.1).
+        if self.state.get() == NDState::Idle
+            || ip_header.get_hop_limit() != ND_HOP_LIMIT
+            || icmp_header.get_code() != 0
+        {
+            return;
+        }
+        let src_addr = ip_header.get_src_addr();
+        match icmp_header.get_options() {
+            ICMP6HeaderOptions::Type134 {
+                router_lifetime, ..
+            } => self.receive_router_advertisement(src_addr, router_lifetime, payload),
+            ICMP6HeaderOptions::Type136 { .. } => {
+                self.receive_neighbor_advertisement(src_addr, payload)
+            }
+            _ => return,
+        }
+        self.update_timer();
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> ICMP6SendClient for SixlowpanND<'a, A> {
+    fn send_done(&self, result: Result<(), ErrorCode>) {
+        if result != Ok(()) {
+            debug!(
+                "[6LoWPAN-ND] Error: message transmission failed: {:?}",
+                result
+            );
+        }
+        self.busy.set(false);
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> time::AlarmClient for SixlowpanND<'a, A> {
+    fn alarm(&self) {
+        if self.state.get() == NDState::Idle {
+            return;
+        }
+        self.rebase_clock();
+        let now = self.now();
+
+        for entry in self.neighbors.iter() {
+            if let Some(neighbor) = entry.get() {
+                if neighbor.expires <= now {
+                    entry.clear();
+                    if self.router.get() == Some(neighbor.ip_addr) {
+                        self.router_lost(ErrorCode::FAIL);
+                    }
+                }
+            }
+        }
+
+        if self.deadline.get().is_some_and(|deadline| deadline <= now) {
+            self.deadline.set(None);
+            match self.state.get() {
+                NDState::Idle => {}
+                NDState::Soliciting => self.send_router_solicitation(),
+                NDState::Registering if self.tries.get() >= MAX_UNICAST_SOLICIT => {
+                    // The router does not answer, look for another one.
+                    self.router_lost(ErrorCode::FAIL);
+                }
+                NDState::Registering => self.send_registration(),
+                NDState::Registered => self.register_address(),
+            }
+        }
+        self.update_timer();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::net::icmpv6::icmpv6_send::ICMP6SendClient;
+    use crate::net::ipv6::ipv6_send::IP6SendClient;
+    use crate::net::ipv6::TransportHeader;
+    use core::cell::RefCell;
+    use kernel::hil::time::{Alarm, AlarmClient, Freq1KHz, Ticks32, Time};
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    const EUI64: [u8; 8] = [0x02, 0, 0, 0, 0, 0, 0, 0x01];
+    const ROUTER_MAC: [u8; 8] = [0x02, 0, 0, 0, 0, 0, 0, 0xfe];
+    const PREFIX: [u8; 8] = [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0];
+
+    struct FakeAlarm {
+        now: Cell<Ticks32>,
+    }
+
+    impl Time for FakeAlarm {
+        type Frequency = Freq1KHz;
+        type Ticks = Ticks32;
+
+        fn now(&self) -> Ticks32 {
+            self.now.get()
+        }
+    }
+
+    impl<'a> Alarm<'a> for FakeAlarm {
+        fn set_alarm_client(&self, _client: &'a dyn AlarmClient) {}
+
+        fn set_alarm(&self, _reference: Ticks32, _dt: Ticks32) {}
+
+        fn get_alarm(&self) -> Ticks32 {
+            Ticks32::from(0)
+        }
+
+        fn disarm(&self) -> Result<(), ErrorCode> {
+            Ok(())
+        }
+
+        fn is_armed(&self) -> bool {
+            false
+        }
+
+        fn minimum_dt(&self) -> Ticks32 {
+            Ticks32::from(1)
+        }
+    }
+
+    /// Records the type, destination and body of every message sent.
+    struct FakeSender {
+        sent: RefCell<Vec<(u8, IPAddr, Vec<u8>)>>,
+    }
+
+    impl<'a> ICMP6Sender<'a> for FakeSender {
+        fn set_client(&self, _client: &'a dyn ICMP6SendClient) {}
+
+        fn send(
+            &self,
+            dest: IPAddr,
+            icmp_header: ICMP6Header,
+            buf: &SubSliceMut<'static, u8>,
+            _net_cap: &'static NetworkCapability,
+        ) -> Result<(), ErrorCode> {
+            self.sent
+                .borrow_mut()
+                .push((icmp_header.get_type_as_int(), dest, buf[..].to_vec()));
+            Ok(())
+        }
+    }
+
+    impl<'a> IP6Sender<'a> for FakeSender {
+        fn set_client(&self, _client: &'a dyn IP6SendClient) {}
+
+        fn set_addr(&self, _src_addr: IPAddr) {}
+
+        fn set_gateway(&self, _gateway: MacAddress) {}
+
+        fn set_header(&mut self, _ip6_header: IP6Header) {}
+
+        fn send_to(
+            &self,
+            _dst: IPAddr,
+            _transport_header: TransportHeader,
+            _payload: &SubSliceMut<'static, u8>,
+            _net_cap: &'static NetworkCapability,
+        ) -> Result<(), ErrorCode> {
+            Err(ErrorCode::NOSUPPORT)
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        Registered(IPAddr, MacAddress),
+        Lost(IPAddr, ErrorCode),
+    }
+
+    struct FakeClient {
+        events: RefCell<Vec<Event>>,
+    }
+
+    impl SixlowpanNDClient for FakeClient {
+        fn address_registered(&self, addr: IPAddr, router_mac: MacAddress) {
+            self.events
+                .borrow_mut()
+                .push(Event::Registered(addr, router_mac));
+        }
+
+        fn address_lost(&self, addr: IPAddr, reason: ErrorCode) {
+            self.events.borrow_mut().push(Event::Lost(addr, reason));
+        }
+    }
+
+    struct Fixture {
+        alarm: FakeAlarm,
+        sender: FakeSender,
+        client: FakeClient,
+        neighbors: [OptionalCell<Neighbor>; 2],
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            Self {
+                alarm: FakeAlarm {
+                    now: Cell::new(Ticks32::from(0)),
+                },
+                sender: FakeSender {
+                    sent: RefCell::new(Vec::new()),
+                },
+                client: FakeClient {
+                    events: RefCell::new(Vec::new()),
+                },
+                neighbors: [OptionalCell::empty(), OptionalCell::empty()],
+            }
+        }
+
+        /// Returns a started `SixlowpanND` whose Router Solicitation was sent.
+        fn nd(&self) -> SixlowpanND<'_, FakeAlarm> {
+            let tx_buffer = Box::leak(Box::new([0; ND_BUF_LEN]));
+            let net_cap = Box::leak(Box::new(NetworkCapability::new_unrestricted()));
+            let nd = SixlowpanND::new(
+                &self.sender,
+                &self.sender,
+                &self.alarm,
+                EUI64,
+                &self.neighbors,
+                SubSliceMut::new(tx_buffer),
+                30,
+                net_cap,
+            );
+            nd.set_client(&self.client);
+            nd.start().unwrap();
+            nd.send_done(Ok(()));
+            let (icmp_type, dst, _) = self.take_sent().pop().unwrap();
+            assert_eq!(icmp_type, 133);
+            assert_eq!(dst, ALL_ROUTERS);
+            nd
+        }
+
+        /// Returns the messages sent so far.
+        fn take_sent(&self) -> Vec<(u8, IPAddr, Vec<u8>)> {
+            self.sender.sent.take()
+        }
+
+        fn take_events(&self) -> Vec<Event> {
+            self.client.events.take()
+        }
+    }
+
+    fn router_addr() -> IPAddr {
+        IPAddr::generate_from_mac(MacAddress::Long(ROUTER_MAC))
+    }
+
+    fn global_addr() -> IPAddr {
+        let mut addr = IPAddr::generate_from_mac(MacAddress::Long(EUI64));
+        addr.set_prefix(&PREFIX, 64);
+        addr
+    }
+
+    fn receive(nd: &SixlowpanND<FakeAlarm>, hop_limit: u8, icmp_header: ICMP6Header, body: &[u8]) {
+        let mut ip_header = IP6Header::new();
+        ip_header.src_addr = router_addr();
+        ip_header.dst_addr = nd.get_link_local_addr();
+        ip_header.set_hop_limit(hop_limit);
+        nd.receive(&ip_header, icmp_header, body);
+        // Complete the transmission of any reply.
+        nd.send_done(Ok(()));
+    }
+
+    /// Builds a Router Advertisement with an SLLAO and a Prefix Information
+    /// Option for `PREFIX`.
+    fn router_advertisement(router_lifetime: u16) -> (ICMP6Header, Vec<u8>) {
+        let mut icmp_header = ICMP6Header::new(ICMP6Type::Type134);
+        icmp_header.set_options(ICMP6HeaderOptions::Type134 {
+            hop_limit: 64,
+            flags: 0,
+            router_lifetime,
+        });
+        let mut body = std::vec![0; 8];
+        let mut sllao = [0; EUI64_OPT_LEN];
+        write_eui64_option(&mut sllao, nd_opt::SLLAO, 0, &ROUTER_MAC);
+        body.extend_from_slice(&sllao);
+        let mut pio = [0; 32];
+        pio[..4].copy_from_slice(&[nd_opt::PIO, 4, 64, PIO_FLAG_AUTONOMOUS]);
+        pio[4..8].copy_from_slice(&3600u32.to_be_bytes());
+        pio[8..12].copy_from_slice(&3600u32.to_be_bytes());
+        pio[16..24].copy_from_slice(&PREFIX);
+        body.extend_from_slice(&pio);
+        (icmp_header, body)
+    }
+
+    /// Builds a Neighbor Advertisement answering the registration of the
+    /// global address with `status`.
+    fn neighbor_advertisement(status: u8, eui64: &[u8; 8]) -> (ICMP6Header, Vec<u8>) {
+        let mut body = global_addr().0.to_vec();
+        let mut aro = [0; EUI64_OPT_LEN];
+        write_eui64_option(&mut aro, nd_opt::ARO, 30, eui64);
+        aro[2] = status;
+        body.extend_from_slice(&aro);
+        (ICMP6Header::new(ICMP6Type::Type136), body)
+    }
+
+    /// Delivers a Router Advertisement and checks that the registration NS
+    /// is sent.
+    fn advertise_router(fixture: &Fixture, nd: &SixlowpanND<FakeAlarm>) {
+        let (icmp_header, body) = router_advertisement(1800);
+        receive(nd, 255, icmp_header, &body);
+        assert_eq!(nd.get_state(), NDState::Registering);
+        let sent = fixture.take_sent();
+        assert_eq!(sent.len(), 1);
+        let (icmp_type, dst, body) = &sent[0];
+        assert_eq!(*icmp_type, 135);
+        assert_eq!(*dst, router_addr());
+        assert_eq!(body[..16], global_addr().0);
+    }
+
+    fn collect_options(buf: &[u8]) -> (bool, Vec<(u8, usize)>) {
+        let mut options = Vec::new();
+        let valid = for_each_option(buf, |opt_type, opt| options.push((opt_type, opt.len())));
+        (valid, options)
+    }
+
+    #[test]
+    fn for_each_option_walks_options() {
+        let mut buf = [0; 24];
+        buf[..2].copy_from_slice(&[nd_opt::SLLAO, 1]);
+        buf[8..10].copy_from_slice(&[nd_opt::ARO, 2]);
+        assert_eq!(
+            collect_options(&buf),
+            (true, std::vec![(nd_opt::SLLAO, 8), (nd_opt::ARO, 16)])
+        );
+        assert_eq!(collect_options(&[]), (true, std::vec![]));
+    }
+
+    #[test]
+    fn for_each_option_rejects_malformed_options() {
+        // Zero length.
+        assert!(!collect_options(&[nd_opt::SLLAO, 0, 0, 0, 0, 0, 0, 0]).0);
+        // Longer than the buffer.
+        assert!(!collect_options(&[nd_opt::SLLAO, 2, 0, 0, 0, 0, 0, 0]).0);
+        // A trailing byte after a valid option.
+        let (valid, options) = collect_options(&[nd_opt::SLLAO, 1, 0, 0, 0, 0, 0, 0, 1]);
+        assert!(!valid);
+        assert_eq!(options, [(nd_opt::SLLAO, 8)]);
+    }
+
+    #[test]
+    fn parse_link_layer_addr_lengths() {
+        let short = [nd_opt::SLLAO, 1, 0x12, 0x34, 0, 0, 0, 0];
+        assert_eq!(
+            parse_link_layer_addr(&short),
+            Some(MacAddress::Short(0x1234))
+        );
+        let mut long = [0; EUI64_OPT_LEN];
+        write_eui64_option(&mut long, nd_opt::SLLAO, 0, &ROUTER_MAC);
+        assert_eq!(
+            parse_link_layer_addr(&long),
+            Some(MacAddress::Long(ROUTER_MAC))
+        );
+        assert_eq!(parse_link_layer_addr(&[0; 24]), None);
+    }
+
+    #[test]
+    fn router_advertisement_starts_registration() {
+        let fixture = Fixture::new();
+        let nd = fixture.nd();
+        advertise_router(&fixture, &nd);
+
+        let router = nd.get_default_router().unwrap();
+        assert_eq!(router.ip_addr, router_addr());
+        assert_eq!(router.mac_addr, MacAddress::Long(ROUTER_MAC));
+        assert!(router.is_router);
+        assert_eq!(nd.get_global_addr(), None);
+    }
+
+    #[test]
+    fn router_advertisement_with_wrong_hop_limit_is_ignored() {
+        let fixture = Fixture::new();
+        let nd = fixture.nd();
+        let (icmp_header, body) = router_advertisement(1800);
+        receive(&nd, 254, icmp_header, &body);
+
+        assert_eq!(nd.get_state(), NDState::Soliciting);
+        assert!(nd.get_default_router().is_none());
+        assert!(fixture.take_sent().is_empty());
+    }
+
+    #[test]
+    fn router_advertisement_with_zero_lifetime_drops_router() {
+        let fixture = Fixture::new();
+        let nd = fixture.nd();
+        advertise_router(&fixture, &nd);
+
+        let (icmp_header, body) = router_advertisement(0);
+        receive(&nd, 255, icmp_header, &body);
+        assert_eq!(nd.get_state(), NDState::Soliciting);
+        assert!(nd.get_default_router().is_none());
+        assert_eq!(
+            fixture.take_events(),
+            [Event::Lost(global_addr(), ErrorCode::FAIL)]
+        );
+    }
+
+    #[test]
+    fn neighbor_advertisement_completes_registration() {
+        let fixture = Fixture::new();
+        let nd = fixture.nd();
+        advertise_router(&fixture, &nd);
+
+        let (icmp_header, body) = neighbor_advertisement(aro_status::SUCCESS, &EUI64);
+        receive(&nd, 255, icmp_header, &body);
+        assert_eq!(nd.get_state(), NDState::Registered);
+        assert_eq!(nd.get_global_addr(), Some(global_addr()));
+        assert_eq!(
+            fixture.take_events(),
+            [Event::Registered(
+                global_addr(),
+                MacAddress::Long(ROUTER_MAC)
+            )]
+        );
+    }
+
+    #[test]
+    fn neighbor_advertisement_with_wrong_hop_limit_or_owner_is_ignored() {
+        let fixture = Fixture::new();
+        let nd = fixture.nd();
+        advertise_router(&fixture, &nd);
+
+        let (icmp_header, body) = neighbor_advertisement(aro_status::SUCCESS, &EUI64);
+        receive(&nd, 254, icmp_header, &body);
+        let (icmp_header, body) = neighbor_advertisement(aro_status::SUCCESS, &ROUTER_MAC);
+        receive(&nd, 255, icmp_header, &body);
+
+        assert_eq!(nd.get_state(), NDState::Registering);
+        assert_eq!(nd.get_global_addr(), None);
+        assert!(fixture.take_events().is_empty());
+    }
+
+    #[test]
+    fn duplicate_address_stops_discovery() {
+        let fixture = Fixture::new();
+        let nd = fixture.nd();
+        advertise_router(&fixture, &nd);
+
+        let (icmp_header, body) = neighbor_advertisement(aro_status::DUPLICATE, &EUI64);
+        receive(&nd, 255, icmp_header, &body);
+        assert_eq!(nd.get_state(), NDState::Idle);
+        assert_eq!(
+            fixture.take_events(),
+            [Event::Lost(global_addr(), ErrorCode::ALREADY)]
+        );
+    }
+
+    #[test]
+    fn full_neighbor_cache_looks_for_another_router() {
+        let fixture = Fixture::new();
+        let nd = fixture.nd();
+        advertise_router(&fixture, &nd);
+
+        let (icmp_header, body) = neighbor_advertisement(aro_status::NEIGHBOR_CACHE_FULL, &EUI64);
+        receive(&nd, 255, icmp_header, &body);
+        assert_eq!(nd.get_state(), NDState::Soliciting);
+        assert!(nd.get_default_router().is_none());
+        assert_eq!(
+            fixture.take_events(),
+            [Event::Lost(global_addr(), ErrorCode::NOMEM)]
+        );
+        assert_eq!(fixture.take_sent()[0].0, 133);
+    }
+}
@@ -0,0 +1,363 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! TCP userspace interface.
+//!
+//! Gives processes access to a fixed pool of kernel
+//! [TcpSocket](../tcp_socket/struct.TcpSocket.html)s. Each process can hold
+//! one socket at a time, which it obtains by either connecting to a remote
+//! endpoint (client socket) or listening on a local port (listening socket).
+//! A listening socket accepts a single connection, after which it is used
+//! exactly like a client socket.
+//!
+//! Data is copied between the allowed buffers and the send and receive
+//! buffers of the socket, so apps can queue data and read received data at
+//! any time without racing the network stack.
+
+use crate::net::ipv6::ip_utils::IPAddr;
+use crate::net::tcp::tcp_socket::{TcpClient, TcpSocket};
+use crate::net::util::{network_slice_to_u16, u16_to_network_slice};
+
+use core::mem::size_of;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::Tcp as usize;
+
+/// IDs for subscribed upcalls.
+mod upcall {
+    /// The connection was established, or could not be established. The
+    /// first argument is the status code.
+    pub const CONNECTED: usize = 0;
+    /// Data was received. The first argument is the number of bytes that can
+    /// be read, the second is 1 if the peer has closed the connection and no
+    /// more data will arrive.
+    pub const RECEIVED: usize = 1;
+    /// Queued data was acknowledged by the peer. The first argument is the
+    /// number of bytes acknowledged.
+    pub const SENT: usize = 2;
+    /// The connection was closed. The first argument is the status code,
+    /// which is `FAIL` if the connection was reset and `NOACK` if the peer
+    /// stopped responding.
+    pub const CLOSED: usize = 3;
+    /// Number of upcalls.
+    pub const COUNT: u8 = 4;
+}
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    /// Write buffer. Contains the data to queue for transmission.
+    pub const WRITE: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Read buffer. Received data is copied into it by the receive command.
+    pub const READ: usize = 0;
+    /// Config buffer. Holds a remote endpoint: a 16 byte IPv6 address
+    /// followed by a 2 byte port in network byte order.
+    pub const CFG: usize = 1;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 2;
+}
+
+const ENDPOINT_LEN: usize = size_of::<IPAddr>() + size_of::<u16>();
+
+#[derive(Default)]
+pub struct App {
+    /// Index of the socket held by this process.
+    socket: Option<usize>,
+}
+
+pub struct TcpDriver<'a> {
+    sockets: &'a [TcpSocket<'a>],
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+}
+
+impl<'a> TcpDriver<'a> {
+    /// Creates the driver. The sockets must already be registered with a
+    /// `MuxTcp`, and the driver must be set as the client of each of them.
+    pub fn new(
+        sockets: &'a [TcpSocket<'a>],
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+    ) -> TcpDriver<'a> {
+        TcpDriver {
+            sockets,
+            apps: grant,
+        }
+    }
+
+    /// Returns the index of a socket not held by any process.
+    fn free_socket(&self) -> Option<usize> {
+        (0..self.sockets.len()).find(|&index| {
+            !self
+                .apps
+                .iter()
+                .any(|app| app.enter(|app, _| app.socket == Some(index)))
+        })
+    }
+
+    /// Returns the socket held by `processid`, allocating one if it holds
+    /// none. A socket left behind by a process that has since exited may
+    /// still have a connection open, which is aborted before reuse.
+    fn get_or_alloc_socket(&self, processid: ProcessId) -> Result<&TcpSocket<'a>, ErrorCode> {
+        let held = self.apps.enter(processid, |app, _| app.socket)?;
+        let index = match held {
+            Some(index) => index,
+            None => {
+                let index = self.free_socket().ok_or(ErrorCode::NOMEM)?;
+                self.sockets[index].abort();
+                self.apps
+                    .enter(processid, |app, _| app.socket = Some(index))?;
+                index
+            }
+        };
+        Ok(&self.sockets[index])
+    }
+
+    /// Returns the socket held by `processid`.
+    fn get_socket(&self, processid: ProcessId) -> Result<&TcpSocket<'a>, ErrorCode> {
+        self.apps
+            .enter(processid, |app, _| app.socket)?
+            .map(|index| &self.sockets[index])
+            .ok_or(ErrorCode::RESERVE)
+    }
+
+    /// Reads the remote endpoint from the config buffer of `processid`.
+    fn read_endpoint(&self, processid: ProcessId) -> Result<(IPAddr, u16), ErrorCode> {
+        self.apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readwrite_processbuffer(rw_allow::CFG)
+                    .and_then(|cfg| {
+                        cfg.enter(|cfg| {
+                            let mut endpoint = [0; ENDPOINT_LEN];
+                            cfg.get(0..ENDPOINT_LEN)
+                                .ok_or(ErrorCode::INVAL)?
+                                .copy_to_slice(&mut endpoint);
+                            let mut addr = IPAddr::new();
+                            addr.0.copy_from_slice(&endpoint[..size_of::<IPAddr>()]);
+                            Ok((addr, network_slice_to_u16(&endpoint[size_of::<IPAddr>()..])))
+                        })
+                    })
+                    .unwrap_or(Err(ErrorCode::INVAL))
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+    }
+
+    /// Schedules `upcall_num` for the process holding `socket`, if any.
+    fn notify(&self, socket: &TcpSocket, upcall_num: usize, args: (usize, usize, usize)) {
+        let index = match self
+            .sockets
+            .iter()
+            .position(|s| core::ptr::addr_eq(s, socket))
+        {
+            Some(index) => index,
+            None => return,
+        };
+        self.apps.each(|_, app, kernel_data| {
+            if app.socket == Some(index) {
+                kernel_data.schedule_upcall(upcall_num, args).ok();
+            }
+        });
+    }
+}
+
+impl SyscallDriver for TcpDriver<'_> {
+    /// TCP control
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Connect to the remote endpoint in the config buffer. `arg1` is
+    ///        the local port, or 0 to pick an ephemeral port. Returns BUSY if
+    ///        the socket of this process is in use or the local port is
+    ///        taken, NOMEM if no socket is free, and INVAL if the endpoint
+    ///        is invalid. The `CONNECTED` upcall signals completion.
+    /// - `2`: Listen on local port `arg1`. Returns BUSY if the socket of this
+    ///        process is in use or the port is taken, and NOMEM if no socket
+    ///        is free. The `CONNECTED` upcall signals an accepted connection.
+    /// - `3`: Queue the first `arg1` bytes of the write buffer for
+    ///        transmission. Returns the number of bytes queued, which is
+    ///        smaller than `arg1` if the send buffer is nearly full. Returns
+    ///        NOMEM if the send buffer is full, OFF if the connection cannot
+    ///        send, and SIZE if `arg1` exceeds the write buffer.
+    /// - `4`: Copy received data into the read buffer. Returns the number of
+    ///        bytes copied, which may be zero.
+    /// - `5`: Close the connection. Queued data is still delivered, and the
+    ///        `CLOSED` upcall signals completion.
+    /// - `6`: Abort the connection and release the socket.
+    /// - `7`: Get the connection state (see `TcpState`).
+    /// - `8`: Write the remote endpoint of the connection into the config
+    ///        buffer, e.g. to find the peer of an accepted connection.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => {
+                let result = self.read_endpoint(processid).and_then(|(addr, port)| {
+                    self.get_or_alloc_socket(processid)?
+                        .connect(arg1 as u16, addr, port)
+                });
+                CommandReturn::from(result)
+            }
+
+            2 => {
+                let result = self
+                    .get_or_alloc_socket(processid)
+                    .and_then(|socket| socket.listen(arg1 as u16));
+                CommandReturn::from(result)
+            }
+
+            3 => {
+                let result = self.get_socket(processid).and_then(|socket| {
+                    self.apps
+                        .enter(processid, |_, kernel_data| {
+                            kernel_data
+                                .get_readonly_processbuffer(ro_allow::WRITE)
+                                .and_then(|write| {
+                                    write.enter(|data| {
+                                        let data = data.get(0..arg1).ok_or(ErrorCode::SIZE)?;
+                                        socket.send_with(data.len(), |buf| {
+                                            data[..buf.len()].copy_to_slice(buf)
+                                        })
+                                    })
+                                })
+                                .unwrap_or(Err(ErrorCode::RESERVE))
+                        })
+                        .unwrap_or_else(|err| Err(err.into()))
+                });
+                match result {
+                    Ok(queued) => CommandReturn::success_u32(queued as u32),
+                    Err(e) => CommandReturn::failure(e),
+                }
+            }
+
+            4 => {
+                let result = self.get_socket(processid).and_then(|socket| {
+                    self.apps
+                        .enter(processid, |_, kernel_data| {
+                            kernel_data
+                                .get_readwrite_processbuffer(rw_allow::READ)
+                                .and_then(|read| {
+                                    read.mut_enter(|buf| {
+                                        socket.recv_with(buf.len(), |data| {
+                                            buf[..data.len()].copy_from_slice(data)
+                                        })
+                                    })
+                                })
+                                .map_err(ErrorCode::from)
+                        })
+                        .unwrap_or_else(|err| Err(err.into()))
+                });
+                match result {
+                    Ok(read) => CommandReturn::success_u32(read as u32),
+                    Err(e) => CommandReturn::failure(e),
+                }
+            }
+
+            5 => CommandReturn::from(self.get_socket(processid).and_then(|socket| socket.close())),
+
+            6 => {
+                let result = self.get_socket(processid).and_then(|socket| {
+                    socket.abort();
+                    self.apps
+                        .enter(processid, |app, _| app.socket = None)
+                        .map_err(ErrorCode::from)
+                });
+                CommandReturn::from(result)
+            }
+
+            7 => match self.get_socket(processid) {
+                Ok(socket) => CommandReturn::success_u32(socket.get_state() as u32),
+                Err(e) => CommandReturn::failure(e),
+            },
+
+            8 => {
+                let result = self.get_socket(processid).and_then(|socket| {
+                    let mut endpoint = [0; ENDPOINT_LEN];
+                    endpoint[..size_of::<IPAddr>()].copy_from_slice(&socket.get_remote_addr().0);
+                    u16_to_network_slice(
+                        socket.get_remote_port(),
+                        &mut endpoint[size_of::<IPAddr>()..],
+                    );
+                    self.apps
+                        .enter(processid, |_, kernel_data| {
+                            kernel_data
+                                .get_readwrite_processbuffer(rw_allow::CFG)
+                                .and_then(|cfg| {
+                                    cfg.mut_enter(|cfg| {
+                                        cfg.get(0..ENDPOINT_LEN)
+                                            .ok_or(ErrorCode::INVAL)?
+                                            .copy_from_slice(&endpoint);
+                                        Ok(())
+                                    })
+                                })
+                                .unwrap_or(Err(ErrorCode::INVAL))
+                        })
+                        .unwrap_or_else(|err| Err(err.into()))
+                });
+                CommandReturn::from(result)
+            }
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+impl TcpClient for TcpDriver<'_> {
+    fn connected(&self, socket: &TcpSocket, result: Result<(), ErrorCode>) {
+        self.notify(
+            socket,
+            upcall::CONNECTED,
+            (kernel::errorcode::into_statuscode(result), 0, 0),
+        );
+    }
+
+    fn received(&self, socket: &TcpSocket, available: usize) {
+        self.notify(socket, upcall::RECEIVED, (available, 0, 0));
+    }
+
+    fn sent(&self, socket: &TcpSocket, acked: usize) {
+        self.notify(socket, upcall::SENT, (acked, 0, 0));
+    }
+
+    fn remote_closed(&self, socket: &TcpSocket) {
+        self.notify(socket, upcall::RECEIVED, (socket.available(), 1, 0));
+    }
+
+    fn closed(&self, socket: &TcpSocket, result: Result<(), ErrorCode>) {
+        self.notify(
+            socket,
+            upcall::CLOSED,
+            (kernel::errorcode::into_statuscode(result), 0, 0),
+        );
+    }
+}
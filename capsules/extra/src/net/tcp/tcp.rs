@@ -0,0 +1,259 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! This file contains the structs and methods associated with the TCP header.
+//! This includes getters and setters for the various header fields, as well
+//! as the standard encode/decode functionality required for serializing
+//! the struct for transmission.
+//!
+//! Segments sent by this stack never carry TCP options, so the header is
+//! always `TCP_HDR_LEN` bytes long. Options on received segments are skipped
+//! using the data offset field, with the exception of the Maximum Segment
+//! Size option, which can be extracted with `parse_mss_option`.
+
+use crate::net::stream::SResult;
+use crate::net::stream::{decode_u16, decode_u32};
+use crate::net::stream::{encode_u16, encode_u32};
+
+/// Length of a TCP header without options.
+pub const TCP_HDR_LEN: usize = 20;
+
+/// Control bits carried in the low byte of `offset_and_control`.
+pub mod tcp_flags {
+    pub const FIN: u16 = 0x01;
+    pub const SYN: u16 = 0x02;
+    pub const RST: u16 = 0x04;
+    pub const PSH: u16 = 0x08;
+    pub const ACK: u16 = 0x10;
+    pub const URG: u16 = 0x20;
+}
+
+const TCP_OPT_END: u8 = 0;
+const TCP_OPT_NOP: u8 = 1;
+const TCP_OPT_MSS: u8 = 2;
+
+/// The `TCPHeader` struct follows the layout for the TCP segment header.
+///
+/// All fields are stored in host byte order, and are converted to network
+/// byte order by `encode`.
+#[derive(Copy, Clone, Debug)]
+pub struct TCPHeader {
+    src_port: u16,
+    dst_port: u16,
+    seq_num: u32,
+    ack_num: u32,
+    offset_and_control: u16,
+    window: u16,
+    cksum: u16,
+    urg_ptr: u16,
+    len: u16, // Not a real TCP field, total segment length for convenience
+}
+
+impl Default for TCPHeader {
+    fn default() -> TCPHeader {
+        TCPHeader {
+            src_port: 0,
+            dst_port: 0,
+            seq_num: 0,
+            ack_num: 0,
+            offset_and_control: ((TCP_HDR_LEN / 4) as u16) << 12,
+            window: 0,
+            cksum: 0,
+            urg_ptr: 0,
+            len: TCP_HDR_LEN as u16,
+        }
+    }
+}
+
+impl TCPHeader {
+    pub fn new() -> TCPHeader {
+        TCPHeader::default()
+    }
+
+    pub fn set_src_port(&mut self, port: u16) {
+        self.src_port = port;
+    }
+
+    pub fn set_dst_port(&mut self, port: u16) {
+        self.dst_port = port;
+    }
+
+    pub fn set_seq_num(&mut self, seq_num: u32) {
+        self.seq_num = seq_num;
+    }
+
+    pub fn set_ack_num(&mut self, ack_num: u32) {
+        self.ack_num = ack_num;
+    }
+
+    /// Sets the control bits (see `tcp_flags`), leaving the data offset
+    /// untouched.
+    pub fn set_flags(&mut self, flags: u16) {
+        self.offset_and_control = (self.offset_and_control & 0xf000) | (flags & 0x01ff);
+    }
+
+    pub fn set_window(&mut self, window: u16) {
+        self.window = window;
+    }
+
+    pub fn set_cksum(&mut self, cksum: u16) {
+        self.cksum = cksum;
+    }
+
+    pub fn set_urg_ptr(&mut self, urg_ptr: u16) {
+        self.urg_ptr = urg_ptr;
+    }
+
+    /// Sets the total length of the segment (header and payload). This value
+    /// is not transmitted, but is required to compute the checksum.
+    pub fn set_len(&mut self, len: u16) {
+        self.len = len;
+    }
+
+    pub fn get_src_port(&self) -> u16 {
+        self.src_port
+    }
+
+    pub fn get_dst_port(&self) -> u16 {
+        self.dst_port
+    }
+
+    pub fn get_seq_num(&self) -> u32 {
+        self.seq_num
+    }
+
+    pub fn get_ack_num(&self) -> u32 {
+        self.ack_num
+    }
+
+    pub fn get_flags(&self) -> u16 {
+        self.offset_and_control & 0x01ff
+    }
+
+    pub fn has_flags(&self, flags: u16) -> bool {
+        self.get_flags() & flags == flags
+    }
+
+    pub fn get_window(&self) -> u16 {
+        self.window
+    }
+
+    pub fn get_cksum(&self) -> u16 {
+        self.cksum
+    }
+
+    pub fn get_urg_ptr(&self) -> u16 {
+        self.urg_ptr
+    }
+
+    pub fn get_len(&self) -> u16 {
+        self.len
+    }
+
+    /// Returns the header length in bytes as indicated by the data offset
+    /// field, which includes any options present on a received segment.
+    pub fn get_data_offset(&self) -> usize {
+        ((self.offset_and_control >> 12) as usize) * 4
+    }
+
+    /// Returns the size of the header produced by `encode`. Options are
+    /// never encoded, so this is always `TCP_HDR_LEN`.
+    pub fn get_hdr_size(&self) -> usize {
+        TCP_HDR_LEN
+    }
+
+    /// This function serializes the `TCPHeader` into the provided buffer.
+    ///
+    /// # Arguments
+    ///
+    /// `buf` - A mutable buffer to serialize the `TCPHeader` into
+    /// `offset` - The current offset into the provided buffer
+    ///
+    /// # Return Value
+    ///
+    /// This function returns the new offset into the buffer wrapped in an
+    /// SResult.
+    pub fn encode(&self, buf: &mut [u8], offset: usize) -> SResult<usize> {
+        stream_len_cond!(buf, self.get_hdr_size() + offset);
+
+        // Options are never encoded, so always advertise a 20 byte header.
+        let offset_and_control = (((TCP_HDR_LEN / 4) as u16) << 12) | self.get_flags();
+
+        let mut off = offset;
+        off = enc_consume!(buf, off; encode_u16, self.src_port);
+        off = enc_consume!(buf, off; encode_u16, self.dst_port);
+        off = enc_consume!(buf, off; encode_u32, self.seq_num);
+        off = enc_consume!(buf, off; encode_u32, self.ack_num);
+        off = enc_consume!(buf, off; encode_u16, offset_and_control);
+        off = enc_consume!(buf, off; encode_u16, self.window);
+        off = enc_consume!(buf, off; encode_u16, self.cksum);
+        off = enc_consume!(buf, off; encode_u16, self.urg_ptr);
+        stream_done!(off, off);
+    }
+
+    /// This function deserializes the `TCPHeader` from the provided buffer.
+    /// The returned offset points past any options, i.e. to the start of
+    /// the segment payload. The `len` field is set to the length of `buf`.
+    ///
+    /// # Arguments
+    ///
+    /// `buf` - The byte array corresponding to a serialized TCP segment
+    ///
+    /// # Return Value
+    ///
+    /// This function returns a `TCPHeader` struct wrapped in an SResult
+    pub fn decode(buf: &[u8]) -> SResult<TCPHeader> {
+        stream_len_cond!(buf, TCP_HDR_LEN);
+        let mut tcp_header = Self::new();
+        let off = 0;
+        let (off, src_port) = dec_try!(buf, off; decode_u16);
+        tcp_header.src_port = src_port;
+        let (off, dst_port) = dec_try!(buf, off; decode_u16);
+        tcp_header.dst_port = dst_port;
+        let (off, seq_num) = dec_try!(buf, off; decode_u32);
+        tcp_header.seq_num = seq_num;
+        let (off, ack_num) = dec_try!(buf, off; decode_u32);
+        tcp_header.ack_num = ack_num;
+        let (off, offset_and_control) = dec_try!(buf, off; decode_u16);
+        tcp_header.offset_and_control = offset_and_control;
+        let (off, window) = dec_try!(buf, off; decode_u16);
+        tcp_header.window = window;
+        let (off, cksum) = dec_try!(buf, off; decode_u16);
+        tcp_header.cksum = cksum;
+        let (_off, urg_ptr) = dec_try!(buf, off; decode_u16);
+        tcp_header.urg_ptr = urg_ptr;
+
+        let data_offset = tcp_header.get_data_offset();
+        stream_cond!(data_offset >= TCP_HDR_LEN);
+        stream_len_cond!(buf, data_offset);
+        tcp_header.len = buf.len() as u16;
+        stream_done!(data_offset, tcp_header);
+    }
+}
+
+/// Extracts the Maximum Segment Size option from a serialized TCP segment,
+/// if present.
+pub fn parse_mss_option(buf: &[u8]) -> Option<u16> {
+    let hdr_len = ((buf.get(12)? >> 4) as usize) * 4;
+    let options = buf.get(TCP_HDR_LEN..hdr_len)?;
+    let mut i = 0;
+    while i < options.len() {
+        match options[i] {
+            TCP_OPT_END => break,
+            TCP_OPT_NOP => i += 1,
+            kind => {
+                let len = *options.get(i + 1)? as usize;
+                if len < 2 {
+                    return None;
+                }
+                if kind == TCP_OPT_MSS && len == 4 {
+                    let mss = options.get(i + 2..i + 4)?;
+                    return Some(((mss[0] as u16) << 8) | (mss[1] as u16));
+                }
+                i += len;
+            }
+        }
+    }
+    None
+}
@@ -0,0 +1,272 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Multiplexes TCP sockets onto the IPv6 layer.
+//!
+//! `MuxTcp` is the TCP receive client of an `IP6Receiver` and the send client
+//! of an `IP6Sender`. Received segments are dispatched to the registered
+//! [TcpSocket](../tcp_socket/struct.TcpSocket.html) whose connection matches
+//! (or which is listening on the destination port), and segments for which
+//! no socket exists are answered with a reset.
+//!
+//! Since the IPv6 sender can only transmit one packet at a time, sockets do
+//! not send segments themselves. Instead, they signal that they have output
+//! pending, and the mux builds and transmits their segments one at a time,
+//! copying the payload into its own staging buffer. A single alarm drives
+//! the timers of all sockets with a granularity of `TICK_MS`, and only runs
+//! while some socket has a timer pending.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let tcp_mux = MuxTcp::new(ip_send, alarm, interface_list, tx_buffer, net_cap);
+//! ip_send.set_client(tcp_mux);
+//! ip_receive.set_tcp_client(tcp_mux);
+//! alarm.set_alarm_client(tcp_mux);
+//!
+//! tcp_mux.add_socket(socket);
+//! socket.set_client(client);
+//! socket.connect(0, remote_addr, 80);
+//! ```
+
+use crate::net::ipv6::ip_utils::{ip6_nh, IPAddr};
+use crate::net::ipv6::ipv6_recv::IP6RecvClient;
+use crate::net::ipv6::ipv6_send::{IP6SendClient, IP6Sender};
+use crate::net::ipv6::{IP6Header, TransportHeader};
+use crate::net::network_capabilities::NetworkCapability;
+use crate::net::tcp::tcp_socket::{TcpSocket, TcpStack, TICK_MS};
+use crate::net::tcp::{parse_mss_option, tcp_flags, TCPHeader};
+
+use core::cell::Cell;
+
+use kernel::collections::list::List;
+use kernel::debug;
+use kernel::hil::time::{self, ConvertTicks};
+use kernel::utilities::cells::{MapCell, OptionalCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::ErrorCode;
+
+/// First port of the dynamic port range (RFC 6335).
+const EPHEMERAL_PORT_START: u16 = 49152;
+
+pub struct MuxTcp<'a, A: time::Alarm<'a>> {
+    sockets: List<'a, TcpSocket<'a>>,
+    ip_sender: &'a dyn IP6Sender<'a>,
+    alarm: &'a A,
+    interface_list: &'static [IPAddr],
+    tx_buffer: MapCell<SubSliceMut<'static, u8>>,
+    busy: Cell<bool>,
+    // Reset segment to send that is not associated with a socket.
+    pending_reset: OptionalCell<(IPAddr, TCPHeader, &'static NetworkCapability)>,
+    net_cap: &'static NetworkCapability,
+    next_port: Cell<u16>,
+    iss_offset: Cell<u32>,
+}
+
+impl<'a, A: time::Alarm<'a>> MuxTcp<'a, A> {
+    pub fn new(
+        ip_sender: &'a dyn IP6Sender<'a>,
+        alarm: &'a A,
+        interface_list: &'static [IPAddr],
+        tx_buffer: SubSliceMut<'static, u8>,
+        net_cap: &'static NetworkCapability,
+    ) -> MuxTcp<'a, A> {
+        MuxTcp {
+            sockets: List::new(),
+            ip_sender,
+            alarm,
+            interface_list,
+            tx_buffer: MapCell::new(tx_buffer),
+            busy: Cell::new(false),
+            pending_reset: OptionalCell::empty(),
+            net_cap,
+            next_port: Cell::new(EPHEMERAL_PORT_START),
+            iss_offset: Cell::new(0),
+        }
+    }
+
+    /// Registers a socket with the mux. Sockets cannot be used before they
+    /// have been added.
+    pub fn add_socket(&'a self, socket: &'a TcpSocket<'a>) {
+        socket.set_stack(self);
+        self.sockets.push_tail(socket);
+    }
+
+    /// Transmits the next pending segment, if the IP sender is idle.
+    fn transmit_next(&self) {
+        if self.busy.get() {
+            return;
+        }
+        let mut buf = match self.tx_buffer.take() {
+            Some(buf) => buf,
+            None => return,
+        };
+
+        let mut segment = self.pending_reset.take().map(|(dst, mut header, net_cap)| {
+            header.set_len(header.get_hdr_size() as u16);
+            (dst, header, 0, net_cap)
+        });
+        if segment.is_none() {
+            for socket in self.sockets.iter() {
+                if socket.has_output() {
+                    segment = socket
+                        .next_segment(buf.as_slice())
+                        .map(|(dst, header, len)| (dst, header, len, socket.get_net_cap()));
+                    if segment.is_some() {
+                        break;
+                    }
+                }
+            }
+        }
+
+        match segment {
+            Some((dst, header, len, net_cap)) => {
+                buf.slice(0..len);
+                self.busy.set(true);
+                let result =
+                    self.ip_sender
+                        .send_to(dst, TransportHeader::TCP(header), &buf, net_cap);
+                buf.reset();
+                self.tx_buffer.replace(buf);
+                if result != Ok(()) {
+                    // Lost segments are recovered by retransmission.
+                    debug!("[TCP] IP send_to failed: {:?}", result);
+                    self.busy.set(false);
+                }
+            }
+            None => {
+                self.tx_buffer.replace(buf);
+            }
+        }
+    }
+
+    /// Starts the tick alarm if some socket has a timer pending.
+    fn update_timer(&self) {
+        if !self.alarm.is_armed() && self.sockets.iter().any(|s| s.timer_active()) {
+            self.alarm
+                .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(TICK_MS));
+        }
+    }
+
+    fn is_local(&self, addr: IPAddr) -> bool {
+        self.interface_list.iter().any(|&iface| iface == addr)
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> TcpStack for MuxTcp<'a, A> {
+    fn port_in_use(&self, socket: &TcpSocket, port: u16) -> bool {
+        self.sockets.iter().any(|other| {
+            !core::ptr::addr_eq(other, socket)
+                && !other.get_state().is_idle()
+                && other.get_local_port() == port
+        })
+    }
+
+    fn ephemeral_port(&self) -> u16 {
+        loop {
+            let port = self.next_port.get();
+            self.next_port.set(if port == u16::MAX {
+                EPHEMERAL_PORT_START
+            } else {
+                port + 1
+            });
+            if !self.sockets.iter().any(|s| s.get_local_port() == port) {
+                return port;
+            }
+        }
+    }
+
+    fn initial_seq_num(&self) -> u32 {
+        // RFC 9293 requires the ISN to be driven by a clock ticking every
+        // 4 microseconds. Successive connections are additionally spaced by
+        // a fixed offset so they never reuse sequence space.
+        let clock = self.alarm.ticks_to_us(self.alarm.now()) / 4;
+        let offset = self.iss_offset.get().wrapping_add(64000);
+        self.iss_offset.set(offset);
+        clock.wrapping_add(offset)
+    }
+
+    fn output_pending(&self) {
+        self.transmit_next();
+        self.update_timer();
+    }
+
+    fn send_reset(&self, dst: IPAddr, header: TCPHeader, net_cap: &'static NetworkCapability) {
+        // Resets are best-effort: if one is already queued, drop this one.
+        if self.pending_reset.is_none() {
+            self.pending_reset.set((dst, header, net_cap));
+        }
+        self.transmit_next();
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> IP6RecvClient for MuxTcp<'a, A> {
+    fn receive(&self, ip_header: IP6Header, payload: &[u8]) {
+        // If the mux is the regular client of the IPv6 layer, it is passed
+        // every received packet, so ignore those carrying other transport
+        // protocols.
+        if ip_header.get_next_header() != ip6_nh::TCP || !self.is_local(ip_header.get_dst_addr()) {
+            return;
+        }
+        let (offset, tcp_header) = match TCPHeader::decode(payload).done() {
+            Some(decoded) => decoded,
+            None => {
+                debug!("[TCP] Error: failed to decode header");
+                return;
+            }
+        };
+        let src_addr = ip_header.get_src_addr();
+        let src_port = tcp_header.get_src_port();
+        let dst_port = tcp_header.get_dst_port();
+        let data = &payload[offset..];
+        let mss = if tcp_header.has_flags(tcp_flags::SYN) {
+            parse_mss_option(payload)
+        } else {
+            None
+        };
+
+        // Connections take precedence over listening sockets.
+        let socket = self
+            .sockets
+            .iter()
+            .find(|s| s.matches(src_addr, src_port, dst_port, false))
+            .or_else(|| {
+                self.sockets
+                    .iter()
+                    .find(|s| s.matches(src_addr, src_port, dst_port, true))
+            });
+        match socket {
+            Some(socket) => socket.segment_arrives(src_addr, &tcp_header, data, mss),
+            None => {
+                if !tcp_header.has_flags(tcp_flags::RST) {
+                    let reply = TcpSocket::reset_reply(&tcp_header, data.len());
+                    self.send_reset(src_addr, reply, self.net_cap);
+                }
+            }
+        }
+        self.transmit_next();
+        self.update_timer();
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> IP6SendClient for MuxTcp<'a, A> {
+    fn send_done(&self, result: Result<(), ErrorCode>) {
+        if result != Ok(()) {
+            debug!("[TCP] Error: segment transmission failed: {:?}", result);
+        }
+        self.busy.set(false);
+        self.transmit_next();
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> time::AlarmClient for MuxTcp<'a, A> {
+    fn alarm(&self) {
+        for socket in self.sockets.iter() {
+            socket.tick(TICK_MS);
+        }
+        self.transmit_next();
+        self.update_timer();
+    }
+}
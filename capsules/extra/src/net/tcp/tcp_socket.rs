@@ -0,0 +1,1576 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Definition and implementation of a TCP socket.
+//!
+//! A [TcpSocket](struct.TcpSocket.html) holds the transmission control
+//! block of a single connection: the connection state, the send and receive
+//! sequence variables, the retransmission timer and the send and receive
+//! buffers. Sockets are registered with a [MuxTcp](../tcp_mux/struct.MuxTcp.html),
+//! which demultiplexes received segments to them, transmits the segments
+//! they produce and drives their timers.
+//!
+//! The state machine follows RFC 9293. In order to keep the implementation
+//! small, it makes the following simplifications, all of which are permitted
+//! by the standard:
+//!
+//! - Out-of-order segments are dropped (and a duplicate ACK is sent), so the
+//!   peer retransmits them once the missing data arrives.
+//! - ACKs are sent immediately rather than delayed.
+//! - Retransmission uses go-back-N from the oldest unacknowledged byte.
+//! - No TCP options are sent, so the peer uses the default IPv6 MSS of
+//!   1220 bytes. The MSS option sent by the peer is honored.
+//! - A listening socket handles a single connection. Once that connection
+//!   has closed the socket returns to `Closed`, and `listen` must be called
+//!   again to accept another connection.
+//!
+//! The retransmission timeout is computed as described in RFC 6298, and RST
+//! and SYN segments are validated as described in RFC 5961.
+
+use crate::net::ipv6::ip_utils::IPAddr;
+use crate::net::network_capabilities::NetworkCapability;
+use crate::net::tcp::tcp_flags;
+use crate::net::tcp::TCPHeader;
+
+use core::cell::Cell;
+use core::cmp;
+
+use kernel::collections::list::{ListLink, ListNode};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// Granularity of the TCP timers, in milliseconds.
+pub const TICK_MS: u32 = 100;
+
+/// Default send MSS for IPv6 when the peer does not send the MSS option.
+const DEFAULT_MSS: u16 = 1220;
+/// Initial retransmission timeout (RFC 6298, Section 2.1).
+const INITIAL_RTO_MS: u32 = 1000;
+/// Lower bound for the retransmission timeout (RFC 6298, Section 2.4).
+const MIN_RTO_MS: u32 = 1000;
+/// Upper bound for the retransmission timeout.
+const MAX_RTO_MS: u32 = 60000;
+/// Number of consecutive retransmissions before the connection is aborted.
+const MAX_RETRIES: u8 = 8;
+/// Time spent in TIME-WAIT (2 * MSL, with an MSL of 30 seconds).
+const TIME_WAIT_MS: u32 = 60000;
+
+/// Connection states, as defined in RFC 9293, Section 3.3.2.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TcpState {
+    Closed = 0,
+    Listen = 1,
+    SynSent = 2,
+    SynReceived = 3,
+    Established = 4,
+    FinWait1 = 5,
+    FinWait2 = 6,
+    CloseWait = 7,
+    Closing = 8,
+    LastAck = 9,
+    TimeWait = 10,
+}
+
+impl TcpState {
+    /// Returns true if the socket can be used for a new connection.
+    pub fn is_idle(&self) -> bool {
+        matches!(self, TcpState::Closed | TcpState::TimeWait)
+    }
+
+    /// Returns true if the handshake has completed and the connection has
+    /// not yet been fully closed.
+    fn is_synchronized(&self) -> bool {
+        !matches!(
+            self,
+            TcpState::Closed | TcpState::Listen | TcpState::SynSent | TcpState::SynReceived
+        )
+    }
+}
+
+// Sequence number comparisons, modulo 2^32 (RFC 9293, Section 3.4).
+fn seq_lt(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+fn seq_le(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) <= 0
+}
+
+fn seq_gt(a: u32, b: u32) -> bool {
+    seq_lt(b, a)
+}
+
+fn seq_ge(a: u32, b: u32) -> bool {
+    seq_le(b, a)
+}
+
+/// Client interface for a `TcpSocket`.
+///
+/// All callbacks pass the socket that generated the event, so that a single
+/// client (e.g. the userspace driver) can manage several sockets.
+pub trait TcpClient {
+    /// The three-way handshake completed (`Ok(())`), or an active open
+    /// failed because the peer refused the connection (`FAIL`) or did not
+    /// answer (`NOACK`). For a listening socket this signals that a
+    /// connection was accepted.
+    fn connected(&self, socket: &TcpSocket, result: Result<(), ErrorCode>);
+
+    /// New data was received. `available` is the total number of bytes that
+    /// can now be read with `TcpSocket::recv`.
+    fn received(&self, socket: &TcpSocket, available: usize);
+
+    /// `acked` bytes of previously queued data were acknowledged by the
+    /// peer, freeing space in the send buffer.
+    fn sent(&self, socket: &TcpSocket, acked: usize);
+
+    /// The peer closed its side of the connection. No more data will be
+    /// received, but data can still be sent until `close` is called.
+    fn remote_closed(&self, socket: &TcpSocket);
+
+    /// The connection is finished. `Ok(())` indicates an orderly close,
+    /// `FAIL` that the peer reset the connection and `NOACK` that the peer
+    /// stopped acknowledging data.
+    fn closed(&self, socket: &TcpSocket, result: Result<(), ErrorCode>);
+}
+
+/// Services a `TcpSocket` requires from the layer that multiplexes sockets
+/// onto the IP layer.
+pub trait TcpStack {
+    /// Returns true if `port` is used as a local port by any registered
+    /// socket other than `socket`.
+    fn port_in_use(&self, socket: &TcpSocket, port: u16) -> bool;
+
+    /// Returns an unused local port in the dynamic port range.
+    fn ephemeral_port(&self) -> u16;
+
+    /// Returns an initial sequence number for a new connection.
+    fn initial_seq_num(&self) -> u32;
+
+    /// Signals that a socket has a segment to transmit or a timer running.
+    fn output_pending(&self);
+
+    /// Sends a reset segment that does not belong to any socket state.
+    fn send_reset(&self, dst: IPAddr, header: TCPHeader, net_cap: &'static NetworkCapability);
+}
+
+/// A single TCP connection endpoint.
+pub struct TcpSocket<'a> {
+    stack: OptionalCell<&'a dyn TcpStack>,
+    client: OptionalCell<&'a dyn TcpClient>,
+    next: ListLink<'a, TcpSocket<'a>>,
+    net_cap: &'static NetworkCapability,
+
+    state: Cell<TcpState>,
+    passive: Cell<bool>,
+    local_port: Cell<u16>,
+    remote_addr: Cell<IPAddr>,
+    remote_port: Cell<u16>,
+
+    // Send sequence variables
+    iss: Cell<u32>,
+    snd_una: Cell<u32>,
+    snd_nxt: Cell<u32>,
+    snd_max: Cell<u32>,
+    snd_wnd: Cell<u32>,
+    snd_wl1: Cell<u32>,
+    snd_wl2: Cell<u32>,
+    snd_mss: Cell<u16>,
+
+    // Receive sequence variables
+    rcv_nxt: Cell<u32>,
+
+    // Data queued by the client, starting at sequence number `snd_una`.
+    tx_buf: TakeCell<'static, [u8]>,
+    tx_len: Cell<usize>,
+    // The client called `close`; a FIN follows the last byte in `tx_buf`.
+    fin_queued: Cell<bool>,
+    // Received in-order data not yet read by the client.
+    rx_buf: TakeCell<'static, [u8]>,
+    rx_len: Cell<usize>,
+
+    ack_pending: Cell<bool>,
+    probe_pending: Cell<bool>,
+
+    // Retransmission, persist and TIME-WAIT timer, in milliseconds. Zero
+    // means the timer is stopped.
+    timer: Cell<u32>,
+    retries: Cell<u8>,
+    rto: Cell<u32>,
+    srtt: Cell<u32>,
+    rttvar: Cell<u32>,
+    // Sequence number of the segment being timed, and the time elapsed
+    // since it was sent.
+    rtt_seq: OptionalCell<u32>,
+    rtt_elapsed: Cell<u32>,
+}
+
+impl<'a> ListNode<'a, TcpSocket<'a>> for TcpSocket<'a> {
+    fn next(&'a self) -> &'a ListLink<'a, TcpSocket<'a>> {
+        &self.next
+    }
+}
+
+impl<'a> TcpSocket<'a> {
+    pub fn new(
+        tx_buf: &'static mut [u8],
+        rx_buf: &'static mut [u8],
+        net_cap: &'static NetworkCapability,
+    ) -> TcpSocket<'a> {
+        TcpSocket {
+            stack: OptionalCell::empty(),
+            client: OptionalCell::empty(),
+            next: ListLink::empty(),
+            net_cap,
+            state: Cell::new(TcpState::Closed),
+            passive: Cell::new(false),
+            local_port: Cell::new(0),
+            remote_addr: Cell::new(IPAddr::new()),
+            remote_port: Cell::new(0),
+            iss: Cell::new(0),
+            snd_una: Cell::new(0),
+            snd_nxt: Cell::new(0),
+            snd_max: Cell::new(0),
+            snd_wnd: Cell::new(0),
+            snd_wl1: Cell::new(0),
+            snd_wl2: Cell::new(0),
+            snd_mss: Cell::new(DEFAULT_MSS),
+            rcv_nxt: Cell::new(0),
+            tx_buf: TakeCell::new(tx_buf),
+            tx_len: Cell::new(0),
+            fin_queued: Cell::new(false),
+            rx_buf: TakeCell::new(rx_buf),
+            rx_len: Cell::new(0),
+            ack_pending: Cell::new(false),
+            probe_pending: Cell::new(false),
+            timer: Cell::new(0),
+            retries: Cell::new(0),
+            rto: Cell::new(INITIAL_RTO_MS),
+            srtt: Cell::new(0),
+            rttvar: Cell::new(0),
+            rtt_seq: OptionalCell::empty(),
+            rtt_elapsed: Cell::new(0),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn TcpClient) {
+        self.client.set(client);
+    }
+
+    pub(crate) fn set_stack(&self, stack: &'a dyn TcpStack) {
+        self.stack.set(stack);
+    }
+
+    pub fn get_state(&self) -> TcpState {
+        self.state.get()
+    }
+
+    pub fn get_local_port(&self) -> u16 {
+        self.local_port.get()
+    }
+
+    pub fn get_remote_addr(&self) -> IPAddr {
+        self.remote_addr.get()
+    }
+
+    pub fn get_remote_port(&self) -> u16 {
+        self.remote_port.get()
+    }
+
+    pub fn get_net_cap(&self) -> &'static NetworkCapability {
+        self.net_cap
+    }
+
+    /// Number of received bytes that can be read with `recv`.
+    pub fn available(&self) -> usize {
+        self.rx_len.get()
+    }
+
+    /// Number of bytes that can currently be queued with `send`.
+    pub fn send_capacity(&self) -> usize {
+        self.tx_buf.map_or(0, |buf| buf.len()) - self.tx_len.get()
+    }
+
+    /// Starts an active open to `remote_addr`:`remote_port`. If `local_port`
+    /// is zero, an ephemeral port is chosen. Completion is signaled through
+    /// `TcpClient::connected`.
+    pub fn connect(
+        &self,
+        local_port: u16,
+        remote_addr: IPAddr,
+        remote_port: u16,
+    ) -> Result<(), ErrorCode> {
+        if !self.state.get().is_idle() {
+            return Err(ErrorCode::BUSY);
+        }
+        if remote_port == 0 || remote_addr.is_unspecified() || remote_addr.is_multicast() {
+            return Err(ErrorCode::INVAL);
+        }
+        let stack = self.stack.get().ok_or(ErrorCode::OFF)?;
+        let local_port = if local_port == 0 {
+            stack.ephemeral_port()
+        } else if stack.port_in_use(self, local_port) {
+            return Err(ErrorCode::BUSY);
+        } else {
+            local_port
+        };
+
+        self.reset_connection();
+        self.passive.set(false);
+        self.local_port.set(local_port);
+        self.remote_addr.set(remote_addr);
+        self.remote_port.set(remote_port);
+        let iss = stack.initial_seq_num();
+        self.iss.set(iss);
+        self.snd_una.set(iss);
+        self.snd_nxt.set(iss);
+        self.snd_max.set(iss);
+        self.state.set(TcpState::SynSent);
+        stack.output_pending();
+        Ok(())
+    }
+
+    /// Waits for a connection on `local_port`. Completion is signaled
+    /// through `TcpClient::connected`.
+    pub fn listen(&self, local_port: u16) -> Result<(), ErrorCode> {
+        if !self.state.get().is_idle() {
+            return Err(ErrorCode::BUSY);
+        }
+        if local_port == 0 {
+            return Err(ErrorCode::INVAL);
+        }
+        let stack = self.stack.get().ok_or(ErrorCode::OFF)?;
+        if stack.port_in_use(self, local_port) {
+            return Err(ErrorCode::BUSY);
+        }
+
+        self.reset_connection();
+        self.passive.set(true);
+        self.local_port.set(local_port);
+        self.remote_addr.set(IPAddr::new());
+        self.remote_port.set(0);
+        self.state.set(TcpState::Listen);
+        Ok(())
+    }
+
+    /// Queues data for transmission, returning the number of bytes copied
+    /// into the send buffer. Completion is signaled through
+    /// `TcpClient::sent` once the peer acknowledges the data.
+    pub fn send(&self, data: &[u8]) -> Result<usize, ErrorCode> {
+        self.send_with(data.len(), |buf| buf.copy_from_slice(&data[..buf.len()]))
+    }
+
+    /// Like `send`, but lets the caller copy the data itself. `fill` is
+    /// passed the part of the send buffer to copy into, which holds at most
+    /// `len` bytes.
+    pub fn send_with<F: FnOnce(&mut [u8])>(&self, len: usize, fill: F) -> Result<usize, ErrorCode> {
+        match self.state.get() {
+            TcpState::SynSent
+            | TcpState::SynReceived
+            | TcpState::Established
+            | TcpState::CloseWait => {}
+            _ => return Err(ErrorCode::OFF),
+        }
+        if self.fin_queued.get() {
+            return Err(ErrorCode::OFF);
+        }
+        let tx_len = self.tx_len.get();
+        let copied = self.tx_buf.map_or(0, |buf| {
+            let copied = cmp::min(len, buf.len() - tx_len);
+            fill(&mut buf[tx_len..tx_len + copied]);
+            copied
+        });
+        if copied == 0 && len > 0 {
+            return Err(ErrorCode::NOMEM);
+        }
+        self.tx_len.set(tx_len + copied);
+        self.stack.map(|stack| stack.output_pending());
+        Ok(copied)
+    }
+
+    /// Copies received data into `buf`, returning the number of bytes read.
+    pub fn recv(&self, buf: &mut [u8]) -> usize {
+        self.recv_with(buf.len(), |data| buf[..data.len()].copy_from_slice(data))
+    }
+
+    /// Like `recv`, but lets the caller copy the data itself. `drain` is
+    /// passed at most `len` bytes of received data, which are then removed
+    /// from the receive buffer.
+    pub fn recv_with<F: FnOnce(&[u8])>(&self, len: usize, drain: F) -> usize {
+        let old_window = self.receive_window();
+        let rx_len = self.rx_len.get();
+        let read = self.rx_buf.map_or(0, |rx_buf| {
+            let read = cmp::min(len, rx_len);
+            drain(&rx_buf[..read]);
+            rx_buf.copy_within(read..rx_len, 0);
+            read
+        });
+        self.rx_len.set(rx_len - read);
+
+        // Send a window update if the window was nearly closed and reading
+        // reopened it substantially, avoiding silly window syndrome.
+        let capacity = self.rx_buf.map_or(0, |rx_buf| rx_buf.len());
+        if read > 0
+            && self.state.get().is_synchronized()
+            && old_window < cmp::min(capacity / 2, self.snd_mss.get() as usize)
+            && self.receive_window() >= capacity / 2
+        {
+            self.ack_pending.set(true);
+            self.stack.map(|stack| stack.output_pending());
+        }
+        read
+    }
+
+    /// Closes the sending side of the connection. Queued data is still
+    /// delivered before the FIN. `TcpClient::closed` is called once the
+    /// connection is finished.
+    pub fn close(&self) -> Result<(), ErrorCode> {
+        match self.state.get() {
+            TcpState::Listen | TcpState::SynSent => {
+                self.enter_closed();
+                Ok(())
+            }
+            TcpState::SynReceived => {
+                // The FIN is sent once the handshake completes.
+                self.fin_queued.set(true);
+                Ok(())
+            }
+            TcpState::Established => {
+                self.fin_queued.set(true);
+                self.state.set(TcpState::FinWait1);
+                self.stack.map(|stack| stack.output_pending());
+                Ok(())
+            }
+            TcpState::CloseWait => {
+                self.fin_queued.set(true);
+                self.state.set(TcpState::LastAck);
+                self.stack.map(|stack| stack.output_pending());
+                Ok(())
+            }
+            _ => Err(ErrorCode::ALREADY),
+        }
+    }
+
+    /// Aborts the connection, sending a reset to the peer if a connection
+    /// was established. Pending data is discarded and no callback is issued.
+    pub fn abort(&self) {
+        match self.state.get() {
+            TcpState::SynReceived
+            | TcpState::Established
+            | TcpState::FinWait1
+            | TcpState::FinWait2
+            | TcpState::CloseWait => {
+                let mut header = self.make_header();
+                header.set_seq_num(self.snd_nxt.get());
+                header.set_flags(tcp_flags::RST);
+                self.stack
+                    .map(|stack| stack.send_reset(self.remote_addr.get(), header, self.net_cap));
+            }
+            _ => {}
+        }
+        self.enter_closed();
+    }
+
+    fn reset_connection(&self) {
+        self.tx_len.set(0);
+        self.rx_len.set(0);
+        self.fin_queued.set(false);
+        self.ack_pending.set(false);
+        self.probe_pending.set(false);
+        self.timer.set(0);
+        self.retries.set(0);
+        self.rto.set(INITIAL_RTO_MS);
+        self.srtt.set(0);
+        self.rttvar.set(0);
+        self.rtt_seq.clear();
+        self.snd_wnd.set(0);
+        self.snd_mss.set(DEFAULT_MSS);
+    }
+
+    fn enter_closed(&self) {
+        self.state.set(TcpState::Closed);
+        self.timer.set(0);
+        self.ack_pending.set(false);
+        self.probe_pending.set(false);
+    }
+
+    fn enter_time_wait(&self) {
+        self.state.set(TcpState::TimeWait);
+        self.timer.set(TIME_WAIT_MS);
+        self.client.map(|client| client.closed(self, Ok(())));
+    }
+
+    /// Closes the socket after a reset or timeout and notifies the client.
+    fn fail(&self, error: ErrorCode) {
+        let state = self.state.get();
+        self.enter_closed();
+        match state {
+            TcpState::SynSent => self.client.map(|client| client.connected(self, Err(error))),
+            TcpState::SynReceived if !self.passive.get() => {
+                self.client.map(|client| client.connected(self, Err(error)))
+            }
+            TcpState::SynReceived => {
+                // A passive open that fails returns to listening.
+                self.state.set(TcpState::Listen);
+                None
+            }
+            _ => self.client.map(|client| client.closed(self, Err(error))),
+        };
+    }
+
+    fn make_header(&self) -> TCPHeader {
+        let mut header = TCPHeader::new();
+        header.set_src_port(self.local_port.get());
+        header.set_dst_port(self.remote_port.get());
+        header
+    }
+
+    fn receive_window(&self) -> usize {
+        self.rx_buf.map_or(0, |rx_buf| rx_buf.len()) - self.rx_len.get()
+    }
+
+    /// Number of bytes of data and FIN sent but not acknowledged.
+    fn in_flight(&self) -> usize {
+        self.snd_nxt.get().wrapping_sub(self.snd_una.get()) as usize
+    }
+
+    /// Returns true if the connection is in a state where our FIN has been
+    /// queued but not yet acknowledged.
+    fn fin_outstanding(&self) -> bool {
+        self.fin_queued.get()
+            && matches!(
+                self.state.get(),
+                TcpState::FinWait1 | TcpState::Closing | TcpState::LastAck
+            )
+    }
+
+    fn start_timer(&self) {
+        if self.timer.get() == 0 {
+            self.timer.set(self.rto.get());
+        }
+    }
+
+    /// Returns true if the socket has a segment to transmit.
+    pub(crate) fn has_output(&self) -> bool {
+        match self.state.get() {
+            TcpState::Closed | TcpState::Listen | TcpState::TimeWait => self.ack_pending.get(),
+            TcpState::SynSent | TcpState::SynReceived => {
+                self.snd_nxt.get() == self.iss.get() || self.ack_pending.get()
+            }
+            _ => {
+                if self.ack_pending.get() || self.probe_pending.get() {
+                    return true;
+                }
+                let in_flight = self.in_flight();
+                let tx_len = self.tx_len.get();
+                if in_flight < tx_len {
+                    in_flight < self.snd_wnd.get() as usize
+                } else {
+                    self.fin_outstanding() && in_flight == tx_len
+                }
+            }
+        }
+    }
+
+    /// Returns true if the socket needs the timer to be running.
+    pub(crate) fn timer_active(&self) -> bool {
+        self.timer.get() != 0
+    }
+
+    /// Builds the next segment to transmit. The segment payload is copied
+    /// into `payload`, and the destination address, header and payload
+    /// length are returned.
+    pub(crate) fn next_segment(&self, payload: &mut [u8]) -> Option<(IPAddr, TCPHeader, usize)> {
+        let state = self.state.get();
+        let mut header = self.make_header();
+        header.set_window(cmp::min(self.receive_window(), u16::MAX as usize) as u16);
+        let mut len = 0;
+
+        match state {
+            TcpState::Closed | TcpState::Listen => {
+                self.ack_pending.set(false);
+                return None;
+            }
+            TcpState::SynSent | TcpState::SynReceived => {
+                if self.snd_nxt.get() == self.iss.get() {
+                    header.set_seq_num(self.iss.get());
+                    if state == TcpState::SynSent {
+                        header.set_flags(tcp_flags::SYN);
+                    } else {
+                        header.set_ack_num(self.rcv_nxt.get());
+                        header.set_flags(tcp_flags::SYN | tcp_flags::ACK);
+                    }
+                    self.snd_nxt.set(self.iss.get().wrapping_add(1));
+                } else if self.ack_pending.get() {
+                    // Simultaneous open or a challenge ACK before the
+                    // handshake completed.
+                    header.set_seq_num(self.snd_nxt.get());
+                    header.set_ack_num(self.rcv_nxt.get());
+                    header.set_flags(tcp_flags::ACK);
+                } else {
+                    return None;
+                }
+            }
+            _ => {
+                let mut flags = tcp_flags::ACK;
+                let in_flight = self.in_flight();
+                let tx_len = self.tx_len.get();
+                header.set_seq_num(self.snd_nxt.get());
+                header.set_ack_num(self.rcv_nxt.get());
+
+                if state != TcpState::TimeWait && in_flight < tx_len {
+                    let unsent = tx_len - in_flight;
+                    let window = (self.snd_wnd.get() as usize).saturating_sub(in_flight);
+                    let window = if window == 0 && self.probe_pending.get() {
+                        1
+                    } else {
+                        window
+                    };
+                    len = cmp::min(
+                        cmp::min(unsent, window),
+                        cmp::min(self.snd_mss.get() as usize, payload.len()),
+                    );
+                    self.tx_buf.map(|tx_buf| {
+                        payload[..len].copy_from_slice(&tx_buf[in_flight..in_flight + len]);
+                    });
+                    if len > 0 && len == unsent {
+                        flags |= tcp_flags::PSH;
+                    }
+                }
+                if self.fin_outstanding() && in_flight + len == tx_len {
+                    flags |= tcp_flags::FIN;
+                }
+                if flags == tcp_flags::ACK && len == 0 && !self.ack_pending.get() {
+                    self.probe_pending.set(false);
+                    return None;
+                }
+                header.set_flags(flags);
+                let consumed = len + usize::from(flags & tcp_flags::FIN != 0);
+                self.snd_nxt
+                    .set(self.snd_nxt.get().wrapping_add(consumed as u32));
+            }
+        }
+        self.ack_pending.set(false);
+        self.probe_pending.set(false);
+
+        let seq = header.get_seq_num();
+        if seq_gt(self.snd_nxt.get(), seq) {
+            // The segment consumes sequence space, so it must be
+            // retransmitted if it is not acknowledged.
+            if seq_ge(seq, self.snd_max.get()) && self.rtt_seq.is_none() {
+                self.rtt_seq.set(seq);
+                self.rtt_elapsed.set(0);
+            }
+            if seq_gt(self.snd_nxt.get(), self.snd_max.get()) {
+                self.snd_max.set(self.snd_nxt.get());
+            }
+            self.start_timer();
+        } else if len == 0 && self.tx_len.get() > self.in_flight() && self.snd_wnd.get() == 0 {
+            // The peer closed its window; start the persist timer.
+            self.start_timer();
+        }
+        header.set_len((header.get_hdr_size() + len) as u16);
+        Some((self.remote_addr.get(), header, len))
+    }
+
+    /// Advances the socket timer by `ms` milliseconds, handling
+    /// retransmission, persist and TIME-WAIT timeouts.
+    pub(crate) fn tick(&self, ms: u32) {
+        if self.rtt_seq.is_some() {
+            self.rtt_elapsed
+                .set(self.rtt_elapsed.get().saturating_add(ms));
+        }
+        let timer = self.timer.get();
+        if timer == 0 {
+            return;
+        }
+        if timer > ms {
+            self.timer.set(timer - ms);
+            return;
+        }
+        self.timer.set(0);
+
+        match self.state.get() {
+            TcpState::Closed | TcpState::Listen => {}
+            TcpState::TimeWait => self.enter_closed(),
+            state => {
+                // Karn's algorithm: do not time retransmitted segments.
+                self.rtt_seq.clear();
+                self.retries.set(self.retries.get() + 1);
+                if self.retries.get() > MAX_RETRIES {
+                    if state.is_synchronized() || state == TcpState::SynReceived {
+                        let mut header = self.make_header();
+                        header.set_seq_num(self.snd_nxt.get());
+                        header.set_flags(tcp_flags::RST);
+                        self.stack.map(|stack| {
+                            stack.send_reset(self.remote_addr.get(), header, self.net_cap)
+                        });
+                    }
+                    self.fail(ErrorCode::NOACK);
+                    return;
+                }
+                self.rto.set(cmp::min(self.rto.get() * 2, MAX_RTO_MS));
+
+                if self.in_flight() == 0 && self.tx_len.get() > 0 && self.snd_wnd.get() == 0 {
+                    // Persist timer: probe the zero window.
+                    self.probe_pending.set(true);
+                } else if state == TcpState::SynSent || state == TcpState::SynReceived {
+                    self.snd_nxt.set(self.iss.get());
+                } else {
+                    self.snd_nxt.set(self.snd_una.get());
+                }
+                self.timer.set(self.rto.get());
+            }
+        }
+    }
+
+    /// Updates the retransmission timeout with a new round-trip time
+    /// measurement (RFC 6298, Section 2).
+    fn update_rto(&self, rtt: u32) {
+        if self.srtt.get() == 0 {
+            self.srtt.set(rtt);
+            self.rttvar.set(rtt / 2);
+        } else {
+            let srtt = self.srtt.get();
+            let delta = srtt.abs_diff(rtt);
+            self.rttvar.set((3 * self.rttvar.get() + delta) / 4);
+            self.srtt.set((7 * srtt + rtt) / 8);
+        }
+        let rto = self.srtt.get() + cmp::max(TICK_MS, 4 * self.rttvar.get());
+        self.rto.set(rto.clamp(MIN_RTO_MS, MAX_RTO_MS));
+    }
+
+    /// Builds the reset sent in response to an unacceptable segment
+    /// (RFC 9293, Section 3.10.7.1).
+    pub(crate) fn reset_reply(header: &TCPHeader, payload_len: usize) -> TCPHeader {
+        let mut reply = TCPHeader::new();
+        reply.set_src_port(header.get_dst_port());
+        reply.set_dst_port(header.get_src_port());
+        if header.has_flags(tcp_flags::ACK) {
+            reply.set_seq_num(header.get_ack_num());
+            reply.set_flags(tcp_flags::RST);
+        } else {
+            let seg_len = payload_len
+                + usize::from(header.has_flags(tcp_flags::SYN))
+                + usize::from(header.has_flags(tcp_flags::FIN));
+            reply.set_ack_num(header.get_seq_num().wrapping_add(seg_len as u32));
+            reply.set_flags(tcp_flags::RST | tcp_flags::ACK);
+        }
+        reply
+    }
+
+    /// Returns true if a segment from `src_addr`:`src_port` to `dst_port`
+    /// belongs to this socket. Listening sockets only match if
+    /// `include_listening` is set, so that established connections take
+    /// precedence.
+    pub(crate) fn matches(
+        &self,
+        src_addr: IPAddr,
+        src_port: u16,
+        dst_port: u16,
+        include_listening: bool,
+    ) -> bool {
+        match self.state.get() {
+            TcpState::Closed => false,
+            TcpState::Listen => include_listening && self.local_port.get() == dst_port,
+            _ => {
+                self.local_port.get() == dst_port
+                    && self.remote_port.get() == src_port
+                    && self.remote_addr.get() == src_addr
+            }
+        }
+    }
+
+    fn send_reset_reply(&self, src_addr: IPAddr, header: &TCPHeader, payload_len: usize) {
+        if !header.has_flags(tcp_flags::RST) {
+            let reply = TcpSocket::reset_reply(header, payload_len);
+            self.stack
+                .map(|stack| stack.send_reset(src_addr, reply, self.net_cap));
+        }
+    }
+
+    fn send_ack(&self) {
+        self.ack_pending.set(true);
+        self.stack.map(|stack| stack.output_pending());
+    }
+
+    /// Processes a segment received for this socket (RFC 9293,
+    /// Section 3.10.7).
+    pub(crate) fn segment_arrives(
+        &self,
+        src_addr: IPAddr,
+        header: &TCPHeader,
+        payload: &[u8],
+        mss: Option<u16>,
+    ) {
+        let seq = header.get_seq_num();
+        let ack = header.get_ack_num();
+        let is_ack = header.has_flags(tcp_flags::ACK);
+        let is_rst = header.has_flags(tcp_flags::RST);
+        let is_syn = header.has_flags(tcp_flags::SYN);
+        let is_fin = header.has_flags(tcp_flags::FIN);
+
+        match self.state.get() {
+            TcpState::Closed => {
+                self.send_reset_reply(src_addr, header, payload.len());
+                return;
+            }
+            TcpState::Listen => {
+                if is_rst {
+                    return;
+                }
+                if is_ack {
+                    self.send_reset_reply(src_addr, header, payload.len());
+                    return;
+                }
+                if is_syn {
+                    let iss = match self.stack.get() {
+                        Some(stack) => stack.initial_seq_num(),
+                        None => return,
+                    };
+                    self.remote_addr.set(src_addr);
+                    self.remote_port.set(header.get_src_port());
+                    self.rcv_nxt.set(seq.wrapping_add(1));
+                    self.iss.set(iss);
+                    self.snd_una.set(iss);
+                    self.snd_nxt.set(iss);
+                    self.snd_max.set(iss);
+                    self.snd_wnd.set(header.get_window() as u32);
+                    self.snd_wl1.set(seq);
+                    self.snd_wl2.set(iss);
+                    mss.map(|mss| self.snd_mss.set(cmp::min(mss, DEFAULT_MSS)));
+                    self.state.set(TcpState::SynReceived);
+                    self.stack.map(|stack| stack.output_pending());
+                }
+                return;
+            }
+            TcpState::SynSent => {
+                if is_ack && (seq_le(ack, self.iss.get()) || seq_gt(ack, self.snd_max.get())) {
+                    self.send_reset_reply(src_addr, header, payload.len());
+                    return;
+                }
+                if is_rst {
+                    if is_ack {
+                        self.fail(ErrorCode::FAIL);
+                    }
+                    return;
+                }
+                if is_syn {
+                    self.rcv_nxt.set(seq.wrapping_add(1));
+                    mss.map(|mss| self.snd_mss.set(cmp::min(mss, DEFAULT_MSS)));
+                    self.snd_wnd.set(header.get_window() as u32);
+                    self.snd_wl1.set(seq);
+                    self.snd_wl2.set(ack);
+                    if is_ack {
+                        self.snd_una.set(ack);
+                        self.rtt_seq
+                            .take()
+                            .map(|_| self.update_rto(self.rtt_elapsed.get()));
+                        self.established();
+                        self.client.map(|client| client.connected(self, Ok(())));
+                    } else {
+                        // Simultaneous open.
+                        self.state.set(TcpState::SynReceived);
+                        self.snd_nxt.set(self.iss.get());
+                        self.stack.map(|stack| stack.output_pending());
+                    }
+                }
+                return;
+            }
+            _ => {}
+        }
+
+        // Check that the segment is within the receive window.
+        let seg_len = payload.len() + usize::from(is_syn) + usize::from(is_fin);
+        let rcv_nxt = self.rcv_nxt.get();
+        let rcv_wnd = self.receive_window() as u32;
+        let in_window = |s: u32| seq_le(rcv_nxt, s) && seq_lt(s, rcv_nxt.wrapping_add(rcv_wnd));
+        let acceptable = match (seg_len, rcv_wnd) {
+            (0, 0) => seq == rcv_nxt,
+            (0, _) => in_window(seq),
+            (_, 0) => false,
+            (_, _) => in_window(seq) || in_window(seq.wrapping_add(seg_len as u32 - 1)),
+        };
+        if !acceptable {
+            if !is_rst {
+                self.send_ack();
+            }
+            return;
+        }
+
+        if is_rst {
+            if seq == rcv_nxt {
+                self.fail(ErrorCode::FAIL);
+            } else {
+                // Challenge ACK (RFC 5961, Section 3.2).
+                self.send_ack();
+            }
+            return;
+        }
+
+        if is_syn {
+            // Challenge ACK (RFC 5961, Section 4.2).
+            self.send_ack();
+            return;
+        }
+
+        if !is_ack {
+            return;
+        }
+
+        if self.state.get() == TcpState::SynReceived {
+            if seq_lt(self.snd_una.get(), ack) && seq_le(ack, self.snd_max.get()) {
+                self.snd_una.set(ack);
+                self.snd_wnd.set(header.get_window() as u32);
+                self.snd_wl1.set(seq);
+                self.snd_wl2.set(ack);
+                self.rtt_seq
+                    .take()
+                    .map(|_| self.update_rto(self.rtt_elapsed.get()));
+                self.established();
+                self.client.map(|client| client.connected(self, Ok(())));
+            } else {
+                self.send_reset_reply(src_addr, header, payload.len());
+                return;
+            }
+        } else if !self.process_ack(seq, ack, header.get_window()) {
+            return;
+        }
+
+        // Process the segment text.
+        let mut fin_seq = seq.wrapping_add(payload.len() as u32);
+        match self.state.get() {
+            TcpState::Established | TcpState::FinWait1 | TcpState::FinWait2
+                if !payload.is_empty() =>
+            {
+                if seq_gt(seq, rcv_nxt) {
+                    // Out of order; ask the peer to retransmit.
+                    self.send_ack();
+                    return;
+                }
+                let skip = rcv_nxt.wrapping_sub(seq) as usize;
+                let rx_len = self.rx_len.get();
+                let accepted = self.rx_buf.map_or(0, |rx_buf| {
+                    let data = payload.get(skip..).unwrap_or(&[]);
+                    let accepted = cmp::min(data.len(), rx_buf.len() - rx_len);
+                    rx_buf[rx_len..rx_len + accepted].copy_from_slice(&data[..accepted]);
+                    accepted
+                });
+                if skip + accepted < payload.len() {
+                    // Part of the segment did not fit; the FIN (if any) was
+                    // not reached.
+                    fin_seq = rcv_nxt.wrapping_add(accepted as u32).wrapping_add(1);
+                }
+                self.rx_len.set(rx_len + accepted);
+                self.rcv_nxt.set(rcv_nxt.wrapping_add(accepted as u32));
+                self.ack_pending.set(true);
+                if accepted > 0 {
+                    self.client
+                        .map(|client| client.received(self, self.rx_len.get()));
+                }
+            }
+            _ => {}
+        }
+
+        if is_fin && fin_seq == self.rcv_nxt.get() {
+            self.rcv_nxt.set(fin_seq.wrapping_add(1));
+            self.ack_pending.set(true);
+            match self.state.get() {
+                TcpState::Established => {
+                    self.state.set(TcpState::CloseWait);
+                    self.client.map(|client| client.remote_closed(self));
+                }
+                // Had our FIN been acknowledged, we would be in FIN-WAIT-2.
+                TcpState::FinWait1 => self.state.set(TcpState::Closing),
+                TcpState::FinWait2 => self.enter_time_wait(),
+                TcpState::TimeWait => self.timer.set(TIME_WAIT_MS),
+                _ => {}
+            }
+        }
+
+        if self.ack_pending.get() {
+            self.stack.map(|stack| stack.output_pending());
+        }
+    }
+
+    fn established(&self) {
+        self.state.set(if self.fin_queued.get() {
+            TcpState::FinWait1
+        } else {
+            TcpState::Established
+        });
+        self.timer.set(0);
+        self.retries.set(0);
+        if self.snd_nxt.get() != self.snd_una.get() {
+            self.snd_nxt.set(self.snd_una.get());
+        }
+        self.ack_pending.set(true);
+        self.stack.map(|stack| stack.output_pending());
+    }
+
+    /// Processes the acknowledgment field of a segment received in a
+    /// synchronized state. Returns false if processing of the segment should
+    /// stop.
+    fn process_ack(&self, seq: u32, ack: u32, window: u16) -> bool {
+        let snd_una = self.snd_una.get();
+        if seq_gt(ack, self.snd_max.get()) {
+            // Acknowledges data that was never sent.
+            self.send_ack();
+            return false;
+        }
+
+        if seq_gt(ack, snd_una) {
+            let fin_was_sent = self.fin_queued.get()
+                && self.snd_max.get().wrapping_sub(snd_una) as usize > self.tx_len.get();
+            let acked = ack.wrapping_sub(snd_una) as usize;
+            let tx_len = self.tx_len.get();
+            let data_acked = cmp::min(acked, tx_len);
+            self.tx_buf
+                .map(|tx_buf| tx_buf.copy_within(data_acked..tx_len, 0));
+            self.tx_len.set(tx_len - data_acked);
+            self.snd_una.set(ack);
+            if seq_lt(self.snd_nxt.get(), ack) {
+                self.snd_nxt.set(ack);
+            }
+            self.retries.set(0);
+            if let Some(rtt_seq) = self.rtt_seq.get() {
+                if seq_gt(ack, rtt_seq) {
+                    self.rtt_seq.clear();
+                    self.update_rto(self.rtt_elapsed.get());
+                }
+            }
+            self.timer.set(if ack == self.snd_max.get() {
+                0
+            } else {
+                self.rto.get()
+            });
+
+            if data_acked > 0 {
+                self.client.map(|client| client.sent(self, data_acked));
+            }
+
+            let fin_acked = fin_was_sent && acked > tx_len;
+            if fin_acked {
+                match self.state.get() {
+                    TcpState::FinWait1 => self.state.set(TcpState::FinWait2),
+                    TcpState::Closing => {
+                        self.enter_time_wait();
+                        return false;
+                    }
+                    TcpState::LastAck => {
+                        self.enter_closed();
+                        self.client.map(|client| client.closed(self, Ok(())));
+                        return false;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        // Update the send window (RFC 9293, Section 3.10.7.4).
+        if seq_lt(self.snd_wl1.get(), seq)
+            || (self.snd_wl1.get() == seq && seq_le(self.snd_wl2.get(), ack))
+        {
+            self.snd_wnd.set(window as u32);
+            self.snd_wl1.set(seq);
+            self.snd_wl2.set(ack);
+        }
+
+        if self.has_output() {
+            self.stack.map(|stack| stack.output_pending());
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use core::cell::RefCell;
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    const LOCAL_PORT: u16 = 4000;
+    const REMOTE_PORT: u16 = 5000;
+    const LOCAL_ISS: u32 = 1000;
+    const REMOTE_ISS: u32 = 7000;
+    const REMOTE_ADDR: IPAddr = IPAddr([0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+
+    struct FakeStack {
+        resets: RefCell<Vec<TCPHeader>>,
+    }
+
+    impl TcpStack for FakeStack {
+        fn port_in_use(&self, _socket: &TcpSocket, _port: u16) -> bool {
+            false
+        }
+
+        fn ephemeral_port(&self) -> u16 {
+            49152
+        }
+
+        fn initial_seq_num(&self) -> u32 {
+            LOCAL_ISS
+        }
+
+        fn output_pending(&self) {}
+
+        fn send_reset(
+            &self,
+            _dst: IPAddr,
+            header: TCPHeader,
+            _net_cap: &'static NetworkCapability,
+        ) {
+            self.resets.borrow_mut().push(header);
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        Connected(Result<(), ErrorCode>),
+        Received(usize),
+        Sent(usize),
+        RemoteClosed,
+        Closed(Result<(), ErrorCode>),
+    }
+
+    struct FakeClient {
+        events: RefCell<Vec<Event>>,
+    }
+
+    impl FakeClient {
+        fn take_events(&self) -> Vec<Event> {
+            self.events.take()
+        }
+    }
+
+    impl TcpClient for FakeClient {
+        fn connected(&self, _socket: &TcpSocket, result: Result<(), ErrorCode>) {
+            self.events.borrow_mut().push(Event::Connected(result));
+        }
+
+        fn received(&self, _socket: &TcpSocket, available: usize) {
+            self.events.borrow_mut().push(Event::Received(available));
+        }
+
+        fn sent(&self, _socket: &TcpSocket, acked: usize) {
+            self.events.borrow_mut().push(Event::Sent(acked));
+        }
+
+        fn remote_closed(&self, _socket: &TcpSocket) {
+            self.events.borrow_mut().push(Event::RemoteClosed);
+        }
+
+        fn closed(&self, _socket: &TcpSocket, result: Result<(), ErrorCode>) {
+            self.events.borrow_mut().push(Event::Closed(result));
+        }
+    }
+
+    struct Fixture {
+        stack: FakeStack,
+        client: FakeClient,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            Self {
+                stack: FakeStack {
+                    resets: RefCell::new(Vec::new()),
+                },
+                client: FakeClient {
+                    events: RefCell::new(Vec::new()),
+                },
+            }
+        }
+
+        fn socket(&self) -> TcpSocket<'_> {
+            let tx_buf = Box::leak(Box::new([0; 64]));
+            let rx_buf = Box::leak(Box::new([0; 64]));
+            let net_cap = Box::leak(Box::new(NetworkCapability::new_unrestricted()));
+            let socket = TcpSocket::new(tx_buf, rx_buf, net_cap);
+            socket.set_stack(&self.stack);
+            socket.set_client(&self.client);
+            socket
+        }
+
+        /// Returns a socket that completed an active open.
+        fn established(&self) -> TcpSocket<'_> {
+            let socket = self.socket();
+            socket
+                .connect(LOCAL_PORT, REMOTE_ADDR, REMOTE_PORT)
+                .unwrap();
+            transmit(&socket).unwrap();
+            arrive(
+                &socket,
+                tcp_flags::SYN | tcp_flags::ACK,
+                REMOTE_ISS,
+                LOCAL_ISS + 1,
+            );
+            transmit(&socket).unwrap();
+            assert_eq!(self.client.take_events(), [Event::Connected(Ok(()))]);
+            socket
+        }
+    }
+
+    /// Returns the next segment sent by `socket` and its payload.
+    fn transmit(socket: &TcpSocket) -> Option<(TCPHeader, Vec<u8>)> {
+        let mut payload = [0; 64];
+        socket
+            .next_segment(&mut payload)
+            .map(|(_, header, len)| (header, payload[..len].to_vec()))
+    }
+
+    /// Delivers a segment from the peer with no payload.
+    fn arrive(socket: &TcpSocket, flags: u16, seq: u32, ack: u32) {
+        arrive_with(socket, flags, seq, ack, &[]);
+    }
+
+    /// Delivers a segment from the peer.
+    fn arrive_with(socket: &TcpSocket, flags: u16, seq: u32, ack: u32, payload: &[u8]) {
+        let mut header = TCPHeader::new();
+        header.set_src_port(REMOTE_PORT);
+        header.set_dst_port(LOCAL_PORT);
+        header.set_seq_num(seq);
+        header.set_ack_num(ack);
+        header.set_flags(flags);
+        header.set_window(1024);
+        socket.segment_arrives(REMOTE_ADDR, &header, payload, None);
+    }
+
+    fn assert_segment(segment: Option<(TCPHeader, Vec<u8>)>, flags: u16, seq: u32, ack: u32) {
+        let (header, _) = segment.expect("no segment sent");
+        assert_eq!(header.get_flags(), flags);
+        assert_eq!(header.get_seq_num(), seq);
+        if flags & tcp_flags::ACK != 0 {
+            assert_eq!(header.get_ack_num(), ack);
+        }
+    }
+
+    #[test]
+    fn active_open() {
+        let fixture = Fixture::new();
+        let socket = fixture.socket();
+        socket
+            .connect(LOCAL_PORT, REMOTE_ADDR, REMOTE_PORT)
+            .unwrap();
+        assert_eq!(socket.get_state(), TcpState::SynSent);
+        assert_segment(transmit(&socket), tcp_flags::SYN, LOCAL_ISS, 0);
+        assert!(transmit(&socket).is_none());
+
+        arrive(
+            &socket,
+            tcp_flags::SYN | tcp_flags::ACK,
+            REMOTE_ISS,
+            LOCAL_ISS + 1,
+        );
+        assert_eq!(socket.get_state(), TcpState::Established);
+        assert_eq!(fixture.client.take_events(), [Event::Connected(Ok(()))]);
+        assert_segment(
+            transmit(&socket),
+            tcp_flags::ACK,
+            LOCAL_ISS + 1,
+            REMOTE_ISS + 1,
+        );
+        assert!(!socket.timer_active());
+    }
+
+    #[test]
+    fn passive_open() {
+        let fixture = Fixture::new();
+        let socket = fixture.socket();
+        socket.listen(LOCAL_PORT).unwrap();
+        assert!(transmit(&socket).is_none());
+
+        arrive(&socket, tcp_flags::SYN, REMOTE_ISS, 0);
+        assert_eq!(socket.get_state(), TcpState::SynReceived);
+        assert_segment(
+            transmit(&socket),
+            tcp_flags::SYN | tcp_flags::ACK,
+            LOCAL_ISS,
+            REMOTE_ISS + 1,
+        );
+
+        arrive(&socket, tcp_flags::ACK, REMOTE_ISS + 1, LOCAL_ISS + 1);
+        assert_eq!(socket.get_state(), TcpState::Established);
+        assert_eq!(socket.get_remote_port(), REMOTE_PORT);
+        assert_eq!(fixture.client.take_events(), [Event::Connected(Ok(()))]);
+    }
+
+    #[test]
+    fn refused_connection() {
+        let fixture = Fixture::new();
+        let socket = fixture.socket();
+        socket
+            .connect(LOCAL_PORT, REMOTE_ADDR, REMOTE_PORT)
+            .unwrap();
+        transmit(&socket).unwrap();
+
+        // A reset that does not acknowledge our SYN is ignored.
+        arrive(&socket, tcp_flags::RST, 0, 0);
+        assert_eq!(socket.get_state(), TcpState::SynSent);
+
+        arrive(&socket, tcp_flags::RST | tcp_flags::ACK, 0, LOCAL_ISS + 1);
+        assert_eq!(socket.get_state(), TcpState::Closed);
+        assert_eq!(
+            fixture.client.take_events(),
+            [Event::Connected(Err(ErrorCode::FAIL))]
+        );
+    }
+
+    #[test]
+    fn syn_retransmission_backs_off_and_gives_up() {
+        let fixture = Fixture::new();
+        let socket = fixture.socket();
+        socket
+            .connect(LOCAL_PORT, REMOTE_ADDR, REMOTE_PORT)
+            .unwrap();
+        transmit(&socket).unwrap();
+
+        let mut rto = INITIAL_RTO_MS;
+        for _ in 0..MAX_RETRIES {
+            socket.tick(rto - 1);
+            assert!(transmit(&socket).is_none());
+            socket.tick(1);
+            assert_segment(transmit(&socket), tcp_flags::SYN, LOCAL_ISS, 0);
+            rto = cmp::min(rto * 2, MAX_RTO_MS);
+        }
+
+        socket.tick(rto);
+        assert_eq!(socket.get_state(), TcpState::Closed);
+        assert_eq!(
+            fixture.client.take_events(),
+            [Event::Connected(Err(ErrorCode::NOACK))]
+        );
+        // No reset is sent for a connection that was never synchronized.
+        assert!(fixture.stack.resets.borrow().is_empty());
+    }
+
+    #[test]
+    fn data_retransmission() {
+        let fixture = Fixture::new();
+        let socket = fixture.established();
+        assert_eq!(socket.send(b"hello"), Ok(5));
+
+        let (header, payload) = transmit(&socket).unwrap();
+        assert_eq!(header.get_flags(), tcp_flags::ACK | tcp_flags::PSH);
+        assert_eq!(header.get_seq_num(), LOCAL_ISS + 1);
+        assert_eq!(payload, b"hello");
+        assert!(transmit(&socket).is_none());
+
+        socket.tick(INITIAL_RTO_MS);
+        let (header, payload) = transmit(&socket).unwrap();
+        assert_eq!(header.get_seq_num(), LOCAL_ISS + 1);
+        assert_eq!(payload, b"hello");
+
+        // A partial acknowledgment keeps the timer running.
+        arrive(&socket, tcp_flags::ACK, REMOTE_ISS + 1, LOCAL_ISS + 3);
+        assert_eq!(fixture.client.take_events(), [Event::Sent(2)]);
+        assert!(socket.timer_active());
+
+        arrive(&socket, tcp_flags::ACK, REMOTE_ISS + 1, LOCAL_ISS + 6);
+        assert_eq!(fixture.client.take_events(), [Event::Sent(3)]);
+        assert!(!socket.timer_active());
+        assert!(transmit(&socket).is_none());
+    }
+
+    #[test]
+    fn unacknowledged_data_resets_connection() {
+        let fixture = Fixture::new();
+        let socket = fixture.established();
+        socket.send(b"hello").unwrap();
+        transmit(&socket).unwrap();
+
+        for _ in 0..MAX_RETRIES {
+            socket.tick(MAX_RTO_MS);
+            assert_eq!(transmit(&socket).unwrap().1, b"hello");
+        }
+        socket.tick(MAX_RTO_MS);
+
+        assert_eq!(socket.get_state(), TcpState::Closed);
+        assert_eq!(
+            fixture.client.take_events(),
+            [Event::Closed(Err(ErrorCode::NOACK))]
+        );
+        let resets = fixture.stack.resets.borrow();
+        assert_eq!(resets.len(), 1);
+        assert_eq!(resets[0].get_flags(), tcp_flags::RST);
+        assert_eq!(resets[0].get_dst_port(), REMOTE_PORT);
+    }
+
+    #[test]
+    fn remote_close() {
+        let fixture = Fixture::new();
+        let socket = fixture.established();
+
+        arrive(
+            &socket,
+            tcp_flags::FIN | tcp_flags::ACK,
+            REMOTE_ISS + 1,
+            LOCAL_ISS + 1,
+        );
+        assert_eq!(socket.get_state(), TcpState::CloseWait);
+        assert_eq!(fixture.client.take_events(), [Event::RemoteClosed]);
+        assert_segment(
+            transmit(&socket),
+            tcp_flags::ACK,
+            LOCAL_ISS + 1,
+            REMOTE_ISS + 2,
+        );
+
+        socket.close().unwrap();
+        assert_eq!(socket.get_state(), TcpState::LastAck);
+        assert_segment(
+            transmit(&socket),
+            tcp_flags::FIN | tcp_flags::ACK,
+            LOCAL_ISS + 1,
+            REMOTE_ISS + 2,
+        );
+
+        arrive(&socket, tcp_flags::ACK, REMOTE_ISS + 2, LOCAL_ISS + 2);
+        assert_eq!(socket.get_state(), TcpState::Closed);
+        assert_eq!(fixture.client.take_events(), [Event::Closed(Ok(()))]);
+    }
+
+    #[test]
+    fn local_close() {
+        let fixture = Fixture::new();
+        let socket = fixture.established();
+
+        socket.close().unwrap();
+        assert_eq!(socket.get_state(), TcpState::FinWait1);
+        assert_segment(
+            transmit(&socket),
+            tcp_flags::FIN | tcp_flags::ACK,
+            LOCAL_ISS + 1,
+            REMOTE_ISS + 1,
+        );
+
+        arrive(&socket, tcp_flags::ACK, REMOTE_ISS + 1, LOCAL_ISS + 2);
+        assert_eq!(socket.get_state(), TcpState::FinWait2);
+
+        arrive(
+            &socket,
+            tcp_flags::FIN | tcp_flags::ACK,
+            REMOTE_ISS + 1,
+            LOCAL_ISS + 2,
+        );
+        assert_eq!(socket.get_state(), TcpState::TimeWait);
+        assert_eq!(fixture.client.take_events(), [Event::Closed(Ok(()))]);
+        assert_segment(
+            transmit(&socket),
+            tcp_flags::ACK,
+            LOCAL_ISS + 2,
+            REMOTE_ISS + 2,
+        );
+
+        socket.tick(TIME_WAIT_MS);
+        assert_eq!(socket.get_state(), TcpState::Closed);
+    }
+
+    #[test]
+    fn reset_at_next_sequence_number() {
+        let fixture = Fixture::new();
+        let socket = fixture.established();
+
+        arrive(&socket, tcp_flags::RST, REMOTE_ISS + 1, 0);
+        assert_eq!(socket.get_state(), TcpState::Closed);
+        assert_eq!(
+            fixture.client.take_events(),
+            [Event::Closed(Err(ErrorCode::FAIL))]
+        );
+        assert!(transmit(&socket).is_none());
+    }
+
+    #[test]
+    fn reset_elsewhere_in_window_gets_challenge_ack() {
+        let fixture = Fixture::new();
+        let socket = fixture.established();
+
+        arrive(&socket, tcp_flags::RST, REMOTE_ISS + 10, 0);
+        assert_eq!(socket.get_state(), TcpState::Established);
+        assert!(fixture.client.take_events().is_empty());
+        assert_segment(
+            transmit(&socket),
+            tcp_flags::ACK,
+            LOCAL_ISS + 1,
+            REMOTE_ISS + 1,
+        );
+
+        // A reset outside the window is dropped without a reply.
+        arrive(&socket, tcp_flags::RST, REMOTE_ISS + 1000, 0);
+        assert_eq!(socket.get_state(), TcpState::Established);
+        assert!(transmit(&socket).is_none());
+    }
+
+    #[test]
+    fn out_of_window_segment() {
+        let fixture = Fixture::new();
+        let socket = fixture.established();
+
+        // The receive window is the 64 byte receive buffer.
+        arrive_with(
+            &socket,
+            tcp_flags::ACK,
+            REMOTE_ISS + 65,
+            LOCAL_ISS + 1,
+            b"late",
+        );
+        arrive_with(
+            &socket,
+            tcp_flags::ACK,
+            REMOTE_ISS - 4,
+            LOCAL_ISS + 1,
+            b"old!",
+        );
+        assert_eq!(socket.available(), 0);
+        assert!(fixture.client.take_events().is_empty());
+        assert_segment(
+            transmit(&socket),
+            tcp_flags::ACK,
+            LOCAL_ISS + 1,
+            REMOTE_ISS + 1,
+        );
+        assert_eq!(socket.get_state(), TcpState::Established);
+    }
+
+    #[test]
+    fn out_of_order_segment() {
+        let fixture = Fixture::new();
+        let socket = fixture.established();
+
+        arrive_with(
+            &socket,
+            tcp_flags::ACK,
+            REMOTE_ISS + 5,
+            LOCAL_ISS + 1,
+            b"next",
+        );
+        assert_eq!(socket.available(), 0);
+        assert_segment(
+            transmit(&socket),
+            tcp_flags::ACK,
+            LOCAL_ISS + 1,
+            REMOTE_ISS + 1,
+        );
+
+        arrive_with(
+            &socket,
+            tcp_flags::ACK,
+            REMOTE_ISS + 1,
+            LOCAL_ISS + 1,
+            b"data",
+        );
+        assert_eq!(fixture.client.take_events(), [Event::Received(4)]);
+        assert_segment(
+            transmit(&socket),
+            tcp_flags::ACK,
+            LOCAL_ISS + 1,
+            REMOTE_ISS + 5,
+        );
+        let mut buf = [0; 8];
+        assert_eq!(socket.recv(&mut buf), 4);
+        assert_eq!(&buf[..4], b"data");
+    }
+}
@@ -5,9 +5,9 @@
 //! Structs and methods associated with the Thread networking layer.
 //!
 //! This represents a first attempt in Tock to support Thread
-//! networking. The current implementation successfully joins a Tock
-//! device as a child node to a Thread parent (tested using
-//! OpenThread). This Thread capsule is a client to the UDP Mux.  The
+//! networking. The current implementation joins a Tock device as a
+//! child node to a Thread parent (tested using OpenThread) and keeps
+//! it attached. This Thread capsule is a client to the UDP Mux. The
 //! associated ThreadNetwork struct must be created in the
 //! `thread_network.rs` component.
 //!
@@ -18,6 +18,25 @@
 //! the Thread network is considered locked. After the Thread network
 //! is "locked", other userspace applications attempting to join the network
 //! will return a failure. This is temporary and will eventually be replaced.
+//!
+//! Attaching follows the MLE child attach process (Thread Spec v1.3.0 --
+//! sect. 4.7): Parent Requests are multicast first to routers and then to
+//! routers and REEDs, the best Parent Response received within the response
+//! timeout is selected, and a Child ID Request is sent to it. Failed attach
+//! cycles are retried after a backoff. Once attached, the child sends Child
+//! Update Requests to keep its parent from timing it out, follows the Leader
+//! Data advertised by its parent, and reattaches if the parent stops
+//! responding or moves to another partition.
+//!
+//! Key Sequences
+//! -------------
+//!
+//! MLE messages are secured with the key of the current key sequence. When a
+//! message secured with a newer key sequence is received, the application is
+//! notified through the `KEY_SEQUENCE` upcall and is expected to derive the
+//! keys of that sequence and pass them to the driver with command `2`. The
+//! keys of the previous key sequence are kept so that messages sent before
+//! the switch can still be processed.
 
 // ------------------------------------------------------------------------------
 // Current Limitations
@@ -25,12 +44,12 @@
 // (1) A majority of the TLV fields used in the parent request/child id request
 //     are hardcoded. Future implementations need to provide options for specifying
 //     varied security policies.
-// (2) Current implementation joins the Thread network sucessfully and consistently
-//     but does not send update/heart beat messages to the parent prior to the child
-//     timing out.
+// (2) Challenges are derived from the alarm and the frame counter rather than
+//     a random number generator.
 // (3) Currently no support for sending UDP messages across Thread interface. The
 //     current interface is unusable for sending data. It can only be used to
-//     join a network.
+//     join a network. Likewise, the network data received from the parent is
+//     only used to track its version.
 
 use crate::ieee802154::framer::{self, get_ccm_nonce};
 use crate::net::ieee802154::{KeyId, MacAddress, Security, SecurityLevel};
@@ -43,9 +62,12 @@ use crate::net::thread::thread_utils::ThreadState;
 use crate::net::thread::thread_utils::MULTICAST_IPV6;
 use crate::net::thread::thread_utils::THREAD_PORT_NUMBER;
 use crate::net::thread::thread_utils::{
-    encode_cryp_data, form_child_id_req, form_parent_req, mac_from_ipv6, MleCommand, NetworkKey,
-    AUTH_DATA_LEN, AUX_SEC_HEADER_LENGTH, IPV6_LEN, SECURITY_SUITE_LEN,
+    encode_cryp_data, for_each_tlv, form_child_id_req, form_child_update_req, form_data_req,
+    form_parent_req, key_id_from_sequence, key_sequence_from_id, key_sequence_from_index,
+    mac_from_ipv6, LeaderData, MleCommand, NetworkKey, AUTH_DATA_LEN, AUX_SEC_HEADER_LENGTH,
+    IPV6_LEN, SECURITY_SUITE_LEN,
 };
+use crate::net::thread::tlv::{MulticastResponder, Tlv};
 use crate::net::udp::udp_port_table::UdpPortManager;
 use crate::net::udp::udp_recv::UDPRecvClient;
 use crate::net::udp::udp_send::{UDPSendClient, UDPSender};
@@ -58,16 +80,44 @@ use kernel::errorcode::into_statuscode;
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
 use kernel::hil::symmetric_encryption::CCMClient;
 use kernel::hil::symmetric_encryption::AES128CCM;
-use kernel::hil::time;
+use kernel::hil::time::{self, ConvertTicks, Ticks};
 use kernel::processbuffer::ReadableProcessBuffer;
 use kernel::syscall::{CommandReturn, SyscallDriver};
-use kernel::utilities::cells::MapCell;
+use kernel::utilities::cells::{MapCell, OptionalCell};
 use kernel::utilities::leasable_buffer::SubSliceMut;
 use kernel::{ErrorCode, ProcessId};
 
 const SECURITY_SUITE_ENCRYP: u8 = 0;
 pub const DRIVER_NUM: usize = driver::NUM::Thread as usize;
 
+/// Number of Parent Requests sent in one attach cycle. The first
+/// `PARENT_REQ_ROUTER_ATTEMPTS` are only answered by routers, the remaining
+/// ones by routers and REEDs (Thread Spec v1.3.0 -- sect. 4.7.1).
+const PARENT_REQ_ATTEMPTS: u8 = 6;
+const PARENT_REQ_ROUTER_ATTEMPTS: u8 = 2;
+
+/// Time given to routers (and REEDs) to answer a Parent Request, in ms.
+const PARENT_RSP_TIMEOUT_ROUTERS_MS: u32 = 750;
+const PARENT_RSP_TIMEOUT_REEDS_MS: u32 = 1250;
+
+/// Time given to the selected parent to answer a Child ID Request, in ms.
+const CHILD_ID_RSP_TIMEOUT_MS: u32 = 1250;
+
+/// Time given to the parent to answer a Child Update Request, in ms, and the
+/// number of requests sent before considering the parent lost.
+const CHILD_UPDATE_RSP_TIMEOUT_MS: u32 = 1000;
+const CHILD_UPDATE_ATTEMPTS: u8 = 4;
+
+/// Delay between two failed attach cycles, in ms.
+const ATTACH_BACKOFF_MS: u32 = 30_000;
+
+/// Child timeout requested from the parent, in seconds.
+const CHILD_TIMEOUT_S: u32 = 240;
+
+/// Upper bound of the interval between two Child Update Requests, in ms. This
+/// keeps the interval within the range of 24-bit alarms.
+const MAX_KEEP_ALIVE_MS: u32 = 60_000;
+
 /// Ids for read-only allow buffers
 mod ro_allow {
     pub const WRITE: usize = 0;
@@ -86,13 +136,151 @@ mod ro_allow {
 
 /// IDs for subscribed upcalls.
 mod upcall {
+    /// An attach cycle completed. The first argument is the status, the
+    /// second the RLOC16 assigned by the parent on success.
     pub const JOINCOMPLETE: usize = 0;
+    /// The device lost its parent and is reattaching. The first argument is
+    /// the reason.
+    pub const DETACHED: usize = 1;
+    /// A message secured with a newer key sequence was received. The first
+    /// argument is the key sequence whose keys should be set with command 2.
+    pub const KEY_SEQUENCE: usize = 2;
+    /// Number of upcalls.
+    pub const COUNT: u8 = 3;
 }
 
 #[derive(Default)]
 pub struct App {}
 
-#[allow(dead_code)]
+/// Crypto operation underway, with the length of the data passed to the
+/// crypto engine (auth data || mle payload || mic).
+#[derive(Clone, Copy)]
+enum PendingCrypt {
+    Send(usize),
+    Receive(usize),
+}
+
+/// A device that answered our Parent Request, and once attached, our parent.
+#[derive(Clone, Copy)]
+struct Parent {
+    ip: IPAddr,
+    rloc16: u16,
+    /// Challenge sent by the parent, which the Child ID Request answers.
+    challenge: [u8; 8],
+    link_margin: u8,
+    parent_priority: u8,
+    /// Last MLE frame counter received from the parent.
+    frame_counter: u32,
+}
+
+impl Parent {
+    /// Rank used to select the best parent: link quality first, then parent
+    /// priority, then link margin (Thread Spec v1.3.0 -- sect. 4.7.2).
+    fn rank(&self) -> (u8, u8, u8) {
+        let link_quality = match self.link_margin {
+            0..=2 => 0,
+            3..=10 => 1,
+            11..=20 => 2,
+            _ => 3,
+        };
+        // Parent priority is a 2 bit signed value in the upper bits.
+        let priority = match self.parent_priority >> 6 {
+            0b01 => 2,
+            0b00 => 1,
+            _ => 0,
+        };
+        (link_quality, priority, self.link_margin)
+    }
+}
+
+/// TLVs of a received MLE message used by the attach state machine.
+#[derive(Default)]
+struct MleTlvs {
+    source_address: Option<u16>,
+    challenge: Option<[u8; 8]>,
+    response: Option<[u8; 8]>,
+    address16: Option<u16>,
+    leader_data: Option<LeaderData>,
+    link_margin: Option<u8>,
+    parent_priority: Option<u8>,
+    timeout: Option<u32>,
+    status: Option<u8>,
+}
+
+impl MleTlvs {
+    fn parse(buf: &[u8]) -> Option<MleTlvs> {
+        let mut tlvs = MleTlvs::default();
+        for_each_tlv(buf, |tlv| match tlv {
+            Tlv::SourceAddress(addr) => tlvs.source_address = Some(addr),
+            Tlv::Challenge(challenge) => tlvs.challenge = Some(challenge),
+            Tlv::Response(response) => tlvs.response = Some(response),
+            Tlv::Address16(addr) => tlvs.address16 = Some(addr),
+            Tlv::LeaderData {
+                partition_id,
+                weighting,
+                data_version,
+                stable_data_version,
+                leader_router_id,
+            } => {
+                tlvs.leader_data = Some(LeaderData {
+                    partition_id,
+                    weighting,
+                    data_version,
+                    stable_data_version,
+                    leader_router_id,
+                })
+            }
+            Tlv::LinkMargin(margin) => tlvs.link_margin = Some(margin),
+            Tlv::Connectivity {
+                parent_priority, ..
+            } => tlvs.parent_priority = Some(parent_priority),
+            Tlv::Timeout(timeout) => tlvs.timeout = Some(timeout),
+            Tlv::Status(status) => tlvs.status = Some(status),
+            _ => (),
+        })
+        .ok()?;
+        Some(tlvs)
+    }
+}
+
+/// Returns the long address of the neighbor with MAC address `addr`. The only
+/// neighbors of a child are itself, with long address `src_mac_addr` and
+/// RLOC16 `rloc16`, and its parent once attached.
+fn lookup_neighbor(
+    addr: MacAddress,
+    src_mac_addr: [u8; 8],
+    rloc16: u16,
+    parent: Option<Parent>,
+) -> Option<[u8; 8]> {
+    match addr {
+        MacAddress::Long(long_addr) => {
+            if long_addr == src_mac_addr
+                || parent.is_some_and(|parent| mac_from_ipv6(parent.ip) == long_addr)
+            {
+                Some(long_addr)
+            } else {
+                None
+            }
+        }
+        MacAddress::Short(short_addr) => {
+            let parent = parent?;
+            if short_addr == parent.rloc16 {
+                Some(mac_from_ipv6(parent.ip))
+            } else if short_addr == rloc16 {
+                Some(src_mac_addr)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// Returns whether network data version `a` is newer than `b`, using serial
+/// number arithmetic.
+fn version_newer(a: u8, b: u8) -> bool {
+    (a.wrapping_sub(b) as i8) > 0
+}
+
 pub struct ThreadNetworkDriver<'a, A: time::Alarm<'a>> {
     /// UDP sender
     sender: &'a dyn UDPSender<'a>,
@@ -104,15 +292,22 @@ pub struct ThreadNetworkDriver<'a, A: time::Alarm<'a>> {
     alarm: &'a A,
 
     /// Grant of apps that use this thread driver.
-    apps: Grant<App, UpcallCount<1>, AllowRoCount<{ ro_allow::COUNT }>, AllowRwCount<0>>,
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<0>,
+    >,
 
     /// mac address of device
     src_mac_addr: [u8; 8],
 
     /// Maximum length payload that an app can transmit via this driver
+    #[allow(dead_code)]
     max_tx_pyld_len: usize,
 
     /// UDP bound port table (manages kernel bindings)
+    #[allow(dead_code)]
     port_table: &'static UdpPortManager,
 
     /// kernel buffer used for sending
@@ -136,8 +331,36 @@ pub struct ThreadNetworkDriver<'a, A: time::Alarm<'a>> {
     /// Stored Thread network containing mac/MLE key
     networkkey: MapCell<NetworkKey>,
 
-    /// Length of the message passed to the crypto engine
-    crypto_sizelock: MapCell<usize>,
+    /// Keys of the previous key sequence
+    prev_networkkey: MapCell<NetworkKey>,
+
+    /// Key sequence of `networkkey`
+    key_sequence: Cell<u32>,
+
+    /// Crypto operation underway, if any
+    pending_crypt: OptionalCell<PendingCrypt>,
+
+    /// Best parent found during the current attach attempt, or the parent
+    /// once attached
+    parent: OptionalCell<Parent>,
+
+    /// Parent Requests sent in the current attach cycle
+    attach_attempt: Cell<u8>,
+
+    /// Child Update Requests sent since the last response of the parent
+    update_attempt: Cell<u8>,
+
+    /// Challenge of the last Parent Request or Child Update Request sent
+    challenge: Cell<[u8; 8]>,
+
+    /// RLOC16 assigned by the parent
+    rloc16: Cell<u16>,
+
+    /// Leader Data of the partition the device is attached to
+    leader_data: Cell<LeaderData>,
+
+    /// Child timeout granted by the parent, in seconds
+    timeout: Cell<u32>,
 }
 
 // Note: For now, we initialize the Thread state as empty.
@@ -152,7 +375,12 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
         sender: &'a dyn UDPSender<'a>,
         aes_crypto: &'a dyn AES128CCM<'a>,
         alarm: &'a A,
-        grant: Grant<App, UpcallCount<1>, AllowRoCount<{ ro_allow::COUNT }>, AllowRwCount<0>>,
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<0>,
+        >,
         src_mac_addr: [u8; 8],
         max_tx_pyld_len: usize,
         port_table: &'static UdpPortManager,
@@ -176,7 +404,16 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
             net_cap,
             frame_count: Cell::new(5),
             networkkey: MapCell::empty(),
-            crypto_sizelock: MapCell::empty(),
+            prev_networkkey: MapCell::empty(),
+            key_sequence: Cell::new(0),
+            pending_crypt: OptionalCell::empty(),
+            parent: OptionalCell::empty(),
+            attach_attempt: Cell::new(0),
+            update_attempt: Cell::new(0),
+            challenge: Cell::new([0; 8]),
+            rloc16: Cell::new(0),
+            leader_data: Cell::new(LeaderData::default()),
+            timeout: Cell::new(CHILD_TIMEOUT_S),
         }
     }
 
@@ -185,6 +422,53 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
         self.networkkey.replace(NetworkKey { mle_key, mac_key });
     }
 
+    /// Switches to the MLE and MAC keys of `key_sequence`. The current keys
+    /// are kept as the previous ones if `key_sequence` directly follows the
+    /// current key sequence.
+    fn switch_networkkey(&self, key_sequence: u32, mle_key: [u8; 16], mac_key: [u8; 16]) {
+        match self.networkkey.take() {
+            Some(prev) if key_sequence == self.key_sequence.get().wrapping_add(1) => {
+                self.prev_networkkey.replace(prev);
+            }
+            _ => {
+                self.prev_networkkey.take();
+            }
+        }
+        self.key_sequence.set(key_sequence);
+        self.set_networkkey(mle_key, mac_key);
+    }
+
+    /// Returns the keys used for messages secured with `key_sequence`.
+    fn networkkey_for_sequence(&self, key_sequence: u32) -> Option<NetworkKey> {
+        let curr_sequence = self.key_sequence.get();
+        if key_sequence == curr_sequence {
+            self.networkkey.get()
+        } else if key_sequence.wrapping_add(1) == curr_sequence {
+            self.prev_networkkey.get()
+        } else {
+            None
+        }
+    }
+
+    fn set_timer_ms(&self, ms: u32) {
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(ms));
+    }
+
+    /// Generates the challenge of a Parent Request or Child Update Request.
+    /// This mixes the current time, the frame counter and the MAC address;
+    /// it should be replaced by a random number generator.
+    fn new_challenge(&self) -> [u8; 8] {
+        let mut x = (u64::from(self.alarm.now().into_u32()) << 32
+            | u64::from(self.frame_count.get()))
+            ^ u64::from_le_bytes(self.src_mac_addr);
+        // xorshift64*
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d).to_le_bytes()
+    }
+
     fn send_parent_req(&self) {
         // UNCOMMENT TO DEBUG THREAD //
         // kernel::debug!("[Thread] Sending parent request...");
@@ -197,27 +481,37 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
             ThreadState::Detached => {
                 // A parent request can only begin from a detached state. We utilize
                 // helper functions to form the request and send the parent request
-                // to the multicast IP/Mac Address
+                // to the multicast IP/Mac Address. The first attempts of an attach
+                // cycle only solicit routers, later attempts also solicit REEDs.
+                let attempt = self.attach_attempt.get() + 1;
+                self.attach_attempt.set(attempt);
+                let scan_mask = if attempt <= PARENT_REQ_ROUTER_ATTEMPTS {
+                    MulticastResponder::Router as u8
+                } else {
+                    MulticastResponder::Router as u8 | MulticastResponder::EndDevice as u8
+                };
+                self.parent.clear();
+                self.challenge.set(self.new_challenge());
+
                 self.state.replace(ThreadState::SendParentReq);
-                let parent_req_mle = form_parent_req();
+                let parent_req_mle = form_parent_req(scan_mask, self.challenge.get());
                 let src_ipv6 = generate_src_ipv6(&self.src_mac_addr);
-                self.thread_mle_send(&parent_req_mle, MULTICAST_IPV6, src_ipv6)
-                    .err()
-                    .map(|code| {
-                        // Thread send failed sending parent req so we terminate and return
-                        // to a detached state.
-                        self.state.replace(ThreadState::Detached);
-
-                        // UNCOMMENT TO DEBUG THREAD //
-                        // kernel::debug!(
-                        //     "[Thread] Failed sending MLE parent request - crypto operation error."
-                        // );
-                        self.terminate_child_join(Err(code));
-                    });
+                if self
+                    .thread_mle_send(&parent_req_mle, MULTICAST_IPV6, src_ipv6)
+                    .is_err()
+                {
+                    // UNCOMMENT TO DEBUG THREAD //
+                    // kernel::debug!(
+                    //     "[Thread] Failed sending MLE parent request - crypto operation error."
+                    // );
+
+                    // The response timeout of this attempt moves on to the next attempt
+                    self.mle_send_complete();
+                }
             }
             ThreadState::SEDActive(_, _)
             | ThreadState::SendUpdate(_, _)
-            | ThreadState::SendUDPMsg => {
+            | ThreadState::WaitingUpdateRsp(_, _) => {
                 // These states constitute a device that has previously sucessfully
                 // joined the network. There is no need to issue a new parent request.
                 // Replace state, and terminate.
@@ -234,6 +528,136 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
         };
     }
 
+    /// Sends the next Parent Request of the attach cycle, or ends the attach
+    /// cycle and schedules the next one if all attempts were made. The state
+    /// must be `Detached`.
+    fn next_attach_attempt(&self) {
+        if self.attach_attempt.get() < PARENT_REQ_ATTEMPTS {
+            self.send_parent_req();
+        } else {
+            // UNCOMMENT TO DEBUG THREAD //
+            // kernel::debug!("[Thread] Attach failed, backing off.");
+            self.attach_attempt.set(0);
+            self.set_timer_ms(ATTACH_BACKOFF_MS);
+            self.terminate_child_join(Err(ErrorCode::FAIL));
+        }
+    }
+
+    /// Sends a Child ID Request to `parent`. The state must be `Detached`.
+    fn send_child_id_req(&self, parent: Parent) {
+        // UNCOMMENT TO DEBUG THREAD //
+        // kernel::debug!("[Thread] Sending Child ID Request...");
+        self.state.take();
+        self.state.replace(ThreadState::SendChildIdReq(parent.ip));
+
+        let (output, offset) =
+            form_child_id_req(parent.challenge, self.frame_count.get(), CHILD_TIMEOUT_S);
+        let src_ipv6 = generate_src_ipv6(&self.src_mac_addr);
+        if self
+            .thread_mle_send(&output[..offset], parent.ip, src_ipv6)
+            .is_err()
+        {
+            // The response timeout moves on to the next attach attempt
+            self.mle_send_complete();
+        }
+    }
+
+    /// Sends a Child Update Request to the parent to keep the device
+    /// attached. Does nothing unless the state is `SEDActive`.
+    fn send_child_update_req(&self) {
+        let (parent_ip, parent_mac) = match self.state.take() {
+            Some(ThreadState::SEDActive(ip, mac)) => (ip, mac),
+            Some(state) => {
+                self.state.replace(state);
+                return;
+            }
+            None => return,
+        };
+
+        self.update_attempt.set(self.update_attempt.get() + 1);
+        self.challenge.set(self.new_challenge());
+        self.state
+            .replace(ThreadState::SendUpdate(parent_ip, parent_mac));
+
+        let output = form_child_update_req(
+            self.rloc16.get(),
+            self.challenge.get(),
+            CHILD_TIMEOUT_S,
+            self.leader_data.get(),
+        );
+        let src_ipv6 = generate_src_ipv6(&self.src_mac_addr);
+        if self.thread_mle_send(&output, parent_ip, src_ipv6).is_err() {
+            // The response timeout retries the request
+            self.mle_send_complete();
+        }
+    }
+
+    /// Asks the parent for its current network data.
+    fn send_data_req(&self, parent_ip: IPAddr) {
+        let src_ipv6 = generate_src_ipv6(&self.src_mac_addr);
+        // On failure, the next advertisement with a newer data version
+        // triggers another request.
+        let _ = self.thread_mle_send(&form_data_req(), parent_ip, src_ipv6);
+    }
+
+    /// Arms the alarm for the next Child Update Request, halfway through the
+    /// child timeout granted by the parent.
+    fn schedule_keep_alive(&self) {
+        self.update_attempt.set(0);
+        self.set_timer_ms(
+            self.timeout
+                .get()
+                .saturating_mul(500)
+                .clamp(1, MAX_KEEP_ALIVE_MS),
+        );
+    }
+
+    /// Drops the parent and starts a new attach cycle.
+    fn detach(&self, reason: ErrorCode) {
+        // UNCOMMENT TO DEBUG THREAD //
+        // kernel::debug!("[Thread] Detached from parent ({:?}).", reason);
+        self.state.take();
+        self.state.replace(ThreadState::Detached);
+        self.parent.clear();
+        self.attach_attempt.set(0);
+
+        self.apps.each(|_, _, kernel_data| {
+            kernel_data
+                .schedule_upcall(upcall::DETACHED, (into_statuscode(Err(reason)), 0, 0))
+                .ok();
+        });
+
+        self.send_parent_req();
+    }
+
+    /// Advances the state machine once an MLE message was sent (or failed to
+    /// send), and arms the timeout for the reply.
+    fn mle_send_complete(&self) {
+        let next_state = match self.state.take() {
+            Some(ThreadState::SendParentReq) => {
+                // UNCOMMENT TO DEBUG THREAD //
+                // kernel::debug!("[Thread] Completed sending parent request to multicast IP");
+                if self.attach_attempt.get() <= PARENT_REQ_ROUTER_ATTEMPTS {
+                    self.set_timer_ms(PARENT_RSP_TIMEOUT_ROUTERS_MS);
+                } else {
+                    self.set_timer_ms(PARENT_RSP_TIMEOUT_REEDS_MS);
+                }
+                ThreadState::WaitingParentRsp
+            }
+            Some(ThreadState::SendChildIdReq(parent_ip)) => {
+                self.set_timer_ms(CHILD_ID_RSP_TIMEOUT_MS);
+                ThreadState::WaitingChildRsp(parent_ip)
+            }
+            Some(ThreadState::SendUpdate(parent_ip, parent_mac)) => {
+                self.set_timer_ms(CHILD_UPDATE_RSP_TIMEOUT_MS);
+                ThreadState::WaitingUpdateRsp(parent_ip, parent_mac)
+            }
+            Some(state) => state,
+            None => return,
+        };
+        self.state.replace(next_state);
+    }
+
     fn thread_mle_send(
         &self,
         mle_buf: &[u8],
@@ -247,68 +671,236 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
             level: SecurityLevel::EncMic32,
             asn_in_nonce: false,
             frame_counter: Some(self.frame_count.get()),
-            key_id: KeyId::Source4Index([0, 0, 0, 0], 1),
+            key_id: key_id_from_sequence(self.key_sequence.get()),
         };
+        let mle_key = self.networkkey.get().ok_or(ErrorCode::NOSUPPORT)?.mle_key;
 
         // Begin cryptographic and sending procedure for the MLE message
         self.send_buffer
             .take()
             .map_or(Err(ErrorCode::NOMEM), |send_buffer| {
-                self.perform_crypt_op(src_addr, dest_addr, security, mle_buf, send_buffer.take())
-                    .map_err(|(code, buf)| {
-                        // Error occured with cryptographic operation, replace buffer
-                        // for future transmissions and return error code
-                        self.send_buffer.replace(SubSliceMut::new(buf));
-                        code
-                    })
+                self.perform_crypt_op(
+                    src_addr,
+                    dest_addr,
+                    security,
+                    &mle_key,
+                    mle_buf,
+                    send_buffer.take(),
+                    true,
+                )
+                .map_err(|(code, buf)| {
+                    // Error occured with cryptographic operation, replace buffer
+                    // for future transmissions and return error code
+                    self.send_buffer.replace(SubSliceMut::new(buf));
+                    code
+                })
             })
     }
 
-    fn recv_logic(&self, sender_ip: IPAddr) -> Result<(), ErrorCode> {
+    fn recv_logic(&self, sender_ip: IPAddr, frame_counter: u32) -> Result<(), ErrorCode> {
         // This function is called once the received MLE payload has been placed
         // into the recv_buffer. The function handles the message and responds accordingly
 
         self.recv_buffer
             .take()
             .map_or(Err(ErrorCode::NOMEM), |mut recv_buf| {
-                if recv_buf[0] == MleCommand::ParentResponse as u8 {
-                    // Received Parent Response -> form Child ID Request
-
-                    // UNCOMMENT TO DEBUG THREAD //
-                    // kernel::debug!("[Thread] Received Parent Response.");
-                    // kernel::debug!("[Thread] Sending Child ID Request...");
-
-                    let src_ipv6 = generate_src_ipv6(&self.src_mac_addr);
-
-                    let (output, offset) =
-                        form_child_id_req(recv_buf.as_slice(), self.frame_count.get())?;
-
-                    // Advance state machine
-                    self.state.replace(ThreadState::SendChildIdReq(sender_ip));
-
-                    self.thread_mle_send(&output[..offset], sender_ip, src_ipv6)?;
-                } else if recv_buf[0] == MleCommand::ChildIdResponse as u8 {
-                    // Receive child id response -> advance state machine
-                    self.state.replace(ThreadState::SEDActive(
-                        sender_ip,
-                        MacAddress::Long(mac_from_ipv6(sender_ip)),
-                    ));
-
-                    // TODO: once heart beats are implemented, we will set
-                    // the timer here (as seen below)
-                    // let curr_time = self.alarm.now();
-                    // self.alarm.set_alarm(
-                    //     curr_time,
-                    //     time::ConvertTicks::ticks_from_seconds(self.alarm, 5),
-                    // );
-                }
-
+                let res = self.handle_mle(recv_buf.as_slice(), sender_ip, frame_counter);
                 recv_buf.reset();
                 self.recv_buffer.replace(recv_buf);
-                Ok(())
+                res
             })
     }
 
+    fn handle_mle(
+        &self,
+        mle: &[u8],
+        sender_ip: IPAddr,
+        frame_counter: u32,
+    ) -> Result<(), ErrorCode> {
+        let (&command, tlv_buf) = mle.split_first().ok_or(ErrorCode::SIZE)?;
+        let tlvs = MleTlvs::parse(tlv_buf).ok_or(ErrorCode::INVAL)?;
+        let curr_state = self.state.take().ok_or(ErrorCode::OFF)?;
+
+        if command == MleCommand::ParentResponse as u8 {
+            if let ThreadState::WaitingParentRsp = curr_state {
+                // UNCOMMENT TO DEBUG THREAD //
+                // kernel::debug!("[Thread] Received Parent Response.");
+                self.parent_response(sender_ip, frame_counter, &tlvs);
+            }
+            self.state.replace(curr_state);
+            return Ok(());
+        }
+
+        // All other messages handled by a child come from its (prospective)
+        // parent, and must not be replayed.
+        let parent_ip = match curr_state {
+            ThreadState::WaitingChildRsp(ip)
+            | ThreadState::SEDActive(ip, _)
+            | ThreadState::WaitingUpdateRsp(ip, _) => ip,
+            _ => {
+                self.state.replace(curr_state);
+                return Ok(());
+            }
+        };
+        let parent = match self.parent.get() {
+            Some(parent) if parent.ip == sender_ip && parent_ip == sender_ip => parent,
+            _ => {
+                self.state.replace(curr_state);
+                return Ok(());
+            }
+        };
+        if frame_counter <= parent.frame_counter {
+            self.state.replace(curr_state);
+            return Err(ErrorCode::ALREADY);
+        }
+        self.parent.set(Parent {
+            frame_counter,
+            ..parent
+        });
+
+        match curr_state {
+            ThreadState::WaitingChildRsp(ip) if command == MleCommand::ChildIdResponse as u8 => {
+                self.child_id_response(ip, &tlvs)
+            }
+            ThreadState::WaitingUpdateRsp(ip, mac)
+                if command == MleCommand::ChildUpdateResponse as u8 =>
+            {
+                self.child_update_response(ip, mac, &tlvs)
+            }
+            ThreadState::SEDActive(ip, _) | ThreadState::WaitingUpdateRsp(ip, _)
+                if command == MleCommand::LinkAdvertisement as u8 =>
+            {
+                self.state.replace(curr_state);
+                self.advertisement(ip, &tlvs)
+            }
+            ThreadState::SEDActive(..) | ThreadState::WaitingUpdateRsp(..)
+                if command == MleCommand::DataResponse as u8 =>
+            {
+                self.state.replace(curr_state);
+                self.update_leader_data(tlvs.leader_data.ok_or(ErrorCode::INVAL)?);
+                Ok(())
+            }
+            state => {
+                self.state.replace(state);
+                Ok(())
+            }
+        }
+    }
+
+    /// Records the sender of a Parent Response as the parent to request a
+    /// Child ID from if it is better than the previous responses.
+    fn parent_response(&self, sender_ip: IPAddr, frame_counter: u32, tlvs: &MleTlvs) {
+        // Responses that do not answer our challenge are ignored
+        if tlvs.response != Some(self.challenge.get()) || tlvs.leader_data.is_none() {
+            return;
+        }
+        let candidate = match (tlvs.source_address, tlvs.challenge, tlvs.link_margin) {
+            (Some(rloc16), Some(challenge), Some(link_margin)) => Parent {
+                ip: sender_ip,
+                rloc16,
+                challenge,
+                link_margin,
+                parent_priority: tlvs.parent_priority.unwrap_or(0),
+                frame_counter,
+            },
+            _ => return,
+        };
+
+        if self
+            .parent
+            .map_or(true, |best| candidate.rank() > best.rank())
+        {
+            self.parent.set(candidate);
+        }
+    }
+
+    fn child_id_response(&self, parent_ip: IPAddr, tlvs: &MleTlvs) -> Result<(), ErrorCode> {
+        let (rloc16, leader_data) = match (tlvs.address16, tlvs.leader_data) {
+            (Some(rloc16), Some(leader_data)) => (rloc16, leader_data),
+            _ => {
+                self.state.replace(ThreadState::WaitingChildRsp(parent_ip));
+                return Err(ErrorCode::INVAL);
+            }
+        };
+
+        // UNCOMMENT TO DEBUG THREAD //
+        // kernel::debug!("[Thread] Attached with RLOC16 {:#06x}.", rloc16);
+        self.rloc16.set(rloc16);
+        self.leader_data.set(leader_data);
+        self.timeout.set(tlvs.timeout.unwrap_or(CHILD_TIMEOUT_S));
+        self.attach_attempt.set(0);
+        self.state.replace(ThreadState::SEDActive(
+            parent_ip,
+            MacAddress::Long(mac_from_ipv6(parent_ip)),
+        ));
+        self.schedule_keep_alive();
+
+        self.apps.each(|_, _, kernel_data| {
+            kernel_data
+                .schedule_upcall(
+                    upcall::JOINCOMPLETE,
+                    (into_statuscode(Ok(())), rloc16 as usize, 0),
+                )
+                .ok();
+        });
+        Ok(())
+    }
+
+    fn child_update_response(
+        &self,
+        parent_ip: IPAddr,
+        parent_mac: MacAddress,
+        tlvs: &MleTlvs,
+    ) -> Result<(), ErrorCode> {
+        if tlvs.status.is_some() {
+            // The parent no longer has us as a child
+            self.state
+                .replace(ThreadState::WaitingUpdateRsp(parent_ip, parent_mac));
+            self.detach(ErrorCode::NOACK);
+            return Ok(());
+        }
+        if tlvs.response != Some(self.challenge.get()) {
+            self.state
+                .replace(ThreadState::WaitingUpdateRsp(parent_ip, parent_mac));
+            return Err(ErrorCode::INVAL);
+        }
+
+        if let Some(timeout) = tlvs.timeout {
+            self.timeout.set(timeout);
+        }
+        self.state
+            .replace(ThreadState::SEDActive(parent_ip, parent_mac));
+        self.schedule_keep_alive();
+        if let Some(leader_data) = tlvs.leader_data {
+            self.update_leader_data(leader_data);
+        }
+        Ok(())
+    }
+
+    /// Processes an MLE advertisement of the parent: a new partition leads to
+    /// a reattach, and newer network data is requested from the parent.
+    fn advertisement(&self, parent_ip: IPAddr, tlvs: &MleTlvs) -> Result<(), ErrorCode> {
+        let leader_data = tlvs.leader_data.ok_or(ErrorCode::INVAL)?;
+        let curr = self.leader_data.get();
+        if leader_data.partition_id != curr.partition_id {
+            self.detach(ErrorCode::FAIL);
+        } else if version_newer(leader_data.data_version, curr.data_version)
+            || version_newer(leader_data.stable_data_version, curr.stable_data_version)
+        {
+            self.send_data_req(parent_ip);
+        }
+        Ok(())
+    }
+
+    /// Adopts the Leader Data sent by the parent along with its network data.
+    fn update_leader_data(&self, leader_data: LeaderData) {
+        if leader_data.partition_id != self.leader_data.get().partition_id {
+            self.detach(ErrorCode::FAIL);
+        } else {
+            self.leader_data.set(leader_data);
+        }
+    }
+
     fn terminate_child_join(&self, res: Result<(), ErrorCode>) {
         // Function to schedule upcall to userland on parent request termination. Notifies
         // userland of the reason for termination with the first argument.
@@ -325,46 +917,43 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
         src_addr: IPAddr,
         dst_addr: IPAddr,
         security: Security,
+        mle_key: &[u8; 16],
         payload: &[u8],
         buf: &'static mut [u8],
+        encrypting: bool,
     ) -> Result<(), (ErrorCode, &'static mut [u8])> {
-        // Wrapper function for performing the AES-128CCM encryption. This function generates the nonce,
-        // sets the nonce/key for the crypto engine, generates the authenticated data, and initiates
-        // the crypto operation.
+        // Wrapper function for performing the AES-128CCM encryption/decryption. This function
+        // generates the nonce, sets the nonce/key for the crypto engine, generates the
+        // authenticated data, and initiates the crypto operation.
 
-        // Note: The payload argument does not include aux sec header
+        // Note: The payload argument does not include aux sec header. When decrypting,
+        // the payload includes the mic.
 
-        // Obtain and unwrap frame counter
-        let frame_counter = security.frame_counter;
-        if frame_counter.is_none() {
+        // A crypto operation is pending until its `crypt_done` callback. The key
+        // and nonce of the crypto engine must not be changed until then.
+        if self.pending_crypt.is_some() {
             // UNCOMMENT TO DEBUG THREAD //
-            // kernel::debug!("[Thread] Malformed auxiliary security header");
-            return Err((ErrorCode::INVAL, buf));
+            // kernel::debug!("[Thread] Error - cryptographic resources in use");
+            return Err((ErrorCode::BUSY, buf));
         }
 
-        // Generate nonce, obtain network key and set crypto engine accordingly
-        let nonce = get_ccm_nonce(
-            &mac_from_ipv6(src_addr),
-            frame_counter.unwrap(),
-            security.level,
-        );
-        let mle_key = self.networkkey.get();
-        let mic_len = security.level.mic_len();
-        match mle_key {
-            Some(netkey) => {
-                if self.aes_crypto.set_key(&netkey.mle_key).is_err()
-                    || self.aes_crypto.set_nonce(&nonce).is_err()
-                {
-                    // UNCOMMENT TO DEBUG THREAD //
-                    // kernel::debug!("[Thread] Failure setting networkkey and/or nonce.");
-                    return Err((ErrorCode::FAIL, buf));
-                }
-            }
+        // Obtain and unwrap frame counter
+        let frame_counter = match security.frame_counter {
+            Some(frame_counter) => frame_counter,
             None => {
                 // UNCOMMENT TO DEBUG THREAD //
-                // kernel::debug!("[Thread] Attempt to access networkkey when no networkkey set.");
-                return Err((ErrorCode::NOSUPPORT, buf));
+                // kernel::debug!("[Thread] Malformed auxiliary security header");
+                return Err((ErrorCode::INVAL, buf));
             }
+        };
+
+        // Generate nonce and set crypto engine accordingly
+        let nonce = get_ccm_nonce(&mac_from_ipv6(src_addr), frame_counter, security.level);
+        let mic_len = security.level.mic_len();
+        if self.aes_crypto.set_key(mle_key).is_err() || self.aes_crypto.set_nonce(&nonce).is_err() {
+            // UNCOMMENT TO DEBUG THREAD //
+            // kernel::debug!("[Thread] Failure setting networkkey and/or nonce.");
+            return Err((ErrorCode::FAIL, buf));
         }
 
         // Thread MLE security utilizes the AES128 CCM security used by 802.15.4 link layer security.
@@ -389,48 +978,44 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
         let aux_sec_header = &mut [0u8; AUX_SEC_HEADER_LENGTH];
         Security::encode(&security, aux_sec_header);
 
-        let m_data_len = payload.len();
+        let m_data_len = if encrypting {
+            payload.len()
+        } else {
+            payload.len() - mic_len
+        };
 
         // Encode auth data and payload into `buf`
         let encode_res = encode_cryp_data(src_addr, dst_addr, aux_sec_header, payload, buf).done();
 
         // Error check on result from encoding, failure likely means buf was not large enough
-        if encode_res.is_none() {
-            // UNCOMMENT TO DEBUG THREAD //
-            // kernel::debug!("[Thread] Error encoding cryptographic data into buffer");
-            return Err((ErrorCode::FAIL, buf));
-        }
-
-        let (offset, ()) = encode_res.unwrap();
+        let (offset, ()) = match encode_res {
+            Some(res) => res,
+            None => {
+                // UNCOMMENT TO DEBUG THREAD //
+                // kernel::debug!("[Thread] Error encoding cryptographic data into buffer");
+                return Err((ErrorCode::FAIL, buf));
+            }
+        };
 
-        // GENERAL NOTE: `self.crypto_sizelock`
-        // This does not seem to be the most elegant solution. The `crypto_sizelock` arose from the fact
-        // that we must know the length of the payload when the `crypt_done` callback
+        // GENERAL NOTE: `self.pending_crypt`
+        // We must know the length of the payload when the `crypt_done` callback
         // occurs in order to only send/receive the portion of the 200 byte buffer that is the message.
-        // The other option to `crypto_sizelock` is to only pass to the crypto engine a buffer
+        // The other option is to only pass to the crypto engine a buffer
         // that is the size of the transmission/reception. This however is flawed
         // as it leads to an inability to replace/restore the 200 byte buffer. The crypto engine
         // requires a reference to static memory (leading to the recv/send buf being taken).
         // This is only able to be replaced when the `crypt_done` callback occurs and returns the
         // buf used by the crypto engine. If a partial buffer is used, it is impossible to then replace
-        // the full sized buffer to the send/recv buf. Likewise, I choose to use the `crypt_sizelock`
-        // and pass the whole 200 byte buffer. The `crypto_sizelock` works for
-        // now until a more elegant solution is implemented.
-
-        // The sizelock is empty except when a crypto operation
-        // is underway. If the sizelock is not empty, return error
-        if self.crypto_sizelock.is_some() {
-            // UNCOMMENT TO DEBUG THREAD //
-            // kernel::debug!(
-            //     "[Thread] Error - cryptographic resources in use; crypto_sizelock occupied"
-            // );
-            return Err((ErrorCode::BUSY, buf));
+        // the full sized buffer to the send/recv buf. Likewise, we pass the whole 200 byte buffer
+        // and store the length along with the direction of the operation.
+        if encrypting {
+            self.pending_crypt.set(PendingCrypt::Send(offset + mic_len));
+        } else {
+            self.pending_crypt.set(PendingCrypt::Receive(offset));
         }
-
-        // Store the length of the payload.
-        self.crypto_sizelock.replace(offset + mic_len);
         self.aes_crypto
-            .crypt(buf, 0, AUTH_DATA_LEN, m_data_len, mic_len, true, true)
+            .crypt(buf, 0, AUTH_DATA_LEN, m_data_len, mic_len, true, encrypting)
+            .inspect_err(|_| self.pending_crypt.clear())
     }
 }
 
@@ -438,23 +1023,36 @@ impl<'a, A: time::Alarm<'a>> framer::KeyProcedure for ThreadNetworkDriver<'a, A>
     /// Gets the key corresponding to the key that matches the given security
     /// level `level` and key ID `key_id`. If no such key matches, returns
     /// `None`.
-    // TODO: This implementation only supports one key
-    fn lookup_key(&self, _level: SecurityLevel, _key_id: KeyId) -> Option<[u8; 16]> {
-        if let Some(netkey) = self.networkkey.get() {
-            Some(netkey.mac_key)
-        } else {
-            None
-        }
+    ///
+    /// Thread MAC frames identify the key by its key index, which is derived
+    /// from the key sequence. The keys of the current and the previous key
+    /// sequence are available.
+    fn lookup_key(&self, _level: SecurityLevel, key_id: KeyId) -> Option<[u8; 16]> {
+        let key_index = match key_id {
+            KeyId::Index(index) | KeyId::Source4Index(_, index) => index,
+            _ => return None,
+        };
+        key_sequence_from_index(key_index, self.key_sequence.get())
+            .and_then(|key_sequence| self.networkkey_for_sequence(key_sequence))
+            .map(|netkey| netkey.mac_key)
     }
 }
 
 impl<'a, A: time::Alarm<'a>> framer::DeviceProcedure for ThreadNetworkDriver<'a, A> {
-    /// Gets the key corresponding to the key that matches the given security
-    /// level `level` and key ID `key_id`. If no such key matches, returns
-    /// `None`.
-    // TODO: This implementation only supports one key
-    fn lookup_addr_long(&self, _addr: MacAddress) -> Option<[u8; 8]> {
-        Some(self.src_mac_addr)
+    /// Gets the long address corresponding to the neighbor that matches the
+    /// given MAC address. The only neighbors of a child are itself and its
+    /// parent. If no such neighbor exists, returns `None`.
+    fn lookup_addr_long(&self, addr: MacAddress) -> Option<[u8; 8]> {
+        let attached = self.state.map_or(false, |state| {
+            matches!(
+                state,
+                ThreadState::SEDActive(..)
+                    | ThreadState::SendUpdate(..)
+                    | ThreadState::WaitingUpdateRsp(..)
+            )
+        });
+        let parent = self.parent.get().filter(|_| attached);
+        lookup_neighbor(addr, self.src_mac_addr, self.rloc16.get(), parent)
     }
 }
 
@@ -462,11 +1060,13 @@ impl<'a, A: time::Alarm<'a>> SyscallDriver for ThreadNetworkDriver<'a, A> {
     /// ### `command_num`
     /// - `0`: Driver Check
     /// - `1`: Add a new mle/mac networkkey and initiate a parent request.
+    /// - `2`: Switch to the mle/mac networkkey of key sequence `arg1`, which
+    ///   must be newer than the current key sequence.
 
     fn command(
         &self,
         command_num: usize,
-        _arg1: usize,
+        arg1: usize,
         _: usize,
         processid: ProcessId,
     ) -> CommandReturn {
@@ -512,11 +1112,43 @@ impl<'a, A: time::Alarm<'a>> SyscallDriver for ThreadNetworkDriver<'a, A> {
                     |ok_val| {
                         // If no failure in saving the mle/mac key, initiate
                         // sending the parent request
-                        self.send_parent_req();
+                        if ok_val.is_success() {
+                            self.send_parent_req();
+                        }
                         ok_val
                     },
                 ),
 
+            2 => self
+                .apps
+                .enter(processid, |_, kernel_data| {
+                    kernel_data
+                        .get_readonly_processbuffer(ro_allow::WRITE)
+                        .and_then(|ro_buf| {
+                            ro_buf.enter(|src_key| {
+                                // Keys can only be switched once the network was joined
+                                if self.networkkey.is_none() {
+                                    return CommandReturn::failure(ErrorCode::OFF);
+                                }
+                                let key_sequence = arg1 as u32;
+                                if key_sequence <= self.key_sequence.get() {
+                                    return CommandReturn::failure(ErrorCode::INVAL);
+                                }
+                                if src_key.len() != 32 {
+                                    return CommandReturn::failure(ErrorCode::SIZE);
+                                }
+                                let mut mle_key = [0u8; 16];
+                                let mut mac_key = [0u8; 16];
+                                src_key[..16].copy_to_slice(&mut mle_key);
+                                src_key[16..32].copy_to_slice(&mut mac_key);
+                                self.switch_networkkey(key_sequence, mle_key, mac_key);
+                                CommandReturn::success()
+                            })
+                        })
+                        .unwrap_or(CommandReturn::failure(ErrorCode::INVAL))
+                })
+                .unwrap_or_else(|err| CommandReturn::failure(err.into())),
+
             _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
         }
     }
@@ -528,49 +1160,61 @@ impl<'a, A: time::Alarm<'a>> SyscallDriver for ThreadNetworkDriver<'a, A> {
 
 impl<'a, A: time::Alarm<'a>> UDPSendClient for ThreadNetworkDriver<'a, A> {
     fn send_done(&self, _result: Result<(), ErrorCode>, mut dgram: SubSliceMut<'static, u8>) {
-        // TODO: handle result from send done and respond accordingly
-
-        // Panicking on unwrap indicates the state was taken without replacement
-        // (unreachable with proper state machine implementation)
-        let curr_state = self.state.take().unwrap();
-
-        // Advance state machine
-        let next_state = match curr_state {
-            ThreadState::SendUpdate(dst_ip, dst_mac) => ThreadState::SEDActive(dst_ip, dst_mac),
-            ThreadState::SendUDPMsg => unimplemented!(),
-            ThreadState::SendChildIdReq(_) => ThreadState::WaitingChildRsp,
-            ThreadState::SendParentReq => {
-                // UNCOMMENT TO DEBUG THREAD //
-                // kernel::debug!("[Thread] Completed sending parent request to multicast IP");
-                ThreadState::WaitingParentRsp
-            }
-            _ => panic!("Thread state machine diverged"),
-        };
-
+        // Failed transmissions are retried once the timeout for the reply
+        // expires, so the state machine advances regardless of the result.
         self.frame_count.set(self.frame_count.get() + 1);
 
-        // Replace the returned buffer and state
+        // Replace the returned buffer and advance the state machine
         dgram.reset();
         self.send_buffer.replace(dgram);
-        self.state.replace(next_state);
+        self.mle_send_complete();
     }
 }
 
 impl<'a, A: time::Alarm<'a>> time::AlarmClient for ThreadNetworkDriver<'a, A> {
-    // TODO: This function is mainly here as a place holder as it will be needed
-    // for implementing timeouts/timing for sending heartbeat messages to the parent
-    // node
     fn alarm(&self) {
-        match self.state.take().unwrap() {
-            // TODO: Implement retries as defined in the thread spec (when timeouts occur)
-            ThreadState::Detached => unimplemented!("[Thread ALARM] Detached"),
-            ThreadState::SendParentReq => unimplemented!("[Thread ALARM] Send Parent Req"),
-            ThreadState::SendChildIdReq(_) => unimplemented!("[Thread ALARM] Send Child ID Req"),
-            ThreadState::SEDActive(_ipaddr, _mac) => {
-                // TODO: SEND HEARTBEAT to parent node
-                unimplemented!("[Thread ALARM] Send Heartbeat")
-            }
-            _ => panic!(""),
+        let curr_state = match self.state.take() {
+            Some(state) => state,
+            None => return,
+        };
+
+        match curr_state {
+            ThreadState::WaitingParentRsp => {
+                // The response window closed; request a Child ID from the
+                // best parent, if any responded.
+                self.state.replace(ThreadState::Detached);
+                match self.parent.get() {
+                    Some(parent) => self.send_child_id_req(parent),
+                    None => self.next_attach_attempt(),
+                }
+            }
+            ThreadState::WaitingChildRsp(_) => {
+                self.state.replace(ThreadState::Detached);
+                self.next_attach_attempt();
+            }
+            ThreadState::Detached => {
+                // The backoff after a failed attach cycle expired
+                self.state.replace(ThreadState::Detached);
+                self.send_parent_req();
+            }
+            ThreadState::SEDActive(_, _) => {
+                // Time to send a keep-alive to the parent
+                self.state.replace(curr_state);
+                self.send_child_update_req();
+            }
+            ThreadState::WaitingUpdateRsp(parent_ip, parent_mac) => {
+                self.state
+                    .replace(ThreadState::SEDActive(parent_ip, parent_mac));
+                if self.update_attempt.get() < CHILD_UPDATE_ATTEMPTS {
+                    self.send_child_update_req();
+                } else {
+                    self.detach(ErrorCode::NOACK);
+                }
+            }
+            state => {
+                // The timeout for a message being sent is armed once it is sent
+                self.state.replace(state);
+            }
         }
     }
 }
@@ -584,25 +1228,54 @@ impl<'a, A: time::Alarm<'a>> UDPRecvClient for ThreadNetworkDriver<'a, A> {
         _dst_port: u16,
         payload: &[u8],
     ) {
-        if payload[0] != SECURITY_SUITE_ENCRYP {
+        if payload.first() != Some(&SECURITY_SUITE_ENCRYP) {
             // Tock's current implementation of Thread ignores all messages that do not possess MLE encryption. This
             // is due to the Thread spec stating "Except for when specifically indicated, incoming
             // messages that are not secured with either MLE or link-layer security SHOULD be ignored." (v.1.3.0 sect 4.10)
             // UNCOMMENT TO DEBUG THREAD //
             // kernel::debug!("[Thread] DROPPED PACKET - Received unencrypted MLE packet.");
+            return;
         }
 
         // decode aux security header from packet into Security data type
         let sec_res = ieee802154::Security::decode(&payload[1..]).done();
 
-        // Guard statement for improperly formated aux sec header
-        if sec_res.is_none() {
+        // Guard statement for improperly formated aux sec header. MLE
+        // messages identify their key by the key sequence.
+        let (security, key_sequence) = match sec_res
+            .and_then(|(_, security)| Some((security, key_sequence_from_id(security.key_id)?)))
+        {
+            Some(res) => res,
+            None => {
+                // UNCOMMENT TO DEBUG THREAD //
+                // kernel::debug!("[Thread] DROPPED PACKET - Malformed auxiliary security header.");
+                return;
+            }
+        };
+
+        if payload.len() < SECURITY_SUITE_LEN + AUX_SEC_HEADER_LENGTH + security.level.mic_len() {
             // UNCOMMENT TO DEBUG THREAD //
-            // kernel::debug!("[Thread] DROPPED PACKET - Malformed auxiliary security header.");
+            // kernel::debug!("[Thread] DROPPED PACKET - Truncated MLE packet.");
             return;
         }
 
-        let security = sec_res.unwrap().1;
+        let mle_key = match self.networkkey_for_sequence(key_sequence) {
+            Some(netkey) => netkey.mle_key,
+            None => {
+                if self.networkkey.is_some() && key_sequence > self.key_sequence.get() {
+                    // The network moved to a newer key sequence. Ask the
+                    // application for the corresponding keys.
+                    self.apps.each(|_, _, kernel_data| {
+                        kernel_data
+                            .schedule_upcall(upcall::KEY_SEQUENCE, (key_sequence as usize, 0, 0))
+                            .ok();
+                    });
+                }
+                // UNCOMMENT TO DEBUG THREAD //
+                // kernel::debug!("[Thread] DROPPED PACKET - No key for key sequence.");
+                return;
+            }
+        };
 
         // Take the receive buffer and pass to the `perform_crypto_op` wrapper function. This
         // initiates encoding all relevant auth data, setting crypto engine and initiating the
@@ -617,9 +1290,10 @@ impl<'a, A: time::Alarm<'a>> UDPRecvClient for ThreadNetworkDriver<'a, A> {
                     src_addr,
                     dst_addr,
                     security,
-                    &payload[SECURITY_SUITE_LEN + AUX_SEC_HEADER_LENGTH
-                        ..payload.len() - security.level.mic_len()],
+                    &mle_key,
+                    &payload[SECURITY_SUITE_LEN + AUX_SEC_HEADER_LENGTH..],
                     recv_buf.take(),
+                    false,
                 )
                 .map_or_else(
                     // Error check on crypto operation. If the crypto operation
@@ -641,11 +1315,27 @@ impl<'a, A: time::Alarm<'a>> UDPRecvClient for ThreadNetworkDriver<'a, A> {
 }
 
 impl<'a, A: time::Alarm<'a>> CCMClient for ThreadNetworkDriver<'a, A> {
-    fn crypt_done(&self, buf: &'static mut [u8], _res: Result<(), ErrorCode>, _tag_is_valid: bool) {
-        // TODO: check validity of result/tag and handle accordingly
+    fn crypt_done(&self, buf: &'static mut [u8], res: Result<(), ErrorCode>, tag_is_valid: bool) {
+        // Obtain the length of the payload and the direction of the operation.
+        // Panicking on unwrap indicates a crypto operation completed that was
+        // not started by this driver (unreachable)
+        let (buf_len, sending) = match self.pending_crypt.take().unwrap() {
+            PendingCrypt::Send(len) => (len, true),
+            PendingCrypt::Receive(len) => (len, false),
+        };
 
-        // Obtain the length of the payload from the sizelock
-        let buf_len = self.crypto_sizelock.take().unwrap();
+        if res.is_err() || (!sending && !tag_is_valid) {
+            // UNCOMMENT TO DEBUG THREAD //
+            // kernel::debug!("[Thread] Crypto operation failed or MIC invalid.");
+            if sending {
+                // The timeout for the reply retries the message
+                self.send_buffer.replace(SubSliceMut::new(buf));
+                self.mle_send_complete();
+            } else {
+                self.recv_buffer.replace(SubSliceMut::new(buf));
+            }
+            return;
+        }
 
         // The auth data contains the src_addr || dest_addr || aux_sec_header;
         // Recover src/dst addr from the auth data
@@ -660,13 +1350,12 @@ impl<'a, A: time::Alarm<'a>> CCMClient for ThreadNetworkDriver<'a, A> {
         let auth_addr_offset = AUTH_DATA_LEN - AUX_SEC_HEADER_LENGTH;
         buf.copy_within(auth_addr_offset.., SECURITY_SUITE_LEN);
 
-        // Recover the length of the mic from the security information encoded in the aux_sec_header
-        let mic_len = ieee802154::Security::decode(&buf[SECURITY_SUITE_LEN..])
+        // Recover the length of the mic and the frame counter from the aux_sec_header
+        let security = ieee802154::Security::decode(&buf[SECURITY_SUITE_LEN..])
             .done()
             .unwrap()
-            .1
-            .level
-            .mic_len();
+            .1;
+        let mic_len = security.level.mic_len();
 
         // We hard code the security suite to `0` for now as all messages are
         // assumed to be encrypted for the current implementation
@@ -678,66 +1367,206 @@ impl<'a, A: time::Alarm<'a>> CCMClient for ThreadNetworkDriver<'a, A> {
         // We create a new subslice that we will slice accordingly depending on if we are sending/receiving
         let mut assembled_subslice = SubSliceMut::new(buf);
 
-        // Panicking on unwrap indicates the state was taken without replacement
-        // (unreachable with proper state machine implementation)
-        let curr_state = self.state.take().unwrap();
+        if sending {
+            // To send, we need to send: security suite || aux sec header || mle payload || mic
+            // which correlates to the assembled_buf_len
+            assembled_subslice.slice(..assembled_buf_len);
+
+            // Begin sending the transmission. The state machine advances once
+            // the `send_done` callback is received
+            let _ = self
+                .sender
+                .driver_send_to(
+                    IPAddr(dst_ipv6),
+                    THREAD_PORT_NUMBER,
+                    THREAD_PORT_NUMBER,
+                    assembled_subslice,
+                    self.driver_send_cap,
+                    self.net_cap,
+                )
+                .map_err(|buf| {
+                    // if the sending fails prior to transmission, replace
+                    // the buffer; the timeout for the reply retries the message
+                    self.send_buffer.replace(buf);
+                    self.mle_send_complete();
+                });
+        } else {
+            // Upon receiving messages, the receive logic only requires the MLE payload. Subsequently,
+            // we slice the assembled_subslice to exclude the security suite, aux sec header, and mic.
+            assembled_subslice
+                .slice(AUX_SEC_HEADER_LENGTH + SECURITY_SUITE_LEN..assembled_buf_len - mic_len);
+
+            // Move the decrypted MLE message into the recv_buf and execute the receiving logic.
+            // Messages that cannot be handled are dropped.
+            self.recv_buffer.replace(assembled_subslice);
+            let _ = self.recv_logic(IPAddr(src_ipv6), security.frame_counter.unwrap_or(0));
+        }
+    }
+}
 
-        match curr_state {
-            ThreadState::SendParentReq | ThreadState::SendChildIdReq(_) => {
-                //TODO: Add alarm for timeouts
-
-                // To send, we need to send: security suite || aux sec header || mle payload || mic
-                // which correlates to the assembled_buf_len
-                assembled_subslice.slice(..assembled_buf_len);
-
-                let dest_ipv6 = match curr_state {
-                    // Determine destination IP depending on message type
-                    ThreadState::SendParentReq => MULTICAST_IPV6,
-                    ThreadState::SendChildIdReq(dst_ipv6) => dst_ipv6,
-                    _ => unreachable!(),
-                };
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec::Vec;
+
+    /// Challenge TLV value of `PARENT_RESPONSE`, as sent.
+    const PARENT_CHALLENGE: [u8; 8] = [0x9e, 0x41, 0x07, 0xd2, 0x5c, 0x13, 0xa8, 0x6f];
+
+    /// A Parent Response with the TLVs, TLV order and TLV lengths that an
+    /// OpenThread router sends.
+    const PARENT_RESPONSE: [u8; 66] = [
+        0x0a, // Parent Response
+        0x00, 0x02, 0x04, 0x00, // Source Address
+        0x0b, 0x08, 0x12, 0x34, 0x56, 0x78, 0x40, 0xa1, 0xb2, 0x05, // Leader Data
+        0x05, 0x04, 0x00, 0x00, 0x01, 0x2c, // Link-layer Frame Counter
+        0x08, 0x04, 0x00, 0x00, 0x01, 0x2c, // MLE Frame Counter
+        0x04, 0x08, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, // Response
+        0x03, 0x08, 0x9e, 0x41, 0x07, 0xd2, 0x5c, 0x13, 0xa8, 0x6f, // Challenge
+        0x10, 0x01, 0x4a, // Link Margin
+        0x0f, 0x0a, 0x40, 0x01, 0x00, 0x00, 0x01, 0x0c, 0x01, 0x05, 0x00,
+        0x01, // Connectivity
+        0x12, 0x02, 0x00, 0x04, // Version
+    ];
+
+    /// A Child ID Response with the TLVs, TLV order and TLV lengths that an
+    /// OpenThread router sends to a child that is not a router.
+    const CHILD_ID_RESPONSE: [u8; 57] = [
+        0x0c, // Child ID Response
+        0x16, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, // Active Timestamp
+        0x00, 0x02, 0x04, 0x00, // Source Address
+        0x0b, 0x08, 0x12, 0x34, 0x56, 0x78, 0x40, 0xa1, 0xb2, 0x05, // Leader Data
+        0x0a, 0x02, 0x04, 0x01, // Address16
+        0x0c, 0x08, 0x08, 0x06, 0xfd, 0x00, 0x0d, 0xb8, 0x00, 0x00, // Network Data
+        0x02, 0x04, 0x00, 0x00, 0x00, 0xf0, // Timeout
+        0x09, 0x0a, 0x0c, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x11, // Route64
+    ];
+
+    const LEADER_DATA: LeaderData = LeaderData {
+        partition_id: 0x1234_5678,
+        weighting: 0x40,
+        data_version: 0xa1,
+        stable_data_version: 0xb2,
+        leader_router_id: 5,
+    };
+
+    fn parent(ip: IPAddr, rloc16: u16) -> Parent {
+        Parent {
+            ip,
+            rloc16,
+            challenge: [0; 8],
+            link_margin: 0,
+            parent_priority: 0,
+            frame_counter: 0,
+        }
+    }
 
-                // we replace the state with the current state here
-                // because we cannot advance the state machine until
-                // after the `send_done` callback is received
-                self.state.replace(curr_state);
+    #[test]
+    fn parse_parent_response() {
+        let tlvs = MleTlvs::parse(&PARENT_RESPONSE[1..]).unwrap();
+        assert_eq!(tlvs.source_address, Some(0x0400));
+        assert!(tlvs.leader_data == Some(LEADER_DATA));
+        assert_eq!(tlvs.link_margin, Some(0x4a));
+        assert_eq!(tlvs.parent_priority, Some(0x40));
+        assert_eq!(tlvs.address16, None);
+        assert_eq!(tlvs.status, None);
+        // Challenge and Response TLVs hold byte strings that are stored in
+        // reverse.
+        let mut challenge = PARENT_CHALLENGE;
+        challenge.reverse();
+        assert_eq!(tlvs.challenge, Some(challenge));
+        assert_eq!(tlvs.response, Some([8, 7, 6, 5, 4, 3, 2, 1]));
+    }
 
-                // Begin sending the transmission
-                self.sender
-                    .driver_send_to(
-                        dest_ipv6,
-                        THREAD_PORT_NUMBER,
-                        THREAD_PORT_NUMBER,
-                        assembled_subslice,
-                        self.driver_send_cap,
-                        self.net_cap,
-                    )
-                    .map_err(|buf| {
-                        // if the sending fails prior to transmission, replace
-                        // the buffer and pass error accordingly to terminate_child_join
-                        // in following unwrap statement
-                        self.send_buffer.replace(buf);
-                        ErrorCode::FAIL
-                    })
-                    .unwrap_or_else(|code| self.terminate_child_join(Err(code)));
-            }
-            ThreadState::WaitingChildRsp => {
-                // TODO: Receive child response
-            }
-            ThreadState::WaitingParentRsp => {
-                // Upon receiving messages, the receive logic only requires the MLE payload. Subsequently,
-                // we slice the assembled_subslice to exclude the security suite, aux sec header, and mic.
-                assembled_subslice
-                    .slice(AUX_SEC_HEADER_LENGTH + SECURITY_SUITE_LEN..assembled_buf_len - mic_len);
-
-                // Move the decrypted MLE message into the recv_buf and execute the receiving logic. Upon
-                // an error in `recv_logic`, joining the network fails and schedule termination upcall
-                self.recv_buffer.replace(assembled_subslice);
-                self.recv_logic(IPAddr(src_ipv6))
-                    .err()
-                    .map(|code| self.terminate_child_join(Err(code)));
-            }
-            _ => (),
+    #[test]
+    fn parse_child_id_response() {
+        let tlvs = MleTlvs::parse(&CHILD_ID_RESPONSE[1..]).unwrap();
+        assert_eq!(tlvs.source_address, Some(0x0400));
+        assert!(tlvs.leader_data == Some(LEADER_DATA));
+        assert_eq!(tlvs.address16, Some(0x0401));
+        assert_eq!(tlvs.timeout, Some(240));
+        assert_eq!(tlvs.challenge, None);
+        assert_eq!(tlvs.response, None);
+    }
+
+    #[test]
+    fn parse_rejects_truncated_message() {
+        for len in [3, 10, PARENT_RESPONSE.len() - 1] {
+            assert!(MleTlvs::parse(&PARENT_RESPONSE[1..len]).is_none());
+        }
+    }
+
+    #[test]
+    fn parent_response_answers_parent_request_challenge() {
+        let challenge = [1, 2, 3, 4, 5, 6, 7, 8];
+        let req = form_parent_req(MulticastResponder::Router as u8, challenge);
+        // The Challenge TLV follows the command and the Mode TLV.
+        assert_eq!(&req[4..6], &[0x03, 0x08]);
+
+        // The parent echoes the challenge bytes it received.
+        let mut rsp = Vec::from([0x04, 0x08]);
+        rsp.extend_from_slice(&req[6..14]);
+        let tlvs = MleTlvs::parse(&rsp).unwrap();
+        assert_eq!(tlvs.response, Some(challenge));
+    }
+
+    #[test]
+    fn child_id_request_echoes_parent_challenge() {
+        let tlvs = MleTlvs::parse(&PARENT_RESPONSE[1..]).unwrap();
+        let (req, len) = form_child_id_req(tlvs.challenge.unwrap(), 7, CHILD_TIMEOUT_S);
+        assert!(len <= req.len());
+        assert_eq!(req[0], MleCommand::ChildIdRequest as u8);
+        // The Response TLV carries the challenge bytes as they were received.
+        assert_eq!(&req[1..3], &[0x04, 0x08]);
+        assert_eq!(req[3..11], PARENT_CHALLENGE);
+    }
+
+    #[test]
+    fn parent_rank_prefers_link_quality_then_priority() {
+        let tlvs = MleTlvs::parse(&PARENT_RESPONSE[1..]).unwrap();
+        let candidate = Parent {
+            link_margin: tlvs.link_margin.unwrap(),
+            parent_priority: tlvs.parent_priority.unwrap(),
+            ..parent(MULTICAST_IPV6, 0x0400)
+        };
+        let weaker_link = Parent {
+            link_margin: 15,
+            parent_priority: 0x40,
+            ..candidate
+        };
+        let lower_priority = Parent {
+            parent_priority: 0xc0,
+            ..candidate
         };
+        assert!(candidate.rank() > weaker_link.rank());
+        assert!(candidate.rank() > lower_priority.rank());
+        assert!(lower_priority.rank() > weaker_link.rank());
+    }
+
+    #[test]
+    fn lookup_neighbor_only_knows_self_and_parent() {
+        let own = [0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17];
+        let parent_mac = [0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27];
+        let parent = parent(generate_src_ipv6(&parent_mac), 0x0400);
+        let other = [0x30; 8];
+
+        let lookup = |addr, parent| lookup_neighbor(addr, own, 0x0401, parent);
+        assert_eq!(lookup(MacAddress::Long(own), None), Some(own));
+        assert_eq!(
+            lookup(MacAddress::Long(parent_mac), Some(parent)),
+            Some(parent_mac)
+        );
+        assert_eq!(lookup(MacAddress::Long(parent_mac), None), None);
+        assert_eq!(lookup(MacAddress::Long(other), Some(parent)), None);
+
+        assert_eq!(
+            lookup(MacAddress::Short(0x0400), Some(parent)),
+            Some(parent_mac)
+        );
+        assert_eq!(lookup(MacAddress::Short(0x0401), Some(parent)), Some(own));
+        assert_eq!(lookup(MacAddress::Short(0x0402), Some(parent)), None);
+        // Short addresses are only assigned once attached.
+        assert_eq!(lookup(MacAddress::Short(0x0401), None), None);
     }
 }
@@ -2,8 +2,9 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 // Copyright Tock Contributors 2023.
 
+use crate::net::ieee802154::KeyId;
 use crate::net::stream::{encode_bytes, SResult};
-use crate::net::thread::tlv::{unwrap_tlv_offset, LinkMode, MulticastResponder, Tlv};
+use crate::net::thread::tlv::{unwrap_tlv_offset, LinkMode, Tlv, TlvType};
 use crate::net::{ieee802154::MacAddress, ipv6::ip_utils::IPAddr};
 pub const THREAD_PORT_NUMBER: u16 = 19788;
 
@@ -14,6 +15,9 @@ pub const AUX_SEC_HEADER_LENGTH: usize = 10;
 pub const AUTH_DATA_LEN: usize = 42;
 pub const IPV6_LEN: usize = 16;
 const PARENT_REQUEST_MLE_SIZE: usize = 21;
+const CHILD_UPDATE_REQUEST_MLE_SIZE: usize = 34;
+const DATA_REQUEST_MLE_SIZE: usize = 4;
+const CHILD_ID_REQUEST_MLE_MAX_SIZE: usize = 200;
 pub const MULTICAST_IPV6: IPAddr = IPAddr([
     0xff, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
 ]);
@@ -24,16 +28,27 @@ pub struct NetworkKey {
     pub mac_key: [u8; 16],
 }
 
+/// Leader Data received from the parent, which identifies the partition
+/// the device is attached to and the version of its network data.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct LeaderData {
+    pub partition_id: u32,
+    pub weighting: u8,
+    pub data_version: u8,
+    pub stable_data_version: u8,
+    pub leader_router_id: u8,
+}
+
+/// States of the child attach state machine. The `IPAddr` and `MacAddress`
+/// are those of the (prospective) parent.
 pub enum ThreadState {
     SendParentReq,
     WaitingParentRsp,
-    RecvParentRsp(IPAddr),
     SendChildIdReq(IPAddr),
-    WaitingChildRsp,
-    RecvChildRsp(IPAddr),
+    WaitingChildRsp(IPAddr),
     SEDActive(IPAddr, MacAddress),
     SendUpdate(IPAddr, MacAddress),
-    SendUDPMsg,
+    WaitingUpdateRsp(IPAddr, MacAddress),
     Detached,
 }
 
@@ -92,20 +107,64 @@ pub fn mac_from_ipv6(ipv6: IPAddr) -> [u8; 8] {
     output
 }
 
-/// Helper function to locate the challenge TLV in a received
-/// MLE packet. Return the challenge to be used as a response
-/// TLV in reply.
-fn find_challenge(buf: &[u8]) -> Result<&[u8], ErrorCode> {
+/// Helper function to call `f` on every TLV of a received MLE message.
+///
+/// The message excludes the command byte. TLVs of types that are not
+/// supported by `Tlv::decode` are skipped. Returns an error if a TLV
+/// runs past the end of the message.
+pub fn for_each_tlv<'b, F: FnMut(Tlv<'b>)>(buf: &'b [u8], mut f: F) -> Result<(), ErrorCode> {
     let mut index = 0;
     while index < buf.len() {
-        let tlv_len = buf[index + 1] as usize;
-        if buf[index] == 3 {
-            return Ok(&buf[index + 2..index + 2 + tlv_len]);
-        } else {
-            index += tlv_len + 2;
+        if index + 2 > buf.len() {
+            return Err(ErrorCode::FAIL);
+        }
+        let end = index + 2 + buf[index + 1] as usize;
+        if end > buf.len() {
+            return Err(ErrorCode::FAIL);
         }
+        if !matches!(TlvType::from(buf[index]), TlvType::NotPresent) {
+            if let Some((_, tlv)) = Tlv::decode(&buf[index..end]).done() {
+                f(tlv);
+            }
+        }
+        index = end;
+    }
+    Ok(())
+}
+
+/// Helper function to build the MLE key identifier of a key sequence.
+///
+/// The key source is the big-endian key sequence, and the key index is
+/// its 7 lowest bits plus one (Thread Spec v1.3.0 -- sect. 7.2.2.2).
+pub fn key_id_from_sequence(key_sequence: u32) -> KeyId {
+    // `KeyId` stores key sources in reverse byte order.
+    KeyId::Source4Index(
+        key_sequence.to_le_bytes(),
+        key_index_from_sequence(key_sequence),
+    )
+}
+
+/// Helper function to recover the key sequence from the key identifier of
+/// a received MLE message.
+pub fn key_sequence_from_id(key_id: KeyId) -> Option<u32> {
+    match key_id {
+        KeyId::Source4Index(source, _) => Some(u32::from_le_bytes(source)),
+        _ => None,
     }
-    Err(ErrorCode::FAIL)
+}
+
+/// Key index used by the MAC layer for frames secured with the keys of
+/// the given key sequence.
+pub fn key_index_from_sequence(key_sequence: u32) -> u8 {
+    (key_sequence & 0x7f) as u8 + 1
+}
+
+/// Helper function to find which of the current key sequence and the one
+/// before it uses the MAC key index `key_index`.
+pub fn key_sequence_from_index(key_index: u8, key_sequence: u32) -> Option<u32> {
+    [key_sequence, key_sequence.wrapping_sub(1)]
+        .into_iter()
+        .find(|&sequence| key_index_from_sequence(sequence) == key_index)
 }
 
 /// Function to encode the crypt data into a/m data
@@ -125,11 +184,9 @@ pub fn encode_cryp_data(
     stream_done!(off)
 }
 
-/// This helper function creates a parent request. For now,
-/// this implementation hard codes all values for the parent request
-pub fn form_parent_req() -> [u8; PARENT_REQUEST_MLE_SIZE] {
-    // TODO: form parent request from alterable values, generate
-    // challenge from random number generator
+/// This helper function creates a parent request soliciting responses
+/// from the devices in `scan_mask` (see `MulticastResponder`).
+pub fn form_parent_req(scan_mask: u8, challenge: [u8; 8]) -> [u8; PARENT_REQUEST_MLE_SIZE] {
     let mut output = [0u8; PARENT_REQUEST_MLE_SIZE];
     let mut offset = 0;
 
@@ -149,15 +206,14 @@ pub fn form_parent_req() -> [u8; PARENT_REQUEST_MLE_SIZE] {
     ));
 
     // Challenge TLV //
-    // TODO: challenge is hardcoded currently; randomly generate number
     offset += unwrap_tlv_offset(Tlv::encode(
-        &Tlv::Challenge([0, 0, 0, 0, 0, 0, 0, 0]),
+        &Tlv::Challenge(challenge),
         &mut output[offset..],
     ));
 
     // Scan Mask TLV //
     offset += unwrap_tlv_offset(Tlv::encode(
-        &Tlv::ScanMask(MulticastResponder::Router as u8),
+        &Tlv::ScanMask(scan_mask),
         &mut output[offset..],
     ));
 
@@ -167,13 +223,19 @@ pub fn form_parent_req() -> [u8; PARENT_REQUEST_MLE_SIZE] {
     output
 }
 
-/// This helper function creates a child id request. For now,
+/// This helper function creates a child id request answering the
+/// `parent_challenge` received in the parent response. For now,
 /// this implementation hard codes many of the values
+///
+/// Challenge and Response TLVs are decoded and encoded with the same byte
+/// order, so the Response TLV carries the challenge bytes as they were
+/// received.
 pub fn form_child_id_req(
-    recv_buf: &[u8],
+    parent_challenge: [u8; 8],
     frame_count: u32,
-) -> Result<([u8; 200], usize), ErrorCode> {
-    let mut output: [u8; 200] = [0; 200];
+    timeout: u32,
+) -> ([u8; CHILD_ID_REQUEST_MLE_MAX_SIZE], usize) {
+    let mut output = [0; CHILD_ID_REQUEST_MLE_MAX_SIZE];
     let mut offset = 0;
 
     /* -- Child ID Request TLVs (Thread Spec 4.5.1 (v1.3.0)) --
@@ -194,18 +256,10 @@ pub fn form_child_id_req(
     offset += 1;
 
     // Response TLV //
-    let received_challenge_tlv: Result<&[u8], ErrorCode> = find_challenge(&recv_buf[1..]);
-
-    if received_challenge_tlv.is_err() {
-        // Challenge TLV not found; malformed request
-        return Err(ErrorCode::FAIL);
-    } else {
-        // Encode response into output
-        let mut rsp_buf: [u8; 8] = [0; 8];
-        rsp_buf.copy_from_slice(received_challenge_tlv.unwrap());
-        rsp_buf.reverse(); // NEED TO DISCUSS BIG/LITTLE ENDIAN ASSUMPTIONS
-        offset += unwrap_tlv_offset(Tlv::encode(&Tlv::Response(rsp_buf), &mut output[offset..]));
-    }
+    offset += unwrap_tlv_offset(Tlv::encode(
+        &Tlv::Response(parent_challenge),
+        &mut output[offset..],
+    ));
 
     // Link-layer Frame Counter TLV //
     offset += unwrap_tlv_offset(Tlv::encode(
@@ -227,7 +281,7 @@ pub fn form_child_id_req(
 
     // Timeout TLV //
     offset += unwrap_tlv_offset(Tlv::encode(
-        &Tlv::Timeout((10_u32).to_be()),
+        &Tlv::Timeout(timeout.to_be()),
         &mut output[offset..],
     ));
 
@@ -243,7 +297,86 @@ pub fn form_child_id_req(
         &mut output[offset..],
     ));
 
-    Ok((output, offset))
+    (output, offset)
+}
+
+/// This helper function creates a child update request, which the
+/// child periodically sends to keep its parent from timing it out.
+pub fn form_child_update_req(
+    rloc16: u16,
+    challenge: [u8; 8],
+    timeout: u32,
+    leader_data: LeaderData,
+) -> [u8; CHILD_UPDATE_REQUEST_MLE_SIZE] {
+    /* -- Child Update Request TLVs (Thread Spec 4.6.3 (v1.3.0)) --
+    Source Address TLV
+    Leader Data TLV
+    Mode TLV
+    [Challenge TLV]
+    [Timeout TLV]
+    [Address Registration TLV]
+    */
+    let mut output = [0u8; CHILD_UPDATE_REQUEST_MLE_SIZE];
+    let mut offset = 0;
+
+    // Command: Child Update Request //
+    output[0..1].copy_from_slice(&[MleCommand::ChildUpdateRequest as u8]);
+    offset += 1;
+
+    // Source Address TLV //
+    offset += unwrap_tlv_offset(Tlv::encode(
+        &Tlv::SourceAddress(rloc16.to_be()),
+        &mut output[offset..],
+    ));
+
+    // Leader Data TLV //
+    offset += unwrap_tlv_offset(Tlv::encode(
+        &Tlv::LeaderData {
+            partition_id: leader_data.partition_id.to_be(),
+            weighting: leader_data.weighting,
+            data_version: leader_data.data_version,
+            stable_data_version: leader_data.stable_data_version,
+            leader_router_id: leader_data.leader_router_id,
+        },
+        &mut output[offset..],
+    ));
+
+    // Mode TLV //
+    offset += unwrap_tlv_offset(Tlv::encode(
+        &Tlv::Mode(LinkMode::FullThreadDevice as u8 + LinkMode::ReceiverOnWhenIdle as u8),
+        &mut output[offset..],
+    ));
+
+    // Challenge TLV //
+    offset += unwrap_tlv_offset(Tlv::encode(
+        &Tlv::Challenge(challenge),
+        &mut output[offset..],
+    ));
+
+    // Timeout TLV //
+    unwrap_tlv_offset(Tlv::encode(
+        &Tlv::Timeout(timeout.to_be()),
+        &mut output[offset..],
+    ));
+
+    output
+}
+
+/// This helper function creates a data request, asking the parent for
+/// the current network data.
+pub fn form_data_req() -> [u8; DATA_REQUEST_MLE_SIZE] {
+    let mut output = [0u8; DATA_REQUEST_MLE_SIZE];
+
+    // Command: Data Request //
+    output[0..1].copy_from_slice(&[MleCommand::DataRequest as u8]);
+
+    // TLV Request TLV: Network Data //
+    unwrap_tlv_offset(Tlv::encode(
+        &Tlv::TlvRequest(&[TlvType::NetworkData as u8]),
+        &mut output[1..],
+    ));
+
+    output
 }
 
 /*
@@ -285,3 +418,117 @@ SENDING/RETRYING PARENT REQUESTS THREAD SPEC v1.3.0 -- section 4.5.1
 
 
 */
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::net::ieee802154::{Security, SecurityLevel};
+    use std::vec::Vec;
+
+    /// Types of the TLVs `for_each_tlv` passes on.
+    fn tlv_types(buf: &[u8]) -> Result<Vec<u8>, ErrorCode> {
+        let mut types = Vec::new();
+        for_each_tlv(buf, |tlv| types.push(TlvType::from(&tlv) as u8))?;
+        Ok(types)
+    }
+
+    #[test]
+    fn for_each_tlv_skips_unsupported_tlvs() {
+        // Source Address, Route64 (unsupported), Link Margin.
+        let buf = [
+            0x00, 0x02, 0x04, 0x00, 0x09, 0x02, 0xaa, 0xbb, 0x10, 0x01, 0x20,
+        ];
+        assert_eq!(tlv_types(&buf), Ok(std::vec![0x00, 0x10]));
+        assert_eq!(tlv_types(&[]), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn for_each_tlv_rejects_truncated_tlv() {
+        // A type without a length.
+        assert_eq!(
+            tlv_types(&[0x00, 0x02, 0x04, 0x00, 0x10]),
+            Err(ErrorCode::FAIL)
+        );
+        // A value shorter than its length.
+        assert_eq!(
+            tlv_types(&[0x00, 0x02, 0x04, 0x00, 0x10, 0x02, 0x20]),
+            Err(ErrorCode::FAIL)
+        );
+    }
+
+    #[test]
+    fn for_each_tlv_rejects_overlong_tlv() {
+        // A length that runs past the end of the message, even of a TLV that
+        // is otherwise skipped.
+        assert_eq!(tlv_types(&[0x10, 0xff, 0x20]), Err(ErrorCode::FAIL));
+        assert_eq!(tlv_types(&[0x09, 0x80, 0xaa]), Err(ErrorCode::FAIL));
+    }
+
+    #[test]
+    fn key_index_rolls_over_after_0x7f() {
+        assert_eq!(key_index_from_sequence(0), 1);
+        assert_eq!(key_index_from_sequence(0x7e), 0x7f);
+        assert_eq!(key_index_from_sequence(0x7f), 0x80);
+        assert_eq!(key_index_from_sequence(0x80), 1);
+        assert_eq!(key_index_from_sequence(0x17f), 0x80);
+    }
+
+    #[test]
+    fn key_sequence_from_index_covers_previous_sequence() {
+        assert_eq!(key_sequence_from_index(1, 0x80), Some(0x80));
+        assert_eq!(key_sequence_from_index(0x80, 0x80), Some(0x7f));
+        assert_eq!(key_sequence_from_index(2, 0x80), None);
+        // The sequence before 0 uses the last key index.
+        assert_eq!(key_sequence_from_index(0x80, 0), Some(u32::MAX));
+        assert_eq!(key_sequence_from_index(1, 0), Some(0));
+    }
+
+    #[test]
+    fn key_id_carries_big_endian_key_sequence() {
+        for key_sequence in [0, 0x7f, 0x80, 0x0102_0380] {
+            let key_id = key_id_from_sequence(key_sequence);
+            assert_eq!(key_sequence_from_id(key_id), Some(key_sequence));
+        }
+        assert_eq!(key_sequence_from_id(KeyId::Index(1)), None);
+
+        let security = Security {
+            level: SecurityLevel::EncMic32,
+            asn_in_nonce: false,
+            frame_counter: Some(5),
+            key_id: key_id_from_sequence(0x0102_0380),
+        };
+        let mut aux_sec_header = [0; AUX_SEC_HEADER_LENGTH];
+        assert!(security.encode(&mut aux_sec_header).done().is_some());
+        // Security control, frame counter, key source, key index.
+        assert_eq!(aux_sec_header[0], 0x15);
+        assert_eq!(&aux_sec_header[5..], &[0x01, 0x02, 0x03, 0x80, 0x01]);
+        let decoded = Security::decode(&aux_sec_header).done().unwrap().1;
+        assert_eq!(key_sequence_from_id(decoded.key_id), Some(0x0102_0380));
+    }
+
+    #[test]
+    fn child_update_request_encoding() {
+        let leader_data = LeaderData {
+            partition_id: 0x1234_5678,
+            weighting: 64,
+            data_version: 0xa1,
+            stable_data_version: 0xb2,
+            leader_router_id: 5,
+        };
+        let challenge = [1, 2, 3, 4, 5, 6, 7, 8];
+        let req = form_child_update_req(0x0401, challenge, 240, leader_data);
+        assert_eq!(
+            req,
+            [
+                13, // Child Update Request
+                0x00, 0x02, 0x04, 0x01, // Source Address
+                0x0b, 0x08, 0x12, 0x34, 0x56, 0x78, 64, 0xa1, 0xb2, 5, // Leader Data
+                0x01, 0x01, 0x0a, // Mode: rx-on-when-idle, FTD
+                0x03, 0x08, 8, 7, 6, 5, 4, 3, 2, 1, // Challenge
+                0x02, 0x04, 0x00, 0x00, 0x00, 0xf0, // Timeout
+            ]
+        );
+    }
+}
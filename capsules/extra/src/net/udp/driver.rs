@@ -10,6 +10,7 @@
 //! Also exposes a list of interface addresses to the application (currently
 //! hard-coded).
 
+use crate::net::icmpv6::{ICMP6Header, ICMP6HeaderOptions};
 use crate::net::ipv6::ip_utils::IPAddr;
 use crate::net::network_capabilities::NetworkCapability;
 use crate::net::stream::encode_u16;
@@ -47,8 +48,14 @@ mod upcall {
     /// currently pass information regarding whether packets were acked at the
     /// link layer.
     pub const PACKET_TRANSMITTED: usize = 1;
+    /// Callback for when an ICMPv6 Destination Unreachable or Packet Too Big
+    /// error is received for a packet sent from the bound port. The
+    /// arguments are the ICMPv6 type, the ICMPv6 code, and the MTU of the
+    /// next hop for Packet Too Big errors. The endpoint the packet was sent
+    /// to is written to the rx config buffer.
+    pub const ICMP_ERROR: usize = 2;
     /// Number of upcalls.
-    pub const COUNT: u8 = 2;
+    pub const COUNT: u8 = 3;
 }
 
 /// Ids for read-only allow buffers
@@ -634,6 +641,60 @@ impl UDPRecvClient for UDPDriver<'_> {
             }
         });
     }
+
+    /// Delivers the error to the app bound to `src_addr`:`src_port`.
+    fn receive_error(
+        &self,
+        src_addr: IPAddr,
+        dst_addr: IPAddr,
+        src_port: u16,
+        dst_port: u16,
+        icmp_header: ICMP6Header,
+    ) {
+        let mtu = match icmp_header.get_options() {
+            ICMP6HeaderOptions::Type2 { mtu } => mtu as usize,
+            _ => 0,
+        };
+        self.apps.each(|_, app, kernel_data| {
+            let for_me = app
+                .bound_port
+                .is_some_and(|bound| bound.addr == src_addr && bound.port == src_port);
+            if !for_me {
+                return;
+            }
+            // Write the address the packet was sent to into rx_cfg so the
+            // app can tell which peer is unreachable
+            let dst_endpoint = UDPEndpoint {
+                addr: dst_addr,
+                port: dst_port,
+            };
+            const CFG_LEN: usize = 2 * size_of::<UDPEndpoint>();
+            let _ = kernel_data
+                .get_readwrite_processbuffer(rw_allow::RX_CFG)
+                .and_then(|rx_cfg| {
+                    rx_cfg.mut_enter(|cfg| {
+                        if cfg.len() != CFG_LEN {
+                            return Err(ErrorCode::INVAL);
+                        }
+                        let mut tmp_cfg_buffer: [u8; CFG_LEN] = [0; CFG_LEN];
+                        dst_endpoint.encode(&mut tmp_cfg_buffer, 0);
+                        cfg.copy_from_slice(&tmp_cfg_buffer);
+                        Ok(())
+                    })
+                })
+                .unwrap_or(Err(ErrorCode::INVAL));
+            kernel_data
+                .schedule_upcall(
+                    upcall::ICMP_ERROR,
+                    (
+                        icmp_header.get_type_as_int() as usize,
+                        icmp_header.get_code() as usize,
+                        mtu,
+                    ),
+                )
+                .ok();
+        });
+    }
 }
 
 impl PortQuery for UDPDriver<'_> {
@@ -12,7 +12,9 @@
 //! bindings of kernel apps to ensure correctness when dispatching
 //! received packets to the appropriate client.
 
-use crate::net::ipv6::ip_utils::IPAddr;
+use crate::net::icmpv6::icmpv6_recv::ICMP6RecvClient;
+use crate::net::icmpv6::{ICMP6Header, ICMP6Type};
+use crate::net::ipv6::ip_utils::{ip6_nh, IPAddr};
 use crate::net::ipv6::ipv6_recv::IP6RecvClient;
 use crate::net::ipv6::IP6Header;
 use crate::net::udp::driver::UDPDriver;
@@ -47,6 +49,11 @@ impl<'a> MuxUdpReceiver<'a> {
 
 impl IP6RecvClient for MuxUdpReceiver<'_> {
     fn receive(&self, ip_header: IP6Header, payload: &[u8]) {
+        // The IPv6 layer passes up every received packet, so ignore those
+        // carrying other transport protocols.
+        if ip_header.get_next_header() != ip6_nh::UDP {
+            return;
+        }
         match UDPHeader::decode(payload).done() {
             Some((offset, udp_header)) => {
                 let len = udp_header.get_len() as usize;
@@ -99,6 +106,66 @@ impl IP6RecvClient for MuxUdpReceiver<'_> {
     }
 }
 
+impl ICMP6RecvClient for MuxUdpReceiver<'_> {
+    /// Passes Destination Unreachable and Packet Too Big errors caused by
+    /// one of our datagrams to the client bound to its source port.
+    fn receive(&self, _ip_header: &IP6Header, icmp_header: ICMP6Header, payload: &[u8]) {
+        match icmp_header.get_type() {
+            ICMP6Type::Type1 | ICMP6Type::Type2 => {}
+            _ => return,
+        }
+        // The message body starts with the datagram that caused the error.
+        let (offset, ip_header) = match IP6Header::decode(payload).done() {
+            Some(decoded) => decoded,
+            None => return,
+        };
+        if ip_header.get_next_header() != ip6_nh::UDP {
+            return;
+        }
+        let udp_header = match UDPHeader::decode(&payload[offset..]).done() {
+            Some((_, udp_header)) => udp_header,
+            None => return,
+        };
+        let src_port = udp_header.get_src_port();
+
+        for rcvr in self.rcvr_list.iter() {
+            match rcvr.binding.take() {
+                Some(binding) => {
+                    let bound = binding.get_port() == src_port;
+                    rcvr.binding.replace(binding);
+                    if bound {
+                        rcvr.client.map(|client| {
+                            client.receive_error(
+                                ip_header.get_src_addr(),
+                                ip_header.get_dst_addr(),
+                                src_port,
+                                udp_header.get_dst_port(),
+                                icmp_header,
+                            )
+                        });
+                        break;
+                    }
+                }
+                // The UDPReceiver used by the driver will not have a binding
+                None => {
+                    if let Some(driver) = self.driver.get() {
+                        if driver.is_bound(src_port) {
+                            driver.receive_error(
+                                ip_header.get_src_addr(),
+                                ip_header.get_dst_addr(),
+                                src_port,
+                                udp_header.get_dst_port(),
+                                icmp_header,
+                            );
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
 /// Client interface trait to receive UDP packets.
 ///
 /// Intended to received packets passed up the network stack to the
@@ -114,6 +181,19 @@ pub trait UDPRecvClient {
         dst_port: u16,
         payload: &[u8],
     );
+
+    /// Called when an ICMPv6 Destination Unreachable or Packet Too Big
+    /// error is received for a datagram sent from `src_addr`:`src_port` to
+    /// `dst_addr`:`dst_port`. The default implementation ignores the error.
+    fn receive_error(
+        &self,
+        _src_addr: IPAddr,
+        _dst_addr: IPAddr,
+        _src_port: u16,
+        _dst_port: u16,
+        _icmp_header: ICMP6Header,
+    ) {
+    }
 }
 
 /// This struct is set as the client of the MuxUdpReceiver, and passes
@@ -4,14 +4,32 @@
 
 //! This provides kernel and userspace access to nonvolatile memory.
 //!
-//! This is an initial implementation that does not provide safety for
-//! individual userland applications. Each application has full access to
-//! the entire memory space that has been provided to userland. Future revisions
-//! should update this to limit applications to only their allocated regions.
+//! The userspace accessible memory is split into fixed-size regions, one per
+//! application. Applications are identified by their `ShortId`, so only
+//! applications with a `ShortId::Fixed` can use this driver and an application
+//! finds its region again after a reboot or an update. Each application sees
+//! its own region starting at address 0 and cannot access anything outside of
+//! it.
 //!
-//! However, the kernel accessible memory does not have to be the same range
-//! as the userspace accessible address space. The kernel memory can overlap
-//! if desired, or can be a completely separate range.
+//! The start of the userspace memory holds the allocation table, which records
+//! the `ShortId` owning each region:
+//!
+//! ```text
+//! +-------+-------------+------------+----------+----------+-----+
+//! | magic | region size | ShortId[N] | region 0 | region 1 | ... |
+//! +-------+-------------+------------+----------+----------+-----+
+//! ```
+//!
+//! An application is assigned a free region the first time it reads, writes
+//! or erases. Regions are never freed. The table is loaded by
+//! [`NonvolatileStorage::init`]; until that completes userspace commands fail
+//! with `BUSY`. If no valid table is found, all regions are erased before an
+//! empty table is stored, so an application never sees data left behind by
+//! another application.
+//!
+//! The kernel accessible memory does not have to be the same range as the
+//! userspace accessible address space. The kernel memory can overlap if
+//! desired, or can be a completely separate range.
 //!
 //! Here is a diagram of the expected stack with this capsule:
 //! Boxes are components and between the boxes are the traits that are the
@@ -51,18 +69,21 @@
 //!         3000,                        // The byte start address for the userspace
 //!                                      // accessible memory region.
 //!         2000,                        // The length of the userspace region.
+//!         512,                         // The size of each app's region.
 //!         0,                           // The byte start address of the region
 //!                                      // that is accessible by the kernel.
 //!         3000,                        // The length of the kernel region.
 //!         &mut capsules::nonvolatile_storage_driver::BUFFER));
 //! hil::nonvolatile_storage::NonvolatileStorage::set_client(fm25cl, nonvolatile_storage);
+//! nonvolatile_storage.init();
 //! ```
 
 use core::cell::Cell;
 use core::cmp;
 
-use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, GrantKernelData, UpcallCount};
 use kernel::hil;
+use kernel::process::ShortId;
 use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
 use kernel::syscall::{CommandReturn, SyscallDriver};
 use kernel::utilities::cells::{OptionalCell, TakeCell};
@@ -78,8 +99,10 @@ mod upcall {
     pub const READ_DONE: usize = 0;
     /// Write done callback.
     pub const WRITE_DONE: usize = 1;
+    /// Erase done callback.
+    pub const ERASE_DONE: usize = 2;
     /// Number of upcalls.
-    pub const COUNT: u8 = 2;
+    pub const COUNT: u8 = 3;
 }
 
 /// Ids for read-only allow buffers
@@ -100,23 +123,47 @@ mod rw_allow {
 
 pub const BUF_LEN: usize = 512;
 
+/// Maximum number of application regions.
+pub const MAX_APP_REGIONS: usize = 32;
+
+/// Marks a valid allocation table ("NVST").
+const TABLE_MAGIC: u32 = 0x5453_564e;
+/// Bytes used by the allocation table at the start of the userspace region:
+/// the magic, the region size and one `ShortId` per region.
+pub const TABLE_LEN: usize = 8 + 4 * MAX_APP_REGIONS;
+
+/// Value of erased bytes.
+const ERASED: u8 = 0xff;
+
 #[derive(Clone, Copy, PartialEq)]
 pub enum NonvolatileCommand {
     UserspaceRead,
     UserspaceWrite,
+    UserspaceErase,
     KernelRead,
     KernelWrite,
 }
 
 #[derive(Clone, Copy)]
 pub enum NonvolatileUser {
-    App { processid: ProcessId },
+    App {
+        processid: ProcessId,
+    },
+    /// An app is erasing part of its region.
+    AppErase {
+        processid: ProcessId,
+    },
     Kernel,
+    /// The allocation table is being loaded or stored.
+    RegionTable,
+    /// All regions are being erased because no valid table was found.
+    Format,
 }
 
 pub struct App {
     pending_command: bool,
     command: NonvolatileCommand,
+    region: usize,
     offset: usize,
     length: usize,
 }
@@ -126,6 +173,7 @@ impl Default for App {
         App {
             pending_command: false,
             command: NonvolatileCommand::UserspaceRead,
+            region: 0,
             offset: 0,
             length: 0,
         }
@@ -150,13 +198,30 @@ pub struct NonvolatileStorage<'a> {
 
     // The first byte that is accessible from userspace.
     userspace_start_address: usize,
-    // How many bytes allocated to userspace.
-    userspace_length: usize,
+    // How many bytes each app gets.
+    app_region_size: usize,
+    // How many app regions fit in the userspace region.
+    region_count: usize,
+    // The start of a valid allocation table.
+    table_header: [u8; 8],
     // The first byte that is accessible from the kernel.
     kernel_start_address: usize,
     // How many bytes allocated to kernel.
     kernel_length: usize,
 
+    // Copy of the allocation table. Entry `i` holds the little endian
+    // `ShortId` of the app owning region `i`, or 0 or 0xFFFFFFFF if the
+    // region is free.
+    regions: Cell<[[u8; 4]; MAX_APP_REGIONS]>,
+    // Whether `regions` has been loaded from storage.
+    table_loaded: Cell<bool>,
+    // Whether `regions` has changes that have not been stored yet.
+    table_dirty: Cell<bool>,
+    // Next physical address to erase while formatting.
+    erase_address: Cell<usize>,
+    // Physical address to stop formatting at.
+    erase_end: Cell<usize>,
+
     // Optional client for the kernel. Only needed if the kernel intends to use
     // this nonvolatile storage.
     kernel_client: OptionalCell<&'a dyn hil::nonvolatile_storage::NonvolatileStorageClient>,
@@ -183,6 +248,7 @@ impl<'a> NonvolatileStorage<'a> {
         >,
         userspace_start_address: usize,
         userspace_length: usize,
+        app_region_size: usize,
         kernel_start_address: usize,
         kernel_length: usize,
         buffer: &'static mut [u8],
@@ -193,9 +259,27 @@ impl<'a> NonvolatileStorage<'a> {
             buffer: TakeCell::new(buffer),
             current_user: OptionalCell::empty(),
             userspace_start_address,
-            userspace_length,
+            app_region_size,
+            region_count: cmp::min(
+                MAX_APP_REGIONS,
+                userspace_length
+                    .saturating_sub(TABLE_LEN)
+                    .checked_div(app_region_size)
+                    .unwrap_or(0),
+            ),
+            table_header: {
+                let mut header = [0; 8];
+                header[..4].copy_from_slice(&TABLE_MAGIC.to_le_bytes());
+                header[4..].copy_from_slice(&(app_region_size as u32).to_le_bytes());
+                header
+            },
             kernel_start_address,
             kernel_length,
+            regions: Cell::new([[0; 4]; MAX_APP_REGIONS]),
+            table_loaded: Cell::new(false),
+            table_dirty: Cell::new(false),
+            erase_address: Cell::new(0),
+            erase_end: Cell::new(0),
             kernel_client: OptionalCell::empty(),
             kernel_pending_command: Cell::new(false),
             kernel_command: Cell::new(NonvolatileCommand::KernelRead),
@@ -205,6 +289,60 @@ impl<'a> NonvolatileStorage<'a> {
         }
     }
 
+    /// Load the allocation table from storage. Userspace commands fail with
+    /// `BUSY` until this completes.
+    pub fn init(&self) -> Result<(), ErrorCode> {
+        if self.table_loaded.get() {
+            return Err(ErrorCode::ALREADY);
+        }
+        if self.current_user.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.buffer
+            .take()
+            .map_or(Err(ErrorCode::RESERVE), |buffer| {
+                self.current_user.set(NonvolatileUser::RegionTable);
+                self.driver
+                    .read(buffer, self.userspace_start_address, TABLE_LEN)
+                    .inspect_err(|_| self.current_user.clear())
+            })
+    }
+
+    // Physical address of the start of an app region.
+    fn region_address(&self, region: usize) -> usize {
+        self.userspace_start_address + TABLE_LEN + region * self.app_region_size
+    }
+
+    // Find the region belonging to this app, assigning a free one if the app
+    // does not have one yet.
+    fn app_region(&self, processid: ProcessId) -> Result<usize, ErrorCode> {
+        let id = match processid.short_app_id() {
+            ShortId::Fixed(id) => id.get().to_le_bytes(),
+            // Without a persistent identity the app cannot be given a region
+            // it would find again.
+            ShortId::LocallyUnique => return Err(ErrorCode::NOSUPPORT),
+        };
+        if !self.table_loaded.get() {
+            return Err(ErrorCode::BUSY);
+        }
+
+        let mut regions = self.regions.get();
+        let mut free = None;
+        for (region, owner) in regions[..self.region_count].iter().enumerate() {
+            if *owner == id {
+                return Ok(region);
+            }
+            if free.is_none() && (*owner == [0; 4] || *owner == [0xff; 4]) {
+                free = Some(region);
+            }
+        }
+        let region = free.ok_or(ErrorCode::NOMEM)?;
+        regions[region] = id;
+        self.regions.set(regions);
+        self.table_dirty.set(true);
+        Ok(region)
+    }
+
     // Check so see if we are doing something. If not, go ahead and do this
     // command. If so, this is queued and will be run when the pending
     // command completes.
@@ -217,12 +355,16 @@ impl<'a> NonvolatileStorage<'a> {
     ) -> Result<(), ErrorCode> {
         // Do bounds check.
         match command {
-            NonvolatileCommand::UserspaceRead | NonvolatileCommand::UserspaceWrite => {
+            NonvolatileCommand::UserspaceRead
+            | NonvolatileCommand::UserspaceWrite
+            | NonvolatileCommand::UserspaceErase => {
                 // Userspace sees memory that starts at address 0 even if it
-                // is offset in the physical memory.
-                if offset >= self.userspace_length
-                    || length > self.userspace_length
-                    || offset + length > self.userspace_length
+                // is offset in the physical memory, and only sees its own
+                // region.
+                if offset >= self.app_region_size
+                    || (length == 0 && command == NonvolatileCommand::UserspaceErase)
+                    || length > self.app_region_size
+                    || offset + length > self.app_region_size
                 {
                     return Err(ErrorCode::INVAL);
                 }
@@ -243,9 +385,14 @@ impl<'a> NonvolatileStorage<'a> {
         // Do very different actions if this is a call from userspace
         // or from the kernel.
         match command {
-            NonvolatileCommand::UserspaceRead | NonvolatileCommand::UserspaceWrite => {
+            NonvolatileCommand::UserspaceRead
+            | NonvolatileCommand::UserspaceWrite
+            | NonvolatileCommand::UserspaceErase => {
                 processid.map_or(Err(ErrorCode::FAIL), |processid| {
-                    self.apps
+                    let region = self.app_region(processid)?;
+
+                    let res = self
+                        .apps
                         .enter(processid, |app, kernel_data| {
                             // Get the length of the correct allowed buffer.
                             let allow_buf_len = match command {
@@ -255,11 +402,12 @@ impl<'a> NonvolatileStorage<'a> {
                                 NonvolatileCommand::UserspaceWrite => kernel_data
                                     .get_readonly_processbuffer(ro_allow::WRITE)
                                     .map_or(0, |read| read.len()),
-                                _ => 0,
+                                // Erasing does not use a buffer.
+                                _ => length,
                             };
 
                             // Check that it exists.
-                            if allow_buf_len == 0 || self.buffer.is_none() {
+                            if allow_buf_len == 0 {
                                 return Err(ErrorCode::RESERVE);
                             }
 
@@ -269,38 +417,20 @@ impl<'a> NonvolatileStorage<'a> {
 
                             // First need to determine if we can execute this or must
                             // queue it.
-                            if self.current_user.is_none() {
+                            if self.current_user.is_none() && !self.table_dirty.get() {
                                 // No app is currently using the underlying storage.
                                 // Mark this app as active, and then execute the command.
-                                self.current_user.set(NonvolatileUser::App { processid });
-
-                                // Need to copy bytes if this is a write!
-                                if command == NonvolatileCommand::UserspaceWrite {
-                                    let _ = kernel_data
-                                        .get_readonly_processbuffer(ro_allow::WRITE)
-                                        .and_then(|write| {
-                                            write.enter(|app_buffer| {
-                                                self.buffer.map(|kernel_buffer| {
-                                                    // Check that the internal buffer and the buffer that was
-                                                    // allowed are long enough.
-                                                    let write_len =
-                                                        cmp::min(active_len, kernel_buffer.len());
-
-                                                    let d = &app_buffer[0..write_len];
-                                                    for (i, c) in kernel_buffer[0..write_len]
-                                                        .iter_mut()
-                                                        .enumerate()
-                                                    {
-                                                        *c = d[i].get();
-                                                    }
-                                                });
-                                            })
-                                        });
-                                }
-
-                                self.userspace_call_driver(command, offset, active_len)
+                                self.userspace_call_driver(
+                                    processid,
+                                    kernel_data,
+                                    command,
+                                    region,
+                                    offset,
+                                    active_len,
+                                )
                             } else {
-                                // Some app is using the storage, we must wait.
+                                // Some app is using the storage or the region
+                                // still has to be recorded, we must wait.
                                 if app.pending_command {
                                     // No more room in the queue, nowhere to store this
                                     // request.
@@ -309,13 +439,21 @@ impl<'a> NonvolatileStorage<'a> {
                                     // We can store this, so lets do it.
                                     app.pending_command = true;
                                     app.command = command;
+                                    app.region = region;
                                     app.offset = offset;
                                     app.length = active_len;
                                     Ok(())
                                 }
                             }
                         })
-                        .unwrap_or_else(|err| Err(err.into()))
+                        .unwrap_or_else(|err| Err(err.into()));
+
+                    // If the command was queued because its region still has
+                    // to be recorded, start doing so.
+                    if self.current_user.is_none() {
+                        self.check_queue();
+                    }
+                    res
                 })
             }
             NonvolatileCommand::KernelRead | NonvolatileCommand::KernelWrite => {
@@ -327,17 +465,7 @@ impl<'a> NonvolatileStorage<'a> {
                         // Check if there is something going on.
                         if self.current_user.is_none() {
                             // Nothing is using this, lets go!
-                            self.current_user.set(NonvolatileUser::Kernel);
-
-                            match command {
-                                NonvolatileCommand::KernelRead => {
-                                    self.driver.read(kernel_buffer, offset, active_len)
-                                }
-                                NonvolatileCommand::KernelWrite => {
-                                    self.driver.write(kernel_buffer, offset, active_len)
-                                }
-                                _ => Err(ErrorCode::FAIL),
-                            }
+                            self.kernel_call_driver(kernel_buffer, command, offset, active_len)
                         } else {
                             if self.kernel_pending_command.get() {
                                 Err(ErrorCode::NOMEM)
@@ -355,15 +483,33 @@ impl<'a> NonvolatileStorage<'a> {
         }
     }
 
+    fn kernel_call_driver(
+        &self,
+        buffer: &'static mut [u8],
+        command: NonvolatileCommand,
+        address: usize,
+        length: usize,
+    ) -> Result<(), ErrorCode> {
+        self.current_user.set(NonvolatileUser::Kernel);
+        match command {
+            NonvolatileCommand::KernelRead => self.driver.read(buffer, address, length),
+            NonvolatileCommand::KernelWrite => self.driver.write(buffer, address, length),
+            _ => Err(ErrorCode::FAIL),
+        }
+    }
+
     fn userspace_call_driver(
         &self,
+        processid: ProcessId,
+        kernel_data: &GrantKernelData,
         command: NonvolatileCommand,
+        region: usize,
         offset: usize,
         length: usize,
     ) -> Result<(), ErrorCode> {
         // Calculate where we want to actually read from in the physical
         // storage.
-        let physical_address = offset + self.userspace_start_address;
+        let physical_address = self.region_address(region) + offset;
 
         self.buffer
             .take()
@@ -372,16 +518,59 @@ impl<'a> NonvolatileStorage<'a> {
                 // allowed are long enough.
                 let active_len = cmp::min(length, buffer.len());
 
-                // self.current_app.set(Some(processid));
-                match command {
+                let res = match command {
                     NonvolatileCommand::UserspaceRead => {
+                        self.current_user.set(NonvolatileUser::App { processid });
                         self.driver.read(buffer, physical_address, active_len)
                     }
                     NonvolatileCommand::UserspaceWrite => {
+                        // Need to copy bytes if this is a write!
+                        let _ = kernel_data
+                            .get_readonly_processbuffer(ro_allow::WRITE)
+                            .and_then(|write| {
+                                write.enter(|app_buffer| {
+                                    let write_len = cmp::min(active_len, app_buffer.len());
+                                    app_buffer[0..write_len]
+                                        .copy_to_slice(&mut buffer[0..write_len]);
+                                })
+                            });
+                        self.current_user.set(NonvolatileUser::App { processid });
+                        self.driver.write(buffer, physical_address, active_len)
+                    }
+                    NonvolatileCommand::UserspaceErase => {
+                        buffer[..active_len].fill(ERASED);
+                        self.current_user
+                            .set(NonvolatileUser::AppErase { processid });
                         self.driver.write(buffer, physical_address, active_len)
                     }
                     _ => Err(ErrorCode::FAIL),
-                }
+                };
+                res.inspect_err(|_| self.current_user.clear())
+            })
+    }
+
+    // Erase the next chunk of the regions being formatted by writing it with
+    // `ERASED` bytes.
+    fn erase_next(&self) -> Result<(), ErrorCode> {
+        self.buffer
+            .take()
+            .map_or(Err(ErrorCode::RESERVE), |buffer| {
+                let address = self.erase_address.get();
+                let length = cmp::min(buffer.len(), self.erase_end.get() - address);
+                buffer[..length].fill(ERASED);
+                self.driver.write(buffer, address, length)
+            })
+    }
+
+    // Store the allocation table at the start of the userspace region.
+    fn store_table(&self) -> Result<(), ErrorCode> {
+        self.buffer
+            .take()
+            .map_or(Err(ErrorCode::RESERVE), |buffer| {
+                buffer[..8].copy_from_slice(&self.table_header);
+                buffer[8..TABLE_LEN].copy_from_slice(self.regions.get().as_flattened());
+                self.driver
+                    .write(buffer, self.userspace_start_address, TABLE_LEN)
             })
     }
 
@@ -390,44 +579,49 @@ impl<'a> NonvolatileStorage<'a> {
         if self.kernel_pending_command.get() {
             self.kernel_buffer.take().map(|kernel_buffer| {
                 self.kernel_pending_command.set(false);
-                self.current_user.set(NonvolatileUser::Kernel);
-
-                match self.kernel_command.get() {
-                    NonvolatileCommand::KernelRead => self.driver.read(
-                        kernel_buffer,
-                        self.kernel_readwrite_address.get(),
-                        self.kernel_readwrite_length.get(),
-                    ),
-                    NonvolatileCommand::KernelWrite => self.driver.write(
-                        kernel_buffer,
-                        self.kernel_readwrite_address.get(),
-                        self.kernel_readwrite_length.get(),
-                    ),
-                    _ => Err(ErrorCode::FAIL),
-                }
+                self.kernel_call_driver(
+                    kernel_buffer,
+                    self.kernel_command.get(),
+                    self.kernel_readwrite_address.get(),
+                    self.kernel_readwrite_length.get(),
+                )
             });
-        } else {
-            // If the kernel is not requesting anything, check all of the apps.
-            for cntr in self.apps.iter() {
-                let processid = cntr.processid();
-                let started_command = cntr.enter(|app, _| {
-                    if app.pending_command {
-                        app.pending_command = false;
-                        self.current_user.set(NonvolatileUser::App { processid });
-                        if let Ok(()) =
-                            self.userspace_call_driver(app.command, app.offset, app.length)
-                        {
-                            true
-                        } else {
-                            false
-                        }
-                    } else {
-                        false
-                    }
-                });
-                if started_command {
-                    break;
+            return;
+        }
+
+        // Next, record newly assigned regions in the table. Regions assigned
+        // while the table is being stored mark it dirty again.
+        if self.table_dirty.get() {
+            self.table_dirty.set(false);
+            self.current_user.set(NonvolatileUser::RegionTable);
+            if self.store_table().is_ok() {
+                return;
+            }
+            self.current_user.clear();
+            self.table_dirty.set(true);
+        }
+
+        // If the kernel is not requesting anything, check all of the apps.
+        for cntr in self.apps.iter() {
+            let processid = cntr.processid();
+            let started_command = cntr.enter(|app, kernel_data| {
+                if app.pending_command && !self.table_dirty.get() {
+                    app.pending_command = false;
+                    self.userspace_call_driver(
+                        processid,
+                        kernel_data,
+                        app.command,
+                        app.region,
+                        app.offset,
+                        app.length,
+                    )
+                    .is_ok()
+                } else {
+                    false
                 }
+            });
+            if started_command {
+                break;
             }
         }
     }
@@ -445,34 +639,58 @@ impl hil::nonvolatile_storage::NonvolatileStorageClient for NonvolatileStorage<'
                     });
                 }
                 NonvolatileUser::App { processid } => {
-                    let _ = self.apps.enter(processid, move |_, kernel_data| {
+                    let _ = self.apps.enter(processid, |_, kernel_data| {
                         // Need to copy in the contents of the buffer
                         let _ = kernel_data
                             .get_readwrite_processbuffer(rw_allow::READ)
                             .and_then(|read| {
                                 read.mut_enter(|app_buffer| {
                                     let read_len = cmp::min(app_buffer.len(), length);
-
-                                    let d = &app_buffer[0..read_len];
-                                    for (i, c) in buffer[0..read_len].iter().enumerate() {
-                                        d[i].set(*c);
-                                    }
+                                    app_buffer[0..read_len].copy_from_slice(&buffer[0..read_len]);
                                 })
                             });
 
-                        // Replace the buffer we used to do this read.
-                        self.buffer.replace(buffer);
-
                         // And then signal the app.
                         kernel_data
                             .schedule_upcall(upcall::READ_DONE, (length, 0, 0))
                             .ok();
                     });
+
+                    // Replace the buffer we used to do this read.
+                    self.buffer.replace(buffer);
+                }
+                NonvolatileUser::RegionTable => {
+                    // Anything but a table written for the same region size is
+                    // treated as no table.
+                    let valid = buffer[..8] == self.table_header;
+                    if valid {
+                        let mut regions = [[0; 4]; MAX_APP_REGIONS];
+                        regions
+                            .as_flattened_mut()
+                            .copy_from_slice(&buffer[8..TABLE_LEN]);
+                        self.regions.set(regions);
+                        self.table_loaded.set(true);
+                    }
+                    self.buffer.replace(buffer);
+
+                    if !valid {
+                        self.erase_address.set(self.userspace_start_address);
+                        self.erase_end.set(self.region_address(self.region_count));
+                        self.current_user.set(NonvolatileUser::Format);
+                        if self.erase_next().is_err() {
+                            self.current_user.clear();
+                        }
+                    }
+                }
+                _ => {
+                    self.buffer.replace(buffer);
                 }
             }
         });
 
-        self.check_queue();
+        if self.current_user.is_none() {
+            self.check_queue();
+        }
     }
 
     fn write_done(&self, buffer: &'static mut [u8], length: usize) {
@@ -484,21 +702,43 @@ impl hil::nonvolatile_storage::NonvolatileStorageClient for NonvolatileStorage<'
                         client.write_done(buffer, length);
                     });
                 }
-                NonvolatileUser::App { processid } => {
-                    let _ = self.apps.enter(processid, move |_app, kernel_data| {
-                        // Replace the buffer we used to do this write.
-                        self.buffer.replace(buffer);
-
-                        // And then signal the app.
-                        kernel_data
-                            .schedule_upcall(upcall::WRITE_DONE, (length, 0, 0))
-                            .ok();
+                NonvolatileUser::App { processid } | NonvolatileUser::AppErase { processid } => {
+                    // Replace the buffer we used to do this write.
+                    self.buffer.replace(buffer);
+
+                    // And then signal the app.
+                    let upcall_num = match user {
+                        NonvolatileUser::AppErase { .. } => upcall::ERASE_DONE,
+                        _ => upcall::WRITE_DONE,
+                    };
+                    let _ = self.apps.enter(processid, |_app, kernel_data| {
+                        kernel_data.schedule_upcall(upcall_num, (length, 0, 0)).ok();
                     });
                 }
+                NonvolatileUser::Format => {
+                    self.buffer.replace(buffer);
+                    self.erase_address.set(self.erase_address.get() + length);
+                    if self.erase_address.get() < self.erase_end.get() {
+                        self.current_user.set(user);
+                        if self.erase_next().is_ok() {
+                            return;
+                        }
+                        self.current_user.clear();
+                    } else {
+                        // Store the empty table.
+                        self.table_loaded.set(true);
+                        self.table_dirty.set(true);
+                    }
+                }
+                NonvolatileUser::RegionTable => {
+                    self.buffer.replace(buffer);
+                }
             }
         });
 
-        self.check_queue();
+        if self.current_user.is_none() {
+            self.check_queue();
+        }
     }
 }
 
@@ -538,9 +778,15 @@ impl SyscallDriver for NonvolatileStorage<'_> {
     /// ### `command_num`
     ///
     /// - `0`: Return Ok(()) if this driver is included on the platform.
-    /// - `1`: Return the number of bytes available to userspace.
+    /// - `1`: Return the number of bytes available to this app.
     /// - `2`: Start a read from the nonvolatile storage.
     /// - `3`: Start a write to the nonvolatile_storage.
+    /// - `4`: Start an erase of the nonvolatile storage, setting the bytes to
+    ///   0xFF. Like reads and writes, at most `BUF_LEN` bytes are erased at
+    ///   once and the upcall reports how many.
+    ///
+    /// Addresses are relative to the start of the app's region. Apps without
+    /// a `ShortId::Fixed` get `NOSUPPORT`.
     fn command(
         &self,
         command_num: usize,
@@ -551,11 +797,12 @@ impl SyscallDriver for NonvolatileStorage<'_> {
         match command_num {
             0 => CommandReturn::success(),
 
-            1 => {
-                // How many bytes are accessible from userspace
+            1 => match processid.short_app_id() {
+                // How many bytes are accessible from this app
                 // TODO: Would break on 64-bit platforms
-                CommandReturn::success_u32(self.userspace_length as u32)
-            }
+                ShortId::Fixed(_) => CommandReturn::success_u32(self.app_region_size as u32),
+                ShortId::LocallyUnique => CommandReturn::failure(ErrorCode::NOSUPPORT),
+            },
 
             2 => {
                 // Issue a read command
@@ -587,6 +834,21 @@ impl SyscallDriver for NonvolatileStorage<'_> {
                 }
             }
 
+            4 => {
+                // Issue an erase command
+                let res = self.enqueue_command(
+                    NonvolatileCommand::UserspaceErase,
+                    offset,
+                    length,
+                    Some(processid),
+                );
+
+                match res {
+                    Ok(()) => CommandReturn::success(),
+                    Err(e) => CommandReturn::failure(e),
+                }
+            }
+
             _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
         }
     }
@@ -0,0 +1,281 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! PBKDF2-HMAC-SHA256 password based key derivation (RFC 8018) on top of an
+//! HMAC-SHA256 engine.
+//!
+//! The salt is copied into the data buffer given to [`Pbkdf2Sha256::new`],
+//! which must hold 32 bytes of working space, the salt and a 4 byte block
+//! counter. Every iteration is a separate HMAC operation, so large iteration
+//! counts take a long time; the capsule never blocks while doing so.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let pbkdf2 = static_init!(
+//!     capsules_extra::pbkdf2::Pbkdf2Sha256<'static, HmacSha256Software<'static, Sha256Software<'static>>>,
+//!     capsules_extra::pbkdf2::Pbkdf2Sha256::new(hmac, data_buffer, digest_buffer)
+//! );
+//! kernel::hil::digest::Digest::set_client(hmac, pbkdf2);
+//! pbkdf2.set_client(client);
+//!
+//! pbkdf2.derive(password, salt, 4096, dk);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::digest;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::{SubSlice, SubSliceMut};
+use kernel::ErrorCode;
+
+const HASH_LEN: usize = 32;
+/// Longest password supported, which is the block size of SHA-256.
+pub const MAX_PASSWORD_LEN: usize = 64;
+
+pub trait Pbkdf2Client {
+    /// Called when a derivation started with `derive` completes.
+    ///
+    /// On success `dk` holds the derived key.
+    fn derive_done(&self, result: Result<(), ErrorCode>, dk: &'static mut [u8]);
+}
+
+pub struct Pbkdf2Sha256<'a, H: digest::Digest<'a, 32> + digest::HmacSha256> {
+    hmac: &'a H,
+    client: OptionalCell<&'a dyn Pbkdf2Client>,
+    busy: Cell<bool>,
+
+    /// Holds the previous `U` value followed by the salt and block counter.
+    data_buffer: TakeCell<'static, [u8]>,
+    digest_buffer: TakeCell<'static, [u8; 32]>,
+    dk: TakeCell<'static, [u8]>,
+
+    password: Cell<[u8; MAX_PASSWORD_LEN]>,
+    password_len: Cell<usize>,
+    salt_len: Cell<usize>,
+    iterations: Cell<u32>,
+
+    /// The block being computed, starting at 1.
+    block: Cell<u32>,
+    /// The iteration of the current block, starting at 1.
+    iteration: Cell<u32>,
+    /// XOR of all `U` values of the current block.
+    accumulator: Cell<[u8; HASH_LEN]>,
+    produced: Cell<usize>,
+}
+
+impl<'a, H: digest::Digest<'a, 32> + digest::HmacSha256> Pbkdf2Sha256<'a, H> {
+    pub fn new(
+        hmac: &'a H,
+        data_buffer: &'static mut [u8],
+        digest_buffer: &'static mut [u8; 32],
+    ) -> Self {
+        Pbkdf2Sha256 {
+            hmac,
+            client: OptionalCell::empty(),
+            busy: Cell::new(false),
+            data_buffer: TakeCell::new(data_buffer),
+            digest_buffer: TakeCell::new(digest_buffer),
+            dk: TakeCell::empty(),
+            password: Cell::new([0; MAX_PASSWORD_LEN]),
+            password_len: Cell::new(0),
+            salt_len: Cell::new(0),
+            iterations: Cell::new(0),
+            block: Cell::new(0),
+            iteration: Cell::new(0),
+            accumulator: Cell::new([0; HASH_LEN]),
+            produced: Cell::new(0),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn Pbkdf2Client) {
+        self.client.set(client);
+    }
+
+    /// Derive `dk.len()` bytes of key from `password` and `salt` using
+    /// `iterations` rounds of HMAC-SHA256 per output block.
+    ///
+    /// ### Return
+    ///
+    /// - On success returns `Ok(())` and `derive_done` will be called.
+    /// - On error, returns `dk` and:
+    ///   - `BUSY`: A derivation is already in progress.
+    ///   - `INVAL`: `iterations` is zero.
+    ///   - `SIZE`: `dk` is empty, `password` is longer than
+    ///     `MAX_PASSWORD_LEN` or `salt` does not fit in the data buffer.
+    pub fn derive(
+        &self,
+        password: &[u8],
+        salt: &[u8],
+        iterations: u32,
+        dk: &'static mut [u8],
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.busy.get() {
+            return Err((ErrorCode::BUSY, dk));
+        }
+        if iterations == 0 {
+            return Err((ErrorCode::INVAL, dk));
+        }
+        if dk.is_empty() || password.len() > MAX_PASSWORD_LEN {
+            return Err((ErrorCode::SIZE, dk));
+        }
+
+        let copied = self.data_buffer.map_or(Err(ErrorCode::BUSY), |buf| {
+            if buf.len() < HASH_LEN + salt.len() + 4 {
+                return Err(ErrorCode::SIZE);
+            }
+            buf[HASH_LEN..HASH_LEN + salt.len()].copy_from_slice(salt);
+            Ok(())
+        });
+        if let Err(e) = copied {
+            return Err((e, dk));
+        }
+
+        let mut stored = [0; MAX_PASSWORD_LEN];
+        stored[..password.len()].copy_from_slice(password);
+        self.password.set(stored);
+        self.password_len.set(password.len());
+        self.salt_len.set(salt.len());
+        self.iterations.set(iterations);
+        self.produced.set(0);
+        self.block.set(1);
+        self.busy.set(true);
+
+        match self.start_block() {
+            Ok(()) => {
+                self.dk.replace(dk);
+                Ok(())
+            }
+            Err(e) => {
+                self.busy.set(false);
+                self.scrub();
+                Err((e, dk))
+            }
+        }
+    }
+
+    /// U_1 = PRF(P, S || INT(i))
+    fn start_block(&self) -> Result<(), ErrorCode> {
+        let counter_offset = HASH_LEN + self.salt_len.get();
+        let block = self.block.get();
+        self.data_buffer.map(|buf| {
+            buf[counter_offset..counter_offset + 4].copy_from_slice(&block.to_be_bytes())
+        });
+        self.iteration.set(1);
+        self.accumulator.set([0; HASH_LEN]);
+        self.start_hmac(HASH_LEN, counter_offset + 4)
+    }
+
+    /// Start an HMAC keyed with the password over `data_buffer[start..end]`.
+    fn start_hmac(&self, start: usize, end: usize) -> Result<(), ErrorCode> {
+        self.hmac
+            .set_mode_hmacsha256(&self.password.get()[..self.password_len.get()])?;
+        let buf = self.data_buffer.take().ok_or(ErrorCode::FAIL)?;
+        let mut data = SubSliceMut::new(buf);
+        data.slice(start..end);
+        self.hmac.add_mut_data(data).map_err(|(e, data)| {
+            self.data_buffer.replace(data.take());
+            e
+        })
+    }
+
+    /// Erase the password and intermediate values.
+    fn scrub(&self) {
+        self.password.set([0; MAX_PASSWORD_LEN]);
+        self.accumulator.set([0; HASH_LEN]);
+        self.data_buffer
+            .map(|buf| buf.iter_mut().for_each(|b| *b = 0));
+    }
+
+    fn done(&self, result: Result<(), ErrorCode>) {
+        self.busy.set(false);
+        self.scrub();
+        if result.is_err() {
+            self.hmac.clear_data();
+        }
+        self.dk.take().map(|dk| {
+            self.client.map(|client| client.derive_done(result, dk));
+        });
+    }
+}
+
+impl<'a, H: digest::Digest<'a, 32> + digest::HmacSha256> digest::ClientData<32>
+    for Pbkdf2Sha256<'a, H>
+{
+    fn add_data_done(&self, _result: Result<(), ErrorCode>, _data: SubSlice<'static, u8>) {}
+
+    fn add_mut_data_done(&self, result: Result<(), ErrorCode>, data: SubSliceMut<'static, u8>) {
+        self.data_buffer.replace(data.take());
+        if !self.busy.get() {
+            return;
+        }
+
+        let result = result.and_then(|()| {
+            let digest = self.digest_buffer.take().ok_or(ErrorCode::FAIL)?;
+            self.hmac.run(digest).map_err(|(e, digest)| {
+                self.digest_buffer.replace(digest);
+                e
+            })
+        });
+        if let Err(e) = result {
+            self.done(Err(e));
+        }
+    }
+}
+
+impl<'a, H: digest::Digest<'a, 32> + digest::HmacSha256> digest::ClientHash<32>
+    for Pbkdf2Sha256<'a, H>
+{
+    fn hash_done(&self, result: Result<(), ErrorCode>, digest: &'static mut [u8; 32]) {
+        let u = *digest;
+        digest.iter_mut().for_each(|b| *b = 0);
+        self.digest_buffer.replace(digest);
+        if !self.busy.get() {
+            return;
+        }
+        if let Err(e) = result {
+            self.done(Err(e));
+            return;
+        }
+
+        let mut t = self.accumulator.get();
+        t.iter_mut().zip(u.iter()).for_each(|(t, u)| *t ^= u);
+        self.accumulator.set(t);
+
+        let iteration = self.iteration.get();
+        let result = if iteration < self.iterations.get() {
+            // U_j = PRF(P, U_{j-1})
+            self.iteration.set(iteration + 1);
+            self.data_buffer
+                .map(|buf| buf[..HASH_LEN].copy_from_slice(&u));
+            self.start_hmac(0, HASH_LEN)
+        } else {
+            // T_i is complete, copy it out.
+            let produced = self.produced.get();
+            let remaining = self.dk.map_or(0, |dk| {
+                let n = core::cmp::min(HASH_LEN, dk.len() - produced);
+                dk[produced..produced + n].copy_from_slice(&t[..n]);
+                self.produced.set(produced + n);
+                dk.len() - produced - n
+            });
+            if remaining == 0 {
+                self.done(Ok(()));
+                return;
+            }
+            self.block.set(self.block.get() + 1);
+            self.start_block()
+        };
+        if let Err(e) = result {
+            self.done(Err(e));
+        }
+    }
+}
+
+impl<'a, H: digest::Digest<'a, 32> + digest::HmacSha256> digest::ClientVerify<32>
+    for Pbkdf2Sha256<'a, H>
+{
+    fn verification_done(&self, _result: Result<bool, ErrorCode>, _compare: &'static mut [u8; 32]) {
+    }
+}
@@ -0,0 +1,299 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Software ECDSA signature verification on the NIST P-256 curve.
+//!
+//! The verifier checks signatures against a single public key, which is the
+//! 64 byte uncompressed curve point `x || y` with both coordinates in
+//! big-endian order. Signatures are the 64 byte concatenation `r || s`, also
+//! big-endian. The hash can be of any length, and as ECDSA specifies, only
+//! its leftmost 256 bits are used.
+//!
+//! Verification runs in a deferred call and takes long enough (tens of
+//! milliseconds on a Cortex-M4) that it should not be used on hot paths. It
+//! does not need to run in constant time as it only handles public values.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let verifier = static_init!(
+//!     capsules_extra::public_key_crypto::ecdsa_p256::EcdsaP256SignatureVerifier<'static, 32>,
+//!     capsules_extra::public_key_crypto::ecdsa_p256::EcdsaP256SignatureVerifier::new(
+//!         public_key
+//!     )
+//! );
+//! kernel::deferred_call::DeferredCallClient::register(verifier);
+//! ```
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::public_key_crypto::signature::{ClientVerify, SignatureVerify};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+use super::modular::{Modulus, U256};
+
+/// The field prime `p = 2^256 - 2^224 + 2^192 + 2^96 - 1`.
+const P: Modulus = Modulus::new(U256::from_be_bytes(&[
+    0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+]));
+
+/// The order `n` of the base point.
+const N: Modulus = Modulus::new(U256::from_be_bytes(&[
+    0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xbc, 0xe6, 0xfa, 0xad, 0xa7, 0x17, 0x9e, 0x84, 0xf3, 0xb9, 0xca, 0xc2, 0xfc, 0x63, 0x25, 0x51,
+]));
+
+/// The constant `b` of the curve equation `y^2 = x^3 - 3x + b`.
+const B: U256 = U256::from_be_bytes(&[
+    0x5a, 0xc6, 0x35, 0xd8, 0xaa, 0x3a, 0x93, 0xe7, 0xb3, 0xeb, 0xbd, 0x55, 0x76, 0x98, 0x86, 0xbc,
+    0x65, 0x1d, 0x06, 0xb0, 0xcc, 0x53, 0xb0, 0xf6, 0x3b, 0xce, 0x3c, 0x3e, 0x27, 0xd2, 0x60, 0x4b,
+]);
+
+/// The x coordinate of the base point.
+const GX: U256 = U256::from_be_bytes(&[
+    0x6b, 0x17, 0xd1, 0xf2, 0xe1, 0x2c, 0x42, 0x47, 0xf8, 0xbc, 0xe6, 0xe5, 0x63, 0xa4, 0x40, 0xf2,
+    0x77, 0x03, 0x7d, 0x81, 0x2d, 0xeb, 0x33, 0xa0, 0xf4, 0xa1, 0x39, 0x45, 0xd8, 0x98, 0xc2, 0x96,
+]);
+
+/// The y coordinate of the base point.
+const GY: U256 = U256::from_be_bytes(&[
+    0x4f, 0xe3, 0x42, 0xe2, 0xfe, 0x1a, 0x7f, 0x9b, 0x8e, 0xe7, 0xeb, 0x4a, 0x7c, 0x0f, 0x9e, 0x16,
+    0x2b, 0xce, 0x33, 0x57, 0x6b, 0x31, 0x5e, 0xce, 0xcb, 0xb6, 0x40, 0x68, 0x37, 0xbf, 0x51, 0xf5,
+]);
+
+/// A point in Jacobian coordinates, `(X / Z^2, Y / Z^3)`, with all
+/// coordinates in Montgomery representation modulo `p`. `Z` is zero for the
+/// point at infinity.
+#[derive(Clone, Copy)]
+struct Point {
+    x: U256,
+    y: U256,
+    z: U256,
+}
+
+impl Point {
+    const INFINITY: Point = Point {
+        x: U256::ZERO,
+        y: U256::ZERO,
+        z: U256::ZERO,
+    };
+
+    /// Creates a point from affine coordinates, which must be less than `p`,
+    /// if it is on the curve.
+    fn from_affine(x: &U256, y: &U256) -> Option<Point> {
+        let x = P.to_montgomery(x);
+        let y = P.to_montgomery(y);
+
+        // y^2 = x^3 - 3x + b
+        let lhs = P.mul(&y, &y);
+        let x3 = P.mul(&P.mul(&x, &x), &x);
+        let three_x = P.add(&P.add(&x, &x), &x);
+        let rhs = P.add(&P.sub(&x3, &three_x), &P.to_montgomery(&B));
+        if lhs != rhs {
+            return None;
+        }
+
+        Some(Point { x, y, z: P.one() })
+    }
+
+    fn is_infinity(&self) -> bool {
+        self.z.is_zero()
+    }
+
+    /// Returns the affine x coordinate, out of Montgomery representation.
+    fn affine_x(&self) -> U256 {
+        let z_inv = P.inv(&self.z);
+        P.to_normal(&P.mul(&self.x, &P.mul(&z_inv, &z_inv)))
+    }
+
+    fn double(&self) -> Point {
+        if self.is_infinity() || self.y.is_zero() {
+            return Point::INFINITY;
+        }
+
+        // "dbl-2001-b" from the Explicit-Formulas Database, for a = -3.
+        let delta = P.mul(&self.z, &self.z);
+        let gamma = P.mul(&self.y, &self.y);
+        let beta = P.mul(&self.x, &gamma);
+        let alpha = P.mul(&P.sub(&self.x, &delta), &P.add(&self.x, &delta));
+        let alpha = P.add(&P.add(&alpha, &alpha), &alpha);
+
+        let beta2 = P.add(&beta, &beta);
+        let beta4 = P.add(&beta2, &beta2);
+        let beta8 = P.add(&beta4, &beta4);
+        let x = P.sub(&P.mul(&alpha, &alpha), &beta8);
+
+        let y_plus_z = P.add(&self.y, &self.z);
+        let z = P.sub(&P.sub(&P.mul(&y_plus_z, &y_plus_z), &gamma), &delta);
+
+        let gamma2 = P.mul(&gamma, &gamma);
+        let gamma2_2 = P.add(&gamma2, &gamma2);
+        let gamma2_4 = P.add(&gamma2_2, &gamma2_2);
+        let gamma2_8 = P.add(&gamma2_4, &gamma2_4);
+        let y = P.sub(&P.mul(&alpha, &P.sub(&beta4, &x)), &gamma2_8);
+
+        Point { x, y, z }
+    }
+
+    #[allow(clippy::many_single_char_names)]
+    fn add(&self, other: &Point) -> Point {
+        if self.is_infinity() {
+            return *other;
+        }
+        if other.is_infinity() {
+            return *self;
+        }
+
+        let z1z1 = P.mul(&self.z, &self.z);
+        let z2z2 = P.mul(&other.z, &other.z);
+        let u1 = P.mul(&self.x, &z2z2);
+        let u2 = P.mul(&other.x, &z1z1);
+        let s1 = P.mul(&self.y, &P.mul(&other.z, &z2z2));
+        let s2 = P.mul(&other.y, &P.mul(&self.z, &z1z1));
+        let h = P.sub(&u2, &u1);
+        let r = P.sub(&s2, &s1);
+
+        if h.is_zero() {
+            return if r.is_zero() {
+                self.double()
+            } else {
+                Point::INFINITY
+            };
+        }
+
+        let hh = P.mul(&h, &h);
+        let hhh = P.mul(&h, &hh);
+        let v = P.mul(&u1, &hh);
+        let x = P.sub(&P.sub(&P.mul(&r, &r), &hhh), &P.add(&v, &v));
+        let y = P.sub(&P.mul(&r, &P.sub(&v, &x)), &P.mul(&s1, &hhh));
+        let z = P.mul(&P.mul(&self.z, &other.z), &h);
+
+        Point { x, y, z }
+    }
+}
+
+/// Computes `u1 * G + u2 * q` with Shamir's trick.
+fn double_scalar_mul(u1: &U256, g: &Point, u2: &U256, q: &Point) -> Point {
+    let g_plus_q = g.add(q);
+    let mut result = Point::INFINITY;
+    for i in (0..256).rev() {
+        result = result.double();
+        match (u1.bit(i), u2.bit(i)) {
+            (true, true) => result = result.add(&g_plus_q),
+            (true, false) => result = result.add(g),
+            (false, true) => result = result.add(q),
+            (false, false) => {}
+        }
+    }
+    result
+}
+
+/// Verifies the ECDSA signature `r || s` of `hash` under `public_key`.
+///
+/// Returns `Err(ErrorCode::INVAL)` if `public_key` is not a point on the
+/// curve.
+#[allow(clippy::many_single_char_names)]
+fn verify_signature(
+    public_key: &[u8; 64],
+    hash: &[u8],
+    signature: &[u8; 64],
+) -> Result<bool, ErrorCode> {
+    let mut qx = [0; 32];
+    let mut qy = [0; 32];
+    qx.copy_from_slice(&public_key[..32]);
+    qy.copy_from_slice(&public_key[32..]);
+    let qx = U256::from_be_bytes(&qx);
+    let qy = U256::from_be_bytes(&qy);
+    if qx >= *P.modulus() || qy >= *P.modulus() {
+        return Err(ErrorCode::INVAL);
+    }
+    let q = Point::from_affine(&qx, &qy).ok_or(ErrorCode::INVAL)?;
+
+    let mut r = [0; 32];
+    let mut s = [0; 32];
+    r.copy_from_slice(&signature[..32]);
+    s.copy_from_slice(&signature[32..]);
+    let r = U256::from_be_bytes(&r);
+    let s = U256::from_be_bytes(&s);
+    if r.is_zero() || s.is_zero() || r >= *N.modulus() || s >= *N.modulus() {
+        return Ok(false);
+    }
+
+    // The leftmost 256 bits of the hash, as an integer modulo n.
+    let mut e = [0; 32];
+    let len = core::cmp::min(hash.len(), 32);
+    e[32 - len..].copy_from_slice(&hash[..len]);
+    let e = N.reduce(U256::from_be_bytes(&e));
+
+    let w = N.inv(&N.to_montgomery(&s));
+    let u1 = N.to_normal(&N.mul(&N.to_montgomery(&e), &w));
+    let u2 = N.to_normal(&N.mul(&N.to_montgomery(&r), &w));
+
+    let g = Point::from_affine(&GX, &GY).ok_or(ErrorCode::FAIL)?;
+    let point = double_scalar_mul(&u1, &g, &u2, &q);
+    if point.is_infinity() {
+        return Ok(false);
+    }
+
+    Ok(N.reduce(point.affine_x()) == r)
+}
+
+/// ECDSA P-256 signature verifier for `HL` byte hashes.
+pub struct EcdsaP256SignatureVerifier<'a, const HL: usize> {
+    public_key: &'a [u8; 64],
+    client: OptionalCell<&'a dyn ClientVerify<HL, 64>>,
+    hash: TakeCell<'static, [u8; HL]>,
+    signature: TakeCell<'static, [u8; 64]>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a, const HL: usize> EcdsaP256SignatureVerifier<'a, HL> {
+    pub fn new(public_key: &'a [u8; 64]) -> Self {
+        Self {
+            public_key,
+            client: OptionalCell::empty(),
+            hash: TakeCell::empty(),
+            signature: TakeCell::empty(),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+}
+
+impl<'a, const HL: usize> SignatureVerify<'a, HL, 64> for EcdsaP256SignatureVerifier<'a, HL> {
+    fn set_verify_client(&self, client: &'a dyn ClientVerify<HL, 64>) {
+        self.client.replace(client);
+    }
+
+    fn verify(
+        &self,
+        hash: &'static mut [u8; HL],
+        signature: &'static mut [u8; 64],
+    ) -> Result<(), (ErrorCode, &'static mut [u8; HL], &'static mut [u8; 64])> {
+        if self.hash.is_some() {
+            return Err((ErrorCode::BUSY, hash, signature));
+        }
+        self.hash.replace(hash);
+        self.signature.replace(signature);
+        self.deferred_call.set();
+        Ok(())
+    }
+}
+
+impl<const HL: usize> DeferredCallClient for EcdsaP256SignatureVerifier<'_, HL> {
+    fn handle_deferred_call(&self) {
+        if let (Some(hash), Some(signature)) = (self.hash.take(), self.signature.take()) {
+            let result =
+                verify_signature(self.public_key, hash, signature).map_err(|_| ErrorCode::FAIL);
+            self.client.map(|client| {
+                client.verification_done(result, hash, signature);
+            });
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
@@ -0,0 +1,319 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Software Ed25519 signature verification.
+//!
+//! The verifier checks signatures against a single 32 byte public key,
+//! encoded as specified in RFC 8032. Since `SignatureVerify` passes the
+//! verifier a hash rather than the signed data, the message that is signed
+//! with Ed25519 is the `HL` byte hash itself. Signatures are the 64 byte
+//! `R || S` of RFC 8032.
+//!
+//! Verification uses the cofactorless check `[S]B = R + [k]A` and rejects
+//! non-canonical `S`. It runs in a deferred call and takes long enough (tens
+//! of milliseconds on a Cortex-M4) that it should not be used on hot paths.
+//! It does not need to run in constant time as it only handles public
+//! values.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let verifier = static_init!(
+//!     capsules_extra::public_key_crypto::ed25519::Ed25519SignatureVerifier<'static, 32>,
+//!     capsules_extra::public_key_crypto::ed25519::Ed25519SignatureVerifier::new(public_key)
+//! );
+//! kernel::deferred_call::DeferredCallClient::register(verifier);
+//! ```
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::public_key_crypto::signature::{ClientVerify, SignatureVerify};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+use super::modular::{Modulus, U256};
+use crate::sha512::{Mode, Sha512State};
+
+/// The field prime `p = 2^255 - 19`.
+const P: Modulus = Modulus::new(U256::from_be_bytes(&[
+    0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xed,
+]));
+
+/// The order `L = 2^252 + 27742317777372353535851937790883648493` of the
+/// base point.
+const L: Modulus = Modulus::new(U256::from_be_bytes(&[
+    0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x14, 0xde, 0xf9, 0xde, 0xa2, 0xf7, 0x9c, 0xd6, 0x58, 0x12, 0x63, 0x1a, 0x5c, 0xf5, 0xd3, 0xed,
+]));
+
+/// The constant `d = -121665 / 121666` of the curve equation
+/// `-x^2 + y^2 = 1 + d x^2 y^2`.
+const D: U256 = U256::from_be_bytes(&[
+    0x52, 0x03, 0x6c, 0xee, 0x2b, 0x6f, 0xfe, 0x73, 0x8c, 0xc7, 0x40, 0x79, 0x77, 0x79, 0xe8, 0x98,
+    0x00, 0x70, 0x0a, 0x4d, 0x41, 0x41, 0xd8, 0xab, 0x75, 0xeb, 0x4d, 0xca, 0x13, 0x59, 0x78, 0xa3,
+]);
+
+/// A square root of -1 modulo `p`, `2^((p - 1) / 4)`.
+const SQRT_M1: U256 = U256::from_be_bytes(&[
+    0x2b, 0x83, 0x24, 0x80, 0x4f, 0xc1, 0xdf, 0x0b, 0x2b, 0x4d, 0x00, 0x99, 0x3d, 0xfb, 0xd7, 0xa7,
+    0x2f, 0x43, 0x18, 0x06, 0xad, 0x2f, 0xe4, 0x78, 0xc4, 0xee, 0x1b, 0x27, 0x4a, 0x0e, 0xa0, 0xb0,
+]);
+
+/// The exponent `(p - 5) / 8` used to compute square roots.
+const SQRT_EXPONENT: U256 = U256::from_be_bytes(&[
+    0x0f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfd,
+]);
+
+/// The encoding of the base point, whose y coordinate is `4/5`.
+const BASE_POINT: [u8; 32] = [
+    0x58, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66,
+    0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66,
+];
+
+/// A point in extended coordinates, `(X / Z, Y / Z)` with `T = X * Y / Z`,
+/// with all coordinates in Montgomery representation modulo `p`.
+#[derive(Clone, Copy)]
+struct Point {
+    x: U256,
+    y: U256,
+    z: U256,
+    t: U256,
+}
+
+impl Point {
+    fn identity() -> Point {
+        Point {
+            x: U256::ZERO,
+            y: P.one(),
+            z: P.one(),
+            t: U256::ZERO,
+        }
+    }
+
+    /// Decodes a point as specified in RFC 8032, section 5.1.3.
+    fn decode(bytes: &[u8; 32]) -> Option<Point> {
+        let mut y = *bytes;
+        let sign = y[31] >> 7 == 1;
+        y[31] &= 0x7f;
+        let y = U256::from_le_bytes(&y);
+        if y >= *P.modulus() {
+            return None;
+        }
+        let y = P.to_montgomery(&y);
+
+        // x^2 = (y^2 - 1) / (d y^2 + 1)
+        let one = P.one();
+        let yy = P.mul(&y, &y);
+        let u = P.sub(&yy, &one);
+        let v = P.add(&P.mul(&P.to_montgomery(&D), &yy), &one);
+
+        // x = u v^3 (u v^7)^((p - 5) / 8)
+        let v3 = P.mul(&P.mul(&v, &v), &v);
+        let v7 = P.mul(&P.mul(&v3, &v3), &v);
+        let mut x = P.mul(&P.mul(&u, &v3), &P.pow(&P.mul(&u, &v7), &SQRT_EXPONENT));
+
+        let vxx = P.mul(&v, &P.mul(&x, &x));
+        if vxx == u {
+        } else if vxx == P.neg(&u) {
+            x = P.mul(&x, &P.to_montgomery(&SQRT_M1));
+        } else {
+            return None;
+        }
+
+        if x.is_zero() && sign {
+            return None;
+        }
+        if P.to_normal(&x).bit(0) != sign {
+            x = P.neg(&x);
+        }
+
+        Some(Point {
+            x,
+            y,
+            z: one,
+            t: P.mul(&x, &y),
+        })
+    }
+
+    /// Encodes the point as specified in RFC 8032, section 5.1.2.
+    fn encode(&self) -> [u8; 32] {
+        let z_inv = P.inv(&self.z);
+        let x = P.to_normal(&P.mul(&self.x, &z_inv));
+        let y = P.to_normal(&P.mul(&self.y, &z_inv));
+        let mut bytes = y.to_le_bytes();
+        if x.bit(0) {
+            bytes[31] |= 0x80;
+        }
+        bytes
+    }
+
+    fn neg(&self) -> Point {
+        Point {
+            x: P.neg(&self.x),
+            y: self.y,
+            z: self.z,
+            t: P.neg(&self.t),
+        }
+    }
+
+    /// "add-2008-hwcd-3" from the Explicit-Formulas Database, which is
+    /// complete for this curve. `d2` is `2 * d`.
+    #[allow(clippy::many_single_char_names)]
+    fn add(&self, other: &Point, d2: &U256) -> Point {
+        let a = P.mul(&P.sub(&self.y, &self.x), &P.sub(&other.y, &other.x));
+        let b = P.mul(&P.add(&self.y, &self.x), &P.add(&other.y, &other.x));
+        let c = P.mul(&P.mul(&self.t, d2), &other.t);
+        let zz = P.mul(&self.z, &other.z);
+        let d = P.add(&zz, &zz);
+        let e = P.sub(&b, &a);
+        let f = P.sub(&d, &c);
+        let g = P.add(&d, &c);
+        let h = P.add(&b, &a);
+        Point {
+            x: P.mul(&e, &f),
+            y: P.mul(&g, &h),
+            z: P.mul(&f, &g),
+            t: P.mul(&e, &h),
+        }
+    }
+
+    /// "dbl-2008-hwcd" from the Explicit-Formulas Database, for a = -1.
+    #[allow(clippy::many_single_char_names)]
+    fn double(&self) -> Point {
+        let a = P.mul(&self.x, &self.x);
+        let b = P.mul(&self.y, &self.y);
+        let zz = P.mul(&self.z, &self.z);
+        let c = P.add(&zz, &zz);
+        let h = P.add(&a, &b);
+        let x_plus_y = P.add(&self.x, &self.y);
+        let e = P.sub(&h, &P.mul(&x_plus_y, &x_plus_y));
+        let g = P.sub(&a, &b);
+        let f = P.add(&c, &g);
+        Point {
+            x: P.mul(&e, &f),
+            y: P.mul(&g, &h),
+            z: P.mul(&f, &g),
+            t: P.mul(&e, &h),
+        }
+    }
+}
+
+/// Computes `s1 * p1 + s2 * p2` with Shamir's trick.
+fn double_scalar_mul(s1: &U256, p1: &Point, s2: &U256, p2: &Point, d2: &U256) -> Point {
+    let p1_plus_p2 = p1.add(p2, d2);
+    let mut result = Point::identity();
+    for i in (0..256).rev() {
+        result = result.double();
+        match (s1.bit(i), s2.bit(i)) {
+            (true, true) => result = result.add(&p1_plus_p2, d2),
+            (true, false) => result = result.add(p1, d2),
+            (false, true) => result = result.add(p2, d2),
+            (false, false) => {}
+        }
+    }
+    result
+}
+
+/// Verifies the Ed25519 signature `R || S` of `message` under `public_key`.
+///
+/// Returns `Err(ErrorCode::INVAL)` if `public_key` is not a valid point
+/// encoding.
+#[allow(clippy::many_single_char_names)]
+fn verify_signature(
+    public_key: &[u8; 32],
+    message: &[u8],
+    signature: &[u8; 64],
+) -> Result<bool, ErrorCode> {
+    let a = Point::decode(public_key).ok_or(ErrorCode::INVAL)?;
+    let base = Point::decode(&BASE_POINT).ok_or(ErrorCode::FAIL)?;
+
+    let mut r = [0; 32];
+    let mut s = [0; 32];
+    r.copy_from_slice(&signature[..32]);
+    s.copy_from_slice(&signature[32..]);
+    let s = U256::from_le_bytes(&s);
+    if s >= *L.modulus() {
+        return Ok(false);
+    }
+
+    // k = SHA-512(R || A || M) mod L
+    let mut sha = Sha512State::new(Mode::Sha512);
+    sha.update(&r);
+    sha.update(public_key);
+    sha.update(message);
+    let digest = sha.finish();
+    let mut low = [0; 32];
+    let mut high = [0; 32];
+    low.copy_from_slice(&digest[..32]);
+    high.copy_from_slice(&digest[32..]);
+    let low = L.reduce(U256::from_le_bytes(&low));
+    let high = L.reduce(U256::from_le_bytes(&high));
+    // Converting to Montgomery representation multiplies by 2^256.
+    let k = L.add(&low, &L.to_montgomery(&high));
+
+    // [S]B - [k]A must equal R.
+    let d = P.to_montgomery(&D);
+    let d2 = P.add(&d, &d);
+    let point = double_scalar_mul(&s, &base, &k, &a.neg(), &d2);
+    Ok(point.encode() == r)
+}
+
+/// Ed25519 signature verifier for `HL` byte hashes.
+pub struct Ed25519SignatureVerifier<'a, const HL: usize> {
+    public_key: &'a [u8; 32],
+    client: OptionalCell<&'a dyn ClientVerify<HL, 64>>,
+    hash: TakeCell<'static, [u8; HL]>,
+    signature: TakeCell<'static, [u8; 64]>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a, const HL: usize> Ed25519SignatureVerifier<'a, HL> {
+    pub fn new(public_key: &'a [u8; 32]) -> Self {
+        Self {
+            public_key,
+            client: OptionalCell::empty(),
+            hash: TakeCell::empty(),
+            signature: TakeCell::empty(),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+}
+
+impl<'a, const HL: usize> SignatureVerify<'a, HL, 64> for Ed25519SignatureVerifier<'a, HL> {
+    fn set_verify_client(&self, client: &'a dyn ClientVerify<HL, 64>) {
+        self.client.replace(client);
+    }
+
+    fn verify(
+        &self,
+        hash: &'static mut [u8; HL],
+        signature: &'static mut [u8; 64],
+    ) -> Result<(), (ErrorCode, &'static mut [u8; HL], &'static mut [u8; 64])> {
+        if self.hash.is_some() {
+            return Err((ErrorCode::BUSY, hash, signature));
+        }
+        self.hash.replace(hash);
+        self.signature.replace(signature);
+        self.deferred_call.set();
+        Ok(())
+    }
+}
+
+impl<const HL: usize> DeferredCallClient for Ed25519SignatureVerifier<'_, HL> {
+    fn handle_deferred_call(&self) {
+        if let (Some(hash), Some(signature)) = (self.hash.take(), self.signature.take()) {
+            let result =
+                verify_signature(self.public_key, hash, signature).map_err(|_| ErrorCode::FAIL);
+            self.client.map(|client| {
+                client.verification_done(result, hash, signature);
+            });
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
@@ -4,4 +4,7 @@
 
 //! Provides capsules for asymmetric encryption
 
+pub mod ecdsa_p256;
+pub mod ed25519;
+mod modular;
 pub mod rsa_keys;
@@ -0,0 +1,271 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Arithmetic on 256-bit integers modulo an odd modulus.
+//!
+//! Numbers are stored as eight 32-bit limbs, least significant limb first.
+//! Multiplication uses Montgomery representation: a residue `a` is stored as
+//! `a * R mod m` with `R = 2^256`.
+//!
+//! This is only used to verify signatures, which only involves public
+//! values. None of these operations run in constant time.
+
+use core::cmp::Ordering;
+
+/// Number of 32-bit limbs in a `U256`.
+const LIMBS: usize = 8;
+
+/// An unsigned 256-bit integer.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct U256(pub [u32; LIMBS]);
+
+impl U256 {
+    pub const ZERO: U256 = U256([0; LIMBS]);
+    pub const ONE: U256 = U256([1, 0, 0, 0, 0, 0, 0, 0]);
+
+    /// Parses a big-endian byte string.
+    pub const fn from_be_bytes(bytes: &[u8; 32]) -> U256 {
+        let mut limbs = [0; LIMBS];
+        let mut i = 0;
+        while i < LIMBS {
+            let b = 32 - 4 * (i + 1);
+            limbs[i] = u32::from_be_bytes([bytes[b], bytes[b + 1], bytes[b + 2], bytes[b + 3]]);
+            i += 1;
+        }
+        U256(limbs)
+    }
+
+    /// Parses a little-endian byte string.
+    pub fn from_le_bytes(bytes: &[u8; 32]) -> U256 {
+        let mut limbs = [0; LIMBS];
+        for (limb, chunk) in limbs.iter_mut().zip(bytes.chunks_exact(4)) {
+            *limb = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+        U256(limbs)
+    }
+
+    /// Writes the number as a little-endian byte string.
+    pub fn to_le_bytes(self) -> [u8; 32] {
+        let mut bytes = [0; 32];
+        for (chunk, limb) in bytes.chunks_exact_mut(4).zip(self.0.iter()) {
+            chunk.copy_from_slice(&limb.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0.iter().all(|&limb| limb == 0)
+    }
+
+    /// Returns bit `i`, where bit 0 is the least significant bit.
+    pub fn bit(&self, i: usize) -> bool {
+        (self.0[i / 32] >> (i % 32)) & 1 == 1
+    }
+
+    /// Adds two numbers, returning the sum modulo `2^256` and the carry.
+    const fn overflowing_add(&self, other: &U256) -> (U256, bool) {
+        let mut limbs = [0; LIMBS];
+        let mut carry = 0;
+        let mut i = 0;
+        while i < LIMBS {
+            let sum = self.0[i] as u64 + other.0[i] as u64 + carry;
+            limbs[i] = sum as u32;
+            carry = sum >> 32;
+            i += 1;
+        }
+        (U256(limbs), carry != 0)
+    }
+
+    /// Subtracts two numbers, returning the difference modulo `2^256` and
+    /// the borrow.
+    const fn overflowing_sub(&self, other: &U256) -> (U256, bool) {
+        let mut limbs = [0; LIMBS];
+        let mut borrow = 0;
+        let mut i = 0;
+        while i < LIMBS {
+            let diff = (self.0[i] as u64)
+                .wrapping_sub(other.0[i] as u64)
+                .wrapping_sub(borrow);
+            limbs[i] = diff as u32;
+            borrow = (diff >> 32) & 1;
+            i += 1;
+        }
+        (U256(limbs), borrow != 0)
+    }
+
+    const fn const_cmp(&self, other: &U256) -> Ordering {
+        let mut i = LIMBS;
+        while i > 0 {
+            i -= 1;
+            if self.0[i] > other.0[i] {
+                return Ordering::Greater;
+            } else if self.0[i] < other.0[i] {
+                return Ordering::Less;
+            }
+        }
+        Ordering::Equal
+    }
+}
+
+impl PartialOrd for U256 {
+    fn partial_cmp(&self, other: &U256) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for U256 {
+    fn cmp(&self, other: &U256) -> Ordering {
+        self.const_cmp(other)
+    }
+}
+
+/// An odd modulus, with the constants for Montgomery multiplication.
+pub struct Modulus {
+    m: U256,
+    /// `-m^-1 mod 2^32`
+    m0_inv: u32,
+    /// `R^2 mod m`
+    r2: U256,
+}
+
+impl Modulus {
+    pub const fn new(m: U256) -> Modulus {
+        // Newton's iteration doubles the number of correct low bits of the
+        // inverse each step, starting from 3 correct bits for odd `m`.
+        let m0 = m.0[0];
+        let mut inv: u32 = m0;
+        let mut i = 0;
+        while i < 4 {
+            inv = inv.wrapping_mul(2u32.wrapping_sub(m0.wrapping_mul(inv)));
+            i += 1;
+        }
+
+        // `R^2 mod m`, by doubling 1 modulo m 512 times.
+        let mut r2 = U256::ONE;
+        let mut i = 0;
+        while i < 512 {
+            r2 = Self::const_add(&m, &r2, &r2);
+            i += 1;
+        }
+
+        Modulus {
+            m,
+            m0_inv: inv.wrapping_neg(),
+            r2,
+        }
+    }
+
+    const fn const_add(m: &U256, a: &U256, b: &U256) -> U256 {
+        let (sum, carry) = a.overflowing_add(b);
+        if carry || !matches!(sum.const_cmp(m), Ordering::Less) {
+            sum.overflowing_sub(m).0
+        } else {
+            sum
+        }
+    }
+
+    pub fn modulus(&self) -> &U256 {
+        &self.m
+    }
+
+    /// Reduces any 256-bit number modulo `m`. This is only fast if `a` is at
+    /// most a small multiple of `m`.
+    pub fn reduce(&self, mut a: U256) -> U256 {
+        while a >= self.m {
+            a = a.overflowing_sub(&self.m).0;
+        }
+        a
+    }
+
+    /// `a + b mod m`, for `a` and `b` less than `m`.
+    pub fn add(&self, a: &U256, b: &U256) -> U256 {
+        Self::const_add(&self.m, a, b)
+    }
+
+    /// `a - b mod m`, for `a` and `b` less than `m`.
+    pub fn sub(&self, a: &U256, b: &U256) -> U256 {
+        let (diff, borrow) = a.overflowing_sub(b);
+        if borrow {
+            diff.overflowing_add(&self.m).0
+        } else {
+            diff
+        }
+    }
+
+    /// `-a mod m`, for `a` less than `m`.
+    pub fn neg(&self, a: &U256) -> U256 {
+        self.sub(&U256::ZERO, a)
+    }
+
+    /// Montgomery multiplication: `a * b / R mod m`, for `a` less than
+    /// `2^256` and `b` less than `m`.
+    pub fn mul(&self, a: &U256, b: &U256) -> U256 {
+        let modulus = &self.m.0;
+        let mut acc = [0u32; LIMBS + 2];
+        for &limb in b.0.iter() {
+            let mut carry: u64 = 0;
+            for j in 0..LIMBS {
+                let uv = acc[j] as u64 + a.0[j] as u64 * limb as u64 + carry;
+                acc[j] = uv as u32;
+                carry = uv >> 32;
+            }
+            let uv = acc[LIMBS] as u64 + carry;
+            acc[LIMBS] = uv as u32;
+            acc[LIMBS + 1] = (uv >> 32) as u32;
+
+            let factor = acc[0].wrapping_mul(self.m0_inv);
+            let uv = acc[0] as u64 + factor as u64 * modulus[0] as u64;
+            let mut carry = uv >> 32;
+            for j in 1..LIMBS {
+                let uv = acc[j] as u64 + factor as u64 * modulus[j] as u64 + carry;
+                acc[j - 1] = uv as u32;
+                carry = uv >> 32;
+            }
+            let uv = acc[LIMBS] as u64 + carry;
+            acc[LIMBS - 1] = uv as u32;
+            acc[LIMBS] = acc[LIMBS + 1] + (uv >> 32) as u32;
+        }
+
+        let mut result = U256([0; LIMBS]);
+        result.0.copy_from_slice(&acc[..LIMBS]);
+        if acc[LIMBS] != 0 || result >= self.m {
+            result = result.overflowing_sub(&self.m).0;
+        }
+        result
+    }
+
+    /// Converts `a`, which must be less than `m`, into Montgomery
+    /// representation.
+    pub fn to_montgomery(&self, a: &U256) -> U256 {
+        self.mul(a, &self.r2)
+    }
+
+    /// Converts `a` out of Montgomery representation.
+    pub fn to_normal(&self, a: &U256) -> U256 {
+        self.mul(&U256::ONE, a)
+    }
+
+    /// One in Montgomery representation.
+    pub fn one(&self) -> U256 {
+        self.to_montgomery(&U256::ONE)
+    }
+
+    /// `a^e mod m`, for `a` in Montgomery representation.
+    pub fn pow(&self, a: &U256, e: &U256) -> U256 {
+        let mut result = self.one();
+        for i in (0..256).rev() {
+            result = self.mul(&result, &result);
+            if e.bit(i) {
+                result = self.mul(&result, a);
+            }
+        }
+        result
+    }
+
+    /// `a^-1 mod m`, for `a` in Montgomery representation and `m` prime.
+    pub fn inv(&self, a: &U256) -> U256 {
+        let exponent = self.m.overflowing_sub(&U256([2, 0, 0, 0, 0, 0, 0, 0])).0;
+        self.pow(a, &exponent)
+    }
+}
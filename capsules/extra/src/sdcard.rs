@@ -1268,6 +1268,12 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
         self.is_initialized.get()
     }
 
+    /// Take back the buffer of a read or write that failed, which is not
+    /// returned by the error callback.
+    pub fn take_buffer(&self) -> Option<&'static mut [u8]> {
+        self.client_buffer.take()
+    }
+
     /// watches SD card detect pin for changes, sends callback on change
     pub fn detect_changes(&self) {
         self.detect_pin.get().map(|pin| {
@@ -1306,58 +1312,32 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
         buffer: &'static mut [u8],
         sector: u32,
         count: u32,
-    ) -> Result<(), ErrorCode> {
-        // only if initialized and installed
-        if self.is_installed() {
-            if self.is_initialized() {
-                self.txbuffer
-                    .take()
-                    .map_or(Err(ErrorCode::NOMEM), |txbuffer| {
-                        self.rxbuffer
-                            .take()
-                            .map_or(Err(ErrorCode::NOMEM), move |rxbuffer| {
-                                // save the user buffer for later
-                                self.client_buffer.replace(buffer);
-                                self.client_offset.set(0);
-
-                                // convert block address to byte address for non-block
-                                //  access cards
-                                let mut address = sector;
-                                if self.card_type.get() != SDCardType::SDv2BlockAddressable {
-                                    address *= 512;
-                                }
-
-                                self.state.set(SpiState::StartReadBlocks { count });
-                                if count == 1 {
-                                    self.send_command(
-                                        SDCmd::CMD17_ReadSingle,
-                                        address,
-                                        txbuffer,
-                                        rxbuffer,
-                                        10,
-                                    );
-                                } else {
-                                    self.send_command(
-                                        SDCmd::CMD18_ReadMultiple,
-                                        address,
-                                        txbuffer,
-                                        rxbuffer,
-                                        10,
-                                    );
-                                }
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        let (txbuffer, rxbuffer) = match self.take_spi_buffers() {
+            Ok(buffers) => buffers,
+            Err(err) => return Err((err, buffer)),
+        };
+
+        // save the user buffer for later
+        self.client_buffer.replace(buffer);
+        self.client_offset.set(0);
+
+        // convert block address to byte address for non-block
+        //  access cards
+        let mut address = sector;
+        if self.card_type.get() != SDCardType::SDv2BlockAddressable {
+            address *= 512;
+        }
 
-                                // command started successfully
-                                Ok(())
-                            })
-                    })
-            } else {
-                // sd card not initialized
-                Err(ErrorCode::RESERVE)
-            }
+        self.state.set(SpiState::StartReadBlocks { count });
+        if count == 1 {
+            self.send_command(SDCmd::CMD17_ReadSingle, address, txbuffer, rxbuffer, 10);
         } else {
-            // sd card not installed
-            Err(ErrorCode::UNINSTALLED)
+            self.send_command(SDCmd::CMD18_ReadMultiple, address, txbuffer, rxbuffer, 10);
         }
+
+        // command started successfully
+        Ok(())
     }
 
     pub fn write_blocks(
@@ -1365,52 +1345,52 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
         buffer: &'static mut [u8],
         sector: u32,
         count: u32,
-    ) -> Result<(), ErrorCode> {
-        // only if initialized and installed
-        if self.is_installed() {
-            if self.is_initialized() {
-                self.txbuffer
-                    .take()
-                    .map_or(Err(ErrorCode::NOMEM), |txbuffer| {
-                        self.rxbuffer
-                            .take()
-                            .map_or(Err(ErrorCode::NOMEM), move |rxbuffer| {
-                                // save the user buffer for later
-                                self.client_buffer.replace(buffer);
-                                self.client_offset.set(0);
-
-                                // convert block address to byte address for non-block
-                                //  access cards
-                                let mut address = sector;
-                                if self.card_type.get() != SDCardType::SDv2BlockAddressable {
-                                    address *= 512;
-                                }
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if count != 1 {
+            // can't write multiple blocks yet
+            return Err((ErrorCode::NOSUPPORT, buffer));
+        }
+        let (txbuffer, rxbuffer) = match self.take_spi_buffers() {
+            Ok(buffers) => buffers,
+            Err(err) => return Err((err, buffer)),
+        };
+
+        // save the user buffer for later
+        self.client_buffer.replace(buffer);
+        self.client_offset.set(0);
+
+        // convert block address to byte address for non-block
+        //  access cards
+        let mut address = sector;
+        if self.card_type.get() != SDCardType::SDv2BlockAddressable {
+            address *= 512;
+        }
 
-                                self.state.set(SpiState::StartWriteBlocks { count });
-                                if count == 1 {
-                                    self.send_command(
-                                        SDCmd::CMD24_WriteSingle,
-                                        address,
-                                        txbuffer,
-                                        rxbuffer,
-                                        10,
-                                    );
-
-                                    // command started successfully
-                                    Ok(())
-                                } else {
-                                    // can't write multiple blocks yet
-                                    Err(ErrorCode::NOSUPPORT)
-                                }
-                            })
-                    })
-            } else {
-                // sd card not initialized
-                Err(ErrorCode::RESERVE)
-            }
-        } else {
+        self.state.set(SpiState::StartWriteBlocks { count });
+        self.send_command(SDCmd::CMD24_WriteSingle, address, txbuffer, rxbuffer, 10);
+
+        // command started successfully
+        Ok(())
+    }
+
+    /// Take the SPI buffers to start a transfer, if the card is ready for it.
+    fn take_spi_buffers(&self) -> Result<(&'static mut [u8], &'static mut [u8]), ErrorCode> {
+        // only if initialized and installed
+        if !self.is_installed() {
             // sd card not installed
-            Err(ErrorCode::UNINSTALLED)
+            return Err(ErrorCode::UNINSTALLED);
+        }
+        if !self.is_initialized() {
+            // sd card not initialized
+            return Err(ErrorCode::RESERVE);
+        }
+        let txbuffer = self.txbuffer.take().ok_or(ErrorCode::NOMEM)?;
+        match self.rxbuffer.take() {
+            Some(rxbuffer) => Ok((txbuffer, rxbuffer)),
+            None => {
+                self.txbuffer.replace(txbuffer);
+                Err(ErrorCode::NOMEM)
+            }
         }
     }
 }
@@ -1636,7 +1616,14 @@ impl<'a, A: hil::time::Alarm<'a>> SyscallDriver for SDCardDriver<'a, A> {
             3 => self.kernel_buf.take().map_or(
                 CommandReturn::failure(ErrorCode::BUSY),
                 |kernel_buf| {
-                    CommandReturn::from(self.sdcard.read_blocks(kernel_buf, data as u32, 1))
+                    CommandReturn::from(
+                        self.sdcard.read_blocks(kernel_buf, data as u32, 1).map_err(
+                            |(err, kernel_buf)| {
+                                self.kernel_buf.replace(kernel_buf);
+                                err
+                            },
+                        ),
+                    )
                 },
             ),
 
@@ -1664,7 +1651,12 @@ impl<'a, A: hil::time::Alarm<'a>> SyscallDriver for SDCardDriver<'a, A> {
                                             }
 
                                             // begin writing
-                                            self.sdcard.write_blocks(kernel_buf, data as u32, 1)
+                                            self.sdcard
+                                                .write_blocks(kernel_buf, data as u32, 1)
+                                                .map_err(|(err, kernel_buf)| {
+                                                    self.kernel_buf.replace(kernel_buf);
+                                                    err
+                                                })
                                         },
                                     )
                                 })
@@ -65,6 +65,17 @@ enum ShaOperation {
     Sha512,
 }
 
+impl ShaOperation {
+    /// Length of the digest this algorithm produces.
+    fn output_len(&self) -> usize {
+        match self {
+            ShaOperation::Sha256 => 32,
+            ShaOperation::Sha384 => 48,
+            ShaOperation::Sha512 => 64,
+        }
+    }
+}
+
 pub struct ShaDriver<'a, H: digest::Digest<'a, L>, const L: usize> {
     sha: &'a H,
 
@@ -366,7 +377,7 @@ impl<
     fn hash_done(&self, result: Result<(), ErrorCode>, digest: &'static mut [u8; L]) {
         self.processid.map(|id| {
             self.apps
-                .enter(id, |_, kernel_data| {
+                .enter(id, |app, kernel_data| {
                     self.sha.clear_data();
 
                     let pointer = digest.as_ref()[0] as *mut u8;
@@ -375,13 +386,14 @@ impl<
                         .get_readwrite_processbuffer(rw_allow::DEST)
                         .and_then(|dest| {
                             dest.mut_enter(|dest| {
-                                let len = dest.len();
-
-                                if len < L {
-                                    dest.copy_from_slice(&digest[0..len]);
-                                } else {
-                                    dest[0..L].copy_from_slice(digest);
-                                }
+                                // Only copy the digest of the selected
+                                // algorithm, which may be shorter than `L`.
+                                let output_len = app
+                                    .sha_operation
+                                    .as_ref()
+                                    .map_or(L, |op| op.output_len().min(L));
+                                let len = dest.len().min(output_len);
+                                dest[0..len].copy_from_slice(&digest[0..len]);
                             })
                         });
 
@@ -0,0 +1,549 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Software implementation of SHA-384 and SHA-512.
+//!
+//! Both algorithms share the same compression function and only differ in
+//! their initial hash values and in SHA-384 truncating the output to 48
+//! bytes. The digest is always returned in a 64 byte buffer; for SHA-384 only
+//! the first 48 bytes are written and compared.
+//!
+//! The hashing happens synchronously in the method calls, callbacks are
+//! issued from a deferred call. SHA-256 is not supported, `set_mode_sha256`
+//! returns `NOSUPPORT`.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let sha512 = static_init!(Sha512Software<'static>, Sha512Software::new());
+//! sha512.register();
+//! kernel::hil::digest::Digest::set_client(sha512, client);
+//! ```
+
+use core::cell::Cell;
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+
+use kernel::hil::digest::{Client, ClientData, ClientHash, ClientVerify};
+use kernel::hil::digest::{ClientDataHash, ClientDataVerify, DigestDataHash, DigestDataVerify};
+use kernel::hil::digest::{Digest, DigestData, DigestHash, DigestVerify};
+use kernel::hil::digest::{Sha256, Sha384, Sha512};
+use kernel::utilities::cells::{MapCell, OptionalCell};
+use kernel::utilities::leasable_buffer::SubSlice;
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::utilities::leasable_buffer::SubSliceMutImmut;
+use kernel::ErrorCode;
+
+pub(crate) const SHA_512_BLOCK_LEN_BYTES: usize = 128;
+pub(crate) const SHA_512_OUTPUT_LEN_BYTES: usize = 64;
+pub(crate) const SHA_384_OUTPUT_LEN_BYTES: usize = 48;
+
+const ROUND_CONSTANTS: [u64; 80] = [
+    0x428a2f98d728ae22,
+    0x7137449123ef65cd,
+    0xb5c0fbcfec4d3b2f,
+    0xe9b5dba58189dbbc,
+    0x3956c25bf348b538,
+    0x59f111f1b605d019,
+    0x923f82a4af194f9b,
+    0xab1c5ed5da6d8118,
+    0xd807aa98a3030242,
+    0x12835b0145706fbe,
+    0x243185be4ee4b28c,
+    0x550c7dc3d5ffb4e2,
+    0x72be5d74f27b896f,
+    0x80deb1fe3b1696b1,
+    0x9bdc06a725c71235,
+    0xc19bf174cf692694,
+    0xe49b69c19ef14ad2,
+    0xefbe4786384f25e3,
+    0x0fc19dc68b8cd5b5,
+    0x240ca1cc77ac9c65,
+    0x2de92c6f592b0275,
+    0x4a7484aa6ea6e483,
+    0x5cb0a9dcbd41fbd4,
+    0x76f988da831153b5,
+    0x983e5152ee66dfab,
+    0xa831c66d2db43210,
+    0xb00327c898fb213f,
+    0xbf597fc7beef0ee4,
+    0xc6e00bf33da88fc2,
+    0xd5a79147930aa725,
+    0x06ca6351e003826f,
+    0x142929670a0e6e70,
+    0x27b70a8546d22ffc,
+    0x2e1b21385c26c926,
+    0x4d2c6dfc5ac42aed,
+    0x53380d139d95b3df,
+    0x650a73548baf63de,
+    0x766a0abb3c77b2a8,
+    0x81c2c92e47edaee6,
+    0x92722c851482353b,
+    0xa2bfe8a14cf10364,
+    0xa81a664bbc423001,
+    0xc24b8b70d0f89791,
+    0xc76c51a30654be30,
+    0xd192e819d6ef5218,
+    0xd69906245565a910,
+    0xf40e35855771202a,
+    0x106aa07032bbd1b8,
+    0x19a4c116b8d2d0c8,
+    0x1e376c085141ab53,
+    0x2748774cdf8eeb99,
+    0x34b0bcb5e19b48a8,
+    0x391c0cb3c5c95a63,
+    0x4ed8aa4ae3418acb,
+    0x5b9cca4f7763e373,
+    0x682e6ff3d6b2b8a3,
+    0x748f82ee5defb2fc,
+    0x78a5636f43172f60,
+    0x84c87814a1f0ab72,
+    0x8cc702081a6439ec,
+    0x90befffa23631e28,
+    0xa4506cebde82bde9,
+    0xbef9a3f7b2c67915,
+    0xc67178f2e372532b,
+    0xca273eceea26619c,
+    0xd186b8c721c0c207,
+    0xeada7dd6cde0eb1e,
+    0xf57d4f7fee6ed178,
+    0x06f067aa72176fba,
+    0x0a637dc5a2c898a6,
+    0x113f9804bef90dae,
+    0x1b710b35131c471b,
+    0x28db77f523047d84,
+    0x32caab7b40c72493,
+    0x3c9ebe0a15c9bebc,
+    0x431d67c49c100d4c,
+    0x4cc5d4becb3e42b6,
+    0x597f299cfc657e2a,
+    0x5fcb6fab3ad6faec,
+    0x6c44198c4a475817,
+];
+
+const SHA_384_INITIAL_VALUES: [u64; 8] = [
+    0xcbbb9d5dc1059ed8,
+    0x629a292a367cd507,
+    0x9159015a3070dd17,
+    0x152fecd8f70e5939,
+    0x67332667ffc00b31,
+    0x8eb44a8768581511,
+    0xdb0c2e0d64f98fa7,
+    0x47b5481dbefa4fa4,
+];
+
+const SHA_512_INITIAL_VALUES: [u64; 8] = [
+    0x6a09e667f3bcc908,
+    0xbb67ae8584caa73b,
+    0x3c6ef372fe94f82b,
+    0xa54ff53a5f1d36f1,
+    0x510e527fade682d1,
+    0x9b05688c2b3e6c1f,
+    0x1f83d9abfb41bd6b,
+    0x5be0cd19137e2179,
+];
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Mode {
+    Sha384,
+    Sha512,
+}
+
+impl Mode {
+    pub(crate) fn output_len(self) -> usize {
+        match self {
+            Mode::Sha384 => SHA_384_OUTPUT_LEN_BYTES,
+            Mode::Sha512 => SHA_512_OUTPUT_LEN_BYTES,
+        }
+    }
+}
+
+/// The running state of a SHA-384 or SHA-512 computation.
+///
+/// This is shared with the HMAC and Ed25519 implementations, which need to
+/// hash data synchronously.
+pub(crate) struct Sha512State {
+    mode: Mode,
+    hash_values: [u64; 8],
+    block: [u8; SHA_512_BLOCK_LEN_BYTES],
+    block_len: usize,
+    total_len: usize,
+}
+
+impl Sha512State {
+    pub(crate) fn new(mode: Mode) -> Sha512State {
+        Sha512State {
+            mode,
+            hash_values: match mode {
+                Mode::Sha384 => SHA_384_INITIAL_VALUES,
+                Mode::Sha512 => SHA_512_INITIAL_VALUES,
+            },
+            block: [0; SHA_512_BLOCK_LEN_BYTES],
+            block_len: 0,
+            total_len: 0,
+        }
+    }
+
+    pub(crate) fn update(&mut self, mut data: &[u8]) {
+        self.total_len += data.len();
+        while !data.is_empty() {
+            let len = core::cmp::min(SHA_512_BLOCK_LEN_BYTES - self.block_len, data.len());
+            self.block[self.block_len..self.block_len + len].copy_from_slice(&data[..len]);
+            self.block_len += len;
+            data = &data[len..];
+            if self.block_len == SHA_512_BLOCK_LEN_BYTES {
+                self.compress();
+            }
+        }
+    }
+
+    /// Pad the message and return the digest. For SHA-384 the last 16 bytes
+    /// are zero. The state must be reset before it is used again.
+    pub(crate) fn finish(&mut self) -> [u8; SHA_512_OUTPUT_LEN_BYTES] {
+        let bit_len = (self.total_len as u128) * 8;
+        self.block[self.block_len] = 0x80;
+        self.block[self.block_len + 1..].fill(0);
+        if self.block_len + 1 > SHA_512_BLOCK_LEN_BYTES - 16 {
+            self.compress();
+            self.block.fill(0);
+        }
+        self.block[SHA_512_BLOCK_LEN_BYTES - 16..].copy_from_slice(&bit_len.to_be_bytes());
+        self.compress();
+
+        let mut digest = [0; SHA_512_OUTPUT_LEN_BYTES];
+        for (chunk, word) in digest.chunks_exact_mut(8).zip(self.hash_values.iter()) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+        digest[self.mode.output_len()..].fill(0);
+        digest
+    }
+
+    fn compress(&mut self) {
+        let mut w = [0u64; 80];
+        for (word, chunk) in w.iter_mut().zip(self.block.chunks_exact(8)) {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(chunk);
+            *word = u64::from_be_bytes(bytes);
+        }
+        for i in 16..80 {
+            let s0 = w[i - 15].rotate_right(1) ^ w[i - 15].rotate_right(8) ^ (w[i - 15] >> 7);
+            let s1 = w[i - 2].rotate_right(19) ^ w[i - 2].rotate_right(61) ^ (w[i - 2] >> 6);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let mut hashes = self.hash_values;
+        for i in 0..80 {
+            let s1 = hashes[4].rotate_right(14)
+                ^ hashes[4].rotate_right(18)
+                ^ hashes[4].rotate_right(41);
+            let ch = (hashes[4] & hashes[5]) ^ (!hashes[4] & hashes[6]);
+            let temp1 = hashes[7]
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(ROUND_CONSTANTS[i])
+                .wrapping_add(w[i]);
+            let s0 = hashes[0].rotate_right(28)
+                ^ hashes[0].rotate_right(34)
+                ^ hashes[0].rotate_right(39);
+            let maj = (hashes[0] & hashes[1]) ^ (hashes[0] & hashes[2]) ^ (hashes[1] & hashes[2]);
+            let temp2 = s0.wrapping_add(maj);
+
+            hashes.copy_within(0..7, 1);
+            hashes[4] = hashes[4].wrapping_add(temp1);
+            hashes[0] = temp1.wrapping_add(temp2);
+        }
+
+        for (state, value) in self.hash_values.iter_mut().zip(hashes) {
+            *state = state.wrapping_add(value);
+        }
+        self.block_len = 0;
+    }
+}
+
+impl Drop for Sha512State {
+    fn drop(&mut self) {
+        self.hash_values.fill(0);
+        self.block.fill(0);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum State {
+    Idle,
+    Data,
+    Hash,
+    Verify,
+    CancelData,
+    CancelHash,
+    CancelVerify,
+}
+
+pub struct Sha512Software<'a> {
+    state: Cell<State>,
+    mode: Cell<Mode>,
+
+    client: OptionalCell<&'a dyn Client<SHA_512_OUTPUT_LEN_BYTES>>,
+    input_data: OptionalCell<SubSliceMutImmut<'static, u8>>,
+    hash: MapCell<Sha512State>,
+
+    // Used to store the hash or the hash to compare against with verify
+    output_data: Cell<Option<&'static mut [u8; SHA_512_OUTPUT_LEN_BYTES]>>,
+    verified: Cell<bool>,
+
+    deferred_call: DeferredCall,
+}
+
+impl Sha512Software<'_> {
+    pub fn new() -> Self {
+        Self {
+            state: Cell::new(State::Idle),
+            mode: Cell::new(Mode::Sha512),
+            client: OptionalCell::empty(),
+            input_data: OptionalCell::empty(),
+            hash: MapCell::new(Sha512State::new(Mode::Sha512)),
+            output_data: Cell::new(None),
+            verified: Cell::new(false),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    pub fn busy(&self) -> bool {
+        self.state.get() != State::Idle
+    }
+
+    fn initialize(&self) {
+        let new_state = match self.state.get() {
+            State::Idle => State::Idle,
+            State::Data | State::CancelData => State::CancelData,
+            State::Hash | State::CancelHash => State::CancelHash,
+            State::Verify | State::CancelVerify => State::CancelVerify,
+        };
+        self.state.set(new_state);
+        self.hash.replace(Sha512State::new(self.mode.get()));
+    }
+
+    fn set_mode(&self, mode: Mode) -> Result<(), ErrorCode> {
+        if self.busy() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.mode.set(mode);
+        self.initialize();
+        Ok(())
+    }
+
+    fn finish(&self) -> [u8; SHA_512_OUTPUT_LEN_BYTES] {
+        self.hash
+            .map_or([0; SHA_512_OUTPUT_LEN_BYTES], |hash| hash.finish())
+    }
+}
+
+impl<'a> DigestData<'a, 64> for Sha512Software<'a> {
+    fn add_data(
+        &self,
+        mut data: SubSlice<'static, u8>,
+    ) -> Result<(), (ErrorCode, SubSlice<'static, u8>)> {
+        if self.busy() {
+            Err((ErrorCode::BUSY, data))
+        } else {
+            self.state.set(State::Data);
+            self.hash.map(|hash| hash.update(data.as_slice()));
+            data.slice(data.len()..data.len());
+            self.input_data.set(SubSliceMutImmut::Immutable(data));
+            self.deferred_call.set();
+            Ok(())
+        }
+    }
+
+    fn add_mut_data(
+        &self,
+        mut data: SubSliceMut<'static, u8>,
+    ) -> Result<(), (ErrorCode, SubSliceMut<'static, u8>)> {
+        if self.busy() {
+            Err((ErrorCode::BUSY, data))
+        } else {
+            self.state.set(State::Data);
+            self.hash.map(|hash| hash.update(data.as_slice()));
+            data.slice(data.len()..data.len());
+            self.input_data.set(SubSliceMutImmut::Mutable(data));
+            self.deferred_call.set();
+            Ok(())
+        }
+    }
+
+    fn clear_data(&self) {
+        self.initialize();
+    }
+
+    fn set_data_client(&'a self, _client: &'a (dyn ClientData<64> + 'a)) {
+        unimplemented!()
+    }
+}
+
+impl<'a> DigestHash<'a, 64> for Sha512Software<'a> {
+    fn run(
+        &'a self,
+        digest: &'static mut [u8; 64],
+    ) -> Result<(), (ErrorCode, &'static mut [u8; 64])> {
+        if self.busy() {
+            Err((ErrorCode::BUSY, digest))
+        } else {
+            self.state.set(State::Hash);
+            let output_len = self.mode.get().output_len();
+            digest[..output_len].copy_from_slice(&self.finish()[..output_len]);
+            self.output_data.set(Some(digest));
+            self.deferred_call.set();
+            Ok(())
+        }
+    }
+
+    fn set_hash_client(&'a self, _client: &'a (dyn ClientHash<64> + 'a)) {
+        unimplemented!()
+    }
+}
+
+impl<'a> DigestVerify<'a, 64> for Sha512Software<'a> {
+    fn verify(
+        &'a self,
+        compare: &'static mut [u8; 64],
+    ) -> Result<(), (ErrorCode, &'static mut [u8; 64])> {
+        if self.busy() {
+            Err((ErrorCode::BUSY, compare))
+        } else {
+            self.state.set(State::Verify);
+            let output_len = self.mode.get().output_len();
+            let digest = self.finish();
+            // Compare every byte so the time taken does not depend on where
+            // the first difference is.
+            let difference = digest[..output_len]
+                .iter()
+                .zip(compare[..output_len].iter())
+                .fold(0, |acc, (a, b)| acc | (a ^ b));
+            self.verified.set(difference == 0);
+            self.output_data.set(Some(compare));
+            self.deferred_call.set();
+            Ok(())
+        }
+    }
+
+    fn set_verify_client(&'a self, _client: &'a (dyn ClientVerify<64> + 'a)) {
+        unimplemented!()
+    }
+}
+
+impl<'a> Digest<'a, 64> for Sha512Software<'a> {
+    fn set_client(&'a self, client: &'a dyn Client<64>) {
+        self.client.set(client);
+    }
+}
+
+impl DeferredCallClient for Sha512Software<'_> {
+    fn handle_deferred_call(&self) {
+        let prior = self.state.get();
+        self.state.set(State::Idle);
+        match prior {
+            State::Idle => {}
+            State::Data => {
+                // Data already hashed in method call
+                match self.input_data.take() {
+                    Some(SubSliceMutImmut::Mutable(buffer)) => {
+                        self.client.map(|client| {
+                            client.add_mut_data_done(Ok(()), buffer);
+                        });
+                    }
+                    Some(SubSliceMutImmut::Immutable(buffer)) => {
+                        self.client.map(|client| {
+                            client.add_data_done(Ok(()), buffer);
+                        });
+                    }
+                    None => {}
+                }
+            }
+            State::Hash => {
+                // Hash already copied in method call.
+                self.clear_data();
+                if let Some(output) = self.output_data.take() {
+                    self.client.map(|client| {
+                        client.hash_done(Ok(()), output);
+                    });
+                }
+            }
+            State::Verify => {
+                // Comparison already done in method call.
+                self.clear_data();
+                if let Some(output) = self.output_data.take() {
+                    self.client.map(|client| {
+                        client.verification_done(Ok(self.verified.get()), output);
+                    });
+                }
+            }
+            State::CancelData => match self.input_data.take() {
+                Some(SubSliceMutImmut::Mutable(buffer)) => {
+                    self.client.map(|client| {
+                        client.add_mut_data_done(Err(ErrorCode::CANCEL), buffer);
+                    });
+                }
+                Some(SubSliceMutImmut::Immutable(buffer)) => {
+                    self.client.map(|client| {
+                        client.add_data_done(Err(ErrorCode::CANCEL), buffer);
+                    });
+                }
+                None => {}
+            },
+            State::CancelHash => {
+                if let Some(output) = self.output_data.take() {
+                    output.fill(0);
+                    self.client.map(|client| {
+                        client.hash_done(Err(ErrorCode::CANCEL), output);
+                    });
+                }
+            }
+            State::CancelVerify => {
+                if let Some(output) = self.output_data.take() {
+                    self.client.map(|client| {
+                        client.verification_done(Err(ErrorCode::CANCEL), output);
+                    });
+                }
+            }
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+impl Sha256 for Sha512Software<'_> {
+    fn set_mode_sha256(&self) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+}
+
+impl Sha384 for Sha512Software<'_> {
+    /// Call before adding data to perform Sha384
+    fn set_mode_sha384(&self) -> Result<(), ErrorCode> {
+        self.set_mode(Mode::Sha384)
+    }
+}
+
+impl Sha512 for Sha512Software<'_> {
+    /// Call before adding data to perform Sha512
+    fn set_mode_sha512(&self) -> Result<(), ErrorCode> {
+        self.set_mode(Mode::Sha512)
+    }
+}
+
+impl<'a> DigestDataHash<'a, 64> for Sha512Software<'a> {
+    fn set_client(&'a self, _client: &'a dyn ClientDataHash<64>) {
+        unimplemented!()
+    }
+}
+
+impl<'a> DigestDataVerify<'a, 64> for Sha512Software<'a> {
+    fn set_client(&'a self, _client: &'a dyn ClientDataVerify<64>) {
+        unimplemented!()
+    }
+}
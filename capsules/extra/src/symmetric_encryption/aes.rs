@@ -10,10 +10,14 @@ pub const DRIVER_NUM: usize = driver::NUM::Aes as usize;
 
 use core::cell::Cell;
 
+use crate::key_store::KeyResolver;
+
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
 use kernel::hil::symmetric_encryption::{
-    AES128Ctr, CCMClient, Client, GCMClient, AES128, AES128CBC, AES128CCM, AES128ECB, AES128GCM,
-    AES128_BLOCK_SIZE,
+    AES128Ctr, AES256Ctr, CCMClient, ChaCha20Poly1305, ChaCha20Poly1305Client, Client, GCMClient,
+    AES128, AES128CBC, AES128CCM, AES128ECB, AES128GCM, AES128_BLOCK_SIZE, AES128_KEY_SIZE, AES256,
+    AES256CBC, AES256CCM, AES256ECB, AES256GCM, AES256_KEY_SIZE, CCM_NONCE_LENGTH,
+    CHACHA20_POLY1305_KEY_SIZE, CHACHA20_POLY1305_NONCE_SIZE, GCM_IV_LENGTH,
 };
 use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
 use kernel::syscall::{CommandReturn, SyscallDriver};
@@ -36,8 +40,16 @@ mod rw_allow {
     pub const COUNT: u8 = 1;
 }
 
-pub struct AesDriver<'a, A: AES128<'a> + AES128CCM<'static> + AES128GCM<'static>> {
+pub struct AesDriver<
+    'a,
+    A: AES128<'a> + AES128CCM<'static> + AES128GCM<'static>,
+    B: AES256<'a> + AES256CCM<'static> + AES256GCM<'static>,
+    C: ChaCha20Poly1305<'static>,
+> {
     aes: &'a A,
+    aes256: &'a B,
+    chacha: &'a C,
+    key_store: OptionalCell<&'a dyn KeyResolver>,
 
     active: Cell<bool>,
 
@@ -61,10 +73,19 @@ impl<
             + AES128ECB
             + AES128CCM<'static>
             + AES128GCM<'static>,
-    > AesDriver<'static, A>
+        B: AES256<'static>
+            + AES256Ctr
+            + AES256CBC
+            + AES256ECB
+            + AES256CCM<'static>
+            + AES256GCM<'static>,
+        C: ChaCha20Poly1305<'static>,
+    > AesDriver<'static, A, B, C>
 {
     pub fn new(
         aes: &'static A,
+        aes256: &'static B,
+        chacha: &'static C,
         source_buffer: &'static mut [u8],
         dest_buffer: &'static mut [u8],
         grant: Grant<
@@ -73,9 +94,12 @@ impl<
             AllowRoCount<{ ro_allow::COUNT }>,
             AllowRwCount<{ rw_allow::COUNT }>,
         >,
-    ) -> AesDriver<'static, A> {
+    ) -> AesDriver<'static, A, B, C> {
         AesDriver {
             aes,
+            aes256,
+            chacha,
+            key_store: OptionalCell::empty(),
             active: Cell::new(false),
             apps: grant,
             processid: OptionalCell::empty(),
@@ -85,106 +109,144 @@ impl<
         }
     }
 
+    /// Lets apps use keys they have opened in `key_store` instead of passing
+    /// key bytes in an allow buffer.
+    pub fn set_key_store(&self, key_store: &'static dyn KeyResolver) {
+        self.key_store.set(key_store);
+    }
+
+    /// Disables the AES engines. The ChaCha20-Poly1305 engine has no power
+    /// state to manage.
+    fn disable_engines(&self) {
+        self.aes.disable();
+        self.aes256.disable();
+    }
+
     fn run(&self) -> Result<(), ErrorCode> {
         self.processid.map_or(Err(ErrorCode::RESERVE), |processid| {
             self.apps
                 .enter(processid, |app, kernel_data| {
-                    self.aes.enable();
                     match app.aes_operation {
                         Some(AesOperation::AES128Ctr(encrypt)) => {
+                            self.aes.enable();
                             self.aes.set_mode_aes128ctr(encrypt)?
                         }
                         Some(AesOperation::AES128CBC(encrypt)) => {
+                            self.aes.enable();
                             self.aes.set_mode_aes128cbc(encrypt)?
                         }
                         Some(AesOperation::AES128ECB(encrypt)) => {
+                            self.aes.enable();
                             self.aes.set_mode_aes128ecb(encrypt)?
                         }
-                        Some(AesOperation::AES128CCM(_encrypt)) => {}
-                        Some(AesOperation::AES128GCM(_encrypt)) => {}
-                        _ => return Err(ErrorCode::INVAL),
+                        Some(AesOperation::AES128CCM(_)) | Some(AesOperation::AES128GCM(_)) => {
+                            self.aes.enable();
+                        }
+                        Some(AesOperation::AES256Ctr(encrypt)) => {
+                            self.aes256.enable();
+                            self.aes256.set_mode_aes256ctr(encrypt)?
+                        }
+                        Some(AesOperation::AES256CBC(encrypt)) => {
+                            self.aes256.enable();
+                            self.aes256.set_mode_aes256cbc(encrypt)?
+                        }
+                        Some(AesOperation::AES256ECB(encrypt)) => {
+                            self.aes256.enable();
+                            self.aes256.set_mode_aes256ecb(encrypt)?
+                        }
+                        Some(AesOperation::AES256CCM(_)) | Some(AesOperation::AES256GCM(_)) => {
+                            self.aes256.enable();
+                        }
+                        Some(AesOperation::ChaCha20Poly1305(_)) => {}
+                        None => return Err(ErrorCode::INVAL),
                     }
 
-                    kernel_data
-                        .get_readonly_processbuffer(ro_allow::KEY)
-                        .and_then(|key| {
-                            key.enter(|key| {
-                                let mut static_buffer_len = 0;
-                                self.source_buffer.map_or(Err(ErrorCode::NOMEM), |buf| {
-                                    // Determine the size of the static buffer we have
-                                    static_buffer_len = buf.len();
-
-                                    if static_buffer_len > key.len() {
-                                        static_buffer_len = key.len()
-                                    }
-
-                                    // Copy the data into the static buffer
-                                    key[..static_buffer_len]
-                                        .copy_to_slice(&mut buf[..static_buffer_len]);
-
-                                    if let Some(op) = app.aes_operation.as_ref() {
-                                        match op {
-                                            AesOperation::AES128Ctr(_)
-                                            | AesOperation::AES128CBC(_)
-                                            | AesOperation::AES128ECB(_) => {
-                                                AES128::set_key(self.aes, buf)?;
-                                                Ok(())
-                                            }
-                                            AesOperation::AES128CCM(_) => {
-                                                AES128CCM::set_key(self.aes, buf)?;
-                                                Ok(())
-                                            }
-                                            AesOperation::AES128GCM(_) => {
-                                                AES128GCM::set_key(self.aes, buf)?;
-                                                Ok(())
-                                            }
-                                        }
-                                    } else {
-                                        Err(ErrorCode::FAIL)
-                                    }
+                    let op = app.aes_operation.as_ref().ok_or(ErrorCode::FAIL)?;
+                    let key_len = op.key_len();
+
+                    // Keys are at most 256 bits, so stage them on the stack
+                    // rather than in the 16 byte source buffer.
+                    let mut buf = [0; AES256_KEY_SIZE];
+                    match app.key_handle.get() {
+                        Some(handle) => {
+                            // The key never passes through process memory.
+                            let len = self.key_store.map_or(Err(ErrorCode::NOSUPPORT), |ks| {
+                                ks.copy_key(processid, handle, &mut buf)
+                            })?;
+                            if len != key_len {
+                                return Err(ErrorCode::SIZE);
+                            }
+                        }
+                        None => kernel_data
+                            .get_readonly_processbuffer(ro_allow::KEY)
+                            .and_then(|key| {
+                                key.enter(|key| {
+                                    let copy_len = core::cmp::min(key_len, key.len());
+
+                                    // Copy the data into the stack buffer
+                                    key[..copy_len].copy_to_slice(&mut buf[..copy_len]);
                                 })
                             })
-                        })
-                        .unwrap_or(Err(ErrorCode::RESERVE))?;
+                            .map_err(|_| ErrorCode::RESERVE)?,
+                    }
+                    let buf = &buf[..key_len];
+
+                    match op {
+                        AesOperation::AES128Ctr(_)
+                        | AesOperation::AES128CBC(_)
+                        | AesOperation::AES128ECB(_) => AES128::set_key(self.aes, buf),
+                        AesOperation::AES128CCM(_) => AES128CCM::set_key(self.aes, buf),
+                        AesOperation::AES128GCM(_) => AES128GCM::set_key(self.aes, buf),
+                        AesOperation::AES256Ctr(_)
+                        | AesOperation::AES256CBC(_)
+                        | AesOperation::AES256ECB(_) => AES256::set_key(self.aes256, buf),
+                        AesOperation::AES256CCM(_) => AES256CCM::set_key(self.aes256, buf),
+                        AesOperation::AES256GCM(_) => AES256GCM::set_key(self.aes256, buf),
+                        AesOperation::ChaCha20Poly1305(_) => self.chacha.set_key(buf),
+                    }?;
 
                     kernel_data
                         .get_readonly_processbuffer(ro_allow::IV)
                         .and_then(|iv| {
                             iv.enter(|iv| {
-                                let mut static_buffer_len = 0;
-                                self.source_buffer.map_or(Err(ErrorCode::NOMEM), |buf| {
-                                    // Determine the size of the static buffer we have
-                                    static_buffer_len = buf.len();
+                                let mut buf = [0; AES128_BLOCK_SIZE];
+                                let copy_len = core::cmp::min(buf.len(), iv.len());
 
-                                    if static_buffer_len > iv.len() {
-                                        static_buffer_len = iv.len()
-                                    }
+                                // Copy the data into the stack buffer
+                                iv[..copy_len].copy_to_slice(&mut buf[..copy_len]);
 
-                                    // Copy the data into the static buffer
-                                    iv[..static_buffer_len]
-                                        .copy_to_slice(&mut buf[..static_buffer_len]);
-
-                                    if let Some(op) = app.aes_operation.as_ref() {
-                                        match op {
-                                            AesOperation::AES128Ctr(_)
-                                            | AesOperation::AES128CBC(_)
-                                            | AesOperation::AES128ECB(_) => {
-                                                AES128::set_iv(self.aes, buf)?;
-                                                Ok(())
-                                            }
-                                            AesOperation::AES128CCM(_) => {
-                                                AES128CCM::set_nonce(self.aes, &buf[0..13])?;
-                                                Ok(())
-                                            }
-                                            AesOperation::AES128GCM(_) => {
-                                                AES128GCM::set_iv(self.aes, &buf[0..13])?;
-                                                Ok(())
-                                            }
+                                if let Some(op) = app.aes_operation.as_ref() {
+                                    match op {
+                                        AesOperation::AES128Ctr(_)
+                                        | AesOperation::AES128CBC(_)
+                                        | AesOperation::AES128ECB(_) => {
+                                            AES128::set_iv(self.aes, &buf)
                                         }
-                                    } else {
-                                        Err(ErrorCode::FAIL)
+                                        AesOperation::AES128CCM(_) => {
+                                            AES128CCM::set_nonce(self.aes, &buf[..CCM_NONCE_LENGTH])
+                                        }
+                                        AesOperation::AES128GCM(_) => {
+                                            AES128GCM::set_iv(self.aes, &buf[..GCM_IV_LENGTH])
+                                        }
+                                        AesOperation::AES256Ctr(_)
+                                        | AesOperation::AES256CBC(_)
+                                        | AesOperation::AES256ECB(_) => {
+                                            AES256::set_iv(self.aes256, &buf)
+                                        }
+                                        AesOperation::AES256CCM(_) => AES256CCM::set_nonce(
+                                            self.aes256,
+                                            &buf[..CCM_NONCE_LENGTH],
+                                        ),
+                                        AesOperation::AES256GCM(_) => {
+                                            AES256GCM::set_iv(self.aes256, &buf[..GCM_IV_LENGTH])
+                                        }
+                                        AesOperation::ChaCha20Poly1305(_) => self
+                                            .chacha
+                                            .set_nonce(&buf[..CHACHA20_POLY1305_NONCE_SIZE]),
                                     }
-                                })
+                                } else {
+                                    Err(ErrorCode::FAIL)
+                                }
                             })
                         })
                         .unwrap_or(Err(ErrorCode::RESERVE))?;
@@ -196,76 +258,31 @@ impl<
                                 let mut static_buffer_len = 0;
 
                                 if let Some(op) = app.aes_operation.as_ref() {
-                                    match op {
-                                        AesOperation::AES128Ctr(_)
-                                        | AesOperation::AES128CBC(_)
-                                        | AesOperation::AES128ECB(_) => {
-                                            self.source_buffer.map_or(
-                                                Err(ErrorCode::NOMEM),
-                                                |buf| {
-                                                    // Determine the size of the static buffer we have
-                                                    static_buffer_len = buf.len();
-
-                                                    if static_buffer_len > source.len() {
-                                                        static_buffer_len = source.len()
-                                                    }
-
-                                                    // Copy the data into the static buffer
-                                                    source[..static_buffer_len].copy_to_slice(
-                                                        &mut buf[..static_buffer_len],
-                                                    );
-
-                                                    self.data_copied.set(static_buffer_len);
-
-                                                    Ok(())
-                                                },
-                                            )?;
-                                        }
-                                        AesOperation::AES128CCM(_) => {
-                                            self.dest_buffer.map_or(
-                                                Err(ErrorCode::NOMEM),
-                                                |buf| {
-                                                    // Determine the size of the static buffer we have
-                                                    static_buffer_len = buf.len();
-
-                                                    if static_buffer_len > source.len() {
-                                                        static_buffer_len = source.len()
-                                                    }
-
-                                                    // Copy the data into the static buffer
-                                                    source[..static_buffer_len].copy_to_slice(
-                                                        &mut buf[..static_buffer_len],
-                                                    );
-
-                                                    self.data_copied.set(static_buffer_len);
-
-                                                    Ok(())
-                                                },
-                                            )?;
+                                    // Block cipher modes are fed one block at a
+                                    // time from the source buffer, the AEAD
+                                    // modes process the whole message in the
+                                    // destination buffer at once.
+                                    let static_buffer = if op.is_aead() {
+                                        &self.dest_buffer
+                                    } else {
+                                        &self.source_buffer
+                                    };
+                                    static_buffer.map_or(Err(ErrorCode::NOMEM), |buf| {
+                                        // Determine the size of the static buffer we have
+                                        static_buffer_len = buf.len();
+
+                                        if static_buffer_len > source.len() {
+                                            static_buffer_len = source.len()
                                         }
-                                        AesOperation::AES128GCM(_) => {
-                                            self.dest_buffer.map_or(
-                                                Err(ErrorCode::NOMEM),
-                                                |buf| {
-                                                    // Determine the size of the static buffer we have
-                                                    static_buffer_len = buf.len();
 
-                                                    if static_buffer_len > source.len() {
-                                                        static_buffer_len = source.len()
-                                                    }
+                                        // Copy the data into the static buffer
+                                        source[..static_buffer_len]
+                                            .copy_to_slice(&mut buf[..static_buffer_len]);
 
-                                                    // Copy the data into the static buffer
-                                                    source[..static_buffer_len].copy_to_slice(
-                                                        &mut buf[..static_buffer_len],
-                                                    );
+                                        self.data_copied.set(static_buffer_len);
 
-                                                    self.data_copied.set(static_buffer_len);
-
-                                                    Ok(())
-                                                },
-                                            )?;
-                                        }
-                                    }
+                                        Ok(())
+                                    })?;
 
                                     self.calculate_output(
                                         op,
@@ -298,36 +315,24 @@ impl<
         mic_len: usize,
         confidential: bool,
     ) -> Result<(), ErrorCode> {
-        match op {
-            AesOperation::AES128Ctr(_)
-            | AesOperation::AES128CBC(_)
-            | AesOperation::AES128ECB(_) => {
-                if let Some(dest_buf) = self.dest_buffer.take() {
-                    if let Some((e, source, dest)) = AES128::crypt(
+        if op.is_aead() {
+            if let Some(buf) = self.dest_buffer.take() {
+                let result = match op {
+                    AesOperation::AES128CCM(encrypting) => AES128CCM::crypt(
                         self.aes,
-                        self.source_buffer.take(),
-                        dest_buf,
-                        0,
-                        AES128_BLOCK_SIZE,
-                    ) {
-                        // Error, clear the processid and data
-                        self.aes.disable();
-                        self.processid.clear();
-                        if let Some(source_buf) = source {
-                            self.source_buffer.replace(source_buf);
-                        }
-                        self.dest_buffer.replace(dest);
-
-                        return e;
+                        buf,
+                        aoff,
+                        moff,
+                        mlen,
+                        mic_len,
+                        confidential,
+                        *encrypting,
+                    ),
+                    AesOperation::AES128GCM(encrypting) => {
+                        AES128GCM::crypt(self.aes, buf, aoff, moff, mlen, *encrypting)
                     }
-                } else {
-                    return Err(ErrorCode::FAIL);
-                }
-            }
-            AesOperation::AES128CCM(encrypting) => {
-                if let Some(buf) = self.dest_buffer.take() {
-                    if let Err((e, dest)) = AES128CCM::crypt(
-                        self.aes,
+                    AesOperation::AES256CCM(encrypting) => AES256CCM::crypt(
+                        self.aes256,
                         buf,
                         aoff,
                         moff,
@@ -335,39 +340,114 @@ impl<
                         mic_len,
                         confidential,
                         *encrypting,
-                    ) {
-                        // Error, clear the processid and data
-                        self.aes.disable();
-                        self.processid.clear();
-                        self.dest_buffer.replace(dest);
-
-                        return Err(e);
+                    ),
+                    AesOperation::AES256GCM(encrypting) => {
+                        AES256GCM::crypt(self.aes256, buf, aoff, moff, mlen, *encrypting)
                     }
-                } else {
-                    return Err(ErrorCode::FAIL);
+                    AesOperation::ChaCha20Poly1305(encrypting) => {
+                        self.chacha.crypt(buf, aoff, moff, mlen, *encrypting)
+                    }
+                    _ => Err((ErrorCode::INVAL, buf)),
+                };
+
+                if let Err((e, dest)) = result {
+                    // Error, clear the processid and data
+                    self.disable_engines();
+                    self.processid.clear();
+                    self.dest_buffer.replace(dest);
+
+                    return Err(e);
                 }
+            } else {
+                return Err(ErrorCode::FAIL);
             }
-            AesOperation::AES128GCM(encrypting) => {
-                if let Some(buf) = self.dest_buffer.take() {
-                    if let Err((e, dest)) =
-                        AES128GCM::crypt(self.aes, buf, aoff, moff, mlen, *encrypting)
-                    {
-                        // Error, clear the appid and data
-                        self.aes.disable();
-                        self.processid.clear();
-                        self.dest_buffer.replace(dest);
+        } else if let Some(dest_buf) = self.dest_buffer.take() {
+            let source_buf = self.source_buffer.take();
+            let result = match op {
+                AesOperation::AES256Ctr(_)
+                | AesOperation::AES256CBC(_)
+                | AesOperation::AES256ECB(_) => {
+                    AES256::crypt(self.aes256, source_buf, dest_buf, 0, AES128_BLOCK_SIZE)
+                }
+                _ => AES128::crypt(self.aes, source_buf, dest_buf, 0, AES128_BLOCK_SIZE),
+            };
 
-                        return Err(e);
-                    }
-                } else {
-                    return Err(ErrorCode::FAIL);
+            if let Some((e, source, dest)) = result {
+                // Error, clear the processid and data
+                self.disable_engines();
+                self.processid.clear();
+                if let Some(source_buf) = source {
+                    self.source_buffer.replace(source_buf);
                 }
+                self.dest_buffer.replace(dest);
+
+                return e;
             }
+        } else {
+            return Err(ErrorCode::FAIL);
         }
 
         Ok(())
     }
 
+    /// Completes a CCM, GCM or ChaCha20-Poly1305 operation, which processes
+    /// the whole message at once, by copying the buffer back to the app.
+    fn aead_done(&self, buf: &'static mut [u8], res: Result<(), ErrorCode>, tag_is_valid: bool) {
+        self.dest_buffer.replace(buf);
+
+        self.processid.map(|id| {
+            self.apps
+                .enter(id, |_, kernel_data| {
+                    let mut exit = false;
+
+                    if let Err(e) = res {
+                        kernel_data.schedule_upcall(0, (e as usize, 0, 0)).ok();
+                        return;
+                    }
+
+                    self.dest_buffer.map(|buf| {
+                        let ret = kernel_data
+                            .get_readwrite_processbuffer(rw_allow::DEST)
+                            .and_then(|dest| {
+                                dest.mut_enter(|dest| {
+                                    // The message was processed in place, so
+                                    // the output (and any tag) is at the same
+                                    // offsets as the input.
+                                    let len = core::cmp::min(buf.len(), dest.len());
+                                    dest[..len].copy_from_slice(&buf[..len]);
+                                })
+                            });
+
+                        if let Err(e) = ret {
+                            // No data buffer, clear the processid and data
+                            self.disable_engines();
+                            self.processid.clear();
+                            kernel_data.schedule_upcall(0, (e as usize, 0, 0)).ok();
+                            exit = true;
+                        }
+                    });
+
+                    if exit {
+                        return;
+                    }
+
+                    // The AEAD modes are online only, we can't send any more
+                    // data in, so just report what we did to the app.
+                    kernel_data
+                        .schedule_upcall(0, (0, self.data_copied.get(), tag_is_valid as usize))
+                        .ok();
+                    self.data_copied.set(0);
+                })
+                .map_err(|err| {
+                    if err == kernel::process::Error::NoSuchApp
+                        || err == kernel::process::Error::InactiveApp
+                    {
+                        self.processid.clear();
+                    }
+                })
+        });
+    }
+
     fn check_queue(&self) {
         for appiter in self.apps.iter() {
             let started_command = appiter.enter(|app, _| {
@@ -398,7 +478,14 @@ impl<
             + AES128ECB
             + AES128CCM<'static>
             + AES128GCM<'static>,
-    > Client<'static> for AesDriver<'static, A>
+        B: AES256<'static>
+            + AES256Ctr
+            + AES256CBC
+            + AES256ECB
+            + AES256CCM<'static>
+            + AES256GCM<'static>,
+        C: ChaCha20Poly1305<'static>,
+    > Client<'static> for AesDriver<'static, A, B, C>
 {
     fn crypt_done(&self, source: Option<&'static mut [u8]>, destination: &'static mut [u8]) {
         if let Some(source_buf) = source {
@@ -448,7 +535,7 @@ impl<
 
                         if let Err(e) = ret {
                             // No data buffer, clear the processid and data
-                            self.aes.disable();
+                            self.disable_engines();
                             self.processid.clear();
                             kernel_data.schedule_upcall(0, (e as usize, 0, 0)).ok();
                             exit = true;
@@ -488,7 +575,7 @@ impl<
 
                         if let Err(e) = ret {
                             // No data buffer, clear the processid and data
-                            self.aes.disable();
+                            self.disable_engines();
                             self.processid.clear();
                             kernel_data.schedule_upcall(0, (e as usize, 0, 0)).ok();
                             exit = true;
@@ -519,7 +606,7 @@ impl<
                                     .is_err()
                                 {
                                     // Error, clear the processid and data
-                                    self.aes.disable();
+                                    self.disable_engines();
                                     self.processid.clear();
                                     self.check_queue();
                                     return;
@@ -555,73 +642,17 @@ impl<
             + AES128ECB
             + AES128CCM<'static>
             + AES128GCM<'static>,
-    > CCMClient for AesDriver<'static, A>
+        B: AES256<'static>
+            + AES256Ctr
+            + AES256CBC
+            + AES256ECB
+            + AES256CCM<'static>
+            + AES256GCM<'static>,
+        C: ChaCha20Poly1305<'static>,
+    > CCMClient for AesDriver<'static, A, B, C>
 {
     fn crypt_done(&self, buf: &'static mut [u8], res: Result<(), ErrorCode>, tag_is_valid: bool) {
-        self.dest_buffer.replace(buf);
-
-        self.processid.map(|id| {
-            self.apps
-                .enter(id, |_, kernel_data| {
-                    let mut exit = false;
-
-                    if let Err(e) = res {
-                        kernel_data.schedule_upcall(0, (e as usize, 0, 0)).ok();
-                        return;
-                    }
-
-                    self.dest_buffer.map(|buf| {
-                        let ret = kernel_data
-                            .get_readwrite_processbuffer(rw_allow::DEST)
-                            .and_then(|dest| {
-                                dest.mut_enter(|dest| {
-                                    let offset = self.data_copied.get()
-                                        - (core::cmp::min(buf.len(), dest.len()));
-                                    let app_len = dest.len();
-                                    let static_len = buf.len();
-
-                                    if app_len < static_len {
-                                        if app_len - offset > 0 {
-                                            dest[offset..app_len]
-                                                .copy_from_slice(&buf[0..(app_len - offset)]);
-                                        }
-                                    } else {
-                                        if offset + static_len <= app_len {
-                                            dest[offset..(offset + static_len)]
-                                                .copy_from_slice(&buf[0..static_len]);
-                                        }
-                                    }
-                                })
-                            });
-
-                        if let Err(e) = ret {
-                            // No data buffer, clear the processid and data
-                            self.aes.disable();
-                            self.processid.clear();
-                            kernel_data.schedule_upcall(0, (e as usize, 0, 0)).ok();
-                            exit = true;
-                        }
-                    });
-
-                    if exit {
-                        return;
-                    }
-
-                    // AES CCM is online only we can't send any more data in, so
-                    // just report what we did to the app.
-                    kernel_data
-                        .schedule_upcall(0, (0, self.data_copied.get(), tag_is_valid as usize))
-                        .ok();
-                    self.data_copied.set(0);
-                })
-                .map_err(|err| {
-                    if err == kernel::process::Error::NoSuchApp
-                        || err == kernel::process::Error::InactiveApp
-                    {
-                        self.processid.clear();
-                    }
-                })
-        });
+        self.aead_done(buf, res, tag_is_valid);
     }
 }
 
@@ -632,73 +663,38 @@ impl<
             + AES128ECB
             + AES128CCM<'static>
             + AES128GCM<'static>,
-    > GCMClient for AesDriver<'static, A>
+        B: AES256<'static>
+            + AES256Ctr
+            + AES256CBC
+            + AES256ECB
+            + AES256CCM<'static>
+            + AES256GCM<'static>,
+        C: ChaCha20Poly1305<'static>,
+    > GCMClient for AesDriver<'static, A, B, C>
 {
     fn crypt_done(&self, buf: &'static mut [u8], res: Result<(), ErrorCode>, tag_is_valid: bool) {
-        self.dest_buffer.replace(buf);
-
-        self.processid.map(|id| {
-            self.apps
-                .enter(id, |_, kernel_data| {
-                    let mut exit = false;
-
-                    if let Err(e) = res {
-                        kernel_data.schedule_upcall(0, (e as usize, 0, 0)).ok();
-                        return;
-                    }
-
-                    self.dest_buffer.map(|buf| {
-                        let ret = kernel_data
-                            .get_readwrite_processbuffer(rw_allow::DEST)
-                            .and_then(|dest| {
-                                dest.mut_enter(|dest| {
-                                    let offset = self.data_copied.get()
-                                        - (core::cmp::min(buf.len(), dest.len()));
-                                    let app_len = dest.len();
-                                    let static_len = buf.len();
-
-                                    if app_len < static_len {
-                                        if app_len - offset > 0 {
-                                            dest[offset..app_len]
-                                                .copy_from_slice(&buf[0..(app_len - offset)]);
-                                        }
-                                    } else {
-                                        if offset + static_len <= app_len {
-                                            dest[offset..(offset + static_len)]
-                                                .copy_from_slice(&buf[0..static_len]);
-                                        }
-                                    }
-                                })
-                            });
-
-                        if let Err(e) = ret {
-                            // No data buffer, clear the appid and data
-                            self.aes.disable();
-                            self.processid.clear();
-                            kernel_data.schedule_upcall(0, (e as usize, 0, 0)).ok();
-                            exit = true;
-                        }
-                    });
-
-                    if exit {
-                        return;
-                    }
+        self.aead_done(buf, res, tag_is_valid);
+    }
+}
 
-                    // AES GCM is online only we can't send any more data in, so
-                    // just report what we did to the app.
-                    kernel_data
-                        .schedule_upcall(0, (0, self.data_copied.get(), tag_is_valid as usize))
-                        .ok();
-                    self.data_copied.set(0);
-                })
-                .map_err(|err| {
-                    if err == kernel::process::Error::NoSuchApp
-                        || err == kernel::process::Error::InactiveApp
-                    {
-                        self.processid.clear();
-                    }
-                })
-        });
+impl<
+        A: AES128<'static>
+            + AES128Ctr
+            + AES128CBC
+            + AES128ECB
+            + AES128CCM<'static>
+            + AES128GCM<'static>,
+        B: AES256<'static>
+            + AES256Ctr
+            + AES256CBC
+            + AES256ECB
+            + AES256CCM<'static>
+            + AES256GCM<'static>,
+        C: ChaCha20Poly1305<'static>,
+    > ChaCha20Poly1305Client for AesDriver<'static, A, B, C>
+{
+    fn crypt_done(&self, buf: &'static mut [u8], res: Result<(), ErrorCode>, tag_is_valid: bool) {
+        self.aead_done(buf, res, tag_is_valid);
     }
 }
 
@@ -709,7 +705,14 @@ impl<
             + AES128ECB
             + AES128CCM<'static>
             + AES128GCM<'static>,
-    > SyscallDriver for AesDriver<'static, A>
+        B: AES256<'static>
+            + AES256Ctr
+            + AES256CBC
+            + AES256ECB
+            + AES256CCM<'static>
+            + AES256GCM<'static>,
+        C: ChaCha20Poly1305<'static>,
+    > SyscallDriver for AesDriver<'static, A, B, C>
 {
     fn command(
         &self,
@@ -773,7 +776,7 @@ impl<
             let ret = self.run();
 
             return if let Err(e) = ret {
-                self.aes.disable();
+                self.disable_engines();
                 self.processid.clear();
                 self.check_queue();
                 CommandReturn::failure(e)
@@ -811,6 +814,30 @@ impl<
                             app.aes_operation = Some(AesOperation::AES128GCM(data2 != 0));
                             CommandReturn::success()
                         }
+                        5 => {
+                            app.aes_operation = Some(AesOperation::AES256Ctr(data2 != 0));
+                            CommandReturn::success()
+                        }
+                        6 => {
+                            app.aes_operation = Some(AesOperation::AES256CBC(data2 != 0));
+                            CommandReturn::success()
+                        }
+                        7 => {
+                            app.aes_operation = Some(AesOperation::AES256ECB(data2 != 0));
+                            CommandReturn::success()
+                        }
+                        8 => {
+                            app.aes_operation = Some(AesOperation::AES256CCM(data2 != 0));
+                            CommandReturn::success()
+                        }
+                        9 => {
+                            app.aes_operation = Some(AesOperation::AES256GCM(data2 != 0));
+                            CommandReturn::success()
+                        }
+                        10 => {
+                            app.aes_operation = Some(AesOperation::ChaCha20Poly1305(data2 != 0));
+                            CommandReturn::success()
+                        }
                         _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
                     },
 
@@ -898,7 +925,7 @@ impl<
                     // This will not trigger a callback and will not process any data from userspace
                     4 => {
                         if app_match {
-                            self.aes.disable();
+                            self.disable_engines();
                             self.processid.clear();
 
                             CommandReturn::success()
@@ -937,6 +964,31 @@ impl<
                         CommandReturn::success()
                     }
 
+                    // Set mlen for CCM, GCM and ChaCha20-Poly1305
+                    // This will not trigger a callback and will not process any data from userspace
+                    9 => {
+                        app.mlen.set(data1);
+                        CommandReturn::success()
+                    }
+
+                    // Use the key store handle in data1 as the key
+                    // This will not trigger a callback and will not process any data from userspace
+                    10 => {
+                        if self.key_store.is_some() {
+                            app.key_handle.set(data1);
+                            CommandReturn::success()
+                        } else {
+                            CommandReturn::failure(ErrorCode::NOSUPPORT)
+                        }
+                    }
+
+                    // Go back to using the key in the key allow buffer
+                    // This will not trigger a callback and will not process any data from userspace
+                    11 => {
+                        app.key_handle.clear();
+                        CommandReturn::success()
+                    }
+
                     // default
                     _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
                 }
@@ -948,6 +1000,9 @@ impl<
             || command_num == 6
             || command_num == 7
             || command_num == 8
+            || command_num == 9
+            || command_num == 10
+            || command_num == 11
         {
             self.check_queue();
         }
@@ -966,6 +1021,43 @@ enum AesOperation {
     AES128ECB(bool),
     AES128CCM(bool),
     AES128GCM(bool),
+    AES256Ctr(bool),
+    AES256CBC(bool),
+    AES256ECB(bool),
+    AES256CCM(bool),
+    AES256GCM(bool),
+    ChaCha20Poly1305(bool),
+}
+
+impl AesOperation {
+    fn key_len(&self) -> usize {
+        match self {
+            AesOperation::AES128Ctr(_)
+            | AesOperation::AES128CBC(_)
+            | AesOperation::AES128ECB(_)
+            | AesOperation::AES128CCM(_)
+            | AesOperation::AES128GCM(_) => AES128_KEY_SIZE,
+            AesOperation::AES256Ctr(_)
+            | AesOperation::AES256CBC(_)
+            | AesOperation::AES256ECB(_)
+            | AesOperation::AES256CCM(_)
+            | AesOperation::AES256GCM(_) => AES256_KEY_SIZE,
+            AesOperation::ChaCha20Poly1305(_) => CHACHA20_POLY1305_KEY_SIZE,
+        }
+    }
+
+    /// Whether the operation authenticates and processes the whole message
+    /// at once, rather than a block at a time.
+    fn is_aead(&self) -> bool {
+        matches!(
+            self,
+            AesOperation::AES128CCM(_)
+                | AesOperation::AES128GCM(_)
+                | AesOperation::AES256CCM(_)
+                | AesOperation::AES256GCM(_)
+                | AesOperation::ChaCha20Poly1305(_)
+        )
+    }
 }
 
 #[derive(Default)]
@@ -978,4 +1070,5 @@ pub struct App {
     mlen: Cell<usize>,
     mic_len: Cell<usize>,
     confidential: Cell<bool>,
+    key_handle: OptionalCell<usize>,
 }
@@ -0,0 +1,385 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Software ChaCha20-Poly1305 AEAD, as specified in RFC 8439.
+//!
+//! ChaCha20 only uses additions, rotations and XORs, so unlike a table based
+//! software AES it runs in constant time. Operations are performed in a
+//! deferred call and the client is called back from there.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let chacha = static_init!(
+//!     capsules_extra::symmetric_encryption::chacha20poly1305::SoftwareChaCha20Poly1305<'static>,
+//!     capsules_extra::symmetric_encryption::chacha20poly1305::SoftwareChaCha20Poly1305::new()
+//! );
+//! kernel::deferred_call::DeferredCallClient::register(chacha);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::symmetric_encryption::{
+    ChaCha20Poly1305, ChaCha20Poly1305Client, CHACHA20_POLY1305_KEY_SIZE,
+    CHACHA20_POLY1305_NONCE_SIZE, CHACHA20_POLY1305_TAG_SIZE,
+};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+const CHACHA20_BLOCK_SIZE: usize = 64;
+const POLY1305_BLOCK_SIZE: usize = 16;
+
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+/// The ChaCha20 block function (RFC 8439 section 2.3).
+fn chacha20_block(
+    key: &[u8; CHACHA20_POLY1305_KEY_SIZE],
+    counter: u32,
+    nonce: &[u8; CHACHA20_POLY1305_NONCE_SIZE],
+) -> [u8; CHACHA20_BLOCK_SIZE] {
+    let mut initial = [0u32; 16];
+    initial[..4].copy_from_slice(&[0x61707865, 0x3320646e, 0x79622d32, 0x6b206574]);
+    for (word, chunk) in initial[4..12].iter_mut().zip(key.chunks_exact(4)) {
+        *word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    }
+    initial[12] = counter;
+    for (word, chunk) in initial[13..].iter_mut().zip(nonce.chunks_exact(4)) {
+        *word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    }
+
+    let mut state = initial;
+    for _ in 0..10 {
+        quarter_round(&mut state, 0, 4, 8, 12);
+        quarter_round(&mut state, 1, 5, 9, 13);
+        quarter_round(&mut state, 2, 6, 10, 14);
+        quarter_round(&mut state, 3, 7, 11, 15);
+        quarter_round(&mut state, 0, 5, 10, 15);
+        quarter_round(&mut state, 1, 6, 11, 12);
+        quarter_round(&mut state, 2, 7, 8, 13);
+        quarter_round(&mut state, 3, 4, 9, 14);
+    }
+
+    let mut block = [0; CHACHA20_BLOCK_SIZE];
+    for (chunk, (word, initial)) in block
+        .chunks_exact_mut(4)
+        .zip(state.iter().zip(initial.iter()))
+    {
+        chunk.copy_from_slice(&word.wrapping_add(*initial).to_le_bytes());
+    }
+    block
+}
+
+/// XORs `data` with the ChaCha20 key stream, starting at block `counter`.
+fn chacha20_xor(
+    key: &[u8; CHACHA20_POLY1305_KEY_SIZE],
+    nonce: &[u8; CHACHA20_POLY1305_NONCE_SIZE],
+    counter: u32,
+    data: &mut [u8],
+) {
+    for (i, chunk) in data.chunks_mut(CHACHA20_BLOCK_SIZE).enumerate() {
+        let key_stream = chacha20_block(key, counter.wrapping_add(i as u32), nonce);
+        chunk
+            .iter_mut()
+            .zip(key_stream.iter())
+            .for_each(|(b, k)| *b ^= k);
+    }
+}
+
+/// The Poly1305 one-time authenticator (RFC 8439 section 2.5), with the
+/// accumulator kept in five 26-bit limbs. Input is zero padded to a whole
+/// block whenever `pad` is called.
+struct Poly1305 {
+    r: [u32; 5],
+    h: [u32; 5],
+    pad: [u32; 4],
+    block: [u8; POLY1305_BLOCK_SIZE],
+    used: usize,
+}
+
+impl Poly1305 {
+    fn new(key: &[u8; 32]) -> Poly1305 {
+        let word = |i: usize| u32::from_le_bytes([key[i], key[i + 1], key[i + 2], key[i + 3]]);
+        Poly1305 {
+            // r is clamped as it is read.
+            r: [
+                word(0) & 0x3ffffff,
+                (word(3) >> 2) & 0x3ffff03,
+                (word(6) >> 4) & 0x3ffc0ff,
+                (word(9) >> 6) & 0x3f03fff,
+                (word(12) >> 8) & 0x00fffff,
+            ],
+            h: [0; 5],
+            pad: [word(16), word(20), word(24), word(28)],
+            block: [0; POLY1305_BLOCK_SIZE],
+            used: 0,
+        }
+    }
+
+    /// Adds a block to the accumulator, with `high_bit` set above its top
+    /// byte, and multiplies by `r`.
+    fn process_block(&mut self, high_bit: u32) {
+        let block = &self.block;
+        let word =
+            |i: usize| u32::from_le_bytes([block[i], block[i + 1], block[i + 2], block[i + 3]]);
+        let h = &mut self.h;
+        h[0] += word(0) & 0x3ffffff;
+        h[1] += (word(3) >> 2) & 0x3ffffff;
+        h[2] += (word(6) >> 4) & 0x3ffffff;
+        h[3] += (word(9) >> 6) & 0x3ffffff;
+        h[4] += (word(12) >> 8) | high_bit;
+
+        // Limbs of h * r overflow past 2^130, which wraps around as a
+        // multiple of 5 since 2^130 = 5 mod p.
+        let r = self.r.map(|limb| limb as u64);
+        let s = [0, r[1] * 5, r[2] * 5, r[3] * 5, r[4] * 5];
+        let h64 = h.map(|limb| limb as u64);
+        let mut d = [
+            h64[0] * r[0] + h64[1] * s[4] + h64[2] * s[3] + h64[3] * s[2] + h64[4] * s[1],
+            h64[0] * r[1] + h64[1] * r[0] + h64[2] * s[4] + h64[3] * s[3] + h64[4] * s[2],
+            h64[0] * r[2] + h64[1] * r[1] + h64[2] * r[0] + h64[3] * s[4] + h64[4] * s[3],
+            h64[0] * r[3] + h64[1] * r[2] + h64[2] * r[1] + h64[3] * r[0] + h64[4] * s[4],
+            h64[0] * r[4] + h64[1] * r[3] + h64[2] * r[2] + h64[3] * r[1] + h64[4] * r[0],
+        ];
+
+        for i in 0..4 {
+            d[i + 1] += d[i] >> 26;
+            d[i] &= 0x3ffffff;
+        }
+        let carry = d[4] >> 26;
+        d[4] &= 0x3ffffff;
+        d[0] += carry * 5;
+        d[1] += d[0] >> 26;
+        d[0] &= 0x3ffffff;
+
+        *h = d.map(|limb| limb as u32);
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for b in data {
+            self.block[self.used] = *b;
+            self.used += 1;
+            if self.used == POLY1305_BLOCK_SIZE {
+                self.process_block(1 << 24);
+                self.used = 0;
+            }
+        }
+    }
+
+    fn pad(&mut self) {
+        if self.used > 0 {
+            self.block[self.used..].fill(0);
+            self.process_block(1 << 24);
+            self.used = 0;
+        }
+    }
+
+    fn finish(mut self) -> [u8; CHACHA20_POLY1305_TAG_SIZE] {
+        if self.used > 0 {
+            // A partial block gets a 1 byte appended instead of the high bit.
+            self.block[self.used] = 1;
+            self.block[self.used + 1..].fill(0);
+            self.process_block(0);
+        }
+
+        // Fully carry h, then compute h - p = h + 5 - 2^130 and keep it if
+        // it is not negative.
+        let mut h = self.h;
+        for i in 1..5 {
+            h[i] += h[i - 1] >> 26;
+            h[i - 1] &= 0x3ffffff;
+        }
+        h[0] += (h[4] >> 26) * 5;
+        h[4] &= 0x3ffffff;
+        h[1] += h[0] >> 26;
+        h[0] &= 0x3ffffff;
+
+        let mut g = [0u32; 5];
+        let mut carry = 5;
+        for i in 0..5 {
+            g[i] = h[i].wrapping_add(carry);
+            carry = g[i] >> 26;
+            g[i] &= 0x3ffffff;
+        }
+        g[4] = g[4].wrapping_add(carry << 26).wrapping_sub(1 << 26);
+        // All ones if g is not negative.
+        let mask = (g[4] >> 31).wrapping_sub(1);
+        for i in 0..5 {
+            h[i] = (h[i] & !mask) | (g[i] & mask);
+        }
+
+        // Pack into 128 bits and add the pad.
+        let packed = [
+            h[0] | (h[1] << 26),
+            (h[1] >> 6) | (h[2] << 20),
+            (h[2] >> 12) | (h[3] << 14),
+            (h[3] >> 18) | (h[4] << 8),
+        ];
+        let mut tag = [0; CHACHA20_POLY1305_TAG_SIZE];
+        let mut carry = 0u64;
+        for (chunk, (word, pad)) in tag
+            .chunks_exact_mut(4)
+            .zip(packed.iter().zip(self.pad.iter()))
+        {
+            let sum = *word as u64 + *pad as u64 + carry;
+            chunk.copy_from_slice(&(sum as u32).to_le_bytes());
+            carry = sum >> 32;
+        }
+        tag
+    }
+}
+
+pub struct SoftwareChaCha20Poly1305<'a> {
+    key: Cell<Option<[u8; CHACHA20_POLY1305_KEY_SIZE]>>,
+    nonce: Cell<[u8; CHACHA20_POLY1305_NONCE_SIZE]>,
+
+    /// `(aad_offset, message_offset, message_len, encrypting)` of the
+    /// operation waiting for the deferred call.
+    operation: OptionalCell<(usize, usize, usize, bool)>,
+    buf: TakeCell<'static, [u8]>,
+
+    client: OptionalCell<&'a dyn ChaCha20Poly1305Client>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a> SoftwareChaCha20Poly1305<'a> {
+    pub fn new() -> SoftwareChaCha20Poly1305<'a> {
+        SoftwareChaCha20Poly1305 {
+            key: Cell::new(None),
+            nonce: Cell::new([0; CHACHA20_POLY1305_NONCE_SIZE]),
+            operation: OptionalCell::empty(),
+            buf: TakeCell::empty(),
+            client: OptionalCell::empty(),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    /// Encrypts or decrypts the message in place (RFC 8439 section 2.8).
+    /// Returns whether the tag is valid.
+    fn crypt_message(
+        key: &[u8; CHACHA20_POLY1305_KEY_SIZE],
+        nonce: &[u8; CHACHA20_POLY1305_NONCE_SIZE],
+        buf: &mut [u8],
+        aad_offset: usize,
+        message_offset: usize,
+        message_len: usize,
+        encrypting: bool,
+    ) -> bool {
+        let message_end = message_offset + message_len;
+
+        // The Poly1305 key is the start of the key stream block 0, and the
+        // message is encrypted from block 1.
+        let mut poly_key = [0; 32];
+        poly_key.copy_from_slice(&chacha20_block(key, 0, nonce)[..32]);
+        let mut mac = Poly1305::new(&poly_key);
+
+        mac.update(&buf[aad_offset..message_offset]);
+        mac.pad();
+        if encrypting {
+            chacha20_xor(key, nonce, 1, &mut buf[message_offset..message_end]);
+            mac.update(&buf[message_offset..message_end]);
+        } else {
+            mac.update(&buf[message_offset..message_end]);
+            chacha20_xor(key, nonce, 1, &mut buf[message_offset..message_end]);
+        }
+        mac.pad();
+        mac.update(&((message_offset - aad_offset) as u64).to_le_bytes());
+        mac.update(&(message_len as u64).to_le_bytes());
+        let tag = mac.finish();
+
+        let tag_range = message_end..message_end + CHACHA20_POLY1305_TAG_SIZE;
+        if encrypting {
+            buf[tag_range].copy_from_slice(&tag);
+            true
+        } else {
+            // Compare without stopping at the first difference.
+            buf[tag_range]
+                .iter()
+                .zip(tag.iter())
+                .fold(0, |acc, (a, b)| acc | (a ^ b))
+                == 0
+        }
+    }
+}
+
+impl DeferredCallClient for SoftwareChaCha20Poly1305<'_> {
+    fn handle_deferred_call(&self) {
+        let (Some((aad_offset, message_offset, message_len, encrypting)), Some(buf), Some(key)) =
+            (self.operation.take(), self.buf.take(), self.key.get())
+        else {
+            return;
+        };
+        let tag_is_valid = Self::crypt_message(
+            &key,
+            &self.nonce.get(),
+            buf,
+            aad_offset,
+            message_offset,
+            message_len,
+            encrypting,
+        );
+        self.client.map(move |client| {
+            client.crypt_done(buf, Ok(()), tag_is_valid);
+        });
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+impl<'a> ChaCha20Poly1305<'a> for SoftwareChaCha20Poly1305<'a> {
+    fn set_client(&'a self, client: &'a dyn ChaCha20Poly1305Client) {
+        self.client.set(client);
+    }
+
+    fn set_key(&self, key: &[u8]) -> Result<(), ErrorCode> {
+        self.key
+            .set(Some(key.try_into().map_err(|_| ErrorCode::INVAL)?));
+        Ok(())
+    }
+
+    fn set_nonce(&self, nonce: &[u8]) -> Result<(), ErrorCode> {
+        self.nonce
+            .set(nonce.try_into().map_err(|_| ErrorCode::INVAL)?);
+        Ok(())
+    }
+
+    fn crypt(
+        &self,
+        buf: &'static mut [u8],
+        aad_offset: usize,
+        message_offset: usize,
+        message_len: usize,
+        encrypting: bool,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.operation.is_some() {
+            return Err((ErrorCode::BUSY, buf));
+        }
+        if self.key.get().is_none() {
+            return Err((ErrorCode::INVAL, buf));
+        }
+        if !(aad_offset <= message_offset
+            && message_offset + message_len + CHACHA20_POLY1305_TAG_SIZE <= buf.len())
+        {
+            return Err((ErrorCode::SIZE, buf));
+        }
+
+        self.buf.replace(buf);
+        self.operation
+            .set((aad_offset, message_offset, message_len, encrypting));
+        self.deferred_call.set();
+        Ok(())
+    }
+}
@@ -3,3 +3,5 @@
 // Copyright Tock Contributors 2022.
 
 pub mod aes;
+pub mod chacha20poly1305;
+pub mod software_aes;
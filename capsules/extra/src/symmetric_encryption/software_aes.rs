@@ -0,0 +1,990 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Software AES with 128 and 256 bit keys.
+//!
+//! `SoftwareAes` implements both the `AES128` and the `AES256` families of
+//! the symmetric encryption HIL, including CCM* and GCM, for chips that have
+//! no AES engine. The key size used by an operation is that of the last key
+//! set, through any of the traits. Operations are performed in a deferred
+//! call and the client is called back from there.
+//!
+//! The cipher uses table lookups, so its timing depends on the key and data.
+//! It should not be used where an attacker can time individual operations.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let aes = static_init!(
+//!     capsules_extra::symmetric_encryption::software_aes::SoftwareAes<'static>,
+//!     capsules_extra::symmetric_encryption::software_aes::SoftwareAes::new()
+//! );
+//! kernel::deferred_call::DeferredCallClient::register(aes);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::symmetric_encryption::{
+    AES128Ctr, AES256Ctr, CCMClient, Client, GCMClient, AES128, AES128CBC, AES128CCM, AES128ECB,
+    AES128GCM, AES128_BLOCK_SIZE, AES128_KEY_SIZE, AES256, AES256CBC, AES256CCM, AES256ECB,
+    AES256GCM, AES256_KEY_SIZE, CCM_NONCE_LENGTH, GCM_IV_LENGTH,
+};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+type Block = [u8; AES128_BLOCK_SIZE];
+
+const GCM_TAG_LENGTH: usize = 16;
+
+const SBOX: [u8; 256] = [
+    0x63, 0x7c, 0x77, 0x7b, 0xf2, 0x6b, 0x6f, 0xc5, 0x30, 0x01, 0x67, 0x2b, 0xfe, 0xd7, 0xab, 0x76,
+    0xca, 0x82, 0xc9, 0x7d, 0xfa, 0x59, 0x47, 0xf0, 0xad, 0xd4, 0xa2, 0xaf, 0x9c, 0xa4, 0x72, 0xc0,
+    0xb7, 0xfd, 0x93, 0x26, 0x36, 0x3f, 0xf7, 0xcc, 0x34, 0xa5, 0xe5, 0xf1, 0x71, 0xd8, 0x31, 0x15,
+    0x04, 0xc7, 0x23, 0xc3, 0x18, 0x96, 0x05, 0x9a, 0x07, 0x12, 0x80, 0xe2, 0xeb, 0x27, 0xb2, 0x75,
+    0x09, 0x83, 0x2c, 0x1a, 0x1b, 0x6e, 0x5a, 0xa0, 0x52, 0x3b, 0xd6, 0xb3, 0x29, 0xe3, 0x2f, 0x84,
+    0x53, 0xd1, 0x00, 0xed, 0x20, 0xfc, 0xb1, 0x5b, 0x6a, 0xcb, 0xbe, 0x39, 0x4a, 0x4c, 0x58, 0xcf,
+    0xd0, 0xef, 0xaa, 0xfb, 0x43, 0x4d, 0x33, 0x85, 0x45, 0xf9, 0x02, 0x7f, 0x50, 0x3c, 0x9f, 0xa8,
+    0x51, 0xa3, 0x40, 0x8f, 0x92, 0x9d, 0x38, 0xf5, 0xbc, 0xb6, 0xda, 0x21, 0x10, 0xff, 0xf3, 0xd2,
+    0xcd, 0x0c, 0x13, 0xec, 0x5f, 0x97, 0x44, 0x17, 0xc4, 0xa7, 0x7e, 0x3d, 0x64, 0x5d, 0x19, 0x73,
+    0x60, 0x81, 0x4f, 0xdc, 0x22, 0x2a, 0x90, 0x88, 0x46, 0xee, 0xb8, 0x14, 0xde, 0x5e, 0x0b, 0xdb,
+    0xe0, 0x32, 0x3a, 0x0a, 0x49, 0x06, 0x24, 0x5c, 0xc2, 0xd3, 0xac, 0x62, 0x91, 0x95, 0xe4, 0x79,
+    0xe7, 0xc8, 0x37, 0x6d, 0x8d, 0xd5, 0x4e, 0xa9, 0x6c, 0x56, 0xf4, 0xea, 0x65, 0x7a, 0xae, 0x08,
+    0xba, 0x78, 0x25, 0x2e, 0x1c, 0xa6, 0xb4, 0xc6, 0xe8, 0xdd, 0x74, 0x1f, 0x4b, 0xbd, 0x8b, 0x8a,
+    0x70, 0x3e, 0xb5, 0x66, 0x48, 0x03, 0xf6, 0x0e, 0x61, 0x35, 0x57, 0xb9, 0x86, 0xc1, 0x1d, 0x9e,
+    0xe1, 0xf8, 0x98, 0x11, 0x69, 0xd9, 0x8e, 0x94, 0x9b, 0x1e, 0x87, 0xe9, 0xce, 0x55, 0x28, 0xdf,
+    0x8c, 0xa1, 0x89, 0x0d, 0xbf, 0xe6, 0x42, 0x68, 0x41, 0x99, 0x2d, 0x0f, 0xb0, 0x54, 0xbb, 0x16,
+];
+
+const INV_SBOX: [u8; 256] = invert_sbox();
+
+const fn invert_sbox() -> [u8; 256] {
+    let mut inv = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        inv[SBOX[i] as usize] = i as u8;
+        i += 1;
+    }
+    inv
+}
+
+/// Multiplies by `x` in GF(2^8).
+fn xtime(b: u8) -> u8 {
+    (b << 1) ^ ((b >> 7) * 0x1b)
+}
+
+/// Multiplies two elements of GF(2^8).
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 == 1 {
+            product ^= a;
+        }
+        a = xtime(a);
+        b >>= 1;
+    }
+    product
+}
+
+fn xor_block(block: &mut [u8], other: &[u8]) {
+    block
+        .iter_mut()
+        .zip(other.iter())
+        .for_each(|(b, o)| *b ^= *o);
+}
+
+fn sub_bytes(block: &mut Block, sbox: &[u8; 256]) {
+    block.iter_mut().for_each(|b| *b = sbox[*b as usize]);
+}
+
+/// The state is stored column by column, so row `r` of column `c` is byte
+/// `r + 4 * c`.
+fn shift_rows(block: &mut Block) {
+    let state = *block;
+    for c in 0..4 {
+        for r in 1..4 {
+            block[r + 4 * c] = state[r + 4 * ((c + r) % 4)];
+        }
+    }
+}
+
+fn inv_shift_rows(block: &mut Block) {
+    let state = *block;
+    for c in 0..4 {
+        for r in 1..4 {
+            block[r + 4 * ((c + r) % 4)] = state[r + 4 * c];
+        }
+    }
+}
+
+fn mix_columns(block: &mut Block) {
+    for column in block.chunks_exact_mut(4) {
+        let [a0, a1, a2, a3] = [column[0], column[1], column[2], column[3]];
+        let all = a0 ^ a1 ^ a2 ^ a3;
+        column[0] ^= all ^ xtime(a0 ^ a1);
+        column[1] ^= all ^ xtime(a1 ^ a2);
+        column[2] ^= all ^ xtime(a2 ^ a3);
+        column[3] ^= all ^ xtime(a3 ^ a0);
+    }
+}
+
+fn inv_mix_columns(block: &mut Block) {
+    for column in block.chunks_exact_mut(4) {
+        let [a0, a1, a2, a3] = [column[0], column[1], column[2], column[3]];
+        column[0] = gf_mul(a0, 14) ^ gf_mul(a1, 11) ^ gf_mul(a2, 13) ^ gf_mul(a3, 9);
+        column[1] = gf_mul(a0, 9) ^ gf_mul(a1, 14) ^ gf_mul(a2, 11) ^ gf_mul(a3, 13);
+        column[2] = gf_mul(a0, 13) ^ gf_mul(a1, 9) ^ gf_mul(a2, 14) ^ gf_mul(a3, 11);
+        column[3] = gf_mul(a0, 11) ^ gf_mul(a1, 13) ^ gf_mul(a2, 9) ^ gf_mul(a3, 14);
+    }
+}
+
+/// Increments the last `width` bytes of `counter` as a big-endian number.
+fn increment(counter: &mut Block, width: usize) {
+    for b in counter[AES128_BLOCK_SIZE - width..].iter_mut().rev() {
+        *b = b.wrapping_add(1);
+        if *b != 0 {
+            break;
+        }
+    }
+}
+
+/// Compares two tags without stopping at the first difference.
+fn tags_match(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b.iter()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// An expanded AES-128 or AES-256 key.
+struct KeySchedule {
+    round_keys: [Block; 15],
+    rounds: usize,
+}
+
+impl KeySchedule {
+    /// Expands a key of `AES128_KEY_SIZE` or `AES256_KEY_SIZE` bytes.
+    fn new(key: &[u8]) -> KeySchedule {
+        let nk = key.len() / 4;
+        let rounds = nk + 6;
+
+        let mut words = [[0u8; 4]; 60];
+        for (word, chunk) in words.iter_mut().zip(key.chunks_exact(4)) {
+            word.copy_from_slice(chunk);
+        }
+        let mut rcon = 1;
+        for i in nk..4 * (rounds + 1) {
+            let mut temp = words[i - 1];
+            if i % nk == 0 {
+                temp = [
+                    SBOX[temp[1] as usize] ^ rcon,
+                    SBOX[temp[2] as usize],
+                    SBOX[temp[3] as usize],
+                    SBOX[temp[0] as usize],
+                ];
+                rcon = xtime(rcon);
+            } else if nk > 6 && i % nk == 4 {
+                temp = temp.map(|b| SBOX[b as usize]);
+            }
+            for j in 0..4 {
+                words[i][j] = words[i - nk][j] ^ temp[j];
+            }
+        }
+
+        let mut round_keys = [[0; AES128_BLOCK_SIZE]; 15];
+        for (round_key, round_words) in round_keys.iter_mut().zip(words.chunks_exact(4)) {
+            for (bytes, word) in round_key.chunks_exact_mut(4).zip(round_words.iter()) {
+                bytes.copy_from_slice(word);
+            }
+        }
+        KeySchedule { round_keys, rounds }
+    }
+
+    fn encrypt(&self, block: &mut Block) {
+        xor_block(block, &self.round_keys[0]);
+        for round_key in self.round_keys[1..self.rounds].iter() {
+            sub_bytes(block, &SBOX);
+            shift_rows(block);
+            mix_columns(block);
+            xor_block(block, round_key);
+        }
+        sub_bytes(block, &SBOX);
+        shift_rows(block);
+        xor_block(block, &self.round_keys[self.rounds]);
+    }
+
+    fn decrypt(&self, block: &mut Block) {
+        xor_block(block, &self.round_keys[self.rounds]);
+        for round_key in self.round_keys[1..self.rounds].iter().rev() {
+            inv_shift_rows(block);
+            sub_bytes(block, &INV_SBOX);
+            xor_block(block, round_key);
+            inv_mix_columns(block);
+        }
+        inv_shift_rows(block);
+        sub_bytes(block, &INV_SBOX);
+        xor_block(block, &self.round_keys[0]);
+    }
+
+    /// XORs `data` with the key stream starting at `counter`, of which the
+    /// last `width` bytes are incremented for each block.
+    fn ctr(&self, counter: &mut Block, width: usize, data: &mut [u8]) {
+        for chunk in data.chunks_mut(AES128_BLOCK_SIZE) {
+            let mut key_stream = *counter;
+            self.encrypt(&mut key_stream);
+            xor_block(chunk, &key_stream);
+            increment(counter, width);
+        }
+    }
+}
+
+/// CBC-MAC, zero padding the input whenever `pad` is called.
+struct CbcMac<'a> {
+    schedule: &'a KeySchedule,
+    state: Block,
+    used: usize,
+}
+
+impl<'a> CbcMac<'a> {
+    fn new(schedule: &'a KeySchedule) -> CbcMac<'a> {
+        CbcMac {
+            schedule,
+            state: [0; AES128_BLOCK_SIZE],
+            used: 0,
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for b in data {
+            self.state[self.used] ^= b;
+            self.used += 1;
+            if self.used == AES128_BLOCK_SIZE {
+                self.schedule.encrypt(&mut self.state);
+                self.used = 0;
+            }
+        }
+    }
+
+    fn pad(&mut self) {
+        if self.used > 0 {
+            self.schedule.encrypt(&mut self.state);
+            self.used = 0;
+        }
+    }
+}
+
+/// GHASH from NIST SP 800-38D, zero padding the input whenever `pad` is
+/// called.
+struct GHash {
+    h: u128,
+    y: u128,
+    block: Block,
+    used: usize,
+}
+
+impl GHash {
+    fn new(h: Block) -> GHash {
+        GHash {
+            h: u128::from_be_bytes(h),
+            y: 0,
+            block: [0; AES128_BLOCK_SIZE],
+            used: 0,
+        }
+    }
+
+    /// Multiplication in GF(2^128) with the bit order of GCM, where the
+    /// first bit of the block is the lowest coefficient.
+    fn multiply(x: u128, y: u128) -> u128 {
+        const R: u128 = 0xe1 << 120;
+        let mut z = 0;
+        let mut v = y;
+        for i in (0..128).rev() {
+            if (x >> i) & 1 == 1 {
+                z ^= v;
+            }
+            v = if v & 1 == 1 { (v >> 1) ^ R } else { v >> 1 };
+        }
+        z
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for b in data {
+            self.block[self.used] = *b;
+            self.used += 1;
+            if self.used == AES128_BLOCK_SIZE {
+                self.pad();
+            }
+        }
+    }
+
+    fn pad(&mut self) {
+        if self.used > 0 {
+            self.block[self.used..].fill(0);
+            self.y = Self::multiply(self.y ^ u128::from_be_bytes(self.block), self.h);
+            self.used = 0;
+        }
+    }
+
+    fn finish(mut self) -> Block {
+        self.pad();
+        self.y.to_be_bytes()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Ctr,
+    Cbc,
+    Ecb,
+}
+
+/// An operation waiting for the deferred call.
+#[derive(Clone, Copy)]
+enum Operation {
+    Blocks {
+        start: usize,
+        stop: usize,
+    },
+    Ccm {
+        a_off: usize,
+        m_off: usize,
+        m_len: usize,
+        mic_len: usize,
+        confidential: bool,
+        encrypting: bool,
+    },
+    Gcm {
+        aad_offset: usize,
+        message_offset: usize,
+        message_len: usize,
+        encrypting: bool,
+    },
+}
+
+pub struct SoftwareAes<'a> {
+    key: Cell<[u8; AES256_KEY_SIZE]>,
+    /// The length of the current key, or 0 if no key has been set.
+    key_len: Cell<usize>,
+    mode: Cell<Option<(Mode, bool)>>,
+    iv: Cell<Block>,
+    /// The chaining value (CBC) or next counter (CTR) of the current message.
+    state: Cell<Block>,
+    nonce: Cell<[u8; CCM_NONCE_LENGTH]>,
+    gcm_iv: Cell<[u8; GCM_IV_LENGTH]>,
+
+    operation: OptionalCell<Operation>,
+    source: TakeCell<'static, [u8]>,
+    dest: TakeCell<'static, [u8]>,
+
+    client: OptionalCell<&'a dyn Client<'a>>,
+    ccm_client: OptionalCell<&'a dyn CCMClient>,
+    gcm_client: OptionalCell<&'a dyn GCMClient>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a> SoftwareAes<'a> {
+    pub fn new() -> SoftwareAes<'a> {
+        SoftwareAes {
+            key: Cell::new([0; AES256_KEY_SIZE]),
+            key_len: Cell::new(0),
+            mode: Cell::new(None),
+            iv: Cell::new([0; AES128_BLOCK_SIZE]),
+            state: Cell::new([0; AES128_BLOCK_SIZE]),
+            nonce: Cell::new([0; CCM_NONCE_LENGTH]),
+            gcm_iv: Cell::new([0; GCM_IV_LENGTH]),
+
+            operation: OptionalCell::empty(),
+            source: TakeCell::empty(),
+            dest: TakeCell::empty(),
+
+            client: OptionalCell::empty(),
+            ccm_client: OptionalCell::empty(),
+            gcm_client: OptionalCell::empty(),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    fn load_key(&self, key: &[u8], len: usize) -> Result<(), ErrorCode> {
+        if key.len() != len {
+            return Err(ErrorCode::INVAL);
+        }
+        let mut new_key = [0; AES256_KEY_SIZE];
+        new_key[..len].copy_from_slice(key);
+        self.key.set(new_key);
+        self.key_len.set(len);
+        Ok(())
+    }
+
+    fn key_schedule(&self) -> KeySchedule {
+        KeySchedule::new(&self.key.get()[..self.key_len.get()])
+    }
+
+    fn load_iv(&self, iv: &[u8]) -> Result<(), ErrorCode> {
+        let iv: Block = iv.try_into().map_err(|_| ErrorCode::INVAL)?;
+        self.iv.set(iv);
+        self.state.set(iv);
+        Ok(())
+    }
+
+    fn restart_message(&self) {
+        if self.operation.is_none() {
+            self.state.set(self.iv.get());
+        }
+    }
+
+    fn load_nonce(&self, nonce: &[u8]) -> Result<(), ErrorCode> {
+        self.nonce
+            .set(nonce.try_into().map_err(|_| ErrorCode::INVAL)?);
+        Ok(())
+    }
+
+    fn load_gcm_iv(&self, iv: &[u8]) -> Result<(), ErrorCode> {
+        if iv.len() > GCM_IV_LENGTH {
+            return Err(ErrorCode::INVAL);
+        }
+        let mut new_iv = [0; GCM_IV_LENGTH];
+        new_iv[..iv.len()].copy_from_slice(iv);
+        self.gcm_iv.set(new_iv);
+        Ok(())
+    }
+
+    fn start_blocks(
+        &self,
+        source: Option<&'static mut [u8]>,
+        dest: &'static mut [u8],
+        start: usize,
+        stop: usize,
+    ) -> Option<(
+        Result<(), ErrorCode>,
+        Option<&'static mut [u8]>,
+        &'static mut [u8],
+    )> {
+        if self.operation.is_some() {
+            return Some((Err(ErrorCode::BUSY), source, dest));
+        }
+        if self.key_len.get() == 0
+            || self.mode.get().is_none()
+            || stop < start
+            || stop > dest.len()
+            || (stop - start) % AES128_BLOCK_SIZE != 0
+            || source.as_ref().is_some_and(|s| s.len() != stop - start)
+        {
+            return Some((Err(ErrorCode::INVAL), source, dest));
+        }
+
+        if let Some(source) = source {
+            self.source.replace(source);
+        }
+        self.dest.replace(dest);
+        self.operation.set(Operation::Blocks { start, stop });
+        self.deferred_call.set();
+        None
+    }
+
+    fn start_ccm(
+        &self,
+        buf: &'static mut [u8],
+        a_off: usize,
+        m_off: usize,
+        m_len: usize,
+        mic_len: usize,
+        confidential: bool,
+        encrypting: bool,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.operation.is_some() {
+            return Err((ErrorCode::BUSY, buf));
+        }
+        if self.key_len.get() == 0
+            || !(a_off <= m_off && m_off + m_len + mic_len <= buf.len())
+            || !(mic_len == 0 || (4..=16).contains(&mic_len) && mic_len % 2 == 0)
+        {
+            return Err((ErrorCode::INVAL, buf));
+        }
+        // With a two byte length field the message must be shorter than 64
+        // kB, and longer additional data would need a different encoding.
+        let a_end = if confidential { m_off } else { m_off + m_len };
+        if m_len > u16::MAX as usize || a_end - a_off >= 0xff00 {
+            return Err((ErrorCode::INVAL, buf));
+        }
+
+        self.dest.replace(buf);
+        self.operation.set(Operation::Ccm {
+            a_off,
+            m_off,
+            m_len,
+            mic_len,
+            confidential,
+            encrypting,
+        });
+        self.deferred_call.set();
+        Ok(())
+    }
+
+    fn start_gcm(
+        &self,
+        buf: &'static mut [u8],
+        aad_offset: usize,
+        message_offset: usize,
+        message_len: usize,
+        encrypting: bool,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.operation.is_some() {
+            return Err((ErrorCode::BUSY, buf));
+        }
+        if self.key_len.get() == 0 {
+            return Err((ErrorCode::INVAL, buf));
+        }
+        if !(aad_offset <= message_offset
+            && message_offset + message_len + GCM_TAG_LENGTH <= buf.len())
+        {
+            return Err((ErrorCode::SIZE, buf));
+        }
+
+        self.dest.replace(buf);
+        self.operation.set(Operation::Gcm {
+            aad_offset,
+            message_offset,
+            message_len,
+            encrypting,
+        });
+        self.deferred_call.set();
+        Ok(())
+    }
+
+    /// Runs the block cipher mode over `data`, reading the input from
+    /// `source` if there is one.
+    fn crypt_blocks(&self, schedule: &KeySchedule, source: Option<&[u8]>, data: &mut [u8]) {
+        let Some((mode, encrypting)) = self.mode.get() else {
+            return;
+        };
+        let mut state = self.state.get();
+        for (i, chunk) in data.chunks_exact_mut(AES128_BLOCK_SIZE).enumerate() {
+            let mut block = [0; AES128_BLOCK_SIZE];
+            match source {
+                Some(source) => block
+                    .copy_from_slice(&source[i * AES128_BLOCK_SIZE..(i + 1) * AES128_BLOCK_SIZE]),
+                None => block.copy_from_slice(chunk),
+            }
+
+            match mode {
+                Mode::Ecb if encrypting => schedule.encrypt(&mut block),
+                Mode::Ecb => schedule.decrypt(&mut block),
+                Mode::Cbc if encrypting => {
+                    xor_block(&mut block, &state);
+                    schedule.encrypt(&mut block);
+                    state = block;
+                }
+                Mode::Cbc => {
+                    let ciphertext = block;
+                    schedule.decrypt(&mut block);
+                    xor_block(&mut block, &state);
+                    state = ciphertext;
+                }
+                Mode::Ctr => schedule.ctr(&mut state, AES128_BLOCK_SIZE, &mut block),
+            }
+
+            chunk.copy_from_slice(&block);
+        }
+        self.state.set(state);
+    }
+
+    /// CCM* as specified in IEEE 802.15.4-2015 Appendix B.4.1, with a two
+    /// byte length field. Returns whether the tag is valid.
+    fn crypt_ccm(
+        &self,
+        schedule: &KeySchedule,
+        buf: &mut [u8],
+        a_off: usize,
+        m_off: usize,
+        m_len: usize,
+        mic_len: usize,
+        confidential: bool,
+        encrypting: bool,
+    ) -> bool {
+        // Without confidentiality the message is authenticated as part of
+        // the additional data and is not encrypted.
+        let m_end = m_off + m_len;
+        let m_start = if confidential { m_off } else { m_end };
+        let a_len = m_start - a_off;
+        let nonce = self.nonce.get();
+
+        // Counter blocks are flags | nonce | counter, with flags = L - 1.
+        let mut counter = [0; AES128_BLOCK_SIZE];
+        counter[0] = 1;
+        counter[1..1 + CCM_NONCE_LENGTH].copy_from_slice(&nonce);
+        let mut tag_key_stream = counter;
+        schedule.encrypt(&mut tag_key_stream);
+        increment(&mut counter, 2);
+
+        if !encrypting {
+            schedule.ctr(&mut counter, 2, &mut buf[m_start..m_end]);
+        }
+
+        // B_0 = flags | nonce | m length
+        // flags = reserved | Adata | (M - 2) / 2 | (L - 1)
+        let mut b0 = [0; AES128_BLOCK_SIZE];
+        if a_len != 0 {
+            b0[0] |= 1 << 6;
+        }
+        if mic_len != 0 {
+            b0[0] |= (((mic_len - 2) / 2) as u8) << 3;
+        }
+        b0[0] |= 1;
+        b0[1..1 + CCM_NONCE_LENGTH].copy_from_slice(&nonce);
+        b0[14..].copy_from_slice(&((m_end - m_start) as u16).to_be_bytes());
+
+        let mut mac = CbcMac::new(schedule);
+        mac.update(&b0);
+        if a_len != 0 {
+            mac.update(&(a_len as u16).to_be_bytes());
+            mac.update(&buf[a_off..m_start]);
+            mac.pad();
+        }
+        mac.update(&buf[m_start..m_end]);
+        mac.pad();
+
+        let mut tag = mac.state;
+        xor_block(&mut tag, &tag_key_stream);
+
+        if encrypting {
+            schedule.ctr(&mut counter, 2, &mut buf[m_start..m_end]);
+            buf[m_end..m_end + mic_len].copy_from_slice(&tag[..mic_len]);
+            true
+        } else {
+            tags_match(&buf[m_end..m_end + mic_len], &tag[..mic_len])
+        }
+    }
+
+    /// GCM as specified in NIST SP 800-38D. Returns whether the tag is
+    /// valid.
+    fn crypt_gcm(
+        &self,
+        schedule: &KeySchedule,
+        buf: &mut [u8],
+        aad_offset: usize,
+        message_offset: usize,
+        message_len: usize,
+        encrypting: bool,
+    ) -> bool {
+        let message_end = message_offset + message_len;
+
+        let mut hash_key = [0; AES128_BLOCK_SIZE];
+        schedule.encrypt(&mut hash_key);
+        let mut ghash = GHash::new(hash_key);
+
+        // J_0 = IV | 0^31 | 1
+        let mut counter = [0; AES128_BLOCK_SIZE];
+        counter[..GCM_IV_LENGTH].copy_from_slice(&self.gcm_iv.get());
+        counter[AES128_BLOCK_SIZE - 1] = 1;
+        let mut tag_key_stream = counter;
+        schedule.encrypt(&mut tag_key_stream);
+        increment(&mut counter, 4);
+
+        ghash.update(&buf[aad_offset..message_offset]);
+        ghash.pad();
+        if encrypting {
+            schedule.ctr(&mut counter, 4, &mut buf[message_offset..message_end]);
+            ghash.update(&buf[message_offset..message_end]);
+        } else {
+            ghash.update(&buf[message_offset..message_end]);
+            schedule.ctr(&mut counter, 4, &mut buf[message_offset..message_end]);
+        }
+        ghash.pad();
+        ghash.update(&((message_offset - aad_offset) as u64 * 8).to_be_bytes());
+        ghash.update(&(message_len as u64 * 8).to_be_bytes());
+
+        let mut tag = ghash.finish();
+        xor_block(&mut tag, &tag_key_stream);
+
+        let tag_range = message_end..message_end + GCM_TAG_LENGTH;
+        if encrypting {
+            buf[tag_range].copy_from_slice(&tag);
+            true
+        } else {
+            tags_match(&buf[tag_range], &tag)
+        }
+    }
+}
+
+impl DeferredCallClient for SoftwareAes<'_> {
+    fn handle_deferred_call(&self) {
+        let Some(operation) = self.operation.take() else {
+            return;
+        };
+        let Some(dest) = self.dest.take() else {
+            return;
+        };
+        let schedule = self.key_schedule();
+
+        match operation {
+            Operation::Blocks { start, stop } => {
+                let source = self.source.take();
+                self.crypt_blocks(&schedule, source.as_deref(), &mut dest[start..stop]);
+                self.client.map(move |client| {
+                    client.crypt_done(source, dest);
+                });
+            }
+            Operation::Ccm {
+                a_off,
+                m_off,
+                m_len,
+                mic_len,
+                confidential,
+                encrypting,
+            } => {
+                let tag_is_valid = self.crypt_ccm(
+                    &schedule,
+                    dest,
+                    a_off,
+                    m_off,
+                    m_len,
+                    mic_len,
+                    confidential,
+                    encrypting,
+                );
+                self.ccm_client.map(move |client| {
+                    client.crypt_done(dest, Ok(()), tag_is_valid);
+                });
+            }
+            Operation::Gcm {
+                aad_offset,
+                message_offset,
+                message_len,
+                encrypting,
+            } => {
+                let tag_is_valid = self.crypt_gcm(
+                    &schedule,
+                    dest,
+                    aad_offset,
+                    message_offset,
+                    message_len,
+                    encrypting,
+                );
+                self.gcm_client.map(move |client| {
+                    client.crypt_done(dest, Ok(()), tag_is_valid);
+                });
+            }
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+impl<'a> AES128<'a> for SoftwareAes<'a> {
+    fn enable(&self) {}
+
+    fn disable(&self) {}
+
+    fn set_client(&'a self, client: &'a dyn Client<'a>) {
+        self.client.set(client);
+    }
+
+    fn set_key(&self, key: &[u8]) -> Result<(), ErrorCode> {
+        self.load_key(key, AES128_KEY_SIZE)
+    }
+
+    fn set_iv(&self, iv: &[u8]) -> Result<(), ErrorCode> {
+        self.load_iv(iv)
+    }
+
+    fn start_message(&self) {
+        self.restart_message();
+    }
+
+    fn crypt(
+        &self,
+        source: Option<&'static mut [u8]>,
+        dest: &'static mut [u8],
+        start_index: usize,
+        stop_index: usize,
+    ) -> Option<(
+        Result<(), ErrorCode>,
+        Option<&'static mut [u8]>,
+        &'static mut [u8],
+    )> {
+        self.start_blocks(source, dest, start_index, stop_index)
+    }
+}
+
+impl<'a> AES256<'a> for SoftwareAes<'a> {
+    fn enable(&self) {}
+
+    fn disable(&self) {}
+
+    fn set_client(&'a self, client: &'a dyn Client<'a>) {
+        self.client.set(client);
+    }
+
+    fn set_key(&self, key: &[u8]) -> Result<(), ErrorCode> {
+        self.load_key(key, AES256_KEY_SIZE)
+    }
+
+    fn set_iv(&self, iv: &[u8]) -> Result<(), ErrorCode> {
+        self.load_iv(iv)
+    }
+
+    fn start_message(&self) {
+        self.restart_message();
+    }
+
+    fn crypt(
+        &self,
+        source: Option<&'static mut [u8]>,
+        dest: &'static mut [u8],
+        start_index: usize,
+        stop_index: usize,
+    ) -> Option<(
+        Result<(), ErrorCode>,
+        Option<&'static mut [u8]>,
+        &'static mut [u8],
+    )> {
+        self.start_blocks(source, dest, start_index, stop_index)
+    }
+}
+
+impl AES128Ctr for SoftwareAes<'_> {
+    fn set_mode_aes128ctr(&self, encrypting: bool) -> Result<(), ErrorCode> {
+        self.mode.set(Some((Mode::Ctr, encrypting)));
+        Ok(())
+    }
+}
+
+impl AES128CBC for SoftwareAes<'_> {
+    fn set_mode_aes128cbc(&self, encrypting: bool) -> Result<(), ErrorCode> {
+        self.mode.set(Some((Mode::Cbc, encrypting)));
+        Ok(())
+    }
+}
+
+impl AES128ECB for SoftwareAes<'_> {
+    fn set_mode_aes128ecb(&self, encrypting: bool) -> Result<(), ErrorCode> {
+        self.mode.set(Some((Mode::Ecb, encrypting)));
+        Ok(())
+    }
+}
+
+impl AES256Ctr for SoftwareAes<'_> {
+    fn set_mode_aes256ctr(&self, encrypting: bool) -> Result<(), ErrorCode> {
+        self.mode.set(Some((Mode::Ctr, encrypting)));
+        Ok(())
+    }
+}
+
+impl AES256CBC for SoftwareAes<'_> {
+    fn set_mode_aes256cbc(&self, encrypting: bool) -> Result<(), ErrorCode> {
+        self.mode.set(Some((Mode::Cbc, encrypting)));
+        Ok(())
+    }
+}
+
+impl AES256ECB for SoftwareAes<'_> {
+    fn set_mode_aes256ecb(&self, encrypting: bool) -> Result<(), ErrorCode> {
+        self.mode.set(Some((Mode::Ecb, encrypting)));
+        Ok(())
+    }
+}
+
+impl<'a> AES128CCM<'a> for SoftwareAes<'a> {
+    fn set_client(&'a self, client: &'a dyn CCMClient) {
+        self.ccm_client.set(client);
+    }
+
+    fn set_key(&self, key: &[u8]) -> Result<(), ErrorCode> {
+        self.load_key(key, AES128_KEY_SIZE)
+    }
+
+    fn set_nonce(&self, nonce: &[u8]) -> Result<(), ErrorCode> {
+        self.load_nonce(nonce)
+    }
+
+    fn crypt(
+        &self,
+        buf: &'static mut [u8],
+        a_off: usize,
+        m_off: usize,
+        m_len: usize,
+        mic_len: usize,
+        confidential: bool,
+        encrypting: bool,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        self.start_ccm(buf, a_off, m_off, m_len, mic_len, confidential, encrypting)
+    }
+}
+
+impl<'a> AES256CCM<'a> for SoftwareAes<'a> {
+    fn set_client(&'a self, client: &'a dyn CCMClient) {
+        self.ccm_client.set(client);
+    }
+
+    fn set_key(&self, key: &[u8]) -> Result<(), ErrorCode> {
+        self.load_key(key, AES256_KEY_SIZE)
+    }
+
+    fn set_nonce(&self, nonce: &[u8]) -> Result<(), ErrorCode> {
+        self.load_nonce(nonce)
+    }
+
+    fn crypt(
+        &self,
+        buf: &'static mut [u8],
+        a_off: usize,
+        m_off: usize,
+        m_len: usize,
+        mic_len: usize,
+        confidential: bool,
+        encrypting: bool,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        self.start_ccm(buf, a_off, m_off, m_len, mic_len, confidential, encrypting)
+    }
+}
+
+impl<'a> AES128GCM<'a> for SoftwareAes<'a> {
+    fn set_client(&'a self, client: &'a dyn GCMClient) {
+        self.gcm_client.set(client);
+    }
+
+    fn set_key(&self, key: &[u8]) -> Result<(), ErrorCode> {
+        self.load_key(key, AES128_KEY_SIZE)
+    }
+
+    fn set_iv(&self, nonce: &[u8]) -> Result<(), ErrorCode> {
+        self.load_gcm_iv(nonce)
+    }
+
+    fn crypt(
+        &self,
+        buf: &'static mut [u8],
+        aad_offset: usize,
+        message_offset: usize,
+        message_len: usize,
+        encrypting: bool,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        self.start_gcm(buf, aad_offset, message_offset, message_len, encrypting)
+    }
+}
+
+impl<'a> AES256GCM<'a> for SoftwareAes<'a> {
+    fn set_client(&'a self, client: &'a dyn GCMClient) {
+        self.gcm_client.set(client);
+    }
+
+    fn set_key(&self, key: &[u8]) -> Result<(), ErrorCode> {
+        self.load_key(key, AES256_KEY_SIZE)
+    }
+
+    fn set_iv(&self, nonce: &[u8]) -> Result<(), ErrorCode> {
+        self.load_gcm_iv(nonce)
+    }
+
+    fn crypt(
+        &self,
+        buf: &'static mut [u8],
+        aad_offset: usize,
+        message_offset: usize,
+        message_len: usize,
+        encrypting: bool,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        self.start_gcm(buf, aad_offset, message_offset, message_len, encrypting)
+    }
+}
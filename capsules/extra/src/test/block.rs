@@ -0,0 +1,135 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Test a block device by writing a pattern to a number of blocks, flushing
+//! the device and reading the blocks back.
+
+use core::cell::Cell;
+
+use capsules_core::test::capsule_test::{CapsuleTest, CapsuleTestClient, CapsuleTestError};
+use kernel::debug;
+use kernel::hil::block::{BlockClient, BlockDevice};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+pub struct TestBlockDevice<'a, B: BlockDevice<'a>> {
+    device: &'a B,
+    num_blocks: usize,
+    seed: u8,
+    buffer: TakeCell<'static, [u8]>,
+    mismatches: Cell<usize>,
+    client: OptionalCell<&'static dyn CapsuleTestClient>,
+}
+
+impl<'a, B: BlockDevice<'a>> TestBlockDevice<'a, B> {
+    /// Test the first `num_blocks` blocks of `device`. `buffer` must hold one
+    /// block. `seed` varies the pattern between runs.
+    pub fn new(device: &'a B, num_blocks: usize, seed: u8, buffer: &'static mut [u8]) -> Self {
+        TestBlockDevice {
+            device,
+            num_blocks,
+            seed,
+            buffer: TakeCell::new(buffer),
+            mismatches: Cell::new(0),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn run(&self) {
+        self.mismatches.set(0);
+        self.write(0);
+    }
+
+    fn pattern(&self, block: usize, index: usize) -> u8 {
+        (index as u8) ^ (block as u8).wrapping_mul(31) ^ self.seed
+    }
+
+    fn write(&self, block: usize) {
+        let Some(buffer) = self.buffer.take() else {
+            return;
+        };
+        for (i, byte) in buffer.iter_mut().enumerate() {
+            *byte = self.pattern(block, i);
+        }
+        if let Err((e, buffer)) = self.device.write(block, buffer) {
+            self.buffer.replace(buffer);
+            self.done(Err(CapsuleTestError::ErrorCode(e)));
+        }
+    }
+
+    fn flush(&self) {
+        match self.device.flush() {
+            Ok(()) => {}
+            Err(ErrorCode::ALREADY) => self.read(0),
+            Err(e) => self.done(Err(CapsuleTestError::ErrorCode(e))),
+        }
+    }
+
+    fn read(&self, block: usize) {
+        let Some(buffer) = self.buffer.take() else {
+            return;
+        };
+        buffer.fill(0);
+        if let Err((e, buffer)) = self.device.read(block, buffer) {
+            self.buffer.replace(buffer);
+            self.done(Err(CapsuleTestError::ErrorCode(e)));
+        }
+    }
+
+    fn done(&self, result: Result<(), CapsuleTestError>) {
+        debug!("BlockDeviceTest: passed: {}", result.is_ok());
+        self.client.map(|client| {
+            client.done(result);
+        });
+    }
+}
+
+impl<'a, B: BlockDevice<'a>> BlockClient for TestBlockDevice<'a, B> {
+    fn read_done(&self, block: usize, buffer: &'static mut [u8], result: Result<(), ErrorCode>) {
+        let matches = buffer
+            .iter()
+            .enumerate()
+            .all(|(i, &byte)| byte == self.pattern(block, i));
+        self.buffer.replace(buffer);
+        if let Err(e) = result {
+            self.done(Err(CapsuleTestError::ErrorCode(e)));
+            return;
+        }
+        if !matches {
+            debug!("BlockDeviceTest: block {} does not match", block);
+            self.mismatches.set(self.mismatches.get() + 1);
+        }
+        if block + 1 < self.num_blocks {
+            self.read(block + 1);
+        } else if self.mismatches.get() == 0 {
+            self.done(Ok(()));
+        } else {
+            self.done(Err(CapsuleTestError::IncorrectResult));
+        }
+    }
+
+    fn write_done(&self, block: usize, buffer: &'static mut [u8], result: Result<(), ErrorCode>) {
+        self.buffer.replace(buffer);
+        match result {
+            Ok(()) if block + 1 < self.num_blocks => self.write(block + 1),
+            Ok(()) => self.flush(),
+            Err(e) => self.done(Err(CapsuleTestError::ErrorCode(e))),
+        }
+    }
+
+    fn erase_done(&self, _block: usize, _result: Result<(), ErrorCode>) {}
+
+    fn flush_done(&self, result: Result<(), ErrorCode>) {
+        match result {
+            Ok(()) => self.read(0),
+            Err(e) => self.done(Err(CapsuleTestError::ErrorCode(e))),
+        }
+    }
+}
+
+impl<'a, B: BlockDevice<'a>> CapsuleTest for TestBlockDevice<'a, B> {
+    fn set_client(&self, client: &'static dyn CapsuleTestClient) {
+        self.client.set(client);
+    }
+}
@@ -0,0 +1,193 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Test a ChaCha20-Poly1305 implementation by encrypting a message, checking
+//! the ciphertext and tag against the expected values, and then decrypting
+//! it again.
+//!
+//! Finally, the ciphertext is decrypted with a corrupted tag and with a
+//! corrupted ciphertext, both of which must be rejected.
+
+use core::cell::Cell;
+
+use capsules_core::test::capsule_test::{CapsuleTest, CapsuleTestClient, CapsuleTestError};
+use kernel::debug;
+use kernel::hil::symmetric_encryption::{
+    ChaCha20Poly1305, ChaCha20Poly1305Client, CHACHA20_POLY1305_TAG_SIZE,
+};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Step {
+    Encrypt,
+    Decrypt,
+    /// Decrypt the ciphertext with a corrupted tag.
+    DecryptCorruptTag,
+    /// Decrypt a corrupted ciphertext with its original tag.
+    DecryptCorruptCiphertext,
+}
+
+pub struct TestChaCha20Poly1305<'a, C: ChaCha20Poly1305<'a>> {
+    chacha: &'a C,
+    key: &'static [u8],
+    nonce: &'static [u8],
+    aad: &'static [u8],
+    plaintext: &'static [u8],
+    // The ciphertext followed by the tag
+    ciphertext: &'static [u8],
+
+    buf: TakeCell<'static, [u8]>,
+    step: Cell<Step>,
+    client: OptionalCell<&'static dyn CapsuleTestClient>,
+}
+
+impl<'a, C: ChaCha20Poly1305<'a>> TestChaCha20Poly1305<'a, C> {
+    /// `buf` must hold the additional data, the message and the tag.
+    pub fn new(
+        chacha: &'a C,
+        key: &'static [u8],
+        nonce: &'static [u8],
+        aad: &'static [u8],
+        plaintext: &'static [u8],
+        ciphertext: &'static [u8],
+        buf: &'static mut [u8],
+    ) -> Self {
+        TestChaCha20Poly1305 {
+            chacha,
+            key,
+            nonce,
+            aad,
+            plaintext,
+            ciphertext,
+            buf: TakeCell::new(buf),
+            step: Cell::new(Step::Encrypt),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn run(&self) {
+        self.step.set(Step::Encrypt);
+        if let Err(e) = self
+            .chacha
+            .set_key(self.key)
+            .and(self.chacha.set_nonce(self.nonce))
+        {
+            self.done(Err(CapsuleTestError::ErrorCode(e)));
+            return;
+        }
+
+        self.buf.take().map(|buf| {
+            let message_offset = self.aad.len();
+            buf[..message_offset].copy_from_slice(self.aad);
+            buf[message_offset..message_offset + self.plaintext.len()]
+                .copy_from_slice(self.plaintext);
+            self.crypt(buf);
+        });
+    }
+
+    fn crypt(&self, buf: &'static mut [u8]) {
+        if let Err((e, buf)) = self.chacha.crypt(
+            buf,
+            0,
+            self.aad.len(),
+            self.plaintext.len(),
+            self.step.get() == Step::Encrypt,
+        ) {
+            self.buf.replace(buf);
+            self.done(Err(CapsuleTestError::ErrorCode(e)));
+        }
+    }
+
+    fn done(&self, result: Result<(), CapsuleTestError>) {
+        self.client.map(|client| {
+            client.done(result);
+        });
+    }
+}
+
+impl<'a, C: ChaCha20Poly1305<'a>> ChaCha20Poly1305Client for TestChaCha20Poly1305<'a, C> {
+    fn crypt_done(&self, buf: &'static mut [u8], res: Result<(), ErrorCode>, tag_is_valid: bool) {
+        if let Err(e) = res {
+            self.buf.replace(buf);
+            self.done(Err(CapsuleTestError::ErrorCode(e)));
+            return;
+        }
+
+        let message_offset = self.aad.len();
+        let message_end = message_offset + self.plaintext.len();
+        match self.step.get() {
+            Step::Encrypt => {
+                let matches = buf[message_offset..message_end + CHACHA20_POLY1305_TAG_SIZE]
+                    == *self.ciphertext;
+                debug!(
+                    "ChaCha20Poly1305Test: encrypted, ciphertext and tag match: {}",
+                    matches
+                );
+                if !matches || !tag_is_valid {
+                    self.buf.replace(buf);
+                    self.done(Err(CapsuleTestError::IncorrectResult));
+                    return;
+                }
+
+                // Decrypt the message again, which also checks the tag.
+                self.step.set(Step::Decrypt);
+                self.crypt(buf);
+            }
+            Step::Decrypt => {
+                let matches = buf[message_offset..message_end] == *self.plaintext;
+                debug!(
+                    "ChaCha20Poly1305Test: decrypted, plaintext matches: {}, tag is valid: {}",
+                    matches, tag_is_valid
+                );
+                if !matches || !tag_is_valid {
+                    self.buf.replace(buf);
+                    self.done(Err(CapsuleTestError::IncorrectResult));
+                    return;
+                }
+
+                buf[message_offset..message_end + CHACHA20_POLY1305_TAG_SIZE]
+                    .copy_from_slice(self.ciphertext);
+                buf[message_end] ^= 1;
+                self.step.set(Step::DecryptCorruptTag);
+                self.crypt(buf);
+            }
+            Step::DecryptCorruptTag => {
+                debug!(
+                    "ChaCha20Poly1305Test: decrypted with a corrupted tag, tag is valid: {}",
+                    tag_is_valid
+                );
+                if tag_is_valid {
+                    self.buf.replace(buf);
+                    self.done(Err(CapsuleTestError::IncorrectResult));
+                    return;
+                }
+
+                buf[message_offset..message_end + CHACHA20_POLY1305_TAG_SIZE]
+                    .copy_from_slice(self.ciphertext);
+                buf[message_offset] ^= 1;
+                self.step.set(Step::DecryptCorruptCiphertext);
+                self.crypt(buf);
+            }
+            Step::DecryptCorruptCiphertext => {
+                debug!(
+                    "ChaCha20Poly1305Test: decrypted a corrupted ciphertext, tag is valid: {}",
+                    tag_is_valid
+                );
+                self.buf.replace(buf);
+                self.done(if tag_is_valid {
+                    Err(CapsuleTestError::IncorrectResult)
+                } else {
+                    Ok(())
+                });
+            }
+        }
+    }
+}
+
+impl<'a, C: ChaCha20Poly1305<'a>> CapsuleTest for TestChaCha20Poly1305<'a, C> {
+    fn set_client(&self, client: &'static dyn CapsuleTestClient) {
+        self.client.set(client);
+    }
+}
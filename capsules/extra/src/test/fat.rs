@@ -0,0 +1,497 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Test the FAT filesystem on freshly formatted FAT12, FAT16 and FAT32
+//! volumes.
+//!
+//! The volumes are kept on a `SparseRamBlock`, which only stores the sectors
+//! that have been written, so volumes large enough for FAT16 and FAT32 fit in
+//! RAM. On each volume, the test creates a file with a long filename in a new
+//! subdirectory, writes it, mounts the volume again, opens and reads the file
+//! and lists both directories.
+
+use core::cell::Cell;
+
+use capsules_core::test::capsule_test::{CapsuleTest, CapsuleTestClient, CapsuleTestError};
+use kernel::debug;
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::block::{BlockClient, BlockDevice};
+use kernel::hil::filesystem::{DirEntry, FileId, FileInfo, FileSystem, FileSystemClient};
+use kernel::storage_permissions::StoragePermissions;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::ErrorCode;
+
+use crate::fat::SECTOR_SIZE;
+
+/// An operation of the block device that completes in a deferred call.
+#[derive(Clone, Copy)]
+enum Pending {
+    Idle,
+    Read(usize),
+    Write(usize),
+    Flush,
+}
+
+/// A RAM block device with 512 byte blocks that only stores the blocks that
+/// have been written, in `N` slots. Other blocks read as zeros.
+pub struct SparseRamBlock<'a, const N: usize> {
+    storage: TakeCell<'static, [[u8; SECTOR_SIZE]; N]>,
+    blocks: [Cell<Option<usize>>; N],
+    num_blocks: Cell<usize>,
+    pending: Cell<Pending>,
+    buffer: TakeCell<'static, [u8]>,
+    client: OptionalCell<&'a dyn BlockClient>,
+    deferred_call: DeferredCall,
+}
+
+impl<const N: usize> SparseRamBlock<'_, N> {
+    pub fn new(storage: &'static mut [[u8; SECTOR_SIZE]; N]) -> Self {
+        SparseRamBlock {
+            storage: TakeCell::new(storage),
+            blocks: core::array::from_fn(|_| Cell::new(None)),
+            num_blocks: Cell::new(0),
+            pending: Cell::new(Pending::Idle),
+            buffer: TakeCell::empty(),
+            client: OptionalCell::empty(),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    /// Replace the contents of the device by an empty FAT volume of `format`.
+    pub fn format(&self, format: &FatFormat) {
+        for block in self.blocks.iter() {
+            block.set(None);
+        }
+        self.num_blocks.set(format.total as usize);
+
+        let mut boot = [0; SECTOR_SIZE];
+        boot[..3].copy_from_slice(&[0xeb, 0x3c, 0x90]);
+        boot[3..11].copy_from_slice(b"TOCKTEST");
+        boot[11..13].copy_from_slice(&(SECTOR_SIZE as u16).to_le_bytes());
+        boot[13] = 1;
+        boot[14..16].copy_from_slice(&format.reserved.to_le_bytes());
+        boot[16] = format.fats;
+        boot[17..19].copy_from_slice(&format.root_entries.to_le_bytes());
+        boot[21] = 0xf8;
+        boot[32..36].copy_from_slice(&format.total.to_le_bytes());
+        if format.bits == 32 {
+            boot[36..40].copy_from_slice(&format.fat_sectors.to_le_bytes());
+            // The root directory is cluster 2, FSInfo is sector 1.
+            boot[44] = 2;
+            boot[48] = 1;
+        } else {
+            boot[22..24].copy_from_slice(&(format.fat_sectors as u16).to_le_bytes());
+        }
+        boot[510] = 0x55;
+        boot[511] = 0xaa;
+        self.store(0, &boot);
+
+        if format.bits == 32 {
+            let mut fsinfo = [0; SECTOR_SIZE];
+            fsinfo[..4].copy_from_slice(&0x4161_5252u32.to_le_bytes());
+            fsinfo[484..488].copy_from_slice(&0x6141_7272u32.to_le_bytes());
+            fsinfo[488..496].fill(0xff);
+            fsinfo[510] = 0x55;
+            fsinfo[511] = 0xaa;
+            self.store(1, &fsinfo);
+        }
+
+        // The media byte and end of chain markers of the reserved clusters 0
+        // and 1, and of the root directory of FAT32 in cluster 2.
+        let mut fat = [0; SECTOR_SIZE];
+        let start: &[u8] = match format.bits {
+            12 => &[0xf8, 0xff, 0xff],
+            16 => &[0xf8, 0xff, 0xff, 0xff],
+            _ => &[
+                0xf8, 0xff, 0xff, 0x0f, 0xff, 0xff, 0xff, 0x0f, 0xff, 0xff, 0xff, 0x0f,
+            ],
+        };
+        fat[..start.len()].copy_from_slice(start);
+        for copy in 0..u32::from(format.fats) {
+            self.store(
+                (u32::from(format.reserved) + copy * format.fat_sectors) as usize,
+                &fat,
+            );
+        }
+    }
+
+    /// Store `data` in `block`, returning false if all slots are used.
+    fn store(&self, block: usize, data: &[u8]) -> bool {
+        let slot = match self.blocks.iter().position(|b| b.get() == Some(block)) {
+            Some(slot) => slot,
+            None => match self.blocks.iter().position(|b| b.get().is_none()) {
+                Some(slot) => slot,
+                None => return false,
+            },
+        };
+        self.blocks[slot].set(Some(block));
+        self.storage
+            .map(|storage| storage[slot].copy_from_slice(&data[..SECTOR_SIZE]))
+            .is_some()
+    }
+
+    fn start(
+        &self,
+        pending: Pending,
+        block: usize,
+        buffer: &'static mut [u8],
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if !matches!(self.pending.get(), Pending::Idle) {
+            return Err((ErrorCode::BUSY, buffer));
+        }
+        if block >= self.num_blocks.get() || buffer.len() < SECTOR_SIZE {
+            return Err((ErrorCode::INVAL, buffer));
+        }
+        self.buffer.replace(buffer);
+        self.pending.set(pending);
+        self.deferred_call.set();
+        Ok(())
+    }
+}
+
+impl<'a, const N: usize> BlockDevice<'a> for SparseRamBlock<'a, N> {
+    fn set_client(&self, client: &'a dyn BlockClient) {
+        self.client.set(client);
+    }
+
+    fn block_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn num_blocks(&self) -> usize {
+        self.num_blocks.get()
+    }
+
+    fn read(
+        &self,
+        block: usize,
+        buffer: &'static mut [u8],
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        self.start(Pending::Read(block), block, buffer)
+    }
+
+    fn write(
+        &self,
+        block: usize,
+        buffer: &'static mut [u8],
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        self.start(Pending::Write(block), block, buffer)
+    }
+
+    fn erase(&self, _block: usize) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+
+    fn flush(&self) -> Result<(), ErrorCode> {
+        if !matches!(self.pending.get(), Pending::Idle) {
+            return Err(ErrorCode::BUSY);
+        }
+        self.pending.set(Pending::Flush);
+        self.deferred_call.set();
+        Ok(())
+    }
+}
+
+impl<const N: usize> DeferredCallClient for SparseRamBlock<'_, N> {
+    fn handle_deferred_call(&self) {
+        let pending = self.pending.replace(Pending::Idle);
+        match pending {
+            Pending::Idle => {}
+            Pending::Read(block) => {
+                if let Some(buffer) = self.buffer.take() {
+                    let slot = self.blocks.iter().position(|b| b.get() == Some(block));
+                    match slot {
+                        Some(slot) => {
+                            self.storage.map(|storage| {
+                                buffer[..SECTOR_SIZE].copy_from_slice(&storage[slot])
+                            });
+                        }
+                        None => buffer[..SECTOR_SIZE].fill(0),
+                    }
+                    self.client
+                        .map(move |client| client.read_done(block, buffer, Ok(())));
+                }
+            }
+            Pending::Write(block) => {
+                if let Some(buffer) = self.buffer.take() {
+                    let result = if self.store(block, buffer) {
+                        Ok(())
+                    } else {
+                        Err(ErrorCode::NOMEM)
+                    };
+                    self.client
+                        .map(move |client| client.write_done(block, buffer, result));
+                }
+            }
+            Pending::Flush => {
+                self.client.map(|client| client.flush_done(Ok(())));
+            }
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+/// The layout of a FAT volume with one sector per cluster.
+pub struct FatFormat {
+    pub name: &'static str,
+    /// Bits of a FAT entry, which must match the number of clusters.
+    pub bits: u8,
+    pub total: u32,
+    pub reserved: u16,
+    pub fats: u8,
+    pub fat_sectors: u32,
+    /// Entries of the fixed root directory, 0 for FAT32.
+    pub root_entries: u16,
+}
+
+/// Formats whose cluster counts select FAT12, FAT16 and FAT32.
+pub const FAT_FORMATS: [FatFormat; 3] = [
+    // 2031 clusters.
+    FatFormat {
+        name: "FAT12",
+        bits: 12,
+        total: 2048,
+        reserved: 1,
+        fats: 2,
+        fat_sectors: 6,
+        root_entries: 64,
+    },
+    // 16251 clusters.
+    FatFormat {
+        name: "FAT16",
+        bits: 16,
+        total: 16384,
+        reserved: 1,
+        fats: 2,
+        fat_sectors: 64,
+        root_entries: 64,
+    },
+    // 68880 clusters.
+    FatFormat {
+        name: "FAT32",
+        bits: 32,
+        total: 70000,
+        reserved: 32,
+        fats: 2,
+        fat_sectors: 544,
+        root_entries: 0,
+    },
+];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Step {
+    Mount,
+    Create,
+    Write,
+    Remount,
+    Open,
+    Read,
+    ListRoot,
+    ListDirectory,
+}
+
+pub struct TestFat<'a, F: FileSystem<'a>, const N: usize> {
+    fs: &'a F,
+    device: &'a SparseRamBlock<'a, N>,
+    permissions: StoragePermissions,
+    /// The subdirectory of the file in the root directory.
+    directory: &'static [u8],
+    /// The path of the file.
+    path: &'static [u8],
+    data: &'static [u8],
+    buffer: TakeCell<'static, [u8]>,
+    file: Cell<FileId>,
+    format: Cell<usize>,
+    step: Cell<Step>,
+    client: OptionalCell<&'static dyn CapsuleTestClient>,
+}
+
+impl<'a, F: FileSystem<'a>, const N: usize> TestFat<'a, F, N> {
+    /// `buffer` must be at least as long as `path` and `data`, and `path`
+    /// must be `directory` followed by a slash and the name of the file. The
+    /// filesystem must allow `permissions` to create files in directory 0.
+    pub fn new(
+        fs: &'a F,
+        device: &'a SparseRamBlock<'a, N>,
+        permissions: StoragePermissions,
+        directory: &'static [u8],
+        path: &'static [u8],
+        data: &'static [u8],
+        buffer: &'static mut [u8],
+    ) -> Self {
+        TestFat {
+            fs,
+            device,
+            permissions,
+            directory,
+            path,
+            data,
+            buffer: TakeCell::new(buffer),
+            file: Cell::new(0),
+            format: Cell::new(0),
+            step: Cell::new(Step::Mount),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn run(&self) {
+        self.format.set(0);
+        self.run_format();
+    }
+
+    /// Format the device with the current format and mount it.
+    fn run_format(&self) {
+        self.device.format(&FAT_FORMATS[self.format.get()]);
+        self.mount(Step::Mount);
+    }
+
+    fn mount(&self, step: Step) {
+        self.step.set(step);
+        if let Err(e) = self.fs.mount() {
+            self.done(Err(CapsuleTestError::ErrorCode(e)));
+        }
+    }
+
+    /// Open the test file, creating it if `create` is true.
+    fn open(&self, create: bool) {
+        self.step
+            .set(if create { Step::Create } else { Step::Open });
+        let mut name = SubSliceMut::new(self.buffer.take().unwrap());
+        name.slice(..self.path.len());
+        name.as_slice().copy_from_slice(self.path);
+        if let Err((e, name)) = self.fs.open(0, name, create, self.permissions) {
+            self.buffer.replace(name.take());
+            self.done(Err(CapsuleTestError::ErrorCode(e)));
+        }
+    }
+
+    /// List the first entry of the root directory, or of the subdirectory.
+    fn list(&self, step: Step) {
+        self.step.set(step);
+        let mut name = SubSliceMut::new(self.buffer.take().unwrap());
+        let path = match step {
+            Step::ListRoot => &[][..],
+            _ => self.directory,
+        };
+        name.slice(..path.len());
+        name.as_slice().copy_from_slice(path);
+        if let Err((e, name)) = self.fs.read_dir(0, 0, name, self.permissions) {
+            self.buffer.replace(name.take());
+            self.done(Err(CapsuleTestError::ErrorCode(e)));
+        }
+    }
+
+    fn check(&self, result: Result<(), ErrorCode>, next: impl FnOnce()) {
+        match result {
+            Ok(()) => next(),
+            Err(e) => self.done(Err(CapsuleTestError::ErrorCode(e))),
+        }
+    }
+
+    fn done(&self, result: Result<(), CapsuleTestError>) {
+        let format = &FAT_FORMATS[self.format.get()];
+        debug!("FatTest: {} passed: {}", format.name, result.is_ok());
+        if result.is_ok() && self.format.get() + 1 < FAT_FORMATS.len() {
+            self.format.set(self.format.get() + 1);
+            self.run_format();
+            return;
+        }
+        self.client.map(|client| {
+            client.done(result);
+        });
+    }
+}
+
+impl<'a, F: FileSystem<'a>, const N: usize> FileSystemClient for TestFat<'a, F, N> {
+    fn mount_done(&self, result: Result<(), ErrorCode>) {
+        self.check(result, || self.open(self.step.get() == Step::Mount));
+    }
+
+    fn open_done(&self, result: Result<FileId, ErrorCode>, name: SubSliceMut<'static, u8>) {
+        self.buffer.replace(name.take());
+        self.check(result.map(|file| self.file.set(file)), || {
+            let mut buffer = SubSliceMut::new(self.buffer.take().unwrap());
+            let result = if self.step.get() == Step::Create {
+                self.step.set(Step::Write);
+                buffer.slice(..self.data.len());
+                buffer.as_slice().copy_from_slice(self.data);
+                self.fs.write(self.file.get(), 0, buffer, self.permissions)
+            } else {
+                self.step.set(Step::Read);
+                self.fs.read(self.file.get(), 0, buffer, self.permissions)
+            };
+            if let Err((e, buffer)) = result {
+                self.buffer.replace(buffer.take());
+                self.done(Err(CapsuleTestError::ErrorCode(e)));
+            }
+        });
+    }
+
+    fn stat_done(&self, _result: Result<FileInfo, ErrorCode>) {}
+
+    fn read_done(&self, result: Result<usize, ErrorCode>, buffer: SubSliceMut<'static, u8>) {
+        let mut buffer = buffer;
+        let matches =
+            result == Ok(self.data.len()) && buffer.as_slice()[..self.data.len()] == *self.data;
+        self.buffer.replace(buffer.take());
+        if result.is_ok() && !matches {
+            debug!("FatTest: read data does not match");
+            self.done(Err(CapsuleTestError::IncorrectResult));
+            return;
+        }
+        self.check(result.map(|_| ()), || self.list(Step::ListRoot));
+    }
+
+    fn write_done(&self, result: Result<usize, ErrorCode>, buffer: SubSliceMut<'static, u8>) {
+        self.buffer.replace(buffer.take());
+        if result.is_ok() && result != Ok(self.data.len()) {
+            self.done(Err(CapsuleTestError::IncorrectResult));
+            return;
+        }
+        // Mount again, so the file is read back from the device.
+        self.check(result.map(|_| ()), || self.mount(Step::Remount));
+    }
+
+    fn unlink_done(&self, _result: Result<(), ErrorCode>) {}
+
+    fn read_dir_done(
+        &self,
+        result: Result<Option<DirEntry>, ErrorCode>,
+        name: SubSliceMut<'static, u8>,
+    ) {
+        let mut name = name;
+        let step = self.step.get();
+        let matches = match result {
+            Ok(Some(entry)) if step == Step::ListRoot => {
+                entry.directory && name.as_slice() == self.directory
+            }
+            Ok(Some(entry)) => {
+                !entry.directory
+                    && entry.info.size == self.data.len()
+                    && name.as_slice() == &self.path[self.directory.len() + 1..]
+            }
+            _ => false,
+        };
+        self.buffer.replace(name.take());
+        if result.is_ok() && !matches {
+            debug!("FatTest: directory entry does not match");
+            self.done(Err(CapsuleTestError::IncorrectResult));
+            return;
+        }
+        self.check(result.map(|_| ()), || match step {
+            Step::ListRoot => self.list(Step::ListDirectory),
+            _ => self.done(Ok(())),
+        });
+    }
+}
+
+impl<'a, F: FileSystem<'a>, const N: usize> CapsuleTest for TestFat<'a, F, N> {
+    fn set_client(&self, client: &'static dyn CapsuleTestClient) {
+        self.client.set(client);
+    }
+}
@@ -0,0 +1,228 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Test a filesystem by creating a file, writing it in two parts, reading it
+//! back, listing it and removing it again.
+
+use core::cell::Cell;
+
+use capsules_core::test::capsule_test::{CapsuleTest, CapsuleTestClient, CapsuleTestError};
+use kernel::debug;
+use kernel::hil::filesystem::{DirEntry, FileId, FileInfo, FileSystem, FileSystemClient};
+use kernel::storage_permissions::StoragePermissions;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::ErrorCode;
+
+/// Bytes written by the first write, the rest of the data is written by a
+/// second write that changes the same block.
+const FIRST_WRITE: usize = 40;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Step {
+    Mount,
+    Create,
+    WriteFirst,
+    WriteSecond,
+    Read,
+    ReadDir,
+    Unlink,
+    OpenRemoved,
+}
+
+pub struct TestFileSystem<'a, F: FileSystem<'a>> {
+    fs: &'a F,
+    permissions: StoragePermissions,
+    name: &'static [u8],
+    data: &'static [u8],
+    buffer: TakeCell<'static, [u8]>,
+    file: Cell<FileId>,
+    step: Cell<Step>,
+    client: OptionalCell<&'static dyn CapsuleTestClient>,
+}
+
+impl<'a, F: FileSystem<'a>> TestFileSystem<'a, F> {
+    /// `buffer` must be at least as long as `name` and `data`. The filesystem
+    /// must allow `permissions` to create files in directory 0.
+    pub fn new(
+        fs: &'a F,
+        permissions: StoragePermissions,
+        name: &'static [u8],
+        data: &'static [u8],
+        buffer: &'static mut [u8],
+    ) -> Self {
+        TestFileSystem {
+            fs,
+            permissions,
+            name,
+            data,
+            buffer: TakeCell::new(buffer),
+            file: Cell::new(0),
+            step: Cell::new(Step::Mount),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn run(&self) {
+        self.step.set(Step::Mount);
+        if let Err(e) = self.fs.mount() {
+            self.done(Err(CapsuleTestError::ErrorCode(e)));
+        }
+    }
+
+    /// Open the test file, creating it if `create` is true.
+    fn open(&self, create: bool) {
+        let mut name = SubSliceMut::new(self.buffer.take().unwrap());
+        name.slice(..self.name.len());
+        name.as_slice().copy_from_slice(self.name);
+        if let Err((e, name)) = self.fs.open(0, name, create, self.permissions) {
+            self.buffer.replace(name.take());
+            self.done(Err(CapsuleTestError::ErrorCode(e)));
+        }
+    }
+
+    /// Write `data[start..end]` at the same offset in the file.
+    fn write(&self, start: usize, end: usize) {
+        let mut buffer = SubSliceMut::new(self.buffer.take().unwrap());
+        buffer.slice(..end - start);
+        buffer.as_slice().copy_from_slice(&self.data[start..end]);
+        if let Err((e, buffer)) = self
+            .fs
+            .write(self.file.get(), start, buffer, self.permissions)
+        {
+            self.buffer.replace(buffer.take());
+            self.done(Err(CapsuleTestError::ErrorCode(e)));
+        }
+    }
+
+    fn check(&self, result: Result<(), ErrorCode>, next: impl FnOnce()) {
+        match result {
+            Ok(()) => next(),
+            Err(e) => self.done(Err(CapsuleTestError::ErrorCode(e))),
+        }
+    }
+
+    fn done(&self, result: Result<(), CapsuleTestError>) {
+        debug!("FileSystemTest: passed: {}", result.is_ok());
+        self.client.map(|client| {
+            client.done(result);
+        });
+    }
+}
+
+impl<'a, F: FileSystem<'a>> FileSystemClient for TestFileSystem<'a, F> {
+    fn mount_done(&self, result: Result<(), ErrorCode>) {
+        self.check(result, || {
+            self.step.set(Step::Create);
+            self.open(true);
+        });
+    }
+
+    fn open_done(&self, result: Result<FileId, ErrorCode>, name: SubSliceMut<'static, u8>) {
+        self.buffer.replace(name.take());
+        if self.step.get() == Step::OpenRemoved {
+            // The file has been removed, so opening it without creating it
+            // must fail.
+            self.done(match result {
+                Err(ErrorCode::NOSUPPORT) => Ok(()),
+                Err(e) => Err(CapsuleTestError::ErrorCode(e)),
+                Ok(_) => Err(CapsuleTestError::IncorrectResult),
+            });
+            return;
+        }
+        self.check(result.map(|file| self.file.set(file)), || {
+            self.step.set(Step::WriteFirst);
+            self.write(0, FIRST_WRITE);
+        });
+    }
+
+    fn stat_done(&self, _result: Result<FileInfo, ErrorCode>) {}
+
+    fn read_done(&self, result: Result<usize, ErrorCode>, buffer: SubSliceMut<'static, u8>) {
+        let mut buffer = buffer;
+        let matches =
+            result == Ok(self.data.len()) && buffer.as_slice()[..self.data.len()] == *self.data;
+        self.buffer.replace(buffer.take());
+        debug!("FileSystemTest: data matches: {}", matches);
+        if result.is_ok() && !matches {
+            self.done(Err(CapsuleTestError::IncorrectResult));
+            return;
+        }
+        self.check(result.map(|_| ()), || {
+            self.step.set(Step::ReadDir);
+            let mut name = SubSliceMut::new(self.buffer.take().unwrap());
+            name.slice(..0);
+            if let Err((e, name)) = self.fs.read_dir(0, 0, name, self.permissions) {
+                self.buffer.replace(name.take());
+                self.done(Err(CapsuleTestError::ErrorCode(e)));
+            }
+        });
+    }
+
+    fn write_done(&self, result: Result<usize, ErrorCode>, buffer: SubSliceMut<'static, u8>) {
+        let written = buffer.len();
+        self.buffer.replace(buffer.take());
+        if result.is_ok() && result != Ok(written) {
+            self.done(Err(CapsuleTestError::IncorrectResult));
+            return;
+        }
+        self.check(result.map(|_| ()), || match self.step.get() {
+            Step::WriteFirst => {
+                self.step.set(Step::WriteSecond);
+                self.write(FIRST_WRITE, self.data.len());
+            }
+            _ => {
+                self.step.set(Step::Read);
+                let buffer = SubSliceMut::new(self.buffer.take().unwrap());
+                if let Err((e, buffer)) = self.fs.read(self.file.get(), 0, buffer, self.permissions)
+                {
+                    self.buffer.replace(buffer.take());
+                    self.done(Err(CapsuleTestError::ErrorCode(e)));
+                }
+            }
+        });
+    }
+
+    fn unlink_done(&self, result: Result<(), ErrorCode>) {
+        self.check(result, || {
+            self.step.set(Step::OpenRemoved);
+            self.open(false);
+        });
+    }
+
+    fn read_dir_done(
+        &self,
+        result: Result<Option<DirEntry>, ErrorCode>,
+        name: SubSliceMut<'static, u8>,
+    ) {
+        let mut name = name;
+        let matches = match result {
+            Ok(Some(entry)) => {
+                entry.file.map_or(true, |file| file == self.file.get())
+                    && !entry.directory
+                    && entry.info.size == self.data.len()
+                    && name.as_slice() == self.name
+            }
+            _ => false,
+        };
+        self.buffer.replace(name.take());
+        debug!("FileSystemTest: directory entry matches: {}", matches);
+        if result.is_ok() && !matches {
+            self.done(Err(CapsuleTestError::IncorrectResult));
+            return;
+        }
+        self.check(result.map(|_| ()), || {
+            self.step.set(Step::Unlink);
+            if let Err(e) = self.fs.unlink(self.file.get(), self.permissions) {
+                self.done(Err(CapsuleTestError::ErrorCode(e)));
+            }
+        });
+    }
+}
+
+impl<'a, F: FileSystem<'a>> CapsuleTest for TestFileSystem<'a, F> {
+    fn set_client(&self, client: &'static dyn CapsuleTestClient) {
+        self.client.set(client);
+    }
+}
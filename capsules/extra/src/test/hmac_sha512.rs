@@ -0,0 +1,118 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Test the software implementation of HMAC-SHA384 and HMAC-SHA512 by
+//! performing an HMAC and checking it against the expected value.
+
+use crate::hmac_sha512::HmacSha512Software;
+use capsules_core::test::capsule_test::{CapsuleTest, CapsuleTestClient, CapsuleTestError};
+use kernel::debug;
+use kernel::hil::digest;
+use kernel::hil::digest::{DigestData, DigestHash, HmacSha384, HmacSha512};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::SubSlice;
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::ErrorCode;
+
+pub struct TestHmacSha512 {
+    hmac: &'static HmacSha512Software<'static>,
+    key: &'static [u8],                  // The key to use for HMAC
+    data: TakeCell<'static, [u8]>,       // The data to authenticate
+    digest: TakeCell<'static, [u8; 64]>, // Buffer for the computed HMAC
+    correct: &'static [u8],              // The expected HMAC, 48 or 64 bytes
+    client: OptionalCell<&'static dyn CapsuleTestClient>,
+}
+
+impl TestHmacSha512 {
+    /// Computes HMAC-SHA384 if `correct` is 48 bytes long, HMAC-SHA512
+    /// otherwise.
+    pub fn new(
+        hmac: &'static HmacSha512Software<'static>,
+        key: &'static [u8],
+        data: &'static mut [u8],
+        digest: &'static mut [u8; 64],
+        correct: &'static [u8],
+    ) -> Self {
+        TestHmacSha512 {
+            hmac,
+            key,
+            data: TakeCell::new(data),
+            digest: TakeCell::new(digest),
+            correct,
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn run(&'static self) {
+        kernel::hil::digest::Digest::set_client(self.hmac, self);
+
+        let r = if self.correct.len() == 48 {
+            self.hmac.set_mode_hmacsha384(self.key)
+        } else {
+            self.hmac.set_mode_hmacsha512(self.key)
+        };
+        if r.is_err() {
+            panic!("HmacSha512Test: failed to set key: {:?}", r);
+        }
+        let data = self.data.take().unwrap();
+        let r = self.hmac.add_mut_data(SubSliceMut::new(data));
+        if r.is_err() {
+            panic!("HmacSha512Test: failed to add data: {:?}", r);
+        }
+    }
+
+    fn done(&self, result: Result<(), CapsuleTestError>) {
+        self.client.map(|client| {
+            client.done(result);
+        });
+    }
+}
+
+impl digest::ClientData<64> for TestHmacSha512 {
+    fn add_data_done(&self, _result: Result<(), ErrorCode>, _data: SubSlice<'static, u8>) {
+        unimplemented!()
+    }
+
+    fn add_mut_data_done(&self, result: Result<(), ErrorCode>, data: SubSliceMut<'static, u8>) {
+        self.data.replace(data.take());
+        if let Err(e) = result {
+            self.done(Err(CapsuleTestError::ErrorCode(e)));
+            return;
+        }
+
+        let r = self.hmac.run(self.digest.take().unwrap());
+        if let Err((e, digest)) = r {
+            self.digest.replace(digest);
+            self.done(Err(CapsuleTestError::ErrorCode(e)));
+        }
+    }
+}
+
+impl digest::ClientHash<64> for TestHmacSha512 {
+    fn hash_done(&self, result: Result<(), ErrorCode>, digest: &'static mut [u8; 64]) {
+        let matches = digest[..self.correct.len()] == *self.correct;
+        self.digest.replace(digest);
+        debug!(
+            "HmacSha512Test: HMAC-SHA{} matches: {}",
+            self.correct.len() * 8,
+            matches
+        );
+        self.done(match result {
+            Err(e) => Err(CapsuleTestError::ErrorCode(e)),
+            Ok(()) if !matches => Err(CapsuleTestError::IncorrectResult),
+            Ok(()) => Ok(()),
+        });
+    }
+}
+
+impl digest::ClientVerify<64> for TestHmacSha512 {
+    fn verification_done(&self, _result: Result<bool, ErrorCode>, _compare: &'static mut [u8; 64]) {
+    }
+}
+
+impl CapsuleTest for TestHmacSha512 {
+    fn set_client(&self, client: &'static dyn CapsuleTestClient) {
+        self.client.set(client);
+    }
+}
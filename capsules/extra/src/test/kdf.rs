@@ -0,0 +1,150 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Test the HKDF-SHA256 and PBKDF2-HMAC-SHA256 capsules by deriving a key and
+//! checking it against the expected output.
+
+use capsules_core::test::capsule_test::{CapsuleTest, CapsuleTestClient, CapsuleTestError};
+use kernel::debug;
+use kernel::hil::digest;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+use crate::hkdf::{HkdfClient, HkdfSha256};
+use crate::pbkdf2::{Pbkdf2Client, Pbkdf2Sha256};
+
+pub struct TestHkdf<'a, H: digest::Digest<'a, 32> + digest::HmacSha256> {
+    hkdf: &'a HkdfSha256<'a, H>,
+    salt: &'static [u8],
+    ikm: &'static [u8],
+    info: &'static [u8],
+    expected: &'static [u8],
+    okm: TakeCell<'static, [u8]>,
+    client: OptionalCell<&'static dyn CapsuleTestClient>,
+}
+
+impl<'a, H: digest::Digest<'a, 32> + digest::HmacSha256> TestHkdf<'a, H> {
+    /// `okm` must be as long as `expected`.
+    pub fn new(
+        hkdf: &'a HkdfSha256<'a, H>,
+        salt: &'static [u8],
+        ikm: &'static [u8],
+        info: &'static [u8],
+        expected: &'static [u8],
+        okm: &'static mut [u8],
+    ) -> Self {
+        TestHkdf {
+            hkdf,
+            salt,
+            ikm,
+            info,
+            expected,
+            okm: TakeCell::new(okm),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn run(&self) {
+        self.okm.take().map(|okm| {
+            if let Err((e, okm)) = self.hkdf.derive(self.salt, self.ikm, self.info, okm) {
+                self.okm.replace(okm);
+                self.done(Err(CapsuleTestError::ErrorCode(e)));
+            }
+        });
+    }
+
+    fn done(&self, result: Result<(), CapsuleTestError>) {
+        self.client.map(|client| {
+            client.done(result);
+        });
+    }
+}
+
+impl<'a, H: digest::Digest<'a, 32> + digest::HmacSha256> HkdfClient for TestHkdf<'a, H> {
+    fn derive_done(&self, result: Result<(), ErrorCode>, okm: &'static mut [u8]) {
+        let matches = result.is_ok() && *okm == *self.expected;
+        self.okm.replace(okm);
+        debug!("HkdfTest: output matches: {}", matches);
+        self.done(match result {
+            Err(e) => Err(CapsuleTestError::ErrorCode(e)),
+            Ok(()) if !matches => Err(CapsuleTestError::IncorrectResult),
+            Ok(()) => Ok(()),
+        });
+    }
+}
+
+impl<'a, H: digest::Digest<'a, 32> + digest::HmacSha256> CapsuleTest for TestHkdf<'a, H> {
+    fn set_client(&self, client: &'static dyn CapsuleTestClient) {
+        self.client.set(client);
+    }
+}
+
+pub struct TestPbkdf2<'a, H: digest::Digest<'a, 32> + digest::HmacSha256> {
+    pbkdf2: &'a Pbkdf2Sha256<'a, H>,
+    password: &'static [u8],
+    salt: &'static [u8],
+    iterations: u32,
+    expected: &'static [u8],
+    dk: TakeCell<'static, [u8]>,
+    client: OptionalCell<&'static dyn CapsuleTestClient>,
+}
+
+impl<'a, H: digest::Digest<'a, 32> + digest::HmacSha256> TestPbkdf2<'a, H> {
+    /// `dk` must be as long as `expected`.
+    pub fn new(
+        pbkdf2: &'a Pbkdf2Sha256<'a, H>,
+        password: &'static [u8],
+        salt: &'static [u8],
+        iterations: u32,
+        expected: &'static [u8],
+        dk: &'static mut [u8],
+    ) -> Self {
+        TestPbkdf2 {
+            pbkdf2,
+            password,
+            salt,
+            iterations,
+            expected,
+            dk: TakeCell::new(dk),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn run(&self) {
+        self.dk.take().map(|dk| {
+            if let Err((e, dk)) = self
+                .pbkdf2
+                .derive(self.password, self.salt, self.iterations, dk)
+            {
+                self.dk.replace(dk);
+                self.done(Err(CapsuleTestError::ErrorCode(e)));
+            }
+        });
+    }
+
+    fn done(&self, result: Result<(), CapsuleTestError>) {
+        self.client.map(|client| {
+            client.done(result);
+        });
+    }
+}
+
+impl<'a, H: digest::Digest<'a, 32> + digest::HmacSha256> Pbkdf2Client for TestPbkdf2<'a, H> {
+    fn derive_done(&self, result: Result<(), ErrorCode>, dk: &'static mut [u8]) {
+        let matches = result.is_ok() && *dk == *self.expected;
+        self.dk.replace(dk);
+        debug!("Pbkdf2Test: output matches: {}", matches);
+        self.done(match result {
+            Err(e) => Err(CapsuleTestError::ErrorCode(e)),
+            Ok(()) if !matches => Err(CapsuleTestError::IncorrectResult),
+            Ok(()) => Ok(()),
+        });
+    }
+}
+
+impl<'a, H: digest::Digest<'a, 32> + digest::HmacSha256> CapsuleTest for TestPbkdf2<'a, H> {
+    fn set_client(&self, client: &'static dyn CapsuleTestClient) {
+        self.client.set(client);
+    }
+}
@@ -0,0 +1,257 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Test the key store by importing a key and opening it again.
+//!
+//! The test then checks that a different `ShortId` cannot open the key, and
+//! that the sealed value fails to unseal once it is copied to another name in
+//! the k-v store.
+
+use core::cell::Cell;
+
+use crate::key_store::{KeyStore, KeyStoreClient, KV_KEY_PREFIX};
+use capsules_core::test::capsule_test::{CapsuleTest, CapsuleTestClient, CapsuleTestError};
+use kernel::debug;
+use kernel::hil::kv::{KVClient, KVPermissions};
+use kernel::hil::rng::Rng;
+use kernel::hil::symmetric_encryption::AES256GCM;
+use kernel::storage_permissions::StoragePermissions;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::ErrorCode;
+
+const NAME: &[u8] = b"test-key";
+const MOVED_NAME: &[u8] = b"moved-key";
+
+static KEY: [u8; 32] = [
+    0x60, 0x3d, 0xeb, 0x10, 0x15, 0xca, 0x71, 0xbe, 0x2b, 0x73, 0xae, 0xf0, 0x85, 0x7d, 0x77, 0x81,
+    0x1f, 0x35, 0x2c, 0x07, 0x3b, 0x61, 0x08, 0xd7, 0x2d, 0x98, 0x10, 0xa3, 0x09, 0x14, 0xdf, 0xf4,
+];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Step {
+    Import,
+    Open,
+    OpenOther,
+    Read,
+    Move,
+    OpenMoved,
+}
+
+pub struct TestKeyStore<'a, K: KVPermissions<'a>, A: AES256GCM<'a>, R: Rng<'a>> {
+    key_store: &'a KeyStore<'a, K, A, R>,
+    /// A k-v store user of its own, to move the sealed value.
+    kv: &'a K,
+    permissions: StoragePermissions,
+    other_permissions: StoragePermissions,
+    key_buffer: TakeCell<'static, [u8]>,
+    kv_key_buffer: TakeCell<'static, [u8]>,
+    value_buffer: TakeCell<'static, [u8]>,
+    step: Cell<Step>,
+    client: OptionalCell<&'static dyn CapsuleTestClient>,
+}
+
+impl<'a, K: KVPermissions<'a>, A: AES256GCM<'a>, R: Rng<'a>> TestKeyStore<'a, K, A, R> {
+    /// `permissions` and `other_permissions` must have different write IDs.
+    /// `key_buffer` must be `MAX_KEY_SIZE` bytes long, `kv_key_buffer`
+    /// `KV_KEY_BUFFER_SIZE` and `value_buffer` `MAX_BLOB_LENGTH` plus the
+    /// header size of `kv`.
+    pub fn new(
+        key_store: &'a KeyStore<'a, K, A, R>,
+        kv: &'a K,
+        permissions: StoragePermissions,
+        other_permissions: StoragePermissions,
+        key_buffer: &'static mut [u8],
+        kv_key_buffer: &'static mut [u8],
+        value_buffer: &'static mut [u8],
+    ) -> Self {
+        TestKeyStore {
+            key_store,
+            kv,
+            permissions,
+            other_permissions,
+            key_buffer: TakeCell::new(key_buffer),
+            kv_key_buffer: TakeCell::new(kv_key_buffer),
+            value_buffer: TakeCell::new(value_buffer),
+            step: Cell::new(Step::Import),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn run(&self) {
+        self.step.set(Step::Import);
+        if let Err(e) = self.key_store.import_key(NAME, &KEY, self.permissions) {
+            self.done(Err(CapsuleTestError::ErrorCode(e)));
+        }
+    }
+
+    fn open(&self, step: Step, name: &[u8], permissions: StoragePermissions) {
+        self.step.set(step);
+        let key = self.key_buffer.take().unwrap();
+        if let Err((e, key)) = self.key_store.open_key(name, permissions, key) {
+            self.key_buffer.replace(key);
+            self.done(Err(CapsuleTestError::ErrorCode(e)));
+        }
+    }
+
+    /// The name the key store uses for the key `name` in the k-v store.
+    fn kv_key(&self, name: &[u8]) -> SubSliceMut<'static, u8> {
+        let write_id = self.permissions.get_write_id().unwrap_or(0);
+        let buf = self.kv_key_buffer.take().unwrap();
+        let id_end = KV_KEY_PREFIX.len() + 4;
+        buf[..KV_KEY_PREFIX.len()].copy_from_slice(KV_KEY_PREFIX);
+        buf[KV_KEY_PREFIX.len()..id_end].copy_from_slice(&write_id.to_le_bytes());
+        buf[id_end..id_end + name.len()].copy_from_slice(name);
+        let mut key = SubSliceMut::new(buf);
+        key.slice(..id_end + name.len());
+        key
+    }
+
+    fn check(&self, result: Result<(), ErrorCode>, next: impl FnOnce()) {
+        match result {
+            Ok(()) => next(),
+            Err(e) => self.done(Err(CapsuleTestError::ErrorCode(e))),
+        }
+    }
+
+    fn done(&self, result: Result<(), CapsuleTestError>) {
+        debug!("KeyStoreTest: passed: {}", result.is_ok());
+        self.client.map(|client| {
+            client.done(result);
+        });
+    }
+}
+
+impl<'a, K: KVPermissions<'a>, A: AES256GCM<'a>, R: Rng<'a>> KeyStoreClient
+    for TestKeyStore<'a, K, A, R>
+{
+    fn import_done(&self, result: Result<(), ErrorCode>) {
+        self.check(result, || self.open(Step::Open, NAME, self.permissions));
+    }
+
+    fn open_done(&self, result: Result<usize, ErrorCode>, key: &'static mut [u8]) {
+        let matches = result.is_ok_and(|len| key[..len] == KEY);
+        self.key_buffer.replace(key);
+
+        match self.step.get() {
+            Step::Open => {
+                debug!("KeyStoreTest: opened key matches: {}", matches);
+                if !matches {
+                    self.done(Err(CapsuleTestError::IncorrectResult));
+                    return;
+                }
+                self.open(Step::OpenOther, NAME, self.other_permissions);
+            }
+            Step::OpenOther => {
+                // The key lives in the namespace of the other write ID.
+                debug!("KeyStoreTest: other ShortId denied: {}", result.is_err());
+                if result.is_ok() {
+                    self.done(Err(CapsuleTestError::IncorrectResult));
+                    return;
+                }
+                self.step.set(Step::Read);
+                let value = SubSliceMut::new(self.value_buffer.take().unwrap());
+                if let Err((key, value, e)) =
+                    self.kv.get(self.kv_key(NAME), value, self.permissions)
+                {
+                    self.kv_key_buffer.replace(key.take());
+                    self.value_buffer.replace(value.take());
+                    self.done(Err(CapsuleTestError::ErrorCode(e)));
+                }
+            }
+            Step::OpenMoved => {
+                // The sealed value authenticates its original name.
+                let failed = result == Err(ErrorCode::FAIL);
+                debug!("KeyStoreTest: moved key fails to unseal: {}", failed);
+                self.done(if failed {
+                    Ok(())
+                } else {
+                    Err(CapsuleTestError::IncorrectResult)
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<'a, K: KVPermissions<'a>, A: AES256GCM<'a>, R: Rng<'a>> KVClient
+    for TestKeyStore<'a, K, A, R>
+{
+    fn get_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        mut value: SubSliceMut<'static, u8>,
+    ) {
+        self.kv_key_buffer.replace(key.take());
+        if let Err(e) = result {
+            self.value_buffer.replace(value.take());
+            self.done(Err(CapsuleTestError::ErrorCode(e)));
+            return;
+        }
+
+        // Store the sealed value, still in place after the header, under a
+        // different name of the same process.
+        self.step.set(Step::Move);
+        value.reset();
+        if let Err((key, value, e)) = self
+            .kv
+            .set(self.kv_key(MOVED_NAME), value, self.permissions)
+        {
+            self.kv_key_buffer.replace(key.take());
+            self.value_buffer.replace(value.take());
+            self.done(Err(CapsuleTestError::ErrorCode(e)));
+        }
+    }
+
+    fn set_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.kv_key_buffer.replace(key.take());
+        self.value_buffer.replace(value.take());
+        self.check(result, || {
+            self.open(Step::OpenMoved, MOVED_NAME, self.permissions)
+        });
+    }
+
+    fn add_complete(
+        &self,
+        _result: Result<(), ErrorCode>,
+        _key: SubSliceMut<'static, u8>,
+        _value: SubSliceMut<'static, u8>,
+    ) {
+    }
+
+    fn update_complete(
+        &self,
+        _result: Result<(), ErrorCode>,
+        _key: SubSliceMut<'static, u8>,
+        _value: SubSliceMut<'static, u8>,
+    ) {
+    }
+
+    fn delete_complete(&self, _result: Result<(), ErrorCode>, _key: SubSliceMut<'static, u8>) {}
+
+    fn garbage_collection_complete(&self, _result: Result<(), ErrorCode>) {}
+
+    fn next_key_complete(
+        &self,
+        _result: Result<(), ErrorCode>,
+        _cursor: usize,
+        _key: SubSliceMut<'static, u8>,
+        _value: SubSliceMut<'static, u8>,
+    ) {
+    }
+}
+
+impl<'a, K: KVPermissions<'a>, A: AES256GCM<'a>, R: Rng<'a>> CapsuleTest
+    for TestKeyStore<'a, K, A, R>
+{
+    fn set_client(&self, client: &'static dyn CapsuleTestClient) {
+        self.client.set(client);
+    }
+}
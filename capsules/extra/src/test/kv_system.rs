@@ -25,6 +25,9 @@
 //! Removed Key: [18, 52, 86, 120, 154, 188, 222, 240]
 //! Try to read removed key: [18, 52, 86, 120, 154, 188, 222, 240]
 //! Unable to find key: [18, 52, 86, 120, 154, 188, 222, 240]
+//! Listing the remaining keys
+//! Found key: [123, 201, 247, 255, 79, 118, 242, 68] with value length 0
+//! No more keys
 //! Let's start a garbage collection
 //! Finished garbage collection
 //! ---Finished TicKV Tests---
@@ -117,7 +120,7 @@ impl<S: KVSystem<'static, K = T>, T: KeyType + core::fmt::Debug> KVSystemClient<
         &self,
         result: Result<(), ErrorCode>,
         key: &'static mut T,
-        ret_buf: SubSliceMut<'static, u8>,
+        mut ret_buf: SubSliceMut<'static, u8>,
     ) {
         match result {
             Ok(()) => {
@@ -131,8 +134,9 @@ impl<S: KVSystem<'static, K = T>, T: KeyType + core::fmt::Debug> KVSystemClient<
                     debug!("Unable to find key: {:?}", key);
                     self.state.set(CurrentState::Normal);
 
-                    debug!("Let's start a garbage collection");
-                    self.kv_system.garbage_collect().unwrap();
+                    debug!("Listing the remaining keys");
+                    ret_buf.reset();
+                    self.kv_system.next_key(0, key, ret_buf).unwrap();
                 } else {
                     panic!("Error finding key: {:?}", e);
                 }
@@ -157,6 +161,32 @@ impl<S: KVSystem<'static, K = T>, T: KeyType + core::fmt::Debug> KVSystemClient<
         }
     }
 
+    fn next_key_complete(
+        &self,
+        result: Result<usize, ErrorCode>,
+        cursor: usize,
+        key: &'static mut T,
+        mut ret_buf: SubSliceMut<'static, u8>,
+    ) {
+        match result {
+            Ok(value_length) => {
+                debug!("Found key: {:?} with value length {}", key, value_length);
+                ret_buf.reset();
+                self.kv_system.next_key(cursor, key, ret_buf).unwrap();
+            }
+            Err(ErrorCode::NOSUPPORT) => {
+                debug!("No more keys");
+                self.ret_buffer.replace(ret_buf.take());
+
+                debug!("Let's start a garbage collection");
+                self.kv_system.garbage_collect().unwrap();
+            }
+            Err(e) => {
+                panic!("Error listing keys: {:?}", e);
+            }
+        }
+    }
+
     fn garbage_collect_complete(&self, result: Result<(), ErrorCode>) {
         match result {
             Ok(()) => {
@@ -3,11 +3,21 @@
 // Copyright Tock Contributors 2023.
 
 pub mod aes;
+pub mod aes256_gcm;
 pub mod aes_ccm;
 pub mod aes_gcm;
+pub mod block;
+pub mod chacha20poly1305;
 pub mod crc;
+pub mod fat;
+pub mod filesystem;
 pub mod hmac_sha256;
+pub mod hmac_sha512;
+pub mod kdf;
+pub mod key_store;
 pub mod kv_system;
 pub mod sha256;
+pub mod sha512;
+pub mod signature_verify;
 pub mod siphash24;
 pub mod udp;
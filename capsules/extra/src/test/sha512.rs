@@ -0,0 +1,137 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Test the software implementation of SHA-384 and SHA-512 by hashing a
+//! message in two parts and checking the result against the expected hash.
+
+use core::cell::Cell;
+
+use crate::sha512::Sha512Software;
+use capsules_core::test::capsule_test::{CapsuleTest, CapsuleTestClient, CapsuleTestError};
+use kernel::debug;
+use kernel::hil::digest;
+use kernel::hil::digest::{DigestData, DigestHash, Sha384, Sha512};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::SubSlice;
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::ErrorCode;
+
+pub struct TestSha512 {
+    sha: &'static Sha512Software<'static>,
+    data: TakeCell<'static, [u8]>,       // The data to hash
+    digest: TakeCell<'static, [u8; 64]>, // Buffer for the computed hash
+    correct: &'static [u8],              // The expected hash, 48 or 64 bytes
+    second_half: Cell<bool>,             // Whether the second half was added
+    client: OptionalCell<&'static dyn CapsuleTestClient>,
+}
+
+impl TestSha512 {
+    /// Computes SHA-384 if `correct` is 48 bytes long, SHA-512 otherwise.
+    pub fn new(
+        sha: &'static Sha512Software<'static>,
+        data: &'static mut [u8],
+        digest: &'static mut [u8; 64],
+        correct: &'static [u8],
+    ) -> Self {
+        TestSha512 {
+            sha,
+            data: TakeCell::new(data),
+            digest: TakeCell::new(digest),
+            correct,
+            second_half: Cell::new(false),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn run(&'static self) {
+        kernel::hil::digest::Digest::set_client(self.sha, self);
+
+        let r = if self.correct.len() == 48 {
+            self.sha.set_mode_sha384()
+        } else {
+            self.sha.set_mode_sha512()
+        };
+        if r.is_err() {
+            panic!("Sha512Test: failed to set mode: {:?}", r);
+        }
+
+        // Add the first half now and the rest once that is done, so that a
+        // message spanning calls is tested.
+        self.second_half.set(false);
+        let data = self.data.take().unwrap();
+        let half = data.len() / 2;
+        let mut buffer = SubSliceMut::new(data);
+        buffer.slice(..half);
+        let r = self.sha.add_mut_data(buffer);
+        if r.is_err() {
+            panic!("Sha512Test: failed to add data: {:?}", r);
+        }
+    }
+
+    fn done(&self, result: Result<(), CapsuleTestError>) {
+        self.client.map(|client| {
+            client.done(result);
+        });
+    }
+}
+
+impl digest::ClientData<64> for TestSha512 {
+    fn add_data_done(&self, _result: Result<(), ErrorCode>, _data: SubSlice<'static, u8>) {
+        unimplemented!()
+    }
+
+    fn add_mut_data_done(&self, result: Result<(), ErrorCode>, mut data: SubSliceMut<'static, u8>) {
+        if let Err(e) = result {
+            self.data.replace(data.take());
+            self.done(Err(CapsuleTestError::ErrorCode(e)));
+            return;
+        }
+
+        data.reset();
+        let half = data.len() / 2;
+        if !self.second_half.get() {
+            self.second_half.set(true);
+            data.slice(half..);
+            let r = self.sha.add_mut_data(data);
+            if r.is_err() {
+                panic!("Sha512Test: failed to add data: {:?}", r);
+            }
+            return;
+        }
+
+        self.data.replace(data.take());
+        let r = self.sha.run(self.digest.take().unwrap());
+        if r.is_err() {
+            panic!("Sha512Test: failed to run: {:?}", r);
+        }
+    }
+}
+
+impl digest::ClientHash<64> for TestSha512 {
+    fn hash_done(&self, result: Result<(), ErrorCode>, digest: &'static mut [u8; 64]) {
+        let matches = digest[..self.correct.len()] == *self.correct;
+        self.digest.replace(digest);
+        debug!(
+            "Sha512Test: SHA-{} matches: {}",
+            self.correct.len() * 8,
+            matches
+        );
+        self.done(match result {
+            Err(e) => Err(CapsuleTestError::ErrorCode(e)),
+            Ok(()) if !matches => Err(CapsuleTestError::IncorrectResult),
+            Ok(()) => Ok(()),
+        });
+    }
+}
+
+impl digest::ClientVerify<64> for TestSha512 {
+    fn verification_done(&self, _result: Result<bool, ErrorCode>, _compare: &'static mut [u8; 64]) {
+    }
+}
+
+impl CapsuleTest for TestSha512 {
+    fn set_client(&self, client: &'static dyn CapsuleTestClient) {
+        self.client.set(client);
+    }
+}
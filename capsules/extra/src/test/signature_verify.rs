@@ -0,0 +1,135 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Test a signature verifier by checking a signature of a hash against the
+//! expected result.
+//!
+//! The test can then check that the verifier rejects changed copies of the
+//! hash and signature, each described by a [`Tamper`].
+
+use core::cell::Cell;
+
+use capsules_core::test::capsule_test::{CapsuleTest, CapsuleTestClient, CapsuleTestError};
+use kernel::debug;
+use kernel::hil::public_key_crypto::signature::{ClientVerify, SignatureVerify};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// A change to the hash or signature which makes a valid signature invalid.
+#[derive(Clone, Copy)]
+pub enum Tamper {
+    /// Flip the bits of the signature byte at this index.
+    FlipSignatureByte(usize),
+    /// Flip the bits of the hash byte at this index.
+    FlipHashByte(usize),
+    /// Overwrite the signature with these bytes, starting at this index.
+    OverwriteSignature(usize, &'static [u8]),
+}
+
+pub struct TestSignatureVerify<
+    V: SignatureVerify<'static, HL, SL> + 'static,
+    const HL: usize,
+    const SL: usize,
+> {
+    verifier: &'static V,
+    hash: TakeCell<'static, [u8; HL]>,
+    signature: TakeCell<'static, [u8; SL]>,
+    correct: Cell<bool>, // Whether the signature is valid
+    original: Cell<Option<([u8; HL], [u8; SL])>>,
+    tampers: &'static [Tamper],
+    next_tamper: Cell<usize>,
+    client: OptionalCell<&'static dyn CapsuleTestClient>,
+}
+
+impl<V: SignatureVerify<'static, HL, SL>, const HL: usize, const SL: usize>
+    TestSignatureVerify<V, HL, SL>
+{
+    pub fn new(
+        verifier: &'static V,
+        hash: &'static mut [u8; HL],
+        signature: &'static mut [u8; SL],
+        correct: bool,
+        tampers: &'static [Tamper],
+    ) -> Self {
+        TestSignatureVerify {
+            verifier,
+            hash: TakeCell::new(hash),
+            signature: TakeCell::new(signature),
+            correct: Cell::new(correct),
+            original: Cell::new(None),
+            tampers,
+            next_tamper: Cell::new(0),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn run(&'static self) {
+        self.verifier.set_verify_client(self);
+        let hash = self.hash.take().unwrap();
+        let signature = self.signature.take().unwrap();
+        self.verify(hash, signature);
+    }
+
+    fn verify(&self, hash: &'static mut [u8; HL], signature: &'static mut [u8; SL]) {
+        if let Err((e, hash, signature)) = self.verifier.verify(hash, signature) {
+            self.hash.replace(hash);
+            self.signature.replace(signature);
+            panic!("SignatureVerifyTest: failed to verify: {:?}", e);
+        }
+    }
+}
+
+impl<V: SignatureVerify<'static, HL, SL>, const HL: usize, const SL: usize> ClientVerify<HL, SL>
+    for TestSignatureVerify<V, HL, SL>
+{
+    fn verification_done(
+        &self,
+        result: Result<bool, ErrorCode>,
+        hash: &'static mut [u8; HL],
+        signature: &'static mut [u8; SL],
+    ) {
+        debug!("SignatureVerifyTest: Verification result: {:?}", result);
+        let result = match result {
+            Ok(valid) if valid == self.correct.get() => Ok(()),
+            Ok(_) => Err(CapsuleTestError::IncorrectResult),
+            Err(e) => Err(CapsuleTestError::ErrorCode(e)),
+        };
+
+        // Check the next tampered copy of the original hash and signature,
+        // which must not verify.
+        let index = self.next_tamper.get();
+        if let (Ok(()), Some(&tamper)) = (&result, self.tampers.get(index)) {
+            let (original_hash, original_signature) =
+                self.original.get().unwrap_or((*hash, *signature));
+            self.original.set(Some((original_hash, original_signature)));
+            *hash = original_hash;
+            *signature = original_signature;
+            match tamper {
+                Tamper::FlipSignatureByte(i) => signature[i] ^= 0xff,
+                Tamper::FlipHashByte(i) => hash[i] ^= 0xff,
+                Tamper::OverwriteSignature(i, bytes) => {
+                    signature[i..i + bytes.len()].copy_from_slice(bytes)
+                }
+            }
+            self.next_tamper.set(index + 1);
+            self.correct.set(false);
+            self.verify(hash, signature);
+            return;
+        }
+
+        self.hash.replace(hash);
+        self.signature.replace(signature);
+        self.client.map(|client| {
+            client.done(result);
+        });
+    }
+}
+
+impl<V: SignatureVerify<'static, HL, SL>, const HL: usize, const SL: usize> CapsuleTest
+    for TestSignatureVerify<V, HL, SL>
+{
+    fn set_client(&self, client: &'static dyn CapsuleTestClient) {
+        self.client.set(client);
+    }
+}
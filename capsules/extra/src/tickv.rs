@@ -95,6 +95,21 @@ pub trait KVSystemClient<K: KeyType> {
     ///
     /// - `result`: Nothing on success, 'ErrorCode' on error
     fn garbage_collect_complete(&self, result: Result<(), ErrorCode>);
+
+    /// This callback is called when the next_key operation completes.
+    ///
+    /// - `result`: The length of the value on success, 'ErrorCode' on error
+    /// - `cursor`: The position to continue from to find the following key
+    /// - `key`: The key buffer, containing the key that was found
+    /// - `ret_buf`: The ret_buf buffer, sliced to the part of the value that
+    ///   was copied into it
+    fn next_key_complete(
+        &self,
+        result: Result<usize, ErrorCode>,
+        cursor: usize,
+        key: &'static mut K,
+        ret_buf: SubSliceMut<'static, u8>,
+    );
 }
 
 pub trait KVSystem<'a> {
@@ -193,6 +208,30 @@ pub trait KVSystem<'a> {
     /// - `INVAL`: An invalid parameter was passed.
     /// - `NODEVICE`: No KV store was setup.
     fn garbage_collect(&self) -> Result<(), ErrorCode>;
+
+    /// Finds the next valid key in the KV Store.
+    ///
+    /// - `cursor`: The position to start looking from, 0 for the first key.
+    /// - `key`: A buffer to store the hashed key to.
+    /// - `ret_buf`: A buffer to store the value to. If the value is longer
+    ///   than the buffer only the start of it is stored.
+    ///
+    /// On success nothing will be returned.
+    /// On error the key, ret_buf and a `Result<(), ErrorCode>` will be returned.
+    ///
+    /// The possible `Result<(), ErrorCode>`s are:
+    /// - `BUSY`: An operation is already in progress
+    /// - `INVAL`: An invalid parameter was passed
+    /// - `NODEVICE`: No KV store was setup
+    /// - `NOSUPPORT`: There are no more keys.
+    ///
+    /// The callback returns `NOSUPPORT` once there are no more keys.
+    fn next_key(
+        &self,
+        cursor: usize,
+        key: &'static mut Self::K,
+        ret_buf: SubSliceMut<'static, u8>,
+    ) -> Result<(), (&'static mut Self::K, SubSliceMut<'static, u8>, ErrorCode)>;
 }
 
 #[derive(Clone, Copy, PartialEq, Debug)]
@@ -203,6 +242,7 @@ enum Operation {
     AppendKey,
     InvalidateKey,
     GarbageCollect,
+    NextKey,
 }
 
 /// Wrapper object that provides the flash interface TicKV expects using the
@@ -302,6 +342,8 @@ pub struct TicKVSystem<'a, F: Flash + 'static, H: Hasher<'a, 8>, const PAGE_SIZE
     /// Holder for a buffer containing a value being read from or written to the
     /// key-value store.
     value_buffer: MapCell<SubSliceMut<'static, u8>>,
+    /// The position to start finding the next key from.
+    cursor: Cell<usize>,
     /// Callback client when the `KVSystem` operation completes.
     client: OptionalCell<&'a dyn KVSystemClient<TicKVKeyType>>,
 }
@@ -329,6 +371,7 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> TicKVSystem<'a, F,
             unhashed_key_buffer: MapCell::empty(),
             key_buffer: TakeCell::empty(),
             value_buffer: MapCell::empty(),
+            cursor: Cell::new(0),
             client: OptionalCell::empty(),
         }
     }
@@ -386,6 +429,20 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> TicKVSystem<'a, F,
                 }
                 _ => {}
             },
+            Operation::NextKey => {
+                match self.next_key(
+                    self.cursor.get(),
+                    self.key_buffer.take().unwrap(),
+                    self.value_buffer.take().unwrap(),
+                ) {
+                    Err((key, value, error)) => {
+                        self.client.map(move |cb| {
+                            cb.next_key_complete(Err(error), self.cursor.get(), key, value);
+                        });
+                    }
+                    _ => {}
+                }
+            }
         }
         self.next_operation.set(Operation::None);
     }
@@ -564,6 +621,33 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> flash::Client<F>
                 }
                 _ => {}
             },
+            Operation::NextKey => match ret {
+                Err(tickv::error_codes::ErrorCode::ReadNotReady(_)) => {
+                    // Need to read the next region.
+                }
+                _ => {
+                    self.operation.set(Operation::None);
+                    let cursor = self.tickv.cursor();
+                    let key = self.key_buffer.take().unwrap();
+                    let mut value = self.value_buffer.take().unwrap();
+
+                    let result = match (ret, self.tickv.next_key_found()) {
+                        (Ok(_), Some((hash, value_length))) => {
+                            *key = hash.to_be_bytes();
+                            // Zero bytes were copied if the value is empty.
+                            value.slice(0..tickv_buf_len);
+                            Ok(value_length)
+                        }
+                        (Err(tickv::error_codes::ErrorCode::KeyNotFound), _) => {
+                            Err(ErrorCode::NOSUPPORT)
+                        }
+                        _ => Err(ErrorCode::FAIL),
+                    };
+                    self.client.map(move |cb| {
+                        cb.next_key_complete(result, cursor, key, value);
+                    });
+                }
+            },
             _ => unreachable!(),
         }
     }
@@ -787,4 +871,48 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> KVSystem<'a>
             }
         }
     }
+
+    fn next_key(
+        &self,
+        cursor: usize,
+        key: &'static mut Self::K,
+        ret_buf: SubSliceMut<'static, u8>,
+    ) -> Result<(), (&'static mut [u8; 8], SubSliceMut<'static, u8>, ErrorCode)> {
+        if ret_buf.is_sliced() {
+            return Err((key, ret_buf, ErrorCode::SIZE));
+        }
+        match self.operation.get() {
+            Operation::None => {
+                self.operation.set(Operation::NextKey);
+
+                match self.tickv.next_key(cursor, ret_buf.take()) {
+                    Ok(_ret) => {
+                        self.key_buffer.replace(key);
+                        Ok(())
+                    }
+                    Err((buf, e)) => {
+                        self.operation.set(Operation::None);
+                        let tock_error = match e {
+                            tickv::error_codes::ErrorCode::KeyNotFound => ErrorCode::NOSUPPORT,
+                            _ => ErrorCode::FAIL,
+                        };
+                        Err((key, SubSliceMut::new(buf), tock_error))
+                    }
+                }
+            }
+            Operation::Init => {
+                // The init process is still occurring.
+                // We can save this request and start it after init.
+                self.next_operation.set(Operation::NextKey);
+                self.cursor.set(cursor);
+                self.key_buffer.replace(key);
+                self.value_buffer.replace(ret_buf);
+                Ok(())
+            }
+            _ => {
+                // An operation is already in process.
+                Err((key, ret_buf, ErrorCode::BUSY))
+            }
+        }
+    }
 }
@@ -41,6 +41,7 @@ enum Operation {
     Update,
     Delete,
     GarbageCollect,
+    NextKey,
 }
 
 /// `TicKVKVStore` implements the KV interface using the TicKV KVSystem
@@ -225,6 +226,49 @@ impl<'a, K: KVSystem<'a, K = T>, T: KeyType> kv::KV<'a> for TicKVKVStore<'a, K,
             Ok(())
         }
     }
+
+    fn next_key(
+        &self,
+        cursor: usize,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) -> Result<
+        (),
+        (
+            SubSliceMut<'static, u8>,
+            SubSliceMut<'static, u8>,
+            ErrorCode,
+        ),
+    > {
+        if self.operation.is_some() {
+            return Err((key, value, ErrorCode::BUSY));
+        }
+
+        // The hashed key is returned, so there must be room for it.
+        let key_len = self
+            .hashed_key
+            .map_or(0, |hashed_key| hashed_key.as_ref().len());
+        if key.len() < key_len {
+            return Err((key, value, ErrorCode::SIZE));
+        }
+
+        self.operation.set(Operation::NextKey);
+
+        match self.hashed_key.take() {
+            Some(hashed_key) => match self.kv.next_key(cursor, hashed_key, value) {
+                Ok(()) => {
+                    self.unhashed_key.replace(key);
+                    Ok(())
+                }
+                Err((hashed_key, value, e)) => {
+                    self.operation.clear();
+                    self.hashed_key.replace(hashed_key);
+                    Err((key, value, e))
+                }
+            },
+            None => Err((key, value, ErrorCode::FAIL)),
+        }
+    }
 }
 
 impl<'a, K: KVSystem<'a, K = T>, T: KeyType> KVSystemClient<T> for TicKVKVStore<'a, K, T> {
@@ -276,7 +320,7 @@ impl<'a, K: KVSystem<'a, K = T>, T: KeyType> KVSystemClient<T> for TicKVKVStore<
                             cb.delete_complete(Err(ErrorCode::FAIL), unhashed_key);
                         });
                     }
-                    Operation::GarbageCollect => {}
+                    Operation::GarbageCollect | Operation::NextKey => {}
                 }
             } else {
                 match op {
@@ -367,7 +411,7 @@ impl<'a, K: KVSystem<'a, K = T>, T: KeyType> KVSystemClient<T> for TicKVKVStore<
                             }
                         };
                     }
-                    Operation::GarbageCollect => {}
+                    Operation::GarbageCollect | Operation::NextKey => {}
                 }
             }
         });
@@ -382,7 +426,7 @@ impl<'a, K: KVSystem<'a, K = T>, T: KeyType> KVSystemClient<T> for TicKVKVStore<
         self.hashed_key.replace(key);
 
         self.operation.map(|op| match op {
-            Operation::Get | Operation::Delete => {}
+            Operation::Get | Operation::Delete | Operation::NextKey => {}
             Operation::Set => {
                 match result {
                     Err(ErrorCode::NOSUPPORT) => {
@@ -496,7 +540,7 @@ impl<'a, K: KVSystem<'a, K = T>, T: KeyType> KVSystemClient<T> for TicKVKVStore<
         self.hashed_key.replace(key);
 
         self.operation.map(|op| match op {
-            Operation::Get | Operation::Add => {}
+            Operation::Get | Operation::Add | Operation::NextKey => {}
             Operation::Set => {
                 // Now that we have deleted the existing key-value we can store
                 // our new key and value.
@@ -593,4 +637,35 @@ impl<'a, K: KVSystem<'a, K = T>, T: KeyType> KVSystemClient<T> for TicKVKVStore<
             cb.garbage_collection_complete(result);
         });
     }
+
+    fn next_key_complete(
+        &self,
+        result: Result<usize, ErrorCode>,
+        cursor: usize,
+        key: &'static mut T,
+        ret_buf: SubSliceMut<'static, u8>,
+    ) {
+        self.operation.clear();
+
+        self.unhashed_key.take().map(|mut unhashed_key| {
+            if result.is_ok() {
+                // Return the hashed key, as that is all that is stored.
+                let hashed_key = key.as_ref();
+                unhashed_key.slice(0..hashed_key.len());
+                unhashed_key.as_slice().copy_from_slice(hashed_key);
+            }
+
+            let result = match result {
+                Ok(value_length) if value_length > ret_buf.len() => Err(ErrorCode::SIZE),
+                Ok(_) => Ok(()),
+                Err(ErrorCode::NOSUPPORT) => Err(ErrorCode::NOSUPPORT),
+                Err(_) => Err(ErrorCode::FAIL),
+            };
+            self.client.map(move |cb| {
+                cb.next_key_complete(result, cursor, unhashed_key, ret_buf);
+            });
+        });
+
+        self.hashed_key.replace(key);
+    }
 }
@@ -49,6 +49,7 @@ enum Operation {
     Add,
     Update,
     GarbageCollect,
+    NextKey(usize),
 }
 
 pub struct VirtualKVPermissions<'a, V: kv::KVPermissions<'a>> {
@@ -235,6 +236,34 @@ impl<'a, V: kv::KVPermissions<'a>> kv::KVPermissions<'a> for VirtualKVPermission
         self.mux_kv.do_next_op(false)
     }
 
+    fn next_key(
+        &self,
+        cursor: usize,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+        permissions: StoragePermissions,
+    ) -> Result<
+        (),
+        (
+            SubSliceMut<'static, u8>,
+            SubSliceMut<'static, u8>,
+            ErrorCode,
+        ),
+    > {
+        if self.operation.is_some() {
+            return Err((key, value, ErrorCode::BUSY));
+        }
+
+        self.operation.set(Operation::NextKey(cursor));
+        self.valid_ids.set(permissions);
+        self.key.replace(key);
+        self.value.replace(value);
+
+        self.mux_kv
+            .do_next_op(false)
+            .map_err(|e| (self.key.take().unwrap(), self.value.take().unwrap(), e))
+    }
+
     fn header_size(&self) -> usize {
         self.mux_kv.kv.header_size()
     }
@@ -397,6 +426,29 @@ impl<'a, V: kv::KVPermissions<'a>> MuxKVPermissions<'a, V> {
                                 }
                             })
                     }
+                    Operation::NextKey(cursor) => node.value.take().map_or(Ok(()), |value| {
+                        node.valid_ids.map_or(Ok(()), |perms| {
+                            match self.kv.next_key(cursor, key, value, perms) {
+                                Ok(()) => {
+                                    self.inflight.set(node);
+                                    Ok(())
+                                }
+                                Err((key, value, e)) => {
+                                    node.operation.clear();
+                                    if async_op {
+                                        node.client.map(move |cb| {
+                                            cb.next_key_complete(Err(e), cursor, key, value);
+                                        });
+                                        Ok(())
+                                    } else {
+                                        node.key.replace(key);
+                                        node.value.replace(value);
+                                        Err(e)
+                                    }
+                                }
+                            }
+                        })
+                    }),
                     Operation::GarbageCollect => Err(ErrorCode::NOSUPPORT),
                 })
             })
@@ -490,4 +542,21 @@ impl<'a, V: kv::KVPermissions<'a>> kv::KVClient for MuxKVPermissions<'a, V> {
 
         let _ = self.do_next_op(true);
     }
+
+    fn next_key_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        cursor: usize,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.inflight.take().map(|node| {
+            node.operation.clear();
+            node.client.map(move |cb| {
+                cb.next_key_complete(result, cursor, key, value);
+            });
+        });
+
+        let _ = self.do_next_op(true);
+    }
 }
@@ -0,0 +1,519 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Crash dumps of faulted processes and kernel panics.
+//!
+//! `CrashDump` records the state of a process when it faults, and the panic
+//! message and kernel stack when the kernel panics, into a memory region that
+//! is kept across resets. Boards place the region in the `.crash_dump` linker
+//! section, which is neither loaded nor zeroed at boot, so the dumps can be
+//! inspected after the board restarted, with the `crashdump` process console
+//! command or by reading the region with a debugger. `tools/crash_dump`
+//! decodes the dumps on the host.
+//!
+//! Process faults are recorded by wrapping the board's fault policy in a
+//! [`CrashDumpFaultPolicy`]. Kernel panics are recorded by the panic handler
+//! calling [`CrashDump::record_panic()`] before printing the panic.
+//!
+//! Dumps are only added while there is room, so the first crashes after the
+//! dumps were cleared are kept.
+//!
+//! Format
+//! ------
+//!
+//! The region holds consecutive records, all fields little endian:
+//!
+//! ```text
+//! +--------+---------+------+----------+--------+--------+----------+
+//! | "TKCD" | version | kind | reserved | length | CRC-32 | sections |
+//! | 4      | 1       | 1    | 2        | 4      | 4      | length   |
+//! +--------+---------+------+----------+--------+--------+----------+
+//! ```
+//!
+//! `kind` is 1 for a process fault and 2 for a kernel panic. The CRC-32
+//! (IEEE) covers the sections. Each section is a tag byte, a reserved byte, a
+//! 16 bit length and the value, padded to a multiple of 4 bytes:
+//!
+//! | Tag | Section        | Value                                             |
+//! |-----|----------------|---------------------------------------------------|
+//! | 1   | Process name   | UTF-8 name of the process.                        |
+//! | 2   | Memory map     | `u32` flash start, flash end, RAM start, app      |
+//! |     |                | break, grant start, RAM end, stack top and lowest |
+//! |     |                | stack pointer (0 if unknown).                     |
+//! | 3   | Registers      | Architecture-specific stored state of the process.|
+//! | 4   | Overview       | Output of the board's `ProcessPrinter`.           |
+//! | 5   | Process stack  | `u32` address, then the stack from that address.  |
+//! | 6   | Process state  | Context, grants and MPU configuration as text.    |
+//! | 7   | Panic message  | Panic message and location as text.               |
+//! | 8   | Kernel stack   | `u32` address, then the stack from that address.  |
+//!
+//! Sections that do not fit in the remaining space are truncated.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! #[link_section = ".crash_dump"]
+//! static mut CRASH_DUMP_MEMORY: [u8; 0x2000] = [0; 0x2000];
+//!
+//! let crash_dump = static_init!(
+//!     capsules_system::crash_dump::CrashDump<'static>,
+//!     capsules_system::crash_dump::CrashDump::new(
+//!         &mut *addr_of_mut!(CRASH_DUMP_MEMORY),
+//!         process_printer,
+//!         512,
+//!     )
+//! );
+//! let fault_policy = static_init!(
+//!     capsules_system::crash_dump::CrashDumpFaultPolicy<'static, StopFaultPolicy>,
+//!     capsules_system::crash_dump::CrashDumpFaultPolicy::new(crash_dump, StopFaultPolicy {})
+//! );
+//! ```
+
+use core::cell::Cell;
+use core::fmt::{self, Write};
+
+use kernel::crash_dump::CrashDumpStore;
+use kernel::process::{Process, ProcessFaultPolicy, ProcessPrinter};
+use kernel::processbuffer::ReadableProcessBuffer;
+use kernel::utilities::binary_write::{BinaryWrite, WriteToBinaryOffsetWrapper};
+use kernel::utilities::cells::TakeCell;
+use kernel::ErrorCode;
+
+/// First bytes of every record.
+const MAGIC: [u8; 4] = *b"TKCD";
+/// Version of the record format.
+const VERSION: u8 = 1;
+/// Length of the record header.
+const HEADER_LEN: usize = 16;
+/// Length of the section header.
+const SECTION_HEADER_LEN: usize = 4;
+
+const KIND_PROCESS_FAULT: u8 = 1;
+const KIND_KERNEL_PANIC: u8 = 2;
+
+const TAG_PROCESS_NAME: u8 = 1;
+const TAG_MEMORY_MAP: u8 = 2;
+const TAG_REGISTERS: u8 = 3;
+const TAG_OVERVIEW: u8 = 4;
+const TAG_PROCESS_STACK: u8 = 5;
+const TAG_PROCESS_STATE: u8 = 6;
+const TAG_PANIC_MESSAGE: u8 = 7;
+const TAG_KERNEL_STACK: u8 = 8;
+
+/// Length of the description of a dump, so it fits on a line.
+const DESCRIPTION_LEN: usize = 60;
+/// Number of bytes of a dump printed per line.
+const PRINT_LINE_LEN: usize = 32;
+
+/// Continue the CRC-32 (IEEE 802.3) `crc` of some data with `data`. The
+/// CRC-32 of the empty message is 0, so `crc32_update(0, data)` is the CRC-32
+/// of `data`.
+pub(crate) fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&buf[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+/// Writer that fills a slice and silently drops what does not fit.
+struct SliceWriter<'b> {
+    buf: &'b mut [u8],
+    len: usize,
+}
+
+impl<'b> SliceWriter<'b> {
+    fn new(buf: &'b mut [u8]) -> Self {
+        SliceWriter { buf, len: 0 }
+    }
+
+    fn push(&mut self, data: &[u8]) -> usize {
+        let count = core::cmp::min(data.len(), self.buf.len() - self.len);
+        self.buf[self.len..self.len + count].copy_from_slice(&data[..count]);
+        self.len += count;
+        count
+    }
+}
+
+impl Write for SliceWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push(s.as_bytes());
+        Ok(())
+    }
+}
+
+impl BinaryWrite for SliceWriter<'_> {
+    fn write_buffer(&mut self, buffer: &[u8]) -> Result<usize, ()> {
+        Ok(self.push(buffer))
+    }
+}
+
+/// Builds a record at the start of a slice.
+struct RecordWriter<'b> {
+    buf: &'b mut [u8],
+    len: usize,
+}
+
+impl<'b> RecordWriter<'b> {
+    /// Start a record in `buf`, or return `None` if not even an empty record
+    /// fits.
+    fn new(buf: &'b mut [u8]) -> Option<Self> {
+        if buf.len() < HEADER_LEN {
+            None
+        } else {
+            Some(RecordWriter {
+                buf,
+                len: HEADER_LEN,
+            })
+        }
+    }
+
+    /// Add a section with `tag`. `fill` gets the space available for the
+    /// value and returns how much of it was used.
+    fn section<F: FnOnce(&mut [u8]) -> usize>(&mut self, tag: u8, fill: F) {
+        let start = self.len;
+        if start + SECTION_HEADER_LEN > self.buf.len() {
+            return;
+        }
+        let value_start = start + SECTION_HEADER_LEN;
+        let max = core::cmp::min(self.buf.len() - value_start, u16::MAX as usize) & !3;
+        let used = core::cmp::min(fill(&mut self.buf[value_start..value_start + max]), max);
+        self.buf[start] = tag;
+        self.buf[start + 1] = 0;
+        self.buf[start + 2..start + 4].copy_from_slice(&(used as u16).to_le_bytes());
+        let padded = (used + 3) & !3;
+        self.buf[value_start + used..value_start + padded].fill(0);
+        self.len = value_start + padded;
+    }
+
+    /// Add a section holding `data` at `address`.
+    fn memory_section(&mut self, tag: u8, address: usize, data: &[u8]) {
+        self.section(tag, |buf| {
+            if buf.len() < 4 {
+                return 0;
+            }
+            buf[..4].copy_from_slice(&(address as u32).to_le_bytes());
+            let len = core::cmp::min(data.len(), buf.len() - 4);
+            buf[4..4 + len].copy_from_slice(&data[..len]);
+            4 + len
+        });
+    }
+
+    /// Add a section with text.
+    fn text_section(&mut self, tag: u8, args: fmt::Arguments) {
+        self.section(tag, |buf| {
+            let mut writer = SliceWriter::new(buf);
+            let _ = writer.write_fmt(args);
+            writer.len
+        });
+    }
+
+    /// Write the header and return the length of the record.
+    fn finish(self, kind: u8) -> usize {
+        let length = self.len - HEADER_LEN;
+        let crc = crc32_update(0, &self.buf[HEADER_LEN..self.len]);
+        self.buf[0..4].copy_from_slice(&MAGIC);
+        self.buf[4] = VERSION;
+        self.buf[5] = kind;
+        self.buf[6..8].fill(0);
+        self.buf[8..12].copy_from_slice(&(length as u32).to_le_bytes());
+        self.buf[12..16].copy_from_slice(&crc.to_le_bytes());
+        self.len
+    }
+}
+
+/// Return the kind and length of the valid record at `offset`, if there is
+/// one.
+fn record_at(region: &[u8], offset: usize) -> Option<(u8, usize)> {
+    let header = region.get(offset..offset + HEADER_LEN)?;
+    if header[0..4] != MAGIC || header[4] != VERSION {
+        return None;
+    }
+    let kind = header[5];
+    if kind != KIND_PROCESS_FAULT && kind != KIND_KERNEL_PANIC {
+        return None;
+    }
+    let length = read_u32(header, 8) as usize;
+    let sections = region.get(offset + HEADER_LEN..(offset + HEADER_LEN).checked_add(length)?)?;
+    if crc32_update(0, sections) != read_u32(header, 12) {
+        return None;
+    }
+    Some((kind, HEADER_LEN + length))
+}
+
+/// Recorder and store of crash dumps.
+pub struct CrashDump<'a> {
+    region: TakeCell<'static, [u8]>,
+    process_printer: &'a dyn ProcessPrinter,
+    /// How much of a stack to include in a dump.
+    stack_len: usize,
+    /// Bytes of the region used by the stored dumps.
+    used: Cell<usize>,
+}
+
+impl<'a> CrashDump<'a> {
+    /// Keep dumps in `region`, including up to `stack_len` bytes of the stack
+    /// of the process or kernel. Dumps that are already in `region` are kept.
+    pub fn new(
+        region: &'static mut [u8],
+        process_printer: &'a dyn ProcessPrinter,
+        stack_len: usize,
+    ) -> Self {
+        // Find the end of the dumps that survived the reset. On a cold boot,
+        // the region holds random data and no valid record.
+        let mut used = 0;
+        while let Some((_, len)) = record_at(region, used) {
+            used += len;
+        }
+        Self::terminate(region, used);
+        CrashDump {
+            region: TakeCell::new(region),
+            process_printer,
+            stack_len,
+            used: Cell::new(used),
+        }
+    }
+
+    /// Make sure that records after `used`, left over from before the dumps
+    /// were cleared, are not mistaken for dumps after the next reset.
+    fn terminate(region: &mut [u8], used: usize) {
+        let end = core::cmp::min(used + MAGIC.len(), region.len());
+        region[used..end].fill(0);
+    }
+
+    /// Find the offset, kind and length of dump `index`.
+    fn find(region: &[u8], used: usize, index: usize) -> Option<(usize, u8, usize)> {
+        let mut offset = 0;
+        for _ in 0..index {
+            let (_, len) = record_at(region, offset)?;
+            offset += len;
+        }
+        if offset >= used {
+            return None;
+        }
+        record_at(region, offset).map(|(kind, len)| (offset, kind, len))
+    }
+
+    /// Add a record of `kind`, with the sections written by `write`.
+    fn record<F: FnOnce(&mut RecordWriter)>(&self, kind: u8, write: F) -> Result<(), ErrorCode> {
+        // If the region is taken, this is a panic while recording a dump.
+        self.region.map_or(Err(ErrorCode::BUSY), |region| {
+            let used = self.used.get();
+            let mut record = RecordWriter::new(&mut region[used..]).ok_or(ErrorCode::NOMEM)?;
+            write(&mut record);
+            let len = record.finish(kind);
+            self.used.set(used + len);
+            Self::terminate(region, used + len);
+            Ok(())
+        })
+    }
+
+    /// Write what `record` is about: the faulted process or the first line
+    /// of the panic message.
+    fn describe(record: &[u8], kind: u8, writer: &mut dyn Write) {
+        let (label, tag) = if kind == KIND_KERNEL_PANIC {
+            ("kernel panic", TAG_PANIC_MESSAGE)
+        } else {
+            ("process fault", TAG_PROCESS_NAME)
+        };
+        let _ = writer.write_str(label);
+
+        let mut section = HEADER_LEN;
+        while section + SECTION_HEADER_LEN <= record.len() {
+            let value_len = u16::from_le_bytes([record[section + 2], record[section + 3]]) as usize;
+            let value_start = section + SECTION_HEADER_LEN;
+            if record[section] == tag {
+                let value = &record[value_start..value_start + value_len];
+                // Only show the first line, shortened to fit the console.
+                let line = value
+                    .split(|&b| b == b'\n' || b == b'\r')
+                    .next()
+                    .unwrap_or(&[]);
+                let line = &line[..core::cmp::min(line.len(), DESCRIPTION_LEN)];
+                let text = match core::str::from_utf8(line) {
+                    Ok(text) => text,
+                    Err(e) => core::str::from_utf8(&line[..e.valid_up_to()]).unwrap_or(""),
+                };
+                let _ = writer.write_fmt(format_args!(": {}", text));
+                break;
+            }
+            section = value_start + ((value_len + 3) & !3);
+        }
+    }
+
+    /// Record the state of `process`, which has faulted.
+    pub fn record_process_fault(&self, process: &dyn Process) -> Result<(), ErrorCode> {
+        self.record(KIND_PROCESS_FAULT, |record| {
+            record.section(TAG_PROCESS_NAME, |buf| {
+                SliceWriter::new(buf).push(process.get_process_name().as_bytes())
+            });
+
+            let addresses = process.get_addresses();
+            record.section(TAG_MEMORY_MAP, |buf| {
+                let mut writer = SliceWriter::new(buf);
+                for address in [
+                    addresses.flash_start,
+                    addresses.flash_end,
+                    addresses.sram_start,
+                    addresses.sram_app_brk,
+                    addresses.sram_grant_start,
+                    addresses.sram_end,
+                    addresses.sram_stack_top.unwrap_or(0),
+                    addresses.sram_stack_bottom.unwrap_or(0),
+                ] {
+                    writer.push(&(address as u32).to_le_bytes());
+                }
+                writer.len
+            });
+
+            record.section(TAG_REGISTERS, |buf| {
+                process.get_stored_state(buf).unwrap_or(0)
+            });
+
+            record.section(TAG_OVERVIEW, |buf| {
+                let mut writer = SliceWriter::new(buf);
+                let _ = self
+                    .process_printer
+                    .print_overview(process, &mut writer, None);
+                writer.len
+            });
+
+            // The stack from the lowest stack pointer the process has used.
+            if let (Some(top), Some(bottom)) =
+                (addresses.sram_stack_top, addresses.sram_stack_bottom)
+            {
+                let len = core::cmp::min(top.saturating_sub(bottom), self.stack_len);
+                if let Ok(stack) = process.build_readonly_process_buffer(bottom as *const u8, len) {
+                    let _ = stack.enter(|stack| {
+                        record.section(TAG_PROCESS_STACK, |buf| {
+                            if buf.len() < 4 {
+                                return 0;
+                            }
+                            buf[..4].copy_from_slice(&(bottom as u32).to_le_bytes());
+                            let len = core::cmp::min(stack.len(), buf.len() - 4);
+                            match stack.get(..len) {
+                                Some(stack) => {
+                                    stack.copy_to_slice(&mut buf[4..4 + len]);
+                                    4 + len
+                                }
+                                None => 0,
+                            }
+                        });
+                    });
+                }
+            }
+
+            record.section(TAG_PROCESS_STATE, |buf| {
+                let mut writer = SliceWriter::new(buf);
+                process.print_full_process(&mut writer);
+                writer.len
+            });
+        })
+    }
+
+    /// Record a kernel panic. `kernel_stack` is the part of the kernel stack
+    /// to include, starting at the current stack pointer.
+    ///
+    /// This is meant to be called by the panic handler before it prints the
+    /// panic.
+    pub fn record_panic(
+        &self,
+        panic_info: &core::panic::PanicInfo,
+        kernel_stack: &[u8],
+    ) -> Result<(), ErrorCode> {
+        self.record(KIND_KERNEL_PANIC, |record| {
+            record.text_section(TAG_PANIC_MESSAGE, format_args!("{}", panic_info));
+            let len = core::cmp::min(kernel_stack.len(), self.stack_len);
+            record.memory_section(
+                TAG_KERNEL_STACK,
+                kernel_stack.as_ptr() as usize,
+                &kernel_stack[..len],
+            );
+        })
+    }
+}
+
+impl CrashDumpStore for CrashDump<'_> {
+    fn count(&self) -> usize {
+        self.region.map_or(0, |region| {
+            let mut count = 0;
+            while Self::find(region, self.used.get(), count).is_some() {
+                count += 1;
+            }
+            count
+        })
+    }
+
+    fn print(&self, writer: &mut dyn BinaryWrite, offset: usize) -> Option<usize> {
+        let mut bww = WriteToBinaryOffsetWrapper::new(writer);
+        bww.set_offset(offset);
+        self.region.map(|region| {
+            let mut index = 0;
+            while let Some((start, kind, len)) = Self::find(region, self.used.get(), index) {
+                let record = &region[start..start + len];
+                let _ = bww.write_fmt(format_args!("Crash dump {}: ", index));
+                Self::describe(record, kind, &mut bww);
+                let _ = bww.write_fmt(format_args!(" ({} bytes)\r\n", len));
+                for (line, data) in record.chunks(PRINT_LINE_LEN).enumerate() {
+                    if bww.bytes_remaining() {
+                        return;
+                    }
+                    let _ = bww.write_fmt(format_args!("{:04x}: ", line * PRINT_LINE_LEN));
+                    for byte in data {
+                        let _ = bww.write_fmt(format_args!("{:02x}", byte));
+                    }
+                    let _ = bww.write_str("\r\n");
+                }
+                index += 1;
+            }
+            if index == 0 {
+                let _ = bww.write_str("No crash dumps.\r\n");
+            }
+        });
+        if bww.bytes_remaining() {
+            Some(bww.get_index())
+        } else {
+            None
+        }
+    }
+
+    fn clear(&self) {
+        self.region.map(|region| Self::terminate(region, 0));
+        self.used.set(0);
+    }
+}
+
+/// Fault policy that records a crash dump of the faulted process and then
+/// takes the action of another fault policy.
+pub struct CrashDumpFaultPolicy<'a, P: ProcessFaultPolicy> {
+    crash_dump: &'a CrashDump<'a>,
+    policy: P,
+}
+
+impl<'a, P: ProcessFaultPolicy> CrashDumpFaultPolicy<'a, P> {
+    pub fn new(crash_dump: &'a CrashDump<'a>, policy: P) -> Self {
+        CrashDumpFaultPolicy { crash_dump, policy }
+    }
+}
+
+impl<P: ProcessFaultPolicy> ProcessFaultPolicy for CrashDumpFaultPolicy<'_, P> {
+    fn action(&self, process: &dyn Process) -> kernel::process::FaultAction {
+        let _ = self.crash_dump.record_process_fault(process);
+        self.policy.action(process)
+    }
+}
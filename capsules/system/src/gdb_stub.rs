@@ -0,0 +1,1272 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! GDB remote serial protocol stub for debugging processes.
+//!
+//! `GdbStub` lets GDB debug one process over a UART, while the kernel and the
+//! other processes keep running. GDB can stop and resume the process, read and
+//! write its registers and memory, and set breakpoints.
+//!
+//! - Memory accesses are limited to the memory the process itself can access:
+//!   its RAM up to the app break can be read and written, its flash can be
+//!   read.
+//! - Breakpoints are set by replacing the instruction with a breakpoint
+//!   instruction, so they only work for code in RAM. When the process hits
+//!   one, the fault is caught and the process is stopped instead of running
+//!   the board's fault policy. The same happens for all other faults of the
+//!   process while GDB is attached, so that GDB can inspect it.
+//! - The hardware cannot single-step a process, so a step runs the process to
+//!   its next system call other than yield or memop, and stops it there.
+//!
+//! To catch faults and system calls, the stub is the board's
+//! `KernelResources::ProcessFault` and `KernelResources::SyscallFilter`.
+//! Boards with another system call filter can call
+//! [`GdbStub::filter_syscall()`] from theirs.
+//!
+//! GDB sees a single process. `target remote` attaches to the first running
+//! process. With `target extended-remote`, `attach <pid>` attaches to another
+//! process and `monitor ps` lists the processes with their pids and addresses,
+//! which is needed to load the symbols of position independent apps with
+//! `add-symbol-file`:
+//!
+//! ```text
+//! (gdb) target extended-remote /dev/ttyACM1
+//! (gdb) monitor ps
+//! pid name       state      flash      ram
+//! 1   blink      Yielded    0x00040000 0x20008000
+//! 2   c_hello    Yielded    0x00042000 0x2000a000
+//! (gdb) attach 2
+//! ```
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let gdb_stub = components::gdb_stub::GdbStubComponent::new(board_kernel, uart_mux)
+//!     .finalize(components::gdb_stub_component_static!());
+//! let _ = gdb_stub.start();
+//!
+//! impl KernelResources<Chip> for Platform {
+//!     type SyscallFilter = GdbStub<'static, UartDevice<'static>, components::gdb_stub::Capability>;
+//!     type ProcessFault = GdbStub<'static, UartDevice<'static>, components::gdb_stub::Capability>;
+//!     fn syscall_filter(&self) -> &Self::SyscallFilter {
+//!         self.gdb_stub
+//!     }
+//!     fn process_fault(&self) -> &Self::ProcessFault {
+//!         self.gdb_stub
+//!     }
+//!     // ...
+//! }
+//! ```
+
+use core::cell::Cell;
+use core::fmt::{self, Write};
+
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::hil::uart;
+use kernel::platform::{ProcessFault, SyscallFilter};
+use kernel::process::{Process, ProcessId, State};
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::Syscall;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, Kernel};
+
+/// Length of the buffer for received packets, which is the packet size
+/// announced to GDB.
+pub const PACKET_BUF_LEN: usize = 512;
+/// Length of the transmit buffer: a reply packet with its framing and an
+/// acknowledgement.
+pub const TX_BUF_LEN: usize = PACKET_BUF_LEN + 8;
+/// Length of the receive buffer. Bytes are received one at a time.
+pub const RX_BUF_LEN: usize = 1;
+
+/// Maximum number of breakpoints.
+const MAX_BREAKPOINTS: usize = 8;
+/// Maximum length of the stored state of a process.
+const STORED_STATE_LEN: usize = 160;
+
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+// Word offsets into the stored state, see `store_context()` of the
+// architectures.
+const CORTEX_M_PSP: usize = 5;
+const CORTEX_M_R4: usize = 6;
+const RISCV_PC: usize = 3;
+const RISCV_X1: usize = 6;
+
+/// Size of the registers the Cortex-M hardware stacks on exception entry: r0-r3,
+/// r12, lr, pc and xpsr.
+const CORTEX_M_FRAME_LEN: usize = 32;
+
+const CORTEX_M_TARGET_XML: &str = concat!(
+    "<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\">",
+    "<target><architecture>arm</architecture>",
+    "<feature name=\"org.gnu.gdb.arm.m-profile\">",
+    "<reg name=\"r0\" bitsize=\"32\"/><reg name=\"r1\" bitsize=\"32\"/>",
+    "<reg name=\"r2\" bitsize=\"32\"/><reg name=\"r3\" bitsize=\"32\"/>",
+    "<reg name=\"r4\" bitsize=\"32\"/><reg name=\"r5\" bitsize=\"32\"/>",
+    "<reg name=\"r6\" bitsize=\"32\"/><reg name=\"r7\" bitsize=\"32\"/>",
+    "<reg name=\"r8\" bitsize=\"32\"/><reg name=\"r9\" bitsize=\"32\"/>",
+    "<reg name=\"r10\" bitsize=\"32\"/><reg name=\"r11\" bitsize=\"32\"/>",
+    "<reg name=\"r12\" bitsize=\"32\"/>",
+    "<reg name=\"sp\" bitsize=\"32\" type=\"data_ptr\"/>",
+    "<reg name=\"lr\" bitsize=\"32\"/>",
+    "<reg name=\"pc\" bitsize=\"32\" type=\"code_ptr\"/>",
+    "<reg name=\"xpsr\" bitsize=\"32\"/>",
+    "</feature></target>",
+);
+
+const RISCV_TARGET_XML: &str = concat!(
+    "<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\">",
+    "<target><architecture>riscv:rv32</architecture>",
+    "<feature name=\"org.gnu.gdb.riscv.cpu\">",
+    "<reg name=\"zero\" bitsize=\"32\"/>",
+    "<reg name=\"ra\" bitsize=\"32\" type=\"code_ptr\"/>",
+    "<reg name=\"sp\" bitsize=\"32\" type=\"data_ptr\"/>",
+    "<reg name=\"gp\" bitsize=\"32\" type=\"data_ptr\"/>",
+    "<reg name=\"tp\" bitsize=\"32\" type=\"data_ptr\"/>",
+    "<reg name=\"t0\" bitsize=\"32\"/><reg name=\"t1\" bitsize=\"32\"/>",
+    "<reg name=\"t2\" bitsize=\"32\"/><reg name=\"fp\" bitsize=\"32\"/>",
+    "<reg name=\"s1\" bitsize=\"32\"/><reg name=\"a0\" bitsize=\"32\"/>",
+    "<reg name=\"a1\" bitsize=\"32\"/><reg name=\"a2\" bitsize=\"32\"/>",
+    "<reg name=\"a3\" bitsize=\"32\"/><reg name=\"a4\" bitsize=\"32\"/>",
+    "<reg name=\"a5\" bitsize=\"32\"/><reg name=\"a6\" bitsize=\"32\"/>",
+    "<reg name=\"a7\" bitsize=\"32\"/><reg name=\"s2\" bitsize=\"32\"/>",
+    "<reg name=\"s3\" bitsize=\"32\"/><reg name=\"s4\" bitsize=\"32\"/>",
+    "<reg name=\"s5\" bitsize=\"32\"/><reg name=\"s6\" bitsize=\"32\"/>",
+    "<reg name=\"s7\" bitsize=\"32\"/><reg name=\"s8\" bitsize=\"32\"/>",
+    "<reg name=\"s9\" bitsize=\"32\"/><reg name=\"s10\" bitsize=\"32\"/>",
+    "<reg name=\"s11\" bitsize=\"32\"/><reg name=\"t3\" bitsize=\"32\"/>",
+    "<reg name=\"t4\" bitsize=\"32\"/><reg name=\"t5\" bitsize=\"32\"/>",
+    "<reg name=\"t6\" bitsize=\"32\"/>",
+    "<reg name=\"pc\" bitsize=\"32\" type=\"code_ptr\"/>",
+    "</feature></target>",
+);
+
+/// Architecture of a process, from the tag of its stored state.
+#[derive(Clone, Copy, PartialEq)]
+enum Arch {
+    CortexM,
+    RiscV,
+}
+
+impl Arch {
+    fn from_stored_state(state: &[u8]) -> Option<Arch> {
+        match state.get(8..12) {
+            Some(b"ctxm") => Some(Arch::CortexM),
+            Some(b"rv5i") => Some(Arch::RiscV),
+            _ => None,
+        }
+    }
+
+    /// Number of registers in the target description.
+    fn register_count(self) -> usize {
+        match self {
+            Arch::CortexM => 17,
+            Arch::RiscV => 33,
+        }
+    }
+
+    /// GDB number of the program counter.
+    fn pc(self) -> usize {
+        match self {
+            Arch::CortexM => 15,
+            Arch::RiscV => 32,
+        }
+    }
+
+    fn target_xml(self) -> &'static str {
+        match self {
+            Arch::CortexM => CORTEX_M_TARGET_XML,
+            Arch::RiscV => RISCV_TARGET_XML,
+        }
+    }
+
+    /// Breakpoint instruction for a GDB breakpoint `kind`, which is the length
+    /// of the instruction it replaces (3 for 32-bit Thumb-2 instructions).
+    fn breakpoint(self, kind: usize) -> Option<&'static [u8]> {
+        match (self, kind) {
+            // bkpt
+            (Arch::CortexM, 2) => Some(&[0x00, 0xbe]),
+            (Arch::CortexM, 3) => Some(&[0x00, 0xbe, 0x00, 0xbe]),
+            // c.ebreak and ebreak
+            (Arch::RiscV, 2) => Some(&[0x02, 0x90]),
+            (Arch::RiscV, 4) => Some(&[0x73, 0x00, 0x10, 0x00]),
+            _ => None,
+        }
+    }
+}
+
+/// The registers of a process, in GDB's numbering.
+struct Registers {
+    arch: Arch,
+    state: [u8; STORED_STATE_LEN],
+    state_len: usize,
+    /// On Cortex-M, the registers stacked by the hardware at the process
+    /// stack pointer: r0-r3, r12, lr, pc and xpsr.
+    frame: [u32; 8],
+}
+
+impl Registers {
+    fn read(process: &dyn Process) -> Result<Registers, ErrorCode> {
+        let mut state = [0; STORED_STATE_LEN];
+        let state_len = process.get_stored_state(&mut state)?;
+        let arch = Arch::from_stored_state(&state).ok_or(ErrorCode::NOSUPPORT)?;
+        let mut registers = Registers {
+            arch,
+            state,
+            state_len,
+            frame: [0; 8],
+        };
+        if arch == Arch::CortexM {
+            let mut frame = [0; CORTEX_M_FRAME_LEN];
+            read_memory(process, registers.word(CORTEX_M_PSP) as usize, &mut frame)?;
+            for (register, bytes) in registers.frame.iter_mut().zip(frame.chunks_exact(4)) {
+                *register = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            }
+        }
+        Ok(registers)
+    }
+
+    fn word(&self, index: usize) -> u32 {
+        let bytes = &self.state[index * 4..index * 4 + 4];
+        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+
+    fn set_word(&mut self, index: usize, value: u32) {
+        self.state[index * 4..index * 4 + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn get(&self, number: usize) -> Option<u32> {
+        match (self.arch, number) {
+            (Arch::CortexM, 0..=3) => Some(self.frame[number]),
+            (Arch::CortexM, 4..=11) => Some(self.word(CORTEX_M_R4 + number - 4)),
+            (Arch::CortexM, 12) => Some(self.frame[4]),
+            // The stack pointer before the frame was stacked, which includes
+            // padding for alignment if bit 9 of the stacked xpsr is set.
+            (Arch::CortexM, 13) => {
+                let padding = (self.frame[7] >> 9) & 1;
+                Some(self.word(CORTEX_M_PSP) + CORTEX_M_FRAME_LEN as u32 + 4 * padding)
+            }
+            (Arch::CortexM, 14..=16) => Some(self.frame[number - 9]),
+            (Arch::RiscV, 0) => Some(0),
+            (Arch::RiscV, 1..=31) => Some(self.word(RISCV_X1 + number - 1)),
+            (Arch::RiscV, 32) => Some(self.word(RISCV_PC)),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, number: usize, value: u32) -> Result<(), ErrorCode> {
+        match (self.arch, number) {
+            (Arch::CortexM, 0..=3) => self.frame[number] = value,
+            (Arch::CortexM, 4..=11) => self.set_word(CORTEX_M_R4 + number - 4, value),
+            (Arch::CortexM, 12) => self.frame[4] = value,
+            // Move the frame below the new stack pointer, without padding.
+            (Arch::CortexM, 13) => {
+                self.set_word(CORTEX_M_PSP, value.wrapping_sub(CORTEX_M_FRAME_LEN as u32));
+                self.frame[7] &= !(1 << 9);
+            }
+            (Arch::CortexM, 14..=16) => self.frame[number - 9] = value,
+            (Arch::RiscV, 0) => {}
+            (Arch::RiscV, 1..=31) => self.set_word(RISCV_X1 + number - 1, value),
+            (Arch::RiscV, 32) => self.set_word(RISCV_PC, value),
+            _ => return Err(ErrorCode::INVAL),
+        }
+        Ok(())
+    }
+
+    fn write(&self, process: &dyn Process) -> Result<(), ErrorCode> {
+        process.set_stored_state(&self.state[..self.state_len])?;
+        if self.arch == Arch::CortexM {
+            let mut frame = [0; CORTEX_M_FRAME_LEN];
+            for (bytes, register) in frame.chunks_exact_mut(4).zip(self.frame.iter()) {
+                bytes.copy_from_slice(&register.to_le_bytes());
+            }
+            write_memory(process, self.word(CORTEX_M_PSP) as usize, &frame)?;
+        }
+        Ok(())
+    }
+}
+
+/// Copies process memory at `address` to `out`, if the process can read it.
+fn read_memory(process: &dyn Process, address: usize, out: &mut [u8]) -> Result<(), ErrorCode> {
+    process
+        .build_readonly_process_buffer(address as *const u8, out.len())?
+        .enter(|memory| memory.copy_to_slice_or_err(out))?
+}
+
+/// Copies `data` to process memory at `address`, if the process can write it.
+fn write_memory(process: &dyn Process, address: usize, data: &[u8]) -> Result<(), ErrorCode> {
+    process
+        .build_readwrite_process_buffer(address as *mut u8, data.len())?
+        .mut_enter(|memory| memory.copy_from_slice_or_err(data))?
+}
+
+/// A breakpoint instruction in process memory and the bytes it replaced.
+#[derive(Clone, Copy)]
+struct Breakpoint {
+    address: usize,
+    len: usize,
+    saved: [u8; 4],
+}
+
+/// How the process stopped, reported to GDB.
+#[derive(Clone, Copy)]
+enum Stop {
+    Signal(u8),
+    Exited(u8),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum RxState {
+    Idle,
+    Packet,
+    Checksum,
+    ChecksumLow(u8),
+}
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+fn hex_digit(byte: u8) -> Option<u8> {
+    (byte as char).to_digit(16).map(|digit| digit as u8)
+}
+
+fn parse_hex(text: &[u8]) -> Option<usize> {
+    if text.is_empty() || text.len() > 2 * core::mem::size_of::<usize>() {
+        return None;
+    }
+    text.iter().try_fold(0, |value, byte| {
+        Some(value << 4 | hex_digit(*byte)? as usize)
+    })
+}
+
+/// Decodes hex `text` into `out`, returning the number of bytes.
+fn decode_hex(text: &[u8], out: &mut [u8]) -> Option<usize> {
+    if text.len() % 2 != 0 || text.len() / 2 > out.len() {
+        return None;
+    }
+    for (byte, pair) in out.iter_mut().zip(text.chunks_exact(2)) {
+        *byte = hex_digit(pair[0])? << 4 | hex_digit(pair[1])?;
+    }
+    Some(text.len() / 2)
+}
+
+/// Splits `text` at the first `separator`.
+fn split(text: &[u8], separator: u8) -> (&[u8], &[u8]) {
+    match text.iter().position(|byte| *byte == separator) {
+        Some(index) => (&text[..index], &text[index + 1..]),
+        None => (text, &[]),
+    }
+}
+
+/// Parses an `address,length` pair.
+fn parse_range(text: &[u8]) -> Option<(usize, usize)> {
+    let (address, length) = split(text, b',');
+    Some((parse_hex(address)?, parse_hex(length)?))
+}
+
+/// A reply being built in the transmit buffer: an optional acknowledgement
+/// followed by a packet.
+struct Reply {
+    buffer: &'static mut [u8],
+    /// Length of the acknowledgement, where the packet starts.
+    packet_start: usize,
+    len: usize,
+    checksum: u8,
+}
+
+impl Reply {
+    fn new(buffer: &'static mut [u8], ack: bool) -> Reply {
+        let mut packet_start = 0;
+        if ack {
+            buffer[0] = b'+';
+            packet_start = 1;
+        }
+        buffer[packet_start] = b'$';
+        Reply {
+            buffer,
+            packet_start,
+            len: packet_start + 1,
+            checksum: 0,
+        }
+    }
+
+    /// Room left for packet data.
+    fn room(&self) -> usize {
+        // Keep room for the checksum.
+        self.buffer.len().saturating_sub(self.len + 3)
+    }
+
+    fn push(&mut self, byte: u8) {
+        if self.room() > 0 {
+            self.buffer[self.len] = byte;
+            self.len += 1;
+            self.checksum = self.checksum.wrapping_add(byte);
+        }
+    }
+
+    fn push_str(&mut self, text: &str) {
+        text.bytes().for_each(|byte| self.push(byte));
+    }
+
+    fn push_hex(&mut self, byte: u8) {
+        self.push(HEX_DIGITS[(byte >> 4) as usize]);
+        self.push(HEX_DIGITS[(byte & 0xf) as usize]);
+    }
+
+    fn error(&mut self, error: ErrorCode) {
+        self.push(b'E');
+        self.push_hex(usize::from(error) as u8);
+    }
+
+    fn result(&mut self, result: Result<(), ErrorCode>) {
+        match result {
+            Ok(()) => self.push_str("OK"),
+            Err(error) => self.error(error),
+        }
+    }
+
+    fn stop(&mut self, stop: Stop) {
+        let (kind, value) = match stop {
+            Stop::Signal(signal) => (b'S', signal),
+            Stop::Exited(code) => (b'W', code),
+        };
+        self.push(kind);
+        self.push_hex(value);
+    }
+
+    /// Ends the packet, or drops it if `send` is false. Returns the bytes to
+    /// transmit.
+    fn finish(&mut self, send: bool) -> usize {
+        if !send {
+            return self.packet_start;
+        }
+        // `push()` keeps room for the checksum.
+        let checksum = self.checksum;
+        self.buffer[self.len] = b'#';
+        self.len += 1;
+        for digit in [checksum >> 4, checksum & 0xf] {
+            self.buffer[self.len] = HEX_DIGITS[digit as usize];
+            self.len += 1;
+        }
+        self.len
+    }
+}
+
+/// Writes text into a reply as hex, for console output.
+struct HexWriter<'r>(&'r mut Reply);
+
+impl Write for HexWriter<'_> {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        text.bytes().for_each(|byte| self.0.push_hex(byte));
+        Ok(())
+    }
+}
+
+pub struct GdbStub<'a, U: uart::UartData<'a>, C: ProcessManagementCapability> {
+    uart: &'a U,
+    kernel: &'static Kernel,
+    capability: C,
+    rx_buffer: TakeCell<'static, [u8]>,
+    tx_buffer: TakeCell<'static, [u8]>,
+    packet: TakeCell<'static, [u8]>,
+    packet_len: Cell<usize>,
+    /// Whether the packet did not fit in the buffer.
+    packet_overflow: Cell<bool>,
+    rx_state: Cell<RxState>,
+    checksum: Cell<u8>,
+    /// A received packet waiting for the transmitter, to be answered.
+    packet_pending: Cell<bool>,
+    /// A stop waiting for the transmitter, to be reported.
+    stop_pending: OptionalCell<Stop>,
+    /// Bytes of the last packet sent, to resend it if GDB did not get it.
+    last_packet: Cell<(usize, usize)>,
+    no_ack: Cell<bool>,
+    /// The process being debugged.
+    process: OptionalCell<ProcessId>,
+    /// Whether the process runs for GDB, which waits for it to stop.
+    running: Cell<bool>,
+    /// Whether to stop the process at its next system call.
+    stepping: Cell<bool>,
+    breakpoints: [Cell<Option<Breakpoint>>; MAX_BREAKPOINTS],
+}
+
+impl<'a, U: uart::UartData<'a>, C: ProcessManagementCapability> GdbStub<'a, U, C> {
+    pub fn new(
+        uart: &'a U,
+        kernel: &'static Kernel,
+        capability: C,
+        rx_buffer: &'static mut [u8],
+        tx_buffer: &'static mut [u8],
+        packet_buffer: &'static mut [u8],
+    ) -> Self {
+        GdbStub {
+            uart,
+            kernel,
+            capability,
+            rx_buffer: TakeCell::new(rx_buffer),
+            tx_buffer: TakeCell::new(tx_buffer),
+            packet: TakeCell::new(packet_buffer),
+            packet_len: Cell::new(0),
+            packet_overflow: Cell::new(false),
+            rx_state: Cell::new(RxState::Idle),
+            checksum: Cell::new(0),
+            packet_pending: Cell::new(false),
+            stop_pending: OptionalCell::empty(),
+            last_packet: Cell::new((0, 0)),
+            no_ack: Cell::new(false),
+            process: OptionalCell::empty(),
+            running: Cell::new(false),
+            stepping: Cell::new(false),
+            breakpoints: Default::default(),
+        }
+    }
+
+    /// Start listening for GDB.
+    pub fn start(&self) -> Result<(), ErrorCode> {
+        self.rx_buffer
+            .take()
+            .map_or(Err(ErrorCode::ALREADY), |buffer| {
+                self.uart
+                    .receive_buffer(buffer, RX_BUF_LEN)
+                    .map_err(|(error, buffer)| {
+                        self.rx_buffer.replace(buffer);
+                        error
+                    })
+            })
+    }
+
+    fn with_process<R>(&self, default: R, closure: impl FnOnce(&dyn Process) -> R) -> R {
+        match self.process.get() {
+            Some(processid) => {
+                self.kernel
+                    .process_map_or_external(default, processid, closure, &self.capability)
+            }
+            None => default,
+        }
+    }
+
+    /// Stops `process` and debugs it instead of the current process.
+    fn attach(&self, process: &dyn Process) {
+        self.detach();
+        process.stop();
+        self.process.set(process.processid());
+    }
+
+    /// Debugs the first running process if no process is debugged.
+    fn attach_default(&self) {
+        if self.process.is_some() {
+            return;
+        }
+        let mut first = None;
+        self.kernel
+            .process_each_capability(&self.capability, |process| {
+                if first.is_none() && process.is_running() {
+                    first = Some(process.processid());
+                }
+            });
+        if let Some(processid) = first {
+            self.kernel.process_map_or_external(
+                (),
+                processid,
+                |process| self.attach(process),
+                &self.capability,
+            );
+        }
+    }
+
+    /// Removes the breakpoints and resumes the process.
+    fn detach(&self) {
+        self.with_process((), |process| {
+            self.remove_breakpoints(process);
+            process.resume();
+        });
+        self.breakpoints.iter().for_each(|slot| slot.set(None));
+        self.process.clear();
+        self.running.set(false);
+        self.stepping.set(false);
+    }
+
+    fn remove_breakpoints(&self, process: &dyn Process) {
+        for slot in &self.breakpoints {
+            if let Some(breakpoint) = slot.take() {
+                let _ = write_memory(
+                    process,
+                    breakpoint.address,
+                    &breakpoint.saved[..breakpoint.len],
+                );
+            }
+        }
+    }
+
+    fn insert_breakpoint(&self, address: usize, kind: usize) -> Result<(), ErrorCode> {
+        let slot = self
+            .breakpoints
+            .iter()
+            .find(|slot| slot.get().is_none())
+            .ok_or(ErrorCode::NOMEM)?;
+        self.with_process(Err(ErrorCode::FAIL), |process| {
+            let mut state = [0; STORED_STATE_LEN];
+            process.get_stored_state(&mut state)?;
+            let instruction = Arch::from_stored_state(&state)
+                .and_then(|arch| arch.breakpoint(kind))
+                .ok_or(ErrorCode::INVAL)?;
+            let mut breakpoint = Breakpoint {
+                address,
+                len: instruction.len(),
+                saved: [0; 4],
+            };
+            read_memory(process, address, &mut breakpoint.saved[..breakpoint.len])?;
+            write_memory(process, address, instruction)?;
+            slot.set(Some(breakpoint));
+            Ok(())
+        })
+    }
+
+    fn remove_breakpoint(&self, address: usize) -> Result<(), ErrorCode> {
+        let slot = self
+            .breakpoints
+            .iter()
+            .find(|slot| slot.get().is_some_and(|b| b.address == address))
+            .ok_or(ErrorCode::INVAL)?;
+        self.with_process(Err(ErrorCode::FAIL), |process| {
+            slot.take().map_or(Ok(()), |breakpoint| {
+                write_memory(process, address, &breakpoint.saved[..breakpoint.len])
+            })
+        })
+    }
+
+    /// Called when the process stopped for GDB.
+    fn stopped(&self, stop: Stop) {
+        self.running.set(false);
+        self.stepping.set(false);
+        if let Stop::Exited(_) = stop {
+            // The breakpoints went with the process.
+            self.breakpoints.iter().for_each(|slot| slot.set(None));
+            self.process.clear();
+        }
+        match self.tx_buffer.take() {
+            Some(buffer) => {
+                let mut reply = Reply::new(buffer, false);
+                reply.stop(stop);
+                self.transmit(reply, true);
+            }
+            None => self.stop_pending.set(stop),
+        }
+    }
+
+    fn transmit(&self, mut reply: Reply, send: bool) {
+        let len = reply.finish(send);
+        if send {
+            self.last_packet.set((reply.packet_start, len));
+        }
+        if len == 0 {
+            self.tx_buffer.replace(reply.buffer);
+        } else if let Err((_, buffer)) = self.uart.transmit_buffer(reply.buffer, len) {
+            self.tx_buffer.replace(buffer);
+        }
+    }
+
+    /// Sends the last packet again, after GDB reported an error.
+    fn retransmit(&self) {
+        let (start, end) = self.last_packet.get();
+        self.tx_buffer.take().map(|buffer| {
+            buffer.copy_within(start..end, 0);
+            if let Err((_, buffer)) = self.uart.transmit_buffer(buffer, end - start) {
+                self.tx_buffer.replace(buffer);
+            }
+            self.last_packet.set((0, end - start));
+        });
+    }
+
+    /// Handles GDB's interrupt (Ctrl-C) by stopping the process.
+    fn interrupt(&self) {
+        if self.running.get() {
+            self.with_process((), |process| process.stop());
+            self.stopped(Stop::Signal(SIGINT));
+        }
+    }
+
+    fn receive_byte(&self, byte: u8) {
+        match self.rx_state.get() {
+            RxState::Idle => match byte {
+                b'$' => {
+                    self.packet_len.set(0);
+                    self.packet_overflow.set(false);
+                    self.checksum.set(0);
+                    self.rx_state.set(RxState::Packet);
+                }
+                0x03 => self.interrupt(),
+                b'-' => self.retransmit(),
+                _ => {}
+            },
+            RxState::Packet => {
+                if byte == b'#' {
+                    self.rx_state.set(RxState::Checksum);
+                } else {
+                    self.checksum.set(self.checksum.get().wrapping_add(byte));
+                    let len = self.packet_len.get();
+                    self.packet.map(|packet| match packet.get_mut(len) {
+                        Some(slot) => {
+                            *slot = byte;
+                            self.packet_len.set(len + 1);
+                        }
+                        None => self.packet_overflow.set(true),
+                    });
+                }
+            }
+            RxState::Checksum => {
+                self.rx_state
+                    .set(RxState::ChecksumLow(hex_digit(byte).unwrap_or(0)));
+            }
+            RxState::ChecksumLow(high) => {
+                self.rx_state.set(RxState::Idle);
+                let checksum = high << 4 | hex_digit(byte).unwrap_or(0);
+                let valid = !self.packet_overflow.get()
+                    && (self.no_ack.get() || checksum == self.checksum.get());
+                match self.tx_buffer.take() {
+                    Some(buffer) if valid => self.handle_packet(buffer),
+                    Some(buffer) => {
+                        buffer[0] = b'-';
+                        if let Err((_, buffer)) = self.uart.transmit_buffer(buffer, 1) {
+                            self.tx_buffer.replace(buffer);
+                        }
+                    }
+                    None => self.packet_pending.set(valid),
+                }
+            }
+        }
+    }
+
+    fn handle_packet(&self, buffer: &'static mut [u8]) {
+        let mut reply = Reply::new(buffer, !self.no_ack.get());
+        let send = self.packet.map_or(false, |packet| {
+            self.handle_command(&packet[..self.packet_len.get()], &mut reply)
+        });
+        self.transmit(reply, send);
+    }
+
+    /// Handles a command from GDB and writes the reply. Returns false if the
+    /// command has no reply.
+    fn handle_command(&self, packet: &[u8], reply: &mut Reply) -> bool {
+        let (command, arguments) = match packet.split_first() {
+            Some((command, arguments)) => (*command, arguments),
+            None => return true,
+        };
+        match command {
+            b'?' => {
+                self.attach_default();
+                match self.process.is_some() {
+                    true => reply.stop(Stop::Signal(SIGTRAP)),
+                    false => reply.stop(Stop::Exited(0)),
+                }
+            }
+            b'g' => match self.with_process(Err(ErrorCode::FAIL), Registers::read) {
+                Ok(registers) => {
+                    for number in 0..registers.arch.register_count() {
+                        let value = registers.get(number).unwrap_or(0);
+                        value.to_le_bytes().iter().for_each(|b| reply.push_hex(*b));
+                    }
+                }
+                Err(error) => reply.error(error),
+            },
+            b'G' => {
+                let result = self.with_process(Err(ErrorCode::FAIL), |process| {
+                    let mut registers = Registers::read(process)?;
+                    for (number, hex) in arguments.chunks(8).enumerate() {
+                        let mut value = [0; 4];
+                        decode_hex(hex, &mut value).ok_or(ErrorCode::INVAL)?;
+                        registers.set(number, u32::from_le_bytes(value))?;
+                    }
+                    registers.write(process)
+                });
+                reply.result(result);
+            }
+            b'p' => {
+                let value = self.with_process(Err(ErrorCode::FAIL), |process| {
+                    let number = parse_hex(arguments).ok_or(ErrorCode::INVAL)?;
+                    Registers::read(process)?
+                        .get(number)
+                        .ok_or(ErrorCode::INVAL)
+                });
+                match value {
+                    Ok(value) => value.to_le_bytes().iter().for_each(|b| reply.push_hex(*b)),
+                    Err(error) => reply.error(error),
+                }
+            }
+            b'P' => {
+                let result = self.with_process(Err(ErrorCode::FAIL), |process| {
+                    let (number, hex) = split(arguments, b'=');
+                    let number = parse_hex(number).ok_or(ErrorCode::INVAL)?;
+                    let mut value = [0; 4];
+                    decode_hex(hex, &mut value).ok_or(ErrorCode::INVAL)?;
+                    let mut registers = Registers::read(process)?;
+                    registers.set(number, u32::from_le_bytes(value))?;
+                    registers.write(process)
+                });
+                reply.result(result);
+            }
+            b'm' => {
+                let mut data = [0; PACKET_BUF_LEN / 2];
+                let result = self.with_process(Err(ErrorCode::FAIL), |process| {
+                    let (address, len) = parse_range(arguments).ok_or(ErrorCode::INVAL)?;
+                    let data = data.get_mut(..len).ok_or(ErrorCode::SIZE)?;
+                    read_memory(process, address, data).map(|()| len)
+                });
+                match result {
+                    Ok(len) => data[..len].iter().for_each(|b| reply.push_hex(*b)),
+                    Err(error) => reply.error(error),
+                }
+            }
+            b'M' => {
+                let result = self.with_process(Err(ErrorCode::FAIL), |process| {
+                    let (range, hex) = split(arguments, b':');
+                    let (address, len) = parse_range(range).ok_or(ErrorCode::INVAL)?;
+                    let mut data = [0; PACKET_BUF_LEN / 2];
+                    if decode_hex(hex, &mut data) != Some(len) {
+                        return Err(ErrorCode::INVAL);
+                    }
+                    write_memory(process, address, &data[..len])
+                });
+                reply.result(result);
+            }
+            b'c' | b's' => {
+                let resumed = self.with_process(Err(ErrorCode::FAIL), |process| {
+                    if let Some(address) = parse_hex(arguments) {
+                        let mut registers = Registers::read(process)?;
+                        registers.set(registers.arch.pc(), address as u32)?;
+                        registers.write(process)?;
+                    }
+                    process.resume();
+                    Ok(())
+                });
+                match resumed {
+                    Ok(()) => {
+                        self.running.set(true);
+                        self.stepping.set(command == b's');
+                        // GDB waits for the process to stop.
+                        return false;
+                    }
+                    Err(error) => reply.error(error),
+                }
+            }
+            b'Z' | b'z' => {
+                let (kind, arguments) = split(arguments, b',');
+                if kind != b"0" {
+                    // Only software breakpoints are supported.
+                    return true;
+                }
+                let result = match (command, parse_range(arguments)) {
+                    (b'Z', Some((address, kind))) => self.insert_breakpoint(address, kind),
+                    (_, Some((address, _))) => self.remove_breakpoint(address),
+                    (_, None) => Err(ErrorCode::INVAL),
+                };
+                reply.result(result);
+            }
+            b'D' => {
+                self.detach();
+                reply.push_str("OK");
+            }
+            b'k' => {
+                // Restart the process, so that it can be debugged again.
+                self.with_process((), |process| {
+                    self.remove_breakpoints(process);
+                    process.try_restart(None);
+                });
+                self.detach();
+                return false;
+            }
+            b'H' | b'T' | b'!' => reply.push_str("OK"),
+            b'q' => self.handle_query(arguments, reply),
+            b'Q' if arguments == b"StartNoAckMode" => {
+                // This packet is still acknowledged.
+                self.no_ack.set(true);
+                reply.push_str("OK");
+            }
+            b'v' => self.handle_v_command(arguments, reply),
+            _ => {}
+        }
+        true
+    }
+
+    fn handle_query(&self, query: &[u8], reply: &mut Reply) {
+        let (name, arguments) = split(query, b':');
+        match name {
+            b"Supported" => {
+                let _ = write!(
+                    reply,
+                    "PacketSize={:x};qXfer:features:read+;QStartNoAckMode+",
+                    PACKET_BUF_LEN
+                );
+            }
+            b"Attached" => reply.push(b'1'),
+            b"C" => reply.push_str("QC1"),
+            b"fThreadInfo" => reply.push_str("m1"),
+            b"sThreadInfo" => reply.push(b'l'),
+            b"Xfer" => {
+                // features:read:target.xml:offset,length
+                let (object, arguments) = split(arguments, b':');
+                let (operation, arguments) = split(arguments, b':');
+                let (annex, range) = split(arguments, b':');
+                if object != b"features" || operation != b"read" || annex != b"target.xml" {
+                    return;
+                }
+                self.attach_default();
+                let arch = self.with_process(None, |process| {
+                    let mut state = [0; STORED_STATE_LEN];
+                    process.get_stored_state(&mut state).ok()?;
+                    Arch::from_stored_state(&state)
+                });
+                match (arch, parse_range(range)) {
+                    (Some(arch), Some((offset, len))) => {
+                        let xml = arch.target_xml().as_bytes();
+                        let start = offset.min(xml.len());
+                        let end = start + len.min(xml.len() - start).min(reply.room() - 1);
+                        reply.push(if end == xml.len() { b'l' } else { b'm' });
+                        xml[start..end].iter().for_each(|b| reply.push(*b));
+                    }
+                    _ => reply.error(ErrorCode::INVAL),
+                }
+            }
+            b"Rcmd" => {
+                let mut command = [0; 16];
+                match decode_hex(arguments, &mut command) {
+                    Some(len) if &command[..len] == b"ps" => self.list_processes(reply),
+                    _ => {
+                        let _ = writeln!(HexWriter(reply), "commands: ps");
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_v_command(&self, command: &[u8], reply: &mut Reply) {
+        let (name, pid) = split(command, b';');
+        let pid = parse_hex(pid);
+        let mut found = false;
+        match name {
+            b"Attach" | b"Kill" => {
+                self.kernel
+                    .process_each_capability(&self.capability, |process| {
+                        if Some(process.processid().id() + 1) != pid {
+                            return;
+                        }
+                        found = true;
+                        if name == b"Attach" {
+                            self.attach(process);
+                        } else {
+                            if self.process.get() == Some(process.processid()) {
+                                self.detach();
+                            }
+                            process.try_restart(None);
+                        }
+                    });
+                match (found, name) {
+                    (false, _) => reply.error(ErrorCode::INVAL),
+                    (true, b"Attach") => reply.stop(Stop::Signal(SIGTRAP)),
+                    (true, _) => reply.push_str("OK"),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Lists the processes for `monitor ps`.
+    fn list_processes(&self, reply: &mut Reply) {
+        let mut writer = HexWriter(reply);
+        let _ = writeln!(writer, "pid name       state      flash      ram");
+        self.kernel
+            .process_each_capability(&self.capability, |process| {
+                let addresses = process.get_addresses();
+                let state = match process.get_state() {
+                    State::Running => "Running",
+                    State::Yielded | State::YieldedFor(_) => "Yielded",
+                    State::Stopped(_) => "Stopped",
+                    State::Faulted => "Faulted",
+                    State::Terminated => "Terminated",
+                };
+                let _ = writeln!(
+                    writer,
+                    "{:<3} {:<10} {:<10} {:#010x} {:#010x}",
+                    process.processid().id() + 1,
+                    process.get_process_name(),
+                    state,
+                    addresses.flash_start,
+                    addresses.sram_start,
+                );
+            });
+    }
+}
+
+impl Write for Reply {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        self.push_str(text);
+        Ok(())
+    }
+}
+
+impl<'a, U: uart::UartData<'a>, C: ProcessManagementCapability> SyscallFilter
+    for GdbStub<'a, U, C>
+{
+    /// Stops the debugged process at its next system call when GDB steps it,
+    /// and reports it exiting. Allows all system calls.
+    fn filter_syscall(&self, process: &dyn Process, syscall: &Syscall) -> Result<(), ErrorCode> {
+        if self.running.get() && self.process.get() == Some(process.processid()) {
+            match syscall {
+                Syscall::Exit {
+                    completion_code, ..
+                } => {
+                    self.remove_breakpoints(process);
+                    self.stopped(Stop::Exited(*completion_code as u8));
+                }
+                _ if self.stepping.get() => {
+                    // The system call still runs, and the process stops when
+                    // it returns.
+                    process.stop();
+                    self.stopped(Stop::Signal(SIGTRAP));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a, U: uart::UartData<'a>, C: ProcessManagementCapability> ProcessFault for GdbStub<'a, U, C> {
+    /// Stops the debugged process when it faults, instead of running the fault
+    /// policy.
+    fn process_fault_hook(&self, process: &dyn Process) -> Result<(), ()> {
+        if !self.running.get() || self.process.get() != Some(process.processid()) {
+            return Err(());
+        }
+        process.stop();
+        let pc = Registers::read(process)
+            .ok()
+            .and_then(|registers| registers.get(registers.arch.pc()));
+        let breakpoint = self.breakpoints.iter().any(|slot| {
+            slot.get()
+                .is_some_and(|breakpoint| Some(breakpoint.address as u32) == pc)
+        });
+        self.stopped(Stop::Signal(if breakpoint { SIGTRAP } else { SIGSEGV }));
+        Ok(())
+    }
+}
+
+impl<'a, U: uart::UartData<'a>, C: ProcessManagementCapability> uart::TransmitClient
+    for GdbStub<'a, U, C>
+{
+    fn transmitted_buffer(
+        &self,
+        buffer: &'static mut [u8],
+        _tx_len: usize,
+        _rcode: Result<(), ErrorCode>,
+    ) {
+        if self.packet_pending.take() {
+            self.handle_packet(buffer);
+        } else if let Some(stop) = self.stop_pending.take() {
+            let mut reply = Reply::new(buffer, false);
+            reply.stop(stop);
+            self.transmit(reply, true);
+        } else {
+            self.tx_buffer.replace(buffer);
+        }
+    }
+}
+
+impl<'a, U: uart::UartData<'a>, C: ProcessManagementCapability> uart::ReceiveClient
+    for GdbStub<'a, U, C>
+{
+    fn received_buffer(
+        &self,
+        buffer: &'static mut [u8],
+        rx_len: usize,
+        _rcode: Result<(), ErrorCode>,
+        _error: uart::Error,
+    ) {
+        if rx_len == 1 {
+            self.receive_byte(buffer[0]);
+        }
+        if let Err((_, buffer)) = self.uart.receive_buffer(buffer, RX_BUF_LEN) {
+            self.rx_buffer.replace(buffer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::boxed::Box;
+
+    fn reply(len: usize, ack: bool) -> Reply {
+        Reply::new(Box::leak(std::vec![0; len].into_boxed_slice()), ack)
+    }
+
+    fn sent(reply: &mut Reply, send: bool) -> &[u8] {
+        let len = reply.finish(send);
+        &reply.buffer[..len]
+    }
+
+    /// Registers of a Cortex-M process with the process stack pointer at
+    /// `psp`, r4-r11 set to 4-11 and the stacked frame `frame`.
+    fn cortex_m_registers(psp: u32, frame: [u32; 8]) -> Registers {
+        let mut registers = Registers {
+            arch: Arch::CortexM,
+            state: [0; STORED_STATE_LEN],
+            state_len: STORED_STATE_LEN,
+            frame,
+        };
+        registers.state[8..12].copy_from_slice(b"ctxm");
+        registers.set_word(CORTEX_M_PSP, psp);
+        for number in 4..=11 {
+            registers.set_word(CORTEX_M_R4 + number - 4, number as u32);
+        }
+        registers
+    }
+
+    #[test]
+    fn parses_hex() {
+        assert_eq!(parse_hex(b"0"), Some(0));
+        assert_eq!(parse_hex(b"20001fA0"), Some(0x20001fa0));
+        assert_eq!(parse_hex(b""), None);
+        assert_eq!(parse_hex(b"12g4"), None);
+        // More digits than fit into a `usize`.
+        let digits = [b'f'; 2 * core::mem::size_of::<usize>() + 1];
+        assert_eq!(parse_hex(&digits[1..]), Some(usize::MAX));
+        assert_eq!(parse_hex(&digits), None);
+    }
+
+    #[test]
+    fn decodes_hex() {
+        let mut out = [0; 4];
+        assert_eq!(decode_hex(b"00bEef", &mut out), Some(3));
+        assert_eq!(out[..3], [0x00, 0xbe, 0xef]);
+        assert_eq!(decode_hex(b"", &mut out), Some(0));
+        assert_eq!(decode_hex(b"abc", &mut out), None);
+        assert_eq!(decode_hex(b"0x12", &mut out), None);
+        assert_eq!(decode_hex(b"0102030405", &mut out), None);
+    }
+
+    #[test]
+    fn parses_ranges() {
+        assert_eq!(parse_range(b"20000400,40"), Some((0x20000400, 0x40)));
+        assert_eq!(parse_range(b"20000400"), None);
+        assert_eq!(parse_range(b",40"), None);
+        assert_eq!(parse_range(b"20000400,4x"), None);
+        assert_eq!(split(b"Z0,1000,2", b','), (&b"Z0"[..], &b"1000,2"[..]));
+    }
+
+    #[test]
+    fn checksums_replies() {
+        // The checksum is the sum of the packet data modulo 256.
+        let mut ok = reply(16, true);
+        ok.result(Ok(()));
+        assert_eq!(sent(&mut ok, true), b"+$OK#9a");
+
+        let mut stop = reply(16, false);
+        stop.stop(Stop::Signal(SIGTRAP));
+        assert_eq!(sent(&mut stop, true), b"$S05#b8");
+
+        // The checksum wraps around.
+        let mut hex = reply(32, false);
+        let _ = HexWriter(&mut hex).write_str("~~~~");
+        assert_eq!(sent(&mut hex, true), b"$7e7e7e7e#70");
+
+        // Without a reply only the acknowledgement is sent.
+        let mut none = reply(16, true);
+        none.push_str("OK");
+        assert_eq!(sent(&mut none, false), b"+");
+    }
+
+    #[test]
+    fn truncates_replies_before_checksum() {
+        let mut long = reply(8, false);
+        long.push_str("abcdefgh");
+        assert_eq!(sent(&mut long, true), b"$abcd#8a");
+    }
+
+    #[test]
+    fn detects_architecture() {
+        let mut state = [0; 12];
+        assert!(Arch::from_stored_state(&state).is_none());
+        state[8..].copy_from_slice(b"ctxm");
+        assert!(Arch::from_stored_state(&state) == Some(Arch::CortexM));
+        state[8..].copy_from_slice(b"rv5i");
+        assert!(Arch::from_stored_state(&state) == Some(Arch::RiscV));
+        assert!(Arch::from_stored_state(&state[..11]).is_none());
+    }
+
+    #[test]
+    fn maps_cortex_m_registers() {
+        let frame = [0, 1, 2, 3, 12, 0x14, 0x15, 0x0100_0000];
+        let registers = cortex_m_registers(0x2000_1000, frame);
+        let expected = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+        for (number, value) in expected.iter().enumerate() {
+            assert_eq!(registers.get(number), Some(*value));
+        }
+        // sp is above the stacked frame.
+        assert_eq!(registers.get(13), Some(0x2000_1020));
+        assert_eq!(registers.get(14), Some(0x14));
+        assert_eq!(registers.get(15), Some(0x15));
+        assert_eq!(registers.get(16), Some(0x0100_0000));
+        assert_eq!(registers.get(17), None);
+    }
+
+    #[test]
+    fn includes_cortex_m_frame_padding() {
+        // Bit 9 of the stacked xpsr marks a word of alignment padding.
+        let mut frame = [0; 8];
+        frame[7] = 0x0100_0200;
+        let registers = cortex_m_registers(0x2000_1000, frame);
+        assert_eq!(registers.get(13), Some(0x2000_1024));
+    }
+
+    #[test]
+    fn sets_cortex_m_registers() {
+        let mut frame = [0; 8];
+        frame[7] = 0x0100_0200;
+        let mut registers = cortex_m_registers(0x2000_1000, frame);
+        registers.set(0, 0xa0).unwrap();
+        registers.set(7, 0xa7).unwrap();
+        registers.set(12, 0xac).unwrap();
+        registers.set(15, 0x0800_0101).unwrap();
+        assert_eq!(registers.frame[0], 0xa0);
+        assert_eq!(registers.word(CORTEX_M_R4 + 3), 0xa7);
+        assert_eq!(registers.frame[4], 0xac);
+        assert_eq!(registers.get(15), Some(0x0800_0101));
+
+        // A new sp moves the frame below it and drops the padding.
+        registers.set(13, 0x2000_2000).unwrap();
+        assert_eq!(registers.word(CORTEX_M_PSP), 0x2000_1fe0);
+        assert_eq!(registers.frame[7], 0x0100_0000);
+        assert_eq!(registers.get(13), Some(0x2000_2000));
+
+        assert_eq!(registers.set(17, 0), Err(ErrorCode::INVAL));
+    }
+
+    #[test]
+    fn maps_riscv_registers() {
+        let mut registers = Registers {
+            arch: Arch::RiscV,
+            state: [0; STORED_STATE_LEN],
+            state_len: STORED_STATE_LEN,
+            frame: [0; 8],
+        };
+        registers.set(0, 0xff).unwrap();
+        registers.set(1, 0x11).unwrap();
+        registers.set(31, 0x31).unwrap();
+        registers.set(32, 0x8000_0000).unwrap();
+        assert_eq!(registers.get(0), Some(0));
+        assert_eq!(registers.word(RISCV_X1), 0x11);
+        assert_eq!(registers.get(31), Some(0x31));
+        assert_eq!(registers.word(RISCV_PC), 0x8000_0000);
+        assert_eq!(registers.get(Arch::RiscV.pc()), Some(0x8000_0000));
+        assert_eq!(registers.get(33), None);
+        assert_eq!(registers.set(33, 0), Err(ErrorCode::INVAL));
+    }
+}
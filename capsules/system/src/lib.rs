@@ -5,7 +5,10 @@
 #![forbid(unsafe_code)]
 #![no_std]
 
+pub mod crash_dump;
+pub mod gdb_stub;
 pub mod process_checker;
 pub mod process_policies;
 pub mod process_printer;
 pub mod storage_permissions;
+pub mod tracer;
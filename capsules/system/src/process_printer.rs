@@ -6,11 +6,16 @@
 
 use core::fmt::Write;
 
-use kernel::process::Process;
+use kernel::process::{Process, ShortId, State, StoppedState};
 use kernel::process::{ProcessPrinter, ProcessPrinterContext};
+use kernel::processbuffer::ReadableProcessBuffer;
+use kernel::syscall::Syscall;
+use kernel::upcall::UpcallId;
 use kernel::utilities::binary_write::BinaryWrite;
 use kernel::utilities::binary_write::WriteToBinaryOffsetWrapper;
 
+use crate::crash_dump::crc32_update;
+
 /// A Process Printer that displays a process as a human-readable string.
 pub struct ProcessPrinterText {}
 
@@ -250,3 +255,352 @@ fn exceeded_check(size: usize, allocated: usize) -> &'static str {
         "          "
     }
 }
+
+/// A Process Printer that encodes a process in a compact binary format for
+/// host-side tools, such as `tools/process_decoder`.
+///
+/// The overview is a frame of records. Since process printers usually write to
+/// a text console (and the panic handler can only pass text through), the
+/// frame is printed base64 encoded between marker lines:
+///
+/// ```text
+/// -----BEGIN TOCK PROCESS-----
+/// VEtQUAEBBABibGluawIBAAY...
+/// -----END TOCK PROCESS-----
+/// ```
+///
+/// The frame starts with `"TKPP"` and a version byte (1). Each record is a tag
+/// byte, a `u16` length and the value. All numbers are little endian, and
+/// addresses are `u32`. Records that do not apply to the process are left out.
+///
+/// | Tag | Record          | Value                                           |
+/// |-----|-----------------|-------------------------------------------------|
+/// | 1   | Name            | UTF-8 name of the process.                      |
+/// | 2   | State           | `u8` state, see below. For the states waiting   |
+/// |     |                 | for an upcall, followed by the `u32` driver and |
+/// |     |                 | subscribe numbers.                              |
+/// | 3   | Statistics      | `u32` queued events, syscalls, dropped upcalls, |
+/// |     |                 | restarts and timeslice expirations.             |
+/// | 4   | Last syscall    | `u8` syscall class and the `u32` driver and     |
+/// |     |                 | subdriver numbers (for yield, memop and exit:   |
+/// |     |                 | their first two arguments).                     |
+/// | 5   | Completion code | `u8` 0 if the process faulted, or 1 and the     |
+/// |     |                 | `u32` completion code if it exited.             |
+/// | 6   | Addresses       | Flash start, non-protected flash start, end of  |
+/// |     |                 | the integrity region, flash end, RAM start, app |
+/// |     |                 | break, grant start, RAM end, heap start, stack  |
+/// |     |                 | top and lowest stack pointer (0 if unknown).    |
+/// | 7   | Sizes           | `u32` sizes of the grant pointers, upcall queue |
+/// |     |                 | and process control block.                      |
+/// | 8   | Stored state    | Architecture-specific registers of the process, |
+/// |     |                 | as returned by `Process::get_stored_state()`.   |
+/// | 9   | Stack           | `u32` address, then the stack from that address.|
+/// | 10  | Short ID        | `u32` fixed short ID of the process.            |
+/// | 0   | End             | `u32` CRC-32 (IEEE) of the frame before it.     |
+///
+/// The states are: 0 running, 1 yielded, 2 yielded for an upcall, 3 stopped
+/// while running, 4 stopped while yielded, 5 stopped while yielded for an
+/// upcall, 6 faulted and 7 terminated.
+///
+/// The stack is read from the lowest stack pointer the process has used, up to
+/// `stack_len` bytes. It is only available while the process can run, or when
+/// the overview is printed as it faults.
+pub struct ProcessPrinterBinary {
+    stack_len: usize,
+}
+
+/// First bytes of the frame.
+const MAGIC: [u8; 4] = *b"TKPP";
+/// Version of the frame format.
+const VERSION: u8 = 1;
+
+const BEGIN_MARKER: &str = "-----BEGIN TOCK PROCESS-----\r\n";
+const END_MARKER: &str = "-----END TOCK PROCESS-----\r\n";
+
+const TAG_END: u8 = 0;
+const TAG_NAME: u8 = 1;
+const TAG_STATE: u8 = 2;
+const TAG_STATISTICS: u8 = 3;
+const TAG_LAST_SYSCALL: u8 = 4;
+const TAG_COMPLETION_CODE: u8 = 5;
+const TAG_ADDRESSES: u8 = 6;
+const TAG_SIZES: u8 = 7;
+const TAG_STORED_STATE: u8 = 8;
+const TAG_STACK: u8 = 9;
+const TAG_SHORT_ID: u8 = 10;
+
+/// Large enough for the stored state of the Cortex-M and RISC-V processes.
+const STORED_STATE_MAX_LEN: usize = 160;
+
+/// Number of base64 characters per line.
+const BASE64_LINE_LEN: usize = 64;
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+impl ProcessPrinterBinary {
+    pub fn new(stack_len: usize) -> ProcessPrinterBinary {
+        ProcessPrinterBinary { stack_len }
+    }
+}
+
+/// Writes a frame as base64 lines, while computing its CRC.
+struct FrameEncoder<'a, 'b> {
+    bww: &'a mut WriteToBinaryOffsetWrapper<'b>,
+    crc: u32,
+    group: [u8; 3],
+    group_len: usize,
+    line_len: usize,
+}
+
+impl<'a, 'b> FrameEncoder<'a, 'b> {
+    fn new(bww: &'a mut WriteToBinaryOffsetWrapper<'b>) -> Self {
+        FrameEncoder {
+            bww,
+            crc: 0,
+            group: [0; 3],
+            group_len: 0,
+            line_len: 0,
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) {
+        self.crc = crc32_update(self.crc, data);
+        for byte in data {
+            self.group[self.group_len] = *byte;
+            self.group_len += 1;
+            if self.group_len == self.group.len() {
+                self.encode_group();
+            }
+        }
+    }
+
+    fn write_u32(&mut self, value: usize) {
+        self.write(&(value as u32).to_le_bytes());
+    }
+
+    /// Start a record with a `len` byte value.
+    fn record(&mut self, tag: u8, len: usize) {
+        self.write(&[tag]);
+        self.write(&(len as u16).to_le_bytes());
+    }
+
+    /// Write the base64 characters for the bytes in `group`, padded if it is
+    /// not full.
+    fn encode_group(&mut self) {
+        let [a, b, c] = self.group;
+        let mut chars = [
+            BASE64_ALPHABET[(a >> 2) as usize],
+            BASE64_ALPHABET[(((a & 0x03) << 4) | (b >> 4)) as usize],
+            BASE64_ALPHABET[(((b & 0x0F) << 2) | (c >> 6)) as usize],
+            BASE64_ALPHABET[(c & 0x3F) as usize],
+        ];
+        for char in chars.iter_mut().skip(self.group_len + 1) {
+            *char = b'=';
+        }
+        self.group = [0; 3];
+        self.group_len = 0;
+
+        let _ = self
+            .bww
+            .write_str(core::str::from_utf8(&chars).unwrap_or(""));
+        self.line_len += chars.len();
+        if self.line_len == BASE64_LINE_LEN {
+            let _ = self.bww.write_str("\r\n");
+            self.line_len = 0;
+        }
+    }
+
+    /// End the frame with its CRC.
+    fn finish(mut self) {
+        self.record(TAG_END, 4);
+        let crc = self.crc;
+        self.write(&crc.to_le_bytes());
+        if self.group_len > 0 {
+            self.encode_group();
+        }
+        if self.line_len > 0 {
+            let _ = self.bww.write_str("\r\n");
+        }
+    }
+}
+
+/// The state code and the upcall the process waits for, if any.
+fn state_code(state: State) -> (u8, Option<UpcallId>) {
+    match state {
+        State::Running => (0, None),
+        State::Yielded => (1, None),
+        State::YieldedFor(upcall_id) => (2, Some(upcall_id)),
+        State::Stopped(StoppedState::Running) => (3, None),
+        State::Stopped(StoppedState::Yielded) => (4, None),
+        State::Stopped(StoppedState::YieldedFor(upcall_id)) => (5, Some(upcall_id)),
+        State::Faulted => (6, None),
+        State::Terminated => (7, None),
+    }
+}
+
+/// The class and the first two arguments of `syscall`.
+fn syscall_code(syscall: Syscall) -> (u8, usize, usize) {
+    match syscall {
+        Syscall::Yield { which, param_a, .. } => (0, which, param_a),
+        Syscall::Subscribe {
+            driver_number,
+            subdriver_number,
+            ..
+        } => (1, driver_number, subdriver_number),
+        Syscall::Command {
+            driver_number,
+            subdriver_number,
+            ..
+        } => (2, driver_number, subdriver_number),
+        Syscall::ReadWriteAllow {
+            driver_number,
+            subdriver_number,
+            ..
+        } => (3, driver_number, subdriver_number),
+        Syscall::ReadOnlyAllow {
+            driver_number,
+            subdriver_number,
+            ..
+        } => (4, driver_number, subdriver_number),
+        Syscall::Memop { operand, arg0 } => (5, operand, arg0),
+        Syscall::Exit {
+            which,
+            completion_code,
+        } => (6, which, completion_code),
+        Syscall::UserspaceReadableAllow {
+            driver_number,
+            subdriver_number,
+            ..
+        } => (7, driver_number, subdriver_number),
+    }
+}
+
+impl ProcessPrinter for ProcessPrinterBinary {
+    // Like `ProcessPrinterText`, this encodes the entire frame on every call
+    // and only passes the bytes after `context` to the writer.
+    fn print_overview(
+        &self,
+        process: &dyn Process,
+        writer: &mut dyn BinaryWrite,
+        context: Option<ProcessPrinterContext>,
+    ) -> Option<ProcessPrinterContext> {
+        let offset = context.map_or(0, |c| c.offset);
+
+        let mut bww = WriteToBinaryOffsetWrapper::new(writer);
+        bww.set_offset(offset);
+
+        let _ = bww.write_str(BEGIN_MARKER);
+        let mut frame = FrameEncoder::new(&mut bww);
+        frame.write(&MAGIC);
+        frame.write(&[VERSION]);
+
+        let name = process.get_process_name().as_bytes();
+        let name = &name[..core::cmp::min(name.len(), u16::MAX as usize)];
+        frame.record(TAG_NAME, name.len());
+        frame.write(name);
+
+        if let ShortId::Fixed(id) = process.short_app_id() {
+            frame.record(TAG_SHORT_ID, 4);
+            frame.write_u32(id.get() as usize);
+        }
+
+        let (state, upcall_id) = state_code(process.get_state());
+        match upcall_id {
+            Some(upcall_id) => {
+                frame.record(TAG_STATE, 9);
+                frame.write(&[state]);
+                frame.write_u32(upcall_id.driver_num);
+                frame.write_u32(upcall_id.subscribe_num);
+            }
+            None => {
+                frame.record(TAG_STATE, 1);
+                frame.write(&[state]);
+            }
+        }
+
+        frame.record(TAG_STATISTICS, 20);
+        frame.write_u32(process.pending_tasks());
+        frame.write_u32(process.debug_syscall_count());
+        frame.write_u32(process.debug_dropped_upcall_count());
+        frame.write_u32(process.get_restart_count());
+        frame.write_u32(process.debug_timeslice_expiration_count());
+
+        if let Some(syscall) = process.debug_syscall_last() {
+            let (class, a, b) = syscall_code(syscall);
+            frame.record(TAG_LAST_SYSCALL, 9);
+            frame.write(&[class]);
+            frame.write_u32(a);
+            frame.write_u32(b);
+        }
+
+        match process.get_completion_code() {
+            Some(Some(code)) => {
+                frame.record(TAG_COMPLETION_CODE, 5);
+                frame.write(&[1]);
+                frame.write_u32(code as usize);
+            }
+            Some(None) => {
+                frame.record(TAG_COMPLETION_CODE, 1);
+                frame.write(&[0]);
+            }
+            None => {}
+        }
+
+        let addresses = process.get_addresses();
+        let address_list = [
+            addresses.flash_start,
+            addresses.flash_non_protected_start,
+            addresses.flash_integrity_end as usize,
+            addresses.flash_end,
+            addresses.sram_start,
+            addresses.sram_app_brk,
+            addresses.sram_grant_start,
+            addresses.sram_end,
+            addresses.sram_heap_start.unwrap_or(0),
+            addresses.sram_stack_top.unwrap_or(0),
+            addresses.sram_stack_bottom.unwrap_or(0),
+        ];
+        frame.record(TAG_ADDRESSES, address_list.len() * 4);
+        for address in address_list {
+            frame.write_u32(address);
+        }
+
+        let sizes = process.get_sizes();
+        frame.record(TAG_SIZES, 12);
+        frame.write_u32(sizes.grant_pointers);
+        frame.write_u32(sizes.upcall_list);
+        frame.write_u32(sizes.process_control_block);
+
+        let mut stored_state = [0; STORED_STATE_MAX_LEN];
+        if let Ok(len) = process.get_stored_state(&mut stored_state) {
+            frame.record(TAG_STORED_STATE, len);
+            frame.write(&stored_state[..len]);
+        }
+
+        if let (Some(top), Some(bottom)) = (addresses.sram_stack_top, addresses.sram_stack_bottom) {
+            let len = core::cmp::min(top.saturating_sub(bottom), self.stack_len);
+            let len = core::cmp::min(len, u16::MAX as usize - 4);
+            if let Ok(stack) = process.build_readonly_process_buffer(bottom as *const u8, len) {
+                let _ = stack.enter(|stack| {
+                    frame.record(TAG_STACK, 4 + stack.len());
+                    frame.write_u32(bottom);
+                    for byte in stack.iter() {
+                        frame.write(&[byte.get()]);
+                    }
+                });
+            }
+        }
+
+        frame.finish();
+        let _ = bww.write_str(END_MARKER);
+
+        if bww.bytes_remaining() {
+            Some(ProcessPrinterContext {
+                offset: bww.get_index(),
+            })
+        } else {
+            None
+        }
+    }
+}
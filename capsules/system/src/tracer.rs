@@ -0,0 +1,357 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Ring buffer backend for kernel tracing.
+//!
+//! `RingBufferTracer` receives the events of the kernel's
+//! [`trace`](kernel::trace) facility, timestamps them with an alarm and stores
+//! them as compact binary records in a ring buffer. The records are drained
+//! periodically to a `uart::Transmit`, which can be a UART, SEGGER RTT or USB
+//! CDC. `tools/trace_decoder` converts the drained frames to Chrome trace
+//! JSON for a timeline view in Perfetto or `chrome://tracing`.
+//!
+//! The kernel only passes events to the tracer if the kernel crate is built
+//! with the `trace_events` feature.
+//!
+//! When the ring buffer is full, new events are dropped and counted, and the
+//! count is reported with the next frame. Draining itself generates events,
+//! such as the interrupts of the UART, so the interval between drains is a
+//! trade-off between the size of the ring buffer and the overhead of tracing.
+//!
+//! Format
+//! ------
+//!
+//! Each frame is a header, the records, and the CRC-32 (IEEE) of the header
+//! and records, all fields little endian:
+//!
+//! ```text
+//! +--------+---------+-----------+-------+-----------+---------+
+//! | "TKTR" | version | tick bits | count | frequency | dropped |
+//! | 4      | 1       | 1         | 2     | 4         | 4       |
+//! +--------+---------+-----------+-------+-----------+---------+
+//! ```
+//!
+//! `tick bits` is the width of the timestamps, which wrap around, and
+//! `frequency` their frequency in Hz. `count` records follow, and `dropped`
+//! events were lost before them. Each record is:
+//!
+//! ```text
+//! +-----------+------+--------+---------+------+------+
+//! | timestamp | kind | detail | process | arg0 | arg1 |
+//! | 4         | 1    | 1      | 2       | 4    | 4    |
+//! +-----------+------+--------+---------+------+------+
+//! ```
+//!
+//! `process` is the low 16 bits of `ProcessId::id()`, or `0xFFFF` for events
+//! of the kernel itself. Fields not listed are 0.
+//!
+//! | Kind | Event                   | Fields                                     |
+//! |------|-------------------------|--------------------------------------------|
+//! | 1    | Switch to process       | `process`                                  |
+//! | 2    | Switch to kernel        | `process`, `detail`: 0 syscall, 1 fault, 2 |
+//! |      |                         | interrupted, 3 switching failed            |
+//! | 3    | Syscall entry           | `process`, `detail`: syscall class, `arg0` |
+//! |      |                         | and `arg1`: driver and subdriver number,   |
+//! |      |                         | or the first two arguments of Yield, Memop |
+//! |      |                         | and Exit                                   |
+//! | 4    | Syscall exit            | `process`                                  |
+//! | 5    | Upcall scheduled        | `process`, `arg0`: driver number, `arg1`:  |
+//! |      |                         | subscribe number                           |
+//! | 6    | Interrupt service start |                                            |
+//! | 7    | Interrupt               | `arg0`: interrupt number                   |
+//! | 8    | Interrupt service end   |                                            |
+//! | 9    | Deferred call start     | `arg0`: deferred call index                |
+//! | 10   | Deferred call end       | `arg0`: deferred call index                |
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let tracer = components::tracer::RingBufferTracerComponent::new(mux_alarm, uart_device, 100)
+//!     .finalize(components::ring_buffer_tracer_component_static!(
+//!         nrf52840::rtc::Rtc,
+//!         capsules_core::virtualizers::virtual_uart::UartDevice<'static>,
+//!         256
+//!     ));
+//! ```
+
+use core::cell::Cell;
+
+use kernel::collections::queue::Queue;
+use kernel::collections::ring_buffer::RingBuffer;
+use kernel::hil::time::{self, Alarm, ConvertTicks, Frequency, Ticks};
+use kernel::hil::uart;
+use kernel::process::ProcessId;
+use kernel::syscall::{ContextSwitchReason, Syscall};
+use kernel::trace::{Event, Tracer};
+use kernel::utilities::cells::{MapCell, TakeCell};
+use kernel::ErrorCode;
+
+use crate::crash_dump::crc32_update;
+
+/// First bytes of every frame.
+const MAGIC: [u8; 4] = *b"TKTR";
+/// Version of the frame format.
+const VERSION: u8 = 1;
+/// Length of the frame header.
+const HEADER_LEN: usize = 16;
+/// Length of a record.
+const RECORD_LEN: usize = 16;
+/// Length of the CRC at the end of a frame.
+const CRC_LEN: usize = 4;
+
+/// Number of records sent in one frame.
+pub const RECORDS_PER_FRAME: usize = 30;
+/// Length of the buffer frames are sent from.
+pub const TX_BUF_LEN: usize = HEADER_LEN + RECORDS_PER_FRAME * RECORD_LEN + CRC_LEN;
+
+const KIND_SWITCH_TO_PROCESS: u8 = 1;
+const KIND_SWITCH_TO_KERNEL: u8 = 2;
+const KIND_SYSCALL_ENTRY: u8 = 3;
+const KIND_SYSCALL_EXIT: u8 = 4;
+const KIND_UPCALL_SCHEDULED: u8 = 5;
+const KIND_INTERRUPT_SERVICE_START: u8 = 6;
+const KIND_INTERRUPT: u8 = 7;
+const KIND_INTERRUPT_SERVICE_END: u8 = 8;
+const KIND_DEFERRED_CALL_START: u8 = 9;
+const KIND_DEFERRED_CALL_END: u8 = 10;
+
+/// `process` of events of the kernel itself.
+const NO_PROCESS: u16 = 0xFFFF;
+
+/// A timestamped event in the ring buffer.
+#[derive(Clone, Copy, Default)]
+pub struct Record {
+    timestamp: u32,
+    kind: u8,
+    detail: u8,
+    process: u16,
+    arg0: u32,
+    arg1: u32,
+}
+
+impl Record {
+    fn write_to(&self, out: &mut [u8]) {
+        out[0..4].copy_from_slice(&self.timestamp.to_le_bytes());
+        out[4] = self.kind;
+        out[5] = self.detail;
+        out[6..8].copy_from_slice(&self.process.to_le_bytes());
+        out[8..12].copy_from_slice(&self.arg0.to_le_bytes());
+        out[12..16].copy_from_slice(&self.arg1.to_le_bytes());
+    }
+}
+
+/// Syscall class and the two arguments recorded for `syscall`.
+fn syscall_fields(syscall: &Syscall) -> (u8, usize, usize) {
+    match *syscall {
+        Syscall::Yield { which, param_a, .. } => (0, which, param_a),
+        Syscall::Subscribe {
+            driver_number,
+            subdriver_number,
+            ..
+        } => (1, driver_number, subdriver_number),
+        Syscall::Command {
+            driver_number,
+            subdriver_number,
+            ..
+        } => (2, driver_number, subdriver_number),
+        Syscall::ReadWriteAllow {
+            driver_number,
+            subdriver_number,
+            ..
+        } => (3, driver_number, subdriver_number),
+        Syscall::ReadOnlyAllow {
+            driver_number,
+            subdriver_number,
+            ..
+        } => (4, driver_number, subdriver_number),
+        Syscall::Memop { operand, arg0 } => (5, operand, arg0),
+        Syscall::Exit {
+            which,
+            completion_code,
+        } => (6, which, completion_code),
+        Syscall::UserspaceReadableAllow {
+            driver_number,
+            subdriver_number,
+            ..
+        } => (7, driver_number, subdriver_number),
+    }
+}
+
+pub struct RingBufferTracer<'a, A: Alarm<'a>, U: uart::Transmit<'a>> {
+    alarm: &'a A,
+    uart: &'a U,
+    records: MapCell<RingBuffer<'a, Record>>,
+    /// Events lost since the last frame was sent.
+    dropped: Cell<u32>,
+    tx_buffer: TakeCell<'static, [u8]>,
+    drain_interval_ms: u32,
+}
+
+impl<'a, A: Alarm<'a>, U: uart::Transmit<'a>> RingBufferTracer<'a, A, U> {
+    pub fn new(
+        alarm: &'a A,
+        uart: &'a U,
+        records: &'a mut [Record],
+        tx_buffer: &'static mut [u8],
+        drain_interval_ms: u32,
+    ) -> RingBufferTracer<'a, A, U> {
+        RingBufferTracer {
+            alarm,
+            uart,
+            records: MapCell::new(RingBuffer::new(records)),
+            dropped: Cell::new(0),
+            tx_buffer: TakeCell::new(tx_buffer),
+            drain_interval_ms,
+        }
+    }
+
+    /// Start draining the records periodically.
+    pub fn start(&self) {
+        self.alarm.set_alarm(
+            self.alarm.now(),
+            self.alarm.ticks_from_ms(self.drain_interval_ms),
+        );
+    }
+
+    fn record(&self, kind: u8, detail: u8, process: Option<ProcessId>, arg0: usize, arg1: usize) {
+        let record = Record {
+            timestamp: self.alarm.now().into_u32(),
+            kind,
+            detail,
+            process: process.map_or(NO_PROCESS, |process| process.id() as u16),
+            arg0: arg0 as u32,
+            arg1: arg1 as u32,
+        };
+        let stored = self
+            .records
+            .map(|records| records.enqueue(record))
+            .unwrap_or(false);
+        if !stored {
+            self.dropped.set(self.dropped.get().saturating_add(1));
+        }
+    }
+
+    /// Send the oldest records as a frame, if there are any and no frame is
+    /// being sent.
+    fn drain(&self) {
+        self.tx_buffer.take().map(|buffer| {
+            let capacity = (buffer.len() - HEADER_LEN - CRC_LEN) / RECORD_LEN;
+            let mut count = 0;
+            self.records.map(|records| {
+                while count < capacity {
+                    match records.dequeue() {
+                        Some(record) => {
+                            record.write_to(&mut buffer[HEADER_LEN + count * RECORD_LEN..]);
+                            count += 1;
+                        }
+                        None => break,
+                    }
+                }
+            });
+            let dropped = self.dropped.take();
+            if count == 0 && dropped == 0 {
+                self.tx_buffer.replace(buffer);
+                return;
+            }
+
+            buffer[0..4].copy_from_slice(&MAGIC);
+            buffer[4] = VERSION;
+            buffer[5] = A::Ticks::width() as u8;
+            buffer[6..8].copy_from_slice(&(count as u16).to_le_bytes());
+            buffer[8..12].copy_from_slice(&A::Frequency::frequency().to_le_bytes());
+            buffer[12..16].copy_from_slice(&dropped.to_le_bytes());
+            let len = HEADER_LEN + count * RECORD_LEN;
+            let crc = crc32_update(0, &buffer[..len]);
+            buffer[len..len + CRC_LEN].copy_from_slice(&crc.to_le_bytes());
+
+            if let Err((_, buffer)) = self.uart.transmit_buffer(buffer, len + CRC_LEN) {
+                self.frame_lost(buffer);
+            }
+        });
+    }
+
+    /// Count the events in the frame in `buffer`, which was not sent, as
+    /// dropped.
+    fn frame_lost(&self, buffer: &'static mut [u8]) {
+        let count = u16::from_le_bytes([buffer[6], buffer[7]]) as u32;
+        let dropped = u32::from_le_bytes([buffer[12], buffer[13], buffer[14], buffer[15]]);
+        self.dropped.set(
+            self.dropped
+                .get()
+                .saturating_add(count)
+                .saturating_add(dropped),
+        );
+        self.tx_buffer.replace(buffer);
+    }
+}
+
+impl<'a, A: Alarm<'a>, U: uart::Transmit<'a>> Tracer for RingBufferTracer<'a, A, U> {
+    fn trace(&self, event: Event) {
+        match event {
+            Event::SwitchToProcess(process) => {
+                self.record(KIND_SWITCH_TO_PROCESS, 0, Some(process), 0, 0)
+            }
+            Event::SwitchToKernel(process, reason) => {
+                let reason = match reason {
+                    Some(ContextSwitchReason::SyscallFired { .. }) => 0,
+                    Some(ContextSwitchReason::Fault) => 1,
+                    Some(ContextSwitchReason::Interrupted) => 2,
+                    None => 3,
+                };
+                self.record(KIND_SWITCH_TO_KERNEL, reason, Some(process), 0, 0)
+            }
+            Event::SyscallEntry(process, syscall) => {
+                let (class, arg0, arg1) = syscall_fields(&syscall);
+                self.record(KIND_SYSCALL_ENTRY, class, Some(process), arg0, arg1)
+            }
+            Event::SyscallExit(process) => self.record(KIND_SYSCALL_EXIT, 0, Some(process), 0, 0),
+            Event::UpcallScheduled(process, upcall_id) => self.record(
+                KIND_UPCALL_SCHEDULED,
+                0,
+                Some(process),
+                upcall_id.driver_num,
+                upcall_id.subscribe_num,
+            ),
+            Event::InterruptServiceStart => {
+                self.record(KIND_INTERRUPT_SERVICE_START, 0, None, 0, 0)
+            }
+            Event::Interrupt(interrupt) => {
+                self.record(KIND_INTERRUPT, 0, None, interrupt as usize, 0)
+            }
+            Event::InterruptServiceEnd => self.record(KIND_INTERRUPT_SERVICE_END, 0, None, 0, 0),
+            Event::DeferredCallStart(index) => {
+                self.record(KIND_DEFERRED_CALL_START, 0, None, index, 0)
+            }
+            Event::DeferredCallEnd(index) => self.record(KIND_DEFERRED_CALL_END, 0, None, index, 0),
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>, U: uart::Transmit<'a>> time::AlarmClient for RingBufferTracer<'a, A, U> {
+    fn alarm(&self) {
+        self.drain();
+        self.start();
+    }
+}
+
+impl<'a, A: Alarm<'a>, U: uart::Transmit<'a>> uart::TransmitClient for RingBufferTracer<'a, A, U> {
+    fn transmitted_buffer(
+        &self,
+        tx_buffer: &'static mut [u8],
+        _tx_len: usize,
+        rval: Result<(), ErrorCode>,
+    ) {
+        if rval.is_err() {
+            self.frame_lost(tx_buffer);
+        } else {
+            self.tx_buffer.replace(tx_buffer);
+        }
+        // Keep up with bursts of events instead of waiting for the alarm.
+        let backlog = self.records.map_or(0, |records| records.len());
+        if backlog >= RECORDS_PER_FRAME {
+            self.drain();
+        }
+    }
+}
@@ -8,6 +8,7 @@ use core::fmt::Write;
 use cortexm4f::{CortexM4F, CortexMVariant};
 use kernel::platform::chip::Chip;
 use kernel::platform::chip::InterruptService;
+use kernel::trace;
 
 pub struct Apollo3<I: InterruptService + 'static> {
     mpu: cortexm4f::mpu::MPU,
@@ -100,6 +101,7 @@ impl<I: InterruptService + 'static> Chip for Apollo3<I> {
         unsafe {
             loop {
                 if let Some(interrupt) = cortexm4f::nvic::next_pending() {
+                    trace::trace(trace::Event::Interrupt(interrupt));
                     if !self.interrupt_service.service_interrupt(interrupt) {
                         panic!("unhandled interrupt, {}", interrupt);
                     }
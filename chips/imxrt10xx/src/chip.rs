@@ -8,6 +8,7 @@ use core::fmt::Write;
 use cortexm7::{CortexM7, CortexMVariant};
 use kernel::debug;
 use kernel::platform::chip::{Chip, InterruptService};
+use kernel::trace;
 
 use crate::nvic;
 
@@ -110,6 +111,7 @@ impl<I: InterruptService + 'static> Chip for Imxrt10xx<I> {
         unsafe {
             loop {
                 if let Some(interrupt) = cortexm7::nvic::next_pending() {
+                    trace::trace(trace::Event::Interrupt(interrupt));
                     let handled = self.interrupt_service.service_interrupt(interrupt);
                     assert!(handled, "Unhandled interrupt number {}", interrupt);
                     let n = cortexm7::nvic::Nvic::new(interrupt);
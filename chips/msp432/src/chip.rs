@@ -9,6 +9,7 @@ use kernel::platform::chip::Chip;
 use crate::nvic;
 use crate::wdt;
 use kernel::platform::chip::InterruptService;
+use kernel::trace;
 
 pub struct Msp432<'a, I: InterruptService + 'a> {
     mpu: cortexm4::mpu::MPU,
@@ -112,6 +113,7 @@ impl<'a, I: InterruptService + 'a> Chip for Msp432<'a, I> {
         unsafe {
             loop {
                 if let Some(interrupt) = cortexm4::nvic::next_pending() {
+                    trace::trace(trace::Event::Interrupt(interrupt));
                     if !self.interrupt_service.service_interrupt(interrupt) {
                         panic!("unhandled interrupt {}", interrupt);
                     }
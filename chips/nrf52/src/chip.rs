@@ -5,6 +5,7 @@
 use core::fmt::Write;
 use cortexm4f::{nvic, CortexM4F, CortexMVariant};
 use kernel::platform::chip::InterruptService;
+use kernel::trace;
 
 pub struct NRF52<'a, I: InterruptService + 'a> {
     mpu: cortexm4f::mpu::MPU,
@@ -120,6 +121,7 @@ impl<'a, I: InterruptService + 'a> kernel::platform::chip::Chip for NRF52<'a, I>
         unsafe {
             loop {
                 if let Some(interrupt) = nvic::next_pending() {
+                    trace::trace(trace::Event::Interrupt(interrupt));
                     if !self.interrupt_service.service_interrupt(interrupt) {
                         panic!("unhandled interrupt {}", interrupt);
                     }
@@ -4,6 +4,7 @@
 
 use kernel::platform::chip::Chip;
 use kernel::platform::chip::InterruptService;
+use kernel::trace;
 
 use crate::{cpuss, gpio, hsiom, peri, scb, srss, tcpwm};
 use cortexm0p::{CortexM0P, CortexMVariant};
@@ -61,6 +62,7 @@ impl<I: InterruptService> Chip for Psoc62xa<'_, I> {
         unsafe {
             loop {
                 if let Some(interrupt) = cortexm0p::nvic::next_pending() {
+                    trace::trace(trace::Event::Interrupt(interrupt));
                     if !self.interrupt_service.service_interrupt(interrupt) {
                         panic!("unhandled interrupt {}", interrupt);
                     }
@@ -7,6 +7,7 @@
 use core::fmt::Write;
 use kernel::platform::chip::Chip;
 use kernel::platform::chip::InterruptService;
+use kernel::trace;
 
 use crate::adc;
 use crate::clocks::Clocks;
@@ -66,6 +67,7 @@ impl<I: InterruptService> Chip for Rp2040<'_, I> {
             };
             loop {
                 if let Some(interrupt) = cortexm0p::nvic::next_pending_with_mask(mask) {
+                    trace::trace(trace::Event::Interrupt(interrupt));
                     // ignore SIO_IRQ_PROC1 as it is intended for processor 1
                     // not able to unset its pending status
                     // probably only processor 1 can unset the pending by reading the fifo
@@ -9,6 +9,7 @@ use crate::pm;
 use core::fmt::Write;
 use cortexm4::{CortexM4, CortexMVariant};
 use kernel::platform::chip::{Chip, InterruptService};
+use kernel::trace;
 
 pub struct Sam4l<I: InterruptService + 'static> {
     mpu: cortexm4::mpu::MPU,
@@ -243,6 +244,7 @@ impl<I: InterruptService + 'static> Chip for Sam4l<I> {
         unsafe {
             loop {
                 if let Some(interrupt) = cortexm4::nvic::next_pending() {
+                    trace::trace(trace::Event::Interrupt(interrupt));
                     match self.interrupt_service.service_interrupt(interrupt) {
                         true => {}
                         false => panic!("unhandled interrupt"),
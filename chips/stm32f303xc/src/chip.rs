@@ -8,6 +8,7 @@ use core::fmt::Write;
 use cortexm4f::{CortexM4F, CortexMVariant};
 use kernel::platform::chip::Chip;
 use kernel::platform::chip::InterruptService;
+use kernel::trace;
 
 use crate::nvic;
 
@@ -109,6 +110,7 @@ impl<'a, I: InterruptService + 'a> Chip for Stm32f3xx<'a, I> {
         unsafe {
             loop {
                 if let Some(interrupt) = cortexm4f::nvic::next_pending() {
+                    trace::trace(trace::Event::Interrupt(interrupt));
                     if !self.interrupt_service.service_interrupt(interrupt) {
                         panic!("unhandled interrupt {}", interrupt);
                     }
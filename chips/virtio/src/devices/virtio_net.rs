@@ -4,6 +4,7 @@
 
 use core::cell::Cell;
 
+use kernel::hil::ethernet::{EthernetAdapterDatapath, EthernetAdapterDatapathClient};
 use kernel::utilities::cells::OptionalCell;
 use kernel::utilities::registers::{register_bitfields, LocalRegisterCopy};
 use kernel::ErrorCode;
@@ -46,9 +47,11 @@ pub struct VirtIONet<'a> {
     rxqueue: &'a SplitVirtqueue<'static, 'static, 2>,
     txqueue: &'a SplitVirtqueue<'static, 'static, 2>,
     tx_header: OptionalCell<&'static mut [u8; 12]>,
+    tx_frame_info: Cell<(u16, usize)>,
     rx_header: OptionalCell<&'static mut [u8]>,
     rx_buffer: OptionalCell<&'static mut [u8]>,
-    client: OptionalCell<&'a dyn VirtIONetClient>,
+    rx_enabled: Cell<bool>,
+    client: OptionalCell<&'a dyn EthernetAdapterDatapathClient>,
 }
 
 impl<'a> VirtIONet<'a> {
@@ -68,9 +71,11 @@ impl<'a> VirtIONet<'a> {
             rxqueue,
             txqueue,
             tx_header: OptionalCell::new(tx_header),
-            client: OptionalCell::empty(),
+            tx_frame_info: Cell::new((0, 0)),
             rx_header: OptionalCell::new(rx_header),
             rx_buffer: OptionalCell::new(rx_buffer),
+            rx_enabled: Cell::new(false),
+            client: OptionalCell::empty(),
         }
     }
 
@@ -78,22 +83,27 @@ impl<'a> VirtIONet<'a> {
         self.id.get()
     }
 
-    pub fn set_client(&self, client: &'a dyn VirtIONetClient) {
-        self.client.set(client);
-    }
-
-    // This is not executed as part of the `device_initialized` hook to avoid
-    // missing any packets if a client has not been registered, and because this
-    // device can be used in a transmit-only fashion before invoking this
-    // function.
-    pub fn enable_rx(&self) {
-        // To start operation, put the receive buffers into the device initially
-        let rx_buffer = self.rx_buffer.take().unwrap();
+    // Hand the receive buffers to the device. This is not executed as part
+    // of the `device_initialized` hook to avoid missing any packets before a
+    // client has been registered, and because this device can be used in a
+    // transmit-only fashion. Once provided, the buffers are kept in the
+    // receive queue even if reception is disabled, and frames received in
+    // the meantime are dropped.
+    fn reinsert_rx_buffer(&self) {
+        let (rx_header, rx_buffer) = match (self.rx_header.take(), self.rx_buffer.take()) {
+            (Some(header), Some(buffer)) => (header, buffer),
+            (header, buffer) => {
+                // Buffers are already owned by the device.
+                header.map(|h| self.rx_header.replace(h));
+                buffer.map(|b| self.rx_buffer.replace(b));
+                return;
+            }
+        };
         let rx_buffer_len = rx_buffer.len();
 
         let mut buffer_chain = [
             Some(VirtqueueBuffer {
-                buf: self.rx_header.take().unwrap(),
+                buf: rx_header,
                 len: 12,
                 device_writeable: true,
             }),
@@ -108,53 +118,46 @@ impl<'a> VirtIONet<'a> {
             .provide_buffer_chain(&mut buffer_chain)
             .unwrap();
     }
+}
+
+impl<'a> EthernetAdapterDatapath<'a> for VirtIONet<'a> {
+    fn set_client(&self, client: &'a dyn EthernetAdapterDatapathClient) {
+        self.client.set(client);
+    }
 
-    pub fn return_rx_buffer(&self, buf: &'static mut [u8]) {
-        assert!(self.rx_buffer.is_none());
-        assert!(self.rx_header.is_some());
-        self.rx_buffer.replace(buf);
+    fn enable_receive(&self) {
+        self.rx_enabled.set(true);
+        self.reinsert_rx_buffer();
+    }
 
-        // Re-register the RX buffer with the Virtqueue:
-        self.enable_rx();
+    fn disable_receive(&self) {
+        self.rx_enabled.set(false);
     }
 
-    pub fn send_packet(
+    fn transmit_frame(
         &self,
-        packet: &'static mut [u8],
-        packet_len: usize,
-    ) -> Result<(), (&'static mut [u8], ErrorCode)> {
+        frame_buffer: &'static mut [u8],
+        len: u16,
+        transmission_identifier: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if usize::from(len) > frame_buffer.len() {
+            return Err((ErrorCode::SIZE, frame_buffer));
+        }
+
         // Try to get a hold of the header buffer
         //
         // Otherwise, the device is currently busy transmissing a buffer
         //
         // TODO: Implement simultaneous transmissions
-        let mut packet_buf = Some(VirtqueueBuffer {
-            buf: packet,
-            len: packet_len,
-            device_writeable: false,
-        });
-
-        let header_buf = self
-            .tx_header
-            .take()
-            .ok_or(ErrorCode::BUSY)
-            .map_err(|ret| (packet_buf.take().unwrap().buf, ret))?;
-
-        // Write the header
-        //
-        // TODO: Can this be done more elegantly using a struct of registers?
-        header_buf[0] = 0; // flags -> we don't want checksumming
-        header_buf[1] = 0; // gso -> no checksumming or fragmentation
-        header_buf[2] = 0; // hdr_len_low
-        header_buf[3] = 0; // hdr_len_high
-        header_buf[4] = 0; // gso_size
-        header_buf[5] = 0; // gso_size
-        header_buf[6] = 0; // csum_start
-        header_buf[7] = 0; // csum_start
-        header_buf[8] = 0; // csum_offset
-        header_buf[9] = 0; // csum_offsetb
-        header_buf[10] = 0; // num_buffers
-        header_buf[11] = 0; // num_buffers
+        let header_buf = match self.tx_header.take() {
+            Some(header_buf) => header_buf,
+            None => return Err((ErrorCode::BUSY, frame_buffer)),
+        };
+
+        // Write the header. We don't want checksumming or segmentation
+        // offloading, so the entire header (flags, gso_type, hdr_len,
+        // gso_size, csum_start, csum_offset, num_buffers) is zero.
+        header_buf.fill(0);
 
         let mut buffer_chain = [
             Some(VirtqueueBuffer {
@@ -162,14 +165,24 @@ impl<'a> VirtIONet<'a> {
                 len: 12,
                 device_writeable: false,
             }),
-            packet_buf.take(),
+            Some(VirtqueueBuffer {
+                buf: frame_buffer,
+                len: usize::from(len),
+                device_writeable: false,
+            }),
         ];
 
-        self.txqueue
-            .provide_buffer_chain(&mut buffer_chain)
-            .map_err(move |ret| (buffer_chain[1].take().unwrap().buf, ret))?;
-
-        Ok(())
+        match self.txqueue.provide_buffer_chain(&mut buffer_chain) {
+            Ok(()) => {
+                self.tx_frame_info.set((len, transmission_identifier));
+                Ok(())
+            }
+            Err(ecode) => {
+                let header_buf = buffer_chain[0].take().unwrap().buf;
+                self.tx_header.replace(header_buf.try_into().unwrap());
+                Err((ecode, buffer_chain[1].take().unwrap().buf))
+            }
+        }
     }
 }
 
@@ -188,9 +201,15 @@ impl SplitVirtqueueClient<'static> for VirtIONet<'_> {
             self.rx_header.replace(rx_header);
 
             let rx_buffer = buffer_chain[1].take().expect("No rx content buffer").buf;
-            self.client.map(move |client| {
-                client.packet_received(self.id.get(), rx_buffer, bytes_used - 12)
-            });
+            let frame_len = bytes_used.saturating_sub(12).min(rx_buffer.len());
+            if self.rx_enabled.get() {
+                self.client
+                    .map(|client| client.received_frame(&rx_buffer[..frame_len], None));
+            }
+
+            // Re-register the RX buffer with the Virtqueue:
+            self.rx_buffer.replace(rx_buffer);
+            self.reinsert_rx_buffer();
         } else if queue_number == self.txqueue.queue_number().unwrap() {
             // Sent a packet
 
@@ -198,8 +217,10 @@ impl SplitVirtqueueClient<'static> for VirtIONet<'_> {
             self.tx_header.replace(header_buf.try_into().unwrap());
 
             let packet_buf = buffer_chain[1].take().expect("No packet buffer").buf;
-            self.client
-                .map(move |client| client.packet_sent(self.id.get(), packet_buf));
+            let (len, transmission_identifier) = self.tx_frame_info.get();
+            self.client.map(move |client| {
+                client.transmit_frame_done(Ok(()), packet_buf, len, transmission_identifier, None)
+            });
         } else {
             panic!("Callback from unknown queue");
         }
@@ -240,8 +261,3 @@ impl VirtIODeviceDriver for VirtIONet<'_> {
         VirtIODeviceType::NetworkCard
     }
 }
-
-pub trait VirtIONetClient {
-    fn packet_sent(&self, id: usize, buffer: &'static mut [u8]);
-    fn packet_received(&self, id: usize, buffer: &'static mut [u8], len: usize);
-}
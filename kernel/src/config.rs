@@ -80,6 +80,14 @@ pub(crate) struct Config {
     // credentials checking, e.g., whether elf2tab and tockloader are generating
     // properly formatted footers.
     pub(crate) debug_process_credentials: bool,
+
+    /// Whether the kernel should pass structured events to the tracer.
+    ///
+    /// If enabled, the kernel passes syscalls, upcalls, context switches,
+    /// interrupt service and deferred calls to the tracer set with
+    /// `kernel::trace::set_tracer()`. If disabled, the trace points are
+    /// compiled out.
+    pub(crate) trace_events: bool,
 }
 
 /// A unique instance of `Config` where compile-time configuration options are
@@ -92,4 +100,5 @@ pub(crate) const CONFIG: Config = Config {
     debug_load_processes: cfg!(feature = "debug_load_processes"),
     debug_panics: !cfg!(feature = "no_debug_panics"),
     debug_process_credentials: cfg!(feature = "debug_process_credentials"),
+    trace_events: cfg!(feature = "trace_events"),
 };
@@ -0,0 +1,33 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Interface for crash dumps kept across resets.
+//!
+//! When a process faults or the kernel panics, a crash dump recorder can save
+//! the state of the process or kernel to memory that survives a reset. This
+//! trait gives tools such as the process console access to the stored dumps,
+//! independent of how and where they are stored.
+
+use crate::utilities::binary_write::BinaryWrite;
+
+/// Access to the stored crash dumps.
+///
+/// Dumps are numbered from 0, oldest first.
+pub trait CrashDumpStore {
+    /// Number of crash dumps currently stored.
+    fn count(&self) -> usize;
+
+    /// Print the stored dumps to `writer`, each with a short description and
+    /// its contents in a form that host-side tools can decode.
+    ///
+    /// Like [`ProcessPrinter::print_overview()`](crate::process::ProcessPrinter::print_overview),
+    /// this is synchronous but supports writers with a small buffer: if it
+    /// returns `Some(offset)`, the writer did not accept all output and the
+    /// caller should call `print()` again with `offset` once the writer is
+    /// ready. The first call passes an `offset` of 0.
+    fn print(&self, writer: &mut dyn BinaryWrite, offset: usize) -> Option<usize>;
+
+    /// Delete all stored crash dumps.
+    fn clear(&self);
+}
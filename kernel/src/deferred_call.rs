@@ -53,6 +53,7 @@
 //! some_capsule.register();
 //! ```
 
+use crate::trace;
 use crate::utilities::cells::OptionalCell;
 use core::cell::Cell;
 use core::marker::Copy;
@@ -210,7 +211,9 @@ impl DeferredCall {
             let new_val = val & !(1 << bit);
             bitmask.set(new_val);
             defcalls[bit].map(|dc| {
+                trace::trace(trace::Event::DeferredCallStart(bit));
                 dc.handle_deferred_call();
+                trace::trace(trace::Event::DeferredCallEnd(bit));
                 bit
             })
         }
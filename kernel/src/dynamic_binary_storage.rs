@@ -0,0 +1,544 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Storing new process binaries in flash and loading them at runtime.
+//!
+//! `SequentialDynamicBinaryStorage` writes a new process binary (a TBF) into
+//! the free part of the app flash region through a
+//! [`NonvolatileStorage`](crate::hil::nonvolatile_storage::NonvolatileStorage)
+//! and then asks a [`ProcessLoadingDynamic`] loader to check it and create a
+//! process for it. This lets boards update individual applications without
+//! reflashing the kernel or the other applications.
+//!
+//! Storing a process binary works in three steps:
+//!
+//! 1. `setup()` reserves space for a binary of the given length, reusing the
+//!    flash of unloaded process binaries where possible. The reserved region
+//!    starts out covered by a padding TBF header, so an incomplete binary is
+//!    skipped when booting.
+//! 2. `write()` stores the binary in chunks, in any order. The first
+//!    `PADDING_TBF_HEADER_LENGTH` bytes of the binary are kept in RAM and the
+//!    padding header stays in flash in their place.
+//! 3. `load()` checks that the TBF base header matches the length passed to
+//!    `setup()`, writes it over the padding header and loads the binary into a
+//!    new process. Once loaded, the binary is also discovered on subsequent
+//!    boots. If loading fails, the padding header is written back.
+//!
+//! `abort()` discards the binary. As the reserved region is still covered by
+//! the padding header, nothing needs to be written to flash.
+//!
+//! `unload()` marks the process binary of a terminated process as disabled in
+//! its TBF header and then removes the process, so that the binary is not
+//! loaded on later boots and its flash can be reused by the next process
+//! binary that is stored.
+//!
+//! The `NonvolatileStorage` must use the same addresses as the memory-mapped
+//! flash the process binaries are executed from.
+
+use core::cell::Cell;
+
+use crate::capabilities::ProcessManagementCapability;
+use crate::hil::nonvolatile_storage::{NonvolatileStorage, NonvolatileStorageClient};
+use crate::process::{NewProcessBinaryLocation, ProcessId, ProcessLoadError};
+use crate::process::{ProcessLoadingDynamic, ProcessLoadingDynamicClient};
+use crate::utilities::cells::{OptionalCell, TakeCell};
+use crate::ErrorCode;
+
+/// Length of a TBF header that only consists of the base header, which marks
+/// a region of app flash as padding.
+pub const PADDING_TBF_HEADER_LENGTH: usize = 16;
+
+/// Bit in the flags word of the TBF base header that marks a process binary
+/// as enabled.
+const TBF_FLAG_ENABLED: u32 = 1;
+
+/// Storage of a new process binary in flash.
+pub trait DynamicBinaryStore<'a> {
+    /// Set the client to receive callbacks about storing the process binary.
+    fn set_storage_client(&self, client: &'a dyn DynamicBinaryStoreClient);
+
+    /// Reserve space in flash for a process binary of `app_length` bytes.
+    ///
+    /// Completion is signalled through `setup_done()`.
+    fn setup(&self, app_length: usize) -> Result<(), ErrorCode>;
+
+    /// Write `length` bytes of `buffer` at `offset` within the process binary.
+    ///
+    /// Chunks can be written in any order. Completion is signalled through
+    /// `write_done()`. On error the buffer is returned, unless the underlying
+    /// storage failed to start the write.
+    fn write(
+        &self,
+        buffer: &'static mut [u8],
+        offset: usize,
+        length: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+
+    /// Discard the process binary being stored.
+    ///
+    /// The binary is discarded when this returns `Ok(())`.
+    fn abort(&self) -> Result<(), ErrorCode>;
+}
+
+/// Client for storing a new process binary.
+pub trait DynamicBinaryStoreClient {
+    /// Space for the process binary was reserved.
+    fn setup_done(&self, result: Result<(), ErrorCode>);
+
+    /// A chunk of the process binary was written.
+    fn write_done(&self, result: Result<(), ErrorCode>, buffer: &'static mut [u8], length: usize);
+}
+
+/// Loading of a process binary stored through `DynamicBinaryStore`.
+pub trait DynamicProcessLoad<'a> {
+    /// Set the client to receive the result of loading.
+    fn set_load_client(&self, client: &'a dyn DynamicProcessLoadClient);
+
+    /// Check the stored process binary and load it into a new process.
+    ///
+    /// Completion is signalled through `load_done()`.
+    fn load(&self) -> Result<(), ErrorCode>;
+}
+
+/// Client for loading a stored process binary.
+pub trait DynamicProcessLoadClient {
+    /// Loading the process binary finished.
+    ///
+    /// If loading failed, the process binary is covered by a padding header
+    /// again and can be rewritten, loaded again or aborted.
+    fn load_done(&self, result: Result<(), ProcessLoadError>);
+}
+
+/// Removal of running processes and their process binaries.
+pub trait DynamicProcessUnload<'a> {
+    /// Set the client to receive the result of unloading.
+    fn set_unload_client(&self, client: &'a dyn DynamicProcessUnloadClient);
+
+    /// Disable the process binary of the process `processid` and remove the
+    /// process.
+    ///
+    /// The process must be terminated. It is removed once disabling its
+    /// process binary has started, and completion of disabling the process
+    /// binary is signalled through `unload_done()`. If an error is returned,
+    /// the process and its process binary are unchanged.
+    fn unload(
+        &self,
+        processid: ProcessId,
+        capability: &dyn ProcessManagementCapability,
+    ) -> Result<(), ErrorCode>;
+}
+
+/// Client for unloading a process.
+pub trait DynamicProcessUnloadClient {
+    /// The process binary of the unloaded process was disabled.
+    fn unload_done(&self, result: Result<(), ErrorCode>);
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum State {
+    /// No process binary is being stored.
+    Idle,
+    /// Writing the padding header behind the reserved region.
+    SetupPaddingAfter,
+    /// Writing the padding header over the reserved region.
+    SetupPlaceholder,
+    /// Writing the padding header in front of the reserved region.
+    SetupPaddingBefore,
+    /// Space is reserved and chunks of the binary can be written.
+    Ready,
+    /// Writing a chunk of the binary.
+    AppWrite,
+    /// Writing the TBF header of the binary over the padding header.
+    LoadHeader,
+    /// Loading the stored binary.
+    Load,
+    /// Writing the padding header back after loading the binary failed.
+    LoadFailed,
+    /// Disabling the binary of an unloaded process.
+    Unload,
+}
+
+/// Stores process binaries sequentially after the existing process binaries
+/// and loads them with a `ProcessLoadingDynamic` loader.
+pub struct SequentialDynamicBinaryStorage<'a, F: NonvolatileStorage<'a>> {
+    /// Storage for the app flash region.
+    storage: &'a F,
+    /// Loader that checks and loads stored process binaries.
+    loader: &'a dyn ProcessLoadingDynamic<'a>,
+    /// Buffer for writing padding TBF headers.
+    buffer: TakeCell<'static, [u8]>,
+    /// Address and length of the process binary being stored.
+    binary: OptionalCell<(usize, usize)>,
+    /// Start of the process binary being stored, which is only written to
+    /// flash by `load()`.
+    header: Cell<[u8; PADDING_TBF_HEADER_LENGTH]>,
+    /// Offset of the chunk being written.
+    write_offset: Cell<usize>,
+    /// Why loading the process binary failed, while the padding header is
+    /// written back.
+    load_error: OptionalCell<ProcessLoadError>,
+    /// Where the process binary being set up is placed in flash.
+    location: OptionalCell<NewProcessBinaryLocation>,
+    state: Cell<State>,
+    storage_client: OptionalCell<&'a dyn DynamicBinaryStoreClient>,
+    load_client: OptionalCell<&'a dyn DynamicProcessLoadClient>,
+    unload_client: OptionalCell<&'a dyn DynamicProcessUnloadClient>,
+}
+
+impl<'a, F: NonvolatileStorage<'a>> SequentialDynamicBinaryStorage<'a, F> {
+    /// `buffer` must be at least `PADDING_TBF_HEADER_LENGTH` bytes long.
+    pub fn new(
+        storage: &'a F,
+        loader: &'a dyn ProcessLoadingDynamic<'a>,
+        buffer: &'static mut [u8],
+    ) -> Self {
+        Self {
+            storage,
+            loader,
+            buffer: TakeCell::new(buffer),
+            binary: OptionalCell::empty(),
+            header: Cell::new([0; PADDING_TBF_HEADER_LENGTH]),
+            write_offset: Cell::new(0),
+            load_error: OptionalCell::empty(),
+            location: OptionalCell::empty(),
+            state: Cell::new(State::Idle),
+            storage_client: OptionalCell::empty(),
+            load_client: OptionalCell::empty(),
+            unload_client: OptionalCell::empty(),
+        }
+    }
+
+    /// A padding TBF header covering `length` bytes.
+    fn padding_header(length: usize) -> Result<[u8; PADDING_TBF_HEADER_LENGTH], ErrorCode> {
+        let total_size = u32::try_from(length).map_err(|_| ErrorCode::SIZE)?;
+        let version = 2_u32;
+        let header_size = PADDING_TBF_HEADER_LENGTH as u32;
+        let flags = 0_u32;
+        let first_word = version | (header_size << 16);
+        let checksum = first_word ^ total_size ^ flags;
+        let mut header = [0; PADDING_TBF_HEADER_LENGTH];
+        for (chunk, word) in header
+            .chunks_exact_mut(4)
+            .zip([first_word, total_size, flags, checksum])
+        {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        Ok(header)
+    }
+
+    /// Write a padding TBF header covering `length` bytes at `address`.
+    fn write_padding_header(&self, address: usize, length: usize) -> Result<(), ErrorCode> {
+        self.write_bytes(address, &Self::padding_header(length)?)
+    }
+
+    /// Write `bytes` to flash at `address`.
+    fn write_bytes(&self, address: usize, bytes: &[u8]) -> Result<(), ErrorCode> {
+        let buffer = self.buffer.take().ok_or(ErrorCode::NOMEM)?;
+        if buffer.len() < bytes.len() {
+            self.buffer.replace(buffer);
+            return Err(ErrorCode::SIZE);
+        }
+        buffer[..bytes.len()].copy_from_slice(bytes);
+        self.storage.write(buffer, address, bytes.len())
+    }
+
+    /// Start writing the next padding header of the setup that follows
+    /// `state`.
+    ///
+    /// The padding behind the reserved region is written first and the
+    /// padding in front of it last, so that the process binaries in flash
+    /// stay linked together if the setup is interrupted. Returns `false` once
+    /// all padding headers are written.
+    fn setup_write_next(&self, state: State) -> Result<bool, ErrorCode> {
+        let location = self.location.get().ok_or(ErrorCode::FAIL)?;
+        let (_, app_length) = self.binary.get().ok_or(ErrorCode::FAIL)?;
+        let steps = [
+            (State::SetupPaddingAfter, location.padding_after),
+            (
+                State::SetupPlaceholder,
+                Some((location.address, app_length)),
+            ),
+            (State::SetupPaddingBefore, location.padding_before),
+        ];
+        let first = steps
+            .iter()
+            .position(|(step, _)| *step == state)
+            .map_or(0, |i| i + 1);
+        for (step, region) in steps.iter().skip(first) {
+            if let Some((address, length)) = region {
+                self.write_padding_header(*address, *length)?;
+                self.state.set(*step);
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Check that `header` is a TBF header for a binary of `app_length`
+    /// bytes.
+    ///
+    /// The header links the process binaries in flash together, so a wrong
+    /// length would hide every binary stored after this one.
+    fn check_header(
+        header: &[u8; PADDING_TBF_HEADER_LENGTH],
+        app_length: usize,
+    ) -> Result<(), ErrorCode> {
+        let lengths = header
+            .get(0..8)
+            .and_then(|h| h.try_into().ok())
+            .ok_or(ErrorCode::INVAL)?;
+        match tock_tbf::parse::parse_tbf_header_lengths(lengths) {
+            Ok((_, _, total_size)) if total_size as usize == app_length => Ok(()),
+            _ => Err(ErrorCode::INVAL),
+        }
+    }
+
+    /// Write the padding header back over the binary after loading it failed
+    /// with `error`, and then report the failure.
+    fn load_failed(&self, error: ProcessLoadError) {
+        let (address, app_length) = self.binary.get().unwrap_or((0, 0));
+        self.state.set(State::Ready);
+        if self.write_padding_header(address, app_length).is_ok() {
+            self.load_error.set(error);
+            self.state.set(State::LoadFailed);
+        } else {
+            self.load_client.map(|client| client.load_done(Err(error)));
+        }
+    }
+
+    fn setup_done(&self, result: Result<(), ErrorCode>) {
+        self.location.clear();
+        if result.is_ok() {
+            self.state.set(State::Ready);
+        } else {
+            self.binary.clear();
+            self.state.set(State::Idle);
+        }
+        self.storage_client.map(|client| client.setup_done(result));
+    }
+}
+
+impl<'a, F: NonvolatileStorage<'a>> DynamicBinaryStore<'a>
+    for SequentialDynamicBinaryStorage<'a, F>
+{
+    fn set_storage_client(&self, client: &'a dyn DynamicBinaryStoreClient) {
+        self.storage_client.set(client);
+    }
+
+    fn setup(&self, app_length: usize) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+
+        let location = self.loader.find_new_process_binary_location(app_length)?;
+        self.location.set(location);
+        self.binary.set((location.address, app_length));
+        self.header.set([0; PADDING_TBF_HEADER_LENGTH]);
+        match self.setup_write_next(State::Idle) {
+            Ok(true) => Ok(()),
+            result => {
+                self.location.clear();
+                self.binary.clear();
+                self.state.set(State::Idle);
+                Err(result.err().unwrap_or(ErrorCode::FAIL))
+            }
+        }
+    }
+
+    fn write(
+        &self,
+        buffer: &'static mut [u8],
+        offset: usize,
+        length: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.state.get() != State::Ready {
+            return Err((ErrorCode::BUSY, buffer));
+        }
+        let (address, app_length) = match self.binary.get() {
+            Some(binary) => binary,
+            None => return Err((ErrorCode::FAIL, buffer)),
+        };
+
+        if length == 0
+            || length > buffer.len()
+            || offset
+                .checked_add(length)
+                .map_or(true, |end| end > app_length)
+        {
+            return Err((ErrorCode::INVAL, buffer));
+        }
+
+        // Keep the start of the binary in RAM and write the padding header
+        // from `setup()` in its place, so that an incomplete binary is
+        // skipped when booting. The chunk is restored in `write_done()`.
+        if offset < PADDING_TBF_HEADER_LENGTH {
+            let padding = match Self::padding_header(app_length) {
+                Ok(padding) => padding,
+                Err(e) => return Err((e, buffer)),
+            };
+            let mut header = self.header.get();
+            let overlap = buffer[..length].iter_mut().zip(header[offset..].iter_mut());
+            for ((byte, kept), padding) in overlap.zip(&padding[offset..]) {
+                *kept = *byte;
+                *byte = *padding;
+            }
+            self.header.set(header);
+        }
+        self.write_offset.set(offset);
+
+        // The storage does not return the buffer if it fails to start the
+        // write, so an empty buffer is returned in its place. This storage
+        // has exclusive use of the `NonvolatileStorage` and is not busy in
+        // the `Ready` state, so this does not happen in practice.
+        self.storage
+            .write(buffer, address + offset, length)
+            .map_err(|e| (e, &mut [][..]))?;
+        self.state.set(State::AppWrite);
+        Ok(())
+    }
+
+    fn abort(&self) -> Result<(), ErrorCode> {
+        match self.state.get() {
+            State::Ready => {}
+            State::Idle => return Err(ErrorCode::ALREADY),
+            _ => return Err(ErrorCode::BUSY),
+        }
+        // The reserved region is still covered by the padding header written
+        // by `setup()`.
+        self.binary.clear();
+        self.state.set(State::Idle);
+        Ok(())
+    }
+}
+
+impl<'a, F: NonvolatileStorage<'a>> DynamicProcessLoad<'a>
+    for SequentialDynamicBinaryStorage<'a, F>
+{
+    fn set_load_client(&self, client: &'a dyn DynamicProcessLoadClient) {
+        self.load_client.set(client);
+    }
+
+    fn load(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Ready {
+            return Err(ErrorCode::BUSY);
+        }
+        let (address, app_length) = self.binary.get().ok_or(ErrorCode::FAIL)?;
+        let header = self.header.get();
+        Self::check_header(&header, app_length)?;
+        self.write_bytes(address, &header)?;
+        self.state.set(State::LoadHeader);
+        Ok(())
+    }
+}
+
+impl<'a, F: NonvolatileStorage<'a>> DynamicProcessUnload<'a>
+    for SequentialDynamicBinaryStorage<'a, F>
+{
+    fn set_unload_client(&self, client: &'a dyn DynamicProcessUnloadClient) {
+        self.unload_client.set(client);
+    }
+
+    fn unload(
+        &self,
+        processid: ProcessId,
+        capability: &dyn ProcessManagementCapability,
+    ) -> Result<(), ErrorCode> {
+        // The flash cannot be written while a process binary is being stored.
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        let flash = self.loader.unloadable_process_flash(processid)?;
+
+        // Clear the enabled flag and update the checksum, which is the XOR of
+        // all header words, to match.
+        let word = |offset: usize| {
+            flash
+                .get(offset..offset + 4)
+                .and_then(|w| w.try_into().ok())
+                .map(u32::from_le_bytes)
+        };
+        let (flags, checksum) = word(8).zip(word(12)).ok_or(ErrorCode::FAIL)?;
+        let disabled_flags = flags & !TBF_FLAG_ENABLED;
+        let disabled_checksum = checksum ^ flags ^ disabled_flags;
+        let mut flags_and_checksum = [0; 8];
+        flags_and_checksum[..4].copy_from_slice(&disabled_flags.to_le_bytes());
+        flags_and_checksum[4..].copy_from_slice(&disabled_checksum.to_le_bytes());
+        self.write_bytes(flash.as_ptr() as usize + 8, &flags_and_checksum)?;
+        self.state.set(State::Unload);
+
+        // This cannot fail as the process was checked above.
+        self.loader
+            .unload_process(processid, capability)
+            .map(|_| ())
+    }
+}
+
+impl<'a, F: NonvolatileStorage<'a>> NonvolatileStorageClient
+    for SequentialDynamicBinaryStorage<'a, F>
+{
+    fn read_done(&self, _buffer: &'static mut [u8], _length: usize) {}
+
+    fn write_done(&self, buffer: &'static mut [u8], length: usize) {
+        match self.state.get() {
+            State::SetupPaddingAfter | State::SetupPlaceholder | State::SetupPaddingBefore => {
+                self.buffer.replace(buffer);
+                match self.setup_write_next(self.state.get()) {
+                    Ok(true) => {}
+                    Ok(false) => self.setup_done(Ok(())),
+                    Err(e) => self.setup_done(Err(e)),
+                }
+            }
+            State::AppWrite => {
+                // Give the client back the chunk it wrote.
+                let offset = self.write_offset.get();
+                if let Some(header) = self.header.get().get(offset..) {
+                    for (byte, kept) in buffer[..length].iter_mut().zip(header) {
+                        *byte = *kept;
+                    }
+                }
+                self.state.set(State::Ready);
+                self.storage_client
+                    .map(move |client| client.write_done(Ok(()), buffer, length));
+            }
+            State::LoadHeader => {
+                self.buffer.replace(buffer);
+                let (address, app_length) = self.binary.get().unwrap_or((0, 0));
+                match self.loader.load_new_process_binary(address, app_length) {
+                    Ok(()) => self.state.set(State::Load),
+                    Err(_) => self.load_failed(ProcessLoadError::InternalError),
+                }
+            }
+            State::LoadFailed => {
+                self.buffer.replace(buffer);
+                self.state.set(State::Ready);
+                let error = self
+                    .load_error
+                    .take()
+                    .unwrap_or(ProcessLoadError::InternalError);
+                self.load_client.map(|client| client.load_done(Err(error)));
+            }
+            State::Unload => {
+                self.buffer.replace(buffer);
+                self.state.set(State::Idle);
+                self.unload_client.map(|client| client.unload_done(Ok(())));
+            }
+            State::Idle | State::Ready | State::Load => {}
+        }
+    }
+}
+
+impl<'a, F: NonvolatileStorage<'a>> ProcessLoadingDynamicClient
+    for SequentialDynamicBinaryStorage<'a, F>
+{
+    fn process_binary_loaded(&self, result: Result<(), ProcessLoadError>) {
+        match result {
+            Ok(()) => {
+                self.binary.clear();
+                self.state.set(State::Idle);
+                self.load_client.map(|client| client.load_done(Ok(())));
+            }
+            Err(e) => self.load_failed(e),
+        }
+    }
+}
@@ -0,0 +1,134 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Interface for block storage devices.
+//!
+//! A block device stores data in blocks of a fixed size, numbered from 0 to
+//! `num_blocks() - 1`. Blocks are read and written whole, one block per
+//! operation. SD cards, flash chips and EEPROMs are made available through
+//! this interface by adapters, so filesystems and logs can be written once for
+//! all of them. Caches and partitions implement the interface on top of
+//! another block device and can be stacked.
+//!
+//! Writing a block does not require erasing it first, devices that need it
+//! erase the block themselves. Erasing a block tells the device that its
+//! contents are no longer needed, they are unspecified until the block is
+//! written again.
+//!
+//! Devices can buffer writes. Written data is only guaranteed to be stored
+//! once `flush()` has completed.
+//!
+//! All operations are split-phase, and only one operation can be outstanding
+//! at a time. Operations return `BUSY` while another one is in progress.
+//!
+//! ```text
+//! +-----------------------+
+//! |  Filesystem / log     |
+//! +-----------------------+
+//!
+//!    hil::block::BlockDevice (this file)
+//!
+//! +-----------------------+
+//! |  Cache / partition    |
+//! +-----------------------+
+//!
+//!    hil::block::BlockDevice (this file)
+//!
+//! +-----------------------+
+//! |  Adapter              |
+//! +-----------------------+
+//!
+//!    hil::flash, hil::nonvolatile_storage, ...
+//! ```
+
+use crate::ErrorCode;
+
+/// Callback trait for block devices.
+///
+/// Implement this trait and use `set_client()` to receive callbacks.
+pub trait BlockClient {
+    /// A block has been read.
+    ///
+    /// - `block`: The block that was read.
+    /// - `buffer`: The buffer passed to `read()`, holding the block in its
+    ///   first `block_size()` bytes.
+    /// - `result`: `Ok(())` on success, `Err(ErrorCode)` if the block could
+    ///   not be read.
+    fn read_done(&self, block: usize, buffer: &'static mut [u8], result: Result<(), ErrorCode>);
+
+    /// A block has been written.
+    ///
+    /// - `block`: The block that was written.
+    /// - `buffer`: The buffer passed to `write()`.
+    /// - `result`: `Ok(())` on success, `Err(ErrorCode)` if the block could
+    ///   not be written.
+    fn write_done(&self, block: usize, buffer: &'static mut [u8], result: Result<(), ErrorCode>);
+
+    /// A block has been erased.
+    ///
+    /// - `block`: The block that was erased.
+    /// - `result`: `Ok(())` on success, `Err(ErrorCode)` if the block could
+    ///   not be erased.
+    fn erase_done(&self, block: usize, result: Result<(), ErrorCode>);
+
+    /// All buffered writes have been stored.
+    ///
+    /// - `result`: `Ok(())` on success, `Err(ErrorCode)` if some data could
+    ///   not be stored.
+    fn flush_done(&self, result: Result<(), ErrorCode>);
+}
+
+/// A device storing data in fixed-size blocks.
+pub trait BlockDevice<'a> {
+    /// Set the client for this device.
+    fn set_client(&self, client: &'a dyn BlockClient);
+
+    /// The size of a block in bytes.
+    fn block_size(&self) -> usize;
+
+    /// The number of blocks on the device.
+    ///
+    /// Returns 0 while the size is not known yet, for example before a
+    /// removable card has been accessed.
+    fn num_blocks(&self) -> usize;
+
+    /// Read a block into the first `block_size()` bytes of `buffer`.
+    ///
+    /// On success returns `Ok(())` and `read_done()` is called later. On
+    /// error returns the buffer with:
+    /// - `SIZE` if the buffer is shorter than a block.
+    /// - `INVAL` if the block is not on the device.
+    /// - `BUSY` if another operation is in progress.
+    /// - `OFF` if the device is not ready.
+    /// - `UNINSTALLED` if there is no medium in the device.
+    fn read(
+        &self,
+        block: usize,
+        buffer: &'static mut [u8],
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+
+    /// Write a block from the first `block_size()` bytes of `buffer`.
+    ///
+    /// On success returns `Ok(())` and `write_done()` is called later. On
+    /// error returns the buffer with the errors of `read()`.
+    fn write(
+        &self,
+        block: usize,
+        buffer: &'static mut [u8],
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+
+    /// Erase a block.
+    ///
+    /// On success returns `Ok(())` and `erase_done()` is called later.
+    /// Returns `ALREADY` if the device does not erase blocks, in which case
+    /// there is no callback. Otherwise returns the errors of `read()`.
+    fn erase(&self, block: usize) -> Result<(), ErrorCode>;
+
+    /// Store all buffered writes.
+    ///
+    /// On success returns `Ok(())` and `flush_done()` is called later.
+    /// Returns `ALREADY` if no writes are buffered, in which case there is no
+    /// callback. Otherwise returns the errors of `read()`.
+    fn flush(&self) -> Result<(), ErrorCode>;
+}
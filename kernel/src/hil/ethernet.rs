@@ -0,0 +1,76 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Interfaces for Ethernet network adapters.
+//!
+//! An Ethernet adapter transmits and receives complete Ethernet II frames,
+//! starting with the destination MAC address and ending with the payload.
+//! The frame check sequence (FCS) is neither passed to nor expected from
+//! the client; adapters compute and strip it themselves.
+//!
+//! This interface only covers the datapath of the adapter. Link
+//! configuration (speed, duplex, MAC address filters) is adapter-specific
+//! and left to the respective driver.
+
+use crate::ErrorCode;
+
+/// Ethernet adapter datapath client.
+pub trait EthernetAdapterDatapathClient {
+    /// An Ethernet frame has been transmitted.
+    ///
+    /// `err` indicates whether the frame was transmitted successfully.
+    /// `frame_buffer` and `len` are the buffer and length passed to
+    /// [`EthernetAdapterDatapath::transmit_frame`], and
+    /// `transmission_identifier` is the opaque value passed along with
+    /// them. `timestamp` is the time at which the frame was transmitted,
+    /// if the adapter supports timestamping.
+    fn transmit_frame_done(
+        &self,
+        err: Result<(), ErrorCode>,
+        frame_buffer: &'static mut [u8],
+        len: u16,
+        transmission_identifier: usize,
+        timestamp: Option<u64>,
+    );
+
+    /// An Ethernet frame has been received.
+    ///
+    /// `frame` contains the full frame excluding the FCS. The buffer is
+    /// only borrowed for the duration of this call; clients must copy out
+    /// any data they wish to retain. `timestamp` is the time at which the
+    /// frame was received, if the adapter supports timestamping.
+    fn received_frame(&self, frame: &[u8], timestamp: Option<u64>);
+}
+
+/// Ethernet adapter datapath.
+pub trait EthernetAdapterDatapath<'a> {
+    /// Set the client for transmit and receive events.
+    fn set_client(&self, client: &'a dyn EthernetAdapterDatapathClient);
+
+    /// Start delivering received frames to the client.
+    fn enable_receive(&self);
+
+    /// Stop delivering received frames to the client. Frames received
+    /// while reception is disabled are dropped.
+    fn disable_receive(&self);
+
+    /// Transmit the first `len` bytes of `frame_buffer` as an Ethernet
+    /// frame.
+    ///
+    /// `transmission_identifier` is an opaque value which is passed back
+    /// in the corresponding `transmit_frame_done` callback.
+    ///
+    /// On error the buffer is returned immediately and no callback is
+    /// issued. Return values:
+    /// - `Ok(())`: the frame is being transmitted.
+    /// - `Err(BUSY)`: the adapter cannot accept another frame right now.
+    /// - `Err(SIZE)`: `len` exceeds the buffer or the adapter's MTU.
+    /// - `Err(OFF)`: the adapter is not operational.
+    fn transmit_frame(
+        &self,
+        frame_buffer: &'static mut [u8],
+        len: u16,
+        transmission_identifier: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+}
@@ -0,0 +1,210 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Interface for filesystems.
+//!
+//! Files are kept in directories identified by a storage identifier, which is
+//! the `ShortId` of the application that created them (or 0 for the kernel).
+//! Every operation takes the `StoragePermissions` of the caller and the
+//! filesystem only allows reading files in directories the permissions can
+//! read, modifying files in directories they can modify, and creating files in
+//! the directory of their write identifier.
+//!
+//! Directories can contain subdirectories, which are named by paths relative
+//! to the directory with components separated by `/`. Filesystems without
+//! subdirectories reject paths with more than one component.
+//!
+//! Open files are identified by a [`FileId`]. A `FileId` has no state, such as
+//! a position, associated with it: reads and writes take the offset in the
+//! file. A `FileId` is valid until the file is unlinked. Filesystems that can
+//! only keep track of a limited number of open files can also invalidate the
+//! `FileId`s of files that have not been used for the longest time when others
+//! are opened. Operations with an invalid `FileId` fail with `NOSUPPORT`.
+//!
+//! All operations are split-phase, and only one operation can be outstanding
+//! at a time. Operations return `BUSY` while another one is in progress.
+//!
+//! ```text
+//! +-----------------------+
+//! |  Filesystem driver    |
+//! +-----------------------+
+//!
+//!    hil::filesystem::FileSystem (this file)
+//!
+//! +-----------------------+
+//! |  Filesystem           |
+//! +-----------------------+
+//!
+//!    hil::flash
+//! ```
+
+use crate::storage_permissions::StoragePermissions;
+use crate::utilities::leasable_buffer::SubSliceMut;
+use crate::ErrorCode;
+
+/// Identifies a file in a filesystem.
+pub type FileId = usize;
+
+/// Information about a file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileInfo {
+    /// The directory holding the file.
+    pub dir: u32,
+    /// The length of the file in bytes.
+    pub size: usize,
+}
+
+/// An entry returned when reading a directory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DirEntry {
+    /// The file this entry refers to, if the filesystem can refer to it
+    /// without opening it.
+    pub file: Option<FileId>,
+    /// Information about the file.
+    pub info: FileInfo,
+    /// Whether the entry is a subdirectory rather than a file.
+    pub directory: bool,
+    /// Cursor to pass to `read_dir()` to get the next entry.
+    pub next: usize,
+}
+
+/// Callback trait for filesystems.
+///
+/// Implement this trait and use `set_client()` to receive callbacks.
+pub trait FileSystemClient {
+    /// The filesystem has been mounted and can be used.
+    ///
+    /// - `result`: `Ok(())` on success, `Err(ErrorCode)` if the filesystem
+    ///   could not be read or created.
+    fn mount_done(&self, result: Result<(), ErrorCode>);
+
+    /// A file has been opened.
+    ///
+    /// - `result`: The `FileId` of the file on success, `Err(ErrorCode)` on
+    ///   error. Valid `ErrorCode`s:
+    ///   - `NOSUPPORT`: The file does not exist and was not to be created, or
+    ///     the caller does not have permission to read or create it.
+    ///   - `NOMEM`: The file could not be created because the filesystem is
+    ///     full.
+    ///   - `SIZE`: The name is too long.
+    ///   - `FAIL`: An internal error occurred.
+    /// - `name`: The name buffer passed to `open()`.
+    fn open_done(&self, result: Result<FileId, ErrorCode>, name: SubSliceMut<'static, u8>);
+
+    /// Information about a file is available.
+    ///
+    /// - `result`: The `FileInfo` on success, `Err(ErrorCode)` on error. Valid
+    ///   `ErrorCode`s:
+    ///   - `NOSUPPORT`: The file does not exist or the caller does not have
+    ///     permission to read it.
+    fn stat_done(&self, result: Result<FileInfo, ErrorCode>);
+
+    /// A read has completed.
+    ///
+    /// - `result`: The number of bytes read into the start of `buffer` on
+    ///   success, which is 0 at the end of the file. Valid `ErrorCode`s:
+    ///   - `NOSUPPORT`: The file does not exist or the caller does not have
+    ///     permission to read it.
+    ///   - `FAIL`: An internal error occurred.
+    /// - `buffer`: The buffer passed to `read()`.
+    fn read_done(&self, result: Result<usize, ErrorCode>, buffer: SubSliceMut<'static, u8>);
+
+    /// A write has completed. Writes can be shorter than the buffer, the
+    /// caller writes the rest with another call.
+    ///
+    /// - `result`: The number of bytes written from the start of `buffer` on
+    ///   success. Valid `ErrorCode`s:
+    ///   - `NOSUPPORT`: The file does not exist or the caller does not have
+    ///     permission to modify it.
+    ///   - `NOMEM`: The filesystem is full.
+    ///   - `INVAL`: The offset is past the end of the file.
+    ///   - `FAIL`: An internal error occurred.
+    /// - `buffer`: The buffer passed to `write()`.
+    fn write_done(&self, result: Result<usize, ErrorCode>, buffer: SubSliceMut<'static, u8>);
+
+    /// A file has been unlinked.
+    ///
+    /// - `result`: `Ok(())` on success, `Err(ErrorCode)` on error. Valid
+    ///   `ErrorCode`s:
+    ///   - `NOSUPPORT`: The file does not exist or the caller does not have
+    ///     permission to modify it.
+    ///   - `FAIL`: An internal error occurred.
+    fn unlink_done(&self, result: Result<(), ErrorCode>);
+
+    /// A directory entry has been read.
+    ///
+    /// - `result`: The next entry on success, or `None` if there are no more
+    ///   entries. The name of the file is copied into the buffer of `name`,
+    ///   which is reset and shortened to the length of the name. Valid
+    ///   `ErrorCode`s:
+    ///   - `NOSUPPORT`: The directory does not exist or the caller does not
+    ///     have permission to read it.
+    ///   - `SIZE`: The name of the entry does not fit in `name`.
+    ///   - `FAIL`: An internal error occurred.
+    /// - `name`: The name buffer passed to `read_dir()`.
+    fn read_dir_done(
+        &self,
+        result: Result<Option<DirEntry>, ErrorCode>,
+        name: SubSliceMut<'static, u8>,
+    );
+}
+
+/// A filesystem.
+pub trait FileSystem<'a> {
+    /// Set the client for callbacks.
+    fn set_client(&self, client: &'a dyn FileSystemClient);
+
+    /// Mount the filesystem. Other operations return `OFF` until this has
+    /// completed.
+    fn mount(&self) -> Result<(), ErrorCode>;
+
+    /// Open the file called `name` in directory `dir`, creating it if it does
+    /// not exist and `create` is true. `name` can be a path, filesystems with
+    /// subdirectories create missing directories along it when creating the
+    /// file.
+    fn open(
+        &self,
+        dir: u32,
+        name: SubSliceMut<'static, u8>,
+        create: bool,
+        permissions: StoragePermissions,
+    ) -> Result<(), (ErrorCode, SubSliceMut<'static, u8>)>;
+
+    /// Get information about a file.
+    fn stat(&self, file: FileId, permissions: StoragePermissions) -> Result<(), ErrorCode>;
+
+    /// Read from `file` at `offset` into `buffer`.
+    fn read(
+        &self,
+        file: FileId,
+        offset: usize,
+        buffer: SubSliceMut<'static, u8>,
+        permissions: StoragePermissions,
+    ) -> Result<(), (ErrorCode, SubSliceMut<'static, u8>)>;
+
+    /// Write `buffer` to `file` at `offset`. `offset` can be at most the
+    /// length of the file, writing at the end extends the file.
+    fn write(
+        &self,
+        file: FileId,
+        offset: usize,
+        buffer: SubSliceMut<'static, u8>,
+        permissions: StoragePermissions,
+    ) -> Result<(), (ErrorCode, SubSliceMut<'static, u8>)>;
+
+    /// Remove `file` and free its storage.
+    fn unlink(&self, file: FileId, permissions: StoragePermissions) -> Result<(), ErrorCode>;
+
+    /// Read the first entry at or after `cursor` of the subdirectory of `dir`
+    /// named by the path in `name`, or of `dir` itself if `name` is empty.
+    /// Start with a cursor of 0 and continue with the `next` cursor of each
+    /// entry. The whole buffer of `name` can hold the name of the entry.
+    fn read_dir(
+        &self,
+        dir: u32,
+        cursor: usize,
+        name: SubSliceMut<'static, u8>,
+        permissions: StoragePermissions,
+    ) -> Result<(), (ErrorCode, SubSliceMut<'static, u8>)>;
+}
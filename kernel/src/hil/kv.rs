@@ -155,6 +155,34 @@ pub trait KVClient {
     ///   - `FAIL`: An internal error occurred and the operation cannot be
     ///     completed.
     fn garbage_collection_complete(&self, result: Result<(), ErrorCode>);
+
+    /// This callback is called when the next_key operation completes.
+    ///
+    /// If there wasn't enough room to store the entire value `SIZE` will be
+    /// returned in `result` and the bytes that did fit will be copied into the
+    /// buffer.
+    ///
+    /// ### Return Values
+    ///
+    /// - `result`: `Ok(())` on success
+    /// - `Err(ErrorCode)` on error. Valid `ErrorCode`s:
+    ///   - `SIZE`: The value is longer than the provided buffer. The amount of
+    ///     the value that fits in the buffer is provided.
+    ///   - `NOSUPPORT`: There are no more keys the caller has permission to
+    ///     read. The data in the `key` and `value` buffers is meaningless.
+    ///   - `FAIL`: An internal error occurred and the operation cannot be
+    ///     completed.
+    /// - `cursor`: The position to pass to the next `next_key()` call to
+    ///   continue after this key.
+    /// - `key`: The key buffer, sliced to the stored key.
+    /// - `value`: The value buffer.
+    fn next_key_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        cursor: usize,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    );
 }
 
 /// Key-Value interface with permissions.
@@ -343,6 +371,47 @@ pub trait KVPermissions<'a> {
     ///     completed.
     fn garbage_collect(&self) -> Result<(), ErrorCode>;
 
+    /// Retrieve the next key the caller has permission to read, and its
+    /// value.
+    ///
+    /// Keys are returned in the order they are stored in, not the order they
+    /// were added in. Stores that only keep a hash of each key return the
+    /// hash.
+    ///
+    /// ### Arguments
+    ///
+    /// - `cursor`: The position to start looking from. 0 finds the first key,
+    ///   the cursor passed to `next_key_complete()` finds the key after that.
+    /// - `key`: Where the returned key will be stored.
+    /// - `value`: Where the returned value will be stored. The buffer must
+    ///   have room for the `KVPermissions.header_size()` bytes of the header.
+    /// - `permissions`: The read/write/modify permissions for this access.
+    ///
+    /// ### Return
+    ///
+    /// - On success returns `Ok(())`. A callback will be issued.
+    /// - On error, returns the buffers and:
+    ///   - `BUSY`: An operation is already in progress.
+    ///   - `SIZE`: The `key` buffer can not hold a key or the `value` buffer
+    ///     can not hold the header.
+    ///   - `NOSUPPORT`: There are no more keys.
+    ///   - `FAIL`: An internal error occurred and the operation cannot be
+    ///     completed.
+    fn next_key(
+        &self,
+        cursor: usize,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+        permissions: StoragePermissions,
+    ) -> Result<
+        (),
+        (
+            SubSliceMut<'static, u8>,
+            SubSliceMut<'static, u8>,
+            ErrorCode,
+        ),
+    >;
+
     /// Returns the length of the key-value store's header in bytes.
     ///
     /// Room for this header must be accommodated in a `set`, `add`, or `update`
@@ -360,6 +429,8 @@ pub trait KVPermissions<'a> {
 /// - `add(key, value)`
 /// - `update(key, value)`
 /// - `delete(key)`
+///
+/// and `next_key(cursor) -> (key, value)` to list the stored keys.
 pub trait KV<'a> {
     /// Configure the client for operation callbacks.
     fn set_client(&self, client: &'a dyn KVClient);
@@ -516,4 +587,40 @@ pub trait KV<'a> {
     ///   - `FAIL`: An internal error occurred and the operation cannot be
     ///     completed.
     fn garbage_collect(&self) -> Result<(), ErrorCode>;
+
+    /// Retrieve the next key and its value.
+    ///
+    /// Keys are returned in the order they are stored in, not the order they
+    /// were added in. Stores that only keep a hash of each key return the
+    /// hash.
+    ///
+    /// ### Arguments
+    ///
+    /// - `cursor`: The position to start looking from. 0 finds the first key,
+    ///   the cursor passed to `next_key_complete()` finds the key after that.
+    /// - `key`: Where the returned key will be stored.
+    /// - `value`: Where the returned value will be stored.
+    ///
+    /// ### Return
+    ///
+    /// - On success returns `Ok(())`. A callback will be issued.
+    /// - On error, returns the buffers and:
+    ///   - `BUSY`: An operation is already in progress.
+    ///   - `SIZE`: The `key` buffer can not hold a key.
+    ///   - `NOSUPPORT`: There are no more keys.
+    ///   - `FAIL`: An internal error occurred and the operation cannot be
+    ///     completed.
+    fn next_key(
+        &self,
+        cursor: usize,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) -> Result<
+        (),
+        (
+            SubSliceMut<'static, u8>,
+            SubSliceMut<'static, u8>,
+            ErrorCode,
+        ),
+    >;
 }
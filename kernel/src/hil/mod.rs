@@ -7,6 +7,7 @@
 pub mod adc;
 pub mod analog_comparator;
 pub mod ble_advertising;
+pub mod block;
 pub mod bus8080;
 pub mod buzzer;
 pub mod can;
@@ -16,6 +17,8 @@ pub mod date_time;
 pub mod digest;
 pub mod eic;
 pub mod entropy;
+pub mod ethernet;
+pub mod filesystem;
 pub mod flash;
 pub mod gpio;
 pub mod gpio_async;
@@ -144,6 +144,9 @@ pub trait AES128CCM<'a> {
     ) -> Result<(), (ErrorCode, &'static mut [u8])>;
 }
 
+/// The IV length recommended for GCM by NIST-800-38D.
+pub const GCM_IV_LENGTH: usize = 12;
+
 pub trait GCMClient {
     /// `res` is Ok(()) if the encryption/decryption process succeeded. This
     /// does not mean that the message has been verified in the case of
@@ -180,3 +183,172 @@ pub trait AES128GCM<'a> {
         encrypting: bool,
     ) -> Result<(), (ErrorCode, &'static mut [u8])>;
 }
+
+/// AES-256 uses the same 16-byte block as AES-128, only the key is longer.
+pub const AES256_KEY_SIZE: usize = 32;
+
+/// The 256-bit key variant of [`AES128`]. Completed operations are reported
+/// through the same [`Client`] trait.
+pub trait AES256<'a> {
+    /// Enable the AES hardware.
+    /// Must be called before any other methods
+    fn enable(&self);
+
+    /// Disable the AES hardware
+    fn disable(&self);
+
+    /// Set the client instance which will receive `crypt_done()` callbacks
+    fn set_client(&'a self, client: &'a dyn Client<'a>);
+
+    /// Set the encryption key.
+    /// Returns `INVAL` if length is not `AES256_KEY_SIZE`
+    fn set_key(&self, key: &[u8]) -> Result<(), ErrorCode>;
+
+    /// Set the IV (or initial counter).
+    /// Returns `INVAL` if length is not `AES128_BLOCK_SIZE`
+    fn set_iv(&self, iv: &[u8]) -> Result<(), ErrorCode>;
+
+    /// Begin a new message (with the configured IV) when `crypt()` is
+    /// next called. See [`AES128::start_message`].
+    fn start_message(&self);
+
+    /// Request an encryption/decryption. The buffers, indices and return
+    /// values follow the same rules as [`AES128::crypt`].
+    fn crypt(
+        &self,
+        source: Option<&'static mut [u8]>,
+        dest: &'static mut [u8],
+        start_index: usize,
+        stop_index: usize,
+    ) -> Option<(
+        Result<(), ErrorCode>,
+        Option<&'static mut [u8]>,
+        &'static mut [u8],
+    )>;
+}
+
+pub trait AES256Ctr {
+    /// Call before `AES256::crypt()` to perform AES256Ctr
+    fn set_mode_aes256ctr(&self, encrypting: bool) -> Result<(), ErrorCode>;
+}
+
+pub trait AES256CBC {
+    /// Call before `AES256::crypt()` to perform AES256CBC
+    fn set_mode_aes256cbc(&self, encrypting: bool) -> Result<(), ErrorCode>;
+}
+
+pub trait AES256ECB {
+    /// Call before `AES256::crypt()` to perform AES256ECB
+    fn set_mode_aes256ecb(&self, encrypting: bool) -> Result<(), ErrorCode>;
+}
+
+/// AES-256 in CCM* mode.
+///
+/// The buffer passed to `crypt()` holds the additional data at
+/// `a_off..m_off`, followed by the message at `m_off..m_off + m_len`,
+/// followed by the `mic_len` byte tag. If `confidential` is false, the
+/// message is only authenticated and is left unencrypted.
+pub trait AES256CCM<'a> {
+    /// Set the client instance which will receive `crypt_done()` callbacks
+    fn set_client(&'a self, client: &'a dyn CCMClient);
+
+    /// Set the key to be used for CCM encryption
+    /// Returns `INVAL` if length is not `AES256_KEY_SIZE`
+    fn set_key(&self, key: &[u8]) -> Result<(), ErrorCode>;
+
+    /// Set the nonce (length CCM_NONCE_LENGTH) to be used for CCM encryption
+    fn set_nonce(&self, nonce: &[u8]) -> Result<(), ErrorCode>;
+
+    /// Try to begin the encryption/decryption process
+    fn crypt(
+        &self,
+        buf: &'static mut [u8],
+        a_off: usize,
+        m_off: usize,
+        m_len: usize,
+        mic_len: usize,
+        confidential: bool,
+        encrypting: bool,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+}
+
+/// AES-256 in GCM mode.
+///
+/// The buffer passed to `crypt()` holds the additional data at
+/// `aad_offset..message_offset`, followed by the message at
+/// `message_offset..message_offset + message_len`, followed by the 16 byte
+/// tag. The message is encrypted or decrypted in place.
+pub trait AES256GCM<'a> {
+    /// Set the client instance which will receive `crypt_done()` callbacks
+    fn set_client(&'a self, client: &'a dyn GCMClient);
+
+    /// Set the key to be used for GCM encryption
+    /// Returns `INVAL` if length is not `AES256_KEY_SIZE`
+    fn set_key(&self, key: &[u8]) -> Result<(), ErrorCode>;
+
+    /// Set the IV to be used for GCM encryption. The IV should be less
+    /// or equal to 12 bytes (96 bits) as recommened in NIST-800-38D.
+    /// Returns `INVAL` if length is greater then 12 bytes
+    fn set_iv(&self, nonce: &[u8]) -> Result<(), ErrorCode>;
+
+    /// Try to begin the encryption/decryption process
+    /// The possible ErrorCodes are:
+    ///     - `BUSY`: An operation is already in progress
+    ///     - `SIZE`: The offset and lengths don't fit inside the buffer
+    fn crypt(
+        &self,
+        buf: &'static mut [u8],
+        aad_offset: usize,
+        message_offset: usize,
+        message_len: usize,
+        encrypting: bool,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+}
+
+pub const CHACHA20_POLY1305_KEY_SIZE: usize = 32;
+pub const CHACHA20_POLY1305_NONCE_SIZE: usize = 12;
+pub const CHACHA20_POLY1305_TAG_SIZE: usize = 16;
+
+pub trait ChaCha20Poly1305Client {
+    /// `res` is Ok(()) if the encryption/decryption process succeeded. This
+    /// does not mean that the message has been verified in the case of
+    /// decryption.
+    /// If we are encrypting: `tag_is_valid` is `true` iff `res` is Ok(()).
+    /// If we are decrypting: `tag_is_valid` is `true` iff `res` is Ok(()) and the
+    /// message authentication tag is valid.
+    fn crypt_done(&self, buf: &'static mut [u8], res: Result<(), ErrorCode>, tag_is_valid: bool);
+}
+
+/// The ChaCha20-Poly1305 AEAD construction from RFC 8439.
+///
+/// The buffer passed to `crypt()` holds the additional data at
+/// `aad_offset..message_offset`, followed by the message at
+/// `message_offset..message_offset + message_len`, followed by the
+/// `CHACHA20_POLY1305_TAG_SIZE` byte tag. The message is encrypted or
+/// decrypted in place. When encrypting the tag is written, when decrypting
+/// it is checked.
+pub trait ChaCha20Poly1305<'a> {
+    /// Set the client instance which will receive `crypt_done()` callbacks
+    fn set_client(&'a self, client: &'a dyn ChaCha20Poly1305Client);
+
+    /// Set the key.
+    /// Returns `INVAL` if length is not `CHACHA20_POLY1305_KEY_SIZE`
+    fn set_key(&self, key: &[u8]) -> Result<(), ErrorCode>;
+
+    /// Set the nonce. A nonce must never be reused with the same key.
+    /// Returns `INVAL` if length is not `CHACHA20_POLY1305_NONCE_SIZE`
+    fn set_nonce(&self, nonce: &[u8]) -> Result<(), ErrorCode>;
+
+    /// Try to begin the encryption/decryption process
+    /// The possible ErrorCodes are:
+    ///     - `BUSY`: An operation is already in progress
+    ///     - `SIZE`: The offset and lengths don't fit inside the buffer
+    fn crypt(
+        &self,
+        buf: &'static mut [u8],
+        aad_offset: usize,
+        message_offset: usize,
+        message_len: usize,
+        encrypting: bool,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+}
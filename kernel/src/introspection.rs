@@ -127,6 +127,78 @@ impl KernelInfo {
             .process_map_or(0, app, |process| process.debug_timeslice_expiration_count())
     }
 
+    /// Returns the processor time in microseconds the app has used. Only time
+    /// the app ran with a timeslice is counted, so this is 0 with a cooperative
+    /// scheduler.
+    pub fn app_cpu_time_us(
+        &self,
+        app: ProcessId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> u64 {
+        self.kernel
+            .process_map_or(0, app, |process| process.debug_cpu_time_us())
+    }
+
+    /// Returns how many syscalls the app has called for each driver.
+    pub fn app_syscall_histogram(
+        &self,
+        app: ProcessId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> process::SyscallHistogram {
+        self.kernel
+            .process_map_or(process::SyscallHistogram::default(), app, |process| {
+                process.debug_syscall_histogram()
+            })
+    }
+
+    /// Returns the highest number of bytes of stack the app has been seen
+    /// using, if the kernel knows where the stack of the app starts.
+    pub fn app_peak_stack_usage(
+        &self,
+        app: ProcessId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> Option<usize> {
+        self.kernel.process_map_or(None, app, |process| {
+            let addresses = process.get_addresses();
+            match (addresses.sram_stack_top, addresses.sram_stack_bottom) {
+                (Some(top), Some(bottom)) => Some(top.saturating_sub(bottom)),
+                _ => None,
+            }
+        })
+    }
+
+    /// Returns the highest number of bytes the heap of the app has grown to,
+    /// if the kernel knows where the heap of the app starts.
+    pub fn app_peak_heap_usage(
+        &self,
+        app: ProcessId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> Option<usize> {
+        self.kernel.process_map_or(None, app, |process| {
+            let addresses = process.get_addresses();
+            let brk_max = addresses
+                .sram_app_brk_max
+                .map_or(addresses.sram_app_brk, |brk_max| {
+                    brk_max.max(addresses.sram_app_brk)
+                });
+            addresses
+                .sram_heap_start
+                .map(|heap_start| brk_max.saturating_sub(heap_start))
+        })
+    }
+
+    /// Returns the highest number of upcalls and other tasks that have been
+    /// queued for the app at once. If this reaches the size of the task queue,
+    /// upcalls are dropped.
+    pub fn app_task_queue_high_water(
+        &self,
+        app: ProcessId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> usize {
+        self.kernel
+            .process_map_or(0, app, |process| process.debug_task_queue_high_water())
+    }
+
     /// Returns a tuple of the (the number of grants in the grant region this
     /// app has allocated, total number of grants that exist in the system).
     pub fn number_app_grant_uses(
@@ -32,6 +32,7 @@ use crate::syscall::SyscallDriver;
 use crate::syscall::{ContextSwitchReason, SyscallReturn};
 use crate::syscall::{Syscall, YieldCall};
 use crate::syscall_driver::CommandReturn;
+use crate::trace;
 use crate::upcall::{Upcall, UpcallId};
 use crate::utilities::cells::NumericCellExt;
 
@@ -550,10 +551,15 @@ impl Kernel {
                         .context_switch_hook(process);
                     process.setup_mpu();
                     chip.mpu().enable_app_mpu();
+                    trace::trace(trace::Event::SwitchToProcess(process.processid()));
                     scheduler_timer.arm();
                     let context_switch_reason = process.switch_to();
                     scheduler_timer.disarm();
                     chip.mpu().disable_app_mpu();
+                    trace::trace(trace::Event::SwitchToKernel(
+                        process.processid(),
+                        context_switch_reason,
+                    ));
 
                     // Now the process has returned back to the kernel. Check
                     // why and handle the process as appropriate.
@@ -571,7 +577,9 @@ impl Kernel {
                             }
                         }
                         Some(ContextSwitchReason::SyscallFired { syscall }) => {
+                            trace::trace(trace::Event::SyscallEntry(process.processid(), syscall));
                             self.handle_syscall(resources, process, syscall);
+                            trace::trace(trace::Event::SyscallExit(process.processid()));
                         }
                         Some(ContextSwitchReason::Interrupted) => {
                             if scheduler_timer.get_remaining_us().is_none() {
@@ -719,6 +727,10 @@ impl Kernel {
             }
         });
 
+        if let Some(time_executed_us) = time_executed_us {
+            process.debug_cpu_time_used(time_executed_us);
+        }
+
         // Reset the scheduler timer in case it unconditionally triggers
         // interrupts upon expiration. We do not want it to expire while the
         // chip is sleeping, for example.
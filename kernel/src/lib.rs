@@ -107,8 +107,10 @@ pub const KERNEL_MINOR_VERSION: u16 = 1;
 pub mod capabilities;
 pub mod collections;
 pub mod component;
+pub mod crash_dump;
 pub mod debug;
 pub mod deferred_call;
+pub mod dynamic_binary_storage;
 pub mod errorcode;
 pub mod grant;
 pub mod hil;
@@ -121,6 +123,7 @@ pub mod processbuffer;
 pub mod scheduler;
 pub mod storage_permissions;
 pub mod syscall;
+pub mod trace;
 pub mod upcall;
 pub mod utilities;
 
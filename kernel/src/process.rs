@@ -29,9 +29,13 @@ pub use crate::process_checker::AcceptedCredential;
 pub use crate::process_checker::{ProcessCheckerMachine, ProcessCheckerMachineClient};
 pub use crate::process_loading::load_processes;
 pub use crate::process_loading::ProcessLoadError;
+pub use crate::process_loading::ProcessLoadingDynamicClient;
 pub use crate::process_loading::SequentialProcessLoaderMachine;
+pub use crate::process_loading::{NewProcessBinaryLocation, ProcessLoadingDynamic};
 pub use crate::process_loading::{ProcessLoadingAsync, ProcessLoadingAsyncClient};
-pub use crate::process_policies::{ProcessFaultPolicy, ProcessStandardStoragePermissionsPolicy};
+pub use crate::process_policies::{
+    ProcessAdmissionPolicy, ProcessFaultPolicy, ProcessStandardStoragePermissionsPolicy,
+};
 pub use crate::process_printer::{ProcessPrinter, ProcessPrinterContext};
 pub use crate::process_standard::ProcessStandard;
 pub use crate::process_standard::{ProcessStandardDebug, ProcessStandardDebugFull};
@@ -511,6 +515,23 @@ pub trait Process {
     /// `None`.
     fn terminate(&self, completion_code: Option<u32>);
 
+    /// Terminate the process and return the memory allocated to it so that
+    /// it can be given to a different process.
+    ///
+    /// This clears all queued tasks and grants like `terminate()`, regardless
+    /// of the state the process is in.
+    ///
+    /// ## Safety
+    ///
+    /// The process object itself may be stored in the returned memory. The
+    /// caller must remove every reference to this process, in particular its
+    /// entry in the processes array, and must not use the process after
+    /// calling this function.
+    unsafe fn release_memory(
+        &self,
+        capability: &dyn capabilities::ProcessManagementCapability,
+    ) -> &'static mut [u8];
+
     /// Get the completion code if the process has previously terminated.
     ///
     /// ## Returns
@@ -666,6 +687,12 @@ pub trait Process {
     /// Returns `None` if the process has no storage permissions.
     fn get_storage_permissions(&self) -> storage_permissions::StoragePermissions;
 
+    /// Get the real-time parameters of the process as a `(period_us,
+    /// budget_us)` tuple, as specified in a TBF Real Time Header.
+    ///
+    /// Returns `None` if the process did not request to run periodically.
+    fn get_real_time_parameters(&self) -> Option<(u32, u32)>;
+
     // mpu
 
     /// Configure the MPU to use the process's allocated regions.
@@ -849,6 +876,14 @@ pub trait Process {
     /// binary representation. Returns `ErrorCode::FAIL` on an internal error.
     fn get_stored_state(&self, out: &mut [u8]) -> Result<usize, ErrorCode>;
 
+    /// Replace the stored state of the process with `state`, in the format
+    /// written by `get_stored_state()`. Debuggers use this to change the
+    /// registers of a process.
+    ///
+    /// Returns `ErrorCode::BUSY` if the process is not stopped, and
+    /// `ErrorCode::INVAL` if `state` is not valid for this architecture.
+    fn set_stored_state(&self, state: &[u8]) -> Result<(), ErrorCode>;
+
     /// Print out the full state of the process: its memory map, its context,
     /// and the state of the memory protection unit (MPU).
     fn print_full_process(&self, writer: &mut dyn Write);
@@ -874,6 +909,21 @@ pub trait Process {
     /// Return the last syscall the process called. Returns `None` if the
     /// process has not called any syscalls or the information is unknown.
     fn debug_syscall_last(&self) -> Option<Syscall>;
+
+    /// Returns how many system calls this process has called for each driver.
+    fn debug_syscall_histogram(&self) -> SyscallHistogram;
+
+    /// Add `time_us` microseconds the process executed for to the processor
+    /// time it has used.
+    fn debug_cpu_time_used(&self, time_us: u32);
+
+    /// Returns the processor time in microseconds this process has used. This
+    /// only includes time the process ran with a timeslice.
+    fn debug_cpu_time_us(&self) -> u64;
+
+    /// Returns the highest number of tasks (for example upcalls) that have
+    /// been queued for this process at once.
+    fn debug_task_queue_high_water(&self) -> usize;
 }
 
 /// Opaque identifier for custom grants allocated dynamically from a process's
@@ -1161,6 +1211,58 @@ pub struct ProcessAddresses {
     /// have reached a lower address, this is only the lowest address seen when
     /// the process calls a syscall.
     pub sram_stack_bottom: Option<usize>,
+    /// The highest address the application break has been moved to, if
+    /// known. The process may have released some of this memory since.
+    pub sram_app_brk_max: Option<usize>,
+}
+
+/// Number of drivers whose system calls are counted separately in a
+/// [`SyscallHistogram`].
+pub const SYSCALL_HISTOGRAM_DRIVERS: usize = 8;
+
+/// Count of the system calls a process called for each driver.
+///
+/// Only the first [`SYSCALL_HISTOGRAM_DRIVERS`] drivers the process calls are
+/// counted separately. System calls for any other driver are counted together.
+/// System calls that are not for a driver (`yield`, `memop` and `exit`) are not
+/// counted.
+#[derive(Clone, Copy, Default)]
+pub struct SyscallHistogram {
+    /// Driver number and count for each separately counted driver.
+    drivers: [Option<(usize, usize)>; SYSCALL_HISTOGRAM_DRIVERS],
+    /// Count of system calls for all other drivers.
+    other: usize,
+}
+
+impl SyscallHistogram {
+    /// Count a system call for the driver `driver_num`.
+    pub fn record(&mut self, driver_num: usize) {
+        for entry in self.drivers.iter_mut() {
+            match entry {
+                Some((num, count)) if *num == driver_num => {
+                    *count += 1;
+                    return;
+                }
+                Some(_) => {}
+                None => {
+                    *entry = Some((driver_num, 1));
+                    return;
+                }
+            }
+        }
+        self.other += 1;
+    }
+
+    /// Iterate the `(driver_num, count)` pairs of the separately counted
+    /// drivers.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.drivers.iter().flatten().copied()
+    }
+
+    /// Count of system calls for drivers that are not counted separately.
+    pub fn other(&self) -> usize {
+        self.other
+    }
 }
 
 /// Collection of process state related to the size in memory of various process
@@ -19,14 +19,16 @@ use crate::capabilities::ProcessManagementCapability;
 use crate::config;
 use crate::debug;
 use crate::deferred_call::{DeferredCall, DeferredCallClient};
+use crate::dynamic_binary_storage::PADDING_TBF_HEADER_LENGTH;
+use crate::errorcode::ErrorCode;
 use crate::kernel::Kernel;
 use crate::platform::chip::Chip;
-use crate::process::{Process, ShortId};
+use crate::process::{Process, ProcessId, ShortId, State};
 use crate::process_binary::{ProcessBinary, ProcessBinaryError};
 use crate::process_checker::AcceptedCredential;
 use crate::process_checker::{AppIdPolicy, ProcessCheckError, ProcessCheckerMachine};
-use crate::process_policies::ProcessFaultPolicy;
 use crate::process_policies::ProcessStandardStoragePermissionsPolicy;
+use crate::process_policies::{ProcessAdmissionPolicy, ProcessFaultPolicy};
 use crate::process_standard::ProcessStandard;
 use crate::process_standard::{ProcessStandardDebug, ProcessStandardDebugFull};
 use crate::utilities::cells::{MapCell, OptionalCell};
@@ -59,6 +61,15 @@ pub enum ProcessLoadError {
     /// There is nowhere in the `PROCESSES` array to store this process.
     NoProcessSlot,
 
+    /// A process with the same AppID or ShortId is already running, so this
+    /// process cannot be loaded next to it.
+    AppIdConflict,
+
+    /// The admission policy rejected the process, for example because the
+    /// scheduler cannot reserve the processor time the process requests next
+    /// to the processes that are already loaded.
+    NotAdmitted,
+
     /// Process loading failed because parsing the binary failed.
     BinaryError(ProcessBinaryError),
 
@@ -98,6 +109,14 @@ impl fmt::Debug for ProcessLoadError {
                 write!(f, "Nowhere to store the loaded process")
             }
 
+            ProcessLoadError::AppIdConflict => {
+                write!(f, "A process with the same AppID is already loaded")
+            }
+
+            ProcessLoadError::NotAdmitted => {
+                write!(f, "The admission policy rejected the process")
+            }
+
             ProcessLoadError::BinaryError(binary_error) => {
                 writeln!(f, "Error parsing process binary")?;
                 write!(f, "{:?}", binary_error)
@@ -449,10 +468,93 @@ pub trait ProcessLoadingAsync<'a> {
     /// Set the credential checking policy for the loader.
     fn set_policy(&self, policy: &'a dyn AppIdPolicy);
 
+    /// Set the policy that decides whether a process can be loaded next to
+    /// the processes that are already loaded. Without one, every process is
+    /// admitted.
+    fn set_admission_policy(&self, admission_policy: &'a dyn ProcessAdmissionPolicy);
+
     /// Start the process loading operation.
     fn start(&self);
 }
 
+/// Client for loading a single process binary at runtime.
+pub trait ProcessLoadingDynamicClient {
+    /// The process binary passed to `load_new_process_binary()` was checked
+    /// and, if `result` is `Ok`, loaded into a new process.
+    fn process_binary_loaded(&self, result: Result<(), ProcessLoadError>);
+}
+
+/// Location in the app flash region where a new process binary can be stored.
+///
+/// The regions before and after the new process binary, given as
+/// `(address, length)`, must be covered with padding TBF headers so that the
+/// process binaries stored after them can still be discovered when booting.
+#[derive(Clone, Copy, Debug)]
+pub struct NewProcessBinaryLocation {
+    /// Address the new process binary must be written to.
+    pub address: usize,
+    /// Unused region in front of the new process binary.
+    pub padding_before: Option<(usize, usize)>,
+    /// Unused region between the new process binary and the next process
+    /// binary in flash.
+    pub padding_after: Option<(usize, usize)>,
+}
+
+/// Loading of process binaries that are written to flash after boot.
+///
+/// Loaders implementing this trait can instantiate a process from a single
+/// process binary stored in their app flash region once the initial process
+/// loading has finished.
+pub trait ProcessLoadingDynamic<'a> {
+    /// Set the client to receive the result of loading a new process binary.
+    fn set_dynamic_client(&self, client: &'a dyn ProcessLoadingDynamicClient);
+
+    /// Find where a new process binary of `app_length` bytes can be stored.
+    ///
+    /// Padding and disabled process binaries, such as those of unloaded
+    /// processes, are reused before the free flash after the last process
+    /// binary. The location is aligned to the next power of two of
+    /// `app_length` so that the MPU can protect the new process. Returns
+    /// `NOMEM` if there is not enough free flash.
+    fn find_new_process_binary_location(
+        &self,
+        app_length: usize,
+    ) -> Result<NewProcessBinaryLocation, ErrorCode>;
+
+    /// Check and load the process binary of `app_length` bytes stored at
+    /// `address`.
+    ///
+    /// The outcome is signalled through
+    /// `ProcessLoadingDynamicClient::process_binary_loaded()`. Returns `BUSY`
+    /// if the loader is still loading processes and `INVAL` if the process
+    /// binary is not in the app flash region.
+    fn load_new_process_binary(&self, address: usize, app_length: usize) -> Result<(), ErrorCode>;
+
+    /// Check that the process `processid` can be unloaded and return the
+    /// flash region of its process binary.
+    ///
+    /// This lets callers prepare changes to the process binary before the
+    /// process is removed with `unload_process()`. Returns `BUSY` if the
+    /// loader is loading a process, `INVAL` if `processid` does not refer to
+    /// a terminated process and `NOMEM` if the loader cannot keep the memory
+    /// of another unloaded process.
+    fn unloadable_process_flash(&self, processid: ProcessId) -> Result<&'static [u8], ErrorCode>;
+
+    /// Remove the process `processid` so that a different process can be
+    /// loaded in its place.
+    ///
+    /// The process must be terminated. Its entry in the processes array is
+    /// freed and its memory is kept for processes loaded later. Returns the
+    /// flash region of the process binary, which is left unmodified, so that
+    /// it can be disabled or erased. Returns the same errors as
+    /// `unloadable_process_flash()`, in which case the process is unchanged.
+    fn unload_process(
+        &self,
+        processid: ProcessId,
+        capability: &dyn ProcessManagementCapability,
+    ) -> Result<&'static [u8], ErrorCode>;
+}
+
 /// Operating mode of the loader.
 #[derive(Clone, Copy)]
 enum SequentialProcessLoaderMachineState {
@@ -460,6 +562,10 @@ enum SequentialProcessLoaderMachineState {
     DiscoverProcessBinaries,
     /// Phase of loading `ProcessBinary`s into `Process`s.
     LoadProcesses,
+    /// Phase of checking a process binary written to flash at runtime.
+    DynamicCheck,
+    /// Phase of loading a checked runtime process binary into a `Process`.
+    DynamicLoad,
 }
 
 /// A machine for loading processes stored sequentially in a region of flash.
@@ -472,16 +578,23 @@ pub struct SequentialProcessLoaderMachine<'a, C: Chip + 'static, D: ProcessStand
 {
     /// Client to notify as processes are loaded and process loading finishes.
     client: OptionalCell<&'a dyn ProcessLoadingAsyncClient>,
+    /// Client to notify when a process binary loaded at runtime is ready.
+    dynamic_client: OptionalCell<&'a dyn ProcessLoadingDynamicClient>,
     /// Machine to use to check process credentials.
     checker: &'static ProcessCheckerMachine,
     /// Array of stored process references for loaded processes.
     procs: MapCell<&'static mut [Option<&'static dyn Process>]>,
     /// Array to store `ProcessBinary`s after checking credentials.
     proc_binaries: MapCell<&'static mut [Option<ProcessBinary>]>,
+    /// Entire flash memory region that process binaries are stored in.
+    flash_bank: &'static [u8],
     /// Flash memory region to load processes from.
     flash: Cell<&'static [u8]>,
     /// Memory available to assign to applications.
     app_memory: Cell<&'static mut [u8]>,
+    /// Memory released by unloaded processes, which is assigned to new
+    /// processes before `app_memory`.
+    free_memory: MapCell<&'static mut [Option<&'static mut [u8]>]>,
     /// Mechanism for generating async callbacks.
     deferred_call: DeferredCall,
     /// Reference to the kernel object for creating Processes.
@@ -490,6 +603,8 @@ pub struct SequentialProcessLoaderMachine<'a, C: Chip + 'static, D: ProcessStand
     chip: &'static C,
     /// The policy to use when determining ShortIds and process uniqueness.
     policy: OptionalCell<&'a dyn AppIdPolicy>,
+    /// The policy to use when deciding whether a process can be loaded.
+    admission_policy: OptionalCell<&'a dyn ProcessAdmissionPolicy>,
     /// The fault policy to assign to each created Process.
     fault_policy: &'static dyn ProcessFaultPolicy,
     /// The storage permissions policy to assign to each created Process.
@@ -511,6 +626,7 @@ impl<C: Chip, D: ProcessStandardDebug> SequentialProcessLoaderMachine<'_, C, D>
         chip: &'static C,
         flash: &'static [u8],
         app_memory: &'static mut [u8],
+        free_memory: &'static mut [Option<&'static mut [u8]>],
         fault_policy: &'static dyn ProcessFaultPolicy,
         storage_policy: &'static dyn ProcessStandardStoragePermissionsPolicy<C, D>,
         policy: &'static dyn AppIdPolicy,
@@ -520,13 +636,17 @@ impl<C: Chip, D: ProcessStandardDebug> SequentialProcessLoaderMachine<'_, C, D>
             deferred_call: DeferredCall::new(),
             checker,
             client: OptionalCell::empty(),
+            dynamic_client: OptionalCell::empty(),
             procs: MapCell::new(procs),
             proc_binaries: MapCell::new(proc_binaries),
             kernel,
             chip,
+            flash_bank: flash,
             flash: Cell::new(flash),
             app_memory: Cell::new(app_memory),
+            free_memory: MapCell::new(free_memory),
             policy: OptionalCell::new(policy),
+            admission_policy: OptionalCell::empty(),
             fault_policy,
             storage_policy,
             state: OptionalCell::empty(),
@@ -720,6 +840,16 @@ impl<C: Chip, D: ProcessStandardDebug> SequentialProcessLoaderMachine<'_, C, D>
                     continue;
                 }
 
+                if !self.is_admitted(&process_binary) {
+                    if config::CONFIG.debug_load_processes {
+                        debug!("Loading: Process not admitted.");
+                    }
+                    self.client.map(|client| {
+                        client.process_loaded(Err(ProcessLoadError::NotAdmitted));
+                    });
+                    continue;
+                }
+
                 // If we get here it is ok to load the process.
                 match self.find_open_process_slot() {
                     Some(index) => {
@@ -802,6 +932,173 @@ impl<C: Chip, D: ProcessStandardDebug> SequentialProcessLoaderMachine<'_, C, D>
         Ok(())
     }
 
+    /// Check a process binary written to flash at runtime.
+    ///
+    /// `self.flash` has been set to the location of the new process binary.
+    fn check_new_process_binary(&self) {
+        let result = self
+            .discover_process_binary()
+            .map_err(ProcessLoadError::BinaryError)
+            .and_then(|pb| self.checker.check(pb).map_err(ProcessLoadError::CheckError));
+        if let Err(e) = result {
+            self.new_process_binary_done(Err(e));
+        }
+    }
+
+    /// Create a process object from a checked runtime process binary.
+    fn load_new_process_object(&self) {
+        let process_binary = self.proc_binaries.map_or(None, |proc_bins| {
+            proc_bins.iter_mut().find_map(|pb| pb.take())
+        });
+        let result = match process_binary {
+            Some(process_binary) => self.load_new_process(process_binary),
+            None => Err(ProcessLoadError::InternalError),
+        };
+        self.new_process_binary_done(result);
+    }
+
+    fn load_new_process(&self, process_binary: ProcessBinary) -> Result<(), ProcessLoadError> {
+        // Unlike during boot there is no version ordering between a running
+        // process and a new one: the old process must be removed first.
+        let blocked = self.procs.map_or(false, |procs| {
+            procs
+                .iter()
+                .flatten()
+                .any(|p| self.is_blocked_from_loading_by_process(&process_binary, *p))
+        });
+        if blocked {
+            return Err(ProcessLoadError::AppIdConflict);
+        }
+        if !self.is_admitted(&process_binary) {
+            return Err(ProcessLoadError::NotAdmitted);
+        }
+
+        let index = self
+            .find_open_process_slot()
+            .ok_or(ProcessLoadError::NoProcessSlot)?;
+        let short_app_id = self.policy.map_or(ShortId::LocallyUnique, |policy| {
+            policy.to_short_id(&process_binary)
+        });
+
+        // Creating a process consumes the process binary even if it fails, so
+        // it is parsed again from flash for every memory region tried.
+        let flash = process_binary.flash;
+        let credential = process_binary.credential.get();
+        let mut process_binary = Some(process_binary);
+
+        // Memory released by unloaded processes is tried first, then the
+        // memory that was never assigned to a process.
+        let num_free = self.free_memory.map_or(0, |free_memory| free_memory.len());
+        let mut result = Err(ProcessLoadError::NotEnoughMemory);
+        for region in (0..num_free).map(Some).chain(core::iter::once(None)) {
+            let memory = match region {
+                Some(i) => match self
+                    .free_memory
+                    .and_then(|free_memory| free_memory[i].take())
+                {
+                    Some(memory) => memory,
+                    None => continue,
+                },
+                None => self.app_memory.take(),
+            };
+            let process_binary = match process_binary.take() {
+                Some(process_binary) => process_binary,
+                None => {
+                    self.flash.set(flash);
+                    let process_binary = self
+                        .discover_process_binary()
+                        .map_err(ProcessLoadError::BinaryError)?;
+                    process_binary.credential.insert(credential);
+                    process_binary
+                }
+            };
+
+            let (unused_memory, load_result) = match load_process(
+                self.kernel,
+                self.chip,
+                process_binary,
+                memory,
+                short_app_id,
+                index,
+                self.fault_policy,
+                self.storage_policy,
+            ) {
+                Ok((new_mem, proc)) => (new_mem, Ok(proc)),
+                Err((new_mem, err)) => (new_mem, Err(err)),
+            };
+            match region {
+                Some(i) => {
+                    self.free_memory.map(|free_memory| {
+                        free_memory[i] = Some(unused_memory).filter(|m| !m.is_empty());
+                    });
+                }
+                None => self.app_memory.set(unused_memory),
+            }
+
+            match load_result {
+                Ok(proc) => {
+                    // Discovery rejects disabled processes and padding, so a
+                    // process object is always created here.
+                    let proc = proc.ok_or(ProcessLoadError::InternalError)?;
+                    if config::CONFIG.debug_load_processes {
+                        debug!("Loading: Loaded new process {}", proc.get_process_name());
+                    }
+                    self.procs.map(|procs| {
+                        procs[index] = Some(proc);
+                    });
+                    return Ok(());
+                }
+                Err(err) => result = Err(err),
+            }
+        }
+        result
+    }
+
+    /// Finish loading a runtime process binary and notify the client.
+    fn new_process_binary_done(&self, result: Result<(), ProcessLoadError>) {
+        self.state.clear();
+        self.dynamic_client.map(|client| {
+            client.process_binary_loaded(result);
+        });
+    }
+
+    /// Whether the entry at `offset` in the flash bank is padding or a
+    /// disabled process binary, whose flash can be reused.
+    fn is_reusable(&self, offset: usize, version: u16, header_length: u16) -> bool {
+        self.flash_bank
+            .get(offset..offset + header_length as usize)
+            .and_then(|header| tock_tbf::parse::parse_tbf_header(header, version).ok())
+            .is_some_and(|header| !header.is_app() || !header.enabled())
+    }
+
+    /// Place a process binary of `app_length` bytes in the unused flash
+    /// region from `start` to `end`.
+    fn fit_process_binary(
+        start: usize,
+        end: usize,
+        app_length: usize,
+    ) -> Option<NewProcessBinaryLocation> {
+        // A gap that is too small to hold a padding header cannot be skipped
+        // over, so move on to the next aligned address.
+        let alignment = app_length.next_power_of_two();
+        let mut address = start.next_multiple_of(alignment);
+        if address != start && address - start < PADDING_TBF_HEADER_LENGTH {
+            address += alignment;
+        }
+
+        let app_end = address.checked_add(app_length)?;
+        let after = end.checked_sub(app_end)?;
+        if after != 0 && after < PADDING_TBF_HEADER_LENGTH {
+            return None;
+        }
+
+        Some(NewProcessBinaryLocation {
+            address,
+            padding_before: (address != start).then_some((start, address - start)),
+            padding_after: (after != 0).then_some((app_end, after)),
+        })
+    }
+
     /// Check if `pb1` is blocked from running by `pb2`.
     ///
     /// `pb2` blocks `pb1` if:
@@ -869,6 +1166,13 @@ impl<C: Chip, D: ProcessStandardDebug> SequentialProcessLoaderMachine<'_, C, D>
 
         blocks
     }
+
+    /// Check whether the admission policy allows loading a process from
+    /// `pb` next to the processes that are already loaded.
+    fn is_admitted(&self, pb: &ProcessBinary) -> bool {
+        self.admission_policy
+            .map_or(true, |admission_policy| admission_policy.admit(pb))
+    }
 }
 
 impl<'a, C: Chip, D: ProcessStandardDebug> ProcessLoadingAsync<'a>
@@ -882,6 +1186,10 @@ impl<'a, C: Chip, D: ProcessStandardDebug> ProcessLoadingAsync<'a>
         self.policy.replace(policy);
     }
 
+    fn set_admission_policy(&self, admission_policy: &'a dyn ProcessAdmissionPolicy) {
+        self.admission_policy.replace(admission_policy);
+    }
+
     fn start(&self) {
         self.state
             .set(SequentialProcessLoaderMachineState::DiscoverProcessBinaries);
@@ -890,6 +1198,158 @@ impl<'a, C: Chip, D: ProcessStandardDebug> ProcessLoadingAsync<'a>
     }
 }
 
+impl<'a, C: Chip, D: ProcessStandardDebug> ProcessLoadingDynamic<'a>
+    for SequentialProcessLoaderMachine<'a, C, D>
+{
+    fn set_dynamic_client(&self, client: &'a dyn ProcessLoadingDynamicClient) {
+        self.dynamic_client.set(client);
+    }
+
+    fn find_new_process_binary_location(
+        &self,
+        app_length: usize,
+    ) -> Result<NewProcessBinaryLocation, ErrorCode> {
+        if app_length < PADDING_TBF_HEADER_LENGTH {
+            return Err(ErrorCode::INVAL);
+        }
+
+        // Walk the linked list of process binaries and look for a run of
+        // reusable entries that the new process binary fits into.
+        let bank_start = self.flash_bank.as_ptr() as usize;
+        let mut free_start = None;
+        let mut offset = 0;
+        while let Some(header) = self
+            .flash_bank
+            .get(offset..offset + 8)
+            .and_then(|h| h.try_into().ok())
+        {
+            let (length, reusable) = match tock_tbf::parse::parse_tbf_header_lengths(header) {
+                Ok((version, header_length, length)) => {
+                    (length, self.is_reusable(offset, version, header_length))
+                }
+                Err(tock_tbf::types::InitialTbfParseError::InvalidHeader(length)) => {
+                    (length, false)
+                }
+                Err(tock_tbf::types::InitialTbfParseError::UnableToParse) => break,
+            };
+            if length == 0 {
+                break;
+            }
+
+            if reusable {
+                free_start.get_or_insert(offset);
+            } else if let Some(start) = free_start.take() {
+                let location =
+                    Self::fit_process_binary(bank_start + start, bank_start + offset, app_length);
+                if let Some(location) = location {
+                    return Ok(location);
+                }
+            }
+            offset += length as usize;
+        }
+
+        // The last run of reusable entries extends into the unused flash after
+        // the last process binary, where no padding is needed after the new
+        // process binary.
+        let list_end = bank_start + offset;
+        let bank_end = bank_start + self.flash_bank.len();
+        let start = bank_start + free_start.unwrap_or(offset);
+        let mut location =
+            Self::fit_process_binary(start, bank_end, app_length).ok_or(ErrorCode::NOMEM)?;
+        location.padding_after = location
+            .padding_after
+            .and_then(|(address, _)| (address < list_end).then_some((address, list_end - address)));
+        if location
+            .padding_after
+            .is_some_and(|(_, length)| length < PADDING_TBF_HEADER_LENGTH)
+        {
+            return Err(ErrorCode::NOMEM);
+        }
+        Ok(location)
+    }
+
+    fn load_new_process_binary(&self, address: usize, app_length: usize) -> Result<(), ErrorCode> {
+        if self.state.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        let offset = address
+            .checked_sub(self.flash_bank.as_ptr() as usize)
+            .ok_or(ErrorCode::INVAL)?;
+        let flash = offset
+            .checked_add(app_length)
+            .and_then(|end| self.flash_bank.get(offset..end))
+            .ok_or(ErrorCode::INVAL)?;
+
+        self.flash.set(flash);
+        self.state
+            .set(SequentialProcessLoaderMachineState::DynamicCheck);
+        self.deferred_call.set();
+        Ok(())
+    }
+
+    fn unloadable_process_flash(&self, processid: ProcessId) -> Result<&'static [u8], ErrorCode> {
+        if self.state.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+
+        let process = self
+            .procs
+            .map_or(None, |procs| procs.get(processid.index).copied().flatten())
+            .filter(|p| p.processid() == processid && p.get_state() == State::Terminated)
+            .ok_or(ErrorCode::INVAL)?;
+
+        // Check for a free slot now, so that the memory of the process is not
+        // lost after it has been removed.
+        if !self
+            .free_memory
+            .map_or(false, |free_memory| free_memory.iter().any(|m| m.is_none()))
+        {
+            return Err(ErrorCode::NOMEM);
+        }
+
+        let addresses = process.get_addresses();
+        let bank_start = self.flash_bank.as_ptr() as usize;
+        Ok(self
+            .flash_bank
+            .get(addresses.flash_start - bank_start..addresses.flash_end - bank_start)
+            .unwrap_or(&[]))
+    }
+
+    fn unload_process(
+        &self,
+        processid: ProcessId,
+        capability: &dyn ProcessManagementCapability,
+    ) -> Result<&'static [u8], ErrorCode> {
+        let flash = self.unloadable_process_flash(processid)?;
+        let process = self
+            .procs
+            .map_or(None, |procs| procs[processid.index].take())
+            .ok_or(ErrorCode::INVAL)?;
+
+        if config::CONFIG.debug_load_processes {
+            debug!("Loading: Unloading process {}", process.get_process_name());
+        }
+
+        // The process is terminated, so it has no tasks and is not chosen by
+        // the scheduler. The kernel and the schedulers only reach processes
+        // through their entry in the processes array, which is now empty, so
+        // `process` is the last reference that is used to access the process
+        // from here on. A caller further up the stack, such as a system call
+        // in progress that unloads its own process, may still hold a
+        // reference, but the released memory is only handed to a new process
+        // from a later deferred call of this loader, after that stack has
+        // unwound.
+        let memory = unsafe { process.release_memory(capability) };
+        self.free_memory.map(|free_memory| {
+            if let Some(slot) = free_memory.iter_mut().find(|m| m.is_none()) {
+                *slot = Some(memory);
+            }
+        });
+
+        Ok(flash)
+    }
+}
+
 impl<C: Chip, D: ProcessStandardDebug> DeferredCallClient
     for SequentialProcessLoaderMachine<'_, C, D>
 {
@@ -899,6 +1359,12 @@ impl<C: Chip, D: ProcessStandardDebug> DeferredCallClient
             Some(SequentialProcessLoaderMachineState::DiscoverProcessBinaries) => {
                 self.load_and_check();
             }
+            Some(SequentialProcessLoaderMachineState::DynamicCheck) => {
+                self.check_new_process_binary();
+            }
+            Some(SequentialProcessLoaderMachineState::DynamicLoad) => {
+                self.load_new_process_object();
+            }
             Some(SequentialProcessLoaderMachineState::LoadProcesses) => {
                 let ret = self.load_process_objects();
                 match ret {
@@ -929,6 +1395,25 @@ impl<C: Chip, D: ProcessStandardDebug> crate::process_checker::ProcessCheckerMac
         process_binary: ProcessBinary,
         result: Result<Option<AcceptedCredential>, crate::process_checker::ProcessCheckError>,
     ) {
+        if let Some(SequentialProcessLoaderMachineState::DynamicCheck) = self.state.get() {
+            match result {
+                Ok(optional_credential) => match self.find_open_process_binary_slot() {
+                    Some(index) => {
+                        self.proc_binaries.map(|proc_binaries| {
+                            process_binary.credential.insert(optional_credential);
+                            proc_binaries[index] = Some(process_binary);
+                        });
+                        self.state
+                            .set(SequentialProcessLoaderMachineState::DynamicLoad);
+                        self.deferred_call.set();
+                    }
+                    None => self.new_process_binary_done(Err(ProcessLoadError::NoProcessSlot)),
+                },
+                Err(e) => self.new_process_binary_done(Err(ProcessLoadError::CheckError(e))),
+            }
+            return;
+        }
+
         // Check if this process was approved by the checker.
         match result {
             Ok(optional_credential) => {
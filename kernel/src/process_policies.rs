@@ -11,6 +11,7 @@
 use crate::platform::chip::Chip;
 use crate::process;
 use crate::process::Process;
+use crate::process_binary::ProcessBinary;
 use crate::process_standard::ProcessStandard;
 use crate::process_standard::ProcessStandardDebug;
 use crate::storage_permissions::StoragePermissions;
@@ -25,6 +26,16 @@ pub trait ProcessFaultPolicy {
     fn action(&self, process: &dyn Process) -> process::FaultAction;
 }
 
+/// Generic trait for implementing a policy on whether a process can be loaded
+/// next to the processes that are already loaded.
+///
+/// Schedulers that reserve processor time for processes use this to reject
+/// processes whose reservation cannot be met.
+pub trait ProcessAdmissionPolicy {
+    /// Return `true` if a process can be created from `process_binary`.
+    fn admit(&self, process_binary: &ProcessBinary) -> bool;
+}
+
 /// Generic trait for implementing a policy on how applications should be
 /// assigned storage permissions.
 pub trait ProcessStandardStoragePermissionsPolicy<C: Chip, D: ProcessStandardDebug> {
@@ -26,7 +26,7 @@ use crate::process::BinaryVersion;
 use crate::process::ProcessBinary;
 use crate::process::{Error, FunctionCall, FunctionCallSource, Process, Task};
 use crate::process::{FaultAction, ProcessCustomGrantIdentifier, ProcessId};
-use crate::process::{ProcessAddresses, ProcessSizes, ShortId};
+use crate::process::{ProcessAddresses, ProcessSizes, ShortId, SyscallHistogram};
 use crate::process::{State, StoppedState};
 use crate::process_checker::AcceptedCredential;
 use crate::process_loading::ProcessLoadError;
@@ -80,6 +80,15 @@ pub trait ProcessStandardDebug: Default {
     /// address if it is the lowest address that the process's stack has
     /// reached.
     fn set_new_app_stack_min_pointer(&self, ptr: *const u8);
+    /// Provide the new application break and record it if it is the highest
+    /// address the application break has reached.
+    fn set_new_app_heap_max_pointer(&self, ptr: *const u8);
+    /// Get the highest address the application break has reached, if it was
+    /// recorded.
+    fn get_app_heap_max_pointer(&self) -> Option<*const u8>;
+    /// Clear any record of the highest address the application break has
+    /// reached.
+    fn reset_app_heap_max_pointer(&self);
 
     /// Record the most recent system call the process called.
     fn set_last_syscall(&self, syscall: Syscall);
@@ -101,6 +110,20 @@ pub trait ProcessStandardDebug: Default {
     /// to 0.
     fn reset_syscall_count(&self);
 
+    /// Increase the recorded count of the number of system calls the process
+    /// has called for the driver `driver_num`.
+    fn increment_driver_syscall_count(&self, driver_num: usize);
+    /// Get the recorded count of the number of system calls the process has
+    /// called for each driver.
+    ///
+    /// This should be empty if
+    /// [`ProcessStandardDebug::increment_driver_syscall_count()`] is never
+    /// called.
+    fn get_syscall_histogram(&self) -> SyscallHistogram;
+    /// Reset the recorded count of the number of system calls called by the app
+    /// for each driver to 0.
+    fn reset_syscall_histogram(&self);
+
     /// Increase the recorded count of the number of upcalls that have been
     /// dropped for the process.
     fn increment_dropped_upcall_count(&self);
@@ -128,6 +151,29 @@ pub trait ProcessStandardDebug: Default {
     /// Reset the recorded count of the number of the process has exceeded its
     /// timeslice to 0.
     fn reset_timeslice_expiration_count(&self);
+
+    /// Add to the recorded processor time the process has used.
+    fn add_cpu_time(&self, time_us: u32);
+    /// Get the recorded processor time in microseconds the process has used.
+    ///
+    /// This should return 0 if [`ProcessStandardDebug::add_cpu_time()`] is
+    /// never called.
+    fn get_cpu_time_us(&self) -> u64;
+    /// Reset the recorded processor time the process has used to 0.
+    fn reset_cpu_time(&self);
+
+    /// Provide the number of tasks queued for the process and record it if it
+    /// is the highest number of tasks that have been queued at once.
+    fn set_new_task_queue_high_water(&self, len: usize);
+    /// Get the recorded highest number of tasks that have been queued for the
+    /// process at once.
+    ///
+    /// This should return 0 if
+    /// [`ProcessStandardDebug::set_new_task_queue_high_water()`] is never
+    /// called.
+    fn get_task_queue_high_water(&self) -> usize;
+    /// Reset the recorded highest number of queued tasks to 0.
+    fn reset_task_queue_high_water(&self);
 }
 
 /// A debugging implementation for [`ProcessStandard`] that records the full
@@ -166,9 +212,16 @@ struct ProcessStandardDebugFullInner {
     /// How low have we ever seen the stack pointer.
     app_stack_min_pointer: Option<*const u8>,
 
+    /// How high have we ever seen the application break.
+    app_heap_max_pointer: Option<*const u8>,
+
     /// How many syscalls have occurred since the process started.
     syscall_count: usize,
 
+    /// How many syscalls have occurred for each driver since the process
+    /// started.
+    syscall_histogram: SyscallHistogram,
+
     /// What was the most recent syscall.
     last_syscall: Option<Syscall>,
 
@@ -179,6 +232,12 @@ struct ProcessStandardDebugFullInner {
     /// How many times this process has been paused because it exceeded its
     /// timeslice.
     timeslice_expiration_count: usize,
+
+    /// How much processor time this process has used, in microseconds.
+    cpu_time_us: u64,
+
+    /// The highest number of tasks that were queued for this process at once.
+    task_queue_high_water: usize,
 }
 
 impl ProcessStandardDebug for ProcessStandardDebugFull {
@@ -225,6 +284,19 @@ impl ProcessStandardDebug for ProcessStandardDebugFull {
             }
         });
     }
+    fn set_new_app_heap_max_pointer(&self, ptr: *const u8) {
+        self.debug.map(|d| {
+            if d.app_heap_max_pointer.map_or(true, |ahmp| ptr > ahmp) {
+                d.app_heap_max_pointer = Some(ptr);
+            }
+        });
+    }
+    fn get_app_heap_max_pointer(&self) -> Option<*const u8> {
+        self.debug.map_or(None, |d| d.app_heap_max_pointer)
+    }
+    fn reset_app_heap_max_pointer(&self) {
+        self.debug.map(|d| d.app_heap_max_pointer = None);
+    }
 
     fn set_last_syscall(&self, syscall: Syscall) {
         self.debug.map(|d| d.last_syscall = Some(syscall));
@@ -246,6 +318,18 @@ impl ProcessStandardDebug for ProcessStandardDebugFull {
         self.debug.map(|d| d.syscall_count = 0);
     }
 
+    fn increment_driver_syscall_count(&self, driver_num: usize) {
+        self.debug.map(|d| d.syscall_histogram.record(driver_num));
+    }
+    fn get_syscall_histogram(&self) -> SyscallHistogram {
+        self.debug
+            .map_or(SyscallHistogram::default(), |d| d.syscall_histogram)
+    }
+    fn reset_syscall_histogram(&self) {
+        self.debug
+            .map(|d| d.syscall_histogram = SyscallHistogram::default());
+    }
+
     fn increment_dropped_upcall_count(&self) {
         self.debug.map(|d| d.dropped_upcall_count += 1);
     }
@@ -265,6 +349,30 @@ impl ProcessStandardDebug for ProcessStandardDebugFull {
     fn reset_timeslice_expiration_count(&self) {
         self.debug.map(|d| d.timeslice_expiration_count = 0);
     }
+
+    fn add_cpu_time(&self, time_us: u32) {
+        self.debug.map(|d| d.cpu_time_us += u64::from(time_us));
+    }
+    fn get_cpu_time_us(&self) -> u64 {
+        self.debug.map_or(0, |d| d.cpu_time_us)
+    }
+    fn reset_cpu_time(&self) {
+        self.debug.map(|d| d.cpu_time_us = 0);
+    }
+
+    fn set_new_task_queue_high_water(&self, len: usize) {
+        self.debug.map(|d| {
+            if len > d.task_queue_high_water {
+                d.task_queue_high_water = len;
+            }
+        });
+    }
+    fn get_task_queue_high_water(&self) -> usize {
+        self.debug.map_or(0, |d| d.task_queue_high_water)
+    }
+    fn reset_task_queue_high_water(&self) {
+        self.debug.map(|d| d.task_queue_high_water = 0);
+    }
 }
 
 impl Default for ProcessStandardDebugFull {
@@ -297,6 +405,11 @@ impl ProcessStandardDebug for () {
         None
     }
     fn set_new_app_stack_min_pointer(&self, _ptr: *const u8) {}
+    fn set_new_app_heap_max_pointer(&self, _ptr: *const u8) {}
+    fn get_app_heap_max_pointer(&self) -> Option<*const u8> {
+        None
+    }
+    fn reset_app_heap_max_pointer(&self) {}
 
     fn set_last_syscall(&self, _syscall: Syscall) {}
     fn get_last_syscall(&self) -> Option<Syscall> {
@@ -309,6 +422,11 @@ impl ProcessStandardDebug for () {
         0
     }
     fn reset_syscall_count(&self) {}
+    fn increment_driver_syscall_count(&self, _driver_num: usize) {}
+    fn get_syscall_histogram(&self) -> SyscallHistogram {
+        SyscallHistogram::default()
+    }
+    fn reset_syscall_histogram(&self) {}
     fn increment_dropped_upcall_count(&self) {}
     fn get_dropped_upcall_count(&self) -> usize {
         0
@@ -319,6 +437,16 @@ impl ProcessStandardDebug for () {
         0
     }
     fn reset_timeslice_expiration_count(&self) {}
+    fn add_cpu_time(&self, _time_us: u32) {}
+    fn get_cpu_time_us(&self) -> u64 {
+        0
+    }
+    fn reset_cpu_time(&self) {}
+    fn set_new_task_queue_high_water(&self, _len: usize) {}
+    fn get_task_queue_high_water(&self) -> usize {
+        0
+    }
+    fn reset_task_queue_high_water(&self) {}
 }
 
 /// Entry that is stored in the grant pointer table at the top of process
@@ -528,6 +656,7 @@ impl<C: Chip, D: 'static + ProcessStandardDebug> Process for ProcessStandard<'_,
             match tasks.enqueue(task) {
                 true => {
                     // The task has been successfully enqueued.
+                    self.debug.set_new_task_queue_high_water(tasks.len());
                     Ok(())
                 }
                 false => {
@@ -714,6 +843,22 @@ impl<C: Chip, D: 'static + ProcessStandardDebug> Process for ProcessStandard<'_,
         self.state.set(State::Terminated);
     }
 
+    unsafe fn release_memory(
+        &self,
+        _capability: &dyn crate::capabilities::ProcessManagementCapability,
+    ) -> &'static mut [u8] {
+        self.tasks.map(|tasks| {
+            tasks.empty();
+        });
+        self.grant_ptrs_reset();
+        self.state.set(State::Terminated);
+
+        // The memory was carved out of the `'static` app memory when this
+        // process was created, and the caller guarantees that this process,
+        // which lives in that memory, is no longer used.
+        slice::from_raw_parts_mut(self.memory_start.cast_mut(), self.memory_len)
+    }
+
     fn get_restart_count(&self) -> usize {
         self.restart_count.get()
     }
@@ -751,6 +896,10 @@ impl<C: Chip, D: 'static + ProcessStandardDebug> Process for ProcessStandard<'_,
         self.storage_permissions
     }
 
+    fn get_real_time_parameters(&self) -> Option<(u32, u32)> {
+        self.header.get_real_time_parameters()
+    }
+
     fn number_writeable_flash_regions(&self) -> usize {
         self.header.number_writeable_flash_regions()
     }
@@ -857,6 +1006,7 @@ impl<C: Chip, D: 'static + ProcessStandardDebug> Process for ProcessStandard<'_,
             } else {
                 let old_break = self.app_break.get();
                 self.app_break.set(new_break);
+                self.debug.set_new_app_heap_max_pointer(new_break);
                 self.chip.mpu().configure_mpu(config);
 
                 let base = self.mem_start() as usize;
@@ -1393,12 +1543,31 @@ impl<C: Chip, D: 'static + ProcessStandardDebug> Process for ProcessStandard<'_,
     fn debug_syscall_called(&self, last_syscall: Syscall) {
         self.debug.increment_syscall_count();
         self.debug.set_last_syscall(last_syscall);
+        if let Some(driver_num) = last_syscall.driver_number() {
+            self.debug.increment_driver_syscall_count(driver_num);
+        }
     }
 
     fn debug_syscall_last(&self) -> Option<Syscall> {
         self.debug.get_last_syscall()
     }
 
+    fn debug_syscall_histogram(&self) -> SyscallHistogram {
+        self.debug.get_syscall_histogram()
+    }
+
+    fn debug_cpu_time_used(&self, time_us: u32) {
+        self.debug.add_cpu_time(time_us);
+    }
+
+    fn debug_cpu_time_us(&self) -> u64 {
+        self.debug.get_cpu_time_us()
+    }
+
+    fn debug_task_queue_high_water(&self) -> usize {
+        self.debug.get_task_queue_high_water()
+    }
+
     fn get_addresses(&self) -> ProcessAddresses {
         ProcessAddresses {
             flash_start: self.flash_start() as usize,
@@ -1414,6 +1583,7 @@ impl<C: Chip, D: 'static + ProcessStandardDebug> Process for ProcessStandard<'_,
             sram_heap_start: self.debug.get_app_heap_start_pointer().map(|p| p as usize),
             sram_stack_top: self.debug.get_app_stack_start_pointer().map(|p| p as usize),
             sram_stack_bottom: self.debug.get_app_stack_min_pointer().map(|p| p as usize),
+            sram_app_brk_max: self.debug.get_app_heap_max_pointer().map(|p| p as usize),
         }
     }
 
@@ -1526,6 +1696,19 @@ impl<C: Chip, D: 'static + ProcessStandardDebug> Process for ProcessStandard<'_,
             })
             .unwrap_or(Err(ErrorCode::FAIL))
     }
+
+    fn set_stored_state(&self, state: &[u8]) -> Result<(), ErrorCode> {
+        if !matches!(self.state.get(), State::Stopped(_)) {
+            return Err(ErrorCode::BUSY);
+        }
+        self.stored_state
+            .map(|stored_state| {
+                self.chip
+                    .userspace_kernel_boundary()
+                    .load_context(stored_state, state)
+            })
+            .unwrap_or(Err(ErrorCode::FAIL))
+    }
 }
 
 impl<C: 'static + Chip, D: 'static + ProcessStandardDebug> ProcessStandard<'_, C, D> {
@@ -2022,6 +2205,10 @@ impl<C: 'static + Chip, D: 'static + ProcessStandardDebug> ProcessStandard<'_, C
         self.debug.reset_syscall_count();
         self.debug.reset_dropped_upcall_count();
         self.debug.reset_timeslice_expiration_count();
+        self.debug.reset_syscall_histogram();
+        self.debug.reset_cpu_time();
+        self.debug.reset_task_queue_high_water();
+        self.debug.reset_app_heap_max_pointer();
 
         // Reset MPU region configuration.
         //
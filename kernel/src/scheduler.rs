@@ -5,6 +5,7 @@
 //! Interface for Tock kernel schedulers.
 
 pub mod cooperative;
+pub mod edf;
 pub mod mlfq;
 pub mod priority;
 pub mod round_robin;
@@ -13,6 +14,7 @@ use crate::deferred_call::DeferredCall;
 use crate::platform::chip::Chip;
 use crate::process::ProcessId;
 use crate::process::StoppedExecutingReason;
+use crate::trace;
 
 use core::num::NonZeroU32;
 
@@ -51,7 +53,9 @@ pub trait Scheduler<C: Chip> {
     /// Custom implementations of this function must be very careful, however,
     /// as this function is called in the core kernel loop.
     unsafe fn execute_kernel_work(&self, chip: &C) {
+        trace::trace(trace::Event::InterruptServiceStart);
         chip.service_pending_interrupts();
+        trace::trace(trace::Event::InterruptServiceEnd);
         while DeferredCall::has_tasks() && !chip.has_pending_interrupts() {
             DeferredCall::service_next_pending();
         }
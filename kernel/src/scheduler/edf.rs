@@ -0,0 +1,315 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Earliest Deadline First Scheduler for Tock
+//!
+//! Processes with a TBF Real Time Header request to run for `budget_us`
+//! microseconds in every period of `period_us` microseconds. The first period
+//! of a process starts when the scheduler first sees it, and each period ends
+//! with a deadline. Of the real-time processes that are ready and have budget
+//! left in their current period, the one with the earliest deadline runs.
+//!
+//! Budgets are enforced with the scheduler timer: a real-time process runs
+//! with a timeslice of the budget it has left, and once it used up its budget
+//! it does not run again until its next period starts.
+//!
+//! The scheduler also acts as the `ProcessAdmissionPolicy` of the process
+//! loader. A real-time process is only loaded if the utilization of all
+//! real-time processes, the sum of their `budget_us / period_us`, stays within
+//! the utilization bound of the scheduler. The bound is below 100% by default
+//! to leave processor time for the kernel. A real-time process is also
+//! rejected if its period is shorter than a tick of the scheduler alarm or
+//! longer than the alarm can measure.
+//!
+//! Processes without a TBF Real Time Header run round robin, but only while
+//! no real-time process is ready with budget left.
+
+use core::cell::Cell;
+use core::num::NonZeroU32;
+
+use crate::collections::list::{List, ListLink, ListNode};
+use crate::deferred_call::DeferredCall;
+use crate::hil::time::{self, ConvertTicks, Frequency, Ticks};
+use crate::platform::chip::Chip;
+use crate::process::Process;
+use crate::process::ProcessId;
+use crate::process::StoppedExecutingReason;
+use crate::process_binary::ProcessBinary;
+use crate::process_policies::ProcessAdmissionPolicy;
+use crate::scheduler::{Scheduler, SchedulingDecision};
+use crate::utilities::cells::OptionalCell;
+
+/// Per-process state of real-time processes.
+#[derive(Default)]
+struct EdfProcState {
+    /// The process this state belongs to. Empty if the process in the slot is
+    /// not a real-time process.
+    processid: OptionalCell<ProcessId>,
+    /// End of the current period, in ticks of the scheduler clock.
+    deadline: Cell<u64>,
+    /// Processor time the process has left in the current period.
+    budget_remaining_us: Cell<u32>,
+}
+
+/// Nodes store per-process state
+pub struct EdfProcessNode<'a> {
+    proc: &'static Option<&'static dyn Process>,
+    state: EdfProcState,
+    next: ListLink<'a, EdfProcessNode<'a>>,
+}
+
+impl<'a> EdfProcessNode<'a> {
+    pub fn new(proc: &'static Option<&'static dyn Process>) -> EdfProcessNode<'a> {
+        EdfProcessNode {
+            proc,
+            state: EdfProcState::default(),
+            next: ListLink::empty(),
+        }
+    }
+
+    fn is_real_time(&self) -> bool {
+        self.state.processid.is_some()
+    }
+
+    fn is_ready(&self) -> bool {
+        self.proc.is_some_and(|proc| proc.ready())
+    }
+}
+
+impl<'a> ListNode<'a, EdfProcessNode<'a>> for EdfProcessNode<'a> {
+    fn next(&'a self) -> &'a ListLink<'a, EdfProcessNode<'a>> {
+        &self.next
+    }
+}
+
+pub struct EdfSched<'a, A: 'static + time::Alarm<'static>> {
+    alarm: &'static A,
+    pub processes: List<'a, EdfProcessNode<'a>>,
+    utilization_bound_percent: u32,
+    /// Ticks of `alarm` since the scheduler started. Unlike the alarm itself
+    /// this does not wrap around.
+    clock: Cell<u64>,
+    last_now: Cell<A::Ticks>,
+    running: OptionalCell<&'a EdfProcessNode<'a>>,
+}
+
+impl<'a, A: 'static + time::Alarm<'static>> EdfSched<'a, A> {
+    /// How long a process without real-time parameters can run before being
+    /// pre-empted
+    const DEFAULT_TIMESLICE_US: u32 = 10000;
+    /// Share of the processor that can be reserved for real-time processes
+    pub const DEFAULT_UTILIZATION_BOUND_PERCENT: u32 = 90;
+
+    pub fn new(alarm: &'static A) -> Self {
+        Self::new_with_bound(alarm, Self::DEFAULT_UTILIZATION_BOUND_PERCENT)
+    }
+
+    pub fn new_with_bound(alarm: &'static A, utilization_bound_percent: u32) -> Self {
+        Self {
+            alarm,
+            processes: List::new(),
+            utilization_bound_percent,
+            clock: Cell::new(0),
+            last_now: Cell::new(A::Ticks::from(0)),
+            running: OptionalCell::empty(),
+        }
+    }
+
+    /// Advance the scheduler clock to the current time of the alarm.
+    fn update_clock(&self) -> u64 {
+        let now = self.alarm.now();
+        let elapsed = now.wrapping_sub(self.last_now.get());
+        self.last_now.set(now);
+        self.clock
+            .set(self.clock.get() + u64::from(elapsed.into_u32()));
+        self.clock.get()
+    }
+
+    /// Start a new period for every real-time process whose period ended,
+    /// and the first period for real-time processes that are new in their
+    /// slot.
+    fn update_periods(&self, clock: u64) {
+        for node in self.processes.iter() {
+            let state = &node.state;
+            let (proc, period_us, budget_us) = match node
+                .proc
+                .and_then(|proc| proc.get_real_time_parameters().map(|(p, b)| (proc, p, b)))
+            {
+                Some(params) => params,
+                None => {
+                    state.processid.clear();
+                    continue;
+                }
+            };
+            // Such periods are not admitted, but run the process round
+            // robin should it be loaded without this admission policy.
+            let period = match Self::period_ticks(period_us) {
+                Some(period) => period,
+                None => {
+                    state.processid.clear();
+                    continue;
+                }
+            };
+
+            if !state.processid.contains(&proc.processid()) {
+                state.processid.set(proc.processid());
+                state.deadline.set(clock + period);
+                state.budget_remaining_us.set(budget_us);
+            } else if clock >= state.deadline.get() {
+                // Periods follow each other, unless the process skipped a
+                // whole period, in which case the next period starts now.
+                let deadline = state.deadline.get() + period;
+                state.deadline.set(if clock < deadline {
+                    deadline
+                } else {
+                    clock + period
+                });
+                state.budget_remaining_us.set(budget_us);
+            }
+        }
+    }
+
+    /// Returns the ready real-time process with budget left that has the
+    /// earliest deadline.
+    fn next_real_time(&self) -> Option<&'a EdfProcessNode<'a>> {
+        self.processes
+            .iter()
+            .filter(|node| {
+                node.is_real_time() && node.state.budget_remaining_us.get() > 0 && node.is_ready()
+            })
+            .min_by_key(|node| node.state.deadline.get())
+    }
+
+    /// Move `node` and all nodes before it to the back of the list, so that
+    /// processes without real-time parameters take turns.
+    fn rotate_past(&self, node: &'a EdfProcessNode<'a>) {
+        while let Some(head) = self.processes.pop_head() {
+            self.processes.push_tail(head);
+            if core::ptr::eq(head, node) {
+                break;
+            }
+        }
+    }
+
+    /// Length of a period of `period_us` in ticks of the scheduler clock, or
+    /// `None` if it is shorter than a tick or longer than the alarm can
+    /// measure. The clock advances by at most `u32::MAX` ticks at a time, so
+    /// that also bounds the period for 64-bit alarms.
+    fn period_ticks(period_us: u32) -> Option<u64> {
+        let ticks = u64::from(A::Frequency::frequency()) * u64::from(period_us) / 1_000_000;
+        let max = u64::from(A::Ticks::max_value().into_u32());
+        (ticks > 0 && ticks <= max).then_some(ticks)
+    }
+
+    /// Utilization of a real-time process in parts per million, rounded up.
+    fn utilization_ppm((period_us, budget_us): (u32, u32)) -> u64 {
+        (u64::from(budget_us) * 1_000_000).div_ceil(u64::from(period_us))
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>, C: Chip> Scheduler<C> for EdfSched<'_, A> {
+    fn next(&self) -> SchedulingDecision {
+        let clock = self.update_clock();
+        self.update_periods(clock);
+
+        if let Some(node) = self.next_real_time() {
+            self.running.set(node);
+            return match node.proc {
+                Some(proc) => SchedulingDecision::RunProcess((
+                    proc.processid(),
+                    NonZeroU32::new(node.state.budget_remaining_us.get()),
+                )),
+                None => SchedulingDecision::TrySleep,
+            };
+        }
+
+        // Every real-time process that is ready used up its budget. The
+        // earliest of their deadlines is when the next of them can run again.
+        let next_period = self
+            .processes
+            .iter()
+            .filter(|node| node.is_real_time() && node.is_ready())
+            .map(|node| node.state.deadline.get())
+            .min()
+            .map(|deadline| A::Ticks::from_or_max(deadline - clock));
+
+        let best_effort = self
+            .processes
+            .iter()
+            .find(|node| !node.is_real_time() && node.is_ready());
+        match best_effort.and_then(|node| node.proc.map(|proc| (node, proc))) {
+            Some((node, proc)) => {
+                self.running.set(node);
+                let timeslice = next_period.map_or(Self::DEFAULT_TIMESLICE_US, |ticks| {
+                    Self::DEFAULT_TIMESLICE_US.min(self.alarm.ticks_to_us(ticks).max(1))
+                });
+                SchedulingDecision::RunProcess((proc.processid(), NonZeroU32::new(timeslice)))
+            }
+            None => {
+                // Wake up at the start of the next period, as nothing else
+                // may wake the kernel before then.
+                if let Some(ticks) = next_period {
+                    self.alarm.set_alarm(self.last_now.get(), ticks);
+                }
+                SchedulingDecision::TrySleep
+            }
+        }
+    }
+
+    fn result(&self, result: StoppedExecutingReason, execution_time_us: Option<u32>) {
+        let execution_time_us = execution_time_us.unwrap(); // should never fail as we never run cooperatively
+        let node = match self.running.take() {
+            Some(node) => node,
+            None => return,
+        };
+        if node.is_real_time() {
+            let budget_remaining_us = if result == StoppedExecutingReason::TimesliceExpired {
+                0
+            } else {
+                node.state
+                    .budget_remaining_us
+                    .get()
+                    .saturating_sub(execution_time_us)
+            };
+            node.state.budget_remaining_us.set(budget_remaining_us);
+        } else if result != StoppedExecutingReason::KernelPreemption {
+            self.rotate_past(node);
+        }
+    }
+
+    unsafe fn continue_process(&self, _: ProcessId, chip: &C) -> bool {
+        // In addition to checking for interrupts, check whether the running
+        // process still has the earliest deadline, as a system call (for
+        // example IPC) may have made another real-time process ready.
+        let preempt = self.running.map_or(false, |running| {
+            self.next_real_time().is_some_and(|next| {
+                !running.is_real_time() || next.state.deadline.get() < running.state.deadline.get()
+            })
+        });
+        !(preempt || chip.has_pending_interrupts() || DeferredCall::has_tasks())
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>> time::AlarmClient for EdfSched<'_, A> {
+    fn alarm(&self) {
+        // The alarm only wakes the kernel so that the scheduler runs again.
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>> ProcessAdmissionPolicy for EdfSched<'_, A> {
+    fn admit(&self, process_binary: &ProcessBinary) -> bool {
+        let requested = match process_binary.header.get_real_time_parameters() {
+            Some((period_us, _)) if Self::period_ticks(period_us).is_none() => return false,
+            Some(params) => Self::utilization_ppm(params),
+            None => return true,
+        };
+        let reserved: u64 = self
+            .processes
+            .iter()
+            .filter_map(|node| node.proc.and_then(|proc| proc.get_real_time_parameters()))
+            .map(Self::utilization_ppm)
+            .sum();
+        reserved + requested <= u64::from(self.utilization_bound_percent) * 10_000
+    }
+}
@@ -712,4 +712,15 @@ pub trait UserspaceKernelBoundary {
     /// Store architecture specific (e.g. CPU registers or status flags) data
     /// for a process. On success returns the number of elements written to out.
     fn store_context(&self, state: &Self::StoredState, out: &mut [u8]) -> Result<usize, ErrorCode>;
+
+    /// Restore architecture specific (e.g. CPU registers or status flags) data
+    /// for a process from `data`, in the format written by `store_context()`.
+    /// This lets debuggers change the registers of a stopped process.
+    ///
+    /// Returns `ErrorCode::INVAL` if `data` is not a valid stored state for
+    /// this architecture. The default implementation returns
+    /// `ErrorCode::NOSUPPORT`.
+    fn load_context(&self, _state: &mut Self::StoredState, _data: &[u8]) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
 }
@@ -0,0 +1,85 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Structured tracing of kernel events.
+//!
+//! Unlike the text output of [`debug!`](crate::debug!), tracing records
+//! compact, typed events: syscall entry and exit, upcall scheduling, context
+//! switches, interrupt service and deferred calls. A board registers a
+//! [`Tracer`] with [`set_tracer()`], which timestamps and stores the events,
+//! for example in a ring buffer that is drained to a host tool for timeline
+//! analysis.
+//!
+//! Tracing is disabled unless the kernel crate is built with the
+//! `trace_events` feature, in which case the trace points compile to nothing.
+//!
+//! Events are recorded from the kernel thread only, including while
+//! interrupts are being serviced, but never from interrupt handlers.
+//! Architecture and chip crates can record the interrupts they service with
+//! [`trace()`] and [`Event::Interrupt`].
+
+use core::ptr::{addr_of, addr_of_mut};
+
+use crate::config;
+use crate::process::ProcessId;
+use crate::syscall::{ContextSwitchReason, Syscall};
+use crate::upcall::UpcallId;
+
+/// A traced kernel event.
+#[derive(Copy, Clone)]
+pub enum Event {
+    /// The kernel is switching to running a process.
+    SwitchToProcess(ProcessId),
+    /// A process stopped running and execution returned to the kernel, with
+    /// the reason, or `None` if switching to the process failed.
+    SwitchToKernel(ProcessId, Option<ContextSwitchReason>),
+    /// The kernel started handling a syscall of a process.
+    SyscallEntry(ProcessId, Syscall),
+    /// The kernel finished handling the last syscall of a process.
+    SyscallExit(ProcessId),
+    /// An upcall was scheduled for a process.
+    UpcallScheduled(ProcessId, UpcallId),
+    /// The kernel started servicing pending interrupts.
+    InterruptServiceStart,
+    /// The chip is servicing the interrupt with this number.
+    Interrupt(u32),
+    /// The kernel finished servicing pending interrupts.
+    InterruptServiceEnd,
+    /// The deferred call with this index started running.
+    DeferredCallStart(usize),
+    /// The deferred call with this index finished running.
+    DeferredCallEnd(usize),
+}
+
+/// Receiver of traced kernel events.
+pub trait Tracer {
+    /// Record `event`. This is called synchronously at the trace point, so it
+    /// should only store the event and return quickly.
+    fn trace(&self, event: Event);
+}
+
+/// The tracer events are passed to, if any.
+// Accessed only via copies of the reference, from the single kernel thread.
+static mut TRACER: Option<&'static dyn Tracer> = None;
+
+/// Set the tracer that kernel events are passed to.
+///
+/// # Safety
+///
+/// Must be called from the kernel thread, before the kernel loop starts.
+pub unsafe fn set_tracer(tracer: &'static dyn Tracer) {
+    *addr_of_mut!(TRACER) = Some(tracer);
+}
+
+/// Pass `event` to the tracer, if tracing is enabled and a tracer is set.
+#[inline]
+pub fn trace(event: Event) {
+    if config::CONFIG.trace_events {
+        // SAFETY: `TRACER` is only written before the kernel loop starts,
+        // and Tock is single threaded.
+        if let Some(tracer) = unsafe { *addr_of!(TRACER) } {
+            tracer.trace(event);
+        }
+    }
+}
@@ -9,6 +9,7 @@ use crate::debug;
 use crate::process;
 use crate::process::ProcessId;
 use crate::syscall::SyscallReturn;
+use crate::trace;
 use crate::utilities::capability_ptr::CapabilityPtr;
 use crate::utilities::machine_register::MachineRegister;
 use crate::ErrorCode;
@@ -171,6 +172,13 @@ impl Upcall {
             }
         };
 
+        if res.is_ok() {
+            trace::trace(trace::Event::UpcallScheduled(
+                self.process_id,
+                self.upcall_id,
+            ));
+        }
+
         if config::CONFIG.trace_syscalls {
             debug!(
                 "[{:?}] schedule[{:#x}:{}] @{:#x}({:#x}, {:#x}, {:#x}, {:#x}) = {:?}",
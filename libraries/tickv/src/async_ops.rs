@@ -149,6 +149,7 @@ pub struct AsyncTicKV<'a, C: FlashController<S>, const S: usize> {
     key: Cell<Option<u64>>,
     value: Cell<Option<&'static mut [u8]>>,
     value_length: Cell<usize>,
+    cursor: Cell<usize>,
 }
 
 impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
@@ -164,6 +165,7 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
             key: Cell::new(None),
             value: Cell::new(None),
             value_length: Cell::new(0),
+            cursor: Cell::new(0),
         }
     }
 
@@ -257,6 +259,52 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
         }
     }
 
+    /// Finds the next valid key in flash storage, see `TicKV::next_key()`.
+    ///
+    /// `cursor`: The position to start looking from, 0 for the first key.
+    /// `buf`: A buffer to store the start of the value to.
+    ///
+    /// On success a `SuccessCode` will be returned. Once the operation has
+    /// completed the key is available from `next_key_found()` and the
+    /// position to continue from from `cursor()`.
+    /// On error a `ErrorCode` will be returned.
+    pub fn next_key(
+        &self,
+        cursor: usize,
+        buf: &'static mut [u8],
+    ) -> Result<SuccessCode, (&'static mut [u8], ErrorCode)> {
+        let mut position = cursor;
+        match self.tickv.next_key(&mut position, buf) {
+            Ok(_key) => {
+                // Ok is a problem, since that means no asynchronous operations
+                // were called, which means our client will never get a
+                // callback. We need to error.
+                Err((buf, ErrorCode::ReadFail))
+            }
+            Err(e) => match e {
+                ErrorCode::ReadNotReady(_) => {
+                    self.cursor.set(position);
+                    self.key.replace(None);
+                    self.value.replace(Some(buf));
+                    Ok(SuccessCode::Queued)
+                }
+                _ => Err((buf, e)),
+            },
+        }
+    }
+
+    /// The hashed key and the length of the value found by the last
+    /// completed `next_key()` operation.
+    pub fn next_key_found(&self) -> Option<(u64, usize)> {
+        self.key.get().map(|key| (key, self.value_length.get()))
+    }
+
+    /// The position to continue finding keys from after the last completed
+    /// `next_key()` operation.
+    pub fn cursor(&self) -> usize {
+        self.cursor.get()
+    }
+
     /// Invalidates the key in flash storage
     ///
     /// `hash`: A hashed key.
@@ -362,6 +410,22 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
                 Ok(bytes_freed) => (Ok(SuccessCode::Complete), bytes_freed),
                 Err(e) => (Err(e), 0),
             },
+            State::NextKey(_) => {
+                let buf = self.value.take().unwrap();
+                let mut cursor = self.cursor.get();
+                let ret = self.tickv.next_key(&mut cursor, buf);
+                let copied = buf.len();
+                self.value.replace(Some(buf));
+                self.cursor.set(cursor);
+                match ret {
+                    Ok((hash, value_length)) => {
+                        self.key.replace(Some(hash));
+                        self.value_length.set(value_length);
+                        (Ok(SuccessCode::Complete), value_length.min(copied))
+                    }
+                    Err(e) => (Err(e), 0),
+                }
+            }
             _ => unreachable!(),
         };
 
@@ -1068,6 +1132,91 @@ mod tests {
             }
         }
 
+        #[test]
+        fn test_next_key() {
+            let mut read_buf: [u8; 1024] = [0; 1024];
+            let mut hash_function = DefaultHasher::new();
+            MAIN_KEY.hash(&mut hash_function);
+            let main_hash = hash_function.finish();
+
+            let tickv = AsyncTicKV::<FlashCtrl<1024>, 1024>::new(
+                FlashCtrl::new(true),
+                &mut read_buf,
+                0x10000,
+            );
+
+            let mut ret = tickv.initialise(main_hash);
+            while ret.is_err() {
+                flash_ctrl_callback(&tickv);
+
+                // There is no actual delay in the test, just continue now
+                let (r, _buf, _len) = tickv.continue_operation();
+                ret = r;
+            }
+
+            static mut VALUE: [u8; 32] = [0x23; 32];
+            static mut BUF: [u8; 4] = [0; 4];
+
+            println!("Add key ONE");
+            let ret =
+                unsafe { tickv.append_key(get_hashed_key(b"ONE"), &mut *addr_of_mut!(VALUE), 32) };
+            match ret {
+                Ok(SuccessCode::Queued) => {
+                    // There is no actual delay in the test, just continue now
+                    flash_ctrl_callback(&tickv);
+                    tickv.continue_operation().0.unwrap();
+                }
+                Err(_) => {}
+                _ => unreachable!(),
+            }
+
+            println!("List the keys");
+            let mut buf: &'static mut [u8] = unsafe { &mut *addr_of_mut!(BUF) };
+            let mut cursor = 0;
+            let mut keys = std::vec::Vec::new();
+            loop {
+                match tickv.next_key(cursor, buf) {
+                    Ok(SuccessCode::Queued) => {}
+                    Err((_buf, e)) => panic!("Expected SuccessCode::Queued, got {e:?}"),
+                    _ => unreachable!(),
+                }
+
+                let (ret, returned_buf, len) = loop {
+                    flash_ctrl_callback(&tickv);
+                    match tickv.continue_operation() {
+                        (Err(ErrorCode::ReadNotReady(_)), _, _) => {}
+                        r => break r,
+                    }
+                };
+                buf = returned_buf.unwrap();
+
+                match ret {
+                    Ok(_) => {
+                        let (key, value_length) = tickv.next_key_found().unwrap();
+                        if key == get_hashed_key(b"ONE") {
+                            assert_eq!(value_length, 32);
+                            assert_eq!(len, 4);
+                            assert_eq!(buf, [0x23; 4]);
+                        } else {
+                            assert_eq!(key, main_hash);
+                            assert_eq!(value_length, 0);
+                            assert_eq!(len, 0);
+                        }
+                        keys.push(key);
+                    }
+                    Err(ErrorCode::KeyNotFound) => break,
+                    Err(e) => panic!("Expected ErrorCode::KeyNotFound, got {e:?}"),
+                }
+                assert!(tickv.cursor() > cursor);
+                cursor = tickv.cursor();
+            }
+
+            keys.sort_unstable();
+            let mut expected = std::vec![main_hash, get_hashed_key(b"ONE")];
+            expected.sort_unstable();
+            assert_eq!(keys, expected);
+        }
+
         #[test]
         fn test_garbage_collect() {
             let mut read_buf: [u8; 1024] = [0; 1024];
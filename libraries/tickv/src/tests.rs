@@ -411,6 +411,66 @@ mod store_flast_ctrl {
         );
     }
 
+    #[test]
+    fn test_next_key() {
+        let mut read_buf: [u8; 1024] = [0; 1024];
+        let mut hash_function = DefaultHasher::new();
+        MAIN_KEY.hash(&mut hash_function);
+        let hash = hash_function.finish();
+
+        let tickv = TicKV::<FlashCtrl, 1024>::new(FlashCtrl::new(), &mut read_buf, 0x10000);
+        tickv.initialise(hash).unwrap();
+
+        let value: [u8; 32] = [0x23; 32];
+        let other_value: [u8; 8] = [0x42; 8];
+
+        println!("Add keys ONE, TWO and THREE");
+        tickv.append_key(get_hashed_key(b"ONE"), &value).unwrap();
+        tickv.append_key(get_hashed_key(b"TWO"), &value).unwrap();
+        tickv
+            .append_key(get_hashed_key(b"THREE"), &other_value)
+            .unwrap();
+
+        println!("Delete Key TWO");
+        tickv.invalidate_key(get_hashed_key(b"TWO")).unwrap();
+
+        println!("List the keys");
+        let mut keys: std::vec::Vec<(u64, usize)> = tickv.keys().map(|key| key.unwrap()).collect();
+        keys.sort_unstable();
+        let mut expected = std::vec![
+            (hash, 0),
+            (get_hashed_key(b"ONE"), 32),
+            (get_hashed_key(b"THREE"), 8),
+        ];
+        expected.sort_unstable();
+        assert_eq!(keys, expected);
+
+        println!("Read the start of the values");
+        let mut cursor = 0;
+        let mut buf: [u8; 4];
+        let mut found = 0;
+        loop {
+            buf = [0; 4];
+            match tickv.next_key(&mut cursor, &mut buf) {
+                Ok((key, _)) if key == get_hashed_key(b"ONE") => assert_eq!(buf, [0x23; 4]),
+                Ok((key, _)) if key == get_hashed_key(b"THREE") => assert_eq!(buf, [0x42; 4]),
+                Ok((key, _)) => assert_eq!(key, hash),
+                Err(e) => {
+                    assert_eq!(e, ErrorCode::KeyNotFound);
+                    break;
+                }
+            }
+            found += 1;
+        }
+        assert_eq!(found, 3);
+
+        println!("Continue after the last key");
+        assert_eq!(
+            tickv.next_key(&mut cursor, &mut buf),
+            Err(ErrorCode::KeyNotFound)
+        );
+    }
+
     #[test]
     fn test_append_and_delete_zeroise() {
         let mut read_buf: [u8; 1024] = [0; 1024];
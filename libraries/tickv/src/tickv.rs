@@ -55,6 +55,8 @@ pub(crate) enum State {
     ZeroiseKey(KeyState),
     /// Running garbage collection
     GarbageCollect(RubbishState),
+    /// Enumerating keys
+    NextKey(KeyState),
 }
 
 /// The struct storing all of the TicKV information.
@@ -983,6 +985,152 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
         }
     }
 
+    /// Find the next valid object in some loaded region data, starting at
+    /// `offset`.
+    ///
+    /// On success return the offset in the region_data of the object and the
+    /// total length of the object, or `None` if there are no more valid
+    /// objects in the region.
+    fn next_object_offset(
+        &self,
+        region_data: &[u8],
+        mut offset: usize,
+    ) -> Result<Option<(usize, u16)>, ErrorCode> {
+        loop {
+            if offset + HEADER_LENGTH >= S {
+                // We have reached the end of the region
+                return Ok(None);
+            }
+
+            let version = *region_data
+                .get(offset + VERSION_OFFSET)
+                .ok_or(ErrorCode::CorruptData)?;
+            if version == 0xFF {
+                // We hit the end.
+                return Ok(None);
+            }
+            if version != VERSION {
+                return Err(ErrorCode::UnsupportedVersion);
+            }
+
+            let flags = *region_data
+                .get(offset + LEN_OFFSET)
+                .ok_or(ErrorCode::CorruptData)?;
+            let total_length = ((flags as u16) & !0xF0) << 8
+                | *region_data
+                    .get(offset + LEN_OFFSET + 1)
+                    .ok_or(ErrorCode::CorruptData)? as u16;
+
+            if total_length == 0 {
+                // We found something invalid here, nothing after it can be
+                // found either.
+                return Ok(None);
+            }
+
+            if flags & 0x80 == 0x80 {
+                return Ok(Some((offset, total_length)));
+            }
+
+            // The entry has been deleted, skip it
+            offset += total_length as usize;
+        }
+    }
+
+    /// Finds the next valid key in flash storage.
+    ///
+    /// This walks the regions in order, so keys are not returned in the
+    /// order they were added. The object of the main key, which is added by
+    /// `initialise()`, is returned as well.
+    ///
+    /// `cursor`: The position to start looking from. This should be 0 to find
+    ///           the first key. On success it is updated to the position
+    ///           after the key that was found, so passing it to the next
+    ///           call finds the following key.
+    /// `buf`: A buffer to store the value to. If the value doesn't fit only
+    ///        the start of it is copied. The check sum is not verified.
+    ///
+    /// On success the hashed key and the length of the value will be
+    /// returned. When there are no more keys `KeyNotFound` is returned.
+    /// On error a `ErrorCode` will be returned.
+    pub fn next_key(&self, cursor: &mut usize, buf: &mut [u8]) -> Result<(u64, usize), ErrorCode> {
+        let num_region = self.flash_size / S;
+
+        loop {
+            let region = *cursor / S;
+            if region >= num_region {
+                self.state.set(State::None);
+                return Err(ErrorCode::KeyNotFound);
+            }
+
+            // Get the data from that region
+            let region_data = self.read_buffer.take().unwrap();
+            if self.state.get() != State::NextKey(KeyState::ReadRegion(region)) {
+                match self.controller.read_region(region, region_data) {
+                    Ok(()) => {}
+                    Err(e) => {
+                        self.read_buffer.replace(Some(region_data));
+                        if let ErrorCode::ReadNotReady(reg) = e {
+                            self.state.set(State::NextKey(KeyState::ReadRegion(reg)));
+                        }
+                        return Err(e);
+                    }
+                };
+            }
+            self.state.set(State::None);
+
+            let (offset, total_length) = match self.next_object_offset(region_data, *cursor % S) {
+                Ok(Some(object)) => object,
+                Ok(None) => {
+                    // Continue with the start of the next region
+                    self.read_buffer.replace(Some(region_data));
+                    *cursor = (region + 1) * S;
+                    continue;
+                }
+                Err(e) => {
+                    self.read_buffer.replace(Some(region_data));
+                    return Err(e);
+                }
+            };
+
+            let result = region_data
+                .get(offset + HASH_OFFSET..offset + HEADER_LENGTH)
+                .and_then(|hash| hash.try_into().ok())
+                .map(u64::from_be_bytes)
+                .zip(
+                    (total_length as usize)
+                        .checked_sub(HEADER_LENGTH + CHECK_SUM_LEN)
+                        .filter(|value_length| offset + HEADER_LENGTH + value_length <= S),
+                );
+            let Some((hash, value_length)) = result else {
+                self.read_buffer.replace(Some(region_data));
+                return Err(ErrorCode::CorruptData);
+            };
+
+            // Copy in as much of the value as fits
+            let copy_length = value_length.min(buf.len());
+            buf[..copy_length].copy_from_slice(
+                &region_data[offset + HEADER_LENGTH..offset + HEADER_LENGTH + copy_length],
+            );
+
+            self.read_buffer.replace(Some(region_data));
+            *cursor = region * S + offset + total_length as usize;
+            return Ok((hash, value_length));
+        }
+    }
+
+    /// Returns an iterator over the hashed keys and value lengths of all
+    /// valid keys, see `next_key()`.
+    ///
+    /// This requires a `FlashController` that completes reads synchronously,
+    /// a `ReadNotReady` error ends the iteration.
+    pub fn keys(&self) -> Keys<'_, 'a, C, S> {
+        Keys {
+            tickv: self,
+            cursor: 0,
+            done: false,
+        }
+    }
+
     fn garbage_collect_region(
         &self,
         region: usize,
@@ -1130,3 +1278,34 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
         Ok(flash_freed)
     }
 }
+
+/// An iterator over the keys of a `TicKV`, created by `TicKV::keys()`.
+///
+/// Each item is the hashed key and the length of the value, or the error that
+/// ended the iteration.
+pub struct Keys<'t, 'a, C: FlashController<S>, const S: usize> {
+    tickv: &'t TicKV<'a, C, S>,
+    cursor: usize,
+    done: bool,
+}
+
+impl<C: FlashController<S>, const S: usize> Iterator for Keys<'_, '_, C, S> {
+    type Item = Result<(u64, usize), ErrorCode>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.tickv.next_key(&mut self.cursor, &mut []) {
+            Ok(key) => Some(Ok(key)),
+            Err(ErrorCode::KeyNotFound) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
@@ -131,6 +131,7 @@ pub enum TbfHeaderTypes {
     TbfHeaderKernelVersion = 8,
     TbfHeaderProgram = 9,
     TbfHeaderShortId = 10,
+    TbfHeaderRealTime = 11,
     TbfFooterCredentials = 128,
 
     /// Some field in the header that we do not understand. Since the TLV format
@@ -250,6 +251,17 @@ pub struct TbfHeaderV2ShortId {
     short_id: Option<core::num::NonZeroU32>,
 }
 
+/// The v2 real-time parameters for apps.
+///
+/// Header for apps that run periodically and need a share of the processor
+/// in every period. The app requests to run for `budget_us` microseconds
+/// every `period_us` microseconds.
+#[derive(Clone, Copy, Debug)]
+pub struct TbfHeaderV2RealTime {
+    period_us: u32,
+    budget_us: u32,
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum TbfFooterV2CredentialsType {
     Reserved = 0,
@@ -259,6 +271,7 @@ pub enum TbfFooterV2CredentialsType {
     SHA384 = 4,
     SHA512 = 5,
     EcdsaNistP256 = 6,
+    Ed25519 = 7,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -330,6 +343,7 @@ impl core::convert::TryFrom<u16> for TbfHeaderTypes {
             8 => Ok(TbfHeaderTypes::TbfHeaderKernelVersion),
             9 => Ok(TbfHeaderTypes::TbfHeaderProgram),
             10 => Ok(TbfHeaderTypes::TbfHeaderShortId),
+            11 => Ok(TbfHeaderTypes::TbfHeaderRealTime),
             128 => Ok(TbfHeaderTypes::TbfFooterCredentials),
             _ => Ok(TbfHeaderTypes::Unknown),
         }
@@ -588,6 +602,34 @@ impl core::convert::TryFrom<&[u8]> for TbfHeaderV2ShortId {
     }
 }
 
+impl core::convert::TryFrom<&[u8]> for TbfHeaderV2RealTime {
+    type Error = TbfParseError;
+
+    fn try_from(b: &[u8]) -> Result<TbfHeaderV2RealTime, Self::Error> {
+        let period_us = u32::from_le_bytes(
+            b.get(0..4)
+                .ok_or(TbfParseError::InternalError)?
+                .try_into()?,
+        );
+        let budget_us = u32::from_le_bytes(
+            b.get(4..8)
+                .ok_or(TbfParseError::InternalError)?
+                .try_into()?,
+        );
+        // An app cannot run for longer than its period, and must run for some
+        // time in each period.
+        if budget_us == 0 || budget_us > period_us {
+            return Err(TbfParseError::BadTlvEntry(
+                TbfHeaderTypes::TbfHeaderRealTime as usize,
+            ));
+        }
+        Ok(TbfHeaderV2RealTime {
+            period_us,
+            budget_us,
+        })
+    }
+}
+
 impl core::convert::TryFrom<&'static [u8]> for TbfFooterV2Credentials {
     type Error = TbfParseError;
 
@@ -605,6 +647,7 @@ impl core::convert::TryFrom<&'static [u8]> for TbfFooterV2Credentials {
             4 => TbfFooterV2CredentialsType::SHA384,
             5 => TbfFooterV2CredentialsType::SHA512,
             6 => TbfFooterV2CredentialsType::EcdsaNistP256,
+            7 => TbfFooterV2CredentialsType::Ed25519,
             _ => {
                 return Err(TbfParseError::BadTlvEntry(
                     TbfHeaderTypes::TbfFooterCredentials as usize,
@@ -619,6 +662,7 @@ impl core::convert::TryFrom<&'static [u8]> for TbfFooterV2Credentials {
             TbfFooterV2CredentialsType::SHA384 => 48,
             TbfFooterV2CredentialsType::SHA512 => 64,
             TbfFooterV2CredentialsType::EcdsaNistP256 => 64,
+            TbfFooterV2CredentialsType::Ed25519 => 64,
         };
         let data = &b
             .get(4..(length + 4))
@@ -661,6 +705,7 @@ pub struct TbfHeaderV2 {
     pub(crate) storage_permissions: Option<&'static [u8]>,
     pub(crate) kernel_version: Option<TbfHeaderV2KernelVersion>,
     pub(crate) short_id: Option<TbfHeaderV2ShortId>,
+    pub(crate) real_time: Option<TbfHeaderV2RealTime>,
 }
 
 /// Type that represents the fields of the Tock Binary Format header.
@@ -1022,4 +1067,14 @@ impl TbfHeader {
             _ => None,
         }
     }
+
+    /// Get the real-time parameters of the application as a `(period_us,
+    /// budget_us)` tuple. Returns `None` if the real-time header is not
+    /// included.
+    pub fn get_real_time_parameters(&self) -> Option<(u32, u32)> {
+        match self {
+            TbfHeader::TbfHeaderV2(hd) => hd.real_time.map(|rt| (rt.period_us, rt.budget_us)),
+            _ => None,
+        }
+    }
 }
@@ -0,0 +1,382 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Decodes Tock crash dumps.
+//!
+//! The input is either the raw contents of the `.crash_dump` memory section or
+//! the output of the `crashdump` process console command. See
+//! `capsules/system/src/crash_dump.rs` for the record format.
+
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+const MAGIC: &[u8; 4] = b"TKCD";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 16;
+
+/// Prints an error message and the usage string.
+fn usage_error(message: &str) {
+    eprintln!(
+        "{}
+
+Usage: crash_dump [--raw <dir>] <file>
+Decode the Tock crash dumps in <file>.
+
+<file> holds either the memory of the .crash_dump section, or the output of
+the `crashdump` process console command.
+
+Options:
+  --raw <dir>  Also write each section of each dump to a file in <dir>",
+        message
+    );
+}
+
+/// Returns the dump data in `input`. Text with lines of the form
+/// `<offset>: <hex>` is console output, anything else is taken as memory.
+fn dump_data(input: &[u8]) -> Vec<u8> {
+    let Ok(text) = std::str::from_utf8(input) else {
+        return input.to_vec();
+    };
+    let mut data = Vec::new();
+    let mut found = false;
+    for line in text.lines() {
+        let Some((offset, hex)) = line.trim().split_once(": ") else {
+            continue;
+        };
+        if offset.is_empty() || !offset.chars().all(|c| c.is_ascii_hexdigit()) {
+            continue;
+        }
+        let hex = hex.trim();
+        if hex.len() % 2 != 0 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            continue;
+        }
+        found = true;
+        for i in (0..hex.len()).step_by(2) {
+            data.push(u8::from_str_radix(&hex[i..i + 2], 16).unwrap_or(0));
+        }
+    }
+    if found {
+        data
+    } else {
+        input.to_vec()
+    }
+}
+
+/// CRC-32 (IEEE 802.3) of `data`.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+/// One section of a dump.
+#[derive(Debug, PartialEq)]
+struct Section<'a> {
+    tag: u8,
+    value: &'a [u8],
+}
+
+/// One dump.
+#[derive(Debug, PartialEq)]
+struct Record<'a> {
+    /// Offset of the record in the input.
+    offset: usize,
+    kind: u8,
+    crc_ok: bool,
+    sections: Vec<Section<'a>>,
+}
+
+/// Find the records in `data`. Records are normally back to back, but the
+/// data is searched for the magic so that dumps can be found in any memory
+/// dump.
+fn parse_records(data: &[u8]) -> Vec<Record<'_>> {
+    let mut records = Vec::new();
+    let mut offset = 0;
+    while offset + HEADER_LEN <= data.len() {
+        if &data[offset..offset + 4] != MAGIC || data[offset + 4] != VERSION {
+            offset += 1;
+            continue;
+        }
+        let length = u32_at(data, offset + 8) as usize;
+        let start = offset + HEADER_LEN;
+        let Some(body) = data.get(start..start + length) else {
+            offset += 1;
+            continue;
+        };
+        let mut sections = Vec::new();
+        let mut pos = 0;
+        while pos + 4 <= body.len() {
+            let tag = body[pos];
+            let len = u16::from_le_bytes([body[pos + 2], body[pos + 3]]) as usize;
+            let value = &body[pos + 4..core::cmp::min(pos + 4 + len, body.len())];
+            sections.push(Section { tag, value });
+            pos += 4 + ((len + 3) & !3);
+        }
+        records.push(Record {
+            offset,
+            kind: data[offset + 5],
+            crc_ok: crc32(body) == u32_at(data, offset + 12),
+            sections,
+        });
+        offset = start + length;
+    }
+    records
+}
+
+fn section_name(tag: u8) -> &'static str {
+    match tag {
+        1 => "process-name",
+        2 => "memory-map",
+        3 => "registers",
+        4 => "overview",
+        5 => "process-stack",
+        6 => "process-state",
+        7 => "panic-message",
+        8 => "kernel-stack",
+        _ => "unknown",
+    }
+}
+
+/// Hex dump of `data`, which starts at `address`.
+fn hexdump(out: &mut String, address: u32, data: &[u8]) {
+    for (i, chunk) in data.chunks(16).enumerate() {
+        let _ = write!(out, "  {:08x}:", address.wrapping_add(i as u32 * 16));
+        for word in chunk.chunks(4) {
+            let _ = write!(out, " ");
+            for byte in word.iter().rev() {
+                let _ = write!(out, "{:02x}", byte);
+            }
+        }
+        let _ = writeln!(out);
+    }
+}
+
+fn decode_memory_map(out: &mut String, value: &[u8]) {
+    const NAMES: [&str; 8] = [
+        "flash start",
+        "flash end",
+        "RAM start",
+        "app break",
+        "grant start",
+        "RAM end",
+        "stack top",
+        "stack lowest",
+    ];
+    for (name, word) in NAMES.iter().zip(value.chunks_exact(4)) {
+        let address = u32_at(word, 0);
+        if address == 0 {
+            let _ = writeln!(out, "  {:<13} unknown", name);
+        } else {
+            let _ = writeln!(out, "  {:<13} {:#010x}", name, address);
+        }
+    }
+}
+
+/// Decodes the stored state of Cortex-M and RISC-V processes, and dumps
+/// anything else.
+fn decode_registers(out: &mut String, value: &[u8]) {
+    let words: Vec<u32> = value.chunks_exact(4).map(|w| u32_at(w, 0)).collect();
+    let names: Vec<String> = match words.get(2).map(|tag| tag.to_le_bytes()) {
+        Some(tag) if &tag == b"ctxm" => ["yield pc", "psr", "psp"]
+            .iter()
+            .map(|s| s.to_string())
+            .chain((4..12).map(|i| format!("r{}", i)))
+            .collect(),
+        Some(tag) if &tag == b"rv5i" => ["pc", "mcause", "mtval"]
+            .iter()
+            .map(|s| s.to_string())
+            .chain((1..32).map(|i| format!("x{}", i)))
+            .collect(),
+        _ => {
+            let _ = writeln!(out, "  unknown architecture");
+            hexdump(out, 0, value);
+            return;
+        }
+    };
+    for (name, word) in names.iter().zip(words.iter().skip(3)) {
+        let _ = writeln!(out, "  {:<9} {:#010x}", name, word);
+    }
+}
+
+fn decode_record(record: &Record) -> String {
+    let mut out = String::new();
+    let kind = match record.kind {
+        1 => "process fault",
+        2 => "kernel panic",
+        _ => "unknown",
+    };
+    let _ = writeln!(
+        out,
+        "Crash dump at offset {:#x}: {}{}",
+        record.offset,
+        kind,
+        if record.crc_ok { "" } else { " (CRC mismatch)" }
+    );
+    for section in &record.sections {
+        let _ = writeln!(out, "[{}]", section_name(section.tag));
+        match section.tag {
+            2 => decode_memory_map(&mut out, section.value),
+            3 => decode_registers(&mut out, section.value),
+            5 | 8 if section.value.len() >= 4 => {
+                hexdump(&mut out, u32_at(section.value, 0), &section.value[4..])
+            }
+            1 | 4 | 6 | 7 => {
+                for line in String::from_utf8_lossy(section.value).lines() {
+                    let _ = writeln!(out, "  {}", line.trim_end());
+                }
+            }
+            _ => hexdump(&mut out, 0, section.value),
+        }
+    }
+    out
+}
+
+fn write_raw(dir: &Path, index: usize, record: &Record) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    for section in &record.sections {
+        let name = format!("dump{}-{}.bin", index, section_name(section.tag));
+        std::fs::write(dir.join(name), section.value)?;
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let mut raw_dir: Option<PathBuf> = None;
+    let mut file: Option<PathBuf> = None;
+    let mut args = std::env::args_os().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--raw" {
+            match args.next() {
+                Some(dir) => raw_dir = Some(dir.into()),
+                None => {
+                    usage_error("--raw needs a directory");
+                    return ExitCode::FAILURE;
+                }
+            }
+        } else if file.is_none() {
+            file = Some(arg.into());
+        } else {
+            usage_error("Too many arguments");
+            return ExitCode::FAILURE;
+        }
+    }
+    let Some(file) = file else {
+        usage_error("No input file");
+        return ExitCode::FAILURE;
+    };
+
+    let input = match std::fs::read(&file) {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("Unable to read {}: {}", file.display(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let data = dump_data(&input);
+    let records = parse_records(&data);
+    if records.is_empty() {
+        eprintln!("No crash dumps found in {}", file.display());
+        return ExitCode::FAILURE;
+    }
+    for (index, record) in records.iter().enumerate() {
+        println!("{}", decode_record(record));
+        if let Some(dir) = &raw_dir {
+            if let Err(e) = write_raw(dir, index, record) {
+                eprintln!("Unable to write sections to {}: {}", dir.display(), e);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A process fault dump of a process called `blink`.
+    fn record() -> Vec<u8> {
+        let mut sections = vec![1, 0, 5, 0];
+        sections.extend_from_slice(b"blink\0\0\0");
+        sections.extend_from_slice(&[5, 0, 8, 0]);
+        sections.extend_from_slice(&0x2000_8000u32.to_le_bytes());
+        sections.extend_from_slice(&[1, 2, 3, 4]);
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&[VERSION, 1, 0, 0]);
+        data.extend_from_slice(&(sections.len() as u32).to_le_bytes());
+        data.extend_from_slice(&crc32(&sections).to_le_bytes());
+        data.extend_from_slice(&sections);
+        data
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn parses_memory() {
+        let mut memory = vec![0xAA; 7];
+        memory.extend_from_slice(&record());
+        memory.extend_from_slice(&[0; 32]);
+        let records = parse_records(&memory);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].offset, 7);
+        assert_eq!(records[0].kind, 1);
+        assert!(records[0].crc_ok);
+        assert_eq!(
+            records[0].sections[0],
+            Section {
+                tag: 1,
+                value: b"blink"
+            }
+        );
+        assert_eq!(records[0].sections[1].value.len(), 8);
+    }
+
+    #[test]
+    fn parses_console_output() {
+        let data = record();
+        let mut text =
+            String::from("tock$ crashdump\r\nCrash dump 0: process fault: blink (52 bytes)\r\n");
+        for (i, chunk) in data.chunks(32).enumerate() {
+            let _ = write!(text, "{:04x}: ", i * 32);
+            for byte in chunk {
+                let _ = write!(text, "{:02x}", byte);
+            }
+            text.push_str("\r\n");
+        }
+        text.push_str("tock$ ");
+        assert_eq!(dump_data(text.as_bytes()), data);
+    }
+
+    #[test]
+    fn detects_corruption() {
+        let mut data = record();
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        let records = parse_records(&data);
+        assert!(!records[0].crc_ok);
+        assert!(decode_record(&records[0]).contains("CRC mismatch"));
+    }
+}
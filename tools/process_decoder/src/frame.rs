@@ -0,0 +1,525 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Parsing of the frames printed by `ProcessPrinterBinary`. See
+//! `capsules/system/src/process_printer.rs` for the format.
+
+const MAGIC: &[u8; 4] = b"TKPP";
+const VERSION: u8 = 1;
+
+const BEGIN_MARKER: &str = "-----BEGIN TOCK PROCESS-----";
+const END_MARKER: &str = "-----END TOCK PROCESS-----";
+
+const TAG_END: u8 = 0;
+const TAG_NAME: u8 = 1;
+const TAG_STATE: u8 = 2;
+const TAG_STATISTICS: u8 = 3;
+const TAG_LAST_SYSCALL: u8 = 4;
+const TAG_COMPLETION_CODE: u8 = 5;
+const TAG_ADDRESSES: u8 = 6;
+const TAG_SIZES: u8 = 7;
+const TAG_STORED_STATE: u8 = 8;
+const TAG_STACK: u8 = 9;
+const TAG_SHORT_ID: u8 = 10;
+
+/// CRC-32 (IEEE 802.3) of `data`.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn base64_value(c: u8) -> Option<u8> {
+    match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    }
+}
+
+/// Decodes base64 `text`, ignoring whitespace. Returns `None` if `text` holds
+/// anything else.
+pub fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    let mut bits = 0u32;
+    let mut bit_count = 0;
+    for c in text.bytes() {
+        if c.is_ascii_whitespace() {
+            continue;
+        }
+        if c == b'=' {
+            break;
+        }
+        bits = (bits << 6) | base64_value(c)? as u32;
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            data.push((bits >> bit_count) as u8);
+        }
+    }
+    Some(data)
+}
+
+/// The frames in `input`: the base64 blocks between the marker lines of a
+/// console log, or else raw frames found by their magic.
+pub fn find_frames(input: &[u8]) -> Vec<Vec<u8>> {
+    let text = String::from_utf8_lossy(input);
+    let mut frames = Vec::new();
+    let mut block: Option<String> = None;
+    for line in text.lines() {
+        // Other output can precede the marker on the same line.
+        if line.trim_end().ends_with(BEGIN_MARKER) {
+            block = Some(String::new());
+        } else if line.trim().starts_with(END_MARKER) {
+            if let Some(frame) = block.take().and_then(|block| base64_decode(&block)) {
+                frames.push(frame);
+            }
+        } else if let Some(block) = &mut block {
+            block.push_str(line);
+        }
+    }
+    if frames.is_empty() {
+        let mut offset = 0;
+        while let Some(position) = input[offset..]
+            .windows(MAGIC.len())
+            .position(|window| window == MAGIC)
+        {
+            frames.push(input[offset + position..].to_vec());
+            offset += position + MAGIC.len();
+        }
+    }
+    frames
+}
+
+/// State of the process.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum State {
+    Running,
+    Yielded,
+    YieldedFor { driver: u32, subscribe: u32 },
+    StoppedRunning,
+    StoppedYielded,
+    StoppedYieldedFor { driver: u32, subscribe: u32 },
+    Faulted,
+    Terminated,
+    Unknown(u8),
+}
+
+impl std::fmt::Display for State {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            State::Running => write!(f, "running"),
+            State::Yielded => write!(f, "yielded"),
+            State::YieldedFor { driver, subscribe } => {
+                write!(f, "yielded for upcall {:#x}:{}", driver, subscribe)
+            }
+            State::StoppedRunning => write!(f, "stopped (running)"),
+            State::StoppedYielded => write!(f, "stopped (yielded)"),
+            State::StoppedYieldedFor { driver, subscribe } => {
+                write!(
+                    f,
+                    "stopped (yielded for upcall {:#x}:{})",
+                    driver, subscribe
+                )
+            }
+            State::Faulted => write!(f, "faulted"),
+            State::Terminated => write!(f, "terminated"),
+            State::Unknown(code) => write!(f, "unknown ({})", code),
+        }
+    }
+}
+
+/// Counters of the process.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Statistics {
+    pub events_queued: u32,
+    pub syscalls: u32,
+    pub dropped_upcalls: u32,
+    pub restarts: u32,
+    pub timeslice_expirations: u32,
+}
+
+/// The last syscall of the process, as its class and first two arguments.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Syscall {
+    pub class: u8,
+    pub a: u32,
+    pub b: u32,
+}
+
+impl Syscall {
+    pub fn class_name(&self) -> &'static str {
+        match self.class {
+            0 => "yield",
+            1 => "subscribe",
+            2 => "command",
+            3 => "read-write allow",
+            4 => "read-only allow",
+            5 => "memop",
+            6 => "exit",
+            7 => "userspace-readable allow",
+            _ => "unknown",
+        }
+    }
+}
+
+impl std::fmt::Display for Syscall {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.class {
+            0 | 6 => write!(
+                f,
+                "{} (which {}, argument {:#x})",
+                self.class_name(),
+                self.a,
+                self.b
+            ),
+            5 => write!(f, "memop (operand {}, argument {:#x})", self.a, self.b),
+            _ => write!(
+                f,
+                "{} (driver {:#x}, subdriver {})",
+                self.class_name(),
+                self.a,
+                self.b
+            ),
+        }
+    }
+}
+
+/// How the process completed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Completion {
+    Faulted,
+    Exited(u32),
+}
+
+/// Addresses of the process. `None` if the kernel does not know them.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Addresses {
+    pub flash_start: u32,
+    pub flash_non_protected_start: u32,
+    pub flash_integrity_end: u32,
+    pub flash_end: u32,
+    pub sram_start: u32,
+    pub sram_app_brk: u32,
+    pub sram_grant_start: u32,
+    pub sram_end: u32,
+    pub sram_heap_start: Option<u32>,
+    pub sram_stack_top: Option<u32>,
+    pub sram_stack_bottom: Option<u32>,
+}
+
+/// Sizes of the kernel data at the end of the process RAM.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Sizes {
+    pub grant_pointers: u32,
+    pub upcall_list: u32,
+    pub process_control_block: u32,
+}
+
+/// A decoded frame.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Process {
+    pub name: String,
+    pub short_id: Option<u32>,
+    pub state: Option<State>,
+    pub statistics: Option<Statistics>,
+    pub last_syscall: Option<Syscall>,
+    pub completion: Option<Completion>,
+    pub addresses: Option<Addresses>,
+    pub sizes: Option<Sizes>,
+    pub stored_state: Vec<u8>,
+    /// Address of the stack snapshot and its contents.
+    pub stack: Option<(u32, Vec<u8>)>,
+    /// Whether the frame was complete and its CRC matched.
+    pub crc_ok: bool,
+}
+
+/// Decodes a frame. Unknown records are skipped, so that newer kernels can
+/// add records.
+pub fn parse(frame: &[u8]) -> Result<Process, String> {
+    if frame.len() < 5 || &frame[..4] != MAGIC {
+        return Err("not a process frame".to_string());
+    }
+    if frame[4] != VERSION {
+        return Err(format!("unsupported frame version {}", frame[4]));
+    }
+    let mut process = Process::default();
+    let mut offset = 5;
+    while offset + 3 <= frame.len() {
+        let tag = frame[offset];
+        let len = u16::from_le_bytes([frame[offset + 1], frame[offset + 2]]) as usize;
+        let value = frame
+            .get(offset + 3..offset + 3 + len)
+            .ok_or_else(|| format!("record {} is truncated", tag))?;
+        let word = |index: usize| u32_at(value, index * 4).unwrap_or(0);
+        match tag {
+            TAG_END => {
+                process.crc_ok = u32_at(value, 0) == Some(crc32(&frame[..offset + 3]));
+                return Ok(process);
+            }
+            TAG_NAME => process.name = String::from_utf8_lossy(value).into_owned(),
+            TAG_SHORT_ID => process.short_id = Some(word(0)),
+            TAG_STATE => {
+                let upcall = (u32_at(value, 1).unwrap_or(0), u32_at(value, 5).unwrap_or(0));
+                process.state = Some(match value.first().copied().unwrap_or(u8::MAX) {
+                    0 => State::Running,
+                    1 => State::Yielded,
+                    2 => State::YieldedFor {
+                        driver: upcall.0,
+                        subscribe: upcall.1,
+                    },
+                    3 => State::StoppedRunning,
+                    4 => State::StoppedYielded,
+                    5 => State::StoppedYieldedFor {
+                        driver: upcall.0,
+                        subscribe: upcall.1,
+                    },
+                    6 => State::Faulted,
+                    7 => State::Terminated,
+                    code => State::Unknown(code),
+                });
+            }
+            TAG_STATISTICS => {
+                process.statistics = Some(Statistics {
+                    events_queued: word(0),
+                    syscalls: word(1),
+                    dropped_upcalls: word(2),
+                    restarts: word(3),
+                    timeslice_expirations: word(4),
+                })
+            }
+            TAG_LAST_SYSCALL => {
+                process.last_syscall = Some(Syscall {
+                    class: value.first().copied().unwrap_or(u8::MAX),
+                    a: u32_at(value, 1).unwrap_or(0),
+                    b: u32_at(value, 5).unwrap_or(0),
+                })
+            }
+            TAG_COMPLETION_CODE => {
+                process.completion = Some(match value.first() {
+                    Some(1) => Completion::Exited(u32_at(value, 1).unwrap_or(0)),
+                    _ => Completion::Faulted,
+                })
+            }
+            TAG_ADDRESSES => {
+                let known = |address: u32| (address != 0).then_some(address);
+                process.addresses = Some(Addresses {
+                    flash_start: word(0),
+                    flash_non_protected_start: word(1),
+                    flash_integrity_end: word(2),
+                    flash_end: word(3),
+                    sram_start: word(4),
+                    sram_app_brk: word(5),
+                    sram_grant_start: word(6),
+                    sram_end: word(7),
+                    sram_heap_start: known(word(8)),
+                    sram_stack_top: known(word(9)),
+                    sram_stack_bottom: known(word(10)),
+                })
+            }
+            TAG_SIZES => {
+                process.sizes = Some(Sizes {
+                    grant_pointers: word(0),
+                    upcall_list: word(1),
+                    process_control_block: word(2),
+                })
+            }
+            TAG_STORED_STATE => process.stored_state = value.to_vec(),
+            TAG_STACK => {
+                if let Some(address) = u32_at(value, 0) {
+                    process.stack = Some((address, value[4..].to_vec()));
+                }
+            }
+            _ => {}
+        }
+        offset += 3 + len;
+    }
+    // The frame ended without an end record.
+    Ok(process)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// Encodes like `ProcessPrinterBinary`.
+    pub struct Encoder {
+        pub frame: Vec<u8>,
+    }
+
+    impl Encoder {
+        pub fn new() -> Self {
+            let mut frame = MAGIC.to_vec();
+            frame.push(VERSION);
+            Encoder { frame }
+        }
+
+        pub fn record(&mut self, tag: u8, value: &[u8]) -> &mut Self {
+            self.frame.push(tag);
+            self.frame
+                .extend_from_slice(&(value.len() as u16).to_le_bytes());
+            self.frame.extend_from_slice(value);
+            self
+        }
+
+        pub fn words(&mut self, tag: u8, words: &[u32]) -> &mut Self {
+            let value: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+            self.record(tag, &value)
+        }
+
+        pub fn finish(&mut self) -> Vec<u8> {
+            self.frame.extend_from_slice(&[TAG_END, 4, 0]);
+            let crc = crc32(&self.frame);
+            self.frame.extend_from_slice(&crc.to_le_bytes());
+            self.frame.clone()
+        }
+    }
+
+    pub fn base64_encode(data: &[u8]) -> String {
+        const ALPHABET: &[u8; 64] =
+            b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut text = String::new();
+        for chunk in data.chunks(3) {
+            let group = [
+                chunk[0],
+                chunk.get(1).copied().unwrap_or(0),
+                chunk.get(2).copied().unwrap_or(0),
+            ];
+            let indices = [
+                group[0] >> 2,
+                ((group[0] & 0x03) << 4) | (group[1] >> 4),
+                ((group[1] & 0x0F) << 2) | (group[2] >> 6),
+                group[2] & 0x3F,
+            ];
+            for (i, index) in indices.iter().enumerate() {
+                text.push(if i <= chunk.len() {
+                    ALPHABET[*index as usize] as char
+                } else {
+                    '='
+                });
+            }
+        }
+        text
+    }
+
+    /// A faulted Cortex-M process called `blink`.
+    pub fn frame() -> Vec<u8> {
+        let mut stored_state = vec![1, 44, u32::from_le_bytes(*b"ctxm"), 0x0004_0101];
+        stored_state.extend_from_slice(&[0x0100_0000, 0x2000_1f00]);
+        stored_state.extend_from_slice(&[0; 8]);
+        let mut stack = 0x2000_1f00u32.to_le_bytes().to_vec();
+        for word in [0, 1, 2, 3, 12, 0x0004_0121, 0x0004_0134, 0x0100_0000] {
+            stack.extend_from_slice(&u32::to_le_bytes(word));
+        }
+        Encoder::new()
+            .record(TAG_NAME, b"blink")
+            .record(TAG_STATE, &[6])
+            .words(TAG_STATISTICS, &[0, 42, 0, 1, 3])
+            .record(TAG_LAST_SYSCALL, &[2, 2, 0, 0, 0, 1, 0, 0, 0])
+            .record(TAG_COMPLETION_CODE, &[0])
+            .words(
+                TAG_ADDRESSES,
+                &[
+                    0x0004_0000,
+                    0x0004_0048,
+                    0x0004_1f00,
+                    0x0004_2000,
+                    0x2000_1000,
+                    0x2000_2800,
+                    0x2000_3c00,
+                    0x2000_4000,
+                    0x2000_2000,
+                    0x2000_2000,
+                    0x2000_1f00,
+                ],
+            )
+            .words(TAG_SIZES, &[40, 80, 368])
+            .words(TAG_STORED_STATE, &stored_state)
+            .record(TAG_STACK, &stack)
+            .record(42, b"from the future")
+            .finish()
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn base64_round_trip() {
+        for len in 0..8 {
+            let data: Vec<u8> = (0..len).map(|i| i * 37).collect();
+            assert_eq!(base64_decode(&base64_encode(&data)), Some(data));
+        }
+        assert_eq!(base64_decode("VEtQUA=="), Some(b"TKPP".to_vec()));
+        assert_eq!(base64_decode("VE*Q"), None);
+    }
+
+    #[test]
+    fn parses_frame() {
+        let process = parse(&frame()).unwrap();
+        assert!(process.crc_ok);
+        assert_eq!(process.name, "blink");
+        assert_eq!(process.state, Some(State::Faulted));
+        assert_eq!(process.statistics.unwrap().syscalls, 42);
+        assert_eq!(
+            process.last_syscall.unwrap().to_string(),
+            "command (driver 0x2, subdriver 1)"
+        );
+        assert_eq!(process.completion, Some(Completion::Faulted));
+        let addresses = process.addresses.unwrap();
+        assert_eq!(addresses.flash_non_protected_start, 0x0004_0048);
+        assert_eq!(addresses.sram_stack_bottom, Some(0x2000_1f00));
+        assert_eq!(process.sizes.unwrap().process_control_block, 368);
+        assert_eq!(process.stored_state.len(), 56);
+        assert_eq!(process.stack.as_ref().unwrap().0, 0x2000_1f00);
+        assert_eq!(process.stack.as_ref().unwrap().1.len(), 32);
+    }
+
+    #[test]
+    fn detects_corruption() {
+        let mut data = frame();
+        data[8] ^= 1;
+        assert!(!parse(&data).unwrap().crc_ok);
+        assert!(parse(b"TKPP\x02").is_err());
+    }
+
+    #[test]
+    fn finds_frames_in_console_log() {
+        let encoded = base64_encode(&frame());
+        let mut log = String::from("Kernel panic\r\nApp: blink -----BEGIN TOCK PROCESS-----\r\n");
+        for line in encoded.as_bytes().chunks(64) {
+            log.push_str(std::str::from_utf8(line).unwrap());
+            log.push_str("\r\n");
+        }
+        log.push_str("-----END TOCK PROCESS-----\r\nmore output\r\n");
+        assert_eq!(find_frames(log.as_bytes()), vec![frame()]);
+    }
+
+    #[test]
+    fn finds_raw_frames() {
+        let mut data = vec![0xAA; 5];
+        data.extend_from_slice(&frame());
+        let frames = find_frames(&data);
+        assert_eq!(frames.len(), 1);
+        assert!(parse(&frames[0]).unwrap().crc_ok);
+    }
+}
@@ -0,0 +1,106 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Decodes the process frames printed by `ProcessPrinterBinary` from a
+//! console log or raw dump, and reports the registers, memory map and likely
+//! call stack of each process, symbolized with the app ELFs.
+
+mod frame;
+mod report;
+mod symbols;
+
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use report::Report;
+use symbols::Symbolizer;
+
+const USAGE: &str = "\
+Usage: process_decoder [--elf <app.elf>]... [--json] [<file>]
+
+Decodes the process frames in <file>, or standard input, which can be a
+console log or a raw dump. ELFs are matched to processes by file name, or
+used for all processes if only one is given.";
+
+fn main() -> ExitCode {
+    let mut elf_paths = Vec::new();
+    let mut json = false;
+    let mut input_path: Option<PathBuf> = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--elf" => match args.next() {
+                Some(path) => elf_paths.push(PathBuf::from(path)),
+                None => {
+                    eprintln!("--elf needs a path\n\n{}", USAGE);
+                    return ExitCode::FAILURE;
+                }
+            },
+            "--json" => json = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            }
+            _ if input_path.is_none() && !arg.starts_with("--") => {
+                input_path = Some(PathBuf::from(arg))
+            }
+            _ => {
+                eprintln!("unexpected argument {}\n\n{}", arg, USAGE);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    let mut elfs = Vec::new();
+    for path in &elf_paths {
+        match Symbolizer::load(path) {
+            Ok(elf) => elfs.push(elf),
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    let input = match &input_path {
+        Some(path) => std::fs::read(path),
+        None => {
+            let mut input = Vec::new();
+            std::io::stdin().read_to_end(&mut input).map(|_| input)
+        }
+    };
+    let input = match input {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("cannot read input: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut processes = Vec::new();
+    for data in frame::find_frames(&input) {
+        match frame::parse(&data) {
+            Ok(process) => processes.push(process),
+            Err(e) => eprintln!("skipping frame: {}", e),
+        }
+    }
+    if processes.is_empty() {
+        eprintln!("no process frames found");
+        return ExitCode::FAILURE;
+    }
+
+    let reports: Vec<Report> = processes
+        .iter()
+        .map(|process| Report::new(process, &elfs))
+        .collect();
+    if json {
+        let reports: Vec<String> = reports.iter().map(Report::json).collect();
+        println!("[{}]", reports.join(","));
+    } else {
+        let reports: Vec<String> = reports.iter().map(Report::text).collect();
+        print!("{}", reports.join("\n"));
+    }
+    ExitCode::SUCCESS
+}
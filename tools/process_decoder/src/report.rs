@@ -0,0 +1,573 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Analysis of a decoded process: registers, memory map and possible return
+//! addresses, printed as text or JSON.
+
+use std::fmt::Write as _;
+
+use crate::frame::{Completion, Process};
+use crate::symbols::{Placement, Symbolizer};
+
+/// Maximum number of possible return addresses to report.
+const MAX_BACKTRACE: usize = 32;
+
+/// A register and the function its value points into, if any.
+#[derive(Debug, PartialEq)]
+pub struct Register {
+    pub name: String,
+    pub value: u32,
+    pub symbol: Option<String>,
+}
+
+/// A region of the process's memory.
+#[derive(Debug, PartialEq)]
+pub struct Region {
+    pub start: u32,
+    pub end: u32,
+    pub name: &'static str,
+    pub note: String,
+}
+
+/// A stack word that points into the code of the process.
+#[derive(Debug, PartialEq)]
+pub struct Frame {
+    pub address: u32,
+    pub value: u32,
+    pub symbol: String,
+}
+
+pub struct Report<'a> {
+    pub process: &'a Process,
+    pub elf: Option<&'a Symbolizer>,
+    pub placement: Option<Placement>,
+    pub architecture: Option<&'static str>,
+    pub registers: Vec<Register>,
+    /// The registers the Cortex-M hardware stacked on exception entry.
+    pub exception_frame: Vec<Register>,
+    pub memory_map: Vec<Region>,
+    pub backtrace: Vec<Frame>,
+    pub warnings: Vec<String>,
+}
+
+fn word(data: &[u8], index: usize) -> Option<u32> {
+    let bytes = data.get(index * 4..index * 4 + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+impl<'a> Report<'a> {
+    /// Analyzes `process`, symbolizing with the first of `elfs` named like the
+    /// process, or the only one.
+    pub fn new(process: &'a Process, elfs: &'a [Symbolizer]) -> Report<'a> {
+        let elf = elfs
+            .iter()
+            .find(|elf| elf.name == process.name)
+            .or(if elfs.len() == 1 { elfs.first() } else { None });
+        let placement = elf.zip(process.addresses).map(|(elf, a)| elf.placement(&a));
+        let mut report = Report {
+            process,
+            elf,
+            placement,
+            architecture: None,
+            registers: Vec::new(),
+            exception_frame: Vec::new(),
+            memory_map: Vec::new(),
+            backtrace: Vec::new(),
+            warnings: Vec::new(),
+        };
+        report.decode_registers();
+        report.decode_memory_map();
+        report.find_return_addresses();
+        report
+    }
+
+    fn register(&self, name: String, value: u32) -> Register {
+        Register {
+            name,
+            value,
+            symbol: self.symbolize(value),
+        }
+    }
+
+    fn symbolize(&self, value: u32) -> Option<String> {
+        let (elf, placement) = self.elf.zip(self.placement)?;
+        elf.symbolize(placement, value)
+    }
+
+    /// The word at `address` in the stack snapshot.
+    fn stack_word(&self, address: u32) -> Option<u32> {
+        let (start, stack) = self.process.stack.as_ref()?;
+        let offset = address.checked_sub(*start)? as usize;
+        let bytes = stack.get(offset..offset + 4)?;
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn decode_registers(&mut self) {
+        let state = &self.process.stored_state;
+        let names: Vec<String> = match word(state, 2).map(u32::to_le_bytes) {
+            Some(tag) if &tag == b"ctxm" => {
+                self.architecture = Some("cortex-m");
+                ["yield pc", "psr", "psp"]
+                    .iter()
+                    .map(|s| s.to_string())
+                    .chain((4..12).map(|i| format!("r{}", i)))
+                    .collect()
+            }
+            Some(tag) if &tag == b"rv5i" => {
+                self.architecture = Some("riscv32");
+                ["pc", "mcause", "mtval"]
+                    .iter()
+                    .map(|s| s.to_string())
+                    .chain((1..32).map(|i| format!("x{}", i)))
+                    .collect()
+            }
+            _ => return,
+        };
+        for (index, name) in names.into_iter().enumerate() {
+            if let Some(value) = word(state, 3 + index) {
+                let register = self.register(name, value);
+                self.registers.push(register);
+            }
+        }
+
+        if self.architecture == Some("cortex-m") {
+            // Exception entry stacks r0-r3, r12, lr, pc and xpsr at the
+            // process stack pointer.
+            let psp = word(state, 5).unwrap_or(0);
+            let names = ["r0", "r1", "r2", "r3", "r12", "lr", "pc", "xpsr"];
+            let values: Option<Vec<u32>> = (0..names.len() as u32)
+                .map(|i| self.stack_word(psp.wrapping_add(i * 4)))
+                .collect();
+            if let Some(values) = values {
+                for (name, value) in names.iter().zip(values) {
+                    let register = self.register(name.to_string(), value);
+                    self.exception_frame.push(register);
+                }
+            }
+        }
+    }
+
+    fn region(&mut self, start: u32, end: u32, name: &'static str, note: String) {
+        if start < end {
+            self.memory_map.push(Region {
+                start,
+                end,
+                name,
+                note,
+            });
+        }
+    }
+
+    fn decode_memory_map(&mut self) {
+        let Some(a) = self.process.addresses else {
+            return;
+        };
+
+        self.region(
+            a.flash_start,
+            a.flash_non_protected_start,
+            "protected flash",
+            "TBF header".to_string(),
+        );
+        let integrity_end = if a.flash_integrity_end > a.flash_non_protected_start {
+            a.flash_integrity_end.min(a.flash_end)
+        } else {
+            a.flash_end
+        };
+        self.region(
+            a.flash_non_protected_start,
+            integrity_end,
+            "app flash",
+            String::new(),
+        );
+        self.region(integrity_end, a.flash_end, "footers", String::new());
+
+        // From the bottom: stack, data, heap, unused, grants and the kernel's
+        // data for the process.
+        let mut start = a.sram_start;
+        if let Some(top) = a.sram_stack_top {
+            let note = match a.sram_stack_bottom {
+                Some(bottom) => format!(
+                    "{} bytes used, lowest stack pointer {:#010x}",
+                    top.saturating_sub(bottom),
+                    bottom
+                ),
+                None => String::new(),
+            };
+            self.region(start, top, "stack", note);
+            start = top;
+        }
+        if let Some(heap) = a.sram_heap_start {
+            self.region(start, heap, "data", String::new());
+            self.region(heap, a.sram_app_brk, "heap", String::new());
+        } else {
+            self.region(start, a.sram_app_brk, "app memory", String::new());
+        }
+        self.region(a.sram_app_brk, a.sram_grant_start, "unused", String::new());
+        let kernel_start = match self.process.sizes {
+            Some(sizes) => a.sram_end.saturating_sub(
+                sizes.grant_pointers + sizes.upcall_list + sizes.process_control_block,
+            ),
+            None => a.sram_end,
+        };
+        self.region(a.sram_grant_start, kernel_start, "grants", String::new());
+        if let Some(sizes) = self.process.sizes {
+            self.region(
+                kernel_start,
+                a.sram_end,
+                "kernel data",
+                format!(
+                    "grant pointers {}, upcalls {}, process {}",
+                    sizes.grant_pointers, sizes.upcall_list, sizes.process_control_block
+                ),
+            );
+        }
+
+        if let Some(bottom) = a.sram_stack_bottom {
+            if bottom < a.sram_start {
+                self.warnings.push(format!(
+                    "stack overflow: the stack pointer reached {:#010x}, below the start of RAM",
+                    bottom
+                ));
+            }
+        }
+        if let (Some(top), Some(heap)) = (a.sram_stack_top, a.sram_heap_start) {
+            if heap < top {
+                self.warnings
+                    .push("the heap starts below the top of the stack".to_string());
+            }
+        }
+        if a.sram_app_brk > a.sram_grant_start {
+            self.warnings
+                .push("the app break is above the start of the grants".to_string());
+        }
+        if self.process.completion == Some(Completion::Faulted) && self.registers.is_empty() {
+            self.warnings
+                .push("the process faulted, but its registers are unknown".to_string());
+        }
+    }
+
+    /// Finds stack words that point into the code, which are likely return
+    /// addresses. On Cortex-M, return addresses are Thumb addresses with the
+    /// lowest bit set.
+    fn find_return_addresses(&mut self) {
+        let (Some(elf), Some(placement)) = (self.elf, self.placement) else {
+            return;
+        };
+        let Some((start, stack)) = &self.process.stack else {
+            return;
+        };
+        // Words below the stack pointer are stale.
+        let sp = match self.architecture {
+            Some("cortex-m") => word(&self.process.stored_state, 5),
+            // x2 is the stack pointer.
+            Some("riscv32") => word(&self.process.stored_state, 3 + 3 + 1),
+            _ => None,
+        }
+        .filter(|sp| *sp >= *start)
+        .unwrap_or(*start);
+        for (index, chunk) in stack.chunks_exact(4).enumerate() {
+            let address = start + index as u32 * 4;
+            let value = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            if address < sp
+                || (self.architecture == Some("cortex-m") && value & 1 == 0)
+                || !elf.is_code(placement, value)
+            {
+                continue;
+            }
+            if let Some(symbol) = elf.symbolize(placement, value) {
+                self.backtrace.push(Frame {
+                    address,
+                    value,
+                    symbol,
+                });
+                if self.backtrace.len() == MAX_BACKTRACE {
+                    break;
+                }
+            }
+        }
+    }
+
+    pub fn text(&self) -> String {
+        let p = self.process;
+        let mut out = String::new();
+        let _ = write!(out, "Process {}", p.name);
+        if let Some(id) = p.short_id {
+            let _ = write!(out, " (short ID {:#x})", id);
+        }
+        if !p.crc_ok {
+            out.push_str(" (incomplete or corrupted: CRC mismatch)");
+        }
+        out.push('\n');
+        if let Some(state) = p.state {
+            let _ = writeln!(out, "  State:            {}", state);
+        }
+        match p.completion {
+            Some(Completion::Faulted) => out.push_str("  Completion:       faulted\n"),
+            Some(Completion::Exited(code)) => {
+                let _ = writeln!(out, "  Completion:       exited with {}", code as i32);
+            }
+            None => {}
+        }
+        if let Some(s) = p.statistics {
+            let _ = writeln!(
+                out,
+                "  Syscalls:         {}   Events queued: {}   Dropped upcalls: {}\n  \
+                 Restarts:         {}   Timeslice expirations: {}",
+                s.syscalls, s.events_queued, s.dropped_upcalls, s.restarts, s.timeslice_expirations
+            );
+        }
+        if let Some(syscall) = p.last_syscall {
+            let _ = writeln!(out, "  Last syscall:     {}", syscall);
+        }
+        if let Some(elf) = self.elf {
+            let _ = writeln!(
+                out,
+                "  Symbols:          {}{}",
+                elf.name,
+                match self.placement {
+                    Some(Placement::Relocated { offset }) =>
+                        format!(" (position independent, offset {:#x})", offset),
+                    _ => String::new(),
+                }
+            );
+        }
+        for warning in &self.warnings {
+            let _ = writeln!(out, "  Warning: {}", warning);
+        }
+
+        let mut registers = |title: &str, registers: &[Register]| {
+            if registers.is_empty() {
+                return;
+            }
+            let _ = writeln!(out, "\n{}", title);
+            for register in registers {
+                let _ = write!(out, "  {:<9} {:#010x}", register.name, register.value);
+                if let Some(symbol) = &register.symbol {
+                    let _ = write!(out, "  {}", symbol);
+                }
+                out.push('\n');
+            }
+        };
+        registers(
+            &format!("Registers ({})", self.architecture.unwrap_or("unknown")),
+            &self.registers,
+        );
+        registers("Exception frame", &self.exception_frame);
+
+        if !self.memory_map.is_empty() {
+            out.push_str("\nMemory map\n");
+            for region in self.memory_map.iter().rev() {
+                let _ = write!(
+                    out,
+                    "  {:#010x}-{:#010x} {:<15} {:>7} bytes",
+                    region.start,
+                    region.end,
+                    region.name,
+                    region.end - region.start
+                );
+                if !region.note.is_empty() {
+                    let _ = write!(out, "  {}", region.note);
+                }
+                out.push('\n');
+            }
+        }
+
+        if !self.backtrace.is_empty() {
+            out.push_str("\nPossible return addresses on the stack\n");
+            for frame in &self.backtrace {
+                let _ = writeln!(
+                    out,
+                    "  [{:#010x}] {:#010x}  {}",
+                    frame.address, frame.value, frame.symbol
+                );
+            }
+        }
+        out
+    }
+
+    pub fn json(&self) -> String {
+        let p = self.process;
+        let mut fields: Vec<(&str, String)> = vec![
+            ("name", json_string(&p.name)),
+            ("crc_ok", p.crc_ok.to_string()),
+        ];
+        if let Some(id) = p.short_id {
+            fields.push(("short_id", id.to_string()));
+        }
+        if let Some(state) = p.state {
+            fields.push(("state", json_string(&state.to_string())));
+        }
+        match p.completion {
+            Some(Completion::Faulted) => fields.push(("faulted", "true".to_string())),
+            Some(Completion::Exited(code)) => {
+                fields.push(("faulted", "false".to_string()));
+                fields.push(("exit_code", (code as i32).to_string()));
+            }
+            None => {}
+        }
+        if let Some(s) = p.statistics {
+            fields.push((
+                "statistics",
+                json_object(&[
+                    ("syscalls", s.syscalls.to_string()),
+                    ("events_queued", s.events_queued.to_string()),
+                    ("dropped_upcalls", s.dropped_upcalls.to_string()),
+                    ("restarts", s.restarts.to_string()),
+                    ("timeslice_expirations", s.timeslice_expirations.to_string()),
+                ]),
+            ));
+        }
+        if let Some(syscall) = p.last_syscall {
+            fields.push((
+                "last_syscall",
+                json_object(&[
+                    ("class", json_string(syscall.class_name())),
+                    ("a", syscall.a.to_string()),
+                    ("b", syscall.b.to_string()),
+                ]),
+            ));
+        }
+        if let Some(architecture) = self.architecture {
+            fields.push(("architecture", json_string(architecture)));
+        }
+        fields.push(("registers", json_registers(&self.registers)));
+        fields.push(("exception_frame", json_registers(&self.exception_frame)));
+        fields.push((
+            "memory_map",
+            json_array(self.memory_map.iter().map(|region| {
+                json_object(&[
+                    ("name", json_string(region.name)),
+                    ("start", region.start.to_string()),
+                    ("end", region.end.to_string()),
+                    ("note", json_string(&region.note)),
+                ])
+            })),
+        ));
+        fields.push((
+            "backtrace",
+            json_array(self.backtrace.iter().map(|frame| {
+                json_object(&[
+                    ("address", frame.address.to_string()),
+                    ("value", frame.value.to_string()),
+                    ("symbol", json_string(&frame.symbol)),
+                ])
+            })),
+        ));
+        fields.push((
+            "warnings",
+            json_array(self.warnings.iter().map(|warning| json_string(warning))),
+        ));
+        json_object(&fields)
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json_object(fields: &[(&str, String)]) -> String {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|(name, value)| format!("{}:{}", json_string(name), value))
+        .collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+fn json_array(values: impl Iterator<Item = String>) -> String {
+    format!("[{}]", values.collect::<Vec<String>>().join(","))
+}
+
+fn json_registers(registers: &[Register]) -> String {
+    json_array(registers.iter().map(|register| {
+        let mut fields = vec![
+            ("name", json_string(&register.name)),
+            ("value", register.value.to_string()),
+        ];
+        if let Some(symbol) = &register.symbol {
+            fields.push(("symbol", json_string(symbol)));
+        }
+        json_object(&fields)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame;
+
+    #[test]
+    fn reconstructs_memory_map() {
+        let process = frame::parse(&frame::tests::frame()).unwrap();
+        let report = Report::new(&process, &[]);
+        let regions: Vec<(&str, u32, u32)> = report
+            .memory_map
+            .iter()
+            .map(|r| (r.name, r.start, r.end))
+            .collect();
+        assert_eq!(
+            regions,
+            vec![
+                ("protected flash", 0x0004_0000, 0x0004_0048),
+                ("app flash", 0x0004_0048, 0x0004_1f00),
+                ("footers", 0x0004_1f00, 0x0004_2000),
+                ("stack", 0x2000_1000, 0x2000_2000),
+                ("heap", 0x2000_2000, 0x2000_2800),
+                ("unused", 0x2000_2800, 0x2000_3c00),
+                ("grants", 0x2000_3c00, 0x2000_3e18),
+                ("kernel data", 0x2000_3e18, 0x2000_4000),
+            ]
+        );
+        assert_eq!(
+            report.memory_map[3].note,
+            "256 bytes used, lowest stack pointer 0x20001f00"
+        );
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn decodes_registers_and_exception_frame() {
+        let process = frame::parse(&frame::tests::frame()).unwrap();
+        let report = Report::new(&process, &[]);
+        assert_eq!(report.architecture, Some("cortex-m"));
+        assert_eq!(report.registers[0].name, "yield pc");
+        assert_eq!(report.registers[0].value, 0x0004_0101);
+        assert_eq!(report.registers[2].value, 0x2000_1f00);
+        assert_eq!(report.registers.len(), 11);
+        let pc = report
+            .exception_frame
+            .iter()
+            .find(|r| r.name == "pc")
+            .unwrap();
+        assert_eq!(pc.value, 0x0004_0134);
+        let lr = report
+            .exception_frame
+            .iter()
+            .find(|r| r.name == "lr")
+            .unwrap();
+        assert_eq!(lr.value, 0x0004_0121);
+    }
+
+    #[test]
+    fn prints_json() {
+        let process = frame::parse(&frame::tests::frame()).unwrap();
+        let json = Report::new(&process, &[]).json();
+        assert!(json.starts_with("{\"name\":\"blink\",\"crc_ok\":true,"));
+        assert!(json.contains("\"state\":\"faulted\",\"faulted\":true,"));
+        assert!(json.contains("{\"name\":\"pc\",\"value\":262452}"));
+        assert_eq!(json_string("a\"b\n"), "\"a\\\"b\\u000a\"");
+    }
+}
@@ -0,0 +1,228 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Symbolization of process addresses with the app ELF.
+
+use std::path::Path;
+
+use addr2line::gimli;
+use object::{Object, ObjectSection, ObjectSegment, ObjectSymbol, SegmentFlags, SymbolKind};
+
+use crate::frame::Addresses;
+
+type Reader = gimli::EndianSlice<'static, gimli::RunTimeEndian>;
+
+/// ELF segment flag for executable segments.
+const PF_X: u32 = 1;
+
+/// Function symbols and debug information of an app ELF.
+pub struct Symbolizer {
+    /// File name of the ELF without extension, to match it to processes.
+    pub name: String,
+    /// Function start, size and name, sorted by start.
+    functions: Vec<(u64, u64, String)>,
+    /// Start and size of the executable segments.
+    code: Vec<(u64, u64)>,
+    /// Line information, if the ELF has DWARF debug information.
+    lines: Option<addr2line::Context<Reader>>,
+    /// Whether this is an ARM ELF, where the lowest address bit marks Thumb
+    /// code.
+    thumb: bool,
+}
+
+/// Where the ELF is in the process's memory.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Placement {
+    /// The ELF is linked to the address the process runs at.
+    Fixed,
+    /// The ELF is position independent, and its code starts at the start of
+    /// the process's non-protected flash.
+    Relocated { offset: i64 },
+}
+
+impl Symbolizer {
+    pub fn load(path: &Path) -> Result<Symbolizer, String> {
+        // The debug information borrows from the ELF for as long as the tool
+        // runs.
+        let data: &'static [u8] = std::fs::read(path).map_err(|e| e.to_string())?.leak();
+        let file = object::File::parse(data).map_err(|e| e.to_string())?;
+        let thumb = file.architecture() == object::Architecture::Arm;
+
+        let mut functions: Vec<(u64, u64, String)> = file
+            .symbols()
+            .filter(|symbol| symbol.kind() == SymbolKind::Text && symbol.size() > 0)
+            .filter_map(|symbol| {
+                let address = if thumb {
+                    symbol.address() & !1
+                } else {
+                    symbol.address()
+                };
+                // Demangle the symbols of libtock-rs apps.
+                let name = format!("{:#}", rustc_demangle::demangle(symbol.name().ok()?));
+                Some((address, symbol.size(), name))
+            })
+            .collect();
+        functions.sort();
+
+        let code = file
+            .segments()
+            .filter(|segment| match segment.flags() {
+                SegmentFlags::Elf { p_flags } => p_flags & PF_X != 0,
+                _ => false,
+            })
+            .map(|segment| (segment.address(), segment.size()))
+            .collect();
+
+        let endian = if file.is_little_endian() {
+            gimli::RunTimeEndian::Little
+        } else {
+            gimli::RunTimeEndian::Big
+        };
+        let load_section = |id: gimli::SectionId| -> Result<Reader, gimli::Error> {
+            let data = file
+                .section_by_name(id.name())
+                .and_then(|section| section.data().ok())
+                .unwrap_or(&[]);
+            Ok(gimli::EndianSlice::new(data, endian))
+        };
+        let lines = gimli::Dwarf::load(load_section)
+            .ok()
+            .and_then(|dwarf| addr2line::Context::from_dwarf(dwarf).ok());
+
+        Ok(Symbolizer {
+            name: path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            functions,
+            code,
+            lines,
+            thumb,
+        })
+    }
+
+    /// Where the ELF is in the memory of a process at `addresses`: ELFs with
+    /// code inside the process's flash are linked for it, others are
+    /// position-independent.
+    pub fn placement(&self, addresses: &Addresses) -> Placement {
+        let flash = addresses.flash_start as u64..addresses.flash_end as u64;
+        match self.code.iter().map(|(start, _)| *start).min() {
+            Some(start) if !flash.contains(&start) => Placement::Relocated {
+                offset: start as i64 - addresses.flash_non_protected_start as i64,
+            },
+            _ => Placement::Fixed,
+        }
+    }
+
+    /// The ELF address of a process `address`.
+    fn elf_address(&self, placement: Placement, address: u32) -> u64 {
+        let address = match placement {
+            Placement::Fixed => address as u64,
+            Placement::Relocated { offset } => (address as i64 + offset) as u64,
+        };
+        if self.thumb {
+            address & !1
+        } else {
+            address
+        }
+    }
+
+    /// Whether `address` is in the code of the process.
+    pub fn is_code(&self, placement: Placement, address: u32) -> bool {
+        let address = self.elf_address(placement, address);
+        self.code
+            .iter()
+            .any(|(start, size)| (*start..start + size).contains(&address))
+    }
+
+    /// The function containing `address` and the offset into it, and the
+    /// source location if known, for example `main+0x1a (main.c:12)`.
+    pub fn symbolize(&self, placement: Placement, address: u32) -> Option<String> {
+        let address = self.elf_address(placement, address);
+        let index = self
+            .functions
+            .partition_point(|(start, _, _)| *start <= address)
+            .checked_sub(1)?;
+        let (start, size, name) = &self.functions[index];
+        if address >= start + size {
+            return None;
+        }
+        let mut description = format!("{}+{:#x}", name, address - start);
+        let location = self
+            .lines
+            .as_ref()
+            .and_then(|lines| lines.find_location(address).ok().flatten());
+        if let Some(location) = location {
+            if let Some(file) = location.file {
+                let file = Path::new(file)
+                    .file_name()
+                    .map_or(file.into(), |name| name.to_string_lossy());
+                description.push_str(&format!(" ({}", file));
+                if let Some(line) = location.line {
+                    description.push_str(&format!(":{}", line));
+                }
+                description.push(')');
+            }
+        }
+        Some(description)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbolizer(code_start: u64) -> Symbolizer {
+        Symbolizer {
+            name: "blink".to_string(),
+            functions: vec![
+                (code_start, 0x20, "_start".to_string()),
+                (code_start + 0x100, 0x40, "main".to_string()),
+            ],
+            code: vec![(code_start, 0x1000)],
+            lines: None,
+            thumb: true,
+        }
+    }
+
+    fn addresses() -> Addresses {
+        Addresses {
+            flash_start: 0x0004_0000,
+            flash_non_protected_start: 0x0004_0048,
+            flash_end: 0x0004_2000,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn symbolizes_fixed_elf() {
+        let elf = symbolizer(0x0004_0048);
+        let placement = elf.placement(&addresses());
+        assert_eq!(placement, Placement::Fixed);
+        assert_eq!(
+            elf.symbolize(placement, 0x0004_0169).as_deref(),
+            Some("main+0x20")
+        );
+        assert_eq!(elf.symbolize(placement, 0x0004_0070), None);
+        assert!(elf.is_code(placement, 0x0004_0070));
+        assert!(!elf.is_code(placement, 0x2000_0000));
+    }
+
+    #[test]
+    fn symbolizes_relocated_elf() {
+        let elf = symbolizer(0x8000_0000);
+        let placement = elf.placement(&addresses());
+        assert_eq!(
+            placement,
+            Placement::Relocated {
+                offset: 0x8000_0000 - 0x0004_0048
+            }
+        );
+        assert_eq!(
+            elf.symbolize(placement, 0x0004_0049).as_deref(),
+            Some("_start+0x0")
+        );
+        assert!(!elf.is_code(placement, 0x0004_1048));
+    }
+}
@@ -0,0 +1,405 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Conversion of traced events to the Chrome trace event format, which
+//! Perfetto (<https://ui.perfetto.dev>) and `chrome://tracing` open.
+//!
+//! The kernel and each process get a track. Processes show when they run and
+//! the syscalls they make, with the upcalls scheduled for them as instant
+//! events. The kernel shows interrupt service, with the interrupts serviced
+//! nested inside, and deferred calls.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::frame::{Frame, Kind, Record};
+
+/// Process ID of the board in the trace.
+const PID: u32 = 1;
+/// Thread ID of the kernel track. Processes use their ID plus one.
+const KERNEL_TID: u32 = 0;
+
+const SYSCALL_CLASSES: [&str; 8] = [
+    "yield",
+    "subscribe",
+    "command",
+    "allow-rw",
+    "allow-ro",
+    "memop",
+    "exit",
+    "allow-userspace-readable",
+];
+
+const SWITCH_REASONS: [&str; 4] = ["syscall", "fault", "interrupted", "switch failed"];
+
+/// Names of common drivers, from `capsules/core/src/driver.rs`.
+const DRIVERS: &[(u32, &str)] = &[
+    (0x00000, "alarm"),
+    (0x00001, "console"),
+    (0x00002, "led"),
+    (0x00003, "button"),
+    (0x00004, "gpio"),
+    (0x00005, "adc"),
+    (0x00006, "dac"),
+    (0x00007, "analog_comparator"),
+    (0x00008, "low_level_debug"),
+    (0x00009, "read_only_state"),
+    (0x00010, "pwm"),
+    (0x10000, "ipc"),
+    (0x20001, "spi"),
+    (0x20003, "i2c_master"),
+    (0x30000, "ble_advertising"),
+    (0x30001, "ieee802154"),
+    (0x30002, "udp"),
+    (0x40001, "rng"),
+    (0x40002, "crc"),
+    (0x40003, "hmac"),
+    (0x50000, "app_flash"),
+    (0x50001, "nvm_storage"),
+    (0x50003, "kv"),
+    (0x60000, "temperature"),
+    (0x60001, "humidity"),
+];
+
+fn driver_name(driver: u32) -> String {
+    DRIVERS
+        .iter()
+        .find(|(number, _)| *number == driver)
+        .map_or_else(|| format!("{:#x}", driver), |(_, name)| name.to_string())
+}
+
+/// Name of the syscall in a syscall entry `record`.
+fn syscall_name(record: &Record) -> String {
+    match record.detail {
+        0 => match record.arg0 {
+            0 => "yield-no-wait".to_string(),
+            1 => "yield-wait".to_string(),
+            2 => "yield-wait-for".to_string(),
+            _ => "yield".to_string(),
+        },
+        5 => format!("memop {}", record.arg0),
+        6 => "exit".to_string(),
+        class @ (1..=4 | 7) => format!(
+            "{} {} {}",
+            SYSCALL_CLASSES[class as usize],
+            driver_name(record.arg0),
+            record.arg1
+        ),
+        _ => "syscall".to_string(),
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut json = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// Kinds of spans, of which each track has at most one open.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Span {
+    Running,
+    Syscall,
+    InterruptService,
+    Interrupt,
+    DeferredCall,
+}
+
+struct OpenSpan {
+    start: f64,
+    name: String,
+    category: &'static str,
+}
+
+/// Converts frames, in the order they were sent, to a Chrome trace.
+pub struct Converter {
+    events: Vec<String>,
+    open: BTreeMap<(u32, Span), OpenSpan>,
+    tracks: BTreeSet<u32>,
+    /// Ticks since the first event.
+    ticks: u64,
+    /// Timestamp of the last event.
+    last: Option<u32>,
+    /// Number of events converted.
+    pub count: usize,
+    /// Number of events lost on the board.
+    pub dropped: u64,
+}
+
+impl Converter {
+    pub fn new() -> Converter {
+        Converter {
+            events: Vec::new(),
+            open: BTreeMap::new(),
+            tracks: BTreeSet::new(),
+            ticks: 0,
+            last: None,
+            count: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Microseconds since the first event at `timestamp`, assuming less than
+    /// a full timer period passed since the last event.
+    fn time_us(&mut self, timestamp: u32, frame: &Frame) -> f64 {
+        let mask = u32::MAX >> (32 - frame.tick_bits as u32);
+        if let Some(last) = self.last {
+            self.ticks += (timestamp.wrapping_sub(last) & mask) as u64;
+        }
+        self.last = Some(timestamp);
+        self.ticks as f64 * 1_000_000.0 / frame.frequency as f64
+    }
+
+    fn open(&mut self, tid: u32, span: Span, time: f64, name: String, category: &'static str) {
+        self.tracks.insert(tid);
+        // A span left open was not closed because its end was not recorded.
+        self.open.insert(
+            (tid, span),
+            OpenSpan {
+                start: time,
+                name,
+                category,
+            },
+        );
+    }
+
+    fn close(&mut self, tid: u32, span: Span, time: f64, args: &str) {
+        if let Some(open) = self.open.remove(&(tid, span)) {
+            self.events.push(format!(
+                "{{\"name\":{},\"cat\":\"{}\",\"ph\":\"X\",\"ts\":{:.3},\"dur\":{:.3},\"pid\":{},\"tid\":{},\"args\":{{{}}}}}",
+                json_string(&open.name),
+                open.category,
+                open.start,
+                time - open.start,
+                PID,
+                tid,
+                args
+            ));
+        }
+    }
+
+    fn instant(&mut self, tid: u32, time: f64, name: &str, category: &str, scope: &str) {
+        self.tracks.insert(tid);
+        self.events.push(format!(
+            "{{\"name\":{},\"cat\":\"{}\",\"ph\":\"i\",\"s\":\"{}\",\"ts\":{:.3},\"pid\":{},\"tid\":{}}}",
+            json_string(name),
+            category,
+            scope,
+            time,
+            PID,
+            tid
+        ));
+    }
+
+    pub fn add_frame(&mut self, frame: &Frame) {
+        if frame.dropped > 0 {
+            self.dropped += frame.dropped as u64;
+            // The ends of open spans may have been lost.
+            self.open.clear();
+            if let Some(first) = frame.records.first() {
+                let time = self.time_us(first.timestamp, frame);
+                let name = format!("{} events dropped", frame.dropped);
+                self.instant(KERNEL_TID, time, &name, "trace", "g");
+            }
+        }
+
+        for record in &frame.records {
+            self.count += 1;
+            let time = self.time_us(record.timestamp, frame);
+            let tid = record
+                .process
+                .map_or(KERNEL_TID, |process| process as u32 + 1);
+            match record.kind {
+                Kind::SwitchToProcess => {
+                    self.open(tid, Span::Running, time, "running".to_string(), "process")
+                }
+                Kind::SwitchToKernel => {
+                    let reason = SWITCH_REASONS
+                        .get(record.detail as usize)
+                        .unwrap_or(&"unknown");
+                    self.close(
+                        tid,
+                        Span::Running,
+                        time,
+                        &format!("\"reason\":{}", json_string(reason)),
+                    );
+                    if record.detail == 1 {
+                        self.instant(tid, time, "fault", "process", "t");
+                    }
+                }
+                Kind::SyscallEntry => {
+                    let name = syscall_name(record);
+                    self.open(tid, Span::Syscall, time, name, "syscall");
+                }
+                Kind::SyscallExit => self.close(tid, Span::Syscall, time, ""),
+                Kind::UpcallScheduled => {
+                    let name = format!("upcall {} {}", driver_name(record.arg0), record.arg1);
+                    self.instant(tid, time, &name, "upcall", "t");
+                }
+                Kind::InterruptServiceStart => self.open(
+                    tid,
+                    Span::InterruptService,
+                    time,
+                    "interrupts".to_string(),
+                    "interrupt",
+                ),
+                Kind::Interrupt => {
+                    // An interrupt is serviced until the next one is, or
+                    // interrupt service ends.
+                    self.close(tid, Span::Interrupt, time, "");
+                    let name = format!("irq {}", record.arg0);
+                    self.open(tid, Span::Interrupt, time, name, "interrupt");
+                }
+                Kind::InterruptServiceEnd => {
+                    self.close(tid, Span::Interrupt, time, "");
+                    self.close(tid, Span::InterruptService, time, "");
+                }
+                Kind::DeferredCallStart => {
+                    let name = format!("deferred call {}", record.arg0);
+                    self.open(tid, Span::DeferredCall, time, name, "deferred_call");
+                }
+                Kind::DeferredCallEnd => self.close(tid, Span::DeferredCall, time, ""),
+            }
+        }
+    }
+
+    /// The trace as JSON. Spans that did not end are shown as unfinished.
+    pub fn finish(mut self) -> String {
+        let open = std::mem::take(&mut self.open);
+        for ((tid, _), span) in open {
+            self.events.push(format!(
+                "{{\"name\":{},\"cat\":\"{}\",\"ph\":\"B\",\"ts\":{:.3},\"pid\":{},\"tid\":{}}}",
+                json_string(&span.name),
+                span.category,
+                span.start,
+                PID,
+                tid
+            ));
+        }
+
+        let mut metadata = vec![format!(
+            "{{\"name\":\"process_name\",\"ph\":\"M\",\"pid\":{},\"args\":{{\"name\":\"Tock\"}}}}",
+            PID
+        )];
+        for tid in &self.tracks {
+            let name = match tid {
+                0 => "kernel".to_string(),
+                tid => format!("process {}", tid - 1),
+            };
+            metadata.push(format!(
+                "{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":{},\"tid\":{},\"args\":{{\"name\":{}}}}}",
+                PID,
+                tid,
+                json_string(&name)
+            ));
+            metadata.push(format!(
+                "{{\"name\":\"thread_sort_index\",\"ph\":\"M\",\"pid\":{},\"tid\":{},\"args\":{{\"sort_index\":{}}}}}",
+                PID, tid, tid
+            ));
+        }
+        metadata.append(&mut self.events);
+        format!(
+            "{{\"displayTimeUnit\":\"ms\",\"traceEvents\":[\n{}\n]}}\n",
+            metadata.join(",\n")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::{find_frames, tests::encode};
+
+    const KERNEL: u16 = 0xFFFF;
+
+    /// The JSON, number of events and number of dropped events of `frames`.
+    fn convert(frames: &[Vec<u8>]) -> (String, usize, u64) {
+        let mut converter = Converter::new();
+        for data in frames {
+            let (frames, _) = find_frames(data);
+            converter.add_frame(&frames[0]);
+        }
+        let (count, dropped) = (converter.count, converter.dropped);
+        (converter.finish(), count, dropped)
+    }
+
+    #[test]
+    fn converts_process_spans() {
+        // 1 MHz ticks, so ticks are microseconds.
+        let frame = encode(
+            32,
+            1_000_000,
+            0,
+            &[
+                (1000, 1, 0, 2, 0, 0),
+                (1100, 2, 0, 2, 0, 0),
+                (1110, 3, 2, 2, 1, 1),
+                (1150, 5, 0, 2, 0, 0),
+                (1160, 4, 0, 2, 0, 0),
+            ],
+        );
+        let (json, count, _) = convert(&[frame]);
+        assert_eq!(count, 5);
+        assert!(json.contains(
+            "{\"name\":\"running\",\"cat\":\"process\",\"ph\":\"X\",\"ts\":0.000,\"dur\":100.000,\"pid\":1,\"tid\":3,\"args\":{\"reason\":\"syscall\"}}"
+        ));
+        assert!(json.contains(
+            "{\"name\":\"command console 1\",\"cat\":\"syscall\",\"ph\":\"X\",\"ts\":110.000,\"dur\":50.000"
+        ));
+        assert!(json.contains("\"name\":\"upcall alarm 0\",\"cat\":\"upcall\",\"ph\":\"i\""));
+        assert!(json.contains("\"tid\":3,\"args\":{\"name\":\"process 2\"}"));
+    }
+
+    #[test]
+    fn nests_interrupts_and_unwraps_timestamps() {
+        // A 24 bit timer at 1 MHz that wraps between the events.
+        let frame = encode(
+            24,
+            1_000_000,
+            0,
+            &[
+                (0xFF_FFF0, 6, 0, KERNEL, 0, 0),
+                (0xFF_FFF8, 7, 0, KERNEL, 17, 0),
+                (0x00_0008, 7, 0, KERNEL, 3, 0),
+                (0x00_0010, 8, 0, KERNEL, 0, 0),
+                (0x00_0020, 9, 0, KERNEL, 4, 0),
+            ],
+        );
+        let (json, _, _) = convert(&[frame]);
+        assert!(json.contains("\"name\":\"interrupts\",\"cat\":\"interrupt\",\"ph\":\"X\",\"ts\":0.000,\"dur\":32.000"));
+        assert!(json.contains(
+            "\"name\":\"irq 17\",\"cat\":\"interrupt\",\"ph\":\"X\",\"ts\":8.000,\"dur\":16.000"
+        ));
+        assert!(json.contains(
+            "\"name\":\"irq 3\",\"cat\":\"interrupt\",\"ph\":\"X\",\"ts\":24.000,\"dur\":8.000"
+        ));
+        // The deferred call did not end.
+        assert!(json.contains(
+            "\"name\":\"deferred call 4\",\"cat\":\"deferred_call\",\"ph\":\"B\",\"ts\":48.000"
+        ));
+        assert!(json.contains("\"tid\":0,\"args\":{\"name\":\"kernel\"}"));
+    }
+
+    #[test]
+    fn reports_dropped_events() {
+        let first = encode(32, 1000, 0, &[(0, 1, 0, 1, 0, 0)]);
+        let second = encode(32, 1000, 7, &[(5, 2, 1, 1, 0, 0)]);
+        let (json, _, dropped) = convert(&[first, second]);
+        assert_eq!(dropped, 7);
+        assert!(json.contains("\"name\":\"7 events dropped\",\"cat\":\"trace\",\"ph\":\"i\",\"s\":\"g\",\"ts\":5000.000"));
+        // The running span is discarded, as its end may have been lost.
+        assert!(!json.contains("\"name\":\"running\""));
+        assert!(json.contains("\"name\":\"fault\""));
+    }
+}
@@ -0,0 +1,259 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Parsing of the frames sent by `RingBufferTracer`. See
+//! `capsules/system/src/tracer.rs` for the format.
+
+const MAGIC: &[u8; 4] = b"TKTR";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 16;
+const RECORD_LEN: usize = 16;
+const CRC_LEN: usize = 4;
+
+/// `process` of events of the kernel itself.
+const NO_PROCESS: u16 = 0xFFFF;
+
+/// CRC-32 (IEEE 802.3) of `data`.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+/// Kind of a traced event.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    SwitchToProcess,
+    SwitchToKernel,
+    SyscallEntry,
+    SyscallExit,
+    UpcallScheduled,
+    InterruptServiceStart,
+    Interrupt,
+    InterruptServiceEnd,
+    DeferredCallStart,
+    DeferredCallEnd,
+}
+
+impl Kind {
+    fn from_u8(kind: u8) -> Option<Kind> {
+        match kind {
+            1 => Some(Kind::SwitchToProcess),
+            2 => Some(Kind::SwitchToKernel),
+            3 => Some(Kind::SyscallEntry),
+            4 => Some(Kind::SyscallExit),
+            5 => Some(Kind::UpcallScheduled),
+            6 => Some(Kind::InterruptServiceStart),
+            7 => Some(Kind::Interrupt),
+            8 => Some(Kind::InterruptServiceEnd),
+            9 => Some(Kind::DeferredCallStart),
+            10 => Some(Kind::DeferredCallEnd),
+            _ => None,
+        }
+    }
+}
+
+/// A traced event.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Record {
+    /// Timestamp in ticks, wrapping at the tick width of the frame.
+    pub timestamp: u32,
+    pub kind: Kind,
+    pub detail: u8,
+    /// Low 16 bits of the process ID, `None` for events of the kernel.
+    pub process: Option<u16>,
+    pub arg0: u32,
+    pub arg1: u32,
+}
+
+/// The records sent at once, with the timer they were timestamped with.
+#[derive(Debug, PartialEq)]
+pub struct Frame {
+    /// Width of the timestamps in bits.
+    pub tick_bits: u8,
+    /// Frequency of the timestamps in Hz.
+    pub frequency: u32,
+    /// Number of events lost before this frame.
+    pub dropped: u32,
+    pub records: Vec<Record>,
+}
+
+/// Parses the frame at the start of `data`, returning it and its length.
+fn parse(data: &[u8]) -> Result<(Frame, usize), String> {
+    if data.len() < HEADER_LEN + CRC_LEN {
+        return Err("truncated header".to_string());
+    }
+    if data[4] != VERSION {
+        return Err(format!("unsupported version {}", data[4]));
+    }
+    let tick_bits = data[5];
+    let count = u16_at(data, 6) as usize;
+    let frequency = u32_at(data, 8);
+    let dropped = u32_at(data, 12);
+    if tick_bits == 0 || tick_bits > 32 || frequency == 0 {
+        return Err("invalid timer".to_string());
+    }
+    let len = HEADER_LEN + count * RECORD_LEN;
+    if data.len() < len + CRC_LEN {
+        return Err("truncated records".to_string());
+    }
+    if crc32(&data[..len]) != u32_at(data, len) {
+        return Err("CRC mismatch".to_string());
+    }
+
+    // Records of kinds added after this tool was written are skipped.
+    let records = data[HEADER_LEN..len]
+        .chunks_exact(RECORD_LEN)
+        .filter_map(|record| {
+            let process = u16_at(record, 6);
+            Some(Record {
+                timestamp: u32_at(record, 0),
+                kind: Kind::from_u8(record[4])?,
+                detail: record[5],
+                process: (process != NO_PROCESS).then_some(process),
+                arg0: u32_at(record, 8),
+                arg1: u32_at(record, 12),
+            })
+        })
+        .collect();
+    Ok((
+        Frame {
+            tick_bits,
+            frequency,
+            dropped,
+            records,
+        },
+        len + CRC_LEN,
+    ))
+}
+
+/// The valid frames in `input`, found by their magic, and the number of
+/// invalid ones.
+pub fn find_frames(input: &[u8]) -> (Vec<Frame>, usize) {
+    let mut frames = Vec::new();
+    let mut invalid = 0;
+    let mut offset = 0;
+    while let Some(position) = input[offset..]
+        .windows(MAGIC.len())
+        .position(|window| window == MAGIC)
+    {
+        let start = offset + position;
+        match parse(&input[start..]) {
+            Ok((frame, len)) => {
+                frames.push(frame);
+                offset = start + len;
+            }
+            Err(_) => {
+                invalid += 1;
+                offset = start + MAGIC.len();
+            }
+        }
+    }
+    (frames, invalid)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// Encodes records given as (timestamp, kind, detail, process, arg0,
+    /// arg1) into a frame, as `RingBufferTracer` does.
+    pub fn encode(
+        tick_bits: u8,
+        frequency: u32,
+        dropped: u32,
+        records: &[(u32, u8, u8, u16, u32, u32)],
+    ) -> Vec<u8> {
+        let mut data = MAGIC.to_vec();
+        data.push(VERSION);
+        data.push(tick_bits);
+        data.extend((records.len() as u16).to_le_bytes());
+        data.extend(frequency.to_le_bytes());
+        data.extend(dropped.to_le_bytes());
+        for (timestamp, kind, detail, process, arg0, arg1) in records {
+            data.extend(timestamp.to_le_bytes());
+            data.push(*kind);
+            data.push(*detail);
+            data.extend(process.to_le_bytes());
+            data.extend(arg0.to_le_bytes());
+            data.extend(arg1.to_le_bytes());
+        }
+        let crc = crc32(&data);
+        data.extend(crc.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn parses_frames() {
+        let mut input = b"boot messages\r\n".to_vec();
+        input.extend(encode(24, 32768, 0, &[(10, 1, 0, 3, 0, 0)]));
+        input.extend(encode(24, 32768, 5, &[(20, 7, 0, 0xFFFF, 42, 0)]));
+        let (frames, invalid) = find_frames(&input);
+        assert_eq!(invalid, 0);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].tick_bits, 24);
+        assert_eq!(frames[0].frequency, 32768);
+        assert_eq!(
+            frames[0].records,
+            vec![Record {
+                timestamp: 10,
+                kind: Kind::SwitchToProcess,
+                detail: 0,
+                process: Some(3),
+                arg0: 0,
+                arg1: 0,
+            }]
+        );
+        assert_eq!(frames[1].dropped, 5);
+        assert_eq!(frames[1].records[0].kind, Kind::Interrupt);
+        assert_eq!(frames[1].records[0].process, None);
+        assert_eq!(frames[1].records[0].arg0, 42);
+    }
+
+    #[test]
+    fn skips_corrupt_and_truncated_frames() {
+        let mut corrupt = encode(32, 1_000_000, 0, &[(1, 4, 0, 1, 0, 0)]);
+        corrupt[HEADER_LEN] ^= 1;
+        let good = encode(32, 1_000_000, 0, &[(2, 4, 0, 1, 0, 0)]);
+        let truncated = encode(32, 1_000_000, 0, &[(3, 4, 0, 1, 0, 0)]);
+
+        let mut input = corrupt;
+        input.extend(&good);
+        input.extend(&truncated[..truncated.len() - 1]);
+        let (frames, invalid) = find_frames(&input);
+        assert_eq!(invalid, 2);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].records[0].timestamp, 2);
+    }
+
+    #[test]
+    fn skips_unknown_records() {
+        let input = encode(32, 1000, 0, &[(1, 200, 0, 1, 0, 0), (2, 3, 2, 1, 1, 0)]);
+        let (frames, _) = find_frames(&input);
+        assert_eq!(frames[0].records.len(), 1);
+        assert_eq!(frames[0].records[0].kind, Kind::SyscallEntry);
+    }
+}
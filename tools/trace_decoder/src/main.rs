@@ -0,0 +1,96 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Converts the kernel trace frames sent by `RingBufferTracer` to a Chrome
+//! trace, for a timeline view in Perfetto or `chrome://tracing`.
+
+mod chrome;
+mod frame;
+
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use chrome::Converter;
+
+const USAGE: &str = "\
+Usage: trace_decoder [-o <trace.json>] [<capture>]
+
+Converts the trace frames in <capture>, or standard input, to Chrome trace
+JSON, written to <trace.json> or standard output. The capture is the raw data
+received from the board's trace UART, RTT channel or USB CDC port.";
+
+fn main() -> ExitCode {
+    let mut output_path: Option<PathBuf> = None;
+    let mut input_path: Option<PathBuf> = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => match args.next() {
+                Some(path) => output_path = Some(PathBuf::from(path)),
+                None => {
+                    eprintln!("{} needs a path\n\n{}", arg, USAGE);
+                    return ExitCode::FAILURE;
+                }
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            }
+            _ if input_path.is_none() && !arg.starts_with('-') => {
+                input_path = Some(PathBuf::from(arg))
+            }
+            _ => {
+                eprintln!("unexpected argument {}\n\n{}", arg, USAGE);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    let input = match &input_path {
+        Some(path) => std::fs::read(path),
+        None => {
+            let mut input = Vec::new();
+            std::io::stdin().read_to_end(&mut input).map(|_| input)
+        }
+    };
+    let input = match input {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("cannot read input: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let (frames, invalid) = frame::find_frames(&input);
+    if invalid > 0 {
+        eprintln!("skipped {} invalid frames", invalid);
+    }
+    if frames.is_empty() {
+        eprintln!("no trace frames found");
+        return ExitCode::FAILURE;
+    }
+
+    let mut converter = Converter::new();
+    for frame in &frames {
+        converter.add_frame(frame);
+    }
+    eprintln!(
+        "{} frames, {} events, {} dropped on the board",
+        frames.len(),
+        converter.count,
+        converter.dropped
+    );
+
+    let json = converter.finish();
+    let written = match &output_path {
+        Some(path) => std::fs::write(path, json),
+        None => std::io::stdout().write_all(json.as_bytes()),
+    };
+    if let Err(e) = written {
+        eprintln!("cannot write output: {}", e);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}